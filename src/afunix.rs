@@ -0,0 +1,255 @@
+//! AF_UNIX sockets on Windows 10 1803 and later, through Winsock.
+//!
+//! std has no `UnixStream` on Windows; this is the part of one psmux needs.
+//! Connecting takes write access to the socket file, and the server gives
+//! the file a DACL admitting only its user and SYSTEM, so like the named
+//! pipe this transport needs no session key.
+//!
+//! FFI style matches `pipe.rs` — raw `#[link]` extern blocks, no new crate
+//! dependencies.
+
+use std::io;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::transport::{Endpoint, Listener, Stream, Transport};
+
+// ── Winsock constants ──────────────────────────────────────────────────
+const AF_UNIX: u16 = 1;
+const SOCK_STREAM: i32 = 1;
+const INVALID_SOCKET: usize = !0;
+const SOCKET_ERROR: i32 = -1;
+const SOMAXCONN: i32 = 0x7fffffff;
+const SOL_SOCKET: i32 = 0xffff;
+const SO_RCVTIMEO: i32 = 0x1006;
+const SD_BOTH: i32 = 2;
+const WINSOCK_2_2: u16 = 0x0202;
+
+const DACL_SECURITY_INFORMATION: u32 = 0x00000004;
+const PROTECTED_DACL_SECURITY_INFORMATION: u32 = 0x80000000;
+
+/// Size of `sockaddr_un.sun_path`, the terminating NUL included.
+pub const SUN_PATH_LEN: usize = 108;
+
+/// DACL for the socket file: full access for the server's user and SYSTEM
+/// only.  Without the user's SID, the file owner (OW) stands in for it.
+fn socket_sddl(user_sid: Option<&str>) -> String {
+    format!("D:P(A;;FA;;;{})(A;;FA;;;SY)", user_sid.unwrap_or("OW"))
+}
+
+// ── FFI declarations ───────────────────────────────────────────────────
+
+#[repr(C)]
+struct SockaddrUn {
+    sun_family: u16,
+    sun_path: [u8; SUN_PATH_LEN],
+}
+
+#[link(name = "ws2_32")]
+extern "system" {
+    fn WSAStartup(wVersionRequested: u16, lpWSAData: *mut std::ffi::c_void) -> i32;
+    fn WSAGetLastError() -> i32;
+    fn socket(af: i32, kind: i32, protocol: i32) -> usize;
+    fn bind(s: usize, name: *const SockaddrUn, namelen: i32) -> i32;
+    fn listen(s: usize, backlog: i32) -> i32;
+    fn accept(s: usize, addr: *mut SockaddrUn, addrlen: *mut i32) -> usize;
+    fn connect(s: usize, name: *const SockaddrUn, namelen: i32) -> i32;
+    fn recv(s: usize, buf: *mut u8, len: i32, flags: i32) -> i32;
+    fn send(s: usize, buf: *const u8, len: i32, flags: i32) -> i32;
+    fn setsockopt(s: usize, level: i32, optname: i32, optval: *const u8, optlen: i32) -> i32;
+    fn shutdown(s: usize, how: i32) -> i32;
+    fn closesocket(s: usize) -> i32;
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn SetFileSecurityW(lpFileName: *const u16, SecurityInformation: u32, pSecurityDescriptor: *mut std::ffi::c_void) -> i32;
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────
+
+/// Start Winsock, once per process, before the first socket call.
+fn startup() -> io::Result<()> {
+    static STARTED: OnceLock<i32> = OnceLock::new();
+    let rc = *STARTED.get_or_init(|| {
+        // WSADATA is 408 bytes on x64; u64s keep it aligned
+        let mut data = [0u64; 64];
+        unsafe { WSAStartup(WINSOCK_2_2, data.as_mut_ptr().cast()) }
+    });
+    if rc == 0 { Ok(()) } else { Err(io::Error::from_raw_os_error(rc)) }
+}
+
+fn last_error() -> io::Error {
+    io::Error::from_raw_os_error(unsafe { WSAGetLastError() })
+}
+
+/// The address of the socket file at `path`.
+fn sockaddr(path: &str) -> io::Result<SockaddrUn> {
+    let bytes = path.as_bytes();
+    if bytes.len() >= SUN_PATH_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("socket path too long: {}", path)));
+    }
+    let mut addr = SockaddrUn { sun_family: AF_UNIX, sun_path: [0; SUN_PATH_LEN] };
+    addr.sun_path[..bytes.len()].copy_from_slice(bytes);
+    Ok(addr)
+}
+
+const SOCKADDR_UN_LEN: i32 = std::mem::size_of::<SockaddrUn>() as i32;
+
+/// An owned SOCKET, closed on drop.
+struct Socket(usize);
+
+impl Socket {
+    fn new() -> io::Result<Socket> {
+        startup()?;
+        let s = unsafe { socket(AF_UNIX as i32, SOCK_STREAM, 0) };
+        if s == INVALID_SOCKET { Err(last_error()) } else { Ok(Socket(s)) }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { closesocket(self.0); }
+    }
+}
+
+// ── UnixStream ─────────────────────────────────────────────────────────
+
+/// A connected AF_UNIX socket.  Clones share the socket, which is closed
+/// when the last of them goes.
+pub struct UnixStream {
+    sock: Arc<Socket>,
+}
+
+impl UnixStream {
+    /// Connect to the server listening at `path`.
+    pub fn connect(path: &str) -> io::Result<UnixStream> {
+        let addr = sockaddr(path)?;
+        let sock = Socket::new()?;
+        if unsafe { connect(sock.0, &addr, SOCKADDR_UN_LEN) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(UnixStream { sock: Arc::new(sock) })
+    }
+}
+
+impl io::Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(i32::MAX as usize) as i32;
+        let n = unsafe { recv(self.sock.0, buf.as_mut_ptr(), len, 0) };
+        if n < 0 { Err(last_error()) } else { Ok(n as usize) }
+    }
+}
+
+impl io::Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(i32::MAX as usize) as i32;
+        let n = unsafe { send(self.sock.0, buf.as_ptr(), len, 0) };
+        if n < 0 { Err(last_error()) } else { Ok(n as usize) }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for UnixStream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        Ok(Box::new(UnixStream { sock: self.sock.clone() }))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // SO_RCVTIMEO is a DWORD of milliseconds, 0 for none
+        let ms: u32 = timeout.map_or(0, |t| t.as_millis().clamp(1, u32::MAX as u128) as u32);
+        let rc = unsafe { setsockopt(self.sock.0, SOL_SOCKET, SO_RCVTIMEO, ms.to_ne_bytes().as_ptr(), 4) };
+        if rc == SOCKET_ERROR { Err(last_error()) } else { Ok(()) }
+    }
+
+    /// No-op — AF_UNIX sockets don't have Nagle's algorithm.
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        unsafe { shutdown(self.sock.0, SD_BOTH); }
+    }
+}
+
+// ── UnixListener ───────────────────────────────────────────────────────
+
+/// A listening AF_UNIX socket.  Its file is removed when it is dropped;
+/// a server that exits removes it along with its registry file.
+pub struct UnixListener {
+    sock: Socket,
+    path: String,
+}
+
+impl UnixListener {
+    /// Listen at `path`, replacing a file a dead server left there.  The
+    /// file is restricted to this user before anyone can connect.
+    pub fn bind(path: &str) -> io::Result<UnixListener> {
+        let addr = sockaddr(path)?;
+        let sock = Socket::new()?;
+        let _ = std::fs::remove_file(path);
+        if unsafe { bind(sock.0, &addr, SOCKADDR_UN_LEN) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        let listener = UnixListener { sock, path: path.to_string() };
+        restrict_to_user(path)?;
+        if unsafe { listen(listener.sock.0, SOMAXCONN) } == SOCKET_ERROR {
+            return Err(last_error());
+        }
+        Ok(listener)
+    }
+}
+
+/// Give the socket file at `path` a DACL admitting only this user and SYSTEM.
+fn restrict_to_user(path: &str) -> io::Result<()> {
+    let sd = crate::pipe::SecurityDescriptor::from_sddl(&socket_sddl(crate::pipe::user_sid()))?;
+    let wide = crate::pipe::to_wide(path);
+    let ok = unsafe { SetFileSecurityW(wide.as_ptr(), DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION, sd.ptr) };
+    if ok == 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+impl Listener for UnixListener {
+    fn accept(&mut self) -> io::Result<Stream> {
+        let s = unsafe { accept(self.sock.0, std::ptr::null_mut(), std::ptr::null_mut()) };
+        if s == INVALID_SOCKET {
+            return Err(last_error());
+        }
+        Ok(Box::new(UnixStream { sock: Arc::new(Socket(s)) }))
+    }
+
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Unix(self.path.clone())
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_addresses_hold_the_path_and_its_nul() {
+        let addr = sockaddr(r"C:\Users\a\.psmux\work.sock").unwrap();
+        assert_eq!(addr.sun_family, AF_UNIX);
+        assert_eq!(&addr.sun_path[..27], br"C:\Users\a\.psmux\work.sock");
+        assert!(addr.sun_path[27..].iter().all(|b| *b == 0));
+        // One byte short of the array: no room left for the NUL
+        assert!(sockaddr(&"d".repeat(SUN_PATH_LEN - 1)).is_ok());
+        assert_eq!(sockaddr(&"d".repeat(SUN_PATH_LEN)).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn socket_sddl_admits_the_user_and_system_only() {
+        let sid = "S-1-5-21-1004336348-1177238915-682003330-1001";
+        assert_eq!(socket_sddl(Some(sid)), format!("D:P(A;;FA;;;{})(A;;FA;;;SY)", sid));
+        assert_eq!(socket_sddl(None), "D:P(A;;FA;;;OW)(A;;FA;;;SY)");
+    }
+}
@@ -0,0 +1,177 @@
+//! Window alerts: output (`monitor-activity`), a quiet spell
+//! (`monitor-silence`) and bells (`monitor-bell`).
+//!
+//! Each pane's reader thread counts the BEL characters its program prints
+//! outside escape sequences (a BEL that ends an OSC title is not a bell).
+//! The server checks the windows on every pass of its loop: a window other
+//! than the current one gets its activity, silence or bell flag (`#`, `~`
+//! and `!` in `window_flags`, its tab drawn in window-status-activity-style
+//! or window-status-bell-style) and the flags clear when it becomes the
+//! current window.
+//!
+//! A newly raised alert fires its hook (`alert-activity`, `alert-silence`,
+//! `alert-bell`) and tells the attached clients: a "... in window N"
+//! message with `visual-activity` / `visual-silence` / `visual-bell`, else
+//! a `%bell` directive that rings the client's terminal bell.  Bells in the
+//! current window ring too; `bell-action` picks which windows' bells do.
+
+use crate::types::{AppState, Node, Window};
+
+/// A kind of alert.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alert {
+    Activity,
+    Silence,
+    Bell,
+}
+
+impl Alert {
+    pub fn hook(self) -> &'static str {
+        match self {
+            Alert::Activity => "alert-activity",
+            Alert::Silence => "alert-silence",
+            Alert::Bell => "alert-bell",
+        }
+    }
+
+    fn visual(self, app: &AppState) -> bool {
+        match self {
+            Alert::Activity => app.visual_activity,
+            Alert::Silence => app.visual_silence,
+            Alert::Bell => app.visual_bell,
+        }
+    }
+
+    fn message(self, window_index: usize) -> String {
+        let what = match self {
+            Alert::Activity => "Activity",
+            Alert::Silence => "Silence",
+            Alert::Bell => "Bell",
+        };
+        format!("{} in window {}", what, window_index)
+    }
+}
+
+/// Counts bells in a pane's output stream, which may split an escape
+/// sequence across reads.
+#[derive(Default)]
+pub struct BellScanner {
+    state: Scan,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+enum Scan {
+    #[default]
+    Ground,
+    /// After ESC
+    Escape,
+    /// In an OSC, DCS, SOS, PM or APC string, which BEL or ST ends
+    Str,
+    /// After ESC in such a string
+    StrEscape,
+}
+
+impl BellScanner {
+    /// The bells in one read of pane output.
+    pub fn feed(&mut self, bytes: &[u8]) -> u64 {
+        let mut bells = 0;
+        for &b in bytes {
+            self.state = match (self.state, b) {
+                (Scan::Ground, 0x07) => { bells += 1; Scan::Ground }
+                (Scan::Ground, 0x1b) => Scan::Escape,
+                (Scan::Ground, _) => Scan::Ground,
+                (Scan::Str, 0x07 | 0x18 | 0x1a) => Scan::Ground,
+                (Scan::Str, 0x1b) => Scan::StrEscape,
+                (Scan::Str, _) => Scan::Str,
+                // ST, or an ESC that cuts the string short and starts
+                // another sequence
+                (Scan::StrEscape, b'\\') => Scan::Ground,
+                (Scan::Escape | Scan::StrEscape, b) => after_escape(b),
+            };
+        }
+        bells
+    }
+}
+
+fn after_escape(b: u8) -> Scan {
+    match b {
+        b']' | b'P' | b'X' | b'^' | b'_' => Scan::Str,
+        0x1b => Scan::Escape,
+        _ => Scan::Ground,
+    }
+}
+
+/// True when a pane of `win` rang a bell since the last call.
+pub fn take_bells(win: &mut Window) -> bool {
+    fn walk(node: &mut Node) -> bool {
+        match node {
+            Node::Leaf(p) => {
+                let n = p.bells.load(std::sync::atomic::Ordering::Acquire);
+                let rang = n != p.bells_seen;
+                p.bells_seen = n;
+                rang
+            }
+            Node::Split { children, .. } => children.iter_mut().fold(false, |rang, c| walk(c) | rang),
+        }
+    }
+    walk(&mut win.root)
+}
+
+/// The style for a window's tab while it has an alert, empty without one.
+pub fn tab_style(app: &AppState, win: &Window) -> String {
+    if win.bell_flag {
+        app.window_status_bell_style.clone()
+    } else if win.activity_flag || win.silence_flag {
+        app.window_status_activity_style.clone()
+    } else {
+        String::new()
+    }
+}
+
+/// Whether `bell-action` lets a bell in window `win_idx` reach the clients.
+fn bell_applies(app: &AppState, win_idx: usize) -> bool {
+    match app.bell_action.as_str() {
+        "none" => false,
+        "current" => win_idx == app.active_idx,
+        "other" => win_idx != app.active_idx,
+        _ => true,
+    }
+}
+
+/// Fire the hooks for newly raised alerts and tell the clients: a message
+/// or one `%bell` for the lot.
+pub fn raise(app: &mut AppState, raised: &[(usize, Alert)]) {
+    let mut ring = false;
+    for &(win_idx, alert) in raised {
+        let context = crate::hooks::window_context(app, win_idx);
+        crate::hooks::queue(app, alert.hook(), context);
+        if alert == Alert::Bell && !bell_applies(app, win_idx) { continue; }
+        let Some(win) = app.windows.get(win_idx) else { continue };
+        if alert.visual(app) {
+            app.pending_message = Some(alert.message(win.index));
+        } else {
+            ring = true;
+        }
+    }
+    if ring {
+        for c in &app.clients { c.send_directive("%bell".to_string()); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bells_outside_escape_sequences_are_counted() {
+        let mut s = BellScanner::default();
+        assert_eq!(s.feed(b"done\x07 \x1b]0;title\x07\x1b[31m\x07"), 2);
+        // A title split across reads, ended by ST, then by BEL
+        assert_eq!(s.feed(b"\x1b]2;bu"), 0);
+        assert_eq!(s.feed(b"ild\x1b"), 0);
+        assert_eq!(s.feed(b"\\\x07\x1bP+q\x07"), 1);
+        // An ESC that cuts a string short starts a new sequence
+        assert_eq!(s.feed(b"\x1b]2;x\x1b[m\x07"), 1);
+        assert_eq!(s.state, Scan::Ground);
+    }
+}
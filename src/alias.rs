@@ -0,0 +1,159 @@
+//! `command-alias`: command names that stand for a command line.
+//!
+//! Like tmux the option is an array of `name=value` entries:
+//! `set -s command-alias[10] 'zoom=resize-pane -Z'` sets entry 10, `-a`
+//! adds one after the last, and without an index the entry with that name
+//! is replaced (or one is added), so a config can define aliases one line
+//! at a time.  `set -su command-alias[10]` removes an entry.
+//!
+//! Running an alias runs its value with the rest of the invoked line
+//! appended: `zoom -t 2` is `resize-pane -Z -t 2`.  A value may itself
+//! start with an alias, up to `MAX_DEPTH` levels; past that the command
+//! fails rather than looping.
+//!
+//! The server looks aliases up only when it meets a command it does not
+//! know, so an alias cannot change what `split-window` does.  That happens
+//! in one function, `expand`, for a client's commands, for key bindings,
+//! menus and hooks (`execute_command_string`), for the command prompt and
+//! for config lines.
+
+use std::collections::BTreeMap;
+
+/// The entries, by index.
+pub type Aliases = BTreeMap<usize, String>;
+
+/// How many aliases one command may go through.
+pub const MAX_DEPTH: usize = 10;
+
+fn split_entry(entry: &str) -> Option<(&str, &str)> {
+    let (name, value) = entry.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+/// The value of the alias `name` (the first entry with that name).
+pub fn lookup<'a>(aliases: &'a Aliases, name: &str) -> Option<&'a str> {
+    aliases.values().filter_map(|e| split_entry(e)).find(|(n, _)| *n == name).map(|(_, v)| v)
+}
+
+/// `command-alias` → `None`, `command-alias[N]` → `Some(N)`; anything else
+/// is not this option.
+pub fn parse_option(option: &str) -> Option<Option<usize>> {
+    let rest = option.strip_prefix("command-alias")?;
+    if rest.is_empty() { return Some(None); }
+    rest.strip_prefix('[')?.strip_suffix(']')?.parse().ok().map(Some)
+}
+
+/// `set command-alias[N] value` / `set command-alias value`.
+pub fn set(aliases: &mut Aliases, index: Option<usize>, value: &str) {
+    let value = value.trim();
+    let index = match index {
+        Some(i) => i,
+        None => {
+            let Some((name, _)) = split_entry(value) else { return };
+            aliases.iter().find(|(_, e)| split_entry(e).is_some_and(|(n, _)| n == name)).map(|(i, _)| *i)
+                .unwrap_or_else(|| next_index(aliases))
+        }
+    };
+    if value.is_empty() { aliases.remove(&index); } else { aliases.insert(index, value.to_string()); }
+}
+
+/// `set -a command-alias value`: a new entry after the last.
+pub fn append(aliases: &mut Aliases, value: &str) {
+    let value = value.trim();
+    if !value.is_empty() { aliases.insert(next_index(aliases), value.to_string()); }
+}
+
+/// `set -u command-alias[N]` removes the entry, `set -u command-alias` all of them.
+pub fn unset(aliases: &mut Aliases, index: Option<usize>) {
+    match index {
+        Some(i) => { aliases.remove(&i); }
+        None => aliases.clear(),
+    }
+}
+
+fn next_index(aliases: &Aliases) -> usize {
+    aliases.keys().next_back().map_or(0, |i| i + 1)
+}
+
+/// The `show-options` lines, in tmux's indexed form.
+pub fn show_lines(aliases: &Aliases) -> Vec<String> {
+    aliases.iter().map(|(i, e)| format!("command-alias[{}] \"{}\"", i, e)).collect()
+}
+
+/// `line` with its leading alias expanded (again while the result starts
+/// with one), or `None` when it does not start with an alias.
+pub fn expand(aliases: &Aliases, line: &str) -> Result<Option<String>, String> {
+    let mut line = line.trim().to_string();
+    let mut depth = 0;
+    loop {
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+        let Some(value) = lookup(aliases, name) else { break };
+        if depth == MAX_DEPTH { return Err(format!("command alias loop: {}", name)); }
+        let rest = rest.trim_start();
+        line = if rest.is_empty() { value.to_string() } else { format!("{} {}", value, rest) };
+        depth += 1;
+    }
+    Ok((depth > 0).then_some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(entries: &[(usize, &str)]) -> Aliases {
+        entries.iter().map(|(i, e)| (*i, e.to_string())).collect()
+    }
+
+    #[test]
+    fn arguments_are_appended_after_the_expansion() {
+        let a = aliases(&[(0, "sp=split-window -h -c '#{pane_current_path}'"), (1, "zoom=resize-pane -Z")]);
+        assert_eq!(expand(&a, "sp -d -t 1").unwrap().as_deref(), Some("split-window -h -c '#{pane_current_path}' -d -t 1"));
+        assert_eq!(expand(&a, "zoom").unwrap().as_deref(), Some("resize-pane -Z"));
+        assert_eq!(expand(&a, "resize-pane -Z").unwrap(), None);
+        // One alias can name another
+        let a = aliases(&[(0, "z=zoom -t 2"), (1, "zoom=resize-pane -Z")]);
+        assert_eq!(expand(&a, "z").unwrap().as_deref(), Some("resize-pane -Z -t 2"));
+    }
+
+    #[test]
+    fn indexed_entries_are_set_overwritten_and_shown() {
+        let mut a = Aliases::new();
+        set(&mut a, parse_option("command-alias[10]").unwrap(), "zoom=resize-pane -Z");
+        set(&mut a, parse_option("command-alias").unwrap(), "sp=split-window");
+        assert_eq!(show_lines(&a), ["command-alias[10] \"zoom=resize-pane -Z\"", "command-alias[11] \"sp=split-window\""]);
+        // The index replaces whatever was there; a name replaces its own entry
+        set(&mut a, Some(10), "zoom=resize-pane -Z -t 0");
+        set(&mut a, None, "sp=split-window -h");
+        append(&mut a, "nw=new-window");
+        assert_eq!(lookup(&a, "zoom"), Some("resize-pane -Z -t 0"));
+        assert_eq!(lookup(&a, "sp"), Some("split-window -h"));
+        assert_eq!(a.keys().copied().collect::<Vec<_>>(), [10, 11, 12]);
+        unset(&mut a, Some(11));
+        assert_eq!(lookup(&a, "sp"), None);
+        assert_eq!(parse_option("command-aliases"), None);
+        assert_eq!(parse_option("command-alias[x]"), None);
+    }
+
+    #[test]
+    fn aliases_run_from_key_bindings_and_the_prompt() {
+        let mut app = crate::types::AppState::new("t".to_string());
+        crate::config::parse_config_line(&mut app, "set -s command-alias[3] 'sb=set-buffer -b kept'");
+        crate::config::parse_config_line(&mut app, "bind-key y sb from-binding");
+        let (bind, _) = crate::config::lookup_key_binding(&app, "prefix", crate::config::parse_key_name("y").unwrap()).unwrap();
+        crate::commands::execute_action(&mut app, &bind.action).unwrap();
+        assert_eq!(app.paste_buffers.get(Some("kept")).unwrap().text, "from-binding");
+
+        app.mode = crate::types::Mode::CommandPrompt { input: "sb from-prompt".into(), cursor: 0 };
+        crate::commands::execute_command_prompt(&mut app).unwrap();
+        assert_eq!(app.paste_buffers.get(Some("kept")).unwrap().text, "from-prompt");
+    }
+
+    #[test]
+    fn alias_loops_fail() {
+        let a = aliases(&[(0, "a=b -x"), (1, "b=a -y")]);
+        assert_eq!(expand(&a, "a").unwrap_err(), "command alias loop: a");
+        let a = aliases(&[(0, "me=me")]);
+        assert!(expand(&a, "me").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Low-bandwidth mode for clients on slow links (`--low-bandwidth`, or at
+//! runtime `refresh-client -f low-bandwidth` / `-f !low-bandwidth`).
+//!
+//! In this mode frames are capped at [`LOW_BANDWIDTH_FRAME_MS`], carry text
+//! runs without styling, and leave out every pane but the active one until
+//! it is focused.  The client also stops dimming predicted text.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::layout::{LayoutJson, RowRunsJson};
+use crate::types::{AppState, ClientConn};
+
+/// Minimum spacing of frames for a low-bandwidth client (10 fps).
+pub const LOW_BANDWIDTH_FRAME_MS: u64 = 100;
+
+/// Name of the flag in `refresh-client -f` and `#{client_flags}`.
+pub const LOW_BANDWIDTH_FLAG: &str = "low-bandwidth";
+
+/// Client flag for a client that hides the status line (`attach --no-status`).
+pub const NO_STATUS_FLAG: &str = "no-status";
+
+/// Frame variant requested by a client.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameKind {
+    /// Per-cell styling for every pane (the default)
+    Styled,
+    /// Text runs only; panes other than the active one are left out
+    Plain,
+    /// Text runs only for every pane (a low-bandwidth client filling its cache)
+    PlainAll,
+    /// Styled, with each row's runs packed (`FORMAT binary`, see crate::cells)
+    Binary,
+}
+
+impl FrameKind {
+    /// Text runs without styling
+    pub fn is_plain(self) -> bool {
+        matches!(self, FrameKind::Plain | FrameKind::PlainAll)
+    }
+}
+
+/// Milliseconds the client waits between frame requests.
+pub fn frame_interval_ms(low_bandwidth: bool, typing: bool, overlays: bool) -> u64 {
+    if low_bandwidth { LOW_BANDWIDTH_FRAME_MS }
+    else if typing { 10 }
+    else if overlays { 33 }
+    else { 50 }
+}
+
+/// How long a client with frames pushed to it (`client-subscribe`) waits
+/// for input before looking for a new frame: briefly while typing, so the
+/// echo shows at once, and about a 60 Hz tick otherwise.
+pub fn push_poll_ms(typing: bool) -> u64 {
+    if typing { 1 } else { 16 }
+}
+
+/// Apply a `refresh-client -f` list such as `low-bandwidth` or
+/// `!low-bandwidth,read-only` to the current setting of one flag.
+/// Other flags in the list are ignored.
+pub fn apply_client_flag(current: bool, name: &str, spec: &str) -> bool {
+    let mut on = current;
+    for flag in spec.split(',').map(str::trim) {
+        match flag.strip_prefix('!') {
+            Some(f) if f == name => on = false,
+            None if flag == name => on = true,
+            _ => {}
+        }
+    }
+    on
+}
+
+/// `#{client_flags}` for a client.
+pub fn client_flags(low_bandwidth: bool, no_status: bool) -> String {
+    let mut flags = "focused".to_string();
+    if low_bandwidth { flags.push(','); flags.push_str(LOW_BANDWIDTH_FLAG); }
+    if no_status { flags.push(','); flags.push_str(NO_STATUS_FLAG); }
+    flags
+}
+
+/// The client that client-scoped formats and commands apply to when none
+/// is named: the one that sent input most recently.
+pub fn current_client(app: &AppState) -> Option<&ClientConn> {
+    app.clients.iter().max_by_key(|c| c.last_input)
+}
+
+/// Round-trip and size of recent frames on the persistent connection,
+/// used to switch low-bandwidth mode on automatically.
+#[derive(Default)]
+pub struct LinkStats {
+    samples: VecDeque<(Duration, usize)>,
+}
+
+impl LinkStats {
+    const WINDOW: usize = 16;
+    const MIN_SAMPLES: usize = 8;
+    const SLOW_RTT: Duration = Duration::from_millis(150);
+    const SLOW_BYTES_PER_SEC: f64 = 256.0 * 1024.0;
+
+    /// Record one full frame: time from request to reply, and its size.
+    pub fn record(&mut self, rtt: Duration, bytes: usize) {
+        if self.samples.len() == Self::WINDOW { self.samples.pop_front(); }
+        self.samples.push_back((rtt, bytes));
+    }
+
+    /// Median round-trip of at least 150ms, or frames arriving slower than
+    /// 256 KiB/s, over enough recent frames to rule out a one-off stall.
+    pub fn is_slow(&self) -> bool {
+        let Some(median) = self.median_rtt() else { return false };
+        let secs: f64 = self.samples.iter().map(|s| s.0.as_secs_f64()).sum();
+        let bytes: usize = self.samples.iter().map(|s| s.1).sum();
+        median >= Self::SLOW_RTT || (secs > 0.0 && bytes as f64 / secs < Self::SLOW_BYTES_PER_SEC)
+    }
+
+    /// Median round-trip of the recent frames, once there are enough.
+    pub fn median_rtt(&self) -> Option<Duration> {
+        if self.samples.len() < Self::MIN_SAMPLES { return None; }
+        let mut rtts: Vec<Duration> = self.samples.iter().map(|s| s.0).collect();
+        rtts.sort();
+        Some(rtts[rtts.len() / 2])
+    }
+}
+
+/// Fill deferred panes of a plain frame with the rows last received for
+/// them and remember the rows of every pane that was sent.  Returns true
+/// when a deferred pane has nothing cached yet (ask for a `PlainAll` frame).
+pub fn merge_deferred_rows(node: &mut LayoutJson, cache: &mut HashMap<usize, Vec<RowRunsJson>>) -> bool {
+    match node {
+        LayoutJson::Split { children, .. } => {
+            let mut missing = false;
+            for c in children.iter_mut() { missing |= merge_deferred_rows(c, cache); }
+            missing
+        }
+        LayoutJson::Leaf { id, rows_v2, deferred, .. } => {
+            if *deferred {
+                match cache.get(id) {
+                    Some(rows) => { *rows_v2 = rows.clone(); false }
+                    None => true,
+                }
+            } else {
+                cache.insert(*id, rows_v2.clone());
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{snapshot_plain, snapshot_runs, write_plain, write_runs};
+
+    #[test]
+    fn client_flags_toggle_low_bandwidth() {
+        assert!(apply_client_flag(false, LOW_BANDWIDTH_FLAG, "low-bandwidth"));
+        assert!(!apply_client_flag(true, LOW_BANDWIDTH_FLAG, "!low-bandwidth"));
+        assert!(apply_client_flag(true, LOW_BANDWIDTH_FLAG, "read-only,no-output"));
+        assert!(!apply_client_flag(false, LOW_BANDWIDTH_FLAG, "low-bandwidth,!low-bandwidth"));
+        assert_eq!(client_flags(true, false), "focused,low-bandwidth");
+        assert_eq!(client_flags(false, false), "focused");
+    }
+
+    #[test]
+    fn client_flags_toggle_no_status_independently() {
+        assert!(apply_client_flag(false, NO_STATUS_FLAG, "no-status"));
+        assert!(!apply_client_flag(false, LOW_BANDWIDTH_FLAG, "no-status"));
+        assert!(!apply_client_flag(true, NO_STATUS_FLAG, "low-bandwidth,!no-status"));
+        assert_eq!(client_flags(false, true), "focused,no-status");
+        assert_eq!(client_flags(true, true), "focused,low-bandwidth,no-status");
+    }
+
+    #[test]
+    fn slow_links_are_detected_after_enough_frames() {
+        let mut fast = LinkStats::default();
+        let mut slow_rtt = LinkStats::default();
+        let mut thin = LinkStats::default();
+        for i in 0..12 {
+            fast.record(Duration::from_millis(2), 60_000);
+            slow_rtt.record(Duration::from_millis(if i % 4 == 0 { 20 } else { 220 }), 2_000);
+            // 60 KB in 400ms is 150 KB/s
+            thin.record(Duration::from_millis(400), 60_000);
+            if i < 6 { assert!(!slow_rtt.is_slow()); }
+        }
+        assert!(!fast.is_slow());
+        assert!(slow_rtt.is_slow());
+        assert!(thin.is_slow());
+        // One stall on a fast link is not enough
+        fast.record(Duration::from_secs(2), 60_000);
+        assert!(!fast.is_slow());
+    }
+
+    /// A leaf as a plain frame carries it: unstyled runs, or deferred.
+    fn leaf(id: usize, text: &str, deferred: bool) -> LayoutJson {
+        let rows = if deferred { serde_json::json!([]) } else { serde_json::json!([{ "runs": [{ "text": text, "width": text.len() }] }]) };
+        serde_json::from_value(serde_json::json!({
+            "type": "leaf", "id": id, "rows": 1, "cols": 10, "cursor_row": 0, "cursor_col": 0,
+            "active": !deferred, "copy_mode": false, "scroll_offset": 0, "deferred": deferred, "rows_v2": rows,
+        })).unwrap()
+    }
+
+    fn first_text(node: &LayoutJson, want: usize) -> Option<String> {
+        match node {
+            LayoutJson::Split { children, .. } => children.iter().find_map(|c| first_text(c, want)),
+            LayoutJson::Leaf { id, rows_v2, .. } if *id == want => rows_v2.first().map(|r| r.runs[0].text.clone()),
+            LayoutJson::Leaf { .. } => None,
+        }
+    }
+
+    #[test]
+    fn deferred_panes_keep_their_last_rows() {
+        let mut cache = HashMap::new();
+        let mut first = LayoutJson::Split { kind: "Horizontal".into(), sizes: vec![50, 50], children: vec![leaf(1, "one", false), leaf(2, "", true)] };
+        if let LayoutJson::Split { children, .. } = &first {
+            if let LayoutJson::Leaf { rows_v2, .. } = &children[0] { assert_eq!(rows_v2[0].runs[0].fg, "default"); }
+        }
+        // Pane 2 was never sent: the client has to ask for everything once
+        assert!(merge_deferred_rows(&mut first, &mut cache));
+        let mut all = LayoutJson::Split { kind: "Horizontal".into(), sizes: vec![50, 50], children: vec![leaf(1, "one", false), leaf(2, "two", false)] };
+        assert!(!merge_deferred_rows(&mut all, &mut cache));
+        let mut later = LayoutJson::Split { kind: "Horizontal".into(), sizes: vec![50, 50], children: vec![leaf(1, "uno", false), leaf(2, "", true)] };
+        assert!(!merge_deferred_rows(&mut later, &mut cache));
+        assert_eq!(first_text(&later, 2).as_deref(), Some("two"));
+        assert_eq!(first_text(&later, 1).as_deref(), Some("uno"));
+    }
+
+    /// Bytes a client receives over two seconds of a colourful build log in
+    /// the active pane and a progress counter in a second pane, with output
+    /// landing every 10ms while the user types.
+    fn bytes_on_wire(low_bandwidth: bool) -> usize {
+        let (rows, cols) = (24, 80);
+        let mut active = vt100::Parser::new(rows, cols, 0);
+        let mut other = vt100::Parser::new(rows, cols, 0);
+        let interval = frame_interval_ms(low_bandwidth, true, false);
+        let mut total = 0;
+        let mut since_frame = interval;
+        for tick in 0..200u64 {
+            active.process(format!(
+                "\x1b[1;32m   Compiling\x1b[0m crate-{} v0.{}.0 (\x1b[36m/src/crate-{}\x1b[0m) \x1b[33mwarning\x1b[0m: {}\r\n",
+                tick, tick % 9, tick, tick * 7).as_bytes());
+            other.process(format!("\x1b[7m progress \x1b[0m \x1b[44m{:>3}%\x1b[0m\r\n", tick / 2).as_bytes());
+            since_frame += 10;
+            if since_frame < interval { continue; }
+            since_frame = 0;
+            let mut frame = String::new();
+            if low_bandwidth {
+                write_plain(&snapshot_plain(active.screen(), rows, cols), &mut frame);
+                // The inactive pane is only a deferred header
+                frame.push_str(r#"{"type":"leaf","id":2,"rows":24,"cols":80,"cursor_row":0,"cursor_col":0,"active":false,"copy_mode":false,"scroll_offset":0,"deferred":true}"#);
+            } else {
+                write_runs(&snapshot_runs(active.screen(), rows, cols), &mut frame);
+                write_runs(&snapshot_runs(other.screen(), rows, cols), &mut frame);
+            }
+            total += frame.len();
+        }
+        total
+    }
+
+    #[test]
+    fn low_bandwidth_mode_sends_far_fewer_bytes() {
+        let normal = bytes_on_wire(false);
+        let low = bytes_on_wire(true);
+        assert!(low * 20 < normal, "normal {} bytes, low-bandwidth {} bytes", normal, low);
+    }
+}
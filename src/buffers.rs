@@ -0,0 +1,214 @@
+//! Paste buffers.
+//!
+//! Buffers are kept most recent first.  Yanks and unnamed `set-buffer`
+//! create automatic buffers named `buffer0000`, `buffer0001`, ... of
+//! which only the newest `buffer-limit` are kept; buffers given a name
+//! with `-b` stay until deleted.  Commands without `-b` act on the most
+//! recent buffer.
+//!
+//! Copy mode's registers are the buffers named with a single letter `a`
+//! to `z`: `"ay` yanks into buffer `a`, and `set-buffer -b a` or
+//! `paste-buffer -b a` reach the same text.
+
+/// Default for `buffer-limit`.
+pub const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasteBuffer {
+    pub name: String,
+    pub text: String,
+    /// Named automatically, and subject to `buffer-limit`
+    pub automatic: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PasteBuffers {
+    list: Vec<PasteBuffer>,
+    next_auto: usize,
+    /// `buffer-limit`: how many automatic buffers are kept
+    pub limit: usize,
+}
+
+impl Default for PasteBuffers {
+    fn default() -> Self {
+        PasteBuffers { list: Vec::new(), next_auto: 0, limit: DEFAULT_LIMIT }
+    }
+}
+
+impl PasteBuffers {
+    /// Add `text` as a new automatic buffer.
+    pub fn push(&mut self, text: String) {
+        let name = format!("buffer{:04}", self.next_auto);
+        self.next_auto += 1;
+        self.list.insert(0, PasteBuffer { name, text, automatic: true });
+        self.trim();
+    }
+
+    /// Store a yank: into register `reg`'s buffer, or a new automatic
+    /// buffer when None.
+    pub fn yank(&mut self, reg: Option<char>, text: String) {
+        match reg {
+            Some(r) => self.set(Some(&r.to_string()), text),
+            None => self.push(text),
+        }
+    }
+
+    /// Set buffer `name` (a new automatic buffer when None), making it the
+    /// most recent.
+    pub fn set(&mut self, name: Option<&str>, text: String) {
+        let Some(name) = name else { return self.push(text) };
+        let automatic = match self.position(name) {
+            Some(i) => self.list.remove(i).automatic,
+            None => false,
+        };
+        self.list.insert(0, PasteBuffer { name: name.to_string(), text, automatic });
+    }
+
+    /// Append to buffer `name` (the most recent when None); a missing
+    /// buffer is created.
+    pub fn append(&mut self, name: Option<&str>, text: &str) {
+        match self.get_mut(name) {
+            Some(b) => b.text.push_str(text),
+            None => self.set(name, text.to_string()),
+        }
+    }
+
+    /// Buffer `name`, or the most recent when None.
+    pub fn get(&self, name: Option<&str>) -> Option<&PasteBuffer> {
+        self.index_of(name).map(|i| &self.list[i])
+    }
+
+    pub fn get_mut(&mut self, name: Option<&str>) -> Option<&mut PasteBuffer> {
+        self.index_of(name).map(move |i| &mut self.list[i])
+    }
+
+    /// Text of the most recent buffer.
+    pub fn top(&self) -> Option<&String> {
+        self.list.first().map(|b| &b.text)
+    }
+
+    /// Delete buffer `name` (the most recent when None).
+    pub fn delete(&mut self, name: Option<&str>) -> Result<PasteBuffer, String> {
+        let i = self.index_of(name).ok_or_else(|| no_buffer(name))?;
+        Ok(self.list.remove(i))
+    }
+
+    /// The `i`th buffer, most recent first.
+    pub fn at(&self, i: usize) -> Option<&PasteBuffer> { self.list.get(i) }
+
+    pub fn remove_at(&mut self, i: usize) -> Option<PasteBuffer> {
+        (i < self.list.len()).then(|| self.list.remove(i))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PasteBuffer> { self.list.iter() }
+    pub fn len(&self) -> usize { self.list.len() }
+    pub fn is_empty(&self) -> bool { self.list.is_empty() }
+
+    /// Drop the oldest automatic buffers beyond `limit`.
+    pub fn trim(&mut self) {
+        let mut kept = 0;
+        let limit = self.limit;
+        self.list.retain(|b| {
+            if !b.automatic { return true; }
+            kept += 1;
+            kept <= limit
+        });
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.list.iter().position(|b| b.name == name)
+    }
+
+    fn index_of(&self, name: Option<&str>) -> Option<usize> {
+        match name {
+            Some(n) => self.position(n),
+            None => (!self.list.is_empty()).then_some(0),
+        }
+    }
+}
+
+fn no_buffer(name: Option<&str>) -> String {
+    match name {
+        Some(n) => format!("no buffer {}", n),
+        None => "no buffers".to_string(),
+    }
+}
+
+/// The first 50 characters of `text`, with line breaks shown as `\n`.
+pub fn sample(text: &str) -> String {
+    text.chars().take(50).collect::<String>().replace('\n', "\\n").replace('\r', "")
+}
+
+/// Whether buffer `name` is a copy-mode register.
+pub fn is_register(name: &str) -> bool {
+    matches!(name.as_bytes(), [c] if c.is_ascii_lowercase())
+}
+
+/// A `list-buffers` line: `name: N bytes: "sample"`, with ` (register)`
+/// after a register's buffer.
+pub fn list_line(b: &PasteBuffer) -> String {
+    let reg = if is_register(&b.name) { " (register)" } else { "" };
+    format!("{}: {} bytes: \"{}\"{}", b.name, b.text.len(), sample(&b.text), reg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(b: &PasteBuffers) -> Vec<&str> {
+        b.iter().map(|b| b.name.as_str()).collect()
+    }
+
+    #[test]
+    fn automatic_buffers_are_numbered_and_limited() {
+        let mut b = PasteBuffers { limit: 2, ..Default::default() };
+        b.set(Some("keep"), "mine".into());
+        for t in ["a", "b", "c"] { b.push(t.into()); }
+        // The oldest automatic buffer went; the named one is never dropped
+        assert_eq!(names(&b), ["buffer0002", "buffer0001", "keep"]);
+        assert_eq!(b.top().map(String::as_str), Some("c"));
+        assert_eq!(b.get(Some("keep")).unwrap().text, "mine");
+        assert_eq!(list_line(b.at(0).unwrap()), r#"buffer0002: 1 bytes: "c""#);
+        b.limit = 1;
+        b.trim();
+        assert_eq!(names(&b), ["buffer0002", "keep"]);
+    }
+
+    #[test]
+    fn named_buffers_are_replaced_appended_and_deleted() {
+        let mut b = PasteBuffers::default();
+        b.set(Some("x"), "one".into());
+        b.push("auto".into());
+        b.set(Some("x"), "two".into());
+        assert_eq!(names(&b), ["x", "buffer0000"]);
+        b.append(Some("x"), "\nthree");
+        assert_eq!(list_line(b.get(Some("x")).unwrap()), r#"x: 9 bytes: "two\nthree" (register)"#);
+        b.append(Some("new"), "n");
+        assert_eq!(names(&b), ["new", "x", "buffer0000"]);
+
+        // Setting an automatic buffer by name keeps it automatic
+        b.set(Some("buffer0000"), "again".into());
+        assert!(b.get(None).unwrap().automatic);
+
+        assert_eq!(b.delete(None).unwrap().name, "buffer0000");
+        assert_eq!(b.delete(Some("nope")), Err("no buffer nope".to_string()));
+        b.delete(Some("x")).unwrap();
+        b.delete(Some("new")).unwrap();
+        assert_eq!(b.delete(None), Err("no buffers".to_string()));
+    }
+
+    #[test]
+    fn register_yanks_replace_their_buffer_and_outlive_the_limit() {
+        let mut b = PasteBuffers { limit: 1, ..Default::default() };
+        b.yank(Some('a'), "first".into());
+        b.yank(None, "one".into());
+        b.yank(Some('a'), "second".into());
+        b.yank(None, "two".into());
+        // Plain yanks share the automatic stack; register a has one buffer
+        assert_eq!(names(&b), ["buffer0001", "a"]);
+        assert_eq!(b.get(Some("a")).unwrap().text, "second");
+        assert!(!b.get(Some("a")).unwrap().automatic);
+        assert_eq!(list_line(b.get(Some("a")).unwrap()), r#"a: 6 bytes: "second" (register)"#);
+        assert!(is_register("z") && !is_register("ab") && !is_register("A"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Packed style runs for clients that send `FORMAT binary` after
+//! PERSISTENT.  Each row of a pane goes out as one base64 string in the
+//! leaf's `rows_bin` instead of `rows_v2`'s objects; JSON stays the
+//! default because it can be read by eye.
+//!
+//! A row is a varint run count, then for each run:
+//!
+//! - one byte of attribute flags (the `flags` bits of `rows_v2`)
+//! - one byte of colour kinds: fg in bits 0-1, bg in bits 2-3 (0 default,
+//!   1 palette index, 2 RGB)
+//! - the fg and bg colours: nothing, one index byte, or three RGB bytes
+//! - varint display width, varint text length, then the UTF-8 text
+
+use crate::layout::{CellRunJson, LayoutJson};
+use crate::util::{base64_decode_bytes, base64_encode_bytes};
+
+const COLOR_DEFAULT: u8 = 0;
+const COLOR_INDEX: u8 = 1;
+const COLOR_RGB: u8 = 2;
+
+fn push_varint(mut n: usize, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn color_kind(c: vt100::Color) -> u8 {
+    match c {
+        vt100::Color::Default => COLOR_DEFAULT,
+        vt100::Color::Idx(_) => COLOR_INDEX,
+        vt100::Color::Rgb(..) => COLOR_RGB,
+    }
+}
+
+fn push_color(c: vt100::Color, out: &mut Vec<u8>) {
+    match c {
+        vt100::Color::Default => {}
+        vt100::Color::Idx(i) => out.push(i),
+        vt100::Color::Rgb(r, g, b) => out.extend_from_slice(&[r, g, b]),
+    }
+}
+
+/// Start a row of `runs` runs.
+pub fn begin_row(runs: usize, out: &mut Vec<u8>) {
+    push_varint(runs, out);
+}
+
+/// Append one run to the row being built.
+pub fn push_run(text: &str, fg: vt100::Color, bg: vt100::Color, flags: u8, width: u16, out: &mut Vec<u8>) {
+    out.push(flags);
+    out.push(color_kind(fg) | color_kind(bg) << 2);
+    push_color(fg, out);
+    push_color(bg, out);
+    push_varint(width as usize, out);
+    push_varint(text.len(), out);
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// A packed row as it goes into `rows_bin`.
+pub fn row_text(row: &[u8]) -> String {
+    base64_encode_bytes(row)
+}
+
+/// Reads a packed row front to back.
+struct Reader<'a> { bytes: &'a [u8], at: usize }
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.bytes.get(self.at)?;
+        self.at += 1;
+        Some(b)
+    }
+
+    fn varint(&mut self) -> Option<usize> {
+        let mut n = 0usize;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 { return Some(n); }
+        }
+        None
+    }
+
+    /// A colour in the form `rows_v2` uses ("default", "idx:N", "rgb:R,G,B").
+    fn color(&mut self, kind: u8) -> Option<String> {
+        match kind {
+            COLOR_DEFAULT => Some("default".to_string()),
+            COLOR_INDEX => Some(format!("idx:{}", self.byte()?)),
+            COLOR_RGB => Some(format!("rgb:{},{},{}", self.byte()?, self.byte()?, self.byte()?)),
+            _ => None,
+        }
+    }
+
+    fn text(&mut self, len: usize) -> Option<String> {
+        let end = self.at.checked_add(len)?;
+        let s = std::str::from_utf8(self.bytes.get(self.at..end)?).ok()?.to_string();
+        self.at = end;
+        Some(s)
+    }
+}
+
+/// The runs of a row from `rows_bin`, or None if it is malformed.
+pub fn decode_row(encoded: &str) -> Option<Vec<CellRunJson>> {
+    let bytes = base64_decode_bytes(encoded)?;
+    let mut r = Reader { bytes: &bytes, at: 0 };
+    let count = r.varint()?;
+    let mut runs = Vec::with_capacity(count.min(bytes.len()));
+    for _ in 0..count {
+        let flags = r.byte()?;
+        let kinds = r.byte()?;
+        let fg = r.color(kinds & 3)?;
+        let bg = r.color(kinds >> 2 & 3)?;
+        let width = u16::try_from(r.varint()?).ok()?;
+        let len = r.varint()?;
+        let text = r.text(len)?;
+        runs.push(CellRunJson { text, fg, bg, flags, width });
+    }
+    (r.at == bytes.len()).then_some(runs)
+}
+
+/// Unpack every leaf's `rows_bin` into `rows_v2`.  Returns false when a
+/// row is malformed (it is left empty; ask for a whole frame).
+pub fn unpack_rows(node: &mut LayoutJson) -> bool {
+    match node {
+        // Every child is unpacked, even after a malformed one
+        LayoutJson::Split { children, .. } => children.iter_mut().map(unpack_rows).filter(|ok| !ok).count() == 0,
+        LayoutJson::Leaf { rows_v2, rows_bin, .. } => {
+            if rows_bin.is_empty() { return true; }
+            let mut ok = true;
+            *rows_v2 = std::mem::take(rows_bin).iter().map(|row| {
+                let runs = decode_row(row);
+                ok &= runs.is_some();
+                crate::layout::RowRunsJson { runs: runs.unwrap_or_default() }
+            }).collect();
+            ok
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vt100::Color;
+
+    /// Text, colors, flags and display width of one packed run
+    type Run<'a> = (&'a str, Color, Color, u8, u16);
+
+    fn pack(runs: &[Run]) -> String {
+        let mut out = Vec::new();
+        begin_row(runs.len(), &mut out);
+        for &(t, fg, bg, fl, w) in runs { push_run(t, fg, bg, fl, w, &mut out); }
+        row_text(&out)
+    }
+
+    #[test]
+    fn runs_round_trip() {
+        let long = "a long run ".repeat(40);
+        let cases: Vec<Vec<Run>> = vec![
+            vec![],
+            vec![("plain", Color::Default, Color::Default, 0, 5)],
+            // Wide characters keep their display width apart from the byte length
+            vec![("漢字", Color::Idx(2), Color::Default, 0, 4), ("😀", Color::Default, Color::Idx(255), 0, 2)],
+            vec![("rgb", Color::Rgb(255, 0, 128), Color::Rgb(0, 0, 0), 2, 3)],
+            // Inverse and underline together, with bold and dim
+            vec![("x", Color::Idx(0), Color::Rgb(1, 2, 3), 8 | 16, 1), ("y", Color::Default, Color::Default, 1 | 2 | 4 | 8 | 16, 1)],
+            vec![(long.as_str(), Color::Idx(7), Color::Idx(4), 4, 440)],
+            vec![("\"quoted\\\n", Color::Default, Color::Default, 0, 9)],
+        ];
+        for case in cases {
+            let runs = decode_row(&pack(&case)).expect("decodes");
+            assert_eq!(runs.len(), case.len());
+            for (got, &(t, fg, bg, fl, w)) in runs.iter().zip(&case) {
+                assert_eq!(got.text, t);
+                assert_eq!(got.flags, fl);
+                assert_eq!(got.width, w);
+                for (s, c) in [(&got.fg, fg), (&got.bg, bg)] {
+                    let want = match c {
+                        Color::Default => "default".to_string(),
+                        Color::Idx(i) => format!("idx:{}", i),
+                        Color::Rgb(r, g, b) => format!("rgb:{},{},{}", r, g, b),
+                    };
+                    assert_eq!(s, &want);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_frame_rows_match_the_json_ones() {
+        use crate::layout::{snapshot_runs, write_runs, write_runs_bin, RowRunsJson};
+        let mut parser = vt100::Parser::new(3, 20, 0);
+        parser.process("plain \x1b[4;7mboth\x1b[0m \x1b[38;2;10;20;30;48;5;17m漢字\x1b[0m\r\n\x1b[1;2;3mattrs\x1b[0m".as_bytes());
+        let rows = snapshot_runs(parser.screen(), 3, 20);
+        let (mut json, mut packed) = (String::from("{"), String::from("{"));
+        write_runs(&rows, &mut json);
+        write_runs_bin(&rows, &mut packed);
+        json.push('}');
+        packed.push('}');
+
+        #[derive(serde::Deserialize)]
+        struct Rows { #[serde(default)] rows_v2: Vec<RowRunsJson>, #[serde(default)] rows_bin: Vec<String> }
+        let json: Rows = serde_json::from_str(&json).unwrap();
+        let packed: Rows = serde_json::from_str(&packed).unwrap();
+        assert_eq!(packed.rows_bin.len(), 3);
+        for (bin, want) in packed.rows_bin.iter().zip(&json.rows_v2) {
+            let got = RowRunsJson { runs: decode_row(bin).unwrap() };
+            assert_eq!(serde_json::to_value(&got).unwrap(), serde_json::to_value(want).unwrap());
+        }
+        // Fewer bytes than the JSON rows, even as base64
+        let json_len: usize = json.rows_v2.iter().map(|r| serde_json::to_string(r).unwrap().len()).sum();
+        assert!(packed.rows_bin.iter().map(String::len).sum::<usize>() * 2 < json_len);
+    }
+
+    #[test]
+    fn malformed_rows_are_rejected() {
+        let good = pack(&[("abc", Color::Rgb(1, 2, 3), Color::Default, 0, 3)]);
+        let bytes = base64_decode_bytes(&good).unwrap();
+        // Cut short anywhere
+        for n in 0..bytes.len() {
+            assert!(decode_row(&row_text(&bytes[..n])).is_none(), "prefix of {} bytes", n);
+        }
+        // Trailing bytes, an unknown colour kind, text that is not UTF-8
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(decode_row(&row_text(&extra)).is_none());
+        assert!(decode_row(&row_text(&[1, 0, 3, 1, 0])).is_none());
+        assert!(decode_row(&row_text(&[1, 0, 0, 1, 1, 0xff])).is_none());
+    }
+}
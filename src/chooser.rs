@@ -0,0 +1,284 @@
+//! choose-tree and choose-window with tmux's flags, from a key binding or
+//! the command line.
+//!
+//! The server lists the session's windows, each followed by its panes
+//! unless `-w`, keeps the entries the `-f` filter expands true for, orders
+//! the windows by `-O` (index, name, or time of last activity; `-r`
+//! reverses) and sends the attached client a `%choose` directive: one row
+//! per entry, labelled by `-F`, with the command to run when it is chosen.
+//! The client shows the rows in its window chooser.
+//!
+//! A template's `%%` is replaced with the entry's target, `:1` for a window
+//! or `%5` for a pane; without a template choosing selects the entry.  `-Z`
+//! zooms the chosen pane, and with `-s` a filter that leaves a single entry
+//! runs its command at once, without showing the chooser.
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::{expand_format_for_pane, expand_format_for_window};
+use crate::types::AppState;
+
+/// Default row text for a window, as the prefix `w` chooser shows it.
+const WINDOW_LABEL: &str = "(#{window_index}) #{window_index}: #{window_name}#{?window_active,*,} (#{window_panes} panes)";
+
+/// Default row text for a pane.
+const PANE_LABEL: &str = "(#{window_index}.#{pane_index}) #{pane_id}: #{pane_current_command} \"#{pane_title}\" [#{pane_width}x#{pane_height}]#{?pane_active, (active),}";
+
+/// `-O`: the order windows are listed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Order {
+    #[default]
+    Index,
+    Name,
+    /// Most recent activity first
+    Time,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ChooseArgs {
+    /// -w: windows only, no panes
+    pub windows_only: bool,
+    /// -Z: zoom the chosen pane
+    pub zoom: bool,
+    /// -s: with a single entry left, choose it without asking
+    pub single: bool,
+    /// -f: entries this expands false for are left out
+    pub filter: Option<String>,
+    /// -F: the text of each row
+    pub format: Option<String>,
+    pub order: Order,
+    /// -r: reverse the order
+    pub reverse: bool,
+    /// Run for the chosen entry, `%%` replaced by its target
+    pub template: Option<String>,
+}
+
+impl ChooseArgs {
+    /// Parse the arguments after the command name, `-t` already taken out.
+    /// choose-window passes `windows_only`, as if given `-w`.
+    pub fn parse(args: &[&str], windows_only: bool) -> Result<Self, String> {
+        let mut a = ChooseArgs { windows_only, ..Default::default() };
+        let mut it = args.iter();
+        while let Some(&arg) = it.next() {
+            let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+                a.template = Some(arg.to_string());
+                continue;
+            };
+            for (i, c) in flags.char_indices() {
+                match c {
+                    'w' => a.windows_only = true,
+                    'Z' => a.zoom = true,
+                    's' => a.single = true,
+                    'r' => a.reverse = true,
+                    // All sessions, no preview, no confirmation: nothing to change here
+                    'G' | 'N' | 'y' => {}
+                    // The value is the rest of the cluster or the next argument
+                    'f' | 'F' | 'O' | 'K' => {
+                        let rest = &flags[i + 1..];
+                        let v = if rest.is_empty() { it.next().copied() } else { Some(rest) };
+                        let v = v.ok_or_else(|| format!("-{} needs an argument", c))?.to_string();
+                        match c {
+                            'f' => a.filter = Some(v),
+                            'F' => a.format = Some(v),
+                            'O' => a.order = match v.as_str() {
+                                "index" => Order::Index,
+                                "name" => Order::Name,
+                                "time" => Order::Time,
+                                _ => return Err(format!("invalid sort order: {}", v)),
+                            },
+                            // Shortcut key format: rows are picked with the arrows here
+                            _ => {}
+                        }
+                        break;
+                    }
+                    _ => return Err(format!("unknown flag -{}", c)),
+                }
+            }
+        }
+        Ok(a)
+    }
+}
+
+/// One row of the chooser.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Choice {
+    pub label: String,
+    /// What choosing the row runs; may chain commands with ` ; `
+    pub command: String,
+    /// The active window, or the active pane in it; the cursor starts on
+    /// the last such row
+    #[serde(default)]
+    pub current: bool,
+}
+
+/// What choose-tree does with its rows.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// `-s` left one entry: run its command now
+    Run(String),
+    /// Show the rows on the client
+    Show(Vec<Choice>),
+}
+
+/// The rows for `a`: windows in order, each followed by its panes.
+pub fn choices(app: &AppState, a: &ChooseArgs) -> Vec<Choice> {
+    let mut order: Vec<usize> = (0..app.windows.len()).collect();
+    match a.order {
+        Order::Index => order.sort_by_key(|&i| app.windows[i].index),
+        Order::Name => order.sort_by(|&x, &y| app.windows[x].name.cmp(&app.windows[y].name)),
+        Order::Time => order.sort_by_key(|&i| std::cmp::Reverse(app.windows[i].activity_at)),
+    }
+    if a.reverse { order.reverse(); }
+    let shown = |expanded: Option<String>| expanded.is_none_or(|s| crate::format::is_truthy(&s));
+
+    let mut rows = Vec::new();
+    for wi in order {
+        let win = &app.windows[wi];
+        let active = wi == app.active_idx;
+        let zoomed = active && app.zoom_saved.is_some();
+        if shown(a.filter.as_deref().map(|f| expand_format_for_window(f, app, wi))) {
+            rows.push(Choice {
+                label: expand_format_for_window(a.format.as_deref().unwrap_or(WINDOW_LABEL), app, wi),
+                command: command(a, "select-window", &format!(":{}", win.index), zoomed),
+                current: active,
+            });
+        }
+        if a.windows_only { continue; }
+        let mut ids = Vec::new();
+        crate::format::collect_pane_ids(&win.root, &mut ids);
+        let active_pane = crate::tree::active_pane(&win.root, &win.active_path).map(|p| p.id);
+        for (pos, id) in ids.into_iter().enumerate() {
+            if !shown(a.filter.as_deref().map(|f| expand_format_for_pane(f, app, wi, pos))) { continue; }
+            rows.push(Choice {
+                label: format!("  {}", expand_format_for_pane(a.format.as_deref().unwrap_or(PANE_LABEL), app, wi, pos)),
+                command: command(a, "select-pane", &format!("%{}", id), zoomed),
+                current: active && active_pane == Some(id),
+            });
+        }
+    }
+    rows
+}
+
+/// The command choosing `target` runs: the template with `%%` replaced, or
+/// `select` (select-window or select-pane) of the target.  `-Z` adds a
+/// zoom unless the window is zoomed already.
+fn command(a: &ChooseArgs, select: &str, target: &str, zoomed: bool) -> String {
+    let mut cmd = match &a.template {
+        Some(t) => t.replace("%%", target),
+        None => format!("{} -t {}", select, target),
+    };
+    if a.zoom && !zoomed { cmd.push_str(" ; resize-pane -Z"); }
+    cmd
+}
+
+/// The rows for `a`, or the single command to run under `-s`.
+pub fn choose(app: &AppState, a: &ChooseArgs) -> Result<Outcome, String> {
+    let mut rows = choices(app, a);
+    if rows.is_empty() {
+        return Err(if a.filter.is_some() { "no windows or panes match the filter" } else { "nothing to choose" }.to_string());
+    }
+    if a.single && rows.len() == 1 { return Ok(Outcome::Run(rows.remove(0).command)); }
+    Ok(Outcome::Show(rows))
+}
+
+/// The `%choose` directive that shows `rows` on a client.
+pub fn directive(rows: &[Choice]) -> String {
+    format!("%choose {}", serde_json::to_string(rows).unwrap_or_else(|_| "[]".to_string()))
+}
+
+/// Run choose-tree (choose-window with `windows_only`) for the current
+/// client: show it the rows, or return the command `-s` chose at once.
+pub fn run(app: &AppState, args: &[&str], windows_only: bool) -> Result<Option<String>, String> {
+    let a = ChooseArgs::parse(args, windows_only)?;
+    match choose(app, &a)? {
+        Outcome::Run(cmd) => Ok(Some(cmd)),
+        Outcome::Show(rows) => {
+            let client = crate::bandwidth::current_client(app).ok_or_else(|| "no current client".to_string())?;
+            client.send_directive(directive(&rows));
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Window;
+
+    fn bare_window(id: usize, index: usize, name: &str, minutes_ago: i64) -> Window {
+        let at = chrono::Local::now() - chrono::Duration::minutes(minutes_ago);
+        let mut win = Window::bare(id, index, name);
+        (win.activity_at, win.visited_at, win.created_at) = (at, at, at);
+        win
+    }
+
+    /// Windows 1 "build", 2 "logs" (active), 3 "logs-old", most recently
+    /// busy last.
+    fn app() -> AppState {
+        let mut app = AppState::new("work".to_string());
+        app.windows.push(bare_window(0, 1, "build", 30));
+        app.windows.push(bare_window(1, 2, "logs", 20));
+        app.windows.push(bare_window(2, 3, "logs-old", 10));
+        app.active_idx = 1;
+        app
+    }
+
+    fn labels(rows: &[Choice]) -> Vec<&str> {
+        rows.iter().map(|r| r.label.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_and_orders_the_rows() {
+        let app = app();
+        let a = ChooseArgs::parse(&["-w"], false).unwrap();
+        assert_eq!(labels(&choices(&app, &a)), [
+            "(1) 1: build (0 panes)", "(2) 2: logs* (0 panes)", "(3) 3: logs-old (0 panes)",
+        ]);
+
+        let a = ChooseArgs::parse(&["-f", "#{m:logs*,#{window_name}}", "-F", "#{window_name}"], true).unwrap();
+        assert_eq!(labels(&choices(&app, &a)), ["logs", "logs-old"]);
+        let a = ChooseArgs::parse(&["-O", "time", "-F", "#{window_name}"], true).unwrap();
+        assert_eq!(labels(&choices(&app, &a)), ["logs-old", "logs", "build"]);
+        let a = ChooseArgs::parse(&["-rO", "name", "-F", "#I"], true).unwrap();
+        assert_eq!(labels(&choices(&app, &a)), ["3", "2", "1"]);
+
+        // The cursor starts on the active window
+        let rows = choices(&app, &ChooseArgs::parse(&[], true).unwrap());
+        assert_eq!(rows.iter().map(|r| r.current).collect::<Vec<_>>(), [false, true, false]);
+
+        assert_eq!(ChooseArgs::parse(&["-O", "size"], false), Err("invalid sort order: size".to_string()));
+        assert_eq!(ChooseArgs::parse(&["-q"], false), Err("unknown flag -q".to_string()));
+        let none = ChooseArgs::parse(&["-f", "#{==:#{window_name},nope}"], true).unwrap();
+        assert!(choose(&app, &none).is_err());
+    }
+
+    #[test]
+    fn a_single_match_is_chosen_at_once() {
+        let app = app();
+        let a = ChooseArgs::parse(&["-Zsw", "-f", "#{==:#{window_name},build}"], false).unwrap();
+        assert_eq!(choose(&app, &a), Ok(Outcome::Run("select-window -t :1 ; resize-pane -Z".to_string())));
+
+        // Without -s the one row is still shown
+        let a = ChooseArgs::parse(&["-w", "-f", "#{==:#{window_name},build}"], false).unwrap();
+        assert!(matches!(choose(&app, &a), Ok(Outcome::Show(rows)) if rows.len() == 1));
+
+        // Two matches: shown, each with its own command
+        let a = ChooseArgs::parse(&["-s", "-f", "#{m:logs*,#{window_name}}"], true).unwrap();
+        let Ok(Outcome::Show(rows)) = choose(&app, &a) else { panic!("expected rows") };
+        assert_eq!(rows.iter().map(|r| r.command.as_str()).collect::<Vec<_>>(), ["select-window -t :2", "select-window -t :3"]);
+    }
+
+    #[test]
+    fn templates_get_the_target() {
+        let app = app();
+        let a = ChooseArgs::parse(&["-w", "-O", "name", "swap-window -s %% -t :9"], false).unwrap();
+        let rows = choices(&app, &a);
+        assert_eq!(rows[0].command, "swap-window -s :1 -t :9");
+        assert_eq!(rows[2].command, "swap-window -s :3 -t :9");
+
+        // The directive carries the rows to the client intact
+        let d = directive(&rows);
+        let json = d.strip_prefix("%choose ").unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Choice>>(json).unwrap(), rows);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::types::{ParsedTarget, VERSION};
+use crate::types::{ParsedTarget, VERSION, TMUX_COMPAT_VERSION};
 
 pub fn get_program_name() -> String {
     std::env::current_exe()
@@ -26,15 +26,21 @@ SESSION COMMANDS:
     a, at, attach, attach-session
                             Attach to an existing session
         -t <name>           Target session name
+        --no-status         Hide the status line on this client (prefix F11 toggles)
+        --timeout <secs>    Limit on each attach handshake step (default: 5)
     ls, list-sessions       List all active sessions
+        --json              One JSON object per session, across all -L namespaces
     has-session, has        Check if a session exists (exit code 0 = yes)
         -t <name>           Target session name
     kill-session, kill-ses  Kill a session
         -t <name>           Target session name
     kill-server             Kill all sessions and the server
     rename-session, rename  Rename the current session
-    switch-client, switchc  Switch to another session
+    save-session            Save windows and panes to ~/.psmux/resurrect/<name>.json
+    restore-session [name]  Recreate a saved session's windows and panes
+    switch-client, switchc  Switch to another session (-t name, -n, -p, -l)
     list-clients, lsc       List connected clients
+    detach-client, detach   Detach a client (-t client, -a all others)
     server-info, info       Show server information
 
 WINDOW COMMANDS:
@@ -95,6 +101,7 @@ COPY & PASTE COMMANDS:
     save-buffer, saveb      Save buffer to file
     load-buffer, loadb      Load buffer from file
     clear-history, clearhist Clear pane scrollback history
+    clear-screen-and-history Clear screen and scrollback in one step
 
 KEY BINDING COMMANDS:
     bind-key, bind          Bind a key to a command
@@ -103,6 +110,12 @@ KEY BINDING COMMANDS:
     send-keys, send         Send keys to a pane
         -l                  Send literally (no key parsing)
         -t <target>         Target pane
+    send-bytes              Write raw bytes to a pane
+        --hex <hex>         Payload as hex digits
+        --base64 <b64>      Payload as base64
+        --newline           Append Enter (CR)
+        --expect <regex>    Wait for the pane to print a match and print it
+        --timeout <secs>    How long --expect waits (default 2)
 
 CONFIGURATION COMMANDS:
     set-option, set         Set a session/window option
@@ -144,11 +157,20 @@ OPTIONS:
     -h, --help              Show this help message
     -V, --version           Show version information
     -t <target>             Target session, window, or pane
+    -L <name>               Socket name (namespaces session names)
+    --socket-dir <dir>      Session registry directory (default: ~/.psmux)
+    --low-bandwidth         Cap frame rate and drop styling (slow SSH links)
+    --no-daemon             Run the session's server inside this process (debugging)
+    --timeout <secs>        Limit on each attach handshake step (default: 5)
 
 TARGET SYNTAX (-t):
     session:window.pane     Full target path
     :2                      Window 2 in current session
     :2.1                    Pane 1 of window 2
+    work:editor             Window named (or starting with) "editor"
+    work:editor.%5          Pane %5, checked to be in that window
+    :=api                   Window named exactly "api"
+    :api#5                  Window 5 of several named "api"
     %3                      Pane by pane ID
     @4                      Window by window ID
     work:2                  Window 2 in session "work"
@@ -208,9 +230,12 @@ SET OPTIONS (use with: set -g <option> <value>):
     pane-base-index     Int  First pane number (default: 0)
     escape-time         Int  Escape delay in ms (default: 500)
     repeat-time         Int  Repeat key timeout in ms (default: 500)
+    prefix-timeout      Int  Prefix or key table times out after ms, 0 never (default: 0)
+    confirm-kill        Bool prefix x and & ask before killing (default: on)
     history-limit       Int  Scrollback lines (default: 2000)
     display-time        Int  Message display time in ms (default: 750)
     display-panes-time  Int  Pane number display time in ms (default: 1000)
+    pattern-timeout     Int  Time limit for matching a search/format regex in ms (default: 250)
     status-interval     Int  Status refresh interval in sec (default: 15)
     mouse               Bool Mouse support (default: on)
     status              Bool Show status bar (default: on)
@@ -219,11 +244,34 @@ SET OPTIONS (use with: set -g <option> <value>):
     mode-keys           Str  "vi" or "emacs" (default: emacs)
     renumber-windows    Bool Auto-renumber on close (default: off)
     automatic-rename    Bool Auto-rename from foreground process (default: on)
+    automatic-rename-format Str Window name format (default: #{{pane_current_command}})
+    allow-duplicate-window-names Bool Let two windows share a name (default: on)
+    popup-warm-shell    Bool Keep a shell started for the next display-popup (default: on)
+    popup-close-key     Key  Closes a popup while its command runs (default: C-q)
     monitor-activity    Bool Flag windows with new output (default: off)
     monitor-silence     Int  Seconds before silence flag (default: 0)
+    idle-window-timeout Int  Hours unused before a window is idle (default: 0, off)
+    idle-window-action  Str  "flag" or "kill" idle windows (default: flag)
+    monitor-bell        Bool Flag windows that ring a bell (default: on)
+    visual-activity     Bool Message instead of a bell on activity (default: off)
+    visual-silence      Bool Message instead of a bell on silence (default: off)
+    client-idle-timeout Int  Detach idle clients after N sec (default: 0 = off)
+    client-idle-warning Int  Warn N sec before idle detach (default: 0)
+    root-any-binding    Bool Allow "Any" bindings in the root table (default: off)
+    history-file        Str  Save prompt history to this file (default: unset)
+    prompt-history-limit Int Prompt history entries kept (default: 100)
+    buffer-limit        Int  Automatic paste buffers kept (default: 50)
+    scroll-without-copy-mode Bool Wheel scrolls the view, not copy mode (default: off)
+    scrolled-input      Str  "snap" or "block" input while scrolled (default: snap)
+    set-clipboard       Str  OSC 52: "on", "external" or "off" (default: on)
+    local-echo          Str  Show typing before the server echoes it: "on", "auto" or "off" (default: off)
+    command-alias       Arr  name=command entries, e.g. command-alias[10] "zoom=resize-pane -Z"
+    compat-version      Str  tmux version #{{version}} reports to plugins (default: 3.3a)
     synchronize-panes   Bool Send input to all panes (default: off)
+    sync-paste          Bool Pastes follow synchronize-panes too (default: off)
     remain-on-exit      Bool Keep panes after process exits (default: off)
     aggressive-resize   Bool Resize to smallest client (default: off)
+    window-size         Str  smallest, largest, latest or manual (default: smallest)
     set-titles          Bool Update terminal title (default: off)
     set-titles-string   Str  Terminal title format
     default-shell       Str  Shell to launch (default: pwsh)
@@ -244,11 +292,21 @@ SET OPTIONS (use with: set -g <option> <value>):
     status-left-style   Str  Left status area style
     status-right-style  Str  Right status area style
     status-justify      Str  Tab alignment: left, centre, right
+    prefix-indicator    Bool Show prefix/key table/copy state at the right of the status line
+    prefix-indicator-style Str Style of that indicator (default reverse)
     message-style       Str  Message bar style
     message-command-style Str Command prompt style
     mode-style          Str  Copy-mode highlight style
+    copy-mode-match-style Str Copy-mode search match style
+    copy-mode-current-match-style Str Style of the current search match
     pane-border-style   Str  Inactive pane border style
     pane-active-border-style Str Active pane border style
+    pane-border-lines   Str  Splitter lines: single, double, heavy, simple, none
+    pane-border-indicators Str Active pane marks: off, colour, arrows, both
+    pane-border-status  Str  Line above or below each pane: off, top, bottom
+    pane-border-format  Str  What the pane-border-status line shows
+    highlight-pattern   Str  Text kept highlighted: PATTERN=STYLE;... (-w, -p)
+    pane-encoding       Str  Pane output encoding: utf8, cp437, cp1252 (-w, -p)
     window-status-format        Str  Inactive window tab format
     window-status-current-format Str  Active window tab format
     window-status-separator     Str  Separator between tabs
@@ -268,6 +326,7 @@ FORMAT VARIABLES (use in status-left, status-right, display-message, etc.):
     #P  pane_index            #T  pane_title
     #D  pane_id               #H  hostname
     #h  host_short
+    display-message -a lists every variable with its current value.
 
     Conditionals:  #{{?window_active,yes,no}}
     Comparison:    #{{==:#I,1}}  #{{!=:#W,bash}}
@@ -288,6 +347,7 @@ KEY BINDINGS (default prefix: Ctrl+B):
     prefix + &          Kill current window
     prefix + z          Toggle pane zoom
     prefix + d          Detach from session
+    prefix + D          Choose a client to detach
     prefix + [          Enter copy/scroll mode
     prefix + ]          Paste from buffer
     prefix + :          Enter command mode
@@ -319,6 +379,7 @@ ENVIRONMENT VARIABLES:
     PSMUX_CURSOR_STYLE       Cursor style (block, underline, bar)
     PSMUX_CURSOR_BLINK       Cursor blinking (1/0)
     PSMUX_DIM_PREDICTIONS    Prediction dimming (1 to enable)
+    PSMUX_DIR                Session registry directory (same as --socket-dir)
     TMUX                     Set inside psmux panes (tmux-compatible)
     TMUX_PANE                Current pane ID (e.g. %1)
 
@@ -341,9 +402,35 @@ For more information: https://github.com/marlocarlo/psmux
 "#, prog = prog, ver = VERSION);
 }
 
+/// True when run as `tmux` (renamed binary, or the shim plugin scripts call).
+pub fn invoked_as_tmux() -> bool {
+    std::env::args().next().is_some_and(|argv0| is_tmux_name(&argv0))
+        || get_program_name() == "tmux"
+        || std::env::var("PSMUX_AS_TMUX").map(|v| v == "1").unwrap_or(false)
+}
+
+/// `argv0` (a bare name or a Windows or Unix path) names `tmux`, with or
+/// without `.exe`.
+pub fn is_tmux_name(argv0: &str) -> bool {
+    let name = argv0.rsplit(['/', '\\']).next().unwrap_or(argv0).to_lowercase();
+    name.strip_suffix(".exe").unwrap_or(&name) == "tmux"
+}
+
+/// What `-V` prints.  Under the tmux name it is `tmux 3.3a-compat (psmux
+/// x.y.z)`: plugins read the second field (`tmux -V | cut -d' ' -f2`) and
+/// compare it with the release that added a feature, so it leads with the
+/// tmux version psmux covers.  -V runs without a server, so it always gives
+/// the built-in version; `compat-version` only changes `#{version}`.
+pub fn version_line(as_tmux: bool, prog: &str) -> String {
+    if as_tmux {
+        format!("tmux {}-compat (psmux {})", TMUX_COMPAT_VERSION, VERSION)
+    } else {
+        format!("{} {}", prog, VERSION)
+    }
+}
+
 pub fn print_version() {
-    let prog = get_program_name();
-    println!("{} {}", prog, VERSION);
+    println!("{}", version_line(invoked_as_tmux(), &get_program_name()));
 }
 
 pub fn print_commands() {
@@ -353,13 +440,15 @@ pub fn print_commands() {
   break-pane                - Break a pane into a new window
   capture-pane              - Capture the contents of a pane
   choose-buffer (chooseb)   - Choose a paste buffer interactively
+  choose-client             - Choose a client to detach
   choose-tree               - Choose a session, window or pane from a tree
   clear-history (clearhist) - Clear pane scrollback history
+  clear-screen-and-history  - Clear the screen and scrollback
   clock-mode                - Display a large clock in current pane
   confirm-before (confirm)  - Run command after confirmation
   copy-mode                 - Enter copy mode
   delete-buffer             - Delete a paste buffer
-  detach-client (detach)    - Detach from the current session
+  detach-client (detach)    - Detach a client (-t client, -a all others)
   display-menu (menu)       - Display a menu
   display-message           - Display a message in the status line
   display-panes             - Display pane numbers
@@ -395,15 +484,19 @@ pub fn print_commands() {
   previous-window (prev)    - Move to the previous window
   refresh-client (refresh)  - Refresh client display
   rename-session            - Rename a session
+  restore-session           - Recreate windows from a saved snapshot
   rename-window (renamew)   - Rename a window
   resize-pane (resizep)     - Resize a pane
+  resize-window (resizew)   - Set the window size used with no client attached
   respawn-pane              - Respawn a pane
   rotate-window (rotatew)   - Rotate panes in a window
   run-shell (run)           - Run a shell command
   save-buffer (saveb)       - Save buffer to file
+  save-session              - Save windows and panes to a snapshot
   select-layout (selectl)   - Apply a layout preset
   select-pane (selectp)     - Select a pane
   select-window (selectw)   - Select a window
+  send-bytes                - Write raw bytes to a pane
   send-keys                 - Send keys to a pane
   set-buffer (setb)         - Set a paste buffer
   set-environment (setenv)  - Set an environment variable
@@ -417,7 +510,7 @@ pub fn print_commands() {
   source-file (source)      - Execute commands from a file
   split-window (splitw)     - Split a window into panes
   start-server              - Start the psmux server
-  suspend-client (suspendc) - Suspend the client
+  suspend-client (suspendc) - Suspend the client to its parent shell
   swap-pane (swapp)         - Swap two panes
   swap-window (swapw)       - Swap two windows
   switch-client (switchc)   - Switch to another session
@@ -483,27 +576,106 @@ pub fn parse_target(target: &str) -> ParsedTarget {
                 result.window = Some(wid);
                 result.window_is_id = true;
             }
-        } else if let Some(dot_pos) = wp.find('.') {
+        } else if let Some(dot_pos) = wp.rfind('.') {
             if dot_pos > 0 {
-                if let Ok(w) = wp[..dot_pos].parse::<usize>() {
-                    result.window = Some(w);
-                }
+                parse_window_part(&wp[..dot_pos], &mut result);
             }
-            if let Ok(p) = wp[dot_pos + 1..].parse::<usize>() {
+            let pane = &wp[dot_pos + 1..];
+            if let Some(id) = pane.strip_prefix('%') {
+                if let Ok(pid) = id.parse::<usize>() {
+                    result.pane = Some(pid);
+                    result.pane_is_id = true;
+                }
+            } else if let Ok(p) = pane.parse::<usize>() {
                 result.pane = Some(p);
             }
         } else {
-            if let Ok(w) = wp.parse::<usize>() {
-                result.window = Some(w);
-            }
+            parse_window_part(wp, &mut result);
         }
     }
     
     result
 }
 
+/// Window part of a target: a display index, or a window name.  Special
+/// tokens such as `+`, `-`, `!` and `{last}` are left to the commands that
+/// read the raw target.
+fn parse_window_part(w: &str, result: &mut ParsedTarget) {
+    if let Ok(idx) = w.parse::<usize>() {
+        result.window = Some(idx);
+    } else if let Some(id) = w.strip_prefix('@').and_then(|id| id.parse::<usize>().ok()) {
+        result.window = Some(id);
+        result.window_is_id = true;
+    } else if w.chars().next().map_or(false, |c| !"+-!^${~".contains(c)) {
+        result.window_name = Some(w.to_string());
+    }
+}
+
+/// True when a target names a window or pane, not just a session.
+pub fn target_names_pane(t: &ParsedTarget) -> bool {
+    t.window.is_some() || t.window_name.is_some() || t.pane.is_some()
+}
+
+/// Commands whose -t is where something goes (a new window index, the pane
+/// to swap with or join, the session to switch to), not an existing window
+/// to focus first.
+pub fn target_is_destination(cmd: &str) -> bool {
+    matches!(cmd, "new-window" | "neww" | "move-window" | "movew" | "swap-window" | "swapw" | "link-window" | "linkw"
+        | "swap-pane" | "swapp" | "join-pane" | "joinp" | "move-pane" | "movep" | "switch-client" | "switchc")
+}
+
 /// Extract the session name from a target string (for port file lookup)
 pub fn extract_session_from_target(target: &str) -> String {
     let parsed = parse_target(target);
     parsed.session.unwrap_or_else(|| "default".to_string())
 }
+
+/// Split command-line arguments into `;`-separated commands, as in
+/// `tmux start-server\; show-option -gqv @tpm_plugins`.  A standalone `;` or
+/// a trailing `;` ends a command; `\;` is a literal semicolon.
+pub fn split_command_sequence(args: &[String]) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+    for arg in args {
+        if arg == ";" {
+            groups.push(Vec::new());
+        } else if let Some(stripped) = arg.strip_suffix("\\;") {
+            groups.last_mut().unwrap().push(format!("{};", stripped));
+        } else if let Some(stripped) = arg.strip_suffix(';') {
+            if !stripped.is_empty() { groups.last_mut().unwrap().push(stripped.to_string()); }
+            groups.push(Vec::new());
+        } else {
+            groups.last_mut().unwrap().push(arg.clone());
+        }
+    }
+    groups.retain(|g| !g.is_empty());
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_is_recognised_from_argv0() {
+        for argv0 in ["tmux", "TMUX.EXE", r"C:\Tools\tmux.exe", "/usr/local/bin/tmux"] {
+            assert!(is_tmux_name(argv0), "{argv0}");
+        }
+        for argv0 in ["psmux", r"C:\tmux\psmux.exe", "tmux-2", "pmux.exe"] {
+            assert!(!is_tmux_name(argv0), "{argv0}");
+        }
+    }
+
+    #[test]
+    fn version_output_leads_with_a_tmux_version() {
+        assert_eq!(version_line(false, "psmux"), format!("psmux {}", VERSION));
+        let line = version_line(true, "tmux");
+        assert_eq!(line, format!("tmux {}-compat (psmux {})", TMUX_COMPAT_VERSION, VERSION));
+
+        // What tmux-yank and friends do with it:
+        // tmux -V | cut -d' ' -f2 | sed 's/[^0-9.]//g', then compare
+        let field = line.split(' ').nth(1).unwrap();
+        let digits: String = field.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect();
+        assert_eq!(digits, "3.3");
+        assert!(digits.as_str() >= "3.2" && field >= "2.4");
+    }
+}
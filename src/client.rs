@@ -7,16 +7,31 @@ use crossterm::event::{Event, KeyCode, KeyModifiers, KeyEventKind};
 use ratatui::prelude::*;
 use ratatui::widgets::*;
 
-use crate::layout::LayoutJson;
+use crate::layout::{LayoutJson, client_areas, panes_area, mouse_row_to_server, server_row_to_client};
 use crate::help;
-use crate::util::{WinTree, base64_encode};
-use crate::session::read_session_key;
-use crate::rendering::{dim_predictions_enabled, map_color, dim_color, centered_rect};
-use crate::style::parse_tmux_style_components;
-use crate::config::{parse_key_string, normalize_key_for_binding};
-use crate::copy_mode::{copy_to_system_clipboard, read_from_system_clipboard};
+use crate::util::{WinTree, WindowSort, base64_encode};
+use crate::rendering::{dim_predictions_enabled, map_color, dim_color, centered_rect, pane_cursor_position, view_scroll_label, dead_pane_banner, border_status_spans};
+use crate::style::{parse_tmux_style_components, parse_inline_ranges, range_columns, StatusRange};
+use crate::config::parse_key_string;
+use crate::platform::clipboard as system_clipboard;
 use crate::layout::RowRunsJson;
-use crate::tree::split_with_gaps;
+use crate::tree::{border_glyphs, split_with_gaps};
+
+/// Character at a local column position within a row's runs.
+fn char_at_col(runs: &[crate::layout::CellRunJson], local_col: usize) -> char {
+    let mut cursor = 0usize;
+    for run in runs {
+        let run_width = run.width.max(1) as usize;
+        if local_col >= cursor && local_col < cursor + run_width {
+            let offset = local_col - cursor;
+            // Run text may be shorter than run_width (e.g. single char repeated)
+            // or multi-char for wide chars. Pick the nth char if available.
+            return run.text.chars().nth(offset).unwrap_or(' ');
+        }
+        cursor += run_width;
+    }
+    ' '
+}
 
 /// Extract selected text from the layout tree given absolute terminal coordinates.
 /// Computes pane areas via the same Layout splitting render_json uses, then reads
@@ -68,22 +83,6 @@ fn extract_selection_text(
     let mut leaves: Vec<PaneLeaf> = Vec::new();
     collect_leaves(layout, content_area, &mut leaves);
 
-    // Helper: get character at a local column position within a row's runs
-    fn char_at_col(runs: &[crate::layout::CellRunJson], local_col: usize) -> char {
-        let mut cursor = 0usize;
-        for run in runs {
-            let run_width = run.width.max(1) as usize;
-            if local_col >= cursor && local_col < cursor + run_width {
-                let offset = local_col - cursor;
-                // Run text may be shorter than run_width (e.g. single char repeated)
-                // or multi-char for wide chars. Pick the nth char if available.
-                return run.text.chars().nth(offset).unwrap_or(' ');
-            }
-            cursor += run_width;
-        }
-        ' '
-    }
-
     let mut result = String::new();
     for row in r0..=r1 {
         let col_start = if row == r0 { c0 } else { 0 };
@@ -120,6 +119,150 @@ fn extract_selection_text(
 
 /// Check if screen coordinates (x, y) fall on a separator line in the layout.
 /// Used to distinguish border-drag (resize) from text selection on left-click.
+/// Rows of the window chooser: a header per session, then its windows in
+/// `sort` order, with their panes for the current session.  Entries are
+/// (is_win, window id, pane id, label, session name); headers have id
+/// usize::MAX.  Windows read `(1) 1: editor* (3 panes)` and panes
+/// `(1.2) %5: pwsh "title" [80x24]`, by display index, as in tmux.
+fn tree_chooser_entries(sessions: &[(String, Vec<WinTree>)], current: &str, sort: WindowSort) -> Vec<(bool, usize, usize, String, String)> {
+    let mut entries = Vec::new();
+    for (sess_name, wins) in sessions {
+        let is_current = sess_name == current;
+        let attached = if is_current { " (attached)" } else { "" };
+        entries.push((true, usize::MAX, 0, format!("{}: {} windows{}", sess_name, wins.len(), attached), sess_name.clone()));
+        for wi in sort.order(wins) {
+            let w = &wins[wi];
+            let flag = if w.active { "*" } else { "" };
+            entries.push((true, w.id, 0, format!("  ({}) {}: {}{} ({} panes)", w.index, w.index, w.name, flag, w.panes.len()), sess_name.clone()));
+            if is_current {
+                for p in &w.panes {
+                    let active = if p.active && w.active { " (active)" } else { "" };
+                    let label = format!("    ({}.{}) %{}: {} \"{}\" [{}x{}]{}", w.index, p.index, p.id, p.command, p.title, p.width, p.height, active);
+                    entries.push((false, w.id, p.id, label, sess_name.clone()));
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// The row to select when the chooser opens: the current session's active
+/// pane, else its active window.
+fn tree_chooser_current(entries: &[(bool, usize, usize, String, String)], sessions: &[(String, Vec<WinTree>)], current: &str) -> usize {
+    let Some(w) = sessions.iter().find(|s| s.0 == current).and_then(|s| s.1.iter().find(|w| w.active)) else { return 0 };
+    let pane = w.panes.iter().find(|p| p.active).map(|p| p.id);
+    let here = |e: &&(bool, usize, usize, String, String)| e.4 == current && e.1 == w.id;
+    entries.iter().position(|e| here(&e) && !e.0 && Some(e.2) == pane)
+        .or_else(|| entries.iter().position(|e| here(&e) && e.0))
+        .unwrap_or(0)
+}
+
+/// The session chooser's rows and the one to select: every running server,
+/// or just this session when the registry turns up nothing.
+fn session_chooser_rows(dir: &str, current: &str) -> (Vec<(String, String)>, usize) {
+    let mut rows = crate::session::chooser_sessions(dir);
+    if rows.is_empty() {
+        rows.push((current.to_string(), format!("{}: (current)", current)));
+    }
+    let selected = rows.iter().position(|(name, _)| name == current).unwrap_or(0);
+    (rows, selected)
+}
+
+/// Does `label` hold the characters of `filter` in order, in any case?
+/// The choose-tree filter: `edma` finds `editor ... main.rs`.
+fn fuzzy_match(label: &str, filter: &str) -> bool {
+    let mut chars = label.chars().flat_map(char::to_lowercase);
+    filter.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).all(|f| chars.any(|c| c == f))
+}
+
+/// The rows of the window chooser on screen, as indices into `entries`.
+/// Collapsed sessions and windows (`(session, usize::MAX)` and
+/// `(session, window id)` in `collapsed`) hide their rows; with a filter
+/// only the rows matching it show, each with the session and window above
+/// it, collapsed or not.
+fn tree_visible_rows(entries: &[(bool, usize, usize, String, String)], collapsed: &std::collections::HashSet<(String, usize)>, filter: &str) -> Vec<usize> {
+    if !filter.trim().is_empty() {
+        let mut keep = vec![false; entries.len()];
+        let (mut header, mut window) = (None, None);
+        for (i, e) in entries.iter().enumerate() {
+            if e.0 && e.1 == usize::MAX { header = Some(i); window = None; } else if e.0 { window = Some(i); }
+            if fuzzy_match(&e.3, filter) {
+                keep[i] = true;
+                for above in [header, window].into_iter().flatten() { keep[above] = true; }
+            }
+        }
+        return (0..entries.len()).filter(|&i| keep[i]).collect();
+    }
+    entries.iter().enumerate().filter(|(_, e)| {
+        let session_shut = collapsed.contains(&(e.4.clone(), usize::MAX));
+        match (e.0, e.1) {
+            (true, usize::MAX) => true,
+            (true, _) => !session_shut,
+            (false, wid) => !session_shut && !collapsed.contains(&(e.4.clone(), wid)),
+        }
+    }).map(|(i, _)| i).collect()
+}
+
+/// The row above `i` in the tree: a pane's window, a window's session.
+fn tree_parent(entries: &[(bool, usize, usize, String, String)], i: usize) -> Option<usize> {
+    let e = entries.get(i)?;
+    let window = |p: &(bool, usize, usize, String, String)| p.0 && p.4 == e.4 && p.1 == e.1;
+    let header = |p: &(bool, usize, usize, String, String)| p.0 && p.4 == e.4 && p.1 == usize::MAX;
+    match (e.0, e.1) {
+        (true, usize::MAX) => None,
+        (true, _) => entries[..i].iter().rposition(header),
+        (false, _) => entries[..i].iter().rposition(window),
+    }
+}
+
+/// Does row `i` have rows below it in the tree (panes, or windows)?
+fn tree_has_children(entries: &[(bool, usize, usize, String, String)], i: usize) -> bool {
+    i + 1 < entries.len() && tree_parent(entries, i + 1) == Some(i)
+}
+
+/// The row `step` places down (negative: up) from `selected` among the
+/// rows on screen, or the first of them when `selected` is hidden.
+fn tree_step(visible: &[usize], selected: usize, step: isize) -> usize {
+    match visible.iter().position(|&i| i == selected) {
+        Some(pos) => visible[pos.saturating_add_signed(step).min(visible.len() - 1)],
+        None => visible.iter().copied().find(|&i| i > selected).or(visible.first().copied()).unwrap_or(selected),
+    }
+}
+
+/// The pane the chooser previews for a row: a pane row's own, a window's
+/// active pane, a session's active window's.
+fn tree_preview_pane(e: &(bool, usize, usize, String, String), sessions: &[(String, Vec<WinTree>)]) -> Option<(String, usize)> {
+    if !e.0 { return Some((e.4.clone(), e.2)); }
+    let wins = &sessions.iter().find(|s| s.0 == e.4)?.1;
+    let w = if e.1 == usize::MAX { wins.iter().find(|w| w.active) } else { wins.iter().find(|w| w.id == e.1) }?;
+    w.panes.iter().find(|p| p.active).or(w.panes.first()).map(|p| (e.4.clone(), p.id))
+}
+
+/// How often the chooser reads the previewed pane again, and the least
+/// time between two reads as the highlight moves, so holding an arrow
+/// does not send a capture-pane per row.
+const PREVIEW_REFRESH: Duration = Duration::from_millis(500);
+const PREVIEW_MIN_GAP: Duration = Duration::from_millis(100);
+
+/// Is it time to capture `wanted` for the preview, `shown` having been
+/// captured at `at`?
+fn preview_due(shown: Option<(&(String, usize), Instant)>, wanted: &(String, usize), now: Instant) -> bool {
+    match shown {
+        None => true,
+        Some((pane, at)) => now.saturating_duration_since(at) >= if pane == wanted { PREVIEW_REFRESH } else { PREVIEW_MIN_GAP },
+    }
+}
+
+/// Pane `%id`'s screen in `session` (capture-pane -p), for the preview.
+fn capture_pane_text(session: &str, pane_id: usize) -> Option<String> {
+    let mut ss = crate::transport::connect(session, 200).ok()?;
+    writeln!(ss, "capture-pane -p -t %{}", pane_id).ok()?;
+    ss.flush().ok()?;
+    let mut text = String::new();
+    io::Read::read_to_string(&mut ss, &mut text).ok()?;
+    Some(text)
+}
+
 fn is_on_separator(layout: &LayoutJson, area: Rect, x: u16, y: u16) -> bool {
     match layout {
         LayoutJson::Leaf { .. } => false,
@@ -160,6 +303,313 @@ fn is_on_separator(layout: &LayoutJson, area: Rect, x: u16, y: u16) -> bool {
     }
 }
 
+/// Split the terminal into the panes' area and `status_lines` rows of
+/// status, above the panes for `status-position top`.  A hidden status
+/// takes no rows.
+fn status_layout(size: Rect, status_lines: u16, hide_status: bool, position: &str) -> (Rect, Rect) {
+    client_areas(size, if hide_status { 0 } else { status_lines }, position == "top")
+}
+
+/// Row `line_idx` of the status area, unless the area is shorter.
+fn status_line_area(status: Rect, line_idx: usize) -> Option<Rect> {
+    let y = status.y.checked_add(u16::try_from(line_idx).ok()?)?;
+    (y < status.bottom()).then_some(Rect { x: status.x, y, width: status.width, height: 1 })
+}
+
+/// The status range drawn under (`column`, `row`), on whichever status line.
+fn status_range_at(ranges: &[(u16, u16, u16, StatusRange)], column: u16, row: u16) -> Option<&StatusRange> {
+    ranges.iter().find(|(y, start, end, _)| *y == row && (*start..*end).contains(&column)).map(|(_, _, _, range)| range)
+}
+
+/// The table a `switch-client -T` command, or the last one in a chain,
+/// switches to.
+fn switch_table_of(cmd: &str) -> Option<String> {
+    crate::config::split_chained_commands_pub(cmd).iter().rev().find_map(|c| {
+        let mut words = c.split_whitespace();
+        if !matches!(words.next(), Some("switch-client" | "switchc")) { return None; }
+        words.skip_while(|w| *w != "-T").nth(1).map(str::to_string)
+    })
+}
+
+/// A single key binding synced from the server.
+#[derive(serde::Deserialize, Clone, Debug)]
+struct BindingEntry {
+    /// Key table name (e.g. "prefix", "root")
+    t: String,
+    /// Key string (e.g. "C-a", "-", "F12")
+    k: String,
+    /// Command string (e.g. "split-window -v")
+    c: String,
+    /// Whether the binding is repeatable
+    #[serde(default)]
+    r: bool,
+}
+
+/// The synced binding `key` runs in `table`, found as the server's
+/// lookup_key_binding finds it: an exact match, else the table's `Any`
+/// binding (in root only with root-any-binding on), whose command gets the
+/// key's name for `#{key}`.
+fn synced_binding(bindings: &[BindingEntry], table: &str, key: (KeyCode, KeyModifiers), root_any: bool) -> Option<BindingEntry> {
+    let in_table = bindings.iter().filter(|b| b.t == table);
+    // Names come from the server's format_key_binding, so parse them as bind-key does
+    let (entry, any) = crate::config::match_key_binding(in_table, |b| crate::config::parse_key_name(&b.k), table != "root" || root_any, key)?;
+    let mut entry = entry.clone();
+    if any { entry.c = crate::config::command_with_key(&entry.c, &crate::config::key_name_for_binding(key)); }
+    Some(entry)
+}
+
+/// Where the attached client's next key is looked up: after the prefix,
+/// in the repeat window of the last `-r` binding, or in a table chosen with
+/// switch-client -T.  The client keeps this itself so a key is routed
+/// without waiting for the server's next frame.
+struct KeyRouting {
+    prefix_armed: bool,
+    armed_at: Instant,
+    repeat_until: Option<Instant>,
+    table: Option<(String, Instant)>,
+}
+
+impl KeyRouting {
+    fn new() -> Self {
+        KeyRouting { prefix_armed: false, armed_at: Instant::now(), repeat_until: None, table: None }
+    }
+
+    fn arm(&mut self, now: Instant) {
+        self.prefix_armed = true;
+        self.armed_at = now;
+    }
+
+    /// Before a key is looked up.  In the repeat window only a repeatable
+    /// key skips the prefix; any other key, or one after repeat-time, is an
+    /// ordinary key, as is one after prefix-timeout.  Returns the table the
+    /// key is looked up in first, if one was chosen and has not timed out.
+    fn begin_key(&mut self, repeats: impl FnOnce() -> bool, timeout: Option<Duration>, now: Instant) -> Option<String> {
+        let timed_out = |since: Instant| timeout.is_some_and(|t| now.saturating_duration_since(since) >= t);
+        if let Some(until) = self.repeat_until.take() {
+            if repeats() && now < until { self.repeat_until = Some(until); } else { self.prefix_armed = false; }
+        }
+        if self.prefix_armed && self.repeat_until.is_none() && timed_out(self.armed_at) { self.prefix_armed = false; }
+        self.table.take().filter(|(_, since)| !timed_out(*since)).map(|(table, _)| table)
+    }
+
+    /// After a key in the prefix table: a -r binding keeps the prefix for
+    /// repeat-time.
+    fn prefix_key_done(&mut self, repeats: bool, repeat_time: Duration, now: Instant) {
+        if repeats { self.repeat_until = Some(now + repeat_time); } else { self.prefix_armed = false; }
+    }
+
+    /// Take up the table switches among the commands a key sent.  The
+    /// prefix table is our own armed prefix, so a lone switch to it is
+    /// dropped.  Returns whether any command switched tables.
+    fn note_switches(&mut self, sent: &mut Vec<String>, now: Instant) -> bool {
+        let mut switched = false;
+        sent.retain(|c| match switch_table_of(c).as_deref() {
+            Some("prefix") => {
+                self.arm(now);
+                switched = true;
+                // Only a lone switch is dropped; a chain still has work for the server
+                crate::config::split_chained_commands_pub(c).len() > 1
+            }
+            Some("root") => { switched = true; true }
+            Some(table) => { self.table = Some((table.to_string(), now)); switched = true; true }
+            None => true,
+        });
+        switched
+    }
+
+    /// The repeat window closes on its own, and with prefix-timeout so do
+    /// the prefix and a key table.  True when a table lapsed, which the
+    /// server is told as a switch back to root.
+    fn expire(&mut self, timeout: Option<Duration>, now: Instant) -> bool {
+        if self.repeat_until.is_some_and(|t| now >= t) {
+            self.repeat_until = None;
+            self.prefix_armed = false;
+        }
+        let Some(timeout) = timeout else { return false };
+        if self.prefix_armed && self.repeat_until.is_none() && now.saturating_duration_since(self.armed_at) >= timeout { self.prefix_armed = false; }
+        self.table.take_if(|(_, since)| now.saturating_duration_since(*since) >= timeout).is_some()
+    }
+}
+
+/// Is the active pane in copy mode?
+fn active_in_copy_mode(node: &LayoutJson) -> bool {
+    match node {
+        LayoutJson::Leaf { active, copy_mode, .. } => *active && *copy_mode,
+        LayoutJson::Split { children, .. } => children.iter().any(active_in_copy_mode),
+    }
+}
+
+/// Give local echo the active pane of a new frame.  Typing is predictable
+/// on the live main screen with the cursor showing, unless the text before
+/// the cursor asks for a password.
+fn reconcile_local_echo(predictor: &mut crate::predict::Predictor, node: &LayoutJson, now: Instant) {
+    match node {
+        LayoutJson::Leaf { id, cols, cursor_row, cursor_col, cursor_hidden, alternate_screen, active: true, copy_mode, view_offset, rows_v2, dead, .. } => {
+            let row = |r: u16| rows_v2.get(r as usize).map_or(&[][..], |r| &r.runs[..]);
+            let before_cursor: String = (0..*cursor_col as usize).map(|c| char_at_col(row(*cursor_row), c)).collect();
+            let cell = |r: u16, c: u16| char_at_col(row(r), c as usize);
+            predictor.frame(now, &crate::predict::PaneFrame {
+                pane: *id,
+                cursor: (*cursor_row, *cursor_col),
+                cols: *cols,
+                predictable: !alternate_screen && !copy_mode && *view_offset == 0 && !cursor_hidden && !dead
+                    && !crate::predict::secret_prompt(&before_cursor),
+                cell: &cell,
+            });
+        }
+        LayoutJson::Leaf { .. } => {}
+        LayoutJson::Split { children, .. } => for c in children { reconcile_local_echo(predictor, c, now) },
+    }
+}
+
+/// Draw local echo's pending predictions dimmed over the active pane, with
+/// the cursor after them.
+fn draw_local_echo(f: &mut Frame, predictor: &crate::predict::Predictor, area: Rect) {
+    for (r, c, ch) in predictor.cells() {
+        if r < area.height && c < area.width {
+            let cell = &mut f.buffer_mut()[(area.x + c, area.y + r)];
+            cell.set_char(ch);
+            cell.modifier.insert(Modifier::DIM);
+        }
+    }
+    if let Some((r, c)) = predictor.cursor() {
+        if r < area.height && c < area.width { f.set_cursor_position((area.x + c, area.y + r)); }
+    }
+}
+
+/// A terminal that pastes its selection on middle-click may report the
+/// click as well.  Both arrive within moments of each other and only the
+/// first should paste.
+#[derive(Default)]
+struct MiddlePasteGuard {
+    click: Option<Instant>,
+    paste: Option<Instant>,
+}
+
+impl MiddlePasteGuard {
+    const WINDOW: Duration = Duration::from_millis(250);
+
+    /// A middle-click arrived: should it go to the server?
+    fn click(&mut self, now: Instant) -> bool {
+        let doubled = self.paste.is_some_and(|t| now.duration_since(t) < Self::WINDOW);
+        self.click = (!doubled).then_some(now);
+        !doubled
+    }
+
+    /// A paste arrived from the terminal: should it go to the pane?
+    fn paste(&mut self, now: Instant) -> bool {
+        let doubled = self.click.is_some_and(|t| now.duration_since(t) < Self::WINDOW);
+        self.paste = (!doubled).then_some(now);
+        !doubled
+    }
+}
+
+/// Counts left-clicks landing on the same cell within `@double-click-time`
+/// of each other: 2 selects a word, 3 a line.
+#[derive(Default)]
+struct ClickCounter {
+    last: Option<(Instant, u16, u16)>,
+    count: u8,
+}
+
+impl ClickCounter {
+    /// A left button press at (`col`, `row`); returns the click count.
+    fn press(&mut self, now: Instant, col: u16, row: u16, interval: Duration) -> u8 {
+        let repeat = self.last.is_some_and(|(t, c, r)| (c, r) == (col, row) && now.duration_since(t) <= interval);
+        self.count = if repeat && self.count < 3 { self.count + 1 } else { 1 };
+        self.last = Some((now, col, row));
+        self.count
+    }
+
+    /// Forget the run of clicks (a drag started).
+    fn reset(&mut self) {
+        self.last = None;
+        self.count = 0;
+    }
+}
+
+/// What the client keeps from earlier frames to skip work.  A repaint
+/// (`refresh-client`, prefix R) drops all of it along with the terminal's
+/// contents and asks for a whole frame.
+#[derive(Default)]
+struct RenderCache {
+    /// The last frame drawn; the same frame again is not redrawn
+    frame: String,
+    /// Rows of the panes a low-bandwidth frame leaves out, by pane id
+    deferred_rows: std::collections::HashMap<usize, Vec<RowRunsJson>>,
+    /// set-titles: the title last given to the terminal
+    title: String,
+    /// The next dump-state asks for a whole frame (`-F`)
+    want_full_frame: bool,
+    /// Rows of the last frame, which delta frames are rebuilt from
+    rows: crate::delta::FrameRows,
+}
+
+impl RenderCache {
+    /// Forget everything drawn so far and ask for a whole frame next.
+    fn repaint(&mut self) {
+        *self = RenderCache { want_full_frame: true, ..Default::default() };
+    }
+
+    /// The dump-state request to send next; `all_panes` asks a low-bandwidth
+    /// frame for every pane.
+    fn request(&mut self, all_panes: bool) -> &'static [u8] {
+        match (std::mem::take(&mut self.want_full_frame), all_panes) {
+            (true, true) => b"dump-state -F -a\n",
+            (true, false) => b"dump-state -F -D\n",
+            (false, true) => b"dump-state -a\n",
+            (false, false) => b"dump-state -D\n",
+        }
+    }
+}
+
+/// `pane-border-indicators`: (colour the active pane's borders, draw arrows
+/// on them).
+fn border_indicators(mode: &str) -> (bool, bool) {
+    match mode {
+        "off" => (false, false),
+        "arrows" => (false, true),
+        "both" => (true, true),
+        _ => (true, false),
+    }
+}
+
+/// Arrows at the middle of each border of the active pane `ar`, pointing
+/// into it.  A side on the edge of the window `area` has no border and gets
+/// no arrow.
+fn border_arrows(ar: Rect, area: Rect) -> Vec<(u16, u16, char)> {
+    let (mid_x, mid_y) = (ar.x + ar.width / 2, ar.y + ar.height / 2);
+    let mut arrows = Vec::new();
+    if ar.x > area.x { arrows.push((ar.x - 1, mid_y, '▶')); }
+    if ar.right() < area.right() { arrows.push((ar.right(), mid_y, '◀')); }
+    if ar.y > area.y { arrows.push((mid_x, ar.y - 1, '▼')); }
+    if ar.bottom() < area.bottom() { arrows.push((mid_x, ar.bottom(), '▲')); }
+    arrows
+}
+
+fn draw_border_arrows(buf: &mut Buffer, ar: Rect, area: Rect, fg: Color) {
+    for (x, y, ch) in border_arrows(ar, area) {
+        if let Some(cell) = buf.cell_mut((x, y)) {
+            cell.set_char(ch);
+            cell.set_style(Style::default().fg(fg));
+        }
+    }
+}
+
+/// Merge the styles of a pane's `highlight-pattern` matches over its cells
+/// in `area`, in order, so later entries win where matches overlap.
+fn draw_highlights(buf: &mut Buffer, area: Rect, highlights: &[crate::highlight::Match], styles: &[String]) {
+    if highlights.is_empty() { return; }
+    let styles: Vec<Style> = styles.iter().map(|s| crate::rendering::parse_tmux_style(s)).collect();
+    for &(r, c0, c1, k) in highlights {
+        let Some(&style) = styles.get(k) else { continue };
+        if r >= area.height { continue; }
+        for c in c0..=c1.min(area.width.saturating_sub(1)) {
+            if let Some(cell) = buf.cell_mut((area.x + c, area.y + r)) { cell.set_style(style); }
+        }
+    }
+}
+
 /// Check if any leaf in a LayoutJson subtree is the active pane.
 /// Compute the rectangle of the active pane by searching the LayoutJson tree.
 fn compute_active_rect_json(node: &LayoutJson, area: Rect) -> Option<Rect> {
@@ -187,41 +637,93 @@ fn compute_active_rect_json(node: &LayoutJson, area: Rect) -> Option<Rect> {
     }
 }
 
-pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input: &crate::ssh_input::InputSource) -> io::Result<()> {
+/// Each leaf's rectangle in `area`, whether it is active, and its
+/// `pane-border-status` text.
+fn leaf_borders_json<'a>(node: &'a LayoutJson, area: Rect, out: &mut Vec<(Rect, bool, Option<&'a str>)>) {
+    match node {
+        LayoutJson::Leaf { active, border_text, .. } => out.push((area, *active, border_text.as_deref())),
+        LayoutJson::Split { kind, sizes, children } => {
+            let effective_sizes: Vec<u16> = if sizes.len() == children.len() {
+                sizes.clone()
+            } else {
+                vec![(100 / children.len().max(1)) as u16; children.len()]
+            };
+            let rects = split_with_gaps(kind == "Horizontal", &effective_sizes, area);
+            for (child, rect) in children.iter().zip(rects) { leaf_borders_json(child, rect, out); }
+        }
+    }
+}
+
+/// The `pane-border-status` line.  The row of `area` left over from the
+/// `panes` is drawn as border, and each pane's border above or below it
+/// carries its text, in the active border colour for the active pane.
+fn draw_border_status(buf: &mut Buffer, root: &LayoutJson, area: Rect, panes: Rect, glyph: char, fg: (Color, Color)) {
+    let top = panes.y > area.y;
+    let extra_row = if top { area.y } else { panes.bottom() };
+    let mut leaves = Vec::new();
+    leaf_borders_json(root, panes, &mut leaves);
+    for x in area.x..area.right() {
+        if let Some(cell) = buf.cell_mut((x, extra_row)) { cell.set_char(glyph).set_style(Style::default().fg(fg.0)); }
+    }
+    for (rect, active, text) in leaves {
+        if rect.width == 0 || rect.height == 0 { continue; }
+        let y = if top { rect.y - 1 } else { rect.bottom() };
+        let style = Style::default().fg(if active { fg.1 } else { fg.0 });
+        // Splitters between panes keep their own colouring
+        if y == extra_row {
+            for x in rect.x..rect.right() {
+                if let Some(cell) = buf.cell_mut((x, y)) { cell.set_style(style); }
+            }
+        }
+        if let Some(text) = text {
+            let width = rect.width.saturating_sub(2);
+            buf.set_line(rect.x + 1, y, &Line::from(border_status_spans(text, width, style)), width);
+        }
+    }
+}
+
+/// Default limit on each step of the attach handshake (`--timeout`).
+pub const ATTACH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A client connection that has finished the attach handshake.
+pub struct Attached {
+    writer: crate::transport::Stream,
+    /// Lines from the server, read on a thread of their own
+    lines: std::sync::mpsc::Receiver<String>,
+    /// Lines read during the handshake (the first frame and any directives
+    /// before it), handled before anything newer
+    pending: std::collections::VecDeque<String>,
+}
+
+/// The limit on each handshake step: `--timeout` seconds
+/// (PSMUX_ATTACH_TIMEOUT), or ATTACH_TIMEOUT.
+fn attach_timeout() -> Duration {
+    env::var("PSMUX_ATTACH_TIMEOUT").ok().and_then(|s| s.parse::<f64>().ok())
+        .filter(|s| *s > 0.0).map_or(ATTACH_TIMEOUT, Duration::from_secs_f64)
+}
+
+/// Connect to the session named by PSMUX_SESSION_NAME and attach to it:
+/// PERSISTENT, client-attach, then the first frame.  Every step is
+/// bounded by the attach timeout, so a wedged server gives an error naming
+/// the step instead of a hang.  Nothing here touches the terminal, which is
+/// only set up once this succeeds.
+pub fn attach_handshake() -> io::Result<Attached> {
     let name = env::var("PSMUX_SESSION_NAME").unwrap_or_else(|_| "default".to_string());
-    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-    let session_key = read_session_key(&name).unwrap_or_default();
-    let last_path = format!("{}\\.psmux\\last_session", home);
+    let registry_dir = crate::session::psmux_dir();
+    let last_path = format!("{}\\last_session", registry_dir);
     let _ = std::fs::write(&last_path, &name);
+    let timeout = attach_timeout();
 
-    // ── Open persistent named pipe connection ────────────────────────────
-    let handle = crate::pipe::connect_to_pipe(&name, 5000)?;
-    let stream = crate::pipe::PipeStream::from_handle(handle);
-    let _ = stream.set_nodelay(true); // no-op for pipes
+    // ── Open persistent connection (AUTH is done here if TCP wants it) ──
+    let stream = crate::transport::connect(&name, timeout.as_millis().min(u32::MAX as u128) as u32)
+        .map_err(|e| if e.kind() == io::ErrorKind::TimedOut { handshake_timeout(&name, "connection", timeout) } else { e })?;
     let mut writer = stream.try_clone()?;
-    let _ = writer.set_nodelay(true);
-    let mut reader = BufReader::new(stream);
 
-    // AUTH handshake
-    let _ = writer.write_all(format!("AUTH {}\n", session_key).as_bytes());
-    let _ = writer.flush();
-    let mut auth_line = String::new();
-    reader.read_line(&mut auth_line)?;
-    if !auth_line.trim().starts_with("OK") {
-        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "auth failed"));
-    }
-
-    // Enter persistent mode + attach
-    let _ = writer.write_all(b"PERSISTENT\n");
-    let _ = writer.write_all(b"client-attach\n");
-    let _ = writer.flush();
-
-    // Spawn a dedicated reader thread so the event loop never blocks on I/O.
-    // The reader thread reads lines from the server and sends them via channel.
-    let _ = reader.get_ref().set_read_timeout(None); // blocking reads in reader thread
-    let (frame_tx, frame_rx) = std::sync::mpsc::channel::<String>();
+    // A dedicated reader thread, so neither the handshake nor the event
+    // loop ever blocks on I/O.
+    let (frame_tx, lines) = std::sync::mpsc::channel::<String>();
     std::thread::spawn(move || {
-        let mut reader = reader;
+        let mut reader = BufReader::new(stream);
         let mut buf = String::with_capacity(64 * 1024);
         loop {
             buf.clear();
@@ -235,29 +737,145 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             }
         }
     });
+    let pending = handshake(&mut writer, &lines, &name, &attach_commands(), timeout)?;
+    Ok(Attached { writer, lines, pending })
+}
+
+/// What the client sends after PERSISTENT to attach with its flags.
+fn attach_commands() -> Vec<String> {
+    // -r marks an SSH client so client-idle-timeout applies to it even
+    // when local clients are exempt.
+    let mut cmds = vec![if crate::ssh_input::is_ssh_session() { "client-attach -r" } else { "client-attach" }.to_string()];
+    // The session this client switched from, for switch-client -l
+    if let Ok(last) = env::var("PSMUX_LAST_SESSION") {
+        cmds[0].push_str(&format!(" -l \"{}\"", last));
+    }
+    // Ahead of the attach, for update-environment: the server picks the
+    // variables it wants
+    cmds.insert(0, format!("client-environment {}", crate::environ::encode_client_env(env::vars())));
+    // PSMUX_LOW_BANDWIDTH=1 (--low-bandwidth) starts in low-bandwidth mode
+    if env::var("PSMUX_LOW_BANDWIDTH").map_or(false, |v| v == "1") {
+        cmds.push(format!("refresh-client -f {}", crate::bandwidth::LOW_BANDWIDTH_FLAG));
+    }
+    // PSMUX_NO_STATUS=1 (attach --no-status) draws panes over the status
+    // line for this client only
+    if env::var("PSMUX_NO_STATUS").map_or(false, |v| v == "1") {
+        cmds.push(format!("refresh-client -f {}", crate::bandwidth::NO_STATUS_FLAG));
+    }
+    if push_frames_wanted() {
+        cmds.push("client-subscribe 1".to_string());
+    }
+    // PSMUX_FRAMES=binary asks for packed rows; it must come straight
+    // after PERSISTENT
+    if env::var("PSMUX_FRAMES").map_or(false, |v| v == "binary") {
+        cmds.insert(0, "FORMAT binary".to_string());
+    }
+    cmds
+}
+
+/// Frames are pushed by the server unless PSMUX_POLL=1 asks for the old
+/// dump-state polling.
+fn push_frames_wanted() -> bool {
+    env::var("PSMUX_POLL").map_or(true, |v| v != "1")
+}
+
+/// The attach handshake over a connection whose lines arrive on `lines`.
+/// Returns the lines read up to and including the first frame.  An
+/// `ERROR: ...` refusal is returned verbatim.
+fn handshake<W: Write>(writer: &mut W, lines: &std::sync::mpsc::Receiver<String>, name: &str,
+        attach: &[String], timeout: Duration) -> io::Result<std::collections::VecDeque<String>> {
+    let next = |step: &str, deadline: Instant| {
+        use std::sync::mpsc::RecvTimeoutError;
+        lines.recv_timeout(deadline.saturating_duration_since(Instant::now())).map_err(|e| match e {
+            RecvTimeoutError::Timeout => handshake_timeout(name, step, timeout),
+            RecvTimeoutError::Disconnected => io::Error::new(io::ErrorKind::ConnectionAborted,
+                format!("session {} closed the connection before the {}", name, step)),
+        })
+    };
+    // Enter persistent mode, attach, and ask for the first frame
+    writer.write_all(b"PERSISTENT\n")?;
+    for cmd in attach { writer.write_all(format!("{}\n", cmd).as_bytes())?; }
+    writer.write_all(b"dump-state\n")?;
+    writer.flush()?;
+    let mut pending = std::collections::VecDeque::new();
+    let ack = next("PERSISTENT ack", Instant::now() + timeout)?;
+    if ack.starts_with("ERROR") {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, ack.trim_end().to_string()));
+    }
+    // A server older than the ack answers with the frame straight away
+    if ack.trim_end() != "OK" { pending.push_back(ack); }
+    let deadline = Instant::now() + timeout;
+    while !pending.back().is_some_and(|l: &String| !l.starts_with('%')) {
+        pending.push_back(next("first frame", deadline)?);
+    }
+    Ok(pending)
+}
+
+fn handshake_timeout(name: &str, step: &str, timeout: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!(
+        "attach: no {} from session {} within {:.1}s; the server may be hung \
+         (try `psmux kill-server -t {}`, see crash.log in {}, or raise --timeout)",
+        step, name, timeout.as_secs_f64(), name, crate::session::psmux_dir()))
+}
+
+pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input: &crate::ssh_input::InputSource, attached: Attached) -> io::Result<()> {
+    let name = env::var("PSMUX_SESSION_NAME").unwrap_or_else(|_| "default".to_string());
+    let registry_dir = crate::session::psmux_dir();
+    let Attached { mut writer, lines: frame_rx, mut pending } = attached;
+    // PSMUX_LOW_BANDWIDTH=1 (--low-bandwidth) starts in low-bandwidth mode;
+    // =0 keeps it off even when the link looks slow.
+    let low_bandwidth_env = env::var("PSMUX_LOW_BANDWIDTH").unwrap_or_default();
+    let mut low_bandwidth = low_bandwidth_env == "1";
+    let mut low_bandwidth_auto = low_bandwidth_env != "0" && !low_bandwidth;
+    // The server pushes frames as the screen changes (client-subscribe,
+    // sent on attach); a low-bandwidth client still asks at its capped rate.
+    let subscribed = push_frames_wanted();
+    // PSMUX_NO_STATUS=1 (attach --no-status): prefix F11 toggles it.
+    let mut hide_status = env::var("PSMUX_NO_STATUS").map_or(false, |v| v == "1");
 
     let mut quit = false;
-    let mut prefix_armed = false;
+    // The armed prefix, the repeat window and a switch-client -T table
+    let mut routing = KeyRouting::new();
+    // Last armed state told to the server (client-prefix)
+    let mut prefix_reported = false;
     let mut renaming = false;
     let mut session_renaming = false;
     let mut rename_buf = String::new();
     let mut pane_renaming = false;
     let mut pane_title_buf = String::new();
-    let mut command_input = false;
-    let mut command_buf = String::new();
+    let mut open_prompt: Option<crate::prompt::Open> = None;  // command-prompt
     let mut chooser = false;
     let mut choices: Vec<(usize, usize)> = Vec::new();
     let mut tree_chooser = false;
     let mut tree_entries: Vec<(bool, usize, usize, String, String)> = Vec::new();  // (is_win, id, sub_id, label, session_name)
     let mut tree_selected: usize = 0;
+    // Sessions and windows behind tree_entries, re-sorted in place by `o`
+    let mut tree_sessions: Vec<(String, Vec<WinTree>)> = Vec::new();
+    let mut tree_sort = WindowSort::default();
+    // Set when the server sent the rows (%choose): what each one runs
+    let mut tree_commands: Vec<String> = Vec::new();
+    // Typed to narrow the rows (see tree_visible_rows)
+    let mut tree_filter = String::new();
+    // Sessions (window id usize::MAX) and windows shown without their rows
+    let mut tree_collapsed: std::collections::HashSet<(String, usize)> = std::collections::HashSet::new();
+    // The previewed pane (session, pane id), its screen and when it was read
+    let mut tree_preview: Option<((String, usize), String, Instant)> = None;
+    // rename-window's -t when `:` in the chooser opened the rename prompt
+    let mut rename_target: Option<String> = None;
     let mut session_chooser = false;
     let mut session_entries: Vec<(String, String)> = Vec::new();
     let mut session_selected: usize = 0;
-    let mut confirm_cmd: Option<String> = None;  // pending kill confirmation
+    let mut open_menu: Option<crate::menu::Open> = None;  // display-menu or a context menu
+    // display-popup is open on the server: every key goes to it
+    let mut popup_open = false;
     let current_session = name.clone();
     let mut last_sent_size: (u16, u16) = (0, 0);
+    // (content, status) rects on this terminal, as of the last client-size
+    let mut areas: (Rect, Rect) = (Rect::default(), Rect::default());
     let mut last_dump_time = Instant::now() - Duration::from_millis(250);
     let mut force_dump = true;
+    // Set by the %suspend directive (suspend-client, prefix C-z)
+    let mut suspend_requested = false;
     let mut last_tree: Vec<WinTree> = Vec::new();
     // Default prefix is Ctrl+B, updated dynamically from server config
     let mut prefix_key: (KeyCode, KeyModifiers) = (KeyCode::Char('b'), KeyModifiers::CONTROL);
@@ -274,24 +892,51 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
     let mut custom_status_right: Option<String> = None;
     let mut pane_border_fg: Color = Color::DarkGray;
     let mut pane_active_border_fg: Color = Color::Green;
+    let mut pane_border_glyphs: (char, char) = border_glyphs("single");
+    let mut pane_border_marks: (bool, bool) = border_indicators("colour");
+    let mut pane_border_status: String = "off".to_string();
     let mut win_status_fmt: String = "#I:#W#{?window_flags,#{window_flags}, }".to_string();
     let mut win_status_current_fmt: String = "#I:#W#{?window_flags,#{window_flags}, }".to_string();
     let mut win_status_sep: String = " ".to_string();
     let mut win_status_style: Option<(Option<Color>, Option<Color>, bool)> = None;
     let mut win_status_current_style: Option<(Option<Color>, Option<Color>, bool)> = None;
     let mut mode_style_str: String = "bg=yellow,fg=black".to_string();
+    let mut match_style_str: String = "bg=cyan,fg=black".to_string();
+    let mut current_match_style_str: String = "bg=magenta,fg=black".to_string();
+    let mut double_click = Duration::from_millis(crate::window_ops::DOUBLE_CLICK_MS);
+    // repeat-time and prefix-timeout
+    let mut repeat_time = Duration::from_millis(500);
+    let mut prefix_timeout: Option<Duration> = None;
+    // confirm-kill: prefix x and & go through confirm-before
+    let mut confirm_kill = true;
+    // root-any-binding: a root `Any` binding catches keys bound nowhere else
+    let mut root_any_binding = false;
+    // @pane-right-click paste: a pane's right-click pastes rather than
+    // opening the pane menu
+    let mut right_click_paste = false;
     let mut status_position_str: String = "bottom".to_string();
+    // The clickable ranges of the status lines as last drawn: (row, first
+    // column, column after, range).  Each window tab is one
+    let mut status_ranges: Vec<(u16, u16, u16, StatusRange)> = Vec::new();
+    // status-lines from the last frame; this client shows none while hide_status
+    let mut server_status_lines: u16 = 1;
     let mut _status_justify_str: String = "left".to_string();
     // Synced bindings from server (updated each frame from DumpState)
     let mut synced_bindings: Vec<BindingEntry> = Vec::new();
 
-    // list-keys overlay state (C-b ?)
-    let mut keys_viewer = false;
-    let mut keys_viewer_lines: Vec<String> = Vec::new();
-    let mut keys_viewer_scroll: usize = 0;
+    // Scrollable text overlay: list-keys (C-b ?), centred, or run-shell
+    // output (%view), over the active pane like tmux's view mode
+    let mut viewer = false;
+    let mut viewer_lines: Vec<String> = Vec::new();
+    let mut viewer_scroll: usize = 0;
+    let mut viewer_title = String::new();
+    let mut viewer_in_pane = false;
+
+    // Server-pushed status message (%message), e.g. the idle-detach countdown
+    let mut server_message: Option<String> = None;
 
     #[derive(serde::Deserialize, Default)]
-    struct WinStatus { id: usize, name: String, active: bool, #[serde(default)] activity: bool, #[serde(default)] tab_text: String }
+    struct WinStatus { id: usize, #[serde(default)] index: Option<usize>, name: String, active: bool, #[serde(default)] activity: bool, #[serde(default)] tab_text: String, #[serde(default)] alert_style: String }
     
     fn default_base_index() -> usize { 1 }
     fn default_prediction_dimming() -> bool { dim_predictions_enabled() }
@@ -299,20 +944,6 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
     fn default_status_right_length() -> usize { 40 }
     fn default_status_lines() -> usize { 1 }
 
-    /// A single key binding synced from the server.
-    #[derive(serde::Deserialize, Clone, Debug)]
-    struct BindingEntry {
-        /// Key table name (e.g. "prefix", "root")
-        t: String,
-        /// Key string (e.g. "C-a", "-", "F12")
-        k: String,
-        /// Command string (e.g. "split-window -v")
-        c: String,
-        /// Whether the binding is repeatable
-        #[serde(default)]
-        r: bool,
-    }
-
     #[derive(serde::Deserialize)]
     struct DumpState {
         layout: LayoutJson,
@@ -337,6 +968,12 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         pane_border_style: Option<String>,
         #[serde(default)]
         pane_active_border_style: Option<String>,
+        #[serde(default)]
+        pane_border_lines: Option<String>,
+        #[serde(default)]
+        pane_border_indicators: Option<String>,
+        #[serde(default)]
+        pane_border_status: Option<String>,
         /// window-status-format (short key to save bandwidth)
         #[serde(default)]
         wsf: Option<String>,
@@ -373,25 +1010,72 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         /// mode-style for copy mode selection highlighting
         #[serde(default)]
         mode_style: Option<String>,
+        /// copy-mode-match-style and copy-mode-current-match-style for
+        /// search matches
+        #[serde(default)]
+        copy_mode_match_style: Option<String>,
+        #[serde(default)]
+        copy_mode_current_match_style: Option<String>,
+        /// @double-click-time: longest gap between clicks of a double or
+        /// triple click, in milliseconds
+        #[serde(default)]
+        double_click_ms: Option<u64>,
+        /// repeat-time in milliseconds
+        #[serde(default)]
+        repeat_time: Option<u64>,
+        /// prefix-timeout in milliseconds, 0 for none
+        #[serde(default)]
+        prefix_timeout: u64,
+        /// confirm-kill
+        #[serde(default)]
+        confirm_kill: Option<bool>,
+        /// root-any-binding
+        #[serde(default)]
+        root_any_binding: bool,
+        /// @pane-right-click is `paste`
+        #[serde(default)]
+        right_click_paste: bool,
+        /// The open display-popup
+        #[serde(default)]
+        popup: Option<crate::popup::PopupJson>,
         /// status-position: "top" or "bottom"
         #[serde(default)]
         status_position: Option<String>,
         /// status-justify: "left", "centre", or "right"
         #[serde(default)]
         status_justify: Option<String>,
+        /// prefix-indicator-style, or None when prefix-indicator is off
+        #[serde(default)]
+        prefix_indicator: Option<String>,
+        /// Key table set by switch-client -T ("" for root)
+        #[serde(default)]
+        key_table: Option<String>,
+        /// local-echo: "on", "off" or "auto"
+        #[serde(default)]
+        local_echo: Option<String>,
+        /// Expanded set-titles-string, or "" when set-titles is off
+        #[serde(default)]
+        title: String,
     }
 
     let mut cmd_batch: Vec<String> = Vec::new();
     let mut dump_buf = String::new();
-    let mut prev_dump_buf = String::new();
+    let mut cache = RenderCache::default();
     let mut last_key_send_time: Option<Instant> = None;
     let mut dump_in_flight = false;
+    // Low-bandwidth bookkeeping: when the pending dump-state went out, recent
+    // frame timings, and the last rows seen for panes a plain frame defers.
+    let mut dump_sent_at: Option<Instant> = None;
+    let mut link_stats = crate::bandwidth::LinkStats::default();
+    // Local echo (local-echo): typing drawn before the server echoes it
+    let mut predictor = crate::predict::Predictor::default();
+    let mut middle_paste_guard = MiddlePasteGuard::default();
+    let mut want_all_panes = false;
 
     // Diagnostic latency log: set PSMUX_LATENCY_LOG=1 to enable
     let latency_log_enabled = env::var("PSMUX_LATENCY_LOG").unwrap_or_default() == "1";
     let mut latency_log: Option<std::fs::File> = if latency_log_enabled {
-        let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-        let path = format!("{}\\.psmux\\latency.log", home);
+        let path = format!("{}\\latency.log", registry_dir);
         std::fs::File::create(&path).ok()
     } else { None };
     let mut loop_count: u64 = 0;
@@ -404,6 +1088,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
     let mut rsel_dragged = false;
     let mut selection_changed = false; // forces redraw for selection overlay
     let mut border_drag = false; // true when dragging a pane separator (resize)
+    let mut clicks = ClickCounter::default();
     loop {
         // Expire stale key_send_instant after 30ms — ConPTY echo should
         // have arrived by then; stop force-dumping to save CPU.
@@ -415,13 +1100,14 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         let mut got_frame = false;
         let mut _nc_count = 0u32;
         loop {
-            match frame_rx.try_recv() {
+            match pending.pop_front().map_or_else(|| frame_rx.try_recv(), Ok) {
                 Ok(line) => {
                     if line.trim() == "NC" {
                         _nc_count += 1;
                         // Server says nothing changed — release dump_in_flight
                         // without touching dump_buf (saves 50-100KB clone + parse).
                         dump_in_flight = false;
+                        dump_sent_at = None;
                         last_dump_time = Instant::now();
                         // If we're waiting for a key echo, force an
                         // immediate dump-state re-request (~1ms TCP RTT)
@@ -429,8 +1115,95 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         if key_send_instant.is_some() {
                             force_dump = true;
                         }
+                    } else if let Some(directive) = line.trim_end().strip_prefix('%') {
+                        // Out-of-band directive; not a reply to dump-state.
+                        if directive == "detach" {
+                            quit = true;
+                        } else if directive == "redraw" {
+                            // refresh-client: clear the terminal and draw a whole new frame
+                            cache.repaint();
+                            let _ = terminal.clear();
+                            want_all_panes = true;
+                            force_dump = true;
+                        } else if directive == "bell" {
+                            // A window alert: ring the terminal's own bell
+                            let _ = crate::sanitize::write_raw(&mut io::stdout(), b"\x07", false);
+                        } else if directive == "suspend" {
+                            suspend_requested = true;
+                        } else if directive == "message-clear" {
+                            server_message = None;
+                            selection_changed = true;
+                        } else if let Some(b64) = directive.strip_prefix("clipboard ") {
+                            // OSC 52 goes straight to the terminal, around ratatui's buffer
+                            let _ = crate::sanitize::write_raw(&mut io::stdout(), crate::clipboard::osc52(b64).as_bytes(), true);
+                        } else if let Some(text) = directive.strip_prefix("message ") {
+                            server_message = Some(crate::sanitize::text(text, crate::sanitize::MAX_MESSAGE));
+                            selection_changed = true;
+                        } else if let Some(rest) = directive.strip_prefix("view ") {
+                            // run-shell output: base64 title and text
+                            let (title, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                            viewer_title = format!(" {} (q/Esc=close) ", crate::sanitize::text(&crate::util::base64_decode(title).unwrap_or_default(), crate::sanitize::MAX_TITLE));
+                            viewer_lines = crate::util::base64_decode(text).unwrap_or_default().lines().map(str::to_string).collect();
+                            viewer_scroll = 0;
+                            viewer_in_pane = true;
+                            viewer = true;
+                            selection_changed = true;
+                        } else if let Some(json) = directive.strip_prefix("prompt ") {
+                            if let Ok(p) = serde_json::from_str::<crate::prompt::Prompt>(json) {
+                                open_prompt = Some(crate::prompt::Open::new(p));
+                                selection_changed = true;
+                            }
+                        } else if let Some(json) = directive.strip_prefix("menu ") {
+                            if let Ok(menu) = serde_json::from_str::<crate::menu::Shown>(json) {
+                                open_menu = Some(crate::menu::Open::new(menu));
+                                selection_changed = true;
+                            }
+                        } else if let Some(base) = directive.strip_prefix("switch ") {
+                            // switch-client: reattach to that session in this process
+                            if base != current_session {
+                                cmd_batch.push("client-detach\n".into());
+                                env::set_var("PSMUX_SWITCH_TO", base);
+                                quit = true;
+                            }
+                        } else if directive == "choose-session" {
+                            (session_entries, session_selected) = session_chooser_rows(&registry_dir, &current_session);
+                            session_chooser = true;
+                            selection_changed = true;
+                        } else if let Some(json) = directive.strip_prefix("choose ") {
+                            // choose-tree run by a binding or a script
+                            if let Ok(rows) = serde_json::from_str::<Vec<crate::chooser::Choice>>(json) {
+                                tree_selected = rows.iter().rposition(|r| r.current).unwrap_or(0);
+                                tree_entries = rows.iter().enumerate()
+                                    .map(|(i, r)| (true, i, 0, r.label.clone(), current_session.clone()))
+                                    .collect();
+                                tree_commands = rows.into_iter().map(|r| r.command).collect();
+                                tree_filter.clear();
+                                tree_collapsed.clear();
+                                tree_preview = None;
+                                tree_chooser = true;
+                                selection_changed = true;
+                            }
+                        } else if let Some(flags) = directive.strip_prefix("flags ") {
+                            let low = flags.split(',').any(|f| f == crate::bandwidth::LOW_BANDWIDTH_FLAG);
+                            if low != low_bandwidth {
+                                low_bandwidth = low;
+                                // Switched by hand: stop second-guessing it
+                                low_bandwidth_auto = false;
+                                cache.deferred_rows.clear();
+                            }
+                            hide_status = flags.split(',').any(|f| f == crate::bandwidth::NO_STATUS_FLAG);
+                            force_dump = true;
+                        }
                     } else {
-                        dump_buf = line; got_frame = true; dump_in_flight = false;
+                        if let Some(sent) = dump_sent_at.take() {
+                            link_stats.record(sent.elapsed(), line.len());
+                        }
+                        dump_in_flight = false;
+                        match cache.rows.apply(line) {
+                            Some(frame) => { dump_buf = frame; got_frame = true; }
+                            // A delta against rows this client does not have
+                            None => { cache.want_full_frame = true; force_dump = true; }
+                        }
                     }
                 }
                 Err(std::sync::mpsc::TryRecvError::Empty) => break,
@@ -438,6 +1211,27 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             }
         }
         if quit && !got_frame { break; }
+        if predictor.expire(Instant::now()) { selection_changed = true; }
+        if suspend_requested {
+            suspend_requested = false;
+            let posix = crate::suspend::is_posix_pty(|k| env::var(k).ok());
+            let mut console = crate::suspend::Console { terminal: &mut *terminal, input };
+            let resumed = crate::suspend::suspend(&mut console, posix, |on| {
+                let _ = writer.write_all(format!("client-suspend {}\n", on as u8).as_bytes());
+                let _ = writer.flush();
+            });
+            if resumed.ok() == Some(crate::suspend::Resumed::Detach) { break; }
+            // Redraw everything; the console may have been resized meanwhile
+            last_sent_size = (0, 0);
+            force_dump = true;
+            selection_changed = true;
+        }
+        if low_bandwidth_auto && link_stats.is_slow() {
+            // Only tried once; refresh-client -f !low-bandwidth undoes it
+            low_bandwidth_auto = false;
+            if writer.write_all(format!("refresh-client -f {}\n", crate::bandwidth::LOW_BANDWIDTH_FLAG).as_bytes()).is_err() { break; }
+            let _ = writer.flush();
+        }
 
         // ── STEP 1: Poll events with adaptive timeout ────────────────────
         let since_dump = last_dump_time.elapsed().as_millis() as u64;
@@ -446,12 +1240,17 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             if kt.elapsed().as_millis() > 100 { last_key_send_time = None; }
         }
         let typing_active = last_key_send_time.is_some();
+        let push = subscribed && !low_bandwidth;
         // When typing: cap at ~100fps to avoid flooding the server with
         // dump-state requests (each one is ~50-100KB of JSON over TCP).
         // When idle: 50ms refresh (20fps) saves CPU.
         let poll_ms = if got_frame { 0 }
             else if dump_in_flight { 1 }
+            // Low-bandwidth: the frame cap applies even when a frame is wanted now
+            else if low_bandwidth { crate::bandwidth::frame_interval_ms(true, typing_active, false).saturating_sub(since_dump) }
             else if force_dump { 0 }
+            // Frames come by themselves; just wait for input a little
+            else if push { crate::bandwidth::push_poll_ms(typing_active) }
             else if typing_active {
                 // Rate-limit to ~100fps (10ms) when typing.  The snapshot-
                 // based serialisation in dump_layout_json_fast now holds
@@ -474,23 +1273,63 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             while let Some(_cur_evt) = _pending_evt {
                 match _cur_evt {
                     Event::Key(key) if key.kind == KeyEventKind::Press || key.kind == KeyEventKind::Repeat => {
+                        let sent_before = cmd_batch.len();
+                        let mut predicted = false;
+                        // A table chosen with switch-client -T takes this key if
+                        // it binds it; otherwise the key goes on as usual
+                        let had_table = routing.table.is_some();
+                        let repeats = || synced_binding(&synced_bindings, "prefix", (key.code, key.modifiers), root_any_binding)
+                            .map_or_else(|| help::default_repeats((key.code, key.modifiers)), |b| b.r);
+                        let table_binding = routing.begin_key(repeats, prefix_timeout, Instant::now())
+                            .filter(|_| open_prompt.is_none() && !renaming && !pane_renaming && !chooser && !tree_chooser && !session_chooser && !viewer && !popup_open)
+                            .and_then(|table| synced_binding(&synced_bindings, &table, (key.code, key.modifiers), root_any_binding));
                         // Dynamic prefix key check (default: Ctrl+B, configurable via .psmux.conf)
                         let is_prefix = (key.code, key.modifiers) == prefix_key
                             || prefix_raw_char.map_or(false, |c| matches!(key.code, KeyCode::Char(ch) if ch == c))
                             || prefix2_key.map_or(false, |p2| (key.code, key.modifiers) == p2)
                             || prefix2_raw_char.map_or(false, |c| matches!(key.code, KeyCode::Char(ch) if ch == c));
 
+                        // An open menu takes every key
+                        if let Some(menu) = open_menu.as_mut() {
+                            match menu.key(key.code) {
+                                crate::menu::Outcome::Stay => {}
+                                crate::menu::Outcome::Close => open_menu = None,
+                                crate::menu::Outcome::Run(cmd) => {
+                                    for c in crate::config::split_chained_commands_pub(&cmd) { cmd_batch.push(format!("{}\n", c)); }
+                                    open_menu = None;
+                                }
+                            }
+                            selection_changed = true;
+                        }
+                        // Esc in a filtered chooser drops the filter; the next closes it
+                        else if matches!(key.code, KeyCode::Esc) && tree_chooser && !tree_filter.is_empty() {
+                            tree_filter.clear();
+                        }
                         // Overlay Esc must be checked BEFORE selection-Esc so that
                         // pressing Esc always closes the active overlay first.
-                        if matches!(key.code, KeyCode::Esc) && (command_input || renaming || pane_renaming || chooser || tree_chooser || session_chooser || confirm_cmd.is_some() || keys_viewer) {
-                            command_input = false;
+                        // So does a command prompt
+                        else if let Some(prompt) = open_prompt.as_mut() {
+                            match prompt.key(key) {
+                                crate::prompt::Outcome::Stay => {}
+                                crate::prompt::Outcome::Close => open_prompt = None,
+                                crate::prompt::Outcome::Run(answers, cmd) => {
+                                    for a in answers.iter().filter(|a| !a.trim().is_empty()) {
+                                        cmd_batch.push(format!("client-prompt-history {}\n", crate::util::base64_encode(a)));
+                                    }
+                                    for c in crate::config::split_chained_commands_pub(cmd.trim()) { cmd_batch.push(format!("{}\n", c)); }
+                                    open_prompt = None;
+                                }
+                            }
+                            selection_changed = true;
+                        }
+                        else if matches!(key.code, KeyCode::Esc) && (renaming || pane_renaming || chooser || tree_chooser || session_chooser || viewer) {
                             renaming = false;
+                            rename_target = None;
                             pane_renaming = false;
                             chooser = false;
                             tree_chooser = false;
                             session_chooser = false;
-                            keys_viewer = false;
-                            confirm_cmd = None;
+                            viewer = false;
                             // Also clear any lingering selection
                             rsel_start = None;
                             rsel_end = None;
@@ -502,37 +1341,35 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                             rsel_end = None;
                             selection_changed = true;
                         }
-                        else if is_prefix { prefix_armed = true; }
+                        else if let Some(entry) = &table_binding {
+                            if entry.c == "detach-client" || entry.c == "detach" {
+                                quit = true;
+                            } else {
+                                cmd_batch.push(format!("{}\n", entry.c));
+                            }
+                        }
+                        else if is_prefix && !popup_open { routing.arm(Instant::now()); }
                         // Check root-table bindings (bind-key -n / bind-key -T root)
                         // These fire without prefix, before keys are forwarded to PTY
-                        else if !command_input && !renaming && !pane_renaming && !chooser && !tree_chooser && !session_chooser && !keys_viewer && confirm_cmd.is_none() && {
-                            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-                            synced_bindings.iter().any(|b| b.t == "root" && parse_key_string(&b.k).map_or(false, |k| normalize_key_for_binding(k) == key_tuple))
-                        } {
-                            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-                            if let Some(entry) = synced_bindings.iter().find(|b| {
-                                b.t == "root" && parse_key_string(&b.k).map_or(false, |k| normalize_key_for_binding(k) == key_tuple)
-                            }) {
-                                if entry.c == "detach-client" || entry.c == "detach" {
-                                    quit = true;
-                                } else {
-                                    cmd_batch.push(format!("{}\n", entry.c));
-                                }
+                        // (an Any binding there never takes the key after the prefix)
+                        else if let Some(entry) = (!renaming && !pane_renaming && !chooser && !tree_chooser && !session_chooser && !viewer && !popup_open)
+                            .then(|| synced_binding(&synced_bindings, "root", (key.code, key.modifiers), root_any_binding && !routing.prefix_armed)).flatten()
+                        {
+                            if entry.c == "detach-client" || entry.c == "detach" {
+                                quit = true;
+                            } else {
+                                cmd_batch.push(format!("{}\n", entry.c));
                             }
                         }
-                        else if prefix_armed {
+                        else if routing.prefix_armed {
                             // Check user-defined synced bindings FIRST (like server-side input.rs).
                             // This lets users override any default hardcoded key binding.
-                            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-                            let user_binding = synced_bindings.iter().find(|b| {
-                                b.t == "prefix" && parse_key_string(&b.k).map_or(false, |k| normalize_key_for_binding(k) == key_tuple)
-                            });
+                            let user_binding = synced_binding(&synced_bindings, "prefix", (key.code, key.modifiers), root_any_binding);
+                            let repeats = user_binding.as_ref().map_or_else(|| help::default_repeats((key.code, key.modifiers)), |b| b.r);
                             if let Some(entry) = user_binding {
                                 // User-defined binding takes priority
                                 if entry.c == "detach-client" || entry.c == "detach" {
                                     quit = true;
-                                } else if entry.c.starts_with("confirm-before") || entry.c == "kill-pane" {
-                                    confirm_cmd = Some(entry.c.clone());
                                 } else {
                                     cmd_batch.push(format!("{}\n", entry.c));
                                 }
@@ -542,8 +1379,9 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 KeyCode::Char('c') => { cmd_batch.push("new-window\n".into()); }
                                 KeyCode::Char('%') => { cmd_batch.push("split-window -h\n".into()); }
                                 KeyCode::Char('"') => { cmd_batch.push("split-window -v\n".into()); }
-                                KeyCode::Char('x') => { confirm_cmd = Some("kill-pane".into()); }
-                                KeyCode::Char('&') => { confirm_cmd = Some("kill-window".into()); }
+                                KeyCode::Char('x') => { cmd_batch.push(format!("{}\n", if confirm_kill { crate::prompt::KILL_PANE } else { "kill-pane" })); }
+                                KeyCode::Char('&') => { cmd_batch.push(format!("{}\n", if confirm_kill { crate::prompt::KILL_WINDOW } else { "kill-window" })); }
+                                KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => { cmd_batch.push("suspend-client\n".into()); }
                                 KeyCode::Char('z') => { cmd_batch.push("zoom-pane\n".into()); }
                                 KeyCode::Char('[') => { cmd_batch.push("copy-enter\n".into()); }
                                 KeyCode::Char(']') => { cmd_batch.push("paste-buffer\n".into()); }
@@ -587,48 +1425,51 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 }
                                 KeyCode::Char('?') => {
                                     // Build comprehensive help overlay from help.rs
-                                    keys_viewer_scroll = 0;
+                                    viewer_scroll = 0;
                                     let user_binds: Vec<(bool, String, String, String)> = synced_bindings
                                         .iter()
                                         .map(|b| (b.r, b.t.clone(), b.k.clone(), b.c.clone()))
                                         .collect();
-                                    keys_viewer_lines = help::build_overlay_lines(&user_binds);
-                                    keys_viewer = true;
+                                    viewer_lines = help::build_overlay_lines(&user_binds);
+                                    viewer_title = " list-keys (q/Esc=close, Up/Down/PgUp/PgDn=scroll) ".to_string();
+                                    viewer_in_pane = false;
+                                    viewer = true;
                                 }
                                 KeyCode::Char('t') => { cmd_batch.push("clock-mode\n".into()); }
+                                KeyCode::Char('R') => { cmd_batch.push("refresh-client\n".into()); }
+                                KeyCode::F(11) => {
+                                    // Re-laid out locally; client-size follows below
+                                    hide_status = !hide_status;
+                                    let bang = if hide_status { "" } else { "!" };
+                                    cmd_batch.push(format!("refresh-client -f {}{}\n", bang, crate::bandwidth::NO_STATUS_FLAG));
+                                }
                                 KeyCode::Char('=') => { cmd_batch.push("choose-buffer\n".into()); }
-                                KeyCode::Char(':') => { command_input = true; command_buf.clear(); }
+                                KeyCode::Char('D') => { cmd_batch.push("choose-client\n".into()); }
+                                KeyCode::Char(':') => { cmd_batch.push("command-prompt\n".into()); }
                                 KeyCode::Char('w') => {
                                     tree_chooser = true;
-                                    tree_entries.clear();
-                                    tree_selected = 0;
+                                    tree_commands.clear();
+                                    tree_sessions.clear();
+                                    tree_filter.clear();
+                                    tree_collapsed.clear();
+                                    tree_preview = None;
                                     // Query ALL sessions (like tmux choose-tree)
-                                    let dir = format!("{}\\.psmux", home);
+                                    let dir = registry_dir.clone();
                                     if let Ok(entries) = std::fs::read_dir(&dir) {
-                                        let mut sessions: Vec<(String, Vec<(usize, String, Vec<(usize, String)>)>)> = Vec::new();
+                                        let mut sessions: Vec<(String, Vec<WinTree>)> = Vec::new();
                                         for e in entries.flatten() {
                                             if let Some(fname) = e.file_name().to_str().map(|s| s.to_string()) {
                                                 if let Some((base, ext)) = fname.rsplit_once('.') {
-                                                    if ext == "key" && crate::pipe::pipe_exists(base) {
-                                                        let sess_key = read_session_key(base).unwrap_or_default();
-                                                        if let Ok(handle) = crate::pipe::connect_to_pipe(base, 1000) {
-                                                            let mut ss = crate::pipe::PipeStream::from_handle(handle);
-                                                            let _ = write!(ss, "AUTH {}\n", sess_key);
+                                                    if ext == "key" && crate::transport::exists(base) {
+                                                        if let Ok(mut ss) = crate::transport::connect(base, 1000) {
                                                             let _ = ss.write_all(b"list-tree\n");
                                                             let _ = ss.flush();
                                                             let mut br = BufReader::new(ss);
-                                                            let mut al = String::new();
-                                                            let _ = br.read_line(&mut al); // AUTH OK
                                                             let mut tree_line = String::new();
                                                             if br.read_line(&mut tree_line).is_ok() {
                                                                 // Parse JSON array of WinTree
                                                                 if let Ok(wins) = serde_json::from_str::<Vec<WinTree>>(&tree_line.trim()) {
-                                                                    let mut win_data = Vec::new();
-                                                                    for w in &wins {
-                                                                        let panes: Vec<(usize, String)> = w.panes.iter().map(|p| (p.id, p.title.clone())).collect();
-                                                                        win_data.push((w.id, w.name.clone(), panes));
-                                                                    }
-                                                                    sessions.push((base.to_string(), win_data));
+                                                                    sessions.push((base.to_string(), wins));
                                                                 }
                                                             }
                                                         }
@@ -642,92 +1483,18 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             else if b.0 == current_session { std::cmp::Ordering::Greater }
                                             else { a.0.cmp(&b.0) }
                                         });
-                                        // Build tree entries: session > window > pane
-                                        // tree_entries format: (is_win, id, sub_id, label, session_name)
-                                        // For session headers: is_win=true with id=usize::MAX as sentinel
-                                        // For windows: is_win=true
-                                        // For panes: is_win=false
-                                        for (sess_name, wins) in &sessions {
-                                            let is_current = sess_name == &current_session;
-                                            let attached = if is_current { " (attached)" } else { "" };
-                                            let nw = wins.len();
-                                            // Session header line
-                                            tree_entries.push((true, usize::MAX, 0,
-                                                format!("{}: {} windows{}", sess_name, nw, attached),
-                                                sess_name.clone()));
-                                            if is_current {
-                                                // Show windows and panes for current session
-                                                for (wi, (wid, wname, panes)) in wins.iter().enumerate() {
-                                                    let flag = if panes.len() > 0 { "" } else { "" };
-                                                    tree_entries.push((true, *wid, 0,
-                                                        format!("  {}: {}{} ({} panes)", wi, wname, flag, panes.len()),
-                                                        sess_name.clone()));
-                                                    for (pid, ptitle) in panes {
-                                                        tree_entries.push((false, *wid, *pid,
-                                                            format!("    {}", ptitle),
-                                                            sess_name.clone()));
-                                                    }
-                                                }
-                                            } else {
-                                                // Show windows for other sessions (collapsed)
-                                                for (wi, (wid, wname, panes)) in wins.iter().enumerate() {
-                                                    tree_entries.push((true, *wid, 0,
-                                                        format!("  {}: {} ({} panes)", wi, wname, panes.len()),
-                                                        sess_name.clone()));
-                                                }
-                                            }
-                                        }
+                                        tree_sessions = sessions;
                                     }
                                     // Fallback: if no sessions found, use current session data
-                                    if tree_entries.is_empty() {
-                                        for wi in &last_tree {
-                                            tree_entries.push((true, wi.id, 0, wi.name.clone(), current_session.clone()));
-                                            for pi in &wi.panes {
-                                                tree_entries.push((false, wi.id, pi.id, pi.title.clone(), current_session.clone()));
-                                            }
-                                        }
+                                    if tree_sessions.is_empty() {
+                                        tree_sessions.push((current_session.clone(), last_tree.clone()));
                                     }
+                                    tree_entries = tree_chooser_entries(&tree_sessions, &current_session, tree_sort);
+                                    tree_selected = tree_chooser_current(&tree_entries, &tree_sessions, &current_session);
                                 }
                                 KeyCode::Char('s') => {
+                                    (session_entries, session_selected) = session_chooser_rows(&registry_dir, &current_session);
                                     session_chooser = true;
-                                    session_entries.clear();
-                                    session_selected = 0;
-                                    let dir = format!("{}\\.psmux", home);
-                                    if let Ok(entries) = std::fs::read_dir(&dir) {
-                                        for e in entries.flatten() {
-                                            if let Some(fname) = e.file_name().to_str() {
-                                                if let Some((base, ext)) = fname.rsplit_once('.') {
-                                                    if ext == "key" && crate::pipe::pipe_exists(base) {
-                                                        let sess_key = read_session_key(base).unwrap_or_default();
-                                                        let info = if let Ok(handle) = crate::pipe::connect_to_pipe(base, 1000) {
-                                                            let mut ss = crate::pipe::PipeStream::from_handle(handle);
-                                                            let _ = write!(ss, "AUTH {}\n", sess_key);
-                                                            let _ = ss.write_all(b"session-info\n");
-                                                            let _ = ss.flush();
-                                                            let mut br = BufReader::new(ss);
-                                                            let mut al = String::new();
-                                                            let _ = br.read_line(&mut al);
-                                                            let mut line = String::new();
-                                                            if br.read_line(&mut line).is_ok() && !line.trim().is_empty() {
-                                                                line.trim().to_string()
-                                                            } else {
-                                                                format!("{}: (no info)", base)
-                                                            }
-                                                        } else {
-                                                            format!("{}: (not responding)", base)
-                                                        };
-                                                        session_entries.push((base.to_string(), info));
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    if session_entries.is_empty() {
-                                        session_entries.push((current_session.clone(), format!("{}: (current)", current_session)));
-                                    }
-                                    for (i, (sname, _)) in session_entries.iter().enumerate() {
-                                        if sname == &current_session { session_selected = i; break; }
-                                    }
                                 }
                                 KeyCode::Char('q') => { chooser = true; }
                                 KeyCode::Char('v') => { cmd_batch.push("copy-anchor\n".into()); }
@@ -736,13 +1503,13 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 KeyCode::Char('(') | KeyCode::Char(')') => {
                                     let dir_next = key.code == KeyCode::Char(')');
                                     // Enumerate sessions
-                                    let dir = format!("{}\\.psmux", home);
+                                    let dir = registry_dir.clone();
                                     let mut names: Vec<String> = Vec::new();
                                     if let Ok(entries) = std::fs::read_dir(&dir) {
                                         for e in entries.flatten() {
                                             if let Some(fname) = e.file_name().to_str() {
                                                 if let Some((base, ext)) = fname.rsplit_once('.') {
-                                                    if ext == "key" && crate::pipe::pipe_exists(base) {
+                                                    if ext == "key" && crate::transport::exists(base) {
                                                         names.push(base.to_string());
                                                     }
                                                 }
@@ -777,8 +1544,11 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 }
                             }
                             } // end of else (no user binding override)
-                            prefix_armed = false;
+                            routing.prefix_key_done(repeats && !quit, repeat_time, Instant::now());
                         } else {
+                            // The chooser's rows on screen, with the filter and collapsed windows
+                            let tree_rows = if tree_chooser { tree_visible_rows(&tree_entries, &tree_collapsed, &tree_filter) } else { Vec::new() };
+                            let tree_row_shown = tree_rows.contains(&tree_selected);
                             match key.code {
                                 KeyCode::Up if session_chooser => { if session_selected > 0 { session_selected -= 1; } }
                                 KeyCode::Down if session_chooser => { if session_selected + 1 < session_entries.len() { session_selected += 1; } }
@@ -804,10 +1574,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             quit = true;
                                         } else {
                                             // Kill another session by connecting to it
-                                            let sess_key = read_session_key(&sname).unwrap_or_default();
-                                            if let Ok(handle) = crate::pipe::connect_to_pipe(&sname, 1000) {
-                                                let mut ss = crate::pipe::PipeStream::from_handle(handle);
-                                                let _ = write!(ss, "AUTH {}\n", sess_key.trim());
+                                            if let Ok(mut ss) = crate::transport::connect(&sname, 1000) {
                                                 let _ = ss.write_all(b"kill-session\n");
                                                 let _ = ss.flush();
                                             }
@@ -822,8 +1589,74 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                         }
                                     }
                                 }
-                                KeyCode::Up if tree_chooser => { if tree_selected > 0 { tree_selected -= 1; } }
-                                KeyCode::Down if tree_chooser => { if tree_selected + 1 < tree_entries.len() { tree_selected += 1; } }
+                                KeyCode::Up if tree_chooser => { tree_selected = tree_step(&tree_rows, tree_selected, -1); }
+                                KeyCode::Down if tree_chooser => { tree_selected = tree_step(&tree_rows, tree_selected, 1); }
+                                KeyCode::Left if tree_chooser => {
+                                    // Collapse the row, or go up to the one above it
+                                    let key = tree_entries.get(tree_selected).map(|e| (e.4.clone(), e.1));
+                                    match key {
+                                        Some(k) if tree_has_children(&tree_entries, tree_selected) && !tree_collapsed.contains(&k) && tree_filter.is_empty() => {
+                                            tree_collapsed.insert(k);
+                                        }
+                                        _ => { if let Some(p) = tree_parent(&tree_entries, tree_selected) { tree_selected = p; } }
+                                    }
+                                }
+                                KeyCode::Right if tree_chooser => {
+                                    if let Some(e) = tree_entries.get(tree_selected) { tree_collapsed.remove(&(e.4.clone(), e.1)); }
+                                }
+                                KeyCode::Backspace if tree_chooser => {
+                                    tree_filter.pop();
+                                    let rows = tree_visible_rows(&tree_entries, &tree_collapsed, &tree_filter);
+                                    if !rows.contains(&tree_selected) { tree_selected = tree_step(&rows, tree_selected, 0); }
+                                }
+                                KeyCode::Char('x') if tree_chooser && tree_commands.is_empty() && tree_filter.is_empty() => {
+                                    // Kill the row's pane, window or session, once confirmed
+                                    if let Some((is_win, wid, pid, _, sess)) = tree_entries.get(tree_selected) {
+                                        if *sess == current_session {
+                                            let cmd = match (*is_win, *wid) {
+                                                (true, usize::MAX) => "kill-session".to_string(),
+                                                (true, wid) => format!("kill-window -t @{}", wid),
+                                                (false, _) => format!("kill-pane -t %{}", pid),
+                                            };
+                                            open_prompt = Some(crate::prompt::Open::new(crate::prompt::confirm(format!("{}? (y/n)", cmd), cmd)));
+                                            tree_chooser = false;
+                                        }
+                                    }
+                                }
+                                KeyCode::Char(':') if tree_chooser && tree_commands.is_empty() && tree_filter.is_empty() => {
+                                    // Rename the row's window (a pane's window) or session
+                                    if let Some((_, wid, _, _, sess)) = tree_entries.get(tree_selected) {
+                                        if *sess == current_session {
+                                            rename_buf.clear();
+                                            if *wid == usize::MAX {
+                                                session_renaming = true;
+                                            } else {
+                                                rename_target = Some(format!("@{}", wid));
+                                                if let Some(w) = tree_sessions.iter().find(|s| s.0 == *sess).and_then(|s| s.1.iter().find(|w| w.id == *wid)) {
+                                                    rename_buf = w.name.clone();
+                                                }
+                                            }
+                                            renaming = true;
+                                            tree_chooser = false;
+                                        }
+                                    }
+                                }
+                                KeyCode::Char('o') if tree_chooser && tree_commands.is_empty() && tree_filter.is_empty() => {
+                                    // Cycle the window order, keeping the same row selected
+                                    let selected = tree_entries.get(tree_selected).map(|e| (e.1, e.2, e.4.clone()));
+                                    tree_sort = tree_sort.next();
+                                    tree_entries = tree_chooser_entries(&tree_sessions, &current_session, tree_sort);
+                                    tree_selected = selected
+                                        .and_then(|(w, p, s)| tree_entries.iter().position(|e| e.1 == w && e.2 == p && e.4 == s))
+                                        .unwrap_or(0);
+                                }
+                                KeyCode::Enter if tree_chooser && !tree_row_shown => {}
+                                KeyCode::Enter if tree_chooser && !tree_commands.is_empty() => {
+                                    if let Some(cmd) = tree_commands.get(tree_selected) {
+                                        for c in crate::config::split_chained_commands_pub(cmd) { cmd_batch.push(format!("{}\n", c)); }
+                                    }
+                                    tree_chooser = false;
+                                }
                                 KeyCode::Enter if tree_chooser => {
                                     if let Some((is_win, wid, pid, _label, sess_name)) = tree_entries.get(tree_selected) {
                                         if *wid == usize::MAX {
@@ -850,52 +1683,42 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                     }
                                 }
                                 KeyCode::Esc if tree_chooser => { tree_chooser = false; }
-                                // --- list-keys viewer (C-b ?) ---
-                                KeyCode::Up if keys_viewer => { if keys_viewer_scroll > 0 { keys_viewer_scroll -= 1; } }
-                                KeyCode::Down if keys_viewer => { keys_viewer_scroll += 1; }
-                                KeyCode::PageUp if keys_viewer => { keys_viewer_scroll = keys_viewer_scroll.saturating_sub(20); }
-                                KeyCode::PageDown if keys_viewer => { keys_viewer_scroll += 20; }
-                                KeyCode::Home if keys_viewer => { keys_viewer_scroll = 0; }
-                                KeyCode::End if keys_viewer => { keys_viewer_scroll = keys_viewer_lines.len().saturating_sub(1); }
-                                KeyCode::Char('q') if keys_viewer => { keys_viewer = false; }
-                                KeyCode::Esc if keys_viewer => { keys_viewer = false; }
-                                KeyCode::Char('k') if keys_viewer => { if keys_viewer_scroll > 0 { keys_viewer_scroll -= 1; } }
-                                KeyCode::Char('j') if keys_viewer => { keys_viewer_scroll += 1; }
-                                // --- kill confirmation: y/Y/Enter confirms, n/N/Esc cancels ---
-                                KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter if confirm_cmd.is_some() => {
-                                    if let Some(cmd) = confirm_cmd.take() {
-                                        cmd_batch.push(format!("{}\n", cmd));
-                                    }
-                                }
-                                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc if confirm_cmd.is_some() => {
-                                    confirm_cmd = None;
+                                KeyCode::Char(c) if tree_chooser && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    // Anything else narrows the rows; the highlight moves to a match
+                                    tree_filter.push(c);
+                                    let rows = tree_visible_rows(&tree_entries, &tree_collapsed, &tree_filter);
+                                    if !rows.contains(&tree_selected) { tree_selected = tree_step(&rows, tree_selected, 0); }
                                 }
+                                // --- list-keys / run-shell viewer ---
+                                KeyCode::Up if viewer => { if viewer_scroll > 0 { viewer_scroll -= 1; } }
+                                KeyCode::Down if viewer => { viewer_scroll += 1; }
+                                KeyCode::PageUp if viewer => { viewer_scroll = viewer_scroll.saturating_sub(20); }
+                                KeyCode::PageDown if viewer => { viewer_scroll += 20; }
+                                KeyCode::Home if viewer => { viewer_scroll = 0; }
+                                KeyCode::End if viewer => { viewer_scroll = viewer_lines.len().saturating_sub(1); }
+                                KeyCode::Char('q') if viewer => { viewer = false; }
+                                KeyCode::Esc if viewer => { viewer = false; }
+                                KeyCode::Char('k') if viewer => { if viewer_scroll > 0 { viewer_scroll -= 1; } }
+                                KeyCode::Char('j') if viewer => { viewer_scroll += 1; }
                                 KeyCode::Char(c) if renaming && !key.modifiers.contains(KeyModifiers::CONTROL) => { rename_buf.push(c); }
                                 KeyCode::Char(c) if pane_renaming && !key.modifiers.contains(KeyModifiers::CONTROL) => { pane_title_buf.push(c); }
-                                KeyCode::Char(c) if command_input && !key.modifiers.contains(KeyModifiers::CONTROL) => { command_buf.push(c); }
                                 KeyCode::Backspace if renaming => { let _ = rename_buf.pop(); }
                                 KeyCode::Backspace if pane_renaming => { let _ = pane_title_buf.pop(); }
-                                KeyCode::Backspace if command_input => { let _ = command_buf.pop(); }
                                 KeyCode::Enter if renaming => {
                                     if session_renaming {
                                         cmd_batch.push(format!("rename-session {}\n", rename_buf));
                                         session_renaming = false;
                                     } else {
-                                        cmd_batch.push(format!("rename-window {}\n", rename_buf));
+                                        match rename_target.take() {
+                                            Some(t) => cmd_batch.push(format!("rename-window -t {} \"{}\"\n", t, rename_buf.replace('\\', "\\\\").replace('"', "\\\""))),
+                                            None => cmd_batch.push(format!("rename-window {}\n", rename_buf)),
+                                        }
                                     }
                                     renaming = false;
                                 }
                                 KeyCode::Enter if pane_renaming => { cmd_batch.push(format!("set-pane-title {}\n", pane_title_buf)); pane_renaming = false; }
-                                KeyCode::Enter if command_input => {
-                                    let trimmed = command_buf.trim().to_string();
-                                    if !trimmed.is_empty() {
-                                        cmd_batch.push(format!("{}\n", trimmed));
-                                    }
-                                    command_input = false;
-                                }
-                                KeyCode::Esc if renaming => { renaming = false; session_renaming = false; }
+                                KeyCode::Esc if renaming => { renaming = false; session_renaming = false; rename_target = None; }
                                 KeyCode::Esc if pane_renaming => { pane_renaming = false; }
-                                KeyCode::Esc if command_input => { command_input = false; }
                                 KeyCode::Char(d) if chooser && d.is_ascii_digit() => {
                                     let raw = d.to_digit(10).unwrap() as usize;
                                     let choice = if raw == 0 { 10 } else { raw };
@@ -905,7 +1728,10 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                     }
                                 }
                                 KeyCode::Esc if chooser => { chooser = false; }
-                                KeyCode::Char(' ') => { cmd_batch.push("send-key space\n".into()); }
+                                KeyCode::Char(' ') => {
+                                    cmd_batch.push("send-key space\n".into());
+                                    predicted = predictor.type_char(Instant::now(), ' ');
+                                }
                                 KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) && key.modifiers.contains(KeyModifiers::ALT) => {
                                     cmd_batch.push(format!("send-key C-M-{}\n", c.to_ascii_lowercase()));
                                 }
@@ -926,14 +1752,26 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                         _ => c.to_string(),
                                     };
                                     cmd_batch.push(format!("send-text \"{}\"\n", escaped));
+                                    predicted = predictor.type_char(Instant::now(), c);
+                                }
+                                KeyCode::Enter => {
+                                    cmd_batch.push("send-key enter\n".into());
+                                    predictor.enter(Instant::now());
                                 }
-                                KeyCode::Enter => { cmd_batch.push("send-key enter\n".into()); }
                                 KeyCode::Tab => { cmd_batch.push("send-key tab\n".into()); }
                                 KeyCode::BackTab => { cmd_batch.push("send-key btab\n".into()); }
-                                KeyCode::Backspace => { cmd_batch.push("send-key backspace\n".into()); }
+                                KeyCode::Backspace => {
+                                    cmd_batch.push("send-key backspace\n".into());
+                                    predicted = predictor.backspace(Instant::now());
+                                }
                                 KeyCode::Delete => { cmd_batch.push("send-key delete\n".into()); }
                                 KeyCode::Esc => { cmd_batch.push("send-key esc\n".into()); }
-                                KeyCode::Left => { cmd_batch.push("send-key left\n".into()); }
+                                KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => { cmd_batch.push("send-key s-left\n".into()); }
+                                KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => { cmd_batch.push("send-key s-right\n".into()); }
+                                KeyCode::Left => {
+                                    cmd_batch.push("send-key left\n".into());
+                                    predicted = predictor.left(Instant::now());
+                                }
                                 KeyCode::Right => { cmd_batch.push("send-key right\n".into()); }
                                 KeyCode::Up => { cmd_batch.push("send-key up\n".into()); }
                                 KeyCode::Down => { cmd_batch.push("send-key down\n".into()); }
@@ -946,25 +1784,88 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 _ => {}
                             }
                         }
+                        // A binding that switches tables: the next key is looked up
+                        // here rather than after the server's next frame
+                        let mut sent = cmd_batch.split_off(sent_before);
+                        let switched = routing.note_switches(&mut sent, Instant::now());
+                        cmd_batch.extend(sent);
+                        if had_table && !switched { cmd_batch.push("switch-client -T root\n".into()); }
+                        // Local echo: draw the prediction now; anything else sent
+                        // has an unknown effect, so predict nothing until it shows
+                        if predicted { selection_changed = true; }
+                        else if cmd_batch.len() > sent_before { predictor.pause(Instant::now()); }
                     }
-                    Event::Paste(data) => {
+                    Event::Paste(data) if middle_paste_guard.paste(Instant::now()) => {
                         let encoded = base64_encode(&data);
                         cmd_batch.push(format!("send-paste {}\n", encoded));
+                        predictor.pause(Instant::now());
                     }
                     Event::Mouse(me) => {
                         use crossterm::event::{MouseEventKind, MouseButton};
                         match me.kind {
+                            // A click while a menu is open is for the menu
+                            MouseEventKind::Down(button) if open_menu.is_some() => {
+                                let outcome = match button {
+                                    MouseButton::Left => open_menu.as_mut().map_or(crate::menu::Outcome::Close, |m| m.click(me.column, me.row)),
+                                    _ => crate::menu::Outcome::Close,
+                                };
+                                match outcome {
+                                    crate::menu::Outcome::Stay => {}
+                                    crate::menu::Outcome::Close => open_menu = None,
+                                    crate::menu::Outcome::Run(cmd) => {
+                                        for c in crate::config::split_chained_commands_pub(&cmd) { cmd_batch.push(format!("{}\n", c)); }
+                                        open_menu = None;
+                                    }
+                                }
+                                selection_changed = true;
+                            }
+                            MouseEventKind::Moved if open_menu.is_some() => {
+                                if open_menu.as_mut().is_some_and(|m| m.hover(me.column, me.row)) { selection_changed = true; }
+                            }
+                            // An open popup gets the mouse as it comes, for its command
+                            kind if popup_open => {
+                                let req = match kind {
+                                    MouseEventKind::Down(MouseButton::Left) => "mouse-down",
+                                    MouseEventKind::Down(MouseButton::Right) => "mouse-down-right",
+                                    MouseEventKind::Down(MouseButton::Middle) => "mouse-down-middle",
+                                    MouseEventKind::Up(MouseButton::Left) => "mouse-up",
+                                    MouseEventKind::Up(MouseButton::Right) => "mouse-up-right",
+                                    MouseEventKind::Up(MouseButton::Middle) => "mouse-up-middle",
+                                    MouseEventKind::Drag(_) => "mouse-drag",
+                                    MouseEventKind::ScrollUp => "scroll-up",
+                                    MouseEventKind::ScrollDown => "scroll-down",
+                                    _ => "",
+                                };
+                                if !req.is_empty() {
+                                    cmd_batch.push(format!("{} {} {}\n", req, me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
+                                }
+                            }
+                            // Right-click on the status line, or Ctrl+right-click
+                            // in a pane: the server opens a context menu
+                            MouseEventKind::Down(MouseButton::Right)
+                                if me.modifiers.contains(KeyModifiers::CONTROL)
+                                    || areas.1.contains(Position { x: me.column, y: me.row }) => {
+                                cmd_batch.push(format!("mouse-down-right {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
+                            }
+                            // A range on the status line, wherever the status sits:
+                            // a window tab, a pane, or a user range's hook
+                            MouseEventKind::Down(MouseButton::Left) if areas.1.contains(Position { x: me.column, y: me.row }) => {
+                                if let Some(range) = status_range_at(&status_ranges, me.column, me.row) { cmd_batch.push(format!("{}\n", range.command())); }
+                            }
+                            // The wheel over the status line cycles windows
+                            MouseEventKind::ScrollUp if areas.1.contains(Position { x: me.column, y: me.row }) => { cmd_batch.push("previous-window\n".into()); }
+                            MouseEventKind::ScrollDown if areas.1.contains(Position { x: me.column, y: me.row }) => { cmd_batch.push("next-window\n".into()); }
                             MouseEventKind::Down(MouseButton::Left) => {
                                 // Detect if click is on a separator line (for border resize)
-                                let on_sep = if !prev_dump_buf.is_empty() {
-                                    if let Ok(state) = serde_json::from_str::<DumpState>(&prev_dump_buf) {
+                                let on_sep = if !cache.frame.is_empty() {
+                                    if let Ok(state) = serde_json::from_str::<DumpState>(&cache.frame) {
                                         let content_area = Rect { x: 0, y: 0, width: last_sent_size.0, height: last_sent_size.1 };
-                                        is_on_separator(&state.layout, content_area, me.column, me.row)
+                                        is_on_separator(&state.layout, content_area, me.column, mouse_row_to_server(me.row, areas.0, areas.1))
                                     } else { false }
                                 } else { false };
 
                                 // Always forward to server for pane focus, tab clicks, border resize
-                                cmd_batch.push(format!("mouse-down {} {}\n", me.column, me.row));
+                                cmd_batch.push(format!("mouse-down {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
 
                                 if on_sep {
                                     // Border resize mode — server handles drag
@@ -972,7 +1873,9 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                     rsel_start = None;
                                     rsel_end = None;
                                     selection_changed = true;
+                                    clicks.reset();
                                 } else {
+                                    clicks.press(Instant::now(), me.column, me.row, double_click);
                                     // Text selection mode
                                     border_drag = false;
                                     rsel_start = Some((me.column, me.row));
@@ -982,12 +1885,14 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                 }
                             }
                             MouseEventKind::Down(MouseButton::Right) => {
-                                // pwsh-style: right-click with active selection → copy + clear
-                                // right-click without selection → paste from clipboard
+                                // Right-click with an active selection → copy + clear;
+                                // without one → the pane menu, or with
+                                // `@pane-right-click paste` pwsh-style paste
                                 if rsel_start.is_some() && rsel_dragged {
                                     // Copy selection to clipboard and clear it
                                     if let (Some(s), Some(e)) = (rsel_start, rsel_end) {
-                                        if let Ok(state) = serde_json::from_str::<DumpState>(&prev_dump_buf) {
+                                        if let Ok(mut state) = serde_json::from_str::<DumpState>(&cache.frame) {
+                                            crate::cells::unpack_rows(&mut state.layout);
                                             let text = extract_selection_text(
                                                 &state.layout,
                                                 last_sent_size.0,
@@ -995,7 +1900,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                                 s, e,
                                             );
                                             if !text.is_empty() {
-                                                copy_to_system_clipboard(&text);
+                                                system_clipboard::set_text(&text);
                                             }
                                         }
                                     }
@@ -1003,12 +1908,17 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                     rsel_end = None;
                                     rsel_dragged = false;
                                     selection_changed = true;
+                                } else if !right_click_paste {
+                                    rsel_start = None;
+                                    rsel_end = None;
+                                    selection_changed = true;
+                                    cmd_batch.push(format!("mouse-down-right {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
                                 } else {
                                     // No selection — paste from clipboard
                                     rsel_start = None;
                                     rsel_end = None;
                                     selection_changed = true;
-                                    if let Some(text) = read_from_system_clipboard() {
+                                    if let Some(text) = system_clipboard::get_text() {
                                         if !text.is_empty() {
                                             let encoded = base64_encode(&text);
                                             cmd_batch.push(format!("send-paste {}\n", encoded));
@@ -1016,17 +1926,18 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                     }
                                 }
                             }
-                            MouseEventKind::Down(MouseButton::Middle) => { cmd_batch.push(format!("mouse-down-middle {} {}\n", me.column, me.row)); }
+                            MouseEventKind::Down(MouseButton::Middle) if middle_paste_guard.click(Instant::now()) => { cmd_batch.push(format!("mouse-down-middle {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1))); }
                             MouseEventKind::Drag(MouseButton::Left) => {
                                 if border_drag {
                                     // Forward drag to server for border resize
-                                    cmd_batch.push(format!("mouse-drag {} {}\n", me.column, me.row));
+                                    cmd_batch.push(format!("mouse-drag {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
                                 } else {
                                     // Left-drag: extend text selection (pwsh behavior)
                                     if rsel_start.is_some() {
                                         rsel_end = Some((me.column, me.row));
                                         rsel_dragged = true;
                                         selection_changed = true;
+                                        clicks.reset();
                                     }
                                 }
                             }
@@ -1034,13 +1945,14 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                             MouseEventKind::Up(MouseButton::Left) => {
                                 if border_drag {
                                     // Forward mouse-up to server to finalize border resize
-                                    cmd_batch.push(format!("mouse-up {} {}\n", me.column, me.row));
+                                    cmd_batch.push(format!("mouse-up {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
                                     border_drag = false;
                                 } else if rsel_dragged {
                                     // Left-drag completed — copy selected text to clipboard
                                     rsel_end = Some((me.column, me.row));
                                     if let (Some(s), Some(e)) = (rsel_start, rsel_end) {
-                                        if let Ok(state) = serde_json::from_str::<DumpState>(&prev_dump_buf) {
+                                        if let Ok(mut state) = serde_json::from_str::<DumpState>(&cache.frame) {
+                                            crate::cells::unpack_rows(&mut state.layout);
                                             let text = extract_selection_text(
                                                 &state.layout,
                                                 last_sent_size.0,
@@ -1048,17 +1960,24 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                                 s, e,
                                             );
                                             if !text.is_empty() {
-                                                copy_to_system_clipboard(&text);
+                                                system_clipboard::set_text(&text);
                                             }
                                         }
                                     }
                                     // Keep selection visible (clears on next click or Escape)
+                                } else if clicks.count >= 2 {
+                                    // Double or triple click: the server selects the
+                                    // word or line in copy mode and yanks it
+                                    rsel_start = None;
+                                    rsel_end = None;
+                                    selection_changed = true;
+                                    cmd_batch.push(format!("mouse-select {} {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1), clicks.count));
                                 } else {
                                     // Plain left-click (no drag) — clear any old selection, forward mouse-up
                                     rsel_start = None;
                                     rsel_end = None;
                                     selection_changed = true;
-                                    cmd_batch.push(format!("mouse-up {} {}\n", me.column, me.row));
+                                    cmd_batch.push(format!("mouse-up {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1)));
                                 }
                             }
                             MouseEventKind::Up(MouseButton::Right) => {}
@@ -1066,8 +1985,8 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                             MouseEventKind::Moved => {
                                 // Don't send bare mouse-move to server - wasteful and server ignores it
                             }
-                            MouseEventKind::ScrollUp => { cmd_batch.push(format!("scroll-up {} {}\n", me.column, me.row)); }
-                            MouseEventKind::ScrollDown => { cmd_batch.push(format!("scroll-down {} {}\n", me.column, me.row)); }
+                            MouseEventKind::ScrollUp => { cmd_batch.push(format!("scroll-up {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1))); }
+                            MouseEventKind::ScrollDown => { cmd_batch.push(format!("scroll-down {} {}\n", me.column, mouse_row_to_server(me.row, areas.0, areas.1))); }
                             _ => {}
                         }
                     }
@@ -1090,7 +2009,8 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         let mut size_changed = false;
         {
             let ts = terminal.size()?;
-            let new_size = (ts.width, ts.height.saturating_sub(1));
+            areas = status_layout(Rect::new(0, 0, ts.width, ts.height), server_status_lines, hide_status, &status_position_str);
+            let new_size = (areas.0.width, areas.0.height);
             if new_size != last_sent_size {
                 last_sent_size = new_size;
                 size_changed = true;
@@ -1100,6 +2020,13 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             }
         }
 
+        if routing.expire(prefix_timeout, Instant::now()) { cmd_batch.push("switch-client -T root\n".into()); }
+        // Tell the server when the prefix arms or resolves, for #{client_prefix}
+        if routing.prefix_armed != prefix_reported {
+            prefix_reported = routing.prefix_armed;
+            cmd_batch.push(format!("client-prefix {}\n", if routing.prefix_armed { 1 } else { 0 }));
+        }
+
         // Send all batched commands immediately — keys reach the server
         // without waiting for a dump-state round-trip
         let sent_keys_this_iter = !cmd_batch.is_empty();
@@ -1114,26 +2041,43 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             key_send_instant = Some(Instant::now());
             // Force immediate dump-state so we start the echo-detection
             // polling chain right away (eliminates 0-10ms initial wait).
-            force_dump = true;
+            // A subscribed client is pushed the echo instead.
+            if !push { force_dump = true; }
         }
 
         // ── STEP 2b: Request screen update (non-blocking) ────────────────
         // Rate-limit dump-state requests to avoid flooding the server.
         // dump_in_flight prevents >1 concurrent request; the interval check
         // ensures we don't re-request faster than ~100fps when typing.
-        let overlays_active = command_input || renaming || pane_renaming || chooser || tree_chooser || session_chooser || keys_viewer || confirm_cmd.is_some();
-        let should_dump = if force_dump || size_changed {
+        let overlays_active = open_prompt.is_some() || renaming || pane_renaming || chooser || tree_chooser || session_chooser || viewer || open_menu.is_some();
+        let frame_interval = crate::bandwidth::frame_interval_ms(low_bandwidth, typing_active, overlays_active);
+        let should_dump = if size_changed || (force_dump && !low_bandwidth) {
             true
-        } else if typing_active {
-            since_dump >= 10  // ~100fps cap when typing (matches poll_ms)
+        } else if push {
+            false
         } else {
-            let idle_frame: u64 = if overlays_active { 33 } else { 50 };
-            since_dump >= idle_frame
+            // ~100fps when typing, 10fps in low-bandwidth mode (matches poll_ms)
+            since_dump >= frame_interval
         };
         if should_dump && !dump_in_flight {
-            if writer.write_all(b"dump-state\n").is_err() { break; }
+            let req = cache.request(low_bandwidth && want_all_panes);
+            if writer.write_all(req).is_err() { break; }
             if writer.flush().is_err() { break; }
             dump_in_flight = true;
+            dump_sent_at = Some(Instant::now());
+            want_all_panes = false;
+        }
+
+        // The chooser previews the highlighted pane, read again now and then
+        if tree_chooser && tree_commands.is_empty() {
+            let wanted = tree_entries.get(tree_selected).and_then(|e| tree_preview_pane(e, &tree_sessions));
+            if let Some(wanted) = wanted {
+                let now = Instant::now();
+                if preview_due(tree_preview.as_ref().map(|(p, _, at)| (p, *at)), &wanted, now) {
+                    let text = capture_pane_text(&wanted.0, wanted.1).unwrap_or_default();
+                    tree_preview = Some((wanted, text, now));
+                }
+            }
         }
 
         // ── STEP 3: Render if we have a frame ────────────────────────────
@@ -1143,13 +2087,13 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
 
         // Skip parse + render when the raw JSON is identical to the previous
         // frame AND selection hasn't changed AND no overlays are active.
-        if dump_buf == prev_dump_buf && !selection_changed && !overlays_active {
+        if dump_buf == cache.frame && !selection_changed && !overlays_active {
             last_dump_time = Instant::now();
             continue;
         }
 
-        // Parse the frame (use prev_dump_buf for selection-only redraws)
-        let frame_to_parse = if got_frame && dump_buf != prev_dump_buf { &dump_buf } else { &prev_dump_buf };
+        // Parse the frame (the cached one for selection-only redraws)
+        let frame_to_parse = if got_frame && dump_buf != cache.frame { &dump_buf } else { &cache.frame };
         let _t_parse = Instant::now();
         let state: DumpState = match serde_json::from_str(frame_to_parse) {
             Ok(s) => s,
@@ -1161,12 +2105,40 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         };
         let _parse_us = _t_parse.elapsed().as_micros();
 
-        let root = state.layout;
+        let mut root = state.layout;
+        if !crate::cells::unpack_rows(&mut root) {
+            cache.want_full_frame = true;
+            force_dump = true;
+            selection_changed = false;
+            continue;
+        }
+        if low_bandwidth && crate::bandwidth::merge_deferred_rows(&mut root, &mut cache.deferred_rows) {
+            want_all_panes = true;
+        }
+        // Local echo: confirm or drop predictions against the new frame
+        let local_echo = match state.local_echo.as_deref() {
+            Some("on") => true,
+            Some("auto") => link_stats.median_rtt().is_some_and(|rtt| rtt >= crate::predict::AUTO_RTT),
+            _ => false,
+        };
+        popup_open = state.popup.is_some();
+        if !local_echo || popup_open {
+            predictor = Default::default();
+        } else if got_frame && dump_buf != cache.frame {
+            predictor.set_rtt(link_stats.median_rtt());
+            reconcile_local_echo(&mut predictor, &root, Instant::now());
+        }
+        if !state.title.is_empty() && state.title != cache.title {
+            let _ = crate::sanitize::write_raw(&mut io::stdout(), crate::sanitize::title(&state.title).as_bytes(), false);
+            cache.title = state.title;
+        }
         let windows = state.windows;
         last_tree = state.tree;
         let base_index = state.base_index;
-        let dim_preds = state.prediction_dimming;
+        // Dimmed predictions need styled frames, which low-bandwidth mode drops
+        let dim_preds = state.prediction_dimming && !low_bandwidth;
         let clock_active = state.clock_mode;
+        let popup = state.popup;
 
         // Update prefix key from server config (if provided)
         if let Some(ref prefix_str) = state.prefix {
@@ -1230,6 +2202,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             }
         }
         let status_lines = state.status_lines;
+        server_status_lines = status_lines as u16;
         let status_format = state.status_format;
         // Update pane border styles
         if let Some(ref pbs) = state.pane_border_style {
@@ -1244,6 +2217,9 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 if let Some(c) = fg { pane_active_border_fg = c; }
             }
         }
+        if let Some(ref pbl) = state.pane_border_lines { pane_border_glyphs = border_glyphs(pbl); }
+        if let Some(ref pbi) = state.pane_border_indicators { pane_border_marks = border_indicators(pbi); }
+        if let Some(ref pbst) = state.pane_border_status { pane_border_status = pbst.clone(); }
         // Update window-status-format strings
         if let Some(ref f) = state.wsf { if !f.is_empty() { win_status_fmt = f.clone(); } }
         if let Some(ref f) = state.wscf { if !f.is_empty() { win_status_current_fmt = f.clone(); } }
@@ -1263,31 +2239,31 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
         if let Some(ref ms) = state.mode_style {
             if !ms.is_empty() { mode_style_str = ms.clone(); }
         }
+        if let Some(ref ms) = state.copy_mode_match_style { match_style_str = ms.clone(); }
+        if let Some(ref ms) = state.copy_mode_current_match_style { current_match_style_str = ms.clone(); }
+        if let Some(ms) = state.double_click_ms { double_click = Duration::from_millis(ms); }
+        if let Some(ms) = state.repeat_time { repeat_time = Duration::from_millis(ms); }
+        prefix_timeout = (state.prefix_timeout > 0).then(|| Duration::from_millis(state.prefix_timeout));
+        if let Some(c) = state.confirm_kill { confirm_kill = c; }
+        root_any_binding = state.root_any_binding;
+        right_click_paste = state.right_click_paste;
         if let Some(ref sp) = state.status_position {
             if !sp.is_empty() { status_position_str = sp.clone(); }
         }
         if let Some(ref sj) = state.status_justify {
             if !sj.is_empty() { _status_justify_str = sj.clone(); }
         }
+        let prefix_indicator_style = state.prefix_indicator.clone();
+        let server_key_table = state.key_table.clone().unwrap_or_default();
+        let in_copy_mode = active_in_copy_mode(&root);
+        let prefix_str = crate::config::format_key_binding(&prefix_key);
 
         // ── STEP 3: Render ───────────────────────────────────────────────
         let sel_s = rsel_start;
         let sel_e = rsel_end;
-        let status_at_top = status_position_str == "top";
         terminal.draw(|f| {
             let area = f.area();
-            let constraints = if status_at_top {
-                vec![Constraint::Length(status_lines as u16), Constraint::Min(1)]
-            } else {
-                vec![Constraint::Min(1), Constraint::Length(status_lines as u16)]
-            };
-            let chunks = Layout::default().direction(Direction::Vertical)
-                .constraints(constraints).split(area);
-            let (content_chunk, status_chunk) = if status_at_top {
-                (chunks[1], chunks[0])
-            } else {
-                (chunks[0], chunks[1])
-            };
+            let (content_chunk, status_chunk) = status_layout(area, status_lines as u16, hide_status, &status_position_str);
 
             /// Render a large ASCII clock overlay (tmux clock-mode)
             fn render_clock_overlay(f: &mut Frame, area: Rect) {
@@ -1335,7 +2311,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 }
             }
 
-            fn render_json(f: &mut Frame, node: &LayoutJson, area: Rect, dim_preds: bool, border_fg: Color, active_border_fg: Color, clock_mode: bool, active_rect: Option<Rect>, mode_style_str: &str) {
+            fn render_json(f: &mut Frame, node: &LayoutJson, area: Rect, dim_preds: bool, border_fg: Color, active_border_fg: Color, glyphs: (char, char), clock_mode: bool, active_rect: Option<Rect>, mode_style_str: &str, match_styles: (&str, &str)) {
                 match node {
                     LayoutJson::Leaf {
                         id: _,
@@ -1343,10 +2319,15 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         cols: _,
                         cursor_row,
                         cursor_col,
+                        cursor_hidden,
                         alternate_screen,
                         active,
                         copy_mode,
                         scroll_offset,
+                        view_offset,
+                        hscroll,
+                        copy_register,
+                        border_text: _,
                         sel_start_row,
                         sel_start_col,
                         sel_end_row,
@@ -1354,13 +2335,24 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         sel_mode,
                         copy_cursor_row,
                         copy_cursor_col,
+                        search_matches,
+                        search_current,
+                        search_position,
+                        highlights,
+                        highlight_styles,
                         content,
                         rows_v2,
+                        rows_bin: _,
+                        deferred: _,
+                        dead,
+                        dead_status,
                     } => {
                         // No borders — content fills entire area (tmux-style)
                         let inner = area;
                         let mut lines: Vec<Line> = Vec::new();
                         let use_full_cells = *copy_mode && *active && !content.is_empty();
+                        let match_style = crate::rendering::parse_tmux_style(match_styles.0);
+                        let current_match_style = crate::rendering::parse_tmux_style(match_styles.1);
                         if use_full_cells || rows_v2.is_empty() {
                             for r in 0..inner.height.min(content.len() as u16) {
                                 let mut spans: Vec<Span> = Vec::new();
@@ -1399,6 +2391,11 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                         fg = dim_color(fg);
                                     }
                                     let mut style = Style::default().fg(fg).bg(bg);
+                                    let search_match = search_matches.iter()
+                                        .position(|&(mr, c0, c1)| mr == r && c >= c0 && c <= c1);
+                                    if let Some(i) = search_match {
+                                        style = if Some(i) == *search_current { current_match_style } else { match_style };
+                                    }
                                     if in_selection {
                                         // Apply mode-style from theme/config instead of hardcoded colors
                                         let ms = crate::rendering::parse_tmux_style(&mode_style_str);
@@ -1448,10 +2445,33 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         f.render_widget(Clear, inner);
                         let para = Paragraph::new(Text::from(lines));
                         f.render_widget(para, inner);
+                        draw_highlights(f.buffer_mut(), inner, highlights, highlight_styles);
+
+                        // A dead pane: its last screen dimmed, the banner on its last row
+                        if *dead && inner.height > 0 {
+                            f.buffer_mut().set_style(inner, Style::default().add_modifier(Modifier::DIM));
+                            let banner = Rect::new(inner.x, inner.y + inner.height - 1, inner.width, 1);
+                            let text = Span::styled(dead_pane_banner(*dead_status), Style::default().add_modifier(Modifier::REVERSED));
+                            f.render_widget(Clear, banner);
+                            f.render_widget(Paragraph::new(Line::from(text)), banner);
+                        }
 
                         // Copy mode indicator (replaces the old block title "[copy mode]")
                         if *copy_mode && *active {
-                            let label = "[copy mode]";
+                            // The unwrapped view names the first column it shows
+                            let mut label = match hscroll {
+                                Some(off) => format!("[copy mode, col {}]", off + 1),
+                                None => "[copy mode]".to_string(),
+                            };
+                            // The register the next yank goes to, or `"` while one is chosen
+                            if let Some(r) = copy_register {
+                                let reg = if *r == '"' { "\"".to_string() } else { format!("\"{}", r) };
+                                label = format!("{}, {}]", label.trim_end_matches(']'), reg);
+                            }
+                            // Which search match the cursor is on, of how many
+                            if let Some((n, total)) = search_position {
+                                label = format!("[{}/{}] {}", n, total, label);
+                            }
                             let lw = label.len() as u16;
                             if area.width >= lw {
                                 let lx = area.x + area.width.saturating_sub(lw);
@@ -1461,6 +2481,17 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                             }
                         }
 
+                        // View scrolled back without copy mode
+                        if !*copy_mode && *view_offset > 0 {
+                            let label = view_scroll_label(*view_offset);
+                            let lw = label.len() as u16;
+                            if area.width >= lw {
+                                let la = Rect::new(area.x + area.width - lw, area.y, lw, 1);
+                                let ls = Span::styled(label, Style::default().fg(Color::Black).bg(Color::Yellow));
+                                f.render_widget(Paragraph::new(Line::from(ls)), la);
+                            }
+                        }
+
                         if *copy_mode && *active && *scroll_offset > 0 {
                             let indicator = format!("[{}/{}]", scroll_offset, scroll_offset);
                             let indicator_width = indicator.len() as u16;
@@ -1473,26 +2504,27 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                             }
                         }
 
-                        if *active && !*copy_mode {
-                            // Clock mode overlay
-                            if clock_mode {
-                                render_clock_overlay(f, inner);
-                            }
-                            let cy = inner.y + (*cursor_row).min(inner.height.saturating_sub(1));
-                            let cx = inner.x + (*cursor_col).min(inner.width.saturating_sub(1));
-                            f.set_cursor_position((cx, cy));
+                        // Clock mode overlay
+                        if *active && !*copy_mode && clock_mode {
+                            render_clock_overlay(f, inner);
                         }
 
-                        // In copy mode, show cursor at copy_pos with a
-                        // highlighted (reverse-video) cell so the user can see
-                        // where the cursor is before starting selection.
-                        if *copy_mode && *active {
-                            if let (Some(cr), Some(cc)) = (copy_cursor_row, copy_cursor_col) {
-                                let cr = (*cr).min(inner.height.saturating_sub(1));
-                                let cc = (*cc).min(inner.width.saturating_sub(1));
-                                let cy = inner.y + cr;
-                                let cx = inner.x + cc;
-                                f.set_cursor_position((cx, cy));
+                        let copy_cursor = match (copy_cursor_row, copy_cursor_col) {
+                            (Some(r), Some(c)) => Some((*r, *c)),
+                            _ => None,
+                        };
+                        // The live cursor is off-screen while the view is scrolled back
+                        let app_cursor_hidden = *cursor_hidden || *view_offset > 0;
+                        let pos = pane_cursor_position(*active, *copy_mode, app_cursor_hidden, copy_cursor, (*cursor_row, *cursor_col));
+                        if let Some((cr, cc)) = pos {
+                            let cy = inner.y + cr.min(inner.height.saturating_sub(1));
+                            let cx = inner.x + cc.min(inner.width.saturating_sub(1));
+                            f.set_cursor_position((cx, cy));
+
+                            // In copy mode, also highlight the cursor cell
+                            // so the user can see where it is before
+                            // starting selection.
+                            if *copy_mode {
                                 // Highlight the cursor cell with reverse video
                                 let buf = f.buffer_mut();
                                 let buf_area = buf.area;
@@ -1520,7 +2552,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
 
                         // Render children first
                         for (i, child) in children.iter().enumerate() {
-                            if i < rects.len() { render_json(f, child, rects[i], dim_preds, border_fg, active_border_fg, clock_mode, active_rect, mode_style_str); }
+                            if i < rects.len() { render_json(f, child, rects[i], dim_preds, border_fg, active_border_fg, glyphs, clock_mode, active_rect, mode_style_str, match_styles); }
                         }
 
                         // Draw separator lines between children using direct buffer access.
@@ -1553,7 +2585,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             let idx = (y - buf.area.y) as usize * buf.area.width as usize
                                                 + (sep_x - buf.area.x) as usize;
                                             if idx < buf.content.len() {
-                                                buf.content[idx].set_char('│');
+                                                buf.content[idx].set_char(glyphs.0);
                                                 buf.content[idx].set_style(sty);
                                             }
                                         }
@@ -1567,7 +2599,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             let idx = (y - buf.area.y) as usize * buf.area.width as usize
                                                 + (sep_x - buf.area.x) as usize;
                                             if idx < buf.content.len() {
-                                                buf.content[idx].set_char('│');
+                                                buf.content[idx].set_char(glyphs.0);
                                                 buf.content[idx].set_style(sty);
                                             }
                                         }
@@ -1588,7 +2620,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             let idx = (sep_y - buf.area.y) as usize * buf.area.width as usize
                                                 + (x - buf.area.x) as usize;
                                             if idx < buf.content.len() {
-                                                buf.content[idx].set_char('─');
+                                                buf.content[idx].set_char(glyphs.1);
                                                 buf.content[idx].set_style(sty);
                                             }
                                         }
@@ -1602,7 +2634,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                                             let idx = (sep_y - buf.area.y) as usize * buf.area.width as usize
                                                 + (x - buf.area.x) as usize;
                                             if idx < buf.content.len() {
-                                                buf.content[idx].set_char('─');
+                                                buf.content[idx].set_char(glyphs.1);
                                                 buf.content[idx].set_style(sty);
                                             }
                                         }
@@ -1614,8 +2646,19 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 }
             }
 
-            let active_rect = compute_active_rect_json(&root, content_chunk);
-            render_json(f, &root, content_chunk, dim_preds, pane_border_fg, pane_active_border_fg, clock_active, active_rect, &mode_style_str);
+            // pane-border-status takes a row above or below the panes
+            let panes_chunk = panes_area(content_chunk, &pane_border_status);
+            let active_rect = compute_active_rect_json(&root, panes_chunk);
+            // Without colour indicators the active pane's borders look like any other
+            let active_border_fg = if pane_border_marks.0 { pane_active_border_fg } else { pane_border_fg };
+            render_json(f, &root, panes_chunk, dim_preds, pane_border_fg, active_border_fg, pane_border_glyphs, clock_active, active_rect, &mode_style_str, (&match_style_str, &current_match_style_str));
+            if panes_chunk != content_chunk {
+                draw_border_status(f.buffer_mut(), &root, content_chunk, panes_chunk, pane_border_glyphs.1, (pane_border_fg, pane_active_border_fg));
+            }
+            if let (true, Some(rect)) = (pane_border_marks.1, active_rect) {
+                draw_border_arrows(f.buffer_mut(), rect, panes_chunk, pane_active_border_fg);
+            }
+            if let Some(rect) = active_rect { draw_local_echo(f, &predictor, rect); }
 
             // ── Left-click drag text selection overlay ────────────────
             if let (Some(s), Some(e)) = (sel_s, sel_e) {
@@ -1661,43 +2704,81 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 f.render_widget(para, overlay.inner(oa));
             }
             if tree_chooser {
-                let overlay = Block::default().borders(Borders::ALL).title("choose-tree");
-                let oa = centered_rect(60, 30, content_chunk);
+                let mut title = if tree_commands.is_empty() { format!("choose-tree (sort: {}, o to change)", tree_sort.label()) } else { "choose-tree".to_string() };
+                if !tree_filter.is_empty() { title = format!("{} filter: {}", title, tree_filter); }
+                let overlay = Block::default().borders(Borders::ALL).title(title);
+                // Wider with the preview beside the rows
+                let preview = tree_preview.as_ref().filter(|_| tree_commands.is_empty());
+                let oa = centered_rect(if preview.is_some() { 90 } else { 60 }, 30, content_chunk);
                 f.render_widget(Clear, oa);
                 f.render_widget(&overlay, oa);
+                let inner = overlay.inner(oa);
+                let (list_area, preview_area) = match preview {
+                    Some(_) => {
+                        let cols = Layout::default().direction(Direction::Horizontal)
+                            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)]).split(inner);
+                        (cols[0], Some(cols[1]))
+                    }
+                    None => (inner, None),
+                };
+                let rows = tree_visible_rows(&tree_entries, &tree_collapsed, &tree_filter);
+                // Scrolled to keep the highlighted row in view
+                let height = list_area.height as usize;
+                let pos = rows.iter().position(|&i| i == tree_selected).unwrap_or(0);
                 let mut lines: Vec<Line> = Vec::new();
-                for (i, (is_win, wid, _pid, label, _sess)) in tree_entries.iter().enumerate() {
+                for &i in rows.iter().skip((pos + 1).saturating_sub(height)).take(height) {
+                    let (is_win, wid, _pid, label, sess) = &tree_entries[i];
+                    let label = if tree_collapsed.contains(&(sess.clone(), *wid)) && *is_win && tree_filter.is_empty() {
+                        format!("{} +", label)
+                    } else {
+                        label.clone()
+                    };
                     let line = if i == tree_selected {
-                        Line::from(Span::styled(label.clone(), Style::default().bg(Color::Yellow).fg(Color::Black)))
+                        Line::from(Span::styled(label, Style::default().bg(Color::Yellow).fg(Color::Black)))
                     } else if *is_win && *wid == usize::MAX {
                         // Session header — bold
-                        Line::from(Span::styled(label.clone(), Style::default().add_modifier(Modifier::BOLD)))
+                        Line::from(Span::styled(label, Style::default().add_modifier(Modifier::BOLD)))
                     } else {
-                        Line::from(label.clone())
+                        Line::from(label)
                     };
                     lines.push(line);
                 }
-                let para = Paragraph::new(Text::from(lines));
-                f.render_widget(para, overlay.inner(oa));
+                if rows.is_empty() { lines.push(Line::from(Span::styled("no matches", Style::default().fg(Color::DarkGray)))); }
+                f.render_widget(Paragraph::new(Text::from(lines)), list_area);
+                if let (Some(((_, pane_id), text, _)), Some(area)) = (preview, preview_area) {
+                    let block = Block::default().borders(Borders::LEFT).title(format!(" %{} ", pane_id));
+                    let shown = block.inner(area);
+                    // The bottom of the screen, where the prompt is
+                    let screen: Vec<&str> = text.trim_end_matches('\n').lines().collect();
+                    let lines: Vec<Line> = screen[screen.len().saturating_sub(shown.height as usize)..].iter()
+                        .map(|l| Line::from(crate::sanitize::text(l, shown.width as usize)))
+                        .collect();
+                    f.render_widget(block, area);
+                    f.render_widget(Paragraph::new(Text::from(lines)), shown);
+                }
             }
-            if keys_viewer {
-                // Proportional overlay: 90% width, up to 80% height
+            if viewer {
+                // Proportional overlay: 90% width, up to 80% height, or the active pane
                 let avail_h = content_chunk.height;
                 let overlay_h = (avail_h * 80 / 100).max(5).min(avail_h.saturating_sub(2));
-                let overlay = Block::default().borders(Borders::ALL)
-                    .title(" list-keys (q/Esc=close, Up/Down/PgUp/PgDn=scroll) ");
-                let oa = centered_rect(90, overlay_h, content_chunk);
+                let overlay = Block::default().borders(Borders::ALL).title(viewer_title.as_str());
+                let oa = match active_rect.filter(|_| viewer_in_pane) {
+                    Some(r) if r.height >= 3 && r.width >= 3 => r,
+                    _ => centered_rect(90, overlay_h, content_chunk),
+                };
                 f.render_widget(Clear, oa);
                 f.render_widget(&overlay, oa);
                 let inner = overlay.inner(oa);
                 let visible_h = inner.height as usize;
                 // Clamp scroll so we don't scroll past the end
-                let max_scroll = keys_viewer_lines.len().saturating_sub(visible_h);
-                if keys_viewer_scroll > max_scroll { keys_viewer_scroll = max_scroll; }
+                let max_scroll = viewer_lines.len().saturating_sub(visible_h);
+                if viewer_scroll > max_scroll { viewer_scroll = max_scroll; }
                 let mut lines: Vec<Line> = Vec::new();
-                for (_i, entry) in keys_viewer_lines.iter().enumerate().skip(keys_viewer_scroll).take(visible_h) {
+                for (_i, entry) in viewer_lines.iter().enumerate().skip(viewer_scroll).take(visible_h) {
                     // Highlight section headers, "bind-key" keyword, and plain text differently
-                    if entry.starts_with("──") || entry.starts_with("── ") {
+                    if viewer_in_pane {
+                        lines.push(Line::from(entry.clone()));
+                    } else if entry.starts_with("──") || entry.starts_with("── ") {
                         lines.push(Line::from(Span::styled(entry.clone(), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))));
                     } else if let Some(rest) = entry.strip_prefix("bind-key") {
                         lines.push(Line::from(vec![
@@ -1712,11 +2793,11 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 let para = Paragraph::new(Text::from(lines));
                 f.render_widget(para, inner);
                 // Scroll position indicator
-                if keys_viewer_lines.len() > visible_h {
-                    let pct = if max_scroll == 0 { 100 } else { keys_viewer_scroll * 100 / max_scroll };
-                    let indicator = if keys_viewer_scroll == 0 {
+                if viewer_lines.len() > visible_h {
+                    let pct = if max_scroll == 0 { 100 } else { viewer_scroll * 100 / max_scroll };
+                    let indicator = if viewer_scroll == 0 {
                         "Top".to_string()
-                    } else if keys_viewer_scroll >= max_scroll {
+                    } else if viewer_scroll >= max_scroll {
                         "Bot".to_string()
                     } else {
                         format!("{}%", pct)
@@ -1750,7 +2831,7 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         }
                     }
                 }
-                rec(&root, content_chunk, &mut rects);
+                rec(&root, panes_chunk, &mut rects);
                 choices.clear();
                 for (i, (pid, r)) in rects.iter().enumerate() {
                     if i < 10 {
@@ -1785,21 +2866,21 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                 Some(ref sl) => sl.clone(),
                 None => format!("[{}] ", name),
             };
-            let mut status_spans: Vec<Span> = crate::rendering::parse_inline_styles(&left_prefix, sb_base);
+            let mut status_spans = parse_inline_ranges(&left_prefix, sb_base, None);
             for (i, w) in windows.iter().enumerate() {
                 // Use pre-expanded tab_text from server (full format expansion)
                 let tab_text = if !w.tab_text.is_empty() {
                     w.tab_text.clone()
                 } else {
                     // Fallback for old server: naive expansion
-                    let display_idx = i + base_index;
+                    let display_idx = w.index.unwrap_or(i + base_index);
                     let fmt = if w.active { &win_status_current_fmt } else { &win_status_fmt };
                     fmt.replace("#I", &display_idx.to_string())
                        .replace("#W", &w.name)
                        .replace("#F", if w.active { "*" } else { "" })
                 };
                 if i > 0 {
-                    status_spans.push(Span::styled(win_status_sep.clone(), sb_base));
+                    status_spans.push((Span::styled(win_status_sep.clone(), sb_base), None));
                 }
                 // Determine fallback style based on window state
                 let fallback_style = if w.active {
@@ -1812,6 +2893,8 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                     } else {
                         sb_base
                     }
+                } else if !w.alert_style.is_empty() {
+                    sb_base.patch(crate::style::parse_tmux_style(&w.alert_style))
                 } else if w.activity {
                     Style::default()
                         .fg(Color::Black)
@@ -1828,50 +2911,61 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
                         sb_base
                     }
                 };
-                // Parse inline #[fg=...,bg=...] style directives from theme format strings
-                let tab_spans = crate::rendering::parse_inline_styles(&tab_text, fallback_style);
-                status_spans.extend(tab_spans);
+                // Parse inline #[fg=...,bg=...] style directives from theme format
+                // strings; the tab is a range selecting its window
+                status_spans.extend(parse_inline_ranges(&tab_text, fallback_style, Some(StatusRange::Window(format!("@{}", w.id)))));
             }
             // Right portion: custom status_right (already expanded by server)
             // Parse inline #[...] style directives for theme support
             let right_text = custom_status_right.as_deref().unwrap_or("").to_string();
-            let right_spans = crate::rendering::parse_inline_styles(&right_text, sb_base);
+            let mut right_spans = parse_inline_ranges(&right_text, sb_base, None);
+            // Key-state indicator at the right edge: armed prefix is ours, the table is the server's
+            if let Some(style) = &prefix_indicator_style {
+                let table = Some(server_key_table.as_str()).filter(|t| !t.is_empty());
+                let text = crate::style::prefix_indicator_text(&prefix_str, routing.prefix_armed, table, in_copy_mode);
+                right_spans.extend(crate::style::prefix_indicator_spans(text.as_deref(), style, sb_base).into_iter().map(|s| (s, None)));
+            }
             // Compute how many columns are used by left + window tabs
-            let left_used: usize = status_spans.iter().map(|s| s.content.len()).sum();
-            let right_len: usize = right_spans.iter().map(|s| s.content.len()).sum();
+            let left_used: usize = status_spans.iter().map(|(s, _)| s.width()).sum();
+            let right_len: usize = right_spans.iter().map(|(s, _)| s.width()).sum();
             let total_width = status_chunk.width as usize;
             if left_used + right_len < total_width {
                 let pad = total_width - left_used - right_len;
-                status_spans.push(Span::styled(" ".repeat(pad), sb_base));
+                status_spans.push((Span::styled(" ".repeat(pad), sb_base), None));
                 status_spans.extend(right_spans);
             }
-            let status_bar = Paragraph::new(Line::from(status_spans)).style(sb_base);
+            // A status-format[0] of its own replaces the built-in line
+            if let Some(text) = status_format.first().filter(|s| !s.is_empty()) {
+                status_spans = parse_inline_ranges(text, sb_base, None);
+            }
             f.render_widget(Clear, status_chunk);
-            // Render the first status line (line 0)
+            // Render the first status line (line 0), then additional status
+            // lines (index 1+) from status_format, each with its own #[...]
+            // styles and ranges over the status style
+            status_ranges.clear();
             let line0_area = Rect { x: status_chunk.x, y: status_chunk.y, width: status_chunk.width, height: 1.min(status_chunk.height) };
-            f.render_widget(status_bar, line0_area);
-            // Render additional status lines (index 1+) from status_format
-            for line_idx in 1..status_lines {
-                let line_y = status_chunk.y + line_idx as u16;
-                if line_y >= status_chunk.y + status_chunk.height { break; }
-                let line_area = Rect { x: status_chunk.x, y: line_y, width: status_chunk.width, height: 1 };
-                let text = if line_idx < status_format.len() && !status_format[line_idx].is_empty() {
-                    status_format[line_idx].clone()
-                } else {
-                    String::new()
+            for line_idx in 0..status_lines {
+                let Some(line_area) = status_line_area(status_chunk, line_idx) else { break };
+                let line_y = line_area.y;
+                let spans = if line_idx == 0 { std::mem::take(&mut status_spans) } else {
+                    parse_inline_ranges(status_format.get(line_idx).map_or("", String::as_str), sb_base, None)
                 };
-                // Pad to full width
-                let padded: String = if text.len() < line_area.width as usize {
-                    format!("{}{}", text, " ".repeat(line_area.width as usize - text.len()))
-                } else {
-                    text.chars().take(line_area.width as usize).collect()
+                status_ranges.extend(range_columns(&spans, line_area.x).into_iter().map(|(start, end, r)| (line_y, start, end, r)));
+                let line = Line::from(spans.into_iter().map(|(s, _)| s).collect::<Vec<_>>());
+                f.render_widget(Paragraph::new(line).style(sb_base), line_area);
+            }
+            if let Some(ref msg) = server_message {
+                let msg_style = Style::default().fg(Color::Black).bg(Color::Yellow);
+                // Without a status line, messages borrow the bottom pane row
+                let msg_area = if line0_area.height > 0 { line0_area } else {
+                    Rect { y: content_chunk.y + content_chunk.height.saturating_sub(1), height: 1.min(content_chunk.height), ..content_chunk }
                 };
-                let line_widget = Paragraph::new(Line::from(Span::styled(padded, sb_base))).style(sb_base);
-                f.render_widget(line_widget, line_area);
+                f.render_widget(Clear, msg_area);
+                f.render_widget(Paragraph::new(msg.as_str()).style(msg_style), msg_area);
             }
             if renaming {
                 let overlay = Block::default().borders(Borders::ALL).title("rename window");
-                let oa = centered_rect(60, 3, chunks[0]);
+                let oa = centered_rect(60, 3, content_chunk);
                 f.render_widget(Clear, oa);
                 f.render_widget(&overlay, oa);
                 let para = Paragraph::new(format!("name: {}", rename_buf));
@@ -1879,27 +2973,22 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             }
             if pane_renaming {
                 let overlay = Block::default().borders(Borders::ALL).title("set pane title");
-                let oa = centered_rect(60, 3, chunks[0]);
+                let oa = centered_rect(60, 3, content_chunk);
                 f.render_widget(Clear, oa);
                 f.render_widget(&overlay, oa);
                 let para = Paragraph::new(format!("title: {}", pane_title_buf));
                 f.render_widget(para, overlay.inner(oa));
             }
-            if command_input {
-                let overlay = Block::default().borders(Borders::ALL).title("command");
-                let oa = centered_rect(60, 3, chunks[0]);
-                f.render_widget(Clear, oa);
-                f.render_widget(&overlay, oa);
-                let para = Paragraph::new(format!(": {}", command_buf));
-                f.render_widget(para, overlay.inner(oa));
+            if let Some(ref prompt) = open_prompt {
+                prompt.render(f, centered_rect(60, 3, content_chunk));
             }
-            if let Some(ref cmd) = confirm_cmd {
-                let overlay = Block::default().borders(Borders::ALL).title("confirm");
-                let oa = centered_rect(50, 3, chunks[0]);
-                f.render_widget(Clear, oa);
-                f.render_widget(&overlay, oa);
-                let para = Paragraph::new(format!("{}? (y/n)", cmd));
-                f.render_widget(para, overlay.inner(oa));
+            if let Some(ref p) = popup {
+                let at = Rect::new(areas.0.x + p.x, server_row_to_client(p.y, areas.0, areas.1), p.width, p.height);
+                if let Some(pos) = crate::popup::render(f, p, at) { f.set_cursor_position(pos); }
+            }
+            if let Some(ref mut menu) = open_menu {
+                let (x, y) = (menu.menu.x, menu.menu.y.map(|y| server_row_to_client(y, areas.0, areas.1)));
+                menu.render(f, f.area(), x, y);
             }
         })?;
         let _render_us = _t_parse.elapsed().as_micros().saturating_sub(_parse_us as u128);
@@ -1912,23 +3001,23 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
             let _ = writeln!(log, "L{}: key->render {}ms  parse={}us  render={}us  json_len={}  since_dump={}",
                 loop_count, elapsed_ms, _parse_us, _render_us, dump_buf.len(), since_dump);
             // Only clear after we rendered a DIFFERENT frame (echo arrived)
-            if got_frame && dump_buf != prev_dump_buf {
-                let _ = writeln!(log, "L{}: ECHO VISIBLE after {}ms  (parse={}us render={}us)",
-                    loop_count, elapsed_ms, _parse_us, _render_us);
+            if got_frame && dump_buf != cache.frame {
+                let _ = writeln!(log, "L{}: ECHO VISIBLE after {}ms  (parse={}us render={}us)  frames={}",
+                    loop_count, elapsed_ms, _parse_us, _render_us, if subscribed && !low_bandwidth { "push" } else { "poll" });
                 key_send_instant = None;
             }
         }
         selection_changed = false;
         // Cache this frame so we can skip identical re-renders.
         // Only update cache when we got a genuinely new frame (not selection-only redraw)
-        if got_frame && dump_buf != prev_dump_buf {
-            std::mem::swap(&mut prev_dump_buf, &mut dump_buf);
+        if got_frame && dump_buf != cache.frame {
+            std::mem::swap(&mut cache.frame, &mut dump_buf);
         }
         // DON'T clear last_key_send_time — keep fast-dumping for 100ms
         // after last keystroke so we catch the ConPTY echo promptly.
         // The timer expires naturally in the poll_ms calculation above.
         // Clear key_send_instant once echo arrives (frame differs).
-        if got_frame && dump_buf != prev_dump_buf {
+        if got_frame && dump_buf != cache.frame {
             key_send_instant = None;
         }
         force_dump = false;
@@ -1939,3 +3028,413 @@ pub fn run_remote(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, input:
     let _ = writer.flush();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_rows_are_reserved_where_the_status_sits() {
+        let term = Rect::new(0, 0, 80, 30);
+        // status-lines 3 at the top: rows 0-2, panes from row 3
+        let (panes, status) = status_layout(term, 3, false, "top");
+        assert_eq!((status.y, status.height, panes.y, panes.height), (0, 3, 3, 27));
+        // ...and at the bottom: panes first, status on rows 27-29
+        let (panes, status) = status_layout(term, 3, false, "bottom");
+        assert_eq!((panes.y, panes.height, status.y, status.height), (0, 27, 27, 3));
+        // One line, and a hidden status taking none
+        assert_eq!(status_layout(term, 1, false, "bottom").1, Rect::new(0, 29, 80, 1));
+        assert_eq!(status_layout(term, 3, true, "top"), (term, Rect::new(0, 0, 80, 0)));
+        // Never so many that no pane row is left
+        assert_eq!(status_layout(Rect::new(0, 0, 80, 3), 5, false, "top").0.height, 1);
+    }
+
+    #[test]
+    fn status_clicks_hit_ranges_on_every_line() {
+        let term = Rect::new(0, 0, 80, 30);
+        let lines = [
+            parse_inline_ranges("0:sh*", Style::default(), Some(StatusRange::Window("@1".into()))),
+            parse_inline_ranges("cpu #[range=window|3]three#[norange] mem", Style::default(), None),
+            parse_inline_ranges("", Style::default(), None),
+        ];
+        for position in ["top", "bottom"] {
+            let (panes, status) = status_layout(term, 3, false, position);
+            // Laid out as the draw loop does, one row per line
+            let mut ranges = Vec::new();
+            for (i, spans) in lines.iter().enumerate() {
+                let area = status_line_area(status, i).unwrap();
+                ranges.extend(range_columns(spans, area.x).into_iter().map(|(start, end, r)| (area.y, start, end, r)));
+            }
+            assert_eq!(status_line_area(status, 3), None);
+            let cmd = |col, row| status_range_at(&ranges, col, row).map(StatusRange::command);
+            assert_eq!(cmd(2, status.y).as_deref(), Some("select-window -t @1"));
+            // The second line's tab, and the text around it
+            assert_eq!(cmd(4, status.y + 1).as_deref(), Some("select-window -t :3"));
+            assert_eq!(cmd(8, status.y + 1).as_deref(), Some("select-window -t :3"));
+            assert_eq!(cmd(2, status.y + 1), None);
+            assert_eq!(cmd(9, status.y + 1), None);
+            // The same columns on the empty third line and in the panes
+            assert_eq!(cmd(4, status.y + 2), None);
+            assert_eq!(cmd(2, panes.y), None);
+        }
+    }
+
+    #[test]
+    fn switch_table_commands_name_their_table() {
+        assert_eq!(switch_table_of("switch-client -T resize\n").as_deref(), Some("resize"));
+        assert_eq!(switch_table_of("switchc -c x -T prefix").as_deref(), Some("prefix"));
+        assert_eq!(switch_table_of("next-window \\; switch-client -T nav").as_deref(), Some("nav"));
+        assert_eq!(switch_table_of("switch-client -t work"), None);
+        assert_eq!(switch_table_of("select-pane -T title"), None);
+    }
+
+    #[test]
+    fn synced_any_bindings_catch_unbound_keys_with_their_name() {
+        let bindings: Vec<BindingEntry> = serde_json::from_str(r##"[
+            {"t":"prefix","k":"c","c":"new-window"},
+            {"t":"prefix","k":"Any","c":"display-message 'prefix #{key}'"},
+            {"t":"nav","k":"Any","c":"select-window -t :#{key}"},
+            {"t":"root","k":"F2","c":"new-window"},
+            {"t":"root","k":"Any","c":"display-message #{key}"}
+        ]"##).unwrap();
+        let cmd = |table: &str, key: (KeyCode, KeyModifiers), root_any: bool| synced_binding(&bindings, table, key, root_any).map(|b| b.c);
+        let ch = |c: char| (KeyCode::Char(c), KeyModifiers::NONE);
+        // An exact binding wins; anything else falls to Any with #{key} filled in
+        assert_eq!(cmd("prefix", ch('c'), false).as_deref(), Some("new-window"));
+        assert_eq!(cmd("prefix", (KeyCode::Char('x'), KeyModifiers::CONTROL), false).as_deref(), Some("display-message 'prefix C-x'"));
+        assert_eq!(cmd("nav", ch('3'), false).as_deref(), Some("select-window -t :3"));
+        // Root's Any only with root-any-binding on
+        assert_eq!(cmd("root", (KeyCode::F(2), KeyModifiers::NONE), false).as_deref(), Some("new-window"));
+        assert_eq!(cmd("root", ch('q'), false), None);
+        assert_eq!(cmd("root", (KeyCode::Up, KeyModifiers::ALT), true).as_deref(), Some("display-message M-Up"));
+        assert_eq!(cmd("other", ch('q'), true), None);
+    }
+
+    /// The bindings a server with `config` syncs to its clients.
+    fn synced(config: &str) -> Vec<BindingEntry> {
+        let mut app = crate::types::AppState::new("t".to_string());
+        crate::config::parse_config_content(&mut app, config);
+        serde_json::from_str(&crate::server::serialize_bindings_json(&app)).unwrap()
+    }
+
+    /// One key through the routing of run_remote's key handler, with no
+    /// overlay open: a switch-client -T table, the prefix key, root, then
+    /// the prefix table.  Returns the commands sent, empty when the key
+    /// went to the pane.
+    fn press(routing: &mut KeyRouting, bindings: &[BindingEntry], key: char, now: Instant) -> Vec<String> {
+        let key = (KeyCode::Char(key), KeyModifiers::NONE);
+        let prefix_binding = synced_binding(bindings, "prefix", key, false);
+        let repeats = prefix_binding.as_ref().map_or_else(|| help::default_repeats(key), |b| b.r);
+        let had_table = routing.table.is_some();
+        let table_binding = routing.begin_key(|| repeats, Some(Duration::from_secs(1)), now)
+            .and_then(|table| synced_binding(bindings, &table, key, false));
+        let mut sent = Vec::new();
+        if let Some(entry) = table_binding {
+            sent.push(entry.c);
+        } else if key == (KeyCode::Char('b'), KeyModifiers::CONTROL) {
+            routing.arm(now);
+        } else if let Some(entry) = synced_binding(bindings, "root", key, false) {
+            sent.push(entry.c);
+        } else if routing.prefix_armed {
+            sent.extend(prefix_binding.map(|b| b.c));
+            routing.prefix_key_done(repeats, Duration::from_millis(500), now);
+        }
+        let switched = routing.note_switches(&mut sent, now);
+        if had_table && !switched { sent.push("switch-client -T root".into()); }
+        sent
+    }
+
+    #[test]
+    fn repeatable_bindings_skip_the_prefix_until_repeat_time() {
+        let bindings = synced("bind -r H next-window\nbind J previous-window");
+        assert!(bindings.iter().any(|b| b.t == "prefix" && b.k == "H" && b.r));
+        // Unbound defaults such as M-Up resize-pane repeat too
+        assert!(help::default_repeats((KeyCode::Up, KeyModifiers::ALT)));
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut routing = KeyRouting::new();
+
+        routing.arm(t0);
+        assert_eq!(press(&mut routing, &bindings, 'H', at(0)), ["next-window"]);
+        assert_eq!(press(&mut routing, &bindings, 'H', at(100)), ["next-window"]);
+        // A key that does not repeat ends the window and goes to the pane
+        assert!(press(&mut routing, &bindings, 'J', at(200)).is_empty());
+        assert!(!routing.prefix_armed);
+
+        // Past repeat-time the prefix is needed again
+        routing.arm(at(300));
+        assert_eq!(press(&mut routing, &bindings, 'H', at(300)), ["next-window"]);
+        assert!(press(&mut routing, &bindings, 'H', at(900)).is_empty());
+        // and the window closes on its own between keys
+        routing.arm(at(1000));
+        press(&mut routing, &bindings, 'H', at(1000));
+        assert!(!routing.expire(None, at(1400)) && routing.prefix_armed);
+        routing.expire(None, at(1500));
+        assert!(!routing.prefix_armed);
+    }
+
+    #[test]
+    fn custom_tables_take_one_key_then_reset_to_root() {
+        let bindings = synced("bind -T nav n next-window\nbind -T nav N switch-client -T nav \\; next-window\nbind m switch-client -T nav");
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut routing = KeyRouting::new();
+
+        routing.arm(t0);
+        assert_eq!(press(&mut routing, &bindings, 'm', at(0)), ["switch-client -T nav"]);
+        assert_eq!(routing.table.as_ref().map(|(t, _)| t.as_str()), Some("nav"));
+        // A binding that switches again keeps the table; one that does not resets it
+        assert_eq!(press(&mut routing, &bindings, 'N', at(100)), ["switch-client -T nav \\; next-window"]);
+        assert!(routing.table.is_some());
+        assert_eq!(press(&mut routing, &bindings, 'n', at(200)), ["next-window", "switch-client -T root"]);
+        assert!(routing.table.is_none());
+        assert!(press(&mut routing, &bindings, 'n', at(300)).is_empty());
+
+        // prefix-timeout: a table left unused goes back to root
+        routing.note_switches(&mut vec!["switch-client -T nav".to_string()], at(400));
+        assert!(!routing.expire(Some(Duration::from_secs(1)), at(900)));
+        assert!(routing.expire(Some(Duration::from_secs(1)), at(1400)));
+        assert!(press(&mut routing, &bindings, 'n', at(1500)).is_empty());
+        // A lone switch to the prefix table is the client's own prefix
+        let mut sent = vec!["switch-client -T prefix".to_string()];
+        assert!(routing.note_switches(&mut sent, at(1600)));
+        assert!(sent.is_empty() && routing.prefix_armed);
+    }
+
+    /// list-tree output of a session with base-index 1 and pane-base-index 1.
+    const TREE: &str = r#"[
+        {"id":3,"name":"editor","active":true,"index":1,"activity_ms":30,"visited_ms":30,"panes":[
+            {"id":4,"title":"vim main.rs","index":1,"command":"nvim","width":80,"height":24,"active":false},
+            {"id":5,"title":"pytest tests/","index":2,"command":"pwsh","width":80,"height":24,"active":true}]},
+        {"id":0,"name":"logs","active":false,"index":2,"activity_ms":50,"visited_ms":10,"panes":[
+            {"id":0,"title":"pane %0","index":1,"command":"pwsh","width":160,"height":48,"active":true}]}
+    ]"#;
+
+    fn labels(entries: &[(bool, usize, usize, String, String)]) -> Vec<&str> {
+        entries.iter().map(|e| e.3.as_str()).collect()
+    }
+
+    #[test]
+    fn tree_chooser_lines_use_display_indices() {
+        let wins: Vec<WinTree> = serde_json::from_str(TREE).unwrap();
+        let sessions = vec![("work".to_string(), wins.clone()), ("other".to_string(), wins)];
+        let entries = tree_chooser_entries(&sessions, "work", WindowSort::Index);
+        assert_eq!(labels(&entries), [
+            "work: 2 windows (attached)",
+            "  (1) 1: editor* (2 panes)",
+            r#"    (1.1) %4: nvim "vim main.rs" [80x24]"#,
+            r#"    (1.2) %5: pwsh "pytest tests/" [80x24] (active)"#,
+            "  (2) 2: logs (1 panes)",
+            r#"    (2.1) %0: pwsh "pane %0" [160x48]"#,
+            "other: 2 windows",
+            "  (1) 1: editor* (2 panes)",
+            "  (2) 2: logs (1 panes)",
+        ]);
+        // The active pane of the active window is picked when the chooser opens
+        let sel = tree_chooser_current(&entries, &sessions, "work");
+        assert_eq!((entries[sel].1, entries[sel].2), (3, 5));
+
+        // Reordering keeps the labels; selection follows the (window, pane) ids
+        let by_activity = tree_chooser_entries(&sessions, "work", WindowSort::Activity);
+        assert_eq!(by_activity[1].3, "  (2) 2: logs (1 panes)");
+        assert_eq!(by_activity[tree_chooser_current(&by_activity, &sessions, "work")].2, 5);
+    }
+
+    #[test]
+    fn tree_chooser_filters_collapses_and_previews() {
+        let wins: Vec<WinTree> = serde_json::from_str(TREE).unwrap();
+        let sessions = vec![("work".to_string(), wins.clone()), ("other".to_string(), wins)];
+        let entries = tree_chooser_entries(&sessions, "work", WindowSort::Index);
+        let mut collapsed = std::collections::HashSet::new();
+        let shown = |c: &std::collections::HashSet<(String, usize)>, f: &str| -> Vec<usize> { tree_visible_rows(&entries, c, f) };
+        assert_eq!(shown(&collapsed, ""), (0..entries.len()).collect::<Vec<_>>());
+
+        // Fuzzy: in order, any case, with the rows above each match
+        assert!(fuzzy_match("    (1.1) %4: nvim \"vim main.rs\" [80x24]", "NVmain"));
+        assert!(!fuzzy_match("  (2) 2: logs (1 panes)", "sgol"));
+        assert_eq!(labels(&shown(&collapsed, "pytest").iter().map(|&i| entries[i].clone()).collect::<Vec<_>>()), [
+            "work: 2 windows (attached)",
+            "  (1) 1: editor* (2 panes)",
+            r#"    (1.2) %5: pwsh "pytest tests/" [80x24] (active)"#,
+        ]);
+        assert!(shown(&collapsed, "zzz").is_empty());
+
+        // Collapsing a window hides its panes, a session its windows
+        collapsed.insert(("work".to_string(), 3));
+        assert_eq!(shown(&collapsed, ""), [0, 1, 4, 5, 6, 7, 8]);
+        collapsed.insert(("other".to_string(), usize::MAX));
+        assert_eq!(shown(&collapsed, ""), [0, 1, 4, 5, 6]);
+        // but not the matches of a filter
+        assert_eq!(shown(&collapsed, "vim"), [0, 1, 2]);
+        assert_eq!((tree_parent(&entries, 2), tree_parent(&entries, 1), tree_parent(&entries, 0)), (Some(1), Some(0), None));
+        assert_eq!(tree_parent(&entries, 7), Some(6));
+        assert!(tree_has_children(&entries, 1) && tree_has_children(&entries, 6) && !tree_has_children(&entries, 7));
+
+        // Moving skips hidden rows and stops at the ends
+        let rows = shown(&collapsed, "");
+        assert_eq!((tree_step(&rows, 1, 1), tree_step(&rows, 6, 1), tree_step(&rows, 0, -1)), (4, 6, 0));
+        assert_eq!(tree_step(&rows, 2, 0), 4);
+
+        // A window previews its active pane, a session its active window's
+        assert_eq!(tree_preview_pane(&entries[5], &sessions), Some(("work".to_string(), 0)));
+        assert_eq!(tree_preview_pane(&entries[1], &sessions), Some(("work".to_string(), 5)));
+        assert_eq!(tree_preview_pane(&entries[6], &sessions), Some(("other".to_string(), 5)));
+        assert_eq!(tree_preview_pane(&entries[2], &sessions), Some(("work".to_string(), 4)));
+
+        // Captures are spaced out
+        let (t0, pane) = (Instant::now(), ("work".to_string(), 4));
+        let other = ("work".to_string(), 5);
+        assert!(preview_due(None, &pane, t0));
+        assert!(!preview_due(Some((&pane, t0)), &pane, t0 + PREVIEW_MIN_GAP));
+        assert!(preview_due(Some((&pane, t0)), &pane, t0 + PREVIEW_REFRESH));
+        assert!(!preview_due(Some((&pane, t0)), &other, t0 + Duration::from_millis(10)));
+        assert!(preview_due(Some((&pane, t0)), &other, t0 + PREVIEW_MIN_GAP));
+    }
+
+    #[test]
+    fn tree_chooser_falls_back_to_the_active_window() {
+        // A server too old to report panes' state
+        let wins: Vec<WinTree> = serde_json::from_str(r#"[{"id":7,"name":"a","active":false,"panes":[]},
+            {"id":9,"name":"b","active":true,"panes":[{"id":2,"title":"t"}]}]"#).unwrap();
+        let sessions = vec![("s".to_string(), wins)];
+        let entries = tree_chooser_entries(&sessions, "s", WindowSort::Index);
+        assert_eq!(entries[tree_chooser_current(&entries, &sessions, "s")].3, "  (0) 0: b* (1 panes)");
+        assert_eq!(tree_chooser_current(&entries, &sessions, "gone"), 0);
+    }
+
+    /// An 11x7 window split 2x2, with `mode`'s arrows drawn for the pane at
+    /// `active`.
+    fn indicator_grid(mode: &str, active: Rect) -> Vec<String> {
+        let area = Rect::new(0, 0, 11, 7);
+        let mut buf = Buffer::empty(area);
+        for y in 0..7 { buf[(5, y)].set_char('│'); }
+        for x in 0..11 { buf[(x, 3)].set_char(if x == 5 { '┼' } else { '─' }); }
+        if border_indicators(mode).1 { draw_border_arrows(&mut buf, active, area, Color::Green); }
+        (0..7).map(|y| (0..11).map(|x| buf[(x, y)].symbol()).collect()).collect()
+    }
+
+    #[test]
+    fn border_indicators_on_a_two_by_two_grid() {
+        let top_left = Rect::new(0, 0, 5, 3);
+        let bottom_right = Rect::new(6, 4, 5, 3);
+        let plain = ["     │     ", "     │     ", "     │     ", "─────┼─────", "     │     ", "     │     ", "     │     "];
+        assert_eq!(indicator_grid("off", top_left), plain);
+        assert_eq!(indicator_grid("colour", top_left), plain);
+        assert_eq!(indicator_grid("arrows", top_left),
+            ["     │     ", "     ◀     ", "     │     ", "──▲──┼─────", "     │     ", "     │     ", "     │     "]);
+        assert_eq!(indicator_grid("both", bottom_right),
+            ["     │     ", "     │     ", "     │     ", "─────┼──▼──", "     │     ", "     ▶     ", "     │     "]);
+        assert_eq!(border_indicators("off"), (false, false));
+        assert_eq!(border_indicators("colour"), (true, false));
+        assert_eq!(border_indicators("arrows"), (false, true));
+        assert_eq!(border_indicators("both"), (true, true));
+        // A pane filling the window has no borders to mark
+        assert!(border_arrows(Rect::new(0, 0, 11, 7), Rect::new(0, 0, 11, 7)).is_empty());
+    }
+
+    #[test]
+    fn highlight_styles_merge_over_cells_in_order() {
+        let area = Rect::new(2, 1, 8, 2);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 12, 4));
+        buf.set_style(area, Style::default().fg(Color::Green).bg(Color::Black));
+        let styles = ["fg=red".to_string(), "bold".to_string(), "bg=blue".to_string()];
+        // Overlapping matches, one at the last column, one past the pane's
+        // edge and one for an entry that is not there
+        let matches = [(0, 0, 5, 0), (0, 4, 7, 1), (1, 6, 12, 2), (1, 0, 0, 3), (2, 0, 3, 0)];
+        draw_highlights(&mut buf, area, &matches, &styles);
+        let style = |x, y| { let c: &ratatui::buffer::Cell = &buf[(x, y)]; (c.fg, c.bg, c.modifier) };
+        let (bold, none) = (Modifier::BOLD, Modifier::empty());
+        assert_eq!(style(2, 1), (Color::Red, Color::Black, none));
+        assert_eq!(style(6, 1), (Color::Red, Color::Black, bold));
+        assert_eq!(style(9, 1), (Color::Green, Color::Black, bold));
+        assert_eq!(style(9, 2), (Color::Green, Color::Blue, none));
+        assert_eq!(style(10, 2), (Color::Reset, Color::Reset, none));
+        assert_eq!(style(2, 2), (Color::Green, Color::Black, none));
+        assert_eq!(style(2, 3), (Color::Reset, Color::Reset, none));
+    }
+
+    /// Run the attach handshake against a server that sends `replies` and
+    /// then stalls; returns what the client wrote and the outcome.
+    fn handshake_with(replies: &[&str]) -> (String, io::Result<std::collections::VecDeque<String>>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        for r in replies { tx.send(format!("{}\n", r)).unwrap(); }
+        let mut sent = Vec::new();
+        let result = handshake(&mut sent, &rx, "work", &["client-attach".to_string()], Duration::from_millis(100));
+        drop(tx);
+        (String::from_utf8(sent).unwrap(), result)
+    }
+
+    #[test]
+    fn a_stalled_handshake_step_times_out_by_name() {
+        for (replies, step) in [(&[][..], "PERSISTENT ack"), (&["OK", "%message hi"][..], "first frame")] {
+            let start = Instant::now();
+            let err = handshake_with(replies).1.unwrap_err();
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(err.to_string().contains(&format!("no {} from session work", step)), "{}", err);
+            assert!(err.to_string().contains("kill-server -t work"));
+        }
+        // The handshake never puts the terminal in raw mode
+        assert!(!crossterm::terminal::is_raw_mode_enabled().unwrap_or(false));
+    }
+
+    #[test]
+    fn handshake_replies() {
+        let (sent, lines) = handshake_with(&["OK", "%message hi", "{\"layout\":1}"]);
+        assert_eq!(sent, "PERSISTENT\nclient-attach\ndump-state\n");
+        assert_eq!(lines.unwrap(), ["%message hi\n", "{\"layout\":1}\n"]);
+        // A server without the PERSISTENT ack sends the frame instead
+        assert_eq!(handshake_with(&["{}"]).1.unwrap(), ["{}\n"]);
+        // Refusals are shown as the server put them
+        let err = handshake_with(&["ERROR: Authentication required"]).1.unwrap_err();
+        assert_eq!(err.to_string(), "ERROR: Authentication required");
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn clicks_on_one_cell_are_counted() {
+        let t = Instant::now();
+        let ms = |n| t + Duration::from_millis(n);
+        let gap = Duration::from_millis(300);
+        let mut c = ClickCounter::default();
+        assert_eq!(c.press(t, 4, 2, gap), 1);
+        assert_eq!(c.press(ms(200), 4, 2, gap), 2);
+        assert_eq!(c.press(ms(400), 4, 2, gap), 3);
+        // A fourth click starts over, as do a slow click and another cell
+        assert_eq!(c.press(ms(500), 4, 2, gap), 1);
+        assert_eq!(c.press(ms(900), 4, 2, gap), 1);
+        assert_eq!(c.press(ms(950), 5, 2, gap), 1);
+        c.reset();
+        assert_eq!(c.press(ms(1000), 5, 2, gap), 1);
+    }
+
+    #[test]
+    fn a_middle_click_pastes_once() {
+        let t = Instant::now();
+        let ms = |n| t + Duration::from_millis(n);
+        let mut g = MiddlePasteGuard::default();
+        // The terminal reports the click, then pastes its selection too
+        assert!(g.click(t));
+        assert!(!g.paste(ms(40)));
+        // Or pastes first and reports the click after
+        let mut g = MiddlePasteGuard::default();
+        assert!(g.paste(t));
+        assert!(!g.click(ms(30)));
+        // Separate actions are left alone
+        assert!(g.paste(ms(1000)));
+        assert!(g.click(ms(2000)));
+        assert!(g.click(ms(2100)), "double middle-click pastes twice");
+    }
+
+    #[test]
+    fn a_repaint_empties_the_cache_and_asks_for_a_whole_frame() {
+        let mut cache = RenderCache { frame: "{}".into(), title: "vim".into(), ..Default::default() };
+        cache.deferred_rows.insert(3, Vec::new());
+        assert_eq!(cache.request(false), b"dump-state -D\n");
+        cache.repaint();
+        assert!(cache.frame.is_empty() && cache.title.is_empty() && cache.deferred_rows.is_empty());
+        // Only the next request is for a whole frame
+        assert_eq!(cache.request(true), b"dump-state -F -a\n");
+        assert_eq!(cache.request(false), b"dump-state -D\n");
+    }
+}
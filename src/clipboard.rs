@@ -0,0 +1,185 @@
+//! Where yanks go besides the paste buffers (`copy-command`,
+//! `set-clipboard`, `@clipboard-crlf`).
+//!
+//! A yank is piped to `copy-command` when one is set; otherwise, unless
+//! `set-clipboard` is `off`, it goes straight to the Windows clipboard of the
+//! machine running the server, with line breaks made CRLF (`@clipboard-crlf`,
+//! default on).  `paste-buffer -p` reads the clipboard back when there are
+//! no paste buffers.
+//!
+//! The server's clipboard is no use to a client attached over SSH.  With `set-clipboard` `on`
+//! or `external` the server also sends each attached client a
+//! `%clipboard <base64>` directive, and the client writes it to its own
+//! terminal as OSC 52 so the outer terminal sets the clipboard there.
+//!
+//! Going the other way, pane output is scanned for OSC 52 as it is read; with
+//! `set-clipboard on` the text an application copies becomes a paste buffer
+//! (and is passed on to the clients like a yank).
+
+use std::sync::Mutex;
+
+use crate::types::AppState;
+
+/// Longest OSC 52 sequence accepted from a pane; anything longer is dropped.
+pub(crate) const MAX_SEQUENCE: usize = 1 << 20;
+
+/// Copies made by pane applications, waiting for the server loop.
+static FROM_PANES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The escape that sets the terminal's clipboard to `b64` (base64 text).
+pub fn osc52(b64: &str) -> String {
+    format!("\x1b]52;c;{}\x07", b64)
+}
+
+/// Send a yank to `copy-command` or the system clipboard and, when
+/// `set-clipboard` allows it, to every attached client's terminal.
+pub fn export(app: &AppState, text: &str) {
+    if !app.copy_command.is_empty() {
+        crate::copy_mode::pipe_text_to_command(text, &app.copy_command);
+    } else if app.set_clipboard != "off" {
+        crate::platform::clipboard::set_text(&to_system(text, crlf(app)));
+    }
+    if matches!(app.set_clipboard.as_str(), "on" | "external") {
+        let b64 = crate::util::base64_encode(text);
+        for c in &app.clients { c.send_directive(format!("%clipboard {}", b64)); }
+    }
+}
+
+/// The system clipboard's text, with CRLF line breaks turned back into LF
+/// under `@clipboard-crlf`.
+pub fn read_system(app: &AppState) -> Option<String> {
+    crate::platform::clipboard::get_text().map(|t| from_system(&t, crlf(app)))
+}
+
+/// `@clipboard-crlf`: line breaks are CRLF on the system clipboard (default
+/// on, as Windows applications expect).
+fn crlf(app: &AppState) -> bool {
+    !matches!(app.environment.get("@clipboard-crlf").map(|s| s.as_str()), Some("off" | "0" | "false" | "no"))
+}
+
+/// Text for the system clipboard: every line break (LF, CRLF or a lone CR)
+/// becomes CRLF when `crlf` is set.
+fn to_system(text: &str, crlf: bool) -> String {
+    if !crlf { return text.to_string(); }
+    text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n")
+}
+
+/// Text read from the system clipboard: CRLF becomes LF when `crlf` is set.
+fn from_system(text: &str, crlf: bool) -> String {
+    if crlf { text.replace("\r\n", "\n") } else { text.to_string() }
+}
+
+/// Turn the copies pane applications made since the last call into paste
+/// buffers, when `set-clipboard` is `on`.
+pub fn accept_from_panes(app: &mut AppState) {
+    let copies = std::mem::take(&mut *FROM_PANES.lock().unwrap_or_else(|e| e.into_inner()));
+    if app.set_clipboard != "on" { return; }
+    for text in copies {
+        export(app, &text);
+        app.paste_buffers.push(text);
+    }
+}
+
+/// Finds OSC 52 copies in a pane's output stream, which may split a
+/// sequence across reads.
+#[derive(Default)]
+pub struct Osc52Scanner {
+    /// An unterminated sequence from the previous read, from its ESC on
+    partial: Vec<u8>,
+}
+
+impl Osc52Scanner {
+    /// Scan one read of pane output, queueing any complete copies for the
+    /// server.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let copies = self.scan(bytes);
+        if !copies.is_empty() {
+            FROM_PANES.lock().unwrap_or_else(|e| e.into_inner()).extend(copies);
+        }
+    }
+
+    fn scan(&mut self, bytes: &[u8]) -> Vec<String> {
+        if self.partial.is_empty() && !bytes.contains(&0x1b) { return Vec::new(); }
+        let mut data = std::mem::take(&mut self.partial);
+        data.extend_from_slice(bytes);
+        let mut copies = Vec::new();
+        let mut i = 0;
+        while let Some(start) = data[i..].iter().position(|&b| b == 0x1b).map(|p| p + i) {
+            let rest = &data[start..];
+            const PREFIX: &[u8] = b"\x1b]52;";
+            if rest.len() < PREFIX.len() && PREFIX.starts_with(rest) {
+                self.partial = rest.to_vec();
+                break;
+            }
+            if !rest.starts_with(PREFIX) { i = start + 1; continue; }
+            match terminator(rest) {
+                Some((body_end, seq_end)) => {
+                    if let Some(text) = decode(&rest[PREFIX.len()..body_end]) { copies.push(text); }
+                    i = start + seq_end;
+                }
+                None => {
+                    if rest.len() <= MAX_SEQUENCE { self.partial = rest.to_vec(); }
+                    break;
+                }
+            }
+        }
+        copies
+    }
+}
+
+/// End of the sequence's body and of the whole sequence (BEL or ST).
+pub(crate) fn terminator(seq: &[u8]) -> Option<(usize, usize)> {
+    seq.iter().enumerate().skip(1).find_map(|(i, &b)| match b {
+        0x07 => Some((i, i + 1)),
+        0x1b if seq.get(i + 1) == Some(&b'\\') => Some((i, i + 2)),
+        _ => None,
+    })
+}
+
+/// `Pc;Pd` → the copied text.  `?` (a clipboard query) and bad base64 give
+/// nothing.
+fn decode(body: &[u8]) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    let (_selection, data) = body.split_once(';')?;
+    if data == "?" || data.is_empty() { return None; }
+    crate::util::base64_decode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::base64_encode;
+
+    #[test]
+    fn finds_copies_split_across_reads() {
+        let mut s = Osc52Scanner::default();
+        let seq = format!("\x1b]52;c;{}\x07", base64_encode("hello"));
+        let (a, b) = seq.as_bytes().split_at(7);
+        assert!(s.scan(&[b"output ".as_slice(), a].concat()).is_empty());
+        assert_eq!(s.scan(&[b, b"\x1b[0m".as_slice()].concat()), ["hello"]);
+
+        // ST terminator, no selection, several per read; the ESC alone at
+        // the end is kept for the next read
+        let two = format!("\x1b]52;;{}\x1b\\x\x1b]52;p;{}\x07\x1b", base64_encode("one"), base64_encode("two"));
+        assert_eq!(s.scan(two.as_bytes()), ["one", "two"]);
+        assert_eq!(s.scan(format!("]52;c;{}\x07", base64_encode("three")).as_bytes()), ["three"]);
+    }
+
+    #[test]
+    fn ignores_queries_and_other_sequences() {
+        let mut s = Osc52Scanner::default();
+        assert!(s.scan(b"\x1b]52;c;?\x07\x1b]0;title\x07\x1b[1m").is_empty());
+        assert!(s.partial.is_empty());
+        assert_eq!(osc52(&base64_encode("hi")), "\x1b]52;c;aGk=\x07");
+    }
+
+    #[test]
+    fn line_breaks_on_the_system_clipboard() {
+        assert_eq!(to_system("a\nb\r\nc\rd\n", true), "a\r\nb\r\nc\r\nd\r\n");
+        assert_eq!(to_system("a\nb", false), "a\nb");
+        assert_eq!(from_system("a\r\nb\r\n", true), "a\nb\n");
+        assert_eq!(from_system("a\r\nb", false), "a\r\nb");
+        // Round trip keeps the text a buffer holds
+        assert_eq!(from_system(&to_system("één\ntwee", true), true), "één\ntwee");
+    }
+}
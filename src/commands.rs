@@ -1,20 +1,20 @@
 use std::io;
 use std::time::Instant;
 
-use crate::types::{AppState, Mode, Action, FocusDir, LayoutKind, MenuItem, Menu, PopupPty};
+use crate::types::{AppState, Mode, Action, FocusDir, LayoutKind, MenuItem, Menu};
 use crate::tree::{compute_rects, kill_all_children};
 use crate::pane::{create_window, split_active, kill_active_pane};
 use crate::copy_mode::{enter_copy_mode, switch_with_copy_save, paste_latest,
     capture_active_pane, save_latest_buffer};
 use crate::session::{send_control_to_session, list_all_sessions_tree};
-use crate::window_ops::toggle_zoom;
+use crate::window_ops::{toggle_zoom, window_pos_by_index, remove_window};
 
 /// Build the choose-tree data for the WindowChooser mode.
 pub fn build_choose_tree(app: &AppState) -> Vec<crate::session::TreeEntry> {
-    let current_windows: Vec<(String, usize, String, bool)> = app.windows.iter().enumerate().map(|(i, w)| {
+    let current_windows: Vec<(usize, String, usize, String, bool)> = app.windows.iter().enumerate().map(|(i, w)| {
         let panes = crate::tree::count_panes(&w.root);
         let size = format!("{}x{}", app.last_window_area.width, app.last_window_area.height);
-        (w.name.clone(), panes, size, i == app.active_idx)
+        (w.index, w.name.clone(), panes, size, i == app.active_idx)
     }).collect();
     list_all_sessions_tree(&app.session_name, &current_windows)
 }
@@ -33,6 +33,8 @@ pub fn parse_command_to_action(cmd: &str) -> Option<Action> {
     
     match parts[0] {
         "display-panes" | "displayp" => Some(Action::DisplayPanes),
+        // -c needs the server, which expands formats like #{pane_current_path}
+        "new-window" | "neww" | "split-window" | "splitw" if parts.contains(&"-c") => Some(Action::Command(cmd.to_string())),
         "new-window" | "neww" => Some(Action::NewWindow),
         "split-window" | "splitw" => {
             if parts.iter().any(|p| *p == "-h") {
@@ -46,9 +48,13 @@ pub fn parse_command_to_action(cmd: &str) -> Option<Action> {
         "previous-window" | "prev" => Some(Action::PrevWindow),
         "copy-mode" => Some(Action::CopyMode),
         "paste-buffer" | "pasteb" => Some(Action::Paste),
+        // Another client, or all the others: the server does that
+        "detach-client" | "detach" if parts.len() > 1 => Some(Action::Command(cmd.to_string())),
         "detach-client" | "detach" => Some(Action::Detach),
         "rename-window" | "renamew" => Some(Action::RenameWindow),
-        "choose-window" | "choose-tree" | "choose-session" => Some(Action::WindowChooser),
+        // Every running server: the client's own chooser
+        "choose-session" => Some(Action::Command(cmd.to_string())),
+        "choose-window" | "choose-tree" => Some(Action::WindowChooser),
         "resize-pane" | "resizep" if parts.iter().any(|p| *p == "-Z") => Some(Action::ZoomPane),
         "zoom-pane" => Some(Action::ZoomPane),
         "select-pane" | "selectp" => {
@@ -85,7 +91,7 @@ pub fn parse_command_to_action(cmd: &str) -> Option<Action> {
         "display-popup" | "popup" => Some(Action::Command(cmd.to_string())),
         "pipe-pane" | "pipep" => Some(Action::Command(cmd.to_string())),
         "rename-session" | "rename" => Some(Action::Command(cmd.to_string())),
-        "clear-history" => Some(Action::Command("clear-history".to_string())),
+        "clear-history" | "clearhist" | "clear-screen-and-history" => Some(Action::Command(cmd.to_string())),
         "set-buffer" | "setb" => Some(Action::Command(cmd.to_string())),
         "delete-buffer" | "deleteb" => Some(Action::Command("delete-buffer".to_string())),
         "display-message" | "display" => Some(Action::Command(cmd.to_string())),
@@ -168,78 +174,113 @@ pub fn parse_command_line(line: &str) -> Vec<String> {
     args
 }
 
-/// Parse a menu definition string into a Menu structure
-pub fn parse_menu_definition(def: &str, x: Option<i16>, y: Option<i16>) -> Menu {
+/// Split a command line into words the way tmux does: double quotes
+/// (with backslash escapes) or single quotes keep spaces, and a quoted
+/// empty word is kept as one.
+pub fn parse_command_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => if let Some(e) = chars.next() { current.push(e) },
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\'' => {
+                quoted = true;
+                for c in chars.by_ref() {
+                    if c == '\'' { break; }
+                    current.push(c);
+                }
+            }
+            c if c.is_whitespace() => {
+                if quoted || !current.is_empty() { words.push(std::mem::take(&mut current)); }
+                quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted || !current.is_empty() { words.push(current); }
+    words
+}
+
+/// `if-shell [-bF] [-t target] <condition> <then> [<else>]`
+pub struct IfShell {
+    pub format: bool,
+    pub background: bool,
+    pub condition: String,
+    pub then_cmd: String,
+    pub else_cmd: Option<String>,
+}
+
+/// Parse an if-shell command line, keeping quoted arguments whole.
+pub fn parse_if_shell(line: &str) -> Option<IfShell> {
+    let mut flags = String::new();
+    let mut positional: Vec<String> = Vec::new();
+    let mut it = parse_command_line(line).into_iter().skip(1);
+    while let Some(a) = it.next() {
+        if a == "-t" { it.next(); }
+        else if a.len() > 1 && a.starts_with('-') && positional.is_empty() { flags.push_str(&a[1..]); }
+        else { positional.push(a); }
+    }
+    let mut positional = positional.into_iter();
+    let (condition, then_cmd) = (positional.next()?, positional.next()?);
+    Some(IfShell { format: flags.contains('F'), background: flags.contains('b'), condition, then_cmd, else_cmd: positional.next() })
+}
+
+/// Parse `display-menu` arguments (everything after the command name):
+/// `-T title`, `-x`/`-y` positions, then items as `name key command`.
+/// An empty name or `-` is a separator and takes no key or command, and
+/// an item whose command is itself a `display-menu` opens as a submenu.
+pub fn parse_menu_definition(def: &str) -> Menu {
     let mut menu = Menu {
         title: String::new(),
         items: Vec::new(),
         selected: 0,
-        x,
-        y,
+        x: None,
+        y: None,
     };
-    
-    let parts: Vec<&str> = def.split_whitespace().collect();
-    if parts.is_empty() {
-        return menu;
-    }
-    
+    let words = parse_command_words(def);
     let mut i = 0;
-    while i < parts.len() {
-        if parts[i] == "-T" {
-            if let Some(title) = parts.get(i + 1) {
-                menu.title = title.trim_matches('"').to_string();
-                i += 2;
-                continue;
-            }
-        }
-        
-        if let Some(name) = parts.get(i) {
-            let name = name.trim_matches('"').to_string();
-            if name.is_empty() || name == "-" {
-                menu.items.push(MenuItem {
-                    name: String::new(),
-                    key: None,
-                    command: String::new(),
-                    is_separator: true,
-                });
-                i += 1;
-            } else {
-                let key = parts.get(i + 1).map(|k| k.trim_matches('"').chars().next()).flatten();
-                let command = parts.get(i + 2).map(|c| c.trim_matches('"').to_string()).unwrap_or_default();
-                menu.items.push(MenuItem {
-                    name,
-                    key,
-                    command,
-                    is_separator: false,
-                });
-                i += 3;
-            }
-        } else {
-            break;
-        }
+    // Flags come before the first item
+    while let Some(w) = words.get(i) {
+        let value = words.get(i + 1).map(String::as_str);
+        match w.as_str() {
+            "-T" => menu.title = value.unwrap_or("").to_string(),
+            "-x" => menu.x = value.and_then(crate::menu::Place::parse),
+            "-y" => menu.y = value.and_then(crate::menu::Place::parse),
+            "-t" | "-c" | "-C" | "-b" | "-s" | "-S" | "-H" => {}
+            "-O" | "-M" => { i += 1; continue; }
+            _ => break,
+        }
+        i += 2;
     }
-    
-    if menu.items.is_empty() && !def.is_empty() {
-        menu.title = "Menu".to_string();
-        menu.items.push(MenuItem {
-            name: def.to_string(),
-            key: Some('1'),
-            command: def.to_string(),
-            is_separator: false,
-        });
+    while let Some(name) = words.get(i) {
+        if name.is_empty() || name == "-" {
+            menu.items.push(MenuItem { name: String::new(), key: None, command: String::new(), is_separator: true, submenu: None });
+            i += 1;
+            continue;
+        }
+        let key = words.get(i + 1).and_then(|k| k.chars().next());
+        let command = words.get(i + 2).cloned().unwrap_or_default();
+        let submenu = match command.split_once(char::is_whitespace) {
+            Some(("display-menu" | "menu", rest)) => Some(Box::new(parse_menu_definition(rest))),
+            _ => None,
+        };
+        menu.items.push(MenuItem { name: name.clone(), key, command, is_separator: false, submenu });
+        i += 3;
     }
-    
     menu
 }
 
-/// Fire hooks for a given event
-pub fn fire_hooks(app: &mut AppState, event: &str) {
-    if let Some(commands) = app.hooks.get(event).cloned() {
-        for cmd in commands {
-            let _ = execute_command_string(app, &cmd);
-        }
-    }
-}
 
 /// Execute an Action (from key bindings)
 pub fn execute_action(app: &mut AppState, action: &Action) -> io::Result<bool> {
@@ -313,11 +354,15 @@ pub fn execute_action(app: &mut AppState, action: &Action) -> io::Result<bool> {
         }
         Action::CommandChain(cmds) => {
             for cmd in cmds {
-                execute_command_string(app, cmd)?;
+                // A table switch in a chain is ours, as on its own
+                match parse_command_to_action(cmd) {
+                    Some(Action::SwitchTable(table)) => crate::input::switch_key_table(app, &table),
+                    _ => execute_command_string(app, cmd)?,
+                }
             }
         }
         Action::SwitchTable(table) => {
-            app.current_key_table = Some(table.clone());
+            crate::input::switch_key_table(app, table);
         }
     }
     Ok(false)
@@ -353,17 +398,14 @@ pub fn execute_command_prompt(app: &mut AppState) -> io::Result<()> {
         "select-window" => {
             if let Some(tidx) = parts.iter().position(|p| *p == "-t").and_then(|i| parts.get(i+1)) {
                 if let Some(n) = parse_window_target(tidx) {
-                    if n >= app.window_base_index {
-                        let internal_idx = n - app.window_base_index;
-                        if internal_idx < app.windows.len() {
-                            app.last_window_idx = app.active_idx;
-                            app.active_idx = internal_idx;
-                        }
+                    if let Some(internal_idx) = window_pos_by_index(app, n) {
+                        app.last_window_idx = app.active_idx;
+                        app.active_idx = internal_idx;
                     }
                 }
             }
         }
-        _ => {}
+        _ => execute_command_string(app, &cmdline)?,
     }
     Ok(())
 }
@@ -374,6 +416,9 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
     if parts.is_empty() { return Ok(()); }
     
     match parts[0] {
+        "new-window" | "neww" | "split-window" | "splitw" if parts.contains(&"-c") => {
+            let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd.trim()));
+        }
         "new-window" | "neww" => {
             {
                 let _ = send_control_to_session(&app.port_file_base(), "new-window\n");
@@ -390,7 +435,7 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
         }
         "kill-window" | "killw" => {
             if app.windows.len() > 1 {
-                let mut win = app.windows.remove(app.active_idx);
+                let mut win = remove_window(app, app.active_idx);
                 kill_all_children(&mut win.root);
                 if app.active_idx >= app.windows.len() {
                     app.active_idx = app.windows.len() - 1;
@@ -426,14 +471,11 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
             if let Some(t_pos) = parts.iter().position(|p| *p == "-t") {
                 if let Some(t) = parts.get(t_pos + 1) {
                     if let Some(idx) = parse_window_target(t) {
-                        if idx >= app.window_base_index {
-                            let internal_idx = idx - app.window_base_index;
-                            if internal_idx < app.windows.len() {
-                                switch_with_copy_save(app, |app| {
-                                    app.last_window_idx = app.active_idx;
-                                    app.active_idx = internal_idx;
-                                });
-                            }
+                        if let Some(internal_idx) = window_pos_by_index(app, idx) {
+                            switch_with_copy_save(app, |app| {
+                                app.last_window_idx = app.active_idx;
+                                app.active_idx = internal_idx;
+                            });
                         }
                     }
                 }
@@ -490,78 +532,24 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
             app.mode = Mode::PaneChooser { opened_at: Instant::now() };
         }
         "confirm-before" | "confirm" => {
-            let rest = parts[1..].join(" ");
-            app.mode = Mode::ConfirmMode {
-                prompt: format!("Run '{}'?", rest),
-                command: rest,
-                input: String::new(),
-            };
+            let words = parse_command_line(cmd);
+            let args: Vec<&str> = words.iter().skip(1).map(String::as_str).collect();
+            match crate::prompt::parse_confirm(&args) {
+                Ok(p) => crate::prompt::show(app, p),
+                Err(e) => app.pending_message = Some(e),
+            }
         }
         "display-menu" | "menu" => {
-            let rest = parts[1..].join(" ");
-            let menu = parse_menu_definition(&rest, None, None);
-            if !menu.items.is_empty() {
-                app.mode = Mode::MenuMode { menu };
-            }
+            let rest = cmd.trim_start().split_once(char::is_whitespace).map_or("", |(_, r)| r);
+            let menu = crate::menu::from_definition(app, &parse_menu_definition(rest));
+            let _ = crate::menu::show(app, &menu);
         }
         "display-popup" | "popup" => {
-            // Parse -w width, -h height, -E close-on-exit flags
-            let mut width: u16 = 80;
-            let mut height: u16 = 24;
-            let close_on_exit = parts.iter().any(|p| *p == "-E");
-            if let Some(pos) = parts.iter().position(|p| *p == "-w") {
-                if let Some(v) = parts.get(pos + 1) { width = v.parse().unwrap_or(80); }
-            }
-            if let Some(pos) = parts.iter().position(|p| *p == "-h") {
-                if let Some(v) = parts.get(pos + 1) { height = v.parse().unwrap_or(24); }
-            }
-            // Collect command (non-flag args)
-            let cmd_parts: Vec<&str> = parts[1..].iter()
-                .filter(|a| !a.starts_with('-'))
-                .copied()
-                .collect();
-            // Skip width/height values
-            let rest = cmd_parts.join(" ");
-            
-            // Try PTY-based popup for interactive commands
-            let pty_result = if !rest.is_empty() {
-                Some(portable_pty::native_pty_system())
-                    .and_then(|pty_sys| {
-                        let pty_size = portable_pty::PtySize { rows: height.saturating_sub(2), cols: width.saturating_sub(2), pixel_width: 0, pixel_height: 0 };
-                        let pair = pty_sys.openpty(pty_size).ok()?;
-                        let mut cmd_builder = portable_pty::CommandBuilder::new(if cfg!(windows) { "pwsh" } else { "sh" });
-                        if cfg!(windows) { cmd_builder.args(["-NoProfile", "-Command", &rest]); } else { cmd_builder.args(["-c", &rest]); }
-                        let child = pair.slave.spawn_command(cmd_builder).ok()?;
-                        // Close the slave handle immediately – required for ConPTY.
-                        drop(pair.slave);
-                        let term = std::sync::Arc::new(std::sync::Mutex::new(vt100::Parser::new(pty_size.rows, pty_size.cols, 0)));
-                        let term_reader = term.clone();
-                        if let Ok(mut reader) = pair.master.try_clone_reader() {
-                            std::thread::spawn(move || {
-                                let mut buf = [0u8; 8192];
-                                loop {
-                                    match std::io::Read::read(&mut reader, &mut buf) {
-                                        Ok(n) if n > 0 => { if let Ok(mut p) = term_reader.lock() { p.process(&buf[..n]); } }
-                                        _ => break,
-                                    }
-                                }
-                            });
-                        }
-                        let mut pty_writer = pair.master.take_writer().ok()?;
-                        crate::pane::conpty_preemptive_dsr_response(&mut *pty_writer);
-                        Some(PopupPty { master: pair.master, writer: pty_writer, child, term })
-                    })
-            } else { None };
-            
-            app.mode = Mode::PopupMode {
-                command: rest,
-                output: String::new(),
-                process: None,
-                width,
-                height,
-                close_on_exit,
-                popup_pty: pty_result,
-            };
+            match crate::popup::parse_args(&parts[1..]) {
+                Ok(popup) if popup.close => crate::popup::close(app),
+                Ok(popup) => crate::popup::open(app, &popup),
+                Err(e) => app.pending_message = Some(e),
+            }
         }
         "resize-pane" | "resizep" => {
             if parts.iter().any(|p| *p == "-Z") {
@@ -586,11 +574,12 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
         }
         "respawn-pane" | "respawnp" => {
             {
-                let _ = send_control_to_session(&app.port_file_base(), "respawn-pane\n");
+                let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd));
             }
         }
         "toggle-sync" => {
-            app.sync_input = !app.sync_input;
+            let on = crate::scoped::active_synchronized(app);
+            if let Some(win) = app.windows.get_mut(app.active_idx) { win.options.synchronize_panes = Some(!on); }
         }
         "set-option" | "set" | "set-window-option" | "setw" => {
             // Forward to server for option handling
@@ -618,6 +607,9 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
                 let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd));
             }
         }
+        "detach-client" | "detach" if parts.len() > 1 => {
+            let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd.trim()));
+        }
         "detach-client" | "detach" => {
             // handled by caller to set quit flag
         }
@@ -642,30 +634,76 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
             }
         }
         "choose-tree" | "choose-window" => {
-            let tree = build_choose_tree(app);
-            let selected = tree.iter().position(|e| e.is_current_session && e.is_active_window && !e.is_session_header).unwrap_or(0);
-            app.mode = Mode::WindowChooser { selected, tree };
+            let words = parse_command_line(cmd);
+            let mut args: Vec<&str> = Vec::new();
+            let mut rest = words.iter().skip(1).map(String::as_str);
+            while let Some(a) = rest.next() {
+                if a == "-t" { rest.next(); } else { args.push(a); }
+            }
+            match crate::chooser::run(app, &args, parts[0] == "choose-window") {
+                Ok(Some(chosen)) => {
+                    for c in crate::config::split_chained_commands_pub(&chosen) {
+                        let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", c));
+                    }
+                }
+                Ok(None) => {}
+                // No client to show it on: the built-in chooser
+                Err(_) if app.clients.is_empty() => {
+                    let tree = build_choose_tree(app);
+                    let selected = tree.iter().position(|e| e.is_current_session && e.is_active_window && !e.is_session_header).unwrap_or(0);
+                    app.mode = Mode::WindowChooser { selected, tree };
+                }
+                Err(e) => app.pending_message = Some(e),
+            }
+        }
+        "choose-session" => {
+            // The client lists every server itself; with none, the built-in chooser
+            match crate::bandwidth::current_client(app) {
+                Some(c) => { c.send_directive("%choose-session".to_string()); }
+                None => {
+                    let tree = build_choose_tree(app);
+                    let selected = tree.iter().position(|e| e.is_current_session && e.is_session_header).unwrap_or(0);
+                    app.mode = Mode::WindowChooser { selected, tree };
+                }
+            }
         }
         "command-prompt" => {
-            // Support -I initial_text, -p prompt (ignored), -1 (ignored)
-            let initial = parts.windows(2).find(|w| w[0] == "-I").map(|w| w[1].to_string()).unwrap_or_default();
-            app.mode = Mode::CommandPrompt { input: initial.clone(), cursor: initial.len() };
+            let words = parse_command_line(cmd);
+            let args: Vec<&str> = words.iter().skip(1).map(String::as_str).collect();
+            match crate::prompt::parse_args(&args) {
+                Ok(p) => crate::prompt::show(app, p),
+                Err(e) => app.pending_message = Some(e),
+            }
         }
         "paste-buffer" | "pasteb" => {
-            paste_latest(app)?;
+            // -p: with no buffers, paste the system clipboard
+            let clip = if parts.contains(&"-p") && app.paste_buffers.is_empty() { crate::clipboard::read_system(app) } else { None };
+            let name = parts.windows(2).find(|w| w[0] == "-b").map(|w| w[1]);
+            // -s: into every pane of the window
+            let sync = parts.contains(&"-s");
+            if clip.is_none() && name.is_none() && !sync {
+                paste_latest(app)?;
+            } else if let Some(text) = clip.or_else(|| app.paste_buffers.get(name).map(|b| b.text.clone())) {
+                let broadcast = crate::input::paste_broadcasts(app, sync);
+                crate::input::paste_to_panes(app, &text, broadcast);
+            }
         }
         "set-buffer" => {
-            if let Some(text) = parts.get(1) {
-                app.paste_buffers.insert(0, text.to_string());
-                if app.paste_buffers.len() > 10 { app.paste_buffers.pop(); }
-            }
+            let name = parts.windows(2).find(|w| w[0] == "-b").map(|w| w[1]);
+            let append = parts.contains(&"-a");
+            let text = parts.iter().enumerate().skip(1)
+                .filter(|(i, a)| !a.starts_with('-') && parts[i - 1] != "-b")
+                .map(|(_, a)| *a).collect::<Vec<_>>().join(" ");
+            if append { app.paste_buffers.append(name, &text); }
+            else if !text.is_empty() { app.paste_buffers.set(name, text); }
         }
         "delete-buffer" => {
-            if !app.paste_buffers.is_empty() { app.paste_buffers.remove(0); }
+            let name = parts.windows(2).find(|w| w[0] == "-b").map(|w| w[1]);
+            if let (Err(e), Some(_)) = (app.paste_buffers.delete(name), name) { app.pending_message = Some(e); }
         }
-        "clear-history" => {
+        "clear-history" | "clearhist" | "clear-screen-and-history" => {
             {
-                let _ = send_control_to_session(&app.port_file_base(), "clear-history\n");
+                let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd));
             }
         }
         "kill-session" => {
@@ -673,12 +711,31 @@ pub fn execute_command_string(app: &mut AppState, cmd: &str) -> io::Result<()> {
                 let _ = send_control_to_session(&app.port_file_base(), "kill-session\n");
             }
         }
-        _ => {
-            // Forward unknown commands to server (catch-all for tmux compat)
-            {
-                let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd));
+        "if-shell" | "if" => {
+            // -F is decided here; shell conditions go to the server like other commands
+            match parse_if_shell(cmd) {
+                Some(ifs) if ifs.format => {
+                    let ok = crate::config::format_is_true(&crate::format::expand_format(&ifs.condition, app));
+                    if let Some(branch) = if ok { Some(ifs.then_cmd) } else { ifs.else_cmd } {
+                        for part in crate::config::split_chained_commands_pub(&branch) {
+                            execute_command_string(app, &part)?;
+                        }
+                    }
+                }
+                Some(_) => { let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd)); }
+                None => {}
             }
         }
+        _ => match crate::alias::expand(&app.command_aliases, cmd) {
+            Ok(Some(expanded)) => {
+                for part in crate::config::split_chained_commands_pub(&expanded) {
+                    execute_command_string(app, &part)?;
+                }
+            }
+            Err(e) => app.pending_message = Some(e),
+            // Forward unknown commands to server (catch-all for tmux compat)
+            Ok(None) => { let _ = send_control_to_session(&app.port_file_base(), &format!("{}\n", cmd)); }
+        },
     }
     Ok(())
 }
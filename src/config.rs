@@ -1,10 +1,11 @@
 use std::env;
 use crossterm::event::{KeyCode, KeyModifiers};
 
-use crate::types::{AppState, Action, Bind};
-use crate::commands::parse_command_to_action;
+use crate::types::{AppState, Action, Bind, ShellJob};
+use crate::commands::{parse_command_to_action, parse_command_line};
 
 pub fn load_config(app: &mut AppState) {
+    install_default_bindings(app);
     let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
     let paths = vec![
         format!("{}\\.psmux.conf", home),
@@ -66,22 +67,21 @@ pub fn parse_config_line(app: &mut AppState, line: &str) {
         parse_if_shell(app, l);
     }
     else if l.starts_with("set-hook ") {
-        // Parse set-hook: set-hook [-g] hook-name command
+        // set-hook [-agRu] hook-name[index] [command]
         let parts: Vec<&str> = l.split_whitespace().collect();
-        let mut i = 1;
-        while i < parts.len() && parts[i].starts_with('-') { i += 1; }
-        if i + 1 < parts.len() {
-            let hook = parts[i].to_string();
-            let cmd = parts[i+1..].join(" ");
-            app.hooks.entry(hook).or_insert_with(Vec::new).push(cmd);
-        }
+        if let Some(set) = crate::hooks::parse_set_hook(&parts[1..]) { crate::hooks::apply(app, set); }
     }
     else if l.starts_with("set-environment ") || l.starts_with("setenv ") {
         let parts: Vec<&str> = l.split_whitespace().collect();
-        let mut i = 1;
-        while i < parts.len() && parts[i].starts_with('-') { i += 1; }
-        if i + 1 < parts.len() {
-            app.environment.insert(parts[i].to_string(), parts[i+1..].join(" "));
+        if let Some(set) = crate::environ::parse_set(&parts[1..]) { crate::environ::apply_set(app, &set); }
+    }
+    else {
+        match crate::alias::expand(&app.command_aliases, l) {
+            Ok(Some(expanded)) => {
+                for part in split_chained_commands(&expanded) { parse_config_line(app, &part); }
+            }
+            Ok(None) => {}
+            Err(e) => app.pending_message = Some(e),
         }
     }
 }
@@ -142,6 +142,22 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
                 app.escape_time_ms = ms;
             }
         }
+        "repeat-time" => {
+            if let Ok(ms) = value.parse::<u64>() {
+                app.repeat_time_ms = ms;
+            }
+        }
+        "prefix-timeout" => {
+            if let Ok(ms) = value.parse::<u64>() {
+                app.prefix_timeout_ms = ms;
+            }
+        }
+        "confirm-kill" => app.confirm_kill = matches!(value, "on" | "true" | "1"),
+        "pattern-timeout" => {
+            if let Ok(ms) = value.parse::<u64>() {
+                app.pattern_timeout_ms = ms;
+            }
+        }
         "prediction-dimming" | "dim-predictions" => {
             app.prediction_dimming = !matches!(value, "off" | "false" | "0");
         }
@@ -173,6 +189,8 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
             if let Ok(n) = value.parse::<u64>() { app.status_interval = n; }
         }
         "status-justify" => { app.status_justify = value.to_string(); }
+        "prefix-indicator" => app.prefix_indicator = matches!(value, "on" | "true" | "1"),
+        "prefix-indicator-style" => { app.prefix_indicator_style = value.to_string(); }
         "base-index" => {
             if let Ok(idx) = value.parse::<usize>() {
                 app.window_base_index = idx;
@@ -185,7 +203,10 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
         }
         "history-limit" => {
             if let Ok(limit) = value.parse::<usize>() {
-                app.history_limit = limit;
+                if limit != app.history_limit {
+                    app.history_limit = limit;
+                    crate::copy_mode::apply_history_limit(app);
+                }
             }
         }
         "display-time" => {
@@ -234,12 +255,31 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
         "status-keys" => { app.environment.insert(key.to_string(), value.to_string()); }
         "pane-border-style" => { app.pane_border_style = value.to_string(); }
         "pane-active-border-style" => { app.pane_active_border_style = value.to_string(); }
+        "pane-border-lines" => { app.pane_border_lines = value.to_string(); }
+        "pane-border-indicators" => { app.pane_border_indicators = value.to_string(); }
+        "pane-border-status" => {
+            if matches!(value, "off" | "top" | "bottom") { crate::tree::set_pane_border_status(app, value); }
+        }
+        "pane-border-format" => { app.pane_border_format = value.to_string(); }
+        "highlight-pattern" => { app.highlight_pattern = value.to_string(); }
+        "pane-encoding" => {
+            if crate::encoding::Encoding::parse(value).is_some() { app.pane_encoding = value.to_string(); }
+        }
         "window-status-format" => { app.window_status_format = value.to_string(); }
         "window-status-current-format" => { app.window_status_current_format = value.to_string(); }
         "window-status-separator" => { app.window_status_separator = value.to_string(); }
         "automatic-rename" => {
             app.automatic_rename = matches!(value, "on" | "true" | "1");
         }
+        "automatic-rename-format" => { app.automatic_rename_format = value.to_string(); }
+        "allow-duplicate-window-names" => {
+            app.allow_duplicate_window_names = matches!(value, "on" | "true" | "1");
+        }
+        "popup-warm-shell" => {
+            app.popup_warm_shell = matches!(value, "on" | "true" | "1");
+        }
+        "popup-close-key" => { app.popup_close_key = value.to_string(); }
+        "sync-paste" => { app.sync_paste = matches!(value, "on" | "true" | "1"); }
         "synchronize-panes" => {
             app.sync_input = matches!(value, "on" | "true" | "1");
         }
@@ -249,14 +289,43 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
         "update-environment" => { app.environment.insert(key.to_string(), value.to_string()); }
         "bell-action" => { app.bell_action = value.to_string(); }
         "visual-bell" => { app.visual_bell = matches!(value, "on" | "true" | "1"); }
+        "monitor-bell" => { app.monitor_bell = matches!(value, "on" | "true" | "1"); }
+        "visual-silence" => { app.visual_silence = matches!(value, "on" | "true" | "1"); }
+        "root-any-binding" => { app.root_any_binding = matches!(value, "on" | "true" | "1"); }
+        "scroll-without-copy-mode" => { app.scroll_without_copy_mode = matches!(value, "on" | "true" | "1"); }
+        "scrolled-input" => { app.scrolled_input_block = value == "block"; }
         "activity-action" => { app.environment.insert(key.to_string(), value.to_string()); }
         "silence-action" => { app.environment.insert(key.to_string(), value.to_string()); }
         "monitor-silence" => {
             if let Ok(n) = value.parse::<u64>() { app.monitor_silence = n; }
         }
+        "idle-window-timeout" => {
+            if let Ok(n) = value.parse::<u64>() { app.idle_window_timeout = n; }
+        }
+        "idle-window-action" => {
+            if matches!(value, "flag" | "kill") { app.idle_window_action = value.to_string(); }
+        }
+        "client-idle-timeout" => {
+            if let Ok(n) = value.parse::<u64>() { app.client_idle_timeout = n; }
+        }
+        "client-idle-warning" => {
+            if let Ok(n) = value.parse::<u64>() { app.client_idle_warning = n; }
+        }
+        "client-idle-exempt-local" => {
+            app.client_idle_exempt_local = matches!(value, "on" | "true" | "1");
+        }
+        "history-file" => { crate::history::set_history_file(app, value); }
+        "prompt-history-limit" => {
+            if let Ok(n) = value.parse::<usize>() { app.prompt_history_limit = n; }
+        }
+        "buffer-limit" => {
+            if let Ok(n) = value.parse::<usize>() { app.paste_buffers.limit = n.max(1); app.paste_buffers.trim(); }
+        }
         "message-style" => { app.message_style = value.to_string(); }
         "message-command-style" => { app.message_command_style = value.to_string(); }
         "mode-style" => { app.mode_style = value.to_string(); }
+        "copy-mode-match-style" => { app.copy_mode_match_style = value.to_string(); }
+        "copy-mode-current-match-style" => { app.copy_mode_current_match_style = value.to_string(); }
         "window-status-style" => { app.window_status_style = value.to_string(); }
         "window-status-current-style" => { app.window_status_current_style = value.to_string(); }
         "window-status-activity-style" => { app.window_status_activity_style = value.to_string(); }
@@ -265,7 +334,6 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
         "status-left-style" => { app.status_left_style = value.to_string(); }
         "status-right-style" => { app.status_right_style = value.to_string(); }
         "clock-mode-colour" | "clock-mode-style" => { app.environment.insert(key.to_string(), value.to_string()); }
-        "pane-border-format" | "pane-border-status" => { app.environment.insert(key.to_string(), value.to_string()); }
         "popup-style" | "popup-border-style" | "popup-border-lines" => { app.environment.insert(key.to_string(), value.to_string()); }
         "window-style" | "window-active-style" => { app.environment.insert(key.to_string(), value.to_string()); }
         "wrap-search" => { app.environment.insert(key.to_string(), value.to_string()); }
@@ -285,13 +353,15 @@ pub fn parse_option_value(app: &mut AppState, rest: &str, _is_global: bool) {
         "window-size" => { app.window_size = value.to_string(); }
         "allow-passthrough" => { app.allow_passthrough = value.to_string(); }
         "copy-command" => { app.copy_command = value.to_string(); }
+        "compat-version" => {
+            if !value.trim().is_empty() { app.compat_version = value.trim().to_string(); }
+        }
         "set-clipboard" => { app.set_clipboard = value.to_string(); }
-        "command-alias" => {
-            if let Some(pos) = value.find('=') {
-                let alias = value[..pos].trim().to_string();
-                let expansion = value[pos+1..].trim().to_string();
-                app.command_aliases.insert(alias, expansion);
-            }
+        "local-echo" => {
+            if matches!(value, "on" | "off" | "auto") { app.local_echo = value.to_string(); }
+        }
+        _ if crate::alias::parse_option(key).is_some() => {
+            crate::alias::set(&mut app.command_aliases, crate::alias::parse_option(key).flatten(), value);
         }
         _ => {
             // Handle status-format[N] patterns
@@ -396,12 +466,18 @@ pub fn parse_unbind_key(app: &mut AppState, line: &str) {
     
     let mut i = 1;
     let mut unbind_all = false;
+    let mut table = "prefix".to_string();
     
     while i < parts.len() {
         let p = parts[i];
-        if p.starts_with('-') {
+        // A bare "-" is a key name, as in bind-key
+        if p.starts_with('-') && p.len() > 1 {
             if p.contains('a') { unbind_all = true; }
-            if p.contains('T') { i += 1; }
+            if p.contains('n') { table = "root".to_string(); }
+            if p.contains('T') {
+                i += 1;
+                if i < parts.len() { table = parts[i].to_string(); }
+            }
             i += 1;
         } else {
             break;
@@ -409,18 +485,91 @@ pub fn parse_unbind_key(app: &mut AppState, line: &str) {
     }
     
     if unbind_all {
-        app.key_tables.clear();
-        return;
+        unbind_key(app, &table, None);
+    } else if let Some(key) = parts.get(i).and_then(|k| parse_key_name(k)) {
+        unbind_key(app, &table, Some(key));
     }
-    
-    if i < parts.len() {
-        if let Some(key) = parse_key_name(parts[i]) {
+}
+
+/// Remove `key` from one key table, or every binding in it when `key` is
+/// None (`unbind -a`).  The table itself stays, so defaults removed here
+/// are not put back by install_default_bindings.
+pub fn unbind_key(app: &mut AppState, table: &str, key: Option<(KeyCode, KeyModifiers)>) {
+    let binds = app.key_tables.entry(table.to_string()).or_default();
+    match key {
+        Some(key) => {
             let key = normalize_key_for_binding(key);
-            // Remove from all tables
-            for table in app.key_tables.values_mut() {
-                table.retain(|b| b.key != key);
-            }
+            binds.retain(|b| b.key != key);
         }
+        None => binds.clear(),
+    }
+}
+
+/// `list-keys` output for every table, tables in name order so the copy-mode
+/// tables always list the same way.
+pub fn list_keys(app: &AppState) -> String {
+    let mut tables: Vec<(&String, &Vec<Bind>)> = app.key_tables.iter().collect();
+    tables.sort_by(|a, b| a.0.cmp(b.0));
+    let entries = tables.into_iter().flat_map(|(table, binds)| {
+        binds.iter().map(move |b| {
+            (table.as_str(), format_key_binding(&b.key), crate::commands::format_action(&b.action), b.repeat)
+        })
+    });
+    crate::help::build_list_keys_output(entries)
+}
+
+/// Default copy-mode-vi table (tmux's, limited to commands psmux has).
+/// `"`, `a`/`i` text objects and count digits stay built in.
+const COPY_MODE_VI_DEFAULTS: &[(&str, &str)] = &[
+    ("Escape", "cancel"), ("q", "cancel"), ("C-c", "cancel"),
+    ("h", "cursor-left"), ("j", "cursor-down"), ("k", "cursor-up"), ("l", "cursor-right"),
+    ("Left", "cursor-left"), ("Down", "cursor-down"), ("Up", "cursor-up"), ("Right", "cursor-right"),
+    ("S-Left", "scroll-left"), ("S-Right", "scroll-right"),
+    ("w", "next-word"), ("b", "previous-word"), ("e", "next-word-end"),
+    ("W", "next-space"), ("B", "previous-space"), ("E", "next-space-end"),
+    ("0", "start-of-line"), ("$", "end-of-line"), ("^", "back-to-indentation"),
+    ("Home", "start-of-line"), ("End", "end-of-line"),
+    ("C-u", "halfpage-up"), ("C-d", "halfpage-down"), ("C-b", "page-up"), ("C-f", "page-down"),
+    ("PageUp", "page-up"), ("PageDown", "page-down"),
+    ("C-y", "scroll-up"), ("C-e", "scroll-down"), ("K", "scroll-up"), ("J", "scroll-down"),
+    ("g", "history-top"), ("G", "history-bottom"),
+    ("H", "top-line"), ("M", "middle-line"), ("L", "bottom-line"),
+    ("{", "previous-paragraph"), ("}", "next-paragraph"), ("%", "next-matching-bracket"),
+    ("f", "jump-forward"), ("F", "jump-backward"), ("t", "jump-to-forward"), ("T", "jump-to-backward"),
+    ("v", "begin-selection"), ("Space", "begin-selection"), ("V", "select-line"),
+    ("C-v", "rectangle-toggle"), ("o", "other-end"),
+    ("y", "copy-selection-and-cancel"), ("Enter", "copy-selection-and-cancel"),
+    ("D", "copy-end-of-line"), ("A", "append-selection-and-cancel"),
+    ("/", "search-forward"), ("?", "search-backward"), ("n", "search-again"), ("N", "search-reverse"),
+];
+
+/// Default copy-mode (emacs) table.
+const COPY_MODE_EMACS_DEFAULTS: &[(&str, &str)] = &[
+    ("Escape", "cancel"), ("q", "cancel"), ("C-c", "cancel"), ("C-g", "clear-selection"),
+    ("Left", "cursor-left"), ("Down", "cursor-down"), ("Up", "cursor-up"), ("Right", "cursor-right"),
+    ("C-b", "cursor-left"), ("C-n", "cursor-down"), ("C-p", "cursor-up"), ("C-f", "cursor-right"),
+    ("S-Left", "scroll-left"), ("S-Right", "scroll-right"),
+    ("C-a", "start-of-line"), ("C-e", "end-of-line"), ("Home", "start-of-line"), ("End", "end-of-line"),
+    ("M-f", "next-word-end"), ("M-b", "previous-word"), ("M-m", "back-to-indentation"),
+    ("C-v", "page-down"), ("M-v", "page-up"), ("PageUp", "page-up"), ("PageDown", "page-down"),
+    ("M-<", "history-top"), ("M->", "history-bottom"),
+    ("C-Space", "begin-selection"), ("M-w", "copy-selection-and-cancel"), ("C-w", "copy-selection-and-cancel"),
+    ("C-k", "copy-end-of-line"),
+    ("C-s", "search-forward"), ("C-r", "search-backward"), ("n", "search-again"), ("N", "search-reverse"),
+];
+
+/// Put the default copy-mode-vi and copy-mode tables into key_tables as
+/// ordinary bindings, so list-keys shows them and unbind/bind edit them.
+/// A table that already exists is left alone, which keeps this idempotent
+/// and stops unbound defaults coming back when the config is re-sourced.
+pub fn install_default_bindings(app: &mut AppState) {
+    for (table, defaults) in [("copy-mode-vi", COPY_MODE_VI_DEFAULTS), ("copy-mode", COPY_MODE_EMACS_DEFAULTS)] {
+        app.key_tables.entry(table.to_string()).or_insert_with(|| {
+            defaults.iter().filter_map(|(key, cmd)| {
+                let key = normalize_key_for_binding(parse_key_name(key)?);
+                Some(Bind { key, action: Action::Command(format!("send-keys -X {}", cmd)), repeat: false })
+            }).collect()
+        });
     }
 }
 
@@ -434,9 +583,69 @@ pub fn normalize_key_for_binding(key: (KeyCode, KeyModifiers)) -> (KeyCode, KeyM
     }
 }
 
+/// The special key name `Any`: matches any key not otherwise bound in a
+/// table.  KeyCode::Null never comes from a real key press.
+pub const ANY_KEY: (KeyCode, KeyModifiers) = (KeyCode::Null, KeyModifiers::NONE);
+
+/// Find the binding for `key` in `table`.  Exact matches win; otherwise an
+/// `Any` binding matches, in non-root tables always and in root only with
+/// root-any-binding on.  The bool is true for an `Any` match.
+pub fn lookup_key_binding(app: &AppState, table: &str, key: (KeyCode, KeyModifiers)) -> Option<(Bind, bool)> {
+    let binds = app.key_tables.get(table)?;
+    match_key_binding(binds.iter(), |b| Some(b.key), table != "root" || app.root_any_binding, key)
+        .map(|(b, any)| (b.clone(), any))
+}
+
+/// The rule of lookup_key_binding over any list of one table's bindings,
+/// `key_of` giving each one's key; the client uses it on the bindings the
+/// server syncs to it.
+pub fn match_key_binding<'a, T>(
+    binds: impl Iterator<Item = &'a T> + Clone,
+    key_of: impl Fn(&T) -> Option<(KeyCode, KeyModifiers)>,
+    any_allowed: bool,
+    key: (KeyCode, KeyModifiers),
+) -> Option<(&'a T, bool)> {
+    let key = normalize_key_for_binding(key);
+    if let Some(b) = binds.clone().find(|b| key_of(b).map(normalize_key_for_binding) == Some(key)) {
+        return Some((b, false));
+    }
+    if !any_allowed { return None; }
+    binds.into_iter().find(|b| key_of(b) == Some(ANY_KEY)).map(|b| (b, true))
+}
+
+/// The name `#{key}` gives the key that triggered a binding.
+pub fn key_name_for_binding(key: (KeyCode, KeyModifiers)) -> String {
+    format_key_binding(&normalize_key_for_binding(key))
+}
+
+/// Substitute `#{key}` in a bound command with the name of the key that
+/// triggered it.  Done up front because commands forwarded to the server
+/// are expanded there, away from the local `#{key}` override.
+pub fn action_with_key(action: &Action, key_name: &str) -> Action {
+    match action {
+        Action::Command(c) => Action::Command(command_with_key(c, key_name)),
+        Action::CommandChain(cmds) => Action::CommandChain(cmds.iter().map(|c| command_with_key(c, key_name)).collect()),
+        other => other.clone(),
+    }
+}
+
+/// action_with_key for a command line.
+pub fn command_with_key(command: &str, key_name: &str) -> String {
+    command.replace("#{key}", key_name)
+}
+
 pub fn parse_key_name(name: &str) -> Option<(KeyCode, KeyModifiers)> {
     let name = name.trim();
+    if name == "Any" { return Some(ANY_KEY); }
     
+    if name.get(2..).map_or(false, |rest| rest.eq_ignore_ascii_case("Space")) {
+        match name.get(..2) {
+            Some("C-") => return Some((KeyCode::Char(' '), KeyModifiers::CONTROL)),
+            Some("M-") => return Some((KeyCode::Char(' '), KeyModifiers::ALT)),
+            _ => {}
+        }
+    }
+
     if name.starts_with("C-") || name.starts_with("^") {
         let ch = if name.starts_with("C-") {
             name.chars().nth(2)
@@ -464,6 +673,10 @@ pub fn parse_key_name(name: &str) -> Option<(KeyCode, KeyModifiers)> {
                 return Some((KeyCode::Char(c.to_ascii_uppercase()), KeyModifiers::SHIFT));
             }
         }
+        // Named keys take Shift too: S-Left, S-Home
+        if let Some((code, mods)) = parse_key_name(rest).filter(|k| !matches!(k.0, KeyCode::Char(_))) {
+            return Some((code, mods | KeyModifiers::SHIFT));
+        }
     }
     
     match name.to_uppercase().as_str() {
@@ -507,14 +720,19 @@ pub fn parse_key_name(name: &str) -> Option<(KeyCode, KeyModifiers)> {
     None
 }
 
-pub fn source_file(app: &mut AppState, path: &str) {
-    let path = path.trim().trim_matches('"').trim_matches('\'');
-    let expanded = if path.starts_with('~') {
+/// Expand a leading `~` to the user's home directory.
+pub fn expand_home(path: &str) -> String {
+    if path.starts_with('~') {
         let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
         path.replacen('~', &home, 1)
     } else {
         path.to_string()
-    };
+    }
+}
+
+pub fn source_file(app: &mut AppState, path: &str) {
+    let path = path.trim().trim_matches('"').trim_matches('\'');
+    let expanded = expand_home(path);
     
     if let Ok(content) = std::fs::read_to_string(&expanded) {
         parse_config_content(app, &content);
@@ -524,6 +742,7 @@ pub fn source_file(app: &mut AppState, path: &str) {
 /// Parse a key string like "C-a", "M-x", "F1", "Space" into (KeyCode, KeyModifiers)
 pub fn parse_key_string(key: &str) -> Option<(KeyCode, KeyModifiers)> {
     let key = key.trim();
+    if key == "Any" { return Some(ANY_KEY); }
     let mut mods = KeyModifiers::empty();
     let mut key_part = key;
     
@@ -645,7 +864,9 @@ pub fn format_key_binding(key: &(KeyCode, KeyModifiers)) -> String {
     }
     
     let key_str = match keycode {
+        KeyCode::Char(' ') => "Space".to_string(),
         KeyCode::Char(c) => c.to_string(),
+        KeyCode::Null => "Any".to_string(),
         KeyCode::Enter => "Enter".to_string(),
         KeyCode::Tab => "Tab".to_string(),
         KeyCode::BackTab => "BTab".to_string(),
@@ -673,7 +894,7 @@ pub fn format_key_binding(key: &(KeyCode, KeyModifiers)) -> String {
 /// Syntax: run-shell [-b] <command>
 /// Without -b, output is silently discarded (we're in config parsing).
 /// With -b, the command runs in the background.
-fn parse_run_shell(_app: &mut AppState, line: &str) {
+fn parse_run_shell(app: &mut AppState, line: &str) {
     let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.len() < 2 { return; }
     let mut background = false;
@@ -686,37 +907,146 @@ fn parse_run_shell(_app: &mut AppState, line: &str) {
     // Strip surrounding quotes if present
     let shell_cmd = shell_cmd.trim_matches(|c| c == '\'' || c == '"');
     if shell_cmd.is_empty() { return; }
+    // Queued rather than run here: the command usually calls back into the
+    // server, which cannot answer while it is still reading its config.
+    app.pending_shell_jobs.push(ShellJob::Run { command: shell_cmd.to_string(), background });
+}
+
+/// Is `cmd` a bash script invocation (tmux plugin entry points such as
+/// `*.tmux`, `*.sh`, or extensionless scripts like tpm)?
+fn is_bash_script(cmd: &str) -> bool {
+    let first = cmd.split_whitespace().next().unwrap_or("");
+    let path = std::path::Path::new(first);
+    if !path.is_file() { return false; }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => matches!(ext, "tmux" | "sh" | "bash"),
+        None => true,
+    }
+}
+
+/// Directory holding the `tmux` shim put on PATH for shell jobs, so plugin
+/// scripts that call `tmux ...` reach psmux.
+fn tmux_shim_dir() -> Option<std::path::PathBuf> {
+    let exe = env::current_exe().ok()?;
+    let dir = std::path::PathBuf::from(crate::session::psmux_dir()).join("bin");
+    let shim = dir.join("tmux");
+    let body = format!("#!/bin/sh\nPSMUX_AS_TMUX=1 exec \"{}\" \"$@\"\n", exe.to_string_lossy().replace('\\', "/"));
+    if std::fs::read_to_string(&shim).ok().as_deref() != Some(body.as_str()) {
+        std::fs::create_dir_all(&dir).ok()?;
+        std::fs::write(&shim, body).ok()?;
+    }
+    Some(dir)
+}
 
-    if background {
-        #[cfg(windows)]
-        { let _ = std::process::Command::new("pwsh").args(["-NoProfile", "-Command", shell_cmd]).spawn(); }
-        #[cfg(not(windows))]
-        { let _ = std::process::Command::new("sh").args(["-c", shell_cmd]).spawn(); }
+/// Build the process for a run-shell / if-shell command.  Bash scripts run
+/// under bash; anything else under pwsh (sh elsewhere).  `session_base`
+/// points the script's `tmux` calls at the session that ran it.
+pub fn shell_command(cmd: &str, session_base: Option<&str>) -> std::process::Command {
+    let cmd = expand_home(cmd.trim());
+    let mut c = if is_bash_script(&cmd) {
+        // bash wants forward slashes in the script path
+        let (script, rest) = cmd.split_once(char::is_whitespace).unwrap_or((cmd.as_str(), ""));
+        let mut c = std::process::Command::new("bash");
+        c.args(["-c", &format!("'{}' {}", script.replace('\\', "/"), rest)]);
+        c
+    } else if cfg!(windows) {
+        let mut c = std::process::Command::new("pwsh");
+        c.args(["-NoProfile", "-Command", &cmd]);
+        c
     } else {
-        #[cfg(windows)]
-        { let _ = std::process::Command::new("pwsh").args(["-NoProfile", "-Command", shell_cmd]).output(); }
-        #[cfg(not(windows))]
-        { let _ = std::process::Command::new("sh").args(["-c", shell_cmd]).output(); }
+        let mut c = std::process::Command::new("sh");
+        c.args(["-c", &cmd]);
+        c
+    };
+    if let Some(base) = session_base {
+        c.env("PSMUX_TARGET_SESSION", base);
+    }
+    if let Some(dir) = tmux_shim_dir() {
+        let path = env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![dir];
+        paths.extend(env::split_paths(&path));
+        if let Ok(joined) = env::join_paths(paths) { c.env("PATH", joined); }
+    }
+    c
+}
+
+/// Run a run-shell command to completion: its exit status (-1 when it was
+/// killed, 127 when it could not start) and stdout followed by stderr.
+pub fn shell_output(mut c: std::process::Command) -> (i32, String) {
+    match c.output() {
+        Ok(out) => {
+            let mut text = String::from_utf8_lossy(&out.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&out.stderr));
+            (out.status.code().unwrap_or(-1), text)
+        }
+        Err(e) => (127, format!("{}\n", e)),
     }
 }
 
+/// Start the queued config shell jobs on a worker thread, in order.  Called
+/// from the server loop, so the jobs' `tmux` calls get answered.
+pub fn spawn_pending_shell_jobs(app: &mut AppState) {
+    if app.pending_shell_jobs.is_empty() { return; }
+    let jobs: Vec<ShellJob> = std::mem::take(&mut app.pending_shell_jobs).into_iter().map(|job| match job {
+        ShellJob::Run { command, background } => ShellJob::Run { command: crate::format::expand_format(&command, app), background },
+        ShellJob::IfShell { condition, then_cmd, else_cmd } =>
+            ShellJob::IfShell { condition: crate::format::expand_format(&condition, app), then_cmd, else_cmd },
+    }).collect();
+    let base = app.port_file_base();
+    // set-environment's variables are passed on, as tmux does
+    let vars = crate::environ::composed(app);
+    std::thread::spawn(move || {
+        for job in jobs {
+            match job {
+                ShellJob::Run { command, background } => {
+                    let mut c = shell_command(&command, Some(&base));
+                    crate::environ::apply_to_job(&mut c, &vars);
+                    if background { let _ = c.spawn(); } else { let _ = c.output(); }
+                }
+                ShellJob::IfShell { condition, then_cmd, else_cmd } => {
+                    let mut c = shell_command(&condition, Some(&base));
+                    crate::environ::apply_to_job(&mut c, &vars);
+                    c.stdout(std::process::Stdio::null())
+                        .stderr(std::process::Stdio::null());
+                    let ok = c.status().map(|s| s.success()).unwrap_or(false);
+                    let chosen = if ok { Some(then_cmd) } else { else_cmd };
+                    // The branch is a tmux command; hand it to the server through the CLI
+                    if let (Some(cmd), Ok(exe)) = (chosen, env::current_exe()) {
+                        for part in split_chained_commands(&cmd) {
+                            let _ = std::process::Command::new(&exe)
+                                .args(parse_command_line(&part))
+                                .env("PSMUX_TARGET_SESSION", &base)
+                                .output();
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// An if-shell -F condition, once expanded: true unless empty or "0".
+pub fn format_is_true(expanded: &str) -> bool {
+    !expanded.is_empty() && expanded != "0"
+}
+
 /// Execute an if-shell / if command from config.
 /// Syntax: if-shell [-bF] <condition> <true-cmd> [<false-cmd>]
 /// Runs the condition command (or evaluates format with -F), then executes the
-/// appropriate branch command as a config line.
+/// appropriate branch command as a config line; `\;` chains several.
 fn parse_if_shell(app: &mut AppState, line: &str) {
     let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.len() < 3 { return; }
 
     let mut format_mode = false;
-    let mut _background = false;
+    let mut background = false;
     let mut positional: Vec<String> = Vec::new();
     let mut i = 1;
     while i < parts.len() {
         match parts[i] {
-            "-b" => { _background = true; }
+            "-b" => { background = true; }
             "-F" => { format_mode = true; }
-            "-bF" | "-Fb" => { _background = true; format_mode = true; }
+            "-bF" | "-Fb" => { background = true; format_mode = true; }
             "-t" => { i += 1; } // skip target
             s => {
                 // Handle quoted strings that might span multiple parts
@@ -751,18 +1081,149 @@ fn parse_if_shell(app: &mut AppState, line: &str) {
     let true_cmd = &positional[1];
     let false_cmd = positional.get(2);
 
+    if background && !format_mode {
+        app.pending_shell_jobs.push(ShellJob::IfShell {
+            condition: condition.clone(),
+            then_cmd: true_cmd.clone(),
+            else_cmd: false_cmd.cloned(),
+        });
+        return;
+    }
+
+    let condition = crate::format::expand_format(condition, app);
     let success = if format_mode {
-        !condition.is_empty() && condition != "0"
+        format_is_true(&condition)
     } else {
-        #[cfg(windows)]
-        { std::process::Command::new("pwsh").args(["-NoProfile", "-Command", condition]).status().map(|s| s.success()).unwrap_or(false) }
-        #[cfg(not(windows))]
-        { std::process::Command::new("sh").args(["-c", condition]).status().map(|s| s.success()).unwrap_or(false) }
+        shell_command(&condition, None).status().map(|s| s.success()).unwrap_or(false)
     };
 
     let cmd_to_run = if success { Some(true_cmd) } else { false_cmd };
     if let Some(cmd) = cmd_to_run {
-        // Execute the branch as a config line (recursive — supports set, bind, source, etc.)
-        parse_config_line(app, cmd);
+        // Execute the branch as config lines (recursive — supports set, bind, source, etc.)
+        for part in split_chained_commands(cmd) {
+            parse_config_line(app, &part);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(app: &mut AppState, line: &str) {
+        parse_config_line(app, line);
+    }
+
+    fn command_of(found: Option<(Bind, bool)>) -> Option<(String, bool)> {
+        found.map(|(b, any)| match b.action {
+            Action::Command(c) => (c, any),
+            _ => (String::new(), any),
+        })
+    }
+
+    fn table_lines(app: &AppState, table: &str) -> Vec<String> {
+        let prefix = format!("bind-key -T {} ", table);
+        list_keys(app).lines().filter(|l| l.starts_with(&prefix)).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn copy_mode_tables_can_be_cleared_and_rebuilt() {
+        let mut app = AppState::new("t".to_string());
+        install_default_bindings(&mut app);
+        assert!(table_lines(&app, "copy-mode-vi").contains(&"bind-key -T copy-mode-vi h send-keys -X cursor-left".to_string()));
+        parse_config_content(&mut app, "unbind -a -T copy-mode-vi\n\
+            bind -T copy-mode-vi v send -X begin-selection\n\
+            bind -T copy-mode-vi y send -X copy-selection-and-cancel\n");
+        // A reload installs defaults again; a cleared table stays cleared
+        install_default_bindings(&mut app);
+        assert_eq!(table_lines(&app, "copy-mode-vi"), vec![
+            "bind-key -T copy-mode-vi v send -X begin-selection".to_string(),
+            "bind-key -T copy-mode-vi y send -X copy-selection-and-cancel".to_string(),
+        ]);
+        // Plain unbind -a only touches the prefix table
+        bound(&mut app, "unbind -a");
+        assert_eq!(table_lines(&app, "copy-mode-vi").len(), 2);
+        assert!(!table_lines(&app, "copy-mode").is_empty());
+    }
+
+    #[test]
+    fn rebinding_a_copy_mode_default_replaces_it() {
+        let mut app = AppState::new("t".to_string());
+        install_default_bindings(&mut app);
+        let before = table_lines(&app, "copy-mode-vi").len();
+        bound(&mut app, "bind -T copy-mode-vi y send -X copy-selection");
+        let lines = table_lines(&app, "copy-mode-vi");
+        assert_eq!(lines.len(), before);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("bind-key -T copy-mode-vi y ")).collect::<Vec<_>>(),
+            vec!["bind-key -T copy-mode-vi y send -X copy-selection"]);
+        bound(&mut app, "unbind -T copy-mode-vi q");
+        assert_eq!(table_lines(&app, "copy-mode-vi").len(), before - 1);
+    }
+
+    #[test]
+    fn exact_binding_beats_any() {
+        let mut app = AppState::new("t".to_string());
+        bound(&mut app, "bind -T mymenu Any display-message other");
+        bound(&mut app, "bind -T mymenu a display-message exact");
+        let a = command_of(lookup_key_binding(&app, "mymenu", (KeyCode::Char('a'), KeyModifiers::NONE)));
+        assert_eq!(a, Some(("display-message exact".to_string(), false)));
+        let b = command_of(lookup_key_binding(&app, "mymenu", (KeyCode::Char('b'), KeyModifiers::NONE)));
+        assert_eq!(b, Some(("display-message other".to_string(), true)));
+        assert!(lookup_key_binding(&app, "othermenu", (KeyCode::Char('b'), KeyModifiers::NONE)).is_none());
+    }
+
+    #[test]
+    fn any_in_root_requires_opt_in() {
+        let mut app = AppState::new("t".to_string());
+        bound(&mut app, "bind -n Any display-message root");
+        let key = (KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(lookup_key_binding(&app, "root", key).is_none());
+        bound(&mut app, "set -g root-any-binding on");
+        assert_eq!(command_of(lookup_key_binding(&app, "root", key)), Some(("display-message root".to_string(), true)));
+    }
+
+    #[test]
+    fn key_names_for_any_bindings() {
+        let name = |code, mods| format_key_binding(&normalize_key_for_binding((code, mods)));
+        assert_eq!(name(KeyCode::Char('x'), KeyModifiers::NONE), "x");
+        assert_eq!(name(KeyCode::Char('X'), KeyModifiers::SHIFT), "X");
+        assert_eq!(name(KeyCode::Char('a'), KeyModifiers::CONTROL), "C-a");
+        assert_eq!(name(KeyCode::Up, KeyModifiers::ALT), "M-Up");
+        assert_eq!(name(KeyCode::Char(' '), KeyModifiers::NONE), "Space");
+        assert_eq!(name(KeyCode::F(5), KeyModifiers::NONE), "F5");
+        assert_eq!(format_key_binding(&ANY_KEY), "Any");
+        assert_eq!(parse_key_string("Any"), Some(ANY_KEY));
+    }
+
+    #[test]
+    fn if_shell_format_conditions_pick_a_chained_branch() {
+        let mut app = AppState::new("laptop".to_string());
+        bound(&mut app, r##"if-shell -F "#{==:#{session_name},laptop}" "set -g status-left A \; set -g status-right B" "set -g status-left C""##);
+        assert_eq!((app.status_left.as_str(), app.status_right.as_str()), ("A", "B"));
+        bound(&mut app, r##"if -F "#{==:#{session_name},desktop}" "set -g status-left A" "set -g status-left C""##);
+        assert_eq!(app.status_left, "C");
+        // A false condition without an else branch does nothing
+        bound(&mut app, r##"if-shell -F "0" "set -g status-left D""##);
+        assert_eq!(app.status_left, "C");
+
+        let ifs = crate::commands::parse_if_shell(r##"if-shell -bF -t :1 "#{pane_in_mode}" "send-keys q" "display -p 'not \"in\" mode'""##).unwrap();
+        assert!(ifs.format && ifs.background);
+        assert_eq!((ifs.condition.as_str(), ifs.then_cmd.as_str()), ("#{pane_in_mode}", "send-keys q"));
+        assert_eq!(ifs.else_cmd.as_deref(), Some(r##"display -p 'not "in" mode'"##));
+        assert!(crate::commands::parse_if_shell("if-shell true").is_none());
+    }
+
+    #[test]
+    fn key_substituted_into_bound_command() {
+        let chain = Action::CommandChain(vec!["run-shell 'notify #{key}'".into(), "display-message #{key}".into()]);
+        match action_with_key(&chain, "C-x") {
+            Action::CommandChain(cmds) => assert_eq!(cmds, vec!["run-shell 'notify C-x'", "display-message C-x"]),
+            _ => panic!("expected a command chain"),
+        }
+        crate::format::set_key_override(Some("M-Up".into()));
+        let app = AppState::new("t".to_string());
+        assert_eq!(crate::format::expand_format("#{key}", &app), "M-Up");
+        crate::format::set_key_override(None);
+        assert_eq!(crate::format::expand_format("#{key}", &app), "");
     }
 }
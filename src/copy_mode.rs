@@ -1,14 +1,5 @@
 use std::io::{self, Write};
-#[cfg(windows)]
-use std::thread;
-#[cfg(windows)]
-use std::time::Duration;
-#[cfg(windows)]
-use windows_sys::Win32::Foundation::{GlobalFree, HGLOBAL};
-#[cfg(windows)]
-use windows_sys::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard, SetClipboardData};
-#[cfg(windows)]
-use windows_sys::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+use std::time::Instant;
 
 use crate::types::{AppState, Mode, CopyModeState};
 use crate::tree::{active_pane, active_pane_mut};
@@ -16,6 +7,15 @@ use crate::tree::{active_pane, active_pane_mut};
 pub fn enter_copy_mode(app: &mut AppState) { 
     app.mode = Mode::CopyMode; 
     app.copy_scroll_offset = 0;
+    app.copy_hscroll = None;
+    // A view scrolled without copy mode becomes copy mode's starting offset
+    let win = &mut app.windows[app.active_idx];
+    if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+        if p.view_scrolled {
+            p.view_scrolled = false;
+            app.copy_scroll_offset = p.term.lock().map(|t| t.screen().scrollback()).unwrap_or(0);
+        }
+    }
     app.copy_selection_mode = crate::types::SelectionMode::Char;
     app.copy_anchor = None;
     // Initialize copy_pos from the terminal cursor so the cursor is
@@ -38,6 +38,7 @@ pub fn exit_copy_mode(app: &mut AppState) {
     app.copy_anchor = None;
     app.copy_pos = None;
     app.copy_scroll_offset = 0;
+    app.copy_hscroll = None;
     let win = &mut app.windows[app.active_idx];
     if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
         // Clear the pane-local copy state so re-entering this pane won't
@@ -61,6 +62,7 @@ pub fn save_copy_state_to_pane(app: &mut AppState) {
         anchor_scroll_offset: app.copy_anchor_scroll_offset,
         pos: app.copy_pos,
         scroll_offset: app.copy_scroll_offset,
+        hscroll: app.copy_hscroll,
         selection_mode: app.copy_selection_mode,
         search_query: app.copy_search_query.clone(),
         count: app.copy_count,
@@ -93,6 +95,7 @@ pub fn restore_copy_state_from_pane(app: &mut AppState) {
         app.copy_anchor_scroll_offset = s.anchor_scroll_offset;
         app.copy_pos = s.pos;
         app.copy_scroll_offset = s.scroll_offset;
+        app.copy_hscroll = s.hscroll;
         app.copy_selection_mode = s.selection_mode;
         app.copy_search_query = s.search_query;
         app.copy_count = s.count;
@@ -136,92 +139,68 @@ pub fn switch_with_copy_save<F: FnOnce(&mut AppState)>(app: &mut AppState, switc
     }
 }
 
-#[cfg(windows)]
-pub fn copy_to_system_clipboard(text: &str) {
-    const CF_UNICODETEXT: u32 = 13;
-
-    // Clipboard can be momentarily locked by other processes; retry briefly.
-    for _ in 0..5 {
-        let opened = unsafe { OpenClipboard(std::ptr::null_mut()) };
-        if opened == 0 {
-            thread::sleep(Duration::from_millis(2));
-            continue;
-        }
-
-        let mut utf16: Vec<u16> = text.encode_utf16().collect();
-        utf16.push(0); // null terminator required by CF_UNICODETEXT
-        let size_bytes = utf16.len() * std::mem::size_of::<u16>();
-        let mut hmem: HGLOBAL = std::ptr::null_mut();
-
-        unsafe {
-            if EmptyClipboard() != 0 {
-                hmem = GlobalAlloc(GMEM_MOVEABLE, size_bytes);
-                if !hmem.is_null() {
-                    let dst = GlobalLock(hmem) as *mut u16;
-                    if !dst.is_null() {
-                        std::ptr::copy_nonoverlapping(utf16.as_ptr(), dst, utf16.len());
-                        GlobalUnlock(hmem);
-                        if !SetClipboardData(CF_UNICODETEXT, hmem).is_null() {
-                            // Ownership transferred to the OS on success.
-                            hmem = std::ptr::null_mut();
-                        }
-                    }
-                }
-            }
-
-            if !hmem.is_null() {
-                let _ = GlobalFree(hmem);
-            }
-            let _ = CloseClipboard();
+/// Bring a pane's copy-mode positions within a new `rows` x `cols` size.
+///
+/// vt100 keeps rows from the top on a resize, so a position keeps its
+/// logical line: the cursor stays put unless its line falls below the new
+/// bottom edge, in which case the view scrolls toward live output to keep
+/// it on screen (or clamps when the line itself was cut off).  Columns are
+/// clamped.  Returns true when the selection was dropped because the
+/// anchor's line no longer exists.
+pub fn fit_copy_state(s: &mut CopyModeState, rows: u16, cols: u16) -> bool {
+    let last_row = rows.max(1) as i64 - 1;
+    let last_col = cols.max(1) - 1;
+    let old_scroll = s.scroll_offset;
+    if let Some((r, c)) = s.pos {
+        let below = r as i64 - last_row;
+        if below > 0 { s.scroll_offset = s.scroll_offset.saturating_sub(below as usize); }
+        let abs = r as i64 - old_scroll as i64;
+        let row = (abs + s.scroll_offset as i64).clamp(0, last_row) as u16;
+        s.pos = Some((row, c.min(last_col)));
+    }
+    let mut cleared = false;
+    if let Some((r, c)) = s.anchor {
+        if r as i64 - s.anchor_scroll_offset as i64 > last_row {
+            s.anchor = None;
+            cleared = true;
+        } else {
+            s.anchor = Some((r, c.min(last_col)));
         }
-        break;
     }
+    // Matches name lines, so only those cut off below the new bottom go
+    s.search_matches.retain(|&(line, c0, _)| line <= last_row && c0 <= last_col);
+    for m in &mut s.search_matches { m.2 = m.2.min(last_col); }
+    if s.search_idx >= s.search_matches.len() { s.search_idx = 0; }
+    cleared
 }
 
-#[cfg(not(windows))]
-pub fn copy_to_system_clipboard(_text: &str) {}
-
-/// Read text from the Windows system clipboard.
-#[cfg(windows)]
-pub fn read_from_system_clipboard() -> Option<String> {
-    const CF_UNICODETEXT: u32 = 13;
-    for _ in 0..5 {
-        let opened = unsafe { OpenClipboard(std::ptr::null_mut()) };
-        if opened == 0 {
-            thread::sleep(Duration::from_millis(2));
-            continue;
-        }
-        let result = unsafe {
-            let hmem = GetClipboardData(CF_UNICODETEXT);
-            if hmem.is_null() {
-                let _ = CloseClipboard();
-                return None;
-            }
-            let ptr = GlobalLock(hmem) as *const u16;
-            if ptr.is_null() {
-                let _ = CloseClipboard();
-                return None;
+/// Fit the copy-mode state of every pane in the active window to its
+/// current size after a resize, syncing the terminal's scrollback with
+/// the adjusted offset.
+pub fn fit_copy_mode_after_resize(app: &mut AppState) {
+    let was_copy = matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. });
+    if was_copy { save_copy_state_to_pane(app); }
+    fn fit_node(node: &mut crate::types::Node, cleared: &mut bool) {
+        match node {
+            crate::types::Node::Leaf(p) => {
+                let Some(s) = p.copy_state.as_mut() else { return };
+                *cleared |= fit_copy_state(s, p.last_rows, p.last_cols);
+                if let Ok(mut parser) = p.term.lock() {
+                    parser.screen_mut().set_scrollback(s.scroll_offset);
+                    s.scroll_offset = parser.screen().scrollback();
+                }
             }
-            // Find null terminator
-            let mut len = 0usize;
-            while *ptr.add(len) != 0 {
-                len += 1;
-                if len > 1_000_000 { break; } // safety limit
+            crate::types::Node::Split { children, .. } => {
+                for c in children.iter_mut() { fit_node(c, cleared); }
             }
-            let slice = std::slice::from_raw_parts(ptr, len);
-            let text = String::from_utf16_lossy(slice);
-            GlobalUnlock(hmem);
-            let _ = CloseClipboard();
-            Some(text)
-        };
-        return result;
+        }
     }
-    None
+    let mut cleared = false;
+    if let Some(win) = app.windows.get_mut(app.active_idx) { fit_node(&mut win.root, &mut cleared); }
+    if was_copy { restore_copy_state_from_pane(app); }
+    if cleared { app.pending_message = Some("Selection cleared: pane resized".to_string()); }
 }
 
-#[cfg(not(windows))]
-pub fn read_from_system_clipboard() -> Option<String> { None }
-
 pub fn current_prompt_pos(app: &mut AppState) -> Option<(u16,u16)> {
     let win = &mut app.windows[app.active_idx];
     let p = active_pane_mut(&mut win.root, &win.active_path)?;
@@ -238,6 +217,23 @@ pub fn move_copy_cursor(app: &mut AppState, dx: i16, dy: i16) {
     let (r, c) = app.copy_pos.unwrap_or_else(|| parser.screen().cursor_position());
     let rows = p.last_rows;
     let cols = p.last_cols;
+    // h/l run on along a wrapped line: past the pane edge they continue on
+    // the row the terminal wrapped into, and the view unwraps to follow
+    if dy == 0 {
+        let screen = parser.screen();
+        let crossed = if dx > 0 && c + 1 >= cols && r + 1 < rows && screen.row_wrapped(r) {
+            Some((r + 1, 0))
+        } else if dx < 0 && c == 0 && r > 0 && screen.row_wrapped(r - 1) {
+            Some((r - 1, cols.saturating_sub(1)))
+        } else { None };
+        if let Some(to) = crossed {
+            drop(parser);
+            app.copy_pos = Some(to);
+            app.copy_hscroll.get_or_insert(0);
+            follow_copy_cursor(app);
+            return;
+        }
+    }
     let desired_r = r as i16 + dy;
     let nc = (c as i16 + dx).max(0).min(cols as i16 - 1) as u16;
     // If cursor would move above the visible area, scroll up into scrollback
@@ -263,6 +259,133 @@ pub fn move_copy_cursor(app: &mut AppState, dx: i16, dy: i16) {
     } else {
         app.copy_pos = Some((desired_r as u16, nc));
     }
+    drop(parser);
+    follow_copy_cursor(app);
+}
+
+// ─── Horizontal scroll ──────────────────────────────────────────────────────
+
+/// What one column of the unwrapped view shows.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViewCell {
+    /// The screen cell at (row, col)
+    Screen(u16, u16),
+    /// `<` or `>`: the line has more text beyond this edge of the view
+    Edge(char),
+    /// Past the end of the line, or below the last line
+    Blank,
+}
+
+/// Copy mode's unwrapped view: every logical line of the screen (rows the
+/// terminal wrapped into one another) on a row of its own, shifted left by
+/// `off` columns.  Copy positions stay in screen coordinates; the view only
+/// maps them to and from what is drawn, so selections and yanks are the
+/// same whatever the offset.
+pub struct HScrollView {
+    off: usize,
+    cols: u16,
+    /// Per logical line: first screen row, rows spanned, and the logical
+    /// columns of its first and one past its last non-blank cell
+    lines: Vec<(u16, u16, usize, usize)>,
+}
+
+impl HScrollView {
+    pub fn new(screen: &vt100::Screen, rows: u16, cols: u16, off: usize) -> Self {
+        let cols = cols.max(1);
+        let width = cols as usize;
+        let mut lines = Vec::new();
+        let mut r = 0;
+        while r < rows {
+            let first = r;
+            while r + 1 < rows && screen.row_wrapped(r) { r += 1; }
+            let (mut start, mut end) = (usize::MAX, 0);
+            for row in first..=r {
+                for c in 0..cols {
+                    if screen.cell(row, c).map_or(false, |cell| !cell.contents().trim().is_empty()) {
+                        let logical = (row - first) as usize * width + c as usize;
+                        start = start.min(logical);
+                        end = logical + 1;
+                    }
+                }
+            }
+            lines.push((first, r - first + 1, start, end));
+            r += 1;
+        }
+        HScrollView { off, cols, lines }
+    }
+
+    /// What column `col` of view row `row` shows.
+    pub fn cell(&self, row: u16, col: u16) -> ViewCell {
+        let Some(&(first, span, start, end)) = self.lines.get(row as usize) else { return ViewCell::Blank };
+        if col == 0 && start < self.off { return ViewCell::Edge('<'); }
+        if col + 1 == self.cols && end > self.off + self.cols as usize { return ViewCell::Edge('>'); }
+        let logical = self.off + col as usize;
+        let r = logical / self.cols as usize;
+        if r >= span as usize { return ViewCell::Blank; }
+        ViewCell::Screen(first + r as u16, (logical % self.cols as usize) as u16)
+    }
+
+    /// View row and logical column (before the offset) of a screen position.
+    pub fn logical_pos(&self, pos: (u16, u16)) -> Option<(u16, usize)> {
+        let i = self.lines.iter().position(|&(first, span, ..)| pos.0 >= first && pos.0 < first + span)?;
+        let first = self.lines[i].0;
+        Some((i as u16, (pos.0 - first) as usize * self.cols as usize + pos.1 as usize))
+    }
+
+    /// Where a screen position is drawn, or None when it is scrolled out of view.
+    pub fn view_pos(&self, pos: (u16, u16)) -> Option<(u16, u16)> {
+        let (row, col) = self.logical_pos(pos)?;
+        (col >= self.off && col < self.off + self.cols as usize).then(|| (row, (col - self.off) as u16))
+    }
+
+    /// Like `view_pos`, but a position off either side is pulled to that
+    /// edge (for drawing a selection that continues out of view).
+    pub fn clamped_pos(&self, pos: (u16, u16)) -> (u16, u16) {
+        match self.logical_pos(pos) {
+            Some((row, col)) => (row, col.saturating_sub(self.off).min(self.cols as usize - 1) as u16),
+            None => (self.lines.len().saturating_sub(1) as u16, self.cols - 1),
+        }
+    }
+
+    /// Largest offset worth scrolling to: the widest line's end at the right edge.
+    pub fn max_offset(&self) -> usize {
+        self.lines.iter().map(|l| l.3).max().unwrap_or(0).saturating_sub(self.cols as usize)
+    }
+}
+
+/// The unwrapped view of the active pane, when copy mode is showing one.
+pub fn copy_hscroll_view(app: &AppState, screen: &vt100::Screen, rows: u16, cols: u16) -> Option<HScrollView> {
+    if !matches!(app.mode, Mode::CopyMode) { return None; }
+    app.copy_hscroll.map(|off| HScrollView::new(screen, rows, cols, off))
+}
+
+/// Scroll the unwrapped view just far enough to show the copy cursor.
+pub fn follow_copy_cursor(app: &mut AppState) {
+    let (Some(off), Some(pos)) = (app.copy_hscroll, app.copy_pos) else { return };
+    let win = &app.windows[app.active_idx];
+    let Some(p) = active_pane(&win.root, &win.active_path) else { return };
+    let cols = p.last_cols.max(1) as usize;
+    let col = match p.term.lock() {
+        Ok(parser) => HScrollView::new(parser.screen(), p.last_rows, p.last_cols, off).logical_pos(pos).map(|(_, c)| c),
+        Err(_) => return,
+    };
+    if let Some(col) = col {
+        app.copy_hscroll = Some(if col < off { col } else if col >= off + cols { col + 1 - cols } else { off });
+    }
+}
+
+/// `scroll-left` / `scroll-right`: shift the unwrapped view half a pane
+/// width, switching to it from the wrapped view.  The cursor stays put.
+pub fn scroll_copy_horizontally(app: &mut AppState, right: bool) {
+    let off = app.copy_hscroll.unwrap_or(0);
+    let win = &app.windows[app.active_idx];
+    let Some(p) = active_pane(&win.root, &win.active_path) else { return };
+    let step = (p.last_cols / 2).max(1) as usize;
+    let max = match p.term.lock() {
+        Ok(parser) => HScrollView::new(parser.screen(), p.last_rows, p.last_cols, off).max_offset(),
+        Err(_) => return,
+    };
+    app.copy_hscroll = Some(if right { (off + step).min(max.max(off)) } else { off.saturating_sub(step) });
 }
 
 /// Helper: read a full row of text from the active pane's screen.
@@ -475,7 +598,16 @@ pub fn scroll_to_bottom(app: &mut AppState) {
     app.copy_scroll_offset = 0;
 }
 
-pub fn yank_selection(app: &mut AppState) -> io::Result<()> {
+/// Yank the selection to the register chosen with `"`, if any, else onto
+/// the automatic buffers.
+pub fn yank_to_register(app: &mut AppState) -> io::Result<()> {
+    let to = app.copy_register.take();
+    yank_selection(app, to)
+}
+
+/// Yank the selection into register `to`'s buffer, or a new automatic
+/// buffer when None.
+pub fn yank_selection(app: &mut AppState, to: Option<char>) -> io::Result<()> {
     let (anchor, pos) = match (app.copy_anchor, app.copy_pos) { (Some(a), Some(p)) => (a,p), _ => return Ok(()) };
     let sel_mode = app.copy_selection_mode;
     let anchor_scroll = app.copy_anchor_scroll_offset;
@@ -525,18 +657,13 @@ pub fn yank_selection(app: &mut AppState) -> io::Result<()> {
             let is_last  = abs_idx + 1 == total_lines;
             match sel_mode {
                 crate::types::SelectionMode::Rect => {
-                    let c0 = anchor.1.min(pos.1); let c1 = anchor.1.max(pos.1);
-                    let mut line = String::new();
-                    for c in c0..=c1 {
-                        if let Some(cell) = parser.screen().cell(r, c) { line.push_str(&cell.contents().to_string()); } else { line.push(' '); }
-                    }
-                    text.push_str(line.trim_end());
+                    text.push_str(&rect_row(parser.screen(), r, anchor.1.min(pos.1), anchor.1.max(pos.1)));
                     if !is_last { text.push('\n'); }
                 }
                 crate::types::SelectionMode::Line => {
                     let mut line = String::new();
                     for c in 0..cols {
-                        if let Some(cell) = parser.screen().cell(r, c) { line.push_str(&cell.contents().to_string()); } else { line.push(' '); }
+                        if let Some(cell) = parser.screen().cell(r, c) { line.push_str(cell.contents()); } else { line.push(' '); }
                     }
                     text.push_str(line.trim_end());
                     text.push('\n');
@@ -545,14 +672,14 @@ pub fn yank_selection(app: &mut AppState) -> io::Result<()> {
                     if total_lines == 1 {
                         let c0 = anchor.1.min(pos.1); let c1 = anchor.1.max(pos.1);
                         for c in c0..=c1 {
-                            if let Some(cell) = parser.screen().cell(r, c) { text.push_str(&cell.contents().to_string()); } else { text.push(' '); }
+                            if let Some(cell) = parser.screen().cell(r, c) { text.push_str(cell.contents()); } else { text.push(' '); }
                         }
                     } else {
                         let line_start = if is_first { top_col } else { 0 };
                         let line_end   = if is_last  { bot_col } else { cols.saturating_sub(1) };
                         let mut line = String::new();
                         for c in line_start..=line_end {
-                            if let Some(cell) = parser.screen().cell(r, c) { line.push_str(&cell.contents().to_string()); } else { line.push(' '); }
+                            if let Some(cell) = parser.screen().cell(r, c) { line.push_str(cell.contents()); } else { line.push(' '); }
                         }
                         text.push_str(line.trim_end());
                         if !is_last { text.push('\n'); }
@@ -565,23 +692,29 @@ pub fn yank_selection(app: &mut AppState) -> io::Result<()> {
     }
     // Restore original scrollback
     parser.screen_mut().set_scrollback(current_scroll);
-    // Store in named register if one was selected
-    if let Some(reg) = app.copy_register.take() {
-        app.named_registers.insert(reg, text.clone());
-    }
-    app.paste_buffers.insert(0, text.clone());
-    if app.paste_buffers.len() > 10 { app.paste_buffers.pop(); }
-    copy_to_system_clipboard(&text);
-    // Pipe to copy-command if configured
-    if !app.copy_command.is_empty() {
-        let cmd = app.copy_command.clone();
-        pipe_text_to_command(&text, &cmd);
-    }
+    drop(parser);
+    app.paste_buffers.yank(to, text.clone());
+    crate::clipboard::export(app, &text);
     Ok(())
 }
 
+/// Columns `c0..=c1` of screen row `r` for a block selection, padded with
+/// spaces to the full width.  A wide character is kept when its first cell
+/// is inside the block; the half of one that starts left of it is a space.
+fn rect_row(screen: &vt100::Screen, r: u16, c0: u16, c1: u16) -> String {
+    let mut line = String::new();
+    for c in c0..=c1 {
+        match screen.cell(r, c) {
+            Some(cell) if cell.is_wide_continuation() => { if c == c0 { line.push(' '); } }
+            Some(cell) if cell.has_contents() => line.push_str(cell.contents()),
+            _ => line.push(' '),
+        }
+    }
+    line
+}
+
 /// Pipe text to a shell command's stdin.
-fn pipe_text_to_command(text: &str, cmd: &str) {
+pub fn pipe_text_to_command(text: &str, cmd: &str) {
     let shell = if cfg!(windows) { "pwsh" } else { "sh" };
     let args: Vec<&str> = if cfg!(windows) {
         vec!["-NoProfile", "-Command", cmd]
@@ -604,16 +737,13 @@ fn pipe_text_to_command(text: &str, cmd: &str) {
 
 pub fn paste_latest(app: &mut AppState) -> io::Result<()> {
     // If a named register was selected, paste from it
-    if let Some(reg) = app.copy_register.take() {
-        if let Some(text) = app.named_registers.get(&reg).cloned() {
-            let win = &mut app.windows[app.active_idx];
-            if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) { let _ = write!(p.writer, "{}", text); }
-        }
-        return Ok(());
-    }
-    if let Some(buf) = app.paste_buffers.first() {
-        let win = &mut app.windows[app.active_idx];
-        if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) { let _ = write!(p.writer, "{}", buf); }
+    let text = match app.copy_register.take() {
+        Some(reg) => app.paste_buffers.get(Some(&reg.to_string())).map(|b| b.text.clone()),
+        None => app.paste_buffers.top().cloned(),
+    };
+    if let Some(text) = text {
+        let broadcast = crate::input::paste_broadcasts(app, false);
+        crate::input::paste_to_panes(app, &text, broadcast);
     }
     Ok(())
 }
@@ -626,15 +756,125 @@ pub fn capture_active_pane(app: &mut AppState) -> io::Result<()> {
     let mut text = String::new();
     for r in 0..p.last_rows {
         let mut row = String::new();
-        for c in 0..p.last_cols { if let Some(cell) = screen.cell(r, c) { row.push_str(&cell.contents().to_string()); } else { row.push(' '); } }
+        for c in 0..p.last_cols { if let Some(cell) = screen.cell(r, c) { row.push_str(cell.contents()); } else { row.push(' '); } }
         text.push_str(row.trim_end());
         text.push('\n');
     }
-    app.paste_buffers.insert(0, text);
-    if app.paste_buffers.len() > 10 { app.paste_buffers.pop(); }
+    app.paste_buffers.push(text);
     Ok(())
 }
 
+/// Drop a pane's scrollback, keeping the visible screen, the cursor, the
+/// input modes and whether the alternate screen is up.  vt100 cannot trim
+/// history in place, so the parser is rebuilt and both screens replayed
+/// into it.  With `screen_too` (clear-history -H) the visible screen is
+/// cleared as well.
+pub fn clear_scrollback(parser: &mut vt100::Parser, history_limit: usize, screen_too: bool) {
+    rebuild(parser, history_limit, &[], screen_too);
+}
+
+/// Give a pane's parser a new scrollback size, keeping as much of its
+/// history as fits (the newest lines) along with everything
+/// `clear_scrollback` keeps.
+pub fn set_history_limit(parser: &mut vt100::Parser, history_limit: usize) {
+    let history = history_lines(parser, history_limit);
+    rebuild(parser, history_limit, &history, false);
+}
+
+/// The newest `max` lines of scrollback, formatted, oldest first.  The view
+/// is left at the bottom.
+fn history_lines(parser: &mut vt100::Parser, max: usize) -> Vec<Vec<u8>> {
+    let cols = parser.screen().size().1;
+    parser.screen_mut().set_scrollback(usize::MAX);
+    let kept = parser.screen().scrollback().min(max);
+    let history = (1..=kept).rev().map(|offset| {
+        parser.screen_mut().set_scrollback(offset);
+        parser.screen().rows_formatted(0, cols).next().unwrap_or_default()
+    }).collect();
+    parser.screen_mut().set_scrollback(0);
+    history
+}
+
+/// The parser for a respawned pane: everything the old one showed, its
+/// history and then its main screen down to the last line written (the
+/// main screen even if the program died in the alternate one), scrolled
+/// into the history above a `separator` line.  The new program starts on
+/// a clear screen below.
+pub fn respawn_parser(old: &mut vt100::Parser, history_limit: usize, separator: &str) -> vt100::Parser {
+    let (rows, cols) = old.screen().size();
+    let mut lines = history_lines(old, history_limit);
+    if old.screen().alternate_screen() { old.process(b"\x1b[?47l"); }
+    let used = old.screen().rows(0, cols).enumerate()
+        .filter(|(_, text)| !text.trim_end().is_empty()).last().map_or(0, |(r, _)| r + 1);
+    lines.extend(old.screen().rows_formatted(0, cols).take(used));
+    lines.push(separator.as_bytes().to_vec());
+
+    let mut fresh = vt100::Parser::new(rows, cols, history_limit);
+    // The lines fill the screen from the top and scroll up, and newlines
+    // on the bottom row push the ones left showing into the history
+    for line in &lines {
+        fresh.process(b"\x1b[m");
+        fresh.process(line);
+        fresh.process(b"\r\n");
+    }
+    let showing = lines.len().min(rows.saturating_sub(1) as usize);
+    fresh.process(format!("\x1b[m\x1b[{};1H", rows).as_bytes());
+    fresh.process(&b"\n".repeat(showing));
+    fresh.process(b"\x1b[H");
+    fresh
+}
+
+/// Lines of scrollback the parser holds.
+pub fn history_size(parser: &mut vt100::Parser) -> usize {
+    let saved = parser.screen().scrollback();
+    parser.screen_mut().set_scrollback(usize::MAX);
+    let size = parser.screen().scrollback();
+    parser.screen_mut().set_scrollback(saved);
+    size
+}
+
+/// Apply `history-limit` to every pane's parser.
+pub fn apply_history_limit(app: &mut AppState) {
+    let limit = app.history_limit;
+    for w in app.windows.iter_mut() {
+        crate::tree::for_each_pane_mut(&mut w.root, &mut |p| {
+            if let Ok(mut parser) = p.term.lock() { set_history_limit(&mut parser, limit); }
+            p.data_version.fetch_add(1, std::sync::atomic::Ordering::Release);
+        });
+    }
+}
+
+/// Replace `parser` with a fresh one holding `history_limit` lines of
+/// scrollback, replaying `history` (formatted lines, oldest first) into it
+/// and then both screens.
+fn rebuild(parser: &mut vt100::Parser, history_limit: usize, history: &[Vec<u8>], screen_too: bool) {
+    let (rows, cols) = parser.screen().size();
+    parser.screen_mut().set_scrollback(0);
+    let alt_state = parser.screen().alternate_screen().then(|| parser.screen().state_formatted());
+    if alt_state.is_some() { parser.process(b"\x1b[?47l"); }
+    let mut fresh = vt100::Parser::new(rows, cols, history_limit);
+    if !history.is_empty() {
+        // Each line is written on the bottom row and scrolled up; enough
+        // newlines after the last push them all off the screen
+        fresh.process(format!("\x1b[{};1H", rows).as_bytes());
+        for line in history {
+            fresh.process(b"\x1b[m");
+            fresh.process(line);
+            fresh.process(b"\r\n");
+        }
+        fresh.process(b"\x1b[m");
+        fresh.process(&b"\n".repeat(rows.saturating_sub(1) as usize));
+    }
+    fresh.process(&parser.screen().state_formatted());
+    if let Some(state) = alt_state {
+        // Saves the main screen's cursor for the application's ?1049l
+        fresh.process(b"\x1b[?1049h");
+        fresh.process(&state);
+    }
+    if screen_too { fresh.process(b"\x1b[H\x1b[2J"); }
+    *parser = fresh;
+}
+
 pub fn capture_active_pane_text(app: &mut AppState) -> io::Result<Option<String>> {
     let win = &mut app.windows[app.active_idx];
     let p = match active_pane_mut(&mut win.root, &win.active_path) { Some(p) => p, None => return Ok(None) };
@@ -643,67 +883,142 @@ pub fn capture_active_pane_text(app: &mut AppState) -> io::Result<Option<String>
     let mut text = String::new();
     for r in 0..p.last_rows {
         let mut row = String::new();
-        for c in 0..p.last_cols { if let Some(cell) = screen.cell(r, c) { row.push_str(&cell.contents().to_string()); } else { row.push(' '); } }
+        for c in 0..p.last_cols { if let Some(cell) = screen.cell(r, c) { row.push_str(cell.contents()); } else { row.push(' '); } }
         text.push_str(row.trim_end());
         text.push('\n');
     }
     Ok(Some(text))
 }
 
+/// Fold the yank just made into the buffer that was most recent before it.
+pub fn append_last_yank(app: &mut AppState) {
+    if app.paste_buffers.len() >= 2 {
+        if let Some(yank) = app.paste_buffers.remove_at(0) { app.paste_buffers.append(None, &yank.text); }
+    }
+}
+
 pub fn save_latest_buffer(app: &mut AppState, file: &str) -> io::Result<()> {
-    if let Some(buf) = app.paste_buffers.first() { std::fs::write(file, buf)?; }
+    if let Some(buf) = app.paste_buffers.top() { std::fs::write(file, buf)?; }
     Ok(())
 }
 
-/// Search the active pane's screen content for a query string.
-/// Populates `app.copy_search_matches` with (row, col_start, col_end) tuples.
-/// If forward is true, sorts matches top-to-bottom; otherwise bottom-to-top.
+/// Search the active pane's history and screen for `query`: a regular
+/// expression when it compiles, plain text otherwise, ignoring case.
+/// Fills `app.copy_search_matches` top to bottom with (line, first column,
+/// last column), lines numbered as for `for_each_line`, and makes current
+/// the first match after the cursor (before it when `forward` is false),
+/// wrapping around.  A scan that runs past `pattern-timeout` is abandoned
+/// with a message.
 pub fn search_copy_mode(app: &mut AppState, query: &str, forward: bool) {
     app.copy_search_matches.clear();
     app.copy_search_idx = 0;
     if query.is_empty() { return; }
 
+    let scroll = app.copy_scroll_offset as i64;
+    let (first, lines) = {
+        let win = &mut app.windows[app.active_idx];
+        let p = match active_pane_mut(&mut win.root, &win.active_path) { Some(p) => p, None => return };
+        let mut parser = match p.term.lock() { Ok(g) => g, Err(_) => return };
+        let cols = p.last_cols;
+        let first = -(history_size(&mut parser) as i64);
+        // Each line's text and the byte offset each column starts at
+        let mut lines: Vec<(String, Vec<usize>)> = Vec::new();
+        for_each_line(&mut parser, Some(i32::MIN), None, |screen, r| {
+            let mut text = String::with_capacity(cols as usize);
+            let mut starts = Vec::with_capacity(cols as usize);
+            for c in 0..cols {
+                starts.push(text.len());
+                match screen.cell(r, c).map(|cell| cell.contents()) {
+                    Some(t) if !t.is_empty() => text.push_str(t),
+                    _ => text.push(' '),
+                }
+            }
+            lines.push((text, starts));
+        });
+        (first, lines)
+    };
+    let budget = crate::pattern::Budget::new(app.pattern_timeout_ms);
+    let re = crate::pattern::compile(query, true)
+        .or_else(|_| crate::pattern::compile(&regex::escape(query), true));
+    let found = re.and_then(|re| crate::pattern::find_in_lines(&re, lines.iter().map(|(t, _)| t.as_str()), &budget));
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => { app.pending_message = Some(e.to_string()); return; }
+    };
+    let col_of = |starts: &[usize], byte: usize| (starts.partition_point(|&s| s <= byte).max(1) - 1) as u16;
+    app.copy_search_matches = found.into_iter()
+        .filter(|&(_, s, e)| e > s)
+        .map(|(i, s, e)| {
+            let starts = &lines[i].1;
+            (first + i as i64, col_of(starts, s), col_of(starts, e - 1))
+        })
+        .collect();
+
+    let (r, c) = app.copy_pos.unwrap_or((0, 0));
+    let cursor = (r as i64 - scroll, c);
+    let matches = &app.copy_search_matches;
+    app.copy_search_idx = if forward {
+        matches.iter().position(|&(l, c0, _)| (l, c0) > cursor).unwrap_or(0)
+    } else {
+        matches.iter().rposition(|&(l, c0, _)| (l, c0) < cursor).unwrap_or(matches.len().saturating_sub(1))
+    };
+}
+
+/// Move the cursor to the current search match, scrolling to bring its
+/// line into view (to the middle of the pane) when it is off screen.
+pub fn jump_to_search_match(app: &mut AppState) {
+    let Some(&(line, c0, _)) = app.copy_search_matches.get(app.copy_search_idx) else { return };
     let win = &mut app.windows[app.active_idx];
-    let p = match active_pane_mut(&mut win.root, &win.active_path) { Some(p) => p, None => return };
-    let parser = match p.term.lock() { Ok(g) => g, Err(_) => return };
-    let screen = parser.screen();
-    let query_lower = query.to_lowercase();
-    let qlen = query_lower.len() as u16;
+    let Some(p) = active_pane_mut(&mut win.root, &win.active_path) else { return };
+    let rows = p.last_rows.max(1) as i64;
+    let Ok(mut parser) = p.term.lock() else { return };
+    let mut scroll = app.copy_scroll_offset as i64;
+    if !(0..rows).contains(&(line + scroll)) { scroll = (rows / 2 - line).max(0); }
+    parser.screen_mut().set_scrollback(scroll as usize);
+    app.copy_scroll_offset = parser.screen().scrollback();
+    let row = (line + app.copy_scroll_offset as i64).clamp(0, rows - 1);
+    app.copy_pos = Some((row as u16, c0));
+}
 
-    // Scan all visible rows
-    for r in 0..p.last_rows {
-        // Build the row text
-        let mut row_text = String::with_capacity(p.last_cols as usize);
-        for c in 0..p.last_cols {
-            if let Some(cell) = screen.cell(r, c) {
-                let t = cell.contents();
-                if t.is_empty() { row_text.push(' '); } else { row_text.push_str(t); }
-            } else {
-                row_text.push(' ');
-            }
-        }
-        // Case-insensitive search
-        let row_lower = row_text.to_lowercase();
-        let mut start = 0;
-        while let Some(pos) = row_lower[start..].find(&query_lower) {
-            let col_start = (start + pos) as u16;
-            let col_end = col_start + qlen;
-            app.copy_search_matches.push((r, col_start, col_end));
-            start += pos + 1;
-        }
-    }
+/// The current match's number and the match count, as copy mode shows
+/// them (`[3/17]`); None without matches.
+pub fn search_position(app: &AppState) -> Option<(usize, usize)> {
+    let total = app.copy_search_matches.len();
+    (total > 0).then(|| (app.copy_search_idx.min(total - 1) + 1, total))
+}
 
-    if !forward {
-        app.copy_search_matches.reverse();
-    }
+/// Note an edit of the search prompt.  The incremental search runs once
+/// typing pauses for [`crate::pattern::SEARCH_DEBOUNCE`], not per keystroke.
+pub fn schedule_incremental_search(app: &mut AppState, now: Instant) {
+    app.copy_search_due = Some(now + crate::pattern::SEARCH_DEBOUNCE);
 }
 
-/// Jump to the next search match in copy mode.
+/// Run the incremental search if it is due, moving the cursor to the
+/// match it finds while the prompt stays open.  Returns true when it ran.
+pub fn run_incremental_search_if_due(app: &mut AppState, now: Instant) -> bool {
+    if !app.copy_search_due.map_or(false, |t| now >= t) { return false; }
+    app.copy_search_due = None;
+    let (query, forward) = match &app.mode {
+        Mode::CopySearch { input, forward } => (input.clone(), *forward),
+        _ => return false,
+    };
+    search_copy_mode(app, &query, forward);
+    jump_to_search_match(app);
+    true
+}
+
+/// Jump to the next search match in the search's direction (`n`).
 pub fn search_next(app: &mut AppState) {
-    if app.copy_search_matches.is_empty() { return; }
-    app.copy_search_idx = (app.copy_search_idx + 1) % app.copy_search_matches.len();
-    let (r, c, _) = app.copy_search_matches[app.copy_search_idx];
-    app.copy_pos = Some((r, c));
+    step_search(app, app.copy_search_forward);
+}
+
+/// Step the current match toward the bottom (`down`) or the top, wrapping
+/// around, and jump to it.
+fn step_search(app: &mut AppState, down: bool) {
+    let n = app.copy_search_matches.len();
+    if n == 0 { return; }
+    app.copy_search_idx = if down { (app.copy_search_idx + 1) % n } else { (app.copy_search_idx + n - 1) % n };
+    jump_to_search_match(app);
 }
 
 /// Move by WORD (whitespace-delimited) forward — W key
@@ -860,75 +1175,72 @@ pub fn copy_end_of_line(app: &mut AppState) -> io::Result<()> {
     let cols = p.last_cols;
     let mut text = String::new();
     for col in c..cols {
-        if let Some(cell) = screen.cell(r, col) { text.push_str(&cell.contents().to_string()); } else { text.push(' '); }
+        if let Some(cell) = screen.cell(r, col) { text.push_str(cell.contents()); } else { text.push(' '); }
     }
     let text = text.trim_end().to_string();
-    app.paste_buffers.insert(0, text.clone());
-    if app.paste_buffers.len() > 10 { app.paste_buffers.pop(); }
-    copy_to_system_clipboard(&text);
+    drop(parser);
+    app.paste_buffers.push(text.clone());
+    crate::clipboard::export(app, &text);
     Ok(())
 }
 
-/// Jump to the previous search match in copy mode.
+/// Jump to the next search match against the search's direction (`N`).
 pub fn search_prev(app: &mut AppState) {
-    if app.copy_search_matches.is_empty() { return; }
-    if app.copy_search_idx == 0 {
-        app.copy_search_idx = app.copy_search_matches.len() - 1;
-    } else {
-        app.copy_search_idx -= 1;
-    }
-    let (r, c, _) = app.copy_search_matches[app.copy_search_idx];
-    app.copy_pos = Some((r, c));
+    step_search(app, !app.copy_search_forward);
 }
 
-pub fn capture_active_pane_range(app: &mut AppState, s: Option<i32>, e: Option<i32>) -> io::Result<Option<String>> {
+/// With `join` (capture-pane -J), rows the pane soft-wrapped are joined
+/// back into one line.
+pub fn capture_active_pane_range(app: &mut AppState, s: Option<i32>, e: Option<i32>, join: bool) -> io::Result<Option<String>> {
     let win = &mut app.windows[app.active_idx];
     let p = match active_pane_mut(&mut win.root, &win.active_path) { Some(p) => p, None => return Ok(None) };
-    let parser = match p.term.lock() { Ok(g) => g, Err(_) => return Ok(None) };
-    let screen = parser.screen();
-    // Negative values are relative to the cursor row (tmux behavior):
-    // -S -5 means "5 lines above the cursor", -E -1 means "1 line above cursor"
-    let (cursor_row, _) = screen.cursor_position();
-    let cursor = cursor_row as i32;
-    let start = match s {
-        Some(v) if v < 0 => (cursor + v + 1).max(0) as u16,
-        Some(v) => (v as u16).min(p.last_rows.saturating_sub(1)),
-        None => 0,
-    };
-    let end = match e {
-        Some(v) if v < 0 => (cursor + v + 1).max(0) as u16,
-        Some(v) => (v as u16).min(p.last_rows.saturating_sub(1)),
-        None => p.last_rows.saturating_sub(1),
-    };
+    let mut parser = match p.term.lock() { Ok(g) => g, Err(_) => return Ok(None) };
+    let cols = p.last_cols;
     let mut text = String::new();
-    for r in start..=end {
+    for_each_line(&mut parser, s, e, |screen, r| {
         let mut row = String::new();
-        for c in 0..p.last_cols { if let Some(cell) = screen.cell(r, c) { row.push_str(&cell.contents().to_string()); } else { row.push(' '); } }
-        text.push_str(row.trim_end());
-        text.push('\n');
-    }
+        for c in 0..cols { if let Some(cell) = screen.cell(r, c) { row.push_str(cell.contents()); } else { row.push(' '); } }
+        if join && screen.row_wrapped(r) {
+            text.push_str(&row);
+        } else {
+            text.push_str(row.trim_end());
+            text.push('\n');
+        }
+    });
+    // The range ended inside a wrapped line
+    if !text.is_empty() && !text.ends_with('\n') { text.push('\n'); }
     Ok(Some(text))
 }
 
+/// Visit lines `s..=e` of a pane, numbered as tmux numbers them: 0 is the
+/// top of the live screen and negative numbers go back into the scrollback
+/// (-1 is the newest history line).  None is the top of the screen for `s`
+/// and the bottom for `e`; both are clamped to the lines there are.  `f`
+/// gets the screen scrolled so the line is showing, and the row it is on.
+/// The pane's view is left as it was.
+pub fn for_each_line(parser: &mut vt100::Parser, s: Option<i32>, e: Option<i32>, mut f: impl FnMut(&vt100::Screen, u16)) {
+    let saved = parser.screen().scrollback();
+    let history = history_size(parser) as i64;
+    let rows = parser.screen().size().0 as i64;
+    let start = s.map_or(0, i64::from).clamp(-history, rows - 1);
+    let end = e.map_or(rows - 1, i64::from).clamp(-history, rows - 1);
+    for line in start..=end {
+        let offset = (-line).max(0);
+        parser.screen_mut().set_scrollback(offset as usize);
+        f(parser.screen(), (line + offset) as u16);
+    }
+    parser.screen_mut().set_scrollback(saved);
+}
+
 /// Capture the active pane's screen content with ANSI escape sequences preserved.
-/// This is the `-e` flag for capture-pane.  Supports optional start/end range.
-pub fn capture_active_pane_styled(app: &mut AppState, s: Option<i32>, e: Option<i32>) -> io::Result<Option<String>> {
+/// This is the `-e` flag for capture-pane.  Supports optional start/end range,
+/// numbered as for `for_each_line`, and `join` as for
+/// `capture_active_pane_range`.
+pub fn capture_active_pane_styled(app: &mut AppState, s: Option<i32>, e: Option<i32>, join: bool) -> io::Result<Option<String>> {
     let win = &mut app.windows[app.active_idx];
     let p = match active_pane_mut(&mut win.root, &win.active_path) { Some(p) => p, None => return Ok(None) };
-    let parser = match p.term.lock() { Ok(g) => g, Err(_) => return Ok(None) };
-    let screen = parser.screen();
-    let (cursor_row, _) = screen.cursor_position();
-    let cursor = cursor_row as i32;
-    let start_row = match s {
-        Some(v) if v < 0 => (cursor + v + 1).max(0) as u16,
-        Some(v) => (v as u16).min(p.last_rows.saturating_sub(1)),
-        None => 0,
-    };
-    let end_row = match e {
-        Some(v) if v < 0 => (cursor + v + 1).max(0) as u16,
-        Some(v) => (v as u16).min(p.last_rows.saturating_sub(1)),
-        None => p.last_rows.saturating_sub(1),
-    };
+    let mut parser = match p.term.lock() { Ok(g) => g, Err(_) => return Ok(None) };
+    let cols = p.last_cols;
     let mut text = String::new();
     let mut prev_fg: Option<vt100::Color> = None;
     let mut prev_bg: Option<vt100::Color> = None;
@@ -937,12 +1249,12 @@ pub fn capture_active_pane_styled(app: &mut AppState, s: Option<i32>, e: Option<
     let mut prev_underline = false;
     let mut prev_inverse = false;
 
-    for r in start_row..=end_row {
+    for_each_line(&mut parser, s, e, |screen, r| {
         // Build the row content, then trim trailing whitespace
         let mut row_chars: Vec<String> = Vec::new();
         let mut row_sgr: Vec<Option<String>> = Vec::new();
         let mut any_style_active = false;
-        for c in 0..p.last_cols {
+        for c in 0..cols {
             if let Some(cell) = screen.cell(r, c) {
                 let fg = cell.fgcolor();
                 let bg = cell.bgcolor();
@@ -1001,6 +1313,14 @@ pub fn capture_active_pane_styled(app: &mut AppState, s: Option<i32>, e: Option<
                 row_chars.push(" ".to_string());
             }
         }
+        // A wrapped row runs on into the next, styles and all
+        if join && screen.row_wrapped(r) {
+            for (sgr, ch) in row_sgr.iter().zip(&row_chars) {
+                if let Some(sgr) = sgr { text.push_str(sgr); }
+                text.push_str(ch);
+            }
+            return;
+        }
         // Find last non-whitespace cell to trim trailing spaces
         let last_non_ws = row_chars.iter().rposition(|s| !s.is_empty() && s.trim() != "");
         let trim_end = match last_non_ws {
@@ -1021,7 +1341,8 @@ pub fn capture_active_pane_styled(app: &mut AppState, s: Option<i32>, e: Option<
             prev_inverse = false;
         }
         text.push('\n');
-    }
+    });
+    if !text.is_empty() && !text.ends_with('\n') { text.push_str("\x1b[0m\n"); }
     Ok(Some(text))
 }
 
@@ -1238,3 +1559,660 @@ pub fn select_a_word_big(app: &mut AppState) {
     }
     app.copy_selection_mode = crate::types::SelectionMode::Char;
 }
+
+/// Run a copy-mode command by name, as `send-keys -X` does (tmux-yank and
+/// other plugins drive copy mode this way).  Enters copy mode first if
+/// needed; unknown commands are ignored.
+pub fn run_copy_command(app: &mut AppState, cmd: &str) {
+    if !matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. }) {
+        enter_copy_mode(app);
+    }
+    match cmd {
+        "cancel" => {
+            app.mode = Mode::Passthrough;
+            app.copy_anchor = None;
+            app.copy_pos = None;
+            app.copy_scroll_offset = 0;
+            let win = &mut app.windows[app.active_idx];
+            if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+                if let Ok(mut parser) = p.term.lock() {
+                    parser.screen_mut().set_scrollback(0);
+                }
+            }
+        }
+        // From a block or line selection, v goes back to a character
+        // selection over the same span, as in vim
+        "begin-selection" if app.copy_anchor.is_some() && app.copy_selection_mode != crate::types::SelectionMode::Char => {
+            app.copy_selection_mode = crate::types::SelectionMode::Char;
+        }
+        "begin-selection" => {
+            if let Some((r,c)) = get_copy_pos(app) {
+                app.copy_anchor = Some((r,c));
+                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
+                app.copy_pos = Some((r,c));
+                app.copy_selection_mode = crate::types::SelectionMode::Char;
+            }
+        }
+        "select-line" => {
+            if let Some((r,c)) = get_copy_pos(app) {
+                app.copy_anchor = Some((r,c));
+                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
+                app.copy_pos = Some((r,c));
+                app.copy_selection_mode = crate::types::SelectionMode::Line;
+            }
+        }
+        // With nothing selected, C-v starts a block at the cursor
+        "rectangle-toggle" if app.copy_anchor.is_none() => {
+            if let Some((r,c)) = get_copy_pos(app) {
+                app.copy_anchor = Some((r,c));
+                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
+                app.copy_pos = Some((r,c));
+                app.copy_selection_mode = crate::types::SelectionMode::Rect;
+            }
+        }
+        "rectangle-toggle" => {
+            app.copy_selection_mode = match app.copy_selection_mode {
+                crate::types::SelectionMode::Rect => crate::types::SelectionMode::Char,
+                _ => crate::types::SelectionMode::Rect,
+            };
+        }
+        // -x keeps the selection, like copy-selection-no-clear
+        "copy-selection" => {
+            let _ = yank_to_register(app);
+            app.copy_anchor = None;
+            app.copy_selection_mode = crate::types::SelectionMode::Char;
+        }
+        "copy-selection -x" => {
+            let _ = yank_to_register(app);
+        }
+        "copy-selection-and-cancel" => {
+            let _ = yank_to_register(app);
+            app.mode = Mode::Passthrough;
+            app.copy_scroll_offset = 0;
+            app.copy_pos = None;
+        }
+        "copy-selection-no-clear" => {
+            let _ = yank_to_register(app);
+        }
+        s if s.starts_with("copy-pipe-and-cancel") || s.starts_with("copy-pipe") => {
+            // copy-pipe[-and-cancel] [command] — yank + pipe to command
+            let _ = yank_to_register(app);
+            // Extract pipe command from argument if present
+            let cancel = s.contains("cancel");
+            let pipe_cmd = cmd.strip_prefix("copy-pipe-and-cancel")
+                .or_else(|| cmd.strip_prefix("copy-pipe"))
+                .unwrap_or("")
+                .trim();
+            if !pipe_cmd.is_empty() {
+                if let Some(text) = app.paste_buffers.top().cloned() {
+                    // Pipe yanked text to the command's stdin
+                    if let Ok(mut child) = std::process::Command::new(if cfg!(windows) { "pwsh" } else { "sh" })
+                        .args(if cfg!(windows) { vec!["-NoProfile", "-Command", pipe_cmd] } else { vec!["-c", pipe_cmd] })
+                        .stdin(std::process::Stdio::piped())
+                        .stdout(std::process::Stdio::null())
+                        .stderr(std::process::Stdio::null())
+                        .spawn() {
+                        if let Some(mut stdin) = child.stdin.take() {
+                            use std::io::Write;
+                            let _ = stdin.write_all(text.as_bytes());
+                        }
+                        let _ = child.wait();
+                    }
+                }
+            }
+            if cancel {
+                app.mode = Mode::Passthrough;
+                app.copy_scroll_offset = 0;
+                app.copy_pos = None;
+            }
+        }
+        "cursor-up" => { move_copy_cursor(app, 0, -1); }
+        "cursor-down" => { move_copy_cursor(app, 0, 1); }
+        "cursor-left" => { move_copy_cursor(app, -1, 0); }
+        "cursor-right" => { move_copy_cursor(app, 1, 0); }
+        "scroll-left" => { scroll_copy_horizontally(app, false); }
+        "scroll-right" => { scroll_copy_horizontally(app, true); }
+        "start-of-line" => { move_to_line_start(app); }
+        "end-of-line" => { move_to_line_end(app); }
+        "back-to-indentation" => { move_to_first_nonblank(app); }
+        "next-word" => { move_word_forward(app); }
+        "previous-word" => { move_word_backward(app); }
+        "next-word-end" => { move_word_end(app); }
+        "next-space" => { move_word_forward_big(app); }
+        "previous-space" => { move_word_backward_big(app); }
+        "next-space-end" => { move_word_end_big(app); }
+        "top-line" => { move_to_screen_top(app); }
+        "middle-line" => { move_to_screen_middle(app); }
+        "bottom-line" => { move_to_screen_bottom(app); }
+        "history-top" => { scroll_to_top(app); }
+        "history-bottom" => { scroll_to_bottom(app); }
+        "halfpage-up" => {
+            let half = app.windows.get(app.active_idx)
+                .and_then(|w| active_pane(&w.root, &w.active_path))
+                .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
+            scroll_copy_up(app, half);
+        }
+        "halfpage-down" => {
+            let half = app.windows.get(app.active_idx)
+                .and_then(|w| active_pane(&w.root, &w.active_path))
+                .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
+            scroll_copy_down(app, half);
+        }
+        "page-up" => { scroll_copy_up(app, 20); }
+        "page-down" => { scroll_copy_down(app, 20); }
+        "scroll-up" => { scroll_copy_up(app, 1); }
+        "scroll-down" => { scroll_copy_down(app, 1); }
+        "search-forward" | "search-forward-incremental" => {
+            app.mode = Mode::CopySearch { input: String::new(), forward: true };
+        }
+        "search-backward" | "search-backward-incremental" => {
+            app.mode = Mode::CopySearch { input: String::new(), forward: false };
+        }
+        "search-again" => { search_next(app); }
+        "search-reverse" => { search_prev(app); }
+        "copy-end-of-line" => { let _ = copy_end_of_line(app); app.mode = Mode::Passthrough; app.copy_scroll_offset = 0; app.copy_pos = None; }
+        "select-word" => { select_inner_word(app); }
+        "other-end" => {
+            if let (Some(a), Some(p)) = (app.copy_anchor, app.copy_pos) {
+                app.copy_anchor = Some(p);
+                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
+                app.copy_pos = Some(a);
+            }
+        }
+        "clear-selection" => {
+            app.copy_anchor = None;
+            app.copy_selection_mode = crate::types::SelectionMode::Char;
+        }
+        "append-selection" => {
+            // Append to existing buffer instead of replacing
+            let _ = yank_to_register(app);
+            append_last_yank(app);
+        }
+        "append-selection-and-cancel" => {
+            let _ = yank_to_register(app);
+            append_last_yank(app);
+            app.mode = Mode::Passthrough;
+            app.copy_scroll_offset = 0;
+            app.copy_pos = None;
+        }
+        "copy-line" => {
+            // Select entire current line and yank
+            if let Some((r, _)) = get_copy_pos(app) {
+                app.copy_anchor = Some((r, 0));
+                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
+                app.copy_selection_mode = crate::types::SelectionMode::Line;
+                let cols = app.windows.get(app.active_idx)
+                    .and_then(|w| active_pane(&w.root, &w.active_path))
+                    .map(|p| p.last_cols).unwrap_or(80);
+                app.copy_pos = Some((r, cols.saturating_sub(1)));
+                let _ = yank_to_register(app);
+            }
+            app.mode = Mode::Passthrough;
+            app.copy_scroll_offset = 0;
+            app.copy_pos = None;
+        }
+        s if s.starts_with("goto-line") => {
+            // goto-line <N> — jump to line N in scrollback
+            let n = s.strip_prefix("goto-line").unwrap_or("").trim()
+                .parse::<u16>().unwrap_or(0);
+            app.copy_pos = Some((n, 0));
+        }
+        "jump-forward" => { app.copy_find_char_pending = Some(0); }
+        "jump-backward" => { app.copy_find_char_pending = Some(1); }
+        "jump-to-forward" => { app.copy_find_char_pending = Some(2); }
+        "jump-to-backward" => { app.copy_find_char_pending = Some(3); }
+        "jump-again" => {
+            // Repeat last find-char in same direction
+            // We'd need to store last char; for now emit the pending
+        }
+        "jump-reverse" => {
+            // Repeat last find-char in reverse direction
+        }
+        "next-paragraph" => {
+            move_next_paragraph(app);
+        }
+        "previous-paragraph" => {
+            move_prev_paragraph(app);
+        }
+        "next-matching-bracket" => {
+            move_matching_bracket(app);
+        }
+        "stop-selection" => {
+            // Keep cursor position but stop extending selection
+            app.copy_anchor = None;
+        }
+        _ => {} // ignore unknown copy-mode commands
+    }
+    // Motions keep the cursor in the unwrapped view; scrolling moves the view alone
+    if !matches!(cmd, "scroll-left" | "scroll-right") { follow_copy_cursor(app); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SelectionMode;
+
+    fn copy_state(anchor: (u16, u16), anchor_scroll: usize, pos: (u16, u16), scroll: usize) -> CopyModeState {
+        CopyModeState {
+            anchor: Some(anchor), anchor_scroll_offset: anchor_scroll, pos: Some(pos), scroll_offset: scroll, hscroll: None,
+            selection_mode: SelectionMode::Char, search_query: String::new(), count: None,
+            search_matches: Vec::new(), search_idx: 0, search_forward: true,
+            find_char_pending: None, text_object_pending: None, register_pending: false, register: None,
+            in_search: false, search_input: String::new(), search_input_forward: true,
+        }
+    }
+
+    /// 10x20 screen over 30 numbered lines, scrolled back `scroll` lines.
+    fn numbered_screen(scroll: usize) -> vt100::Parser {
+        let mut p = vt100::Parser::new(10, 20, 100);
+        for i in 0..30 { p.process(format!("line {:02}\r\n", i).as_bytes()); }
+        p.screen_mut().set_scrollback(scroll);
+        p
+    }
+
+    fn row_text(p: &vt100::Parser, row: u16) -> String {
+        p.screen().rows(0, p.screen().size().1).nth(row as usize).unwrap_or_default().trim_end().to_string()
+    }
+
+    /// Position in (absolute line, column) order; abs grows toward live output.
+    fn abs(pos: (u16, u16), scroll: usize) -> (i64, u16) { (pos.0 as i64 - scroll as i64, pos.1) }
+
+    fn assert_in_bounds(s: &CopyModeState, rows: u16, cols: u16) {
+        let (r, c) = s.pos.unwrap();
+        assert!(r < rows && c < cols, "cursor {:?} outside {}x{}", s.pos, rows, cols);
+        if let Some((ar, ac)) = s.anchor {
+            assert!(ar as i64 - (s.anchor_scroll_offset as i64) < rows as i64 && ac < cols, "anchor {:?} outside", s.anchor);
+            assert!(abs((ar, ac), s.anchor_scroll_offset) <= abs(s.pos.unwrap(), s.scroll_offset), "selection order flipped");
+        }
+        for &(line, c0, c1) in &s.search_matches { assert!(line < rows as i64 && c0 <= c1 && c1 < cols); }
+    }
+
+    fn lines(p: &mut vt100::Parser, s: Option<i32>, e: Option<i32>) -> Vec<String> {
+        let mut out = Vec::new();
+        for_each_line(p, s, e, |screen, r| {
+            out.push(screen.rows(0, 20).nth(r as usize).unwrap_or_default().trim_end().to_string());
+        });
+        out
+    }
+
+    #[test]
+    fn lines_are_numbered_back_into_the_history() {
+        let mut p = numbered_screen(4);
+        assert_eq!(history_size(&mut p), 21);
+        assert_eq!(lines(&mut p, Some(-3), Some(1)), ["line 18", "line 19", "line 20", "line 21", "line 22"]);
+        assert_eq!(lines(&mut p, Some(i32::MIN), Some(-20)), ["line 00", "line 01"]);
+        assert_eq!(lines(&mut p, Some(8), None), ["line 29", ""]);
+        // The pane's own view is untouched
+        assert_eq!(p.screen().scrollback(), 4);
+
+        // A smaller history-limit keeps the newest history and the screen
+        set_history_limit(&mut p, 5);
+        assert_eq!(history_size(&mut p), 5);
+        assert_eq!(lines(&mut p, Some(i32::MIN), Some(0)), ["line 16", "line 17", "line 18", "line 19", "line 20", "line 21"]);
+        assert_eq!(p.screen().cursor_position(), (9, 0));
+        p.process(b"more\r\n");
+        assert_eq!(lines(&mut p, Some(-5), Some(-5)), ["line 17"]);
+    }
+
+    #[test]
+    fn capture_joins_wrapped_lines() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        {
+            // A parser of our own, out of reach of the shell's output
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            let mut parser = vt100::Parser::new(3, 10, 100);
+            parser.process(b"\x1b[31mabcdefghijKL\x1b[0mM\r\nshort   ");
+            p.term = std::sync::Arc::new(std::sync::Mutex::new(parser));
+            p.last_cols = 10;
+        }
+        assert_eq!(capture_active_pane_range(&mut app, None, None, false).unwrap().unwrap(), "abcdefghij\nKLM\nshort\n");
+        assert_eq!(capture_active_pane_range(&mut app, None, None, true).unwrap().unwrap(), "abcdefghijKLM\nshort\n");
+        let styled = capture_active_pane_styled(&mut app, None, Some(1), true).unwrap().unwrap();
+        assert_eq!(styled, "\x1b[0;31mabcdefghijKL\x1b[0mM\x1b[0m\n");
+        assert_eq!(capture_active_pane_range(&mut app, Some(0), Some(0), true).unwrap().unwrap(), "abcdefghij\n");
+        crate::tree::kill_all_children(&mut app.windows[0].root);
+    }
+
+    #[test]
+    fn shrinking_keeps_the_cursor_on_its_line() {
+        let mut p = numbered_screen(5);
+        let mut s = copy_state((2, 3), 5, (8, 15), 5);
+        s.search_matches = vec![(-4, 2, 12), (7, 0, 6)];
+        let cursor_line = row_text(&p, 8);
+        let anchor_line = row_text(&p, 2);
+
+        p.screen_mut().set_size(6, 10);
+        assert!(!fit_copy_state(&mut s, 6, 10));
+        p.screen_mut().set_scrollback(s.scroll_offset);
+        assert_in_bounds(&s, 6, 10);
+        // The view scrolled toward live output so the cursor's line stays visible
+        assert_eq!(s.pos, Some((5, 9)));
+        assert_eq!(row_text(&p, 5), cursor_line);
+        // The anchor (now above the view) still names the same line
+        let (ar, _) = s.anchor.unwrap();
+        p.screen_mut().set_scrollback(s.anchor_scroll_offset);
+        assert_eq!(row_text(&p, ar), anchor_line);
+        // Matches keep their lines; the one on a line cut off below is gone
+        assert_eq!(s.search_matches, vec![(-4, 2, 9)]);
+    }
+
+    #[test]
+    fn growing_leaves_positions_alone() {
+        let mut p = numbered_screen(5);
+        let mut s = copy_state((2, 3), 5, (8, 15), 5);
+        let cursor_line = row_text(&p, 8);
+        p.screen_mut().set_size(14, 30);
+        assert!(!fit_copy_state(&mut s, 14, 30));
+        p.screen_mut().set_scrollback(s.scroll_offset);
+        assert_in_bounds(&s, 14, 30);
+        assert_eq!((s.anchor, s.pos, s.scroll_offset), (Some((2, 3)), Some((8, 15)), 5));
+        assert_eq!(row_text(&p, 8), cursor_line);
+    }
+
+    #[test]
+    fn selection_is_cleared_when_its_anchor_line_is_cut_off() {
+        // Live view, anchor below the cursor near the bottom edge
+        let mut s = copy_state((8, 2), 0, (3, 4), 0);
+        assert!(fit_copy_state(&mut s, 5, 20));
+        assert_eq!(s.anchor, None);
+        assert_in_bounds(&s, 5, 20);
+        // Cursor on a cut-off line, anchor above: clamped, order kept
+        let mut s = copy_state((1, 2), 0, (9, 19), 0);
+        assert!(!fit_copy_state(&mut s, 4, 8));
+        assert_eq!((s.anchor, s.pos), (Some((1, 2)), Some((3, 7))));
+        assert_in_bounds(&s, 4, 8);
+    }
+
+    fn scrollback_len(p: &mut vt100::Parser) -> usize {
+        p.screen_mut().set_scrollback(usize::MAX);
+        let n = p.screen().scrollback();
+        p.screen_mut().set_scrollback(0);
+        n
+    }
+
+    #[test]
+    fn clear_history_keeps_the_screen_and_cursor() {
+        let mut p = numbered_screen(4);
+        p.process(b"\x1b[3;5H\x1b[?1h");
+        p.screen_mut().set_scrollback(0);
+        let (screen, cursor) = (p.screen().contents(), p.screen().cursor_position());
+        assert_eq!(scrollback_len(&mut p), 21);
+
+        clear_scrollback(&mut p, 100, false);
+        assert_eq!(p.screen().contents(), screen);
+        assert_eq!(p.screen().cursor_position(), cursor);
+        assert!(p.screen().application_cursor());
+        // Nothing left for copy mode to scroll up into
+        assert_eq!(scrollback_len(&mut p), 0);
+        p.screen_mut().set_scrollback(10);
+        assert_eq!(p.screen().contents(), screen);
+        // New output scrolls into the fresh history as usual
+        p.process(b"\x1b[10;1H\r\nmore");
+        assert_eq!(scrollback_len(&mut p), 1);
+    }
+
+    #[test]
+    fn clear_history_leaves_the_alternate_screen_up() {
+        let mut p = numbered_screen(0);
+        let main = p.screen().contents();
+        p.process(b"\x1b[?1049h\x1b[2;2Hfull-screen app");
+        clear_scrollback(&mut p, 100, false);
+        assert!(p.screen().alternate_screen());
+        assert_eq!(row_text(&p, 1), " full-screen app");
+        assert_eq!(scrollback_len(&mut p), 0);
+        // Leaving it shows the main screen as it was, cursor restored
+        p.process(b"\x1b[?1049l");
+        assert_eq!(p.screen().contents(), main);
+        assert_eq!(p.screen().cursor_position(), (9, 0));
+
+        // -H clears what is visible too, still on the alternate screen
+        p.process(b"\x1b[?1049hx");
+        clear_scrollback(&mut p, 100, true);
+        assert!(p.screen().alternate_screen());
+        assert_eq!(p.screen().contents(), "");
+    }
+
+    #[test]
+    fn a_respawned_pane_keeps_the_dead_programs_output() {
+        let mut old = numbered_screen(3);
+        old.process(b"\x1b[31mfatal: boom\x1b[m");
+        let mut p = respawn_parser(&mut old, 100, "-- respawned --");
+        assert_eq!(p.screen().contents(), "");
+        assert_eq!(p.screen().cursor_position(), (0, 0));
+        let history = lines(&mut p, Some(i32::MIN), Some(-1));
+        assert_eq!(history[0], "line 00");
+        assert_eq!(history[history.len() - 3..], ["line 29", "fatal: boom", "-- respawned --"]);
+        // Colours come along
+        p.screen_mut().set_scrollback(2);
+        assert_eq!(p.screen().cell(0, 0).unwrap().fgcolor(), vt100::Color::Idx(1));
+
+        // Dead in the alternate screen: the main screen is what is kept
+        let mut old = vt100::Parser::new(10, 20, 100);
+        old.process(b"$ vim\r\n\x1b[?1049h\x1b[2;2Hediting");
+        let mut p = respawn_parser(&mut old, 100, "-- respawned --");
+        assert!(!p.screen().alternate_screen());
+        assert_eq!(lines(&mut p, Some(i32::MIN), Some(-1)), ["$ vim", "-- respawned --"]);
+    }
+
+    /// 4x10 screen: one logical line wrapped over three rows, then a short one.
+    fn long_line_screen() -> vt100::Parser {
+        let mut p = vt100::Parser::new(4, 10, 0);
+        p.process(b"0123456789abcdefghijKLMNO\r\nshort");
+        p
+    }
+
+    fn view_rows(p: &vt100::Parser, off: usize) -> Vec<String> {
+        let view = HScrollView::new(p.screen(), 4, 10, off);
+        (0..4).map(|r| {
+            let row: String = (0..10).map(|c| match view.cell(r, c) {
+                ViewCell::Screen(sr, sc) => p.screen().cell(sr, sc).map(|cell| cell.contents().to_string()).filter(|t| !t.is_empty()).unwrap_or(" ".into()),
+                ViewCell::Edge(ch) => ch.to_string(),
+                ViewCell::Blank => " ".to_string(),
+            }).collect();
+            row.trim_end().to_string()
+        }).collect()
+    }
+
+    #[test]
+    fn unwrapped_view_slices_long_lines_with_edge_marks() {
+        let p = long_line_screen();
+        assert_eq!(view_rows(&p, 0), ["012345678>", "short", "", ""]);
+        assert_eq!(view_rows(&p, 8), ["<9abcdefg>", "<", "", ""]);
+        assert_eq!(view_rows(&p, 15), ["<ghijKLMNO", "<", "", ""]);
+        let view = HScrollView::new(p.screen(), 4, 10, 8);
+        assert_eq!(view.max_offset(), 15);
+        // 'c' sits on the second screen row, logical column 12
+        assert_eq!(view.logical_pos((1, 2)), Some((0, 12)));
+        assert_eq!(view.view_pos((1, 2)), Some((0, 4)));
+        assert_eq!(view.view_pos((3, 1)), None);
+        assert_eq!(view.clamped_pos((0, 2)), (0, 0));
+        assert_eq!(view.clamped_pos((2, 4)), (0, 9));
+    }
+
+    #[test]
+    fn yanks_ignore_the_horizontal_view_offset() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        {
+            // Swap in a synthetic screen; the shell keeps writing to the old one
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            p.term = std::sync::Arc::new(std::sync::Mutex::new(long_line_screen()));
+            p.last_rows = 4;
+            p.last_cols = 10;
+        }
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((0, 7));
+        run_copy_command(&mut app, "begin-selection");
+        // l past the right edge runs on into the wrapped row and unwraps the view
+        for _ in 0..6 { run_copy_command(&mut app, "cursor-right"); }
+        assert_eq!(app.copy_pos, Some((1, 3)));
+        assert_eq!(app.copy_hscroll, Some(4));
+        run_copy_command(&mut app, "copy-selection -x");
+        let yanked = app.paste_buffers.top().cloned().unwrap();
+        assert_eq!(yanked.replace('\n', ""), "789abcd");
+        // Scrolling the view leaves the cursor and the selection alone
+        run_copy_command(&mut app, "scroll-right");
+        run_copy_command(&mut app, "scroll-right");
+        assert_eq!((app.copy_pos, app.copy_hscroll), (Some((1, 3)), Some(14)));
+        run_copy_command(&mut app, "copy-selection");
+        assert_eq!(app.paste_buffers.top(), Some(&yanked));
+        // The next motion brings the cursor back into view
+        run_copy_command(&mut app, "cursor-left");
+        assert_eq!(app.copy_hscroll, Some(12));
+        run_copy_command(&mut app, "scroll-left");
+        assert_eq!(app.copy_hscroll, Some(7));
+        exit_copy_mode(&mut app);
+        assert_eq!(app.copy_hscroll, None);
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn block_rows_are_cut_to_the_block_width() {
+        let mut p = vt100::Parser::new(3, 12, 0);
+        p.process("ab\u{4e2d}cd\r\nx\u{6587}\u{5b57}yz\r\n12".as_bytes());
+        let s = p.screen();
+        // Row 0: a b 中中 c d; the wide character starts inside columns 1..=2
+        assert_eq!(rect_row(s, 0, 1, 2), "b\u{4e2d}");
+        // Row 1: x 文文 字字 y; column 2 is the second half of 文
+        assert_eq!(rect_row(s, 1, 2, 4), " \u{5b57}");
+        // Short rows are padded to the block width
+        assert_eq!(rect_row(s, 2, 1, 4), "2   ");
+    }
+
+    #[test]
+    fn block_selection_toggles_like_vim() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            let mut screen = vt100::Parser::new(4, 10, 0);
+            screen.process(b"abcdef\r\nghijkl\r\nmn");
+            p.term = std::sync::Arc::new(std::sync::Mutex::new(screen));
+            p.last_rows = 4;
+            p.last_cols = 10;
+        }
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((0, 1));
+        // C-v with nothing selected starts a block; v turns it into a
+        // character selection over the same span and C-v back again
+        run_copy_command(&mut app, "rectangle-toggle");
+        assert_eq!((app.copy_anchor, app.copy_selection_mode), (Some((0, 1)), SelectionMode::Rect));
+        run_copy_command(&mut app, "cursor-down");
+        run_copy_command(&mut app, "cursor-down");
+        run_copy_command(&mut app, "cursor-right");
+        run_copy_command(&mut app, "begin-selection");
+        assert_eq!((app.copy_anchor, app.copy_selection_mode), (Some((0, 1)), SelectionMode::Char));
+        run_copy_command(&mut app, "rectangle-toggle");
+        assert_eq!(app.copy_selection_mode, SelectionMode::Rect);
+
+        // -x keeps the selection; plain copy-selection clears it
+        run_copy_command(&mut app, "copy-selection -x");
+        assert_eq!(app.paste_buffers.top().map(String::as_str), Some("bc\nhi\nn "));
+        assert!(app.copy_anchor.is_some());
+        run_copy_command(&mut app, "copy-selection");
+        assert_eq!(app.paste_buffers.len(), 2);
+        assert_eq!((app.copy_anchor, app.copy_selection_mode), (None, SelectionMode::Char));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn incremental_search_waits_for_typing_to_pause() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            p.term = std::sync::Arc::new(std::sync::Mutex::new(numbered_screen(0)));
+            p.last_rows = 10;
+            p.last_cols = 20;
+        }
+        enter_copy_mode(&mut app);
+        app.mode = Mode::CopySearch { input: String::new(), forward: true };
+        let t0 = Instant::now();
+        let step = crate::pattern::SEARCH_DEBOUNCE / 4;
+        for (i, ch) in "LINE 2".chars().enumerate() {
+            let now = t0 + step * i as u32;
+            if let Mode::CopySearch { ref mut input, .. } = app.mode { input.push(ch); }
+            schedule_incremental_search(&mut app, now);
+            // Keystrokes closer together than the debounce never scan
+            assert!(!run_incremental_search_if_due(&mut app, now + step / 2));
+        }
+        let last = t0 + step * 5;
+        assert!(run_incremental_search_if_due(&mut app, last + crate::pattern::SEARCH_DEBOUNCE));
+        // "line 20" is in the history; the screen holds "line 21".."line 29"
+        // above an empty prompt line.  Nothing follows the cursor, so the
+        // search wraps to the top and scrolls "line 20" to mid-screen.
+        assert_eq!(app.copy_search_matches.len(), 10);
+        assert_eq!((app.copy_search_idx, app.copy_scroll_offset, app.copy_pos), (0, 6, Some((5, 0))));
+        assert!(matches!(app.mode, Mode::CopySearch { .. }));
+        assert!(!run_incremental_search_if_due(&mut app, last + crate::pattern::SEARCH_DEBOUNCE * 2));
+
+        // A scan that outlives pattern-timeout ends with a message, not a hang
+        app.pattern_timeout_ms = 0;
+        search_copy_mode(&mut app, "line", true);
+        assert!(app.copy_search_matches.is_empty());
+        assert_eq!(app.pending_message.as_deref(), Some("pattern too slow"));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn search_finds_regex_matches_through_the_history() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            p.term = std::sync::Arc::new(std::sync::Mutex::new(numbered_screen(0)));
+            p.last_rows = 10;
+            p.last_cols = 20;
+        }
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((9, 0));
+        app.copy_search_forward = false;
+
+        // "line 10".."line 19" are all in the history (lines -11 to -2); a
+        // backward search starts at the nearest one above the cursor
+        search_copy_mode(&mut app, "LINE 1[0-9]", false);
+        assert_eq!(app.copy_search_matches.len(), 10);
+        assert_eq!(app.copy_search_matches[0], (-11, 0, 6));
+        assert_eq!(search_position(&app), Some((10, 10)));
+        jump_to_search_match(&mut app);
+        assert_eq!((app.copy_scroll_offset, app.copy_pos), (7, Some((5, 0))));
+
+        // n keeps going up, N comes back down, both wrapping around
+        search_next(&mut app);
+        assert_eq!(search_position(&app), Some((9, 10)));
+        assert_eq!((app.copy_scroll_offset, app.copy_pos), (7, Some((4, 0))));
+        search_prev(&mut app);
+        search_prev(&mut app);
+        assert_eq!(search_position(&app), Some((1, 10)));
+        assert_eq!((app.copy_scroll_offset, app.copy_pos), (16, Some((5, 0))));
+
+        // Not a valid regex: searched for as plain text
+        search_copy_mode(&mut app, "line 0(", true);
+        assert!(app.copy_search_matches.is_empty());
+        assert_eq!(app.pending_message, None);
+        search_copy_mode(&mut app, "e 2", true);
+        assert_eq!(search_position(&app), Some((1, 10)));
+        assert_eq!(app.copy_search_matches[0], (-1, 3, 5));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+}
@@ -0,0 +1,227 @@
+//! Delta frames: an attached client that asks with `dump-state -D` gets,
+//! after its first whole frame, only the rows of each pane that changed
+//! since the frame before.
+//!
+//! The connection's writer keeps the rows it last sent per pane and
+//! replaces a leaf's `rows_v2` with `"rows_delta":[{"y":12,"runs":[...]}]`;
+//! the frame is marked `"delta":true`.  Packed rows (`rows_bin`, see
+//! crate::cells) go the same way as `{"y":12,"bin":"..."}`.  The client keeps the rows it last
+//! received and rebuilds the whole frame from them.  A pane it has no rows
+//! for, or whose size differs, makes it ask for a whole frame (`-F`)
+//! instead.  One-shot connections and low-bandwidth clients always get
+//! whole frames.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// Handed to a connection's writer ahead of the answer to `dump-state -F`:
+/// the next frame goes out whole.  It is never written to the client.
+pub const FULL_FRAME: &str = "%full-frame";
+
+/// The rows of every pane in the last frame, by pane id, with the pane's
+/// (rows, cols) and the field they came in.
+#[derive(Default)]
+pub struct FrameRows {
+    panes: HashMap<u64, (Shape, Vec<Value>)>,
+}
+
+/// (rows, cols) and `rows_v2` or `rows_bin`: a pane's rows diff only
+/// against rows of the same shape.
+type Shape = ((u64, u64), &'static str);
+
+/// The field a leaf's rows are in.
+fn rows_field(leaf: &Map<String, Value>) -> &'static str {
+    if leaf.contains_key("rows_bin") { "rows_bin" } else { "rows_v2" }
+}
+
+/// A changed row as it goes into `rows_delta`.
+fn change(y: usize, row: &Value, field: &str) -> Value {
+    if field == "rows_bin" {
+        serde_json::json!({ "y": y, "bin": row })
+    } else {
+        serde_json::json!({ "y": y, "runs": row.get("runs").cloned().unwrap_or(Value::Null) })
+    }
+}
+
+/// The row a `rows_delta` entry carries.
+fn changed_row(ch: &Value) -> Value {
+    match ch.get("bin") {
+        Some(bin) => bin.clone(),
+        None => serde_json::json!({ "runs": ch.get("runs").cloned().unwrap_or(Value::Null) }),
+    }
+}
+
+/// Call `f` on every leaf of a `layout` tree.
+fn for_each_leaf(node: &mut Value, f: &mut impl FnMut(&mut Map<String, Value>)) {
+    let Some(obj) = node.as_object_mut() else { return };
+    if obj.get("type").and_then(Value::as_str) == Some("leaf") {
+        f(obj);
+    } else if let Some(children) = obj.get_mut("children").and_then(Value::as_array_mut) {
+        for c in children { for_each_leaf(c, f); }
+    }
+}
+
+/// A leaf's id and shape.
+fn leaf_key(leaf: &Map<String, Value>) -> Option<(u64, Shape)> {
+    let n = |k: &str| leaf.get(k).and_then(Value::as_u64);
+    Some((n("id")?, ((n("rows")?, n("cols")?), rows_field(leaf))))
+}
+
+impl FrameRows {
+    /// Forget every pane: the next frame is sent or taken whole.
+    pub fn clear(&mut self) {
+        self.panes.clear();
+    }
+
+    /// Server side: the delta of `frame` against the frame sent before,
+    /// or None to send `frame` as it is (nothing to diff against, or not
+    /// a frame).
+    pub fn encode(&mut self, frame: &str) -> Option<String> {
+        let Ok(mut v) = serde_json::from_str::<Value>(frame) else {
+            self.clear();
+            return None;
+        };
+        let mut next = HashMap::new();
+        let mut delta = false;
+        if let Some(layout) = v.get_mut("layout") {
+            for_each_leaf(layout, &mut |leaf| {
+                let Some((id, shape)) = leaf_key(leaf) else { return };
+                let (size, field) = shape;
+                let rows = match leaf.get(field).and_then(Value::as_array) {
+                    Some(r) if r.len() as u64 == size.0 => r.clone(),
+                    _ => return,
+                };
+                if let Some((_, old)) = self.panes.get(&id).filter(|(s, _)| *s == shape) {
+                    let changed: Vec<Value> = rows.iter().zip(old).enumerate()
+                        .filter(|(_, (new, old))| new != old)
+                        .map(|(y, (new, _))| change(y, new, field))
+                        .collect();
+                    leaf.insert(field.into(), Value::Array(Vec::new()));
+                    leaf.insert("rows_delta".into(), Value::Array(changed));
+                    delta = true;
+                }
+                next.insert(id, (shape, rows));
+            });
+        }
+        self.panes = next;
+        if !delta { return None; }
+        v["delta"] = Value::Bool(true);
+        serde_json::to_string(&v).ok()
+    }
+
+    /// Client side: the whole frame `frame` stands for, remembering its
+    /// rows.  None when a delta does not fit the rows kept (ask for a
+    /// whole frame).
+    pub fn apply(&mut self, frame: String) -> Option<String> {
+        let Ok(mut v) = serde_json::from_str::<Value>(&frame) else { return Some(frame) };
+        let delta = v.get("delta").and_then(Value::as_bool) == Some(true);
+        let mut next = HashMap::new();
+        let mut fits = true;
+        if let Some(layout) = v.get_mut("layout") {
+            for_each_leaf(layout, &mut |leaf| {
+                let Some((id, shape)) = leaf_key(leaf) else { return };
+                let field = shape.1;
+                let rows = match leaf.remove("rows_delta") {
+                    Some(Value::Array(changes)) => {
+                        let Some((_, old)) = self.panes.get(&id).filter(|(s, _)| *s == shape) else {
+                            fits = false;
+                            return;
+                        };
+                        let mut rows = old.clone();
+                        for ch in changes {
+                            let y = ch.get("y").and_then(Value::as_u64).unwrap_or(u64::MAX) as usize;
+                            let Some(row) = rows.get_mut(y) else { fits = false; return };
+                            *row = changed_row(&ch);
+                        }
+                        leaf.insert(field.into(), Value::Array(rows.clone()));
+                        rows
+                    }
+                    _ => leaf.get(field).and_then(Value::as_array).cloned().unwrap_or_default(),
+                };
+                next.insert(id, (shape, rows));
+            });
+        }
+        if !fits {
+            self.clear();
+            return None;
+        }
+        self.panes = next;
+        if !delta { return Some(frame); }
+        if let Some(obj) = v.as_object_mut() { obj.remove("delta"); }
+        serde_json::to_string(&v).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> String {
+        let rows: Vec<Value> = rows.iter().map(|t| serde_json::json!({ "runs": [{ "text": t, "width": t.len() }] })).collect();
+        serde_json::json!({
+            "layout": { "type": "split", "kind": "Horizontal", "sizes": [50, 50], "children": [
+                { "type": "leaf", "id": 1, "rows": rows.len(), "cols": 10, "rows_v2": rows },
+                { "type": "leaf", "id": 2, "rows": 1, "cols": 10, "rows_v2": [{ "runs": [] }] },
+            ]},
+            "status_left": "s",
+        }).to_string()
+    }
+
+    #[test]
+    fn only_changed_rows_are_sent_and_rebuilt() {
+        let (mut server, mut client) = (FrameRows::default(), FrameRows::default());
+        let first = frame(&["a", "b", "c"]);
+        assert!(server.encode(&first).is_none(), "the first frame goes out whole");
+        assert_eq!(client.apply(first.clone()).as_deref(), Some(first.as_str()));
+
+        let second = frame(&["a", "B", "c"]);
+        let delta = server.encode(&second).expect("a delta");
+        let v: Value = serde_json::from_str(&delta).unwrap();
+        assert_eq!(v["delta"], true);
+        let leaves = &v["layout"]["children"];
+        assert_eq!(leaves[0]["rows_delta"].as_array().unwrap().len(), 1);
+        assert_eq!(leaves[0]["rows_delta"][0]["y"], 1);
+        assert_eq!(leaves[1]["rows_delta"].as_array().unwrap().len(), 0);
+
+        let rebuilt = client.apply(delta).expect("fits");
+        let (a, b): (Value, Value) = (serde_json::from_str(&rebuilt).unwrap(), serde_json::from_str(&second).unwrap());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn resized_pane_is_sent_whole_and_unknown_pane_asks_for_a_frame() {
+        let mut server = FrameRows::default();
+        server.encode(&frame(&["a", "b"]));
+        let delta = server.encode(&frame(&["a", "b", "c"])).unwrap();
+        let v: Value = serde_json::from_str(&delta).unwrap();
+        assert_eq!(v["layout"]["children"][0]["rows_v2"].as_array().unwrap().len(), 3);
+        assert!(v["layout"]["children"][0].get("rows_delta").is_none());
+
+        // A client that never saw the first frame cannot use the delta
+        let mut client = FrameRows::default();
+        assert!(client.apply(delta).is_none());
+    }
+
+    #[test]
+    fn packed_rows_are_diffed_too() {
+        let packed = |rows: &[&str]| serde_json::json!({
+            "layout": { "type": "leaf", "id": 4, "rows": rows.len(), "cols": 10, "rows_bin": rows },
+        }).to_string();
+        let (mut server, mut client) = (FrameRows::default(), FrameRows::default());
+        let first = packed(&["AQ==", "Ag=="]);
+        assert!(server.encode(&first).is_none());
+        client.apply(first);
+        let second = packed(&["AQ==", "Aw=="]);
+        let delta = server.encode(&second).unwrap();
+        let v: Value = serde_json::from_str(&delta).unwrap();
+        assert_eq!(v["layout"]["rows_delta"], serde_json::json!([{ "y": 1, "bin": "Aw==" }]));
+        let rebuilt: Value = serde_json::from_str(&client.apply(delta).unwrap()).unwrap();
+        assert_eq!(rebuilt, serde_json::from_str::<Value>(&second).unwrap());
+        // A switch between JSON and packed rows sends the pane whole
+        let json = serde_json::json!({
+            "layout": { "type": "leaf", "id": 4, "rows": 2, "cols": 10, "rows_v2": [{ "runs": [] }, { "runs": [] }] },
+        }).to_string();
+        assert!(server.encode(&json).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! `pane-encoding`: how a pane's output bytes are read before the terminal
+//! parser sees them.
+//!
+//! Programs are expected to write UTF-8, but some Windows tools still write
+//! in the console's legacy code page (an old `chcp`, old build tools), and
+//! their bytes above 0x7F come out as replacement characters.  The option
+//! is `utf8` (the default), `cp437` or `cp1252`, and like
+//! `highlight-pattern` it can be set for the session, a window (`-w`) or a
+//! pane (`-p`).
+//!
+//! Each pane's reader thread runs its output through a `Decoder`.  In UTF-8
+//! a sequence split across two reads is held back until the rest arrives,
+//! and an invalid sequence becomes one U+FFFD, decoding starting again at
+//! the next byte that can begin a character, so a stray byte never eats
+//! the text after it.  The code pages map each byte above 0x7F through a
+//! table; the bytes below, escape sequences included, pass through as they
+//! are.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::types::{AppState, Node, Pane, Window};
+
+/// How a pane's output is encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Encoding {
+    #[default]
+    Utf8 = 0,
+    Cp437 = 1,
+    Cp1252 = 2,
+}
+
+impl Encoding {
+    /// The option value, None when it names no encoding.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Some(Encoding::Utf8),
+            "cp437" | "437" => Some(Encoding::Cp437),
+            "cp1252" | "1252" => Some(Encoding::Cp1252),
+            _ => None,
+        }
+    }
+
+    fn from_u8(n: u8) -> Self {
+        match n {
+            1 => Encoding::Cp437,
+            2 => Encoding::Cp1252,
+            _ => Encoding::Utf8,
+        }
+    }
+
+    /// The encoding a reader thread was last told to use.
+    pub fn load(shared: &AtomicU8) -> Self {
+        Self::from_u8(shared.load(Ordering::Relaxed))
+    }
+
+    fn high_byte(self, b: u8) -> char {
+        match self {
+            Encoding::Cp437 => CP437_HIGH[(b - 0x80) as usize],
+            Encoding::Cp1252 if b < 0xA0 => CP1252_C1[(b - 0x80) as usize],
+            _ => b as char,
+        }
+    }
+}
+
+/// Turns one pane's output into UTF-8, a read at a time.
+#[derive(Default)]
+pub struct Decoder {
+    /// The start of a UTF-8 sequence the last read cut off
+    partial: Vec<u8>,
+}
+
+impl Decoder {
+    /// `bytes` as UTF-8, borrowed when they already are valid UTF-8.
+    pub fn decode<'a>(&mut self, encoding: Encoding, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(bytes)
+        } else {
+            let mut joined = std::mem::take(&mut self.partial);
+            joined.extend_from_slice(bytes);
+            Cow::Owned(joined)
+        };
+        match encoding {
+            Encoding::Utf8 => {
+                if std::str::from_utf8(&input).is_ok() { return input; }
+                let mut out = Vec::with_capacity(input.len() + 8);
+                let mut rest = &input[..];
+                loop {
+                    match std::str::from_utf8(rest) {
+                        Ok(s) => { out.extend_from_slice(s.as_bytes()); break; }
+                        Err(e) => {
+                            let (good, bad) = rest.split_at(e.valid_up_to());
+                            out.extend_from_slice(good);
+                            match e.error_len() {
+                                Some(n) => {
+                                    out.extend_from_slice("\u{FFFD}".as_bytes());
+                                    rest = &bad[n..];
+                                }
+                                // Cut off at the end: wait for the rest
+                                None => { self.partial = bad.to_vec(); break; }
+                            }
+                        }
+                    }
+                }
+                Cow::Owned(out)
+            }
+            _ if input.is_ascii() => input,
+            _ => {
+                let mut out = String::with_capacity(input.len() + 16);
+                for &b in input.iter() {
+                    out.push(if b < 0x80 { b as char } else { encoding.high_byte(b) });
+                }
+                Cow::Owned(out.into_bytes())
+            }
+        }
+    }
+}
+
+/// The pane's `pane-encoding`: its own, else its window's, else the
+/// session's.
+pub fn effective(app: &AppState, win: Option<&Window>, pane: Option<&Pane>) -> Encoding {
+    pane.and_then(|p| p.pane_encoding.as_deref())
+        .or(win.and_then(|w| w.pane_encoding.as_deref()))
+        .and_then(Encoding::parse)
+        .or_else(|| Encoding::parse(&app.pane_encoding))
+        .unwrap_or_default()
+}
+
+/// The shared setting for a new pane's reader thread, in window `win` (None
+/// for a new window).
+pub fn for_new_pane(app: &AppState, win: Option<&Window>) -> Arc<AtomicU8> {
+    Arc::new(AtomicU8::new(effective(app, win, None) as u8))
+}
+
+/// Tell every pane's reader thread its encoding, after the option changed.
+pub fn refresh(app: &AppState) {
+    fn walk(app: &AppState, win: &Window, node: &Node) {
+        match node {
+            Node::Leaf(p) => p.encoding.store(effective(app, Some(win), Some(p)) as u8, Ordering::Relaxed),
+            Node::Split { children, .. } => for c in children { walk(app, win, c) },
+        }
+    }
+    for win in &app.windows { walk(app, win, &win.root); }
+}
+
+/// CP437 bytes 0x80 to 0xFF.
+const CP437_HIGH: [char; 128] = [
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}',
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}',
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}',
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}',
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}',
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}',
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}',
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}',
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
+/// CP1252 bytes 0x80 to 0x9F; 0xA0 to 0xFF are Latin-1.  The five bytes
+/// CP1252 leaves undefined decode to U+FFFD.
+const CP1252_C1: [char; 32] = [
+    '\u{20AC}', '\u{FFFD}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{FFFD}', '\u{017D}', '\u{FFFD}',
+    '\u{FFFD}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{FFFD}', '\u{017E}', '\u{0178}',
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_text(decoder: &mut Decoder, encoding: Encoding, reads: &[&[u8]]) -> String {
+        let mut parser = vt100::Parser::new(3, 20, 0);
+        for bytes in reads { parser.process(&decoder.decode(encoding, bytes)); }
+        parser.screen().contents()
+    }
+
+    #[test]
+    fn cp437_box_drawing_renders_as_boxes() {
+        let mut d = Decoder::default();
+        let box_bytes: &[u8] = b"\xc9\xcd\xcd\xbb\r\n\xba\x1b[1mok\x1b[m\xba\r\n\xc8\xcd\xcd\xbc";
+        assert_eq!(screen_text(&mut d, Encoding::Cp437, &[box_bytes]), "╔══╗\n║ok║\n╚══╝");
+        // The same bytes read as UTF-8 are mojibake
+        let garbled = String::from_utf8(Decoder::default().decode(Encoding::Utf8, box_bytes).into_owned()).unwrap();
+        assert!(garbled.contains('\u{FFFD}') && !garbled.contains('╔'), "{garbled:?}");
+        assert_eq!(screen_text(&mut d, Encoding::Cp1252, &[b"caf\xe9 \x93q\x94 \x80"]), "café “q” €");
+    }
+
+    #[test]
+    fn utf8_waits_for_split_sequences_and_resynchronises() {
+        let mut d = Decoder::default();
+        // "é" split across reads, then "漢" likewise
+        assert_eq!(&*d.decode(Encoding::Utf8, b"caf\xc3"), b"caf");
+        assert_eq!(&*d.decode(Encoding::Utf8, b"\xa9 \xe6\xbc"), "é ".as_bytes());
+        assert_eq!(&*d.decode(Encoding::Utf8, b"\xa2!"), "漢!".as_bytes());
+        // A stray lead byte or continuation byte does not swallow what follows
+        assert_eq!(&*d.decode(Encoding::Utf8, b"a\xe6b\x80c"), "a\u{FFFD}b\u{FFFD}c".as_bytes());
+        assert!(matches!(d.decode(Encoding::Utf8, b"plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn settings_follow_pane_window_session() {
+        let mut app = AppState::new("t".to_string());
+        assert_eq!(effective(&app, None, None), Encoding::Utf8);
+        app.pane_encoding = "cp437".to_string();
+        assert_eq!(effective(&app, None, None), Encoding::Cp437);
+        let shared = for_new_pane(&app, None);
+        assert_eq!(Encoding::load(&shared), Encoding::Cp437);
+        // An unknown value falls back to the wider setting
+        app.pane_encoding = "ebcdic".to_string();
+        assert_eq!(effective(&app, None, None), Encoding::Utf8);
+    }
+}
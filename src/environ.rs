@@ -0,0 +1,200 @@
+//! set-environment: variables new panes and shell jobs start with, on top
+//! of the server's own environment.  The global environment (`-g`, what
+//! the config sets for every session) comes first and this session's
+//! overrides it.  A variable marked with `-r` is taken out of a new pane's
+//! environment even if the server has it.
+//!
+//! On attach a client sends its environment, and the variables named by
+//! the `update-environment` option are copied from it into the session's:
+//! set if the client has them, marked for removal if not.
+
+use std::collections::BTreeMap;
+
+use portable_pty::CommandBuilder;
+
+use crate::types::AppState;
+
+/// update-environment unless the config sets it.
+pub const DEFAULT_UPDATE_ENVIRONMENT: &str =
+    "DISPLAY SSH_ASKPASS SSH_AUTH_SOCK SSH_AGENT_PID SSH_CONNECTION WT_SESSION WT_PROFILE_ID";
+
+/// One environment: a value per variable, None for one marked with `-r`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvMap {
+    vars: BTreeMap<String, Option<String>>,
+}
+
+impl EnvMap {
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.vars.insert(name.to_string(), Some(value.to_string()));
+    }
+
+    /// `-r`: kept here, but removed from new panes' environments.
+    pub fn mark_removed(&mut self, name: &str) {
+        self.vars.insert(name.to_string(), None);
+    }
+
+    /// `-u`: forget it; a session variable goes back to the global one.
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Option<&str>> {
+        self.vars.get(name).map(|v| v.as_deref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.vars.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    /// show-environment lines: `NAME=value`, or `-NAME` for one marked
+    /// for removal.
+    pub fn show_lines(&self) -> Vec<String> {
+        self.iter().map(|(k, v)| match v {
+            Some(v) => format!("{}={}", k, v),
+            None => format!("-{}", k),
+        }).collect()
+    }
+}
+
+/// A set-environment command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetEnv {
+    /// `-g`: the global environment rather than the session's
+    pub global: bool,
+    pub name: String,
+    pub action: EnvAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvAction { Set(String), Remove, Unset }
+
+/// Parse set-environment's arguments (after the command name, without
+/// `-t`).  None without a variable name.
+pub fn parse_set(args: &[&str]) -> Option<SetEnv> {
+    let flags: String = args.iter().take_while(|a| a.starts_with('-') && a.len() > 1).map(|a| &a[1..]).collect();
+    let rest: Vec<&str> = args.iter().skip_while(|a| a.starts_with('-') && a.len() > 1).copied().collect();
+    let name = rest.first()?.to_string();
+    let action = if flags.contains('u') {
+        EnvAction::Unset
+    } else if flags.contains('r') {
+        EnvAction::Remove
+    } else {
+        let value = rest[1..].join(" ");
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(&value);
+        EnvAction::Set(value.to_string())
+    };
+    Some(SetEnv { global: flags.contains('g'), name, action })
+}
+
+pub fn apply_set(app: &mut AppState, set: &SetEnv) {
+    let env = if set.global { &mut app.global_env } else { &mut app.session_env };
+    match &set.action {
+        EnvAction::Set(v) => env.set(&set.name, v),
+        EnvAction::Remove => env.mark_removed(&set.name),
+        EnvAction::Unset => env.unset(&set.name),
+    }
+}
+
+/// show-environment: the global or the session environment as lines.
+pub fn show(app: &AppState, global: bool) -> String {
+    let mut lines = if global { app.global_env.show_lines() } else { app.session_env.show_lines() };
+    if global {
+        // The server's own PSMUX_/TMUX_ variables count as global ones
+        for (key, value) in std::env::vars() {
+            if (key.starts_with("PSMUX") || key.starts_with("TMUX")) && app.global_env.get(&key).is_none() {
+                lines.push(format!("{}={}", key, value));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// What a new pane or job gets on top of the server's environment: each
+/// variable with its value, or None to remove it.
+pub fn composed(app: &AppState) -> Vec<(String, Option<String>)> {
+    let mut vars: BTreeMap<&str, Option<&str>> = app.global_env.iter().collect();
+    vars.extend(app.session_env.iter());
+    vars.into_iter().map(|(k, v)| (k.to_string(), v.map(str::to_string))).collect()
+}
+
+/// Give a new pane's command the composed environment.
+pub fn apply_to_pane(builder: &mut CommandBuilder, vars: &[(String, Option<String>)]) {
+    for (k, v) in vars {
+        match v {
+            Some(v) => builder.env(k, v),
+            None => builder.env_remove(k),
+        }
+    }
+}
+
+/// Give a shell job (run-shell, if-shell) the composed environment.
+pub fn apply_to_job(cmd: &mut std::process::Command, vars: &[(String, Option<String>)]) {
+    for (k, v) in vars {
+        match v {
+            Some(v) => { cmd.env(k, v); }
+            None => { cmd.env_remove(k); }
+        }
+    }
+}
+
+/// The variables update-environment names.
+pub fn update_names(app: &AppState) -> Vec<String> {
+    app.environment.get("update-environment").map_or(DEFAULT_UPDATE_ENVIRONMENT, |s| s.as_str())
+        .split_whitespace().map(str::to_string).collect()
+}
+
+/// An attaching client's environment: copy the update-environment
+/// variables into the session's, marking the ones it lacks for removal.
+pub fn update_from_client(app: &mut AppState, client: &[(String, String)]) {
+    for name in update_names(app) {
+        match client.iter().find(|(k, _)| k.eq_ignore_ascii_case(&name)) {
+            Some((_, v)) => app.session_env.set(&name, v),
+            None => app.session_env.mark_removed(&name),
+        }
+    }
+}
+
+/// The client's environment as it goes in `client-environment`: NAME=value
+/// pairs separated by NULs, base64-encoded so one line carries it.
+pub fn encode_client_env(vars: impl Iterator<Item = (String, String)>) -> String {
+    let joined: Vec<String> = vars.map(|(k, v)| format!("{}={}", k, v)).collect();
+    crate::util::base64_encode_bytes(joined.join("\0").as_bytes())
+}
+
+pub fn decode_client_env(encoded: &str) -> Vec<(String, String)> {
+    let Some(bytes) = crate::util::base64_decode_bytes(encoded) else { return Vec::new() };
+    String::from_utf8_lossy(&bytes).split('\0')
+        .filter_map(|kv| kv.split_once('='))
+        .filter(|(k, _)| !k.is_empty())
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_overrides_global_and_attach_updates_it() {
+        let mut app = AppState::new("t".to_string());
+        app.global_env.set("EDITOR", "vim");
+        app.global_env.set("PAGER", "less");
+        app.session_env.set("EDITOR", "nano");
+        app.session_env.mark_removed("PAGER");
+        assert_eq!(composed(&app), vec![("EDITOR".into(), Some("nano".into())), ("PAGER".into(), None)]);
+        assert_eq!(app.session_env.show_lines(), vec!["EDITOR=nano", "-PAGER"]);
+        app.session_env.unset("EDITOR");
+        assert_eq!(composed(&app)[0], ("EDITOR".into(), Some("vim".into())));
+
+        // Only the update-environment variables are taken from the client
+        app.environment.insert("update-environment".into(), "SSH_AUTH_SOCK DISPLAY".into());
+        let sent = encode_client_env([("SSH_AUTH_SOCK", "\\\\.\\pipe\\agent=1"), ("HOME", "C:\\Users\\x")].into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string())));
+        update_from_client(&mut app, &decode_client_env(&sent));
+        assert_eq!(app.session_env.get("SSH_AUTH_SOCK"), Some(Some("\\\\.\\pipe\\agent=1")));
+        assert_eq!(app.session_env.get("DISPLAY"), Some(None));
+        assert_eq!(app.session_env.get("HOME"), None);
+        assert!(decode_client_env("not base64!").is_empty());
+    }
+}
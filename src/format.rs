@@ -8,7 +8,7 @@
 // -F custom format for list commands.
 
 use std::env;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 
 use crate::types::{AppState, Node, LayoutKind, Pane, Mode, VERSION};
 use crate::tree::{split_with_gaps, get_active_pane_id, active_pane, count_panes};
@@ -20,6 +20,8 @@ use crate::config::format_key_binding;
 thread_local! {
     static PANE_POS_OVERRIDE: Cell<Option<usize>> = const { Cell::new(None) };
     static BUFFER_IDX_OVERRIDE: Cell<Option<usize>> = const { Cell::new(None) };
+    static KEY_OVERRIDE: RefCell<Option<String>> = const { RefCell::new(None) };
+    static HOOK_CONTEXT: RefCell<Option<(String, crate::hooks::Context)>> = const { RefCell::new(None) };
 }
 
 /// Set the buffer index for per-buffer format expansion in list-buffers -F.
@@ -27,6 +29,25 @@ pub fn set_buffer_idx_override(idx: Option<usize>) {
     BUFFER_IDX_OVERRIDE.set(idx);
 }
 
+/// Set the key that triggered the running binding (`#{key}`).
+pub fn set_key_override(key: Option<String>) {
+    KEY_OVERRIDE.with(|k| *k.borrow_mut() = key);
+}
+
+/// Set the hook whose commands are running (`#{hook}`) and the variables
+/// bound to what fired it; returns the ones set before.
+pub fn set_hook_context(hook: Option<(String, crate::hooks::Context)>) -> Option<(String, crate::hooks::Context)> {
+    HOOK_CONTEXT.with(|h| std::mem::replace(&mut *h.borrow_mut(), hook))
+}
+
+/// A variable bound by the running hook.
+fn hook_var(var: &str) -> Option<String> {
+    HOOK_CONTEXT.with(|h| h.borrow().as_ref().and_then(|(hook, vars)| {
+        if var == "hook" { return Some(hook.clone()); }
+        vars.iter().find(|(k, _)| k == var).map(|(_, v)| v.clone())
+    }))
+}
+
 // ─────────────────── tmux window_layout generation ────────────────────
 
 /// Generate a tmux-compatible window_layout string from the pane tree.
@@ -72,7 +93,7 @@ fn layout_node(node: &Node, area: ratatui::prelude::Rect) -> String {
 }
 
 /// Compute tmux layout checksum (16-bit CSUM as used by tmux src/layout-custom.c).
-fn tmux_layout_checksum(layout: &str) -> u16 {
+pub fn tmux_layout_checksum(layout: &str) -> u16 {
     let mut csum: u16 = 0;
     for &b in layout.as_bytes() {
         csum = (csum >> 1) | ((csum & 1) << 15); // rotate right 1 bit
@@ -91,118 +112,189 @@ pub fn expand_format(fmt: &str, app: &AppState) -> String {
 
 /// Expand tmux format strings for a specific window index.
 pub fn expand_format_for_window(fmt: &str, app: &AppState, win_idx: usize) -> String {
-    let mut result = String::with_capacity(fmt.len() * 2);
-    let bytes = fmt.as_bytes();
-    let len = bytes.len();
-    let mut i = 0;
+    let template = Template::parse(fmt);
+    apply_strftime(template.render(app, win_idx), template.has_strftime)
+}
 
-    // Whether the original format contains strftime %-sequences.
-    // If so, we need to escape '%' in expanded variable content so chrono
-    // only interprets the real strftime codes from the original format.
-    let has_strftime = fmt.contains('%');
+/// A format string split into literal text, `#{...}` expressions and `#X`
+/// shorthands.  `FormatCache` keeps these so a format is scanned once.
+struct Template {
+    fmt: String,
+    parts: Vec<Part>,
+    /// The format contains strftime %-sequences.  If so, '%' in expanded
+    /// variable content is escaped so chrono only interprets the real
+    /// strftime codes from the original format.
+    has_strftime: bool,
+}
 
-    while i < len {
-        if bytes[i] == b'#' && i + 1 < len {
-            if bytes[i + 1] == b'{' {
-                // #{...} expression
-                if let Some(close) = find_matching_brace(fmt, i + 2) {
-                    let inner = &fmt[i + 2..close];
-                    let expanded = expand_expression(inner, app, win_idx);
-                    if has_strftime {
-                        result.push_str(&escape_strftime_percent(&expanded));
-                    } else {
-                        result.push_str(&expanded);
-                    }
-                    i = close + 1;
+enum Part {
+    /// Literal text, as a byte range of the format
+    Text(std::ops::Range<usize>),
+    /// The inside of a `#{...}`
+    Expr(std::ops::Range<usize>),
+    /// `#S`, `#I`, ...
+    Short(u8),
+}
+
+impl Template {
+    fn parse(fmt: &str) -> Self {
+        let bytes = fmt.as_bytes();
+        let len = bytes.len();
+        let mut parts = Vec::new();
+        let mut text_start = 0;
+        let mut i = 0;
+        while i < len {
+            if bytes[i] == b'#' && i + 1 < len {
+                let part = match bytes[i + 1] {
+                    b'{' => find_matching_brace(fmt, i + 2).map(|close| (Part::Expr(i + 2..close), close + 1)),
+                    // Escaped comma inside conditional branches, and ##
+                    b',' | b'#' => Some((Part::Text(i + 1..i + 2), i + 2)),
+                    c if SHORTHANDS.contains(&c) => Some((Part::Short(c), i + 2)),
+                    _ => None,
+                };
+                if let Some((part, next)) = part {
+                    if text_start < i { parts.push(Part::Text(text_start..i)); }
+                    parts.push(part);
+                    i = next;
+                    text_start = i;
                     continue;
                 }
             }
-            if bytes[i + 1] == b',' {
-                // Escaped comma inside conditional branches
-                result.push(',');
-                i += 2;
-                continue;
-            }
-            // Shorthand #X
-            match bytes[i + 1] {
-                b'S' => {
-                    if has_strftime {
-                        result.push_str(&escape_strftime_percent(&app.session_name));
-                    } else {
-                        result.push_str(&app.session_name);
-                    }
-                    i += 2; continue;
-                }
-                b'I' => {
-                    let n = if win_idx < app.windows.len() { win_idx + app.window_base_index } else { 0 };
-                    result.push_str(&n.to_string());
-                    i += 2; continue;
-                }
-                b'W' | b'T' => {
-                    if let Some(w) = app.windows.get(win_idx) {
-                        if has_strftime {
-                            result.push_str(&escape_strftime_percent(&w.name));
-                        } else {
-                            result.push_str(&w.name);
-                        }
-                    }
-                    i += 2; continue;
-                }
-                b'P' => {
-                    if let Some(w) = app.windows.get(win_idx) {
-                        let active_id = get_active_pane_id(&w.root, &w.active_path).unwrap_or(0);
-                        let pos = crate::tree::get_pane_position_in_window(&w.root, active_id).unwrap_or(0);
-                        result.push_str(&(pos + app.pane_base_index).to_string());
-                    }
-                    i += 2; continue;
-                }
-                b'F' => {
-                    if win_idx == app.active_idx { result.push('*'); }
-                    else if win_idx == app.last_window_idx { result.push('-'); }
-                    i += 2; continue;
-                }
-                b'H' | b'h' => {
-                    if has_strftime {
-                        result.push_str(&escape_strftime_percent(&hostname_cached()));
-                    } else {
-                        result.push_str(&hostname_cached());
-                    }
-                    i += 2; continue;
-                }
-                b'D' => {
-                    // tmux: #D = unique pane id (like %0, %1)
-                    if let Some(w) = app.windows.get(win_idx) {
-                        let active_id = get_active_pane_id(&w.root, &w.active_path).unwrap_or(0);
-                        if has_strftime {
-                            // Escape the '%' so chrono doesn't misinterpret %0, %1, etc.
-                            result.push_str(&format!("%%{}", active_id));
-                        } else {
-                            result.push_str(&format!("%{}", active_id));
-                        }
-                    }
-                    i += 2; continue;
+            i += 1;
+        }
+        if text_start < len { parts.push(Part::Text(text_start..len)); }
+        Template { fmt: fmt.to_string(), parts, has_strftime: fmt.contains('%') }
+    }
+
+    /// The expansion, before strftime.
+    fn render(&self, app: &AppState, win_idx: usize) -> String {
+        let mut result = String::with_capacity(self.fmt.len() * 2);
+        for part in &self.parts {
+            let expanded = match part {
+                Part::Text(r) => { result.push_str(&self.fmt[r.clone()]); continue; }
+                Part::Expr(r) => expand_expression(&self.fmt[r.clone()], app, win_idx),
+                Part::Short(c) => {
+                    let value = shorthand(*c, app, win_idx);
+                    record(Source::Short(*c), "", win_idx, Some(&value));
+                    value
                 }
-                b'#' => { result.push('#'); i += 2; continue; }
-                _ => {}
+            };
+            if self.has_strftime {
+                result.push_str(&escape_strftime_percent(&expanded));
+            } else {
+                result.push_str(&expanded);
             }
         }
-        result.push(bytes[i] as char);
-        i += 1;
+        result
     }
-    // Expand strftime %-sequences only if the ORIGINAL format contained '%'
-    if has_strftime && result.contains('%') {
-        // Use write! to catch chrono format errors instead of panicking.
-        // Expanded variable content has '%' escaped to '%%' above, so chrono
-        // will only interpret the real strftime codes from the original format.
-        use std::fmt::Write;
-        let formatted = chrono::Local::now().format(&result);
-        let mut buf = String::with_capacity(result.len() + 32);
-        if write!(buf, "{}", formatted).is_ok() {
-            result = buf;
+}
+
+/// The `#X` shorthands there are.
+const SHORTHANDS: &[u8] = b"SIWTPFHhD";
+
+/// The value of shorthand `#c`.
+fn shorthand(c: u8, app: &AppState, win_idx: usize) -> String {
+    let win = app.windows.get(win_idx);
+    match c {
+        b'S' => app.session_name.clone(),
+        b'I' => win.map_or(0, |w| w.index).to_string(),
+        b'W' | b'T' => win.map(|w| w.name.clone()).unwrap_or_default(),
+        b'P' => win.map(|w| {
+            let active_id = get_active_pane_id(&w.root, &w.active_path).unwrap_or(0);
+            let pos = crate::tree::get_pane_position_in_window(&w.root, active_id).unwrap_or(0);
+            (pos + app.pane_base_index).to_string()
+        }).unwrap_or_default(),
+        b'F' => {
+            if win_idx == app.active_idx { "*".into() }
+            else if win_idx == app.last_window_idx { "-".into() }
+            else { String::new() }
         }
+        b'H' | b'h' => hostname_cached(),
+        // tmux: #D = unique pane id (like %0, %1)
+        b'D' => win.map(|w| format!("%{}", get_active_pane_id(&w.root, &w.active_path).unwrap_or(0))).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Expand strftime %-sequences, only if the original format contained '%'.
+fn apply_strftime(result: String, has_strftime: bool) -> String {
+    if !has_strftime || !result.contains('%') { return result; }
+    // Use write! to catch chrono format errors instead of panicking.
+    // Expanded variable content has '%' escaped to '%%' above, so chrono
+    // will only interpret the real strftime codes from the original format.
+    use std::fmt::Write;
+    let translated = translate_strftime(&result);
+    let formatted = chrono::Local::now().format(&translated);
+    let mut buf = String::with_capacity(result.len() + 32);
+    if write!(buf, "{}", formatted).is_ok() {
+        buf
+    } else {
         // On error, keep the pre-strftime result as-is
+        result
     }
-    result
+}
+
+/// Conversions chrono's strftime knows (after `%`, past a `-`, `_` or `0`
+/// padding flag); `.`, `:` and digits lead into `%.3f` and `%:z`.
+const CHRONO_CONVERSIONS: &str = "YCymbBhdeaAwuUWGgVjDxFvHkIlPpMSfRTXrZzstnc+%.:0123456789";
+
+/// Conversions whose output changes every second.
+const SECONDS_CONVERSIONS: &str = "STXrsc+";
+
+/// The conversion characters of the %-sequences in `fmt`, as `(flag, code)`;
+/// `E`/`O` modifiers are skipped, as glibc's strftime does in the C locale.
+fn strftime_conversions(fmt: &str) -> impl Iterator<Item = (Option<char>, Option<char>)> + '_ {
+    let mut chars = fmt.chars();
+    std::iter::from_fn(move || {
+        chars.by_ref().find(|&c| c == '%')?;
+        let mut next = chars.next();
+        let flag = next.filter(|c| "-_0^#".contains(*c));
+        if flag.is_some() { next = chars.next(); }
+        if matches!(next, Some('E' | 'O')) { next = chars.next(); }
+        Some((flag, next))
+    })
+}
+
+/// Rewrite tmux (C strftime) %-sequences into ones chrono takes: E/O
+/// modifiers and the `^`/`#` case flags are dropped, and a conversion
+/// chrono does not know is kept as literal text rather than failing the
+/// whole line.
+fn translate_strftime(fmt: &str) -> String {
+    let mut out = String::with_capacity(fmt.len());
+    let mut rest = fmt;
+    while let Some(pos) = rest.find('%') {
+        out.push_str(&rest[..pos]);
+        let seq_start = &rest[pos..];
+        let (flag, code) = strftime_conversions(seq_start).next().unwrap_or((None, None));
+        // The sequence's length: '%', the flag, an E/O modifier, the code
+        let mut len = 1 + flag.map_or(0, char::len_utf8);
+        if seq_start[len..].starts_with(['E', 'O']) { len += 1; }
+        len += code.map_or(0, char::len_utf8);
+        match code {
+            Some(c) if CHRONO_CONVERSIONS.contains(c) => {
+                out.push('%');
+                if let Some(f) = flag.filter(|f| "-_0".contains(*f)) { out.push(f); }
+                out.push(c);
+            }
+            _ => out.push_str(&seq_start[..len].replace('%', "%%")),
+        }
+        rest = &seq_start[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// How often the status line must be redrawn for its clock: every
+/// `status-interval` seconds, or every second when a status format shows
+/// seconds.  None when `status-interval` is 0.
+pub fn status_refresh_interval(app: &AppState) -> Option<std::time::Duration> {
+    if app.status_interval == 0 { return None; }
+    let formats = [&app.status_left, &app.status_right, &app.window_status_format, &app.window_status_current_format]
+        .into_iter().chain(&app.status_format);
+    let seconds = formats.into_iter().any(|f| {
+        strftime_conversions(f).any(|(_, c)| c.is_some_and(|c| SECONDS_CONVERSIONS.contains(c)))
+    });
+    Some(std::time::Duration::from_secs(if seconds { 1 } else { app.status_interval }))
 }
 
 /// Escape '%' to '%%' in expanded variable content so chrono's strftime
@@ -230,6 +322,126 @@ pub fn expand_format_for_pane(
     result
 }
 
+// ─────────────────── expansion cache ───────────────────────
+
+// What the expansion being recorded has read so far.  `record` is a no-op
+// unless `FormatCache` is recording.
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Recording {
+    deps: Vec<Dep>,
+    /// Read something no dependency describes (pane content), so the
+    /// expansion cannot be reused
+    volatile: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source { Var, Option, Short(u8) }
+
+/// One value an expansion read, and where from.
+struct Dep {
+    source: Source,
+    name: String,
+    win_idx: usize,
+    /// PANE_POS_OVERRIDE at the time (inside `#{P:...}`)
+    pane: Option<usize>,
+    value: Option<String>,
+}
+
+impl Dep {
+    fn key(&self) -> (Source, &str, usize, Option<usize>) { (self.source, &self.name, self.win_idx, self.pane) }
+
+    /// The value still reads the same.
+    fn holds(&self, app: &AppState) -> bool {
+        let saved = PANE_POS_OVERRIDE.replace(self.pane);
+        let now = match self.source {
+            Source::Var => Some(var_value(&self.name, app, self.win_idx)),
+            Source::Option => option_value(&self.name, app, self.win_idx),
+            Source::Short(c) => Some(shorthand(c, app, self.win_idx)),
+        };
+        PANE_POS_OVERRIDE.set(saved);
+        now == self.value
+    }
+}
+
+fn record(source: Source, name: &str, win_idx: usize, value: Option<&str>) {
+    RECORDING.with(|r| if let Some(rec) = r.borrow_mut().as_mut() {
+        rec.deps.push(Dep { source, name: name.to_string(), win_idx, pane: PANE_POS_OVERRIDE.get(), value: value.map(str::to_string) });
+    });
+}
+
+fn mark_volatile() {
+    RECORDING.with(|r| if let Some(rec) = r.borrow_mut().as_mut() { rec.volatile = true; });
+}
+
+/// Run `f`, returning what it read along with its result.
+fn recording<T>(f: impl FnOnce() -> T) -> (T, Recording) {
+    let saved = RECORDING.replace(Some(Recording::default()));
+    let out = f();
+    let rec = RECORDING.replace(saved).unwrap_or_default();
+    (out, rec)
+}
+
+/// Formats cached by `FormatCache` before it starts over.
+const MAX_CACHED_FORMATS: usize = 64;
+
+/// Expansions of the formats expanded on every frame (window-status tabs,
+/// status-left/right), reused while nothing they read has changed.
+///
+/// Each format is parsed once into a `Template`.  Expanding it records every
+/// variable, option and shorthand the expansion read, with the window (and
+/// pane, inside `#{P:...}`) it was read for and the value it had.  The next
+/// expansion for the same window only re-reads those values, and expands
+/// again when any of them differs.  Comparing values rather than bumping
+/// counters at each mutation means no change to the state can be missed.
+/// strftime codes are applied afresh each time; `#{C:...}` reads pane
+/// content and is never reused.
+#[derive(Default)]
+pub struct FormatCache {
+    formats: std::collections::HashMap<String, CachedFormat>,
+    /// Expansions reused
+    pub hits: u64,
+    /// Expansions done
+    pub misses: u64,
+}
+
+struct CachedFormat {
+    template: Template,
+    /// By window index: the expansion before strftime, and what it read
+    windows: std::collections::HashMap<usize, (String, Vec<Dep>)>,
+}
+
+impl FormatCache {
+    /// `expand_format_for_window`, reusing the last expansion when it is
+    /// still current.
+    pub fn expand(&mut self, fmt: &str, app: &AppState, win_idx: usize) -> String {
+        if let Some(cached) = self.formats.get(fmt) {
+            if let Some((text, deps)) = cached.windows.get(&win_idx) {
+                if deps.iter().all(|d| d.holds(app)) {
+                    self.hits += 1;
+                    return apply_strftime(text.clone(), cached.template.has_strftime);
+                }
+            }
+        }
+        self.misses += 1;
+        if self.formats.len() >= MAX_CACHED_FORMATS && !self.formats.contains_key(fmt) { self.formats.clear(); }
+        let cached = self.formats.entry(fmt.to_string())
+            .or_insert_with(|| CachedFormat { template: Template::parse(fmt), windows: Default::default() });
+        let (text, mut rec) = recording(|| cached.template.render(app, win_idx));
+        if rec.volatile {
+            cached.windows.remove(&win_idx);
+        } else {
+            rec.deps.sort_by(|a, b| a.key().cmp(&b.key()));
+            rec.deps.dedup_by(|a, b| a.key() == b.key());
+            cached.windows.insert(win_idx, (text.clone(), rec.deps));
+        }
+        apply_strftime(text, cached.template.has_strftime)
+    }
+}
+
 // ─────────────────── expression dispatcher ───────────────────────
 
 /// Expand a `#{...}` expression (the content between `#{` and `}`).
@@ -265,6 +477,8 @@ fn expand_expression(expr: &str, app: &AppState, win_idx: usize) -> String {
                 // #{W:fmt} — expand fmt once per window, join with spaces
                 let inner_fmt = &expr[2..];
                 let mut parts = Vec::new();
+                // How many windows there are is read too
+                record(Source::Var, "session_windows", win_idx, Some(&app.windows.len().to_string()));
                 for wi in 0..app.windows.len() {
                     parts.push(expand_format_for_window(inner_fmt, app, wi));
                 }
@@ -277,6 +491,7 @@ fn expand_expression(expr: &str, app: &AppState, win_idx: usize) -> String {
                 if let Some(win) = app.windows.get(win_idx) {
                     let mut pane_ids = Vec::new();
                     collect_pane_ids(&win.root, &mut pane_ids);
+                    record(Source::Var, "window_panes", win_idx, Some(&pane_ids.len().to_string()));
                     for (pos, _pid) in pane_ids.iter().enumerate() {
                         PANE_POS_OVERRIDE.set(Some(pos));
                         parts.push(expand_format_for_window(inner_fmt, app, win_idx));
@@ -391,6 +606,19 @@ fn find_modifier_colon(s: &str) -> Option<usize> {
         if b == b':' && depth == 0 {
             return Some(i);
         }
+        // The pattern and replacement of s/pat/rep/ may contain ':'
+        if b == b's' && depth == 0 && (i == 0 || bytes[i - 1] == b';')
+            && i + 1 < len && !bytes[i + 1].is_ascii_alphanumeric() && bytes[i + 1] != b':'
+        {
+            let sep = bytes[i + 1];
+            let mut seen = 0;
+            i += 2;
+            while i < len && seen < 2 {
+                if bytes[i] == sep { seen += 1; }
+                i += 1;
+            }
+            continue;
+        }
         i += 1;
     }
     None
@@ -409,7 +637,7 @@ enum Modifier {
     Trim(i32),
     TrimWithMarker(i32, String),
     Pad(i32),
-    MathExpr { op: char, floating: bool, decimals: u32 },
+    MathExpr { op: String, floating: bool, decimals: u32 },
     Match { regex: bool, case_insensitive: bool },
     SearchContent { _regex: bool, _case_insensitive: bool },
     Width,
@@ -466,7 +694,8 @@ fn parse_single_modifier(spec: &str) -> Option<Modifier> {
             let inner = &rest[1..];
             let parts: Vec<&str> = inner.splitn(3, sep).collect();
             let pattern = parts.first().unwrap_or(&"").to_string();
-            let replacement = parts.get(1).unwrap_or(&"").to_string();
+            // tmux writes back-references as \1; the regex crate as ${1}
+            let replacement = BACKREF.replace_all(parts.get(1).unwrap_or(&""), "$${$1}").into_owned();
             let flags = parts.get(2).unwrap_or(&"");
             Some(Modifier::Substitute {
                 pattern,
@@ -479,7 +708,7 @@ fn parse_single_modifier(spec: &str) -> Option<Modifier> {
             let sep = rest.as_bytes()[0] as char;
             let inner = &rest[1..];
             let parts: Vec<&str> = inner.splitn(3, sep).collect();
-            let op = parts.first().and_then(|s| s.chars().next()).unwrap_or('+');
+            let op = parts.first().filter(|s| !s.is_empty()).unwrap_or(&"+").to_string();
             let flags = parts.get(1).unwrap_or(&"");
             let floating = flags.contains('f');
             let decimals: u32 = parts.get(2).and_then(|s| s.parse().ok())
@@ -530,6 +759,8 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
             expand_format_for_window(value, app, win_idx)
         }
         Modifier::ExpandTime => {
+            // Depends on the time, which is not recorded
+            mark_volatile();
             let expanded = expand_format_for_window(value, app, win_idx);
             if expanded.contains('%') {
                 use std::fmt::Write;
@@ -554,40 +785,12 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
             }
             out
         }
-        Modifier::Trim(n) => {
-            let n = *n;
-            if n == 0 { return value.to_string(); }
-            let chars: Vec<char> = value.chars().collect();
-            if n > 0 {
-                let len = n as usize;
-                if chars.len() > len { chars[..len].iter().collect() }
-                else { value.to_string() }
-            } else {
-                let len = (-n) as usize;
-                if chars.len() > len { chars[chars.len() - len..].iter().collect() }
-                else { value.to_string() }
-            }
-        }
-        Modifier::TrimWithMarker(n, marker) => {
-            let n = *n;
-            if n == 0 { return value.to_string(); }
-            let chars: Vec<char> = value.chars().collect();
-            if n > 0 {
-                let len = n as usize;
-                if chars.len() > len {
-                    let mut trimmed: String = chars[..len].iter().collect();
-                    trimmed.push_str(marker);
-                    trimmed
-                } else { value.to_string() }
-            } else {
-                let len = (-n) as usize;
-                if chars.len() > len {
-                    let mut trimmed = marker.clone();
-                    trimmed.extend(chars[chars.len() - len..].iter());
-                    trimmed
-                } else { value.to_string() }
-            }
-        }
+        Modifier::Trim(n) => trim_visible(value, *n).unwrap_or_else(|| value.to_string()),
+        Modifier::TrimWithMarker(n, marker) => match trim_visible(value, *n) {
+            Some(trimmed) if *n > 0 => trimmed + marker,
+            Some(trimmed) => format!("{marker}{trimmed}"),
+            None => value.to_string(),
+        },
         Modifier::Pad(n) => {
             let n = *n;
             let abs_n = n.unsigned_abs() as usize;
@@ -599,19 +802,24 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
             else { format!("{}{}", spaces, value) }
         }
         Modifier::Substitute { pattern, replacement, case_insensitive } => {
-            let re_pattern = if *case_insensitive {
-                format!("(?i){}", pattern)
-            } else {
-                pattern.clone()
-            };
-            match regex::Regex::new(&re_pattern) {
-                Ok(re) => re.replace(value, replacement.as_str()).to_string(),
-                Err(_) => value.to_string(),
-            }
+            let budget = crate::pattern::Budget::new(app.pattern_timeout_ms);
+            crate::pattern::compile(pattern, *case_insensitive)
+                .and_then(|re| crate::pattern::replace_first(&re, value, replacement, &budget))
+                .unwrap_or_else(|_| value.to_string())
         }
         Modifier::MathExpr { op, floating, decimals } => {
             let parts = split_at_depth0(value, b',');
             if parts.len() < 2 { return "0".into(); }
+            if matches!(op.as_str(), "==" | "!=" | "<" | ">" | "<=" | ">=") {
+                let a: f64 = parts[0].trim().parse().unwrap_or(0.0);
+                let b: f64 = parts[1].trim().parse().unwrap_or(0.0);
+                let r = match op.as_str() {
+                    "==" => a == b, "!=" => a != b, "<" => a < b,
+                    ">" => a > b, "<=" => a <= b, _ => a >= b,
+                };
+                return if r { "1" } else { "0" }.into();
+            }
+            let op = op.chars().next().unwrap_or('+');
             if *floating {
                 let a: f64 = parts[0].parse().unwrap_or(0.0);
                 let b: f64 = parts[1].parse().unwrap_or(0.0);
@@ -642,11 +850,11 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
             let pattern = &parts[0];
             let subject = &parts[1];
             if *regex {
-                let re_pat = if *case_insensitive { format!("(?i){}", pattern) }
-                    else { pattern.to_string() };
-                match regex::Regex::new(&re_pat) {
-                    Ok(re) => if re.is_match(subject) { "1".into() } else { "0".into() },
-                    Err(_) => "0".into(),
+                let budget = crate::pattern::Budget::new(app.pattern_timeout_ms);
+                match crate::pattern::compile(pattern, *case_insensitive)
+                    .and_then(|re| crate::pattern::first_matching_line(&re, [subject.as_str()], &budget)) {
+                    Ok(Some(_)) => "1".into(),
+                    _ => "0".into(),
                 }
             } else {
                 if glob_match(pattern, subject, *case_insensitive) { "1".into() }
@@ -657,20 +865,14 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
             // #{C:pattern} — Search for pattern in pane content, return line number or empty
             let pattern = value;
             if pattern.is_empty() { return String::new(); }
+            mark_volatile();
             if let Some(w) = app.windows.get(win_idx) {
                 if let Some(p) = active_pane(&w.root, &w.active_path) {
                     if let Ok(parser) = p.term.lock() {
                         let screen = parser.screen();
-                        let re_result = if *_regex {
-                            let pat = if *_case_insensitive { format!("(?i){}", pattern) } else { pattern.to_string() };
-                            regex::Regex::new(&pat).ok()
-                        } else {
-                            let escaped = regex::escape(pattern);
-                            let pat = if *_case_insensitive { format!("(?i){}", escaped) } else { escaped };
-                            regex::Regex::new(&pat).ok()
-                        };
-                        if let Some(re) = re_result {
-                            for r in 0..p.last_rows {
+                        let pat = if *_regex { pattern.to_string() } else { regex::escape(pattern) };
+                        if let Ok(re) = crate::pattern::compile(&pat, *_case_insensitive) {
+                            let rows: Vec<String> = (0..p.last_rows).map(|r| {
                                 let mut row_text = String::with_capacity(p.last_cols as usize);
                                 for c in 0..p.last_cols {
                                     if let Some(cell) = screen.cell(r, c) {
@@ -678,9 +880,11 @@ fn apply_modifier(m: &Modifier, value: &str, app: &AppState, win_idx: usize) ->
                                         if t.is_empty() { row_text.push(' '); } else { row_text.push_str(t); }
                                     } else { row_text.push(' '); }
                                 }
-                                if re.is_match(&row_text) {
-                                    return r.to_string();
-                                }
+                                row_text
+                            }).collect();
+                            let budget = crate::pattern::Budget::new(app.pattern_timeout_ms);
+                            if let Ok(Some(r)) = crate::pattern::first_matching_line(&re, rows.iter().map(String::as_str), &budget) {
+                                return r.to_string();
                             }
                         }
                     }
@@ -706,7 +910,7 @@ fn expand_var_or_format(target: &str, app: &AppState, win_idx: usize) -> String
         let val = expand_var(target, app, win_idx);
         if val.is_empty() && !target.is_empty() {
             // Try as option
-            if let Some(opt_val) = lookup_option(target, app) {
+            if let Some(opt_val) = lookup_option(target, app, win_idx) {
                 return opt_val;
             }
             // Not a known variable — return as literal
@@ -716,11 +920,26 @@ fn expand_var_or_format(target: &str, app: &AppState, win_idx: usize) -> String
     }
 }
 
-/// Look up a tmux option by name.
-fn lookup_option(name: &str, app: &AppState) -> Option<String> {
+/// Look up a tmux option by name, as window `win_idx` (and the pane being
+/// expanded) sees it.
+fn lookup_option(name: &str, app: &AppState, win_idx: usize) -> Option<String> {
+    let value = option_value(name, app, win_idx);
+    record(Source::Option, name, win_idx, value.as_deref());
+    value
+}
+
+fn option_value(name: &str, app: &AppState, win_idx: usize) -> Option<String> {
     if name.starts_with('@') {
         return app.environment.get(name).cloned();
     }
+    if crate::scoped::NAMES.contains(&name) {
+        let win = app.windows.get(win_idx);
+        let pane = win.and_then(|w| match PANE_POS_OVERRIDE.get() {
+            Some(pos) => crate::tree::get_nth_pane(&w.root, pos),
+            None => crate::tree::active_pane(&w.root, &w.active_path),
+        });
+        return Some(crate::scoped::value(app, win, pane, name).0);
+    }
     match name {
         "status-left" => Some(app.status_left.clone()),
         "status-right" => Some(app.status_right.clone()),
@@ -732,19 +951,32 @@ fn lookup_option(name: &str, app: &AppState) -> Option<String> {
         "base-index" => Some(app.window_base_index.to_string()),
         "pane-base-index" => Some(app.pane_base_index.to_string()),
         "escape-time" => Some(app.escape_time_ms.to_string()),
+        "repeat-time" => Some(app.repeat_time_ms.to_string()),
+        "prefix-timeout" => Some(app.prefix_timeout_ms.to_string()),
+        "confirm-kill" => Some(if app.confirm_kill { "on" } else { "off" }.to_string()),
         "history-limit" => Some(app.history_limit.to_string()),
+        "buffer-limit" => Some(app.paste_buffers.limit.to_string()),
+        "local-echo" => Some(app.local_echo.clone()),
         "mouse" => Some(if app.mouse_enabled { "on".into() } else { "off".into() }),
         "mode-keys" => Some(app.mode_keys.clone()),
         "default-command" | "default-shell" => Some(app.default_shell.clone()),
         "word-separators" => Some(app.word_separators.clone()),
         "renumber-windows" => Some(if app.renumber_windows { "on".into() } else { "off".into() }),
-        "automatic-rename" => Some(if app.automatic_rename { "on".into() } else { "off".into() }),
-        "monitor-activity" => Some(if app.monitor_activity { "on".into() } else { "off".into() }),
-        "remain-on-exit" => Some(if app.remain_on_exit { "on".into() } else { "off".into() }),
+        "automatic-rename-format" => Some(app.automatic_rename_format.clone()),
+        "allow-duplicate-window-names" => Some(if app.allow_duplicate_window_names { "on".into() } else { "off".into() }),
+        "popup-warm-shell" => Some(if app.popup_warm_shell { "on".into() } else { "off".into() }),
+        "popup-close-key" => Some(app.popup_close_key.clone()),
         "set-titles" => Some(if app.set_titles { "on".into() } else { "off".into() }),
         "set-titles-string" => Some(app.set_titles_string.clone()),
         "pane-border-style" => Some(app.pane_border_style.clone()),
         "pane-active-border-style" => Some(app.pane_active_border_style.clone()),
+        "pane-border-lines" => Some(app.pane_border_lines.clone()),
+        "pane-border-indicators" => Some(app.pane_border_indicators.clone()),
+        "pane-border-status" => Some(app.pane_border_status.clone()),
+        "pane-border-format" => Some(app.pane_border_format.clone()),
+        "highlight-pattern" => Some(app.highlight_pattern.clone()),
+        "pane-encoding" => Some(app.pane_encoding.clone()),
+        "compat-version" => Some(app.compat_version.clone()),
         "window-status-format" => Some(app.window_status_format.clone()),
         "window-status-current-format" => Some(app.window_status_current_format.clone()),
         "window-status-separator" => Some(app.window_status_separator.clone()),
@@ -756,18 +988,26 @@ fn lookup_option(name: &str, app: &AppState) -> Option<String> {
         "message-style" => Some(app.message_style.clone()),
         "message-command-style" => Some(app.message_command_style.clone()),
         "mode-style" => Some(app.mode_style.clone()),
+        "copy-mode-match-style" => Some(app.copy_mode_match_style.clone()),
+        "copy-mode-current-match-style" => Some(app.copy_mode_current_match_style.clone()),
         "status-left-style" => Some(app.status_left_style.clone()),
         "status-right-style" => Some(app.status_right_style.clone()),
         "status-interval" => Some(app.status_interval.to_string()),
         "status-justify" => Some(app.status_justify.clone()),
+        "prefix-indicator" => Some(if app.prefix_indicator { "on".into() } else { "off".into() }),
+        "prefix-indicator-style" => Some(app.prefix_indicator_style.clone()),
         "display-time" => Some(app.display_time_ms.to_string()),
+        "pattern-timeout" => Some(app.pattern_timeout_ms.to_string()),
         "display-panes-time" => Some(app.display_panes_time_ms.to_string()),
         "focus-events" => Some(if app.focus_events { "on".into() } else { "off".into() }),
         "aggressive-resize" => Some(if app.aggressive_resize { "on".into() } else { "off".into() }),
-        "synchronize-panes" => Some(if app.sync_input { "on".into() } else { "off".into() }),
-        "monitor-silence" => Some(app.monitor_silence.to_string()),
+        "sync-paste" => Some(if app.sync_paste { "on".into() } else { "off".into() }),
+        "idle-window-timeout" => Some(app.idle_window_timeout.to_string()),
+        "idle-window-action" => Some(app.idle_window_action.clone()),
         "bell-action" => Some(app.bell_action.clone()),
         "visual-bell" => Some(if app.visual_bell { "on".into() } else { "off".into() }),
+        "visual-activity" => Some(if app.visual_activity { "on".into() } else { "off".into() }),
+        "visual-silence" => Some(if app.visual_silence { "on".into() } else { "off".into() }),
         _ => app.environment.get(name).cloned(),
     }
 }
@@ -816,7 +1056,7 @@ fn expand_boolean_and(body: &str, app: &AppState, win_idx: usize) -> String {
 }
 
 #[inline]
-fn is_truthy(s: &str) -> bool {
+pub(crate) fn is_truthy(s: &str) -> bool {
     !s.is_empty() && s != "0" && s != "off" && s != "no"
 }
 
@@ -890,8 +1130,72 @@ fn find_comparison_in_cond(cond: &str) -> Option<(&str, &str, &str)> {
 
 // ─────────────────── variable expansion ──────────────────────────
 
+/// Every variable [`expand_var`] resolves, sorted.  `display-message -a`
+/// lists these; a test keeps the table in step with the match below.
+/// Context variables (`key`, `hook`) and `@` user options are listed
+/// separately, only when they have a value.
+pub const FORMAT_VARIABLES: &[&str] = &[
+    "alternate_on", "alternate_saved_x", "alternate_saved_y",
+    "buffer_created", "buffer_name", "buffer_sample", "buffer_size",
+    "client_activity", "client_activity_string", "client_cell_height", "client_cell_width",
+    "client_control_mode", "client_created", "client_created_string", "client_discarded",
+    "client_flags", "client_height", "client_key_table", "client_last_session", "client_name",
+    "client_pid", "client_prefix", "client_session", "client_termfeatures", "client_termname",
+    "client_termtype", "client_tty", "client_utf8", "client_width", "client_written",
+    "command", "command_list_alias", "command_list_name", "command_list_usage",
+    "config_files",
+    "copy_cursor_line", "copy_cursor_word", "copy_cursor_x", "copy_cursor_y",
+    "cursor_character", "cursor_flag", "cursor_x", "cursor_y",
+    "history_limit", "history_size", "host", "host_short", "hostname", "insert_flag",
+    "keypad_cursor_flag", "keypad_flag", "last_shell_exit", "line", "mode_keys", "mouse", "origin_flag",
+    "pane_active", "pane_at_bottom", "pane_at_left", "pane_at_right", "pane_at_top", "pane_bottom",
+    "pane_current_command", "pane_current_command_full", "pane_current_path", "pane_dead", "pane_dead_signal",
+    "pane_dead_status", "pane_dead_time", "pane_format", "pane_height", "pane_highlight_count", "pane_id", "pane_in_mode",
+    "pane_index", "pane_input_off", "pane_last", "pane_left", "pane_marked", "pane_marked_set",
+    "pane_mode", "pane_path", "pane_pid", "pane_pipe", "pane_right", "pane_scroll_position",
+    "pane_scrolled", "pane_search_string", "pane_start_command", "pane_start_path",
+    "pane_synchronized", "pane_tabs", "pane_title", "pane_top", "pane_tty", "pane_unseen_changes",
+    "pane_width",
+    "pid", "prefix", "prefix2", "psmux_version", "scroll_position", "scroll_region_lower", "scroll_region_upper",
+    "search_match", "search_present",
+    "selection_active", "selection_end_x", "selection_end_y", "selection_present",
+    "selection_start_x", "selection_start_y",
+    "server_pid",
+    "session_activity", "session_activity_string", "session_alerts", "session_attached",
+    "session_created", "session_created_string", "session_format", "session_group",
+    "session_group_attached", "session_group_list", "session_group_size", "session_grouped",
+    "session_id", "session_last_attached", "session_many_attached", "session_name", "session_path",
+    "session_stack", "session_windows",
+    "socket_path", "start_time", "status", "version",
+    "window_active", "window_activity", "window_activity_flag", "window_bell_flag", "window_bigger",
+    "window_cell_height", "window_cell_width", "window_created", "window_end_flag", "window_flags", "window_format",
+    "window_height", "window_id", "window_index", "window_last_flag", "window_last_visited", "window_layout",
+    "window_linked", "window_linked_sessions", "window_linked_sessions_list", "window_name",
+    "window_offset_x", "window_offset_y", "window_panes", "window_raw_flags", "window_silence_flag",
+    "window_stack_index", "window_start_flag", "window_visible_layout", "window_width",
+    "window_zoomed_flag",
+    "wrap_flag",
+];
+
 /// Expand a named variable.
 pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
+    let value = var_value(var, app, win_idx);
+    record(Source::Var, var, win_idx, Some(&value));
+    value
+}
+
+fn var_value(var: &str, app: &AppState, win_idx: usize) -> String {
+    // Key that triggered the running binding; independent of any window
+    if var == "key" {
+        return KEY_OVERRIDE.with(|k| k.borrow().clone()).unwrap_or_default();
+    }
+    // Hook whose commands are running, and what fired it
+    if let Some(v) = hook_var(var) {
+        return v;
+    }
+    if var == "hook" {
+        return String::new();
+    }
     let win = match app.windows.get(win_idx) {
         Some(w) => w,
         None => {
@@ -901,11 +1205,12 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
                 "session_windows" => app.windows.len().to_string(),
                 "session_id" => format!("${}", app.session_id),
                 "pid" | "server_pid" => std::process::id().to_string(),
-                "version" => VERSION.to_string(),
+                "version" => app.compat_version.clone(),
+                "psmux_version" => VERSION.to_string(),
                 "host" | "hostname" => hostname_cached(),
                 "host_short" => { let h = hostname_cached(); h.split('.').next().unwrap_or(&h).to_string() }
                 _ => {
-                    if let Some(v) = lookup_option(var, app) { v } else { String::new() }
+                    if let Some(v) = lookup_option(var, app, win_idx) { v } else { String::new() }
                 }
             };
         }
@@ -946,7 +1251,7 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
         "session_path" => env::var("HOME").or_else(|_| env::var("USERPROFILE")).unwrap_or_default(),
 
         // ── Window ──
-        "window_index" => (win_idx + app.window_base_index).to_string(),
+        "window_index" => win.index.to_string(),
         "window_name" => win.name.clone(),
         "window_active" => if win_idx == app.active_idx { "1".into() } else { "0".into() },
         "window_panes" => count_panes(&win.root).to_string(),
@@ -955,16 +1260,21 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
             if win_idx == app.active_idx { f.push('*'); }
             else if win_idx == app.last_window_idx { f.push('-'); }
             if win.activity_flag { f.push('#'); }
+            if win.bell_flag { f.push('!'); }
+            if win.silence_flag { f.push('~'); }
+            if win.idle_flag { f.push('?'); }
             f
         }
         "window_id" => format!("@{}", win.id),
         "window_activity_flag" => if win.activity_flag { "1".into() } else { "0".into() },
         "window_zoomed_flag" => if app.zoom_saved.is_some() && win_idx == app.active_idx { "1".into() } else { "0".into() },
         "window_layout" | "window_visible_layout" => generate_window_layout(&win.root, app.last_window_area),
-        "window_width" => app.last_window_area.width.to_string(),
-        "window_height" => app.last_window_area.height.to_string(),
+        "window_width" => crate::tree::window_size(app).0.to_string(),
+        "window_height" => crate::tree::window_size(app).1.to_string(),
         "window_format" => "1".into(),
-        "window_activity" => app.created_at.timestamp().to_string(),
+        "window_activity" => win.activity_at.timestamp().to_string(),
+        "window_created" => win.created_at.timestamp().to_string(),
+        "window_last_visited" => win.visited_at.timestamp().to_string(),
         "window_silence_flag" => if win.silence_flag { "1".into() } else { "0".into() },
         "window_bell_flag" => if win.bell_flag { "1".into() } else { "0".into() },
         "window_linked" => "0".into(),
@@ -998,28 +1308,13 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
         }
         "pane_active" => if fmt_pane_is_active { "1".into() } else { "0".into() },
         "pane_current_command" => {
-            if let Some(p) = target_pane() {
-                if let Some(pid) = p.child_pid {
-                    crate::platform::process_info::get_foreground_process_name(pid)
-                        .unwrap_or_else(|| "shell".into())
-                } else if !p.title.is_empty() {
-                    p.title.clone()
-                } else {
-                    "shell".into()
-                }
-            } else { String::new() }
+            target_pane().map_or(String::new(), crate::pane::pane_current_command)
+        }
+        "pane_current_command_full" => {
+            target_pane().map_or(String::new(), |p| crate::pane::pane_command_full(p, &app.process_lookup))
         }
         "pane_current_path" | "pane_path" => {
-            if let Some(p) = target_pane() {
-                if let Some(pid) = p.child_pid {
-                    crate::platform::process_info::get_foreground_cwd(pid)
-                        .unwrap_or_default()
-                } else {
-                    std::env::current_dir()
-                        .map(|d| d.to_string_lossy().into_owned())
-                        .unwrap_or_default()
-                }
-            } else { String::new() }
+            target_pane().map_or(String::new(), |p| crate::pane::pane_current_path(p, &app.process_lookup))
         }
         "pane_pid" => {
             if let Some(p) = target_pane() {
@@ -1039,13 +1334,26 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
             Mode::ClockMode => "clock-mode".into(),
             _ => String::new(),
         },
-        "pane_synchronized" => if app.sync_input { "1".into() } else { "0".into() },
+        "pane_synchronized" => if crate::scoped::synchronize_panes(app, win) { "1".into() } else { "0".into() },
+        // View scrolled back without copy mode (scroll-without-copy-mode)
+        "pane_scrolled" => {
+            let off = target_pane().map_or(0, crate::window_ops::view_offset);
+            if off > 0 { "1".into() } else { "0".into() }
+        }
+        "pane_scroll_position" => {
+            if fmt_pane_is_active && matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. }) {
+                app.copy_scroll_offset.to_string()
+            } else {
+                target_pane().map_or(0, crate::window_ops::view_offset).to_string()
+            }
+        }
         "pane_dead" => {
             if let Some(p) = target_pane() {
                 if p.dead { "1".into() } else { "0".into() }
             } else { "0".into() }
         }
-        "pane_dead_signal" | "pane_dead_status" | "pane_dead_time" => "0".into(),
+        "pane_dead_status" => target_pane().and_then(|p| p.exit_status.filter(|_| p.dead)).map_or(String::new(), |c| c.to_string()),
+        "pane_dead_signal" | "pane_dead_time" => "0".into(),
         "pane_format" => "1".into(),
         "pane_input_off"
         | "pane_pipe" | "pane_unseen_changes" => "0".into(),
@@ -1060,99 +1368,28 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
             "0".into()
         }
         "pane_marked" => {
-            if let Some(p) = target_pane() {
-                if let Some((mw, mp)) = app.marked_pane {
-                    if mw == win_idx && mp == p.id { "1".into() } else { "0".into() }
-                } else { "0".into() }
-            } else { "0".into() }
+            if target_pane().map_or(false, |p| p.marked) { "1".into() } else { "0".into() }
         }
-        "pane_marked_set" => {
-            if app.marked_pane.is_some() { "1".into() } else { "0".into() }
-        }
-        "pane_left" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) { rect.x.to_string() } else { "0".into() }
-            } else { "0".into() }
-        }
-        "pane_top" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) { rect.y.to_string() } else { "0".into() }
-            } else { "0".into() }
-        }
-        "pane_right" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) { (rect.x + rect.width).saturating_sub(1).to_string() } else { "79".into() }
-            } else { "79".into() }
-        }
-        "pane_bottom" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) { (rect.y + rect.height).saturating_sub(1).to_string() } else { "23".into() }
-            } else { "23".into() }
-        }
-        "pane_at_top" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) {
-                    if rect.y == app.last_window_area.y { "1".into() } else { "0".into() }
-                } else { "1".into() }
-            } else { "1".into() }
-        }
-        "pane_at_bottom" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) {
-                    let bottom = rect.y + rect.height;
-                    let win_bottom = app.last_window_area.y + app.last_window_area.height;
-                    if bottom >= win_bottom { "1".into() } else { "0".into() }
-                } else { "1".into() }
-            } else { "1".into() }
-        }
-        "pane_at_left" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) {
-                    if rect.x == app.last_window_area.x { "1".into() } else { "0".into() }
-                } else { "1".into() }
-            } else { "1".into() }
-        }
-        "pane_at_right" => {
-            if let Some(p) = target_pane() {
-                let mut rects = Vec::new();
-                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
-                if let Some((_, rect)) = rects.iter().find(|(path, _)| {
-                    crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id)
-                }) {
-                    let right = rect.x + rect.width;
-                    let win_right = app.last_window_area.x + app.last_window_area.width;
-                    if right >= win_right { "1".into() } else { "0".into() }
-                } else { "1".into() }
-            } else { "1".into() }
+        "pane_highlight_count" => {
+            target_pane().map_or(0, |p| crate::highlight::count(app, win, p)).to_string()
         }
+        "pane_marked_set" => {
+            if crate::tree::marked_pane(app).is_some() { "1".into() } else { "0".into() }
+        }
+        "pane_left" => pane_rect(app, win, target_pane()).map_or("0".into(), |r| r.x.to_string()),
+        "pane_top" => pane_rect(app, win, target_pane()).map_or("0".into(), |r| r.y.to_string()),
+        "pane_right" => pane_rect(app, win, target_pane())
+            .map_or("79".into(), |r| (r.x + r.width).saturating_sub(1).to_string()),
+        "pane_bottom" => pane_rect(app, win, target_pane())
+            .map_or("23".into(), |r| (r.y + r.height).saturating_sub(1).to_string()),
+        // Against the window area; a pane that cannot be found counts as
+        // touching every edge, as a window's only pane does
+        "pane_at_top" => flag(pane_rect(app, win, target_pane()).map_or(true, |r| r.y <= app.last_window_area.y)),
+        "pane_at_bottom" => flag(pane_rect(app, win, target_pane())
+            .map_or(true, |r| r.y + r.height >= app.last_window_area.y + app.last_window_area.height)),
+        "pane_at_left" => flag(pane_rect(app, win, target_pane()).map_or(true, |r| r.x <= app.last_window_area.x)),
+        "pane_at_right" => flag(pane_rect(app, win, target_pane())
+            .map_or(true, |r| r.x + r.width >= app.last_window_area.x + app.last_window_area.width)),
         "pane_search_string" => app.copy_search_query.clone(),
         "pane_start_command" => app.default_shell.clone(),
         "pane_start_path" | "pane_tabs" => String::new(),
@@ -1272,33 +1509,55 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
         // ── Buffer ──
         "buffer_size" => {
             let idx = BUFFER_IDX_OVERRIDE.get().unwrap_or(0);
-            app.paste_buffers.get(idx).map(|b| b.len().to_string()).unwrap_or("0".into())
+            app.paste_buffers.at(idx).map(|b| b.text.len().to_string()).unwrap_or("0".into())
         }
         "buffer_sample" => {
             let idx = BUFFER_IDX_OVERRIDE.get().unwrap_or(0);
-            app.paste_buffers.get(idx).map(|b| b.chars().take(50).collect::<String>()).unwrap_or_default()
+            app.paste_buffers.at(idx).map(|b| crate::buffers::sample(&b.text)).unwrap_or_default()
         }
         "buffer_name" => {
             let idx = BUFFER_IDX_OVERRIDE.get().unwrap_or(0);
-            if idx < app.paste_buffers.len() { format!("buffer{:04}", idx) } else { String::new() }
+            app.paste_buffers.at(idx).map(|b| b.name.clone()).unwrap_or_default()
         }
         "buffer_created" => app.created_at.timestamp().to_string(),
 
         // ── Client ──
-        "client_width" => app.last_window_area.width.to_string(),
-        "client_height" => (app.last_window_area.height + if app.status_visible { 1 } else { 0 }).to_string(),
-        "client_session" | "client_last_session" => app.session_name.clone(),
-        "client_name" | "client_tty" => "client0".into(),
+        // The terminal of the client typing, once it has reported its size
+        "client_width" => match client_size(app) {
+            Some((w, _)) => w.to_string(),
+            None => app.last_window_area.width.to_string(),
+        },
+        "client_height" => match client_size(app) {
+            Some((_, h)) => h.to_string(),
+            None => (crate::tree::window_size(app).1 + if app.status_visible { 1 } else { 0 }).to_string(),
+        },
+        "client_session" => app.session_name.clone(),
+        "client_last_session" => crate::bandwidth::current_client(app).and_then(|c| c.last_session.as_deref())
+            .map(|l| l.split_once("__").map_or(l, |(_, name)| name).to_string())
+            .unwrap_or_default(),
+        "client_name" | "client_tty" => format!("client{}", crate::bandwidth::current_client(app).map_or(0, |c| c.id)),
         "client_pid" => std::process::id().to_string(),
-        "client_prefix" => match app.mode { Mode::Prefix { .. } => "1".into(), _ => "0".into() },
-        "client_activity" | "client_created" => app.created_at.timestamp().to_string(),
-        "client_activity_string" | "client_created_string" => app.created_at.format("%a %b %e %H:%M:%S %Y").to_string(),
+        "client_prefix" => {
+            let armed = matches!(app.mode, Mode::Prefix { .. })
+                || crate::bandwidth::current_client(app).is_some_and(|c| c.prefix_armed);
+            if armed { "1".into() } else { "0".into() }
+        }
+        "client_activity" => app.created_at.timestamp().to_string(),
+        "client_activity_string" => app.created_at.format("%a %b %e %H:%M:%S %Y").to_string(),
+        "client_created" => crate::bandwidth::current_client(app).map_or(app.created_at, |c| c.attached_at).timestamp().to_string(),
+        "client_created_string" => crate::bandwidth::current_client(app).map_or(app.created_at, |c| c.attached_at)
+            .format("%a %b %e %H:%M:%S %Y").to_string(),
         "client_control_mode" => "0".into(),
-        "client_flags" => "focused".into(),
-        "client_key_table" => match app.mode {
-            Mode::Prefix { .. } => "prefix".into(),
-            Mode::CopyMode => "copy-mode-vi".into(),
-            _ => "root".into(),
+        "client_flags" => crate::bandwidth::current_client(app)
+            .map_or_else(|| crate::bandwidth::client_flags(false, false),
+                |c| crate::bandwidth::client_flags(c.low_bandwidth.load(std::sync::atomic::Ordering::Relaxed), c.no_status)),
+        "client_key_table" => match (&app.current_key_table, &app.mode) {
+            (Some(table), _) => table.clone(),
+            (None, Mode::Prefix { .. }) => "prefix".into(),
+            (None, _) if crate::bandwidth::current_client(app).is_some_and(|c| c.prefix_armed) => "prefix".into(),
+            (None, Mode::CopyMode | Mode::CopySearch { .. }) if app.mode_keys == "emacs" => "copy-mode".into(),
+            (None, Mode::CopyMode | Mode::CopySearch { .. }) => "copy-mode-vi".into(),
+            (None, _) => "root".into(),
         },
         "client_termname" | "client_termtype" => env::var("TERM").unwrap_or_else(|_| "xterm-256color".into()),
         "client_termfeatures" => "256,RGB,title".into(),
@@ -1311,7 +1570,10 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
         "host" | "hostname" => hostname_cached(),
         "host_short" => { let h = hostname_cached(); h.split('.').next().unwrap_or(&h).to_string() }
         "pid" | "server_pid" => std::process::id().to_string(),
-        "version" => VERSION.to_string(),
+        // The tmux version plugins compare against (`compat-version`);
+        // psmux's own is psmux_version
+        "version" => app.compat_version.clone(),
+        "psmux_version" => VERSION.to_string(),
         "start_time" => app.created_at.timestamp().to_string(),
         "socket_path" => {
             let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
@@ -1325,7 +1587,10 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
         "status" => if app.status_visible { "on".into() } else { "off".into() },
         "mode_keys" => app.mode_keys.clone(),
         "history_limit" => app.history_limit.to_string(),
-        "history_size" => app.history_limit.to_string(),
+        "history_size" => active_pane(&win.root, &win.active_path)
+            .and_then(|p| p.term.lock().ok().map(|mut parser| crate::copy_mode::history_size(&mut parser)))
+            .unwrap_or(0).to_string(),
+        "last_shell_exit" => app.last_shell_exit.to_string(),
         "alternate_on" => {
             if let Some(p) = active_pane(&win.root, &win.active_path) {
                 if let Ok(parser) = p.term.lock() {
@@ -1343,12 +1608,50 @@ pub fn expand_var(var: &str, app: &AppState, win_idx: usize) -> String {
 
         // Anything else: try as option, then env
         _ => {
-            if let Some(val) = lookup_option(var, app) { val }
+            if let Some(val) = lookup_option(var, app, win_idx) { val }
             else { String::new() }
         }
     }
 }
 
+/// Where pane `p` of `win` is drawn within the window area.
+fn pane_rect(app: &AppState, win: &crate::types::Window, p: Option<&crate::types::Pane>) -> Option<ratatui::layout::Rect> {
+    let p = p?;
+    let mut rects = Vec::new();
+    crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
+    rects.into_iter()
+        .find(|(path, _)| crate::tree::get_active_pane_id_at_path(&win.root, path) == Some(p.id))
+        .map(|(_, rect)| rect)
+}
+
+fn client_size(app: &AppState) -> Option<(u16, u16)> {
+    crate::bandwidth::current_client(app).map(|c| c.size).filter(|&(w, h)| w > 0 && h > 0)
+}
+
+fn flag(b: bool) -> String {
+    if b { "1".into() } else { "0".into() }
+}
+
+/// `display-message -a`: every format variable with its value in the
+/// current context, as sorted `name=value` lines.
+pub fn list_format_variables(app: &AppState, win_idx: usize) -> String {
+    let mut vars: Vec<(String, String)> = FORMAT_VARIABLES.iter()
+        .map(|v| (v.to_string(), expand_var(v, app, win_idx)))
+        .collect();
+    for ctx in ["key", "hook"] {
+        let v = expand_var(ctx, app, win_idx);
+        if !v.is_empty() { vars.push((ctx.to_string(), v)); }
+    }
+    HOOK_CONTEXT.with(|h| if let Some((_, bound)) = h.borrow().as_ref() {
+        vars.extend(bound.iter().filter(|(k, _)| k.starts_with("hook_")).cloned());
+    });
+    vars.extend(app.environment.iter()
+        .filter(|(k, _)| k.starts_with('@'))
+        .map(|(k, v)| (k.clone(), v.clone())));
+    vars.sort();
+    vars.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("\n")
+}
+
 // ─────────────────── helper utilities ────────────────────────────
 
 fn hostname_cached() -> String {
@@ -1378,6 +1681,48 @@ fn find_matching_brace(s: &str, start: usize) -> Option<usize> {
     None
 }
 
+/// `\N` in an `s/` replacement.
+static BACKREF: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"\\([0-9])").unwrap());
+
+/// `=N`: the first N shown characters of `value` (the last -N when N is
+/// negative), or `None` when it is no longer than that.  `#[...]` styles
+/// are not counted and all of them are kept, so a theme's colours survive
+/// the cut.
+fn trim_visible(value: &str, n: i32) -> Option<String> {
+    let mut tokens: Vec<(bool, &str)> = Vec::new();
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("##") {
+            tokens.push((true, "#"));
+            tokens.push((true, "#"));
+            rest = &rest[2..];
+            continue;
+        } else if rest.starts_with("#[") {
+            match rest.find(']') { Some(end) => { tokens.push((false, &rest[..=end])); end + 1 } None => rest.len() }
+        } else {
+            tokens.push((true, &rest[..c.len_utf8()]));
+            c.len_utf8()
+        };
+        rest = &rest[len..];
+    }
+    let shown = tokens.iter().filter(|(visible, _)| *visible).count();
+    let len = n.unsigned_abs() as usize;
+    if n == 0 || shown <= len { return None; }
+    let keep = |i: usize| if n > 0 { i < len } else { i >= shown - len };
+    let mut out = String::with_capacity(value.len());
+    let mut i = 0;
+    for (visible, text) in tokens {
+        if visible {
+            if keep(i) { out.push_str(text); }
+            i += 1;
+        } else {
+            out.push_str(text);
+        }
+    }
+    Some(out)
+}
+
 fn split_at_depth0(s: &str, delim: u8) -> Vec<String> {
     let bytes = s.as_bytes();
     let mut parts = Vec::new();
@@ -1450,7 +1795,7 @@ fn glob_match_impl(pattern: &[u8], text: &[u8]) -> bool {
 
 /// Default format for list-windows (tmux-style one-per-line).
 pub fn default_list_windows_format() -> &'static str {
-    "#{window_index}: #{window_name}#{window_flags} (#{window_panes} panes) [#{window_width}x#{window_height}]"
+    "#{window_index}: #{window_name}#{window_flags} (#{window_panes} panes) [#{window_width}x#{window_height}] [layout #{window_layout}]"
 }
 
 /// Default format for list-panes.
@@ -1493,7 +1838,7 @@ pub fn format_list_panes(app: &AppState, fmt: &str, win_idx: usize) -> String {
     }).collect::<Vec<_>>().join("\n")
 }
 
-fn collect_pane_ids(node: &Node, ids: &mut Vec<usize>) {
+pub(crate) fn collect_pane_ids(node: &Node, ids: &mut Vec<usize>) {
     match node {
         Node::Leaf(p) => ids.push(p.id),
         Node::Split { children, .. } => {
@@ -1569,7 +1914,7 @@ mod tests {
     fn test_math_add() {
         let app = mock_app();
         let val = apply_modifier(
-            &Modifier::MathExpr { op: '+', floating: false, decimals: 0 },
+            &Modifier::MathExpr { op: "+".into(), floating: false, decimals: 0 },
             "3,5", &app, 0
         );
         assert_eq!(val, "8");
@@ -1579,7 +1924,7 @@ mod tests {
     fn test_math_float_div() {
         let app = mock_app();
         let val = apply_modifier(
-            &Modifier::MathExpr { op: '/', floating: true, decimals: 4 },
+            &Modifier::MathExpr { op: "/".into(), floating: true, decimals: 4 },
             "10,3", &app, 0
         );
         assert_eq!(val, "3.3333");
@@ -1605,6 +1950,56 @@ mod tests {
         assert_eq!(expand_expression("==:version,version", &app, 0), "1");
     }
 
+    #[test]
+    fn strftime_codes_are_translated_for_chrono() {
+        assert_eq!(translate_strftime("%H:%M:%S %d-%b-%y"), "%H:%M:%S %d-%b-%y");
+        assert_eq!(translate_strftime("%-I%p %Ec %^a %#Z"), "%-I%p %c %a %Z");
+        // Unknown conversions stay as text; escaped percents stay escaped
+        assert_eq!(translate_strftime("%Q 100%% %"), "%%Q 100%% %%");
+        let app = mock_app();
+        let year = chrono::Local::now().format("%Y").to_string();
+        assert_eq!(expand_format("%Q %Y", &app), format!("%Q {}", year));
+        assert_eq!(expand_format("%EY", &app), year);
+    }
+
+    #[test]
+    fn status_refreshes_every_second_only_when_it_shows_seconds() {
+        use std::time::Duration;
+        let mut app = mock_app();
+        app.status_right = "%H:%M %d-%b-%y".into();
+        assert_eq!(status_refresh_interval(&app), Some(Duration::from_secs(15)));
+        app.status_right = "%H:%M:%S".into();
+        assert_eq!(status_refresh_interval(&app), Some(Duration::from_secs(1)));
+        app.status_right = "100%%S #{session_name}".into();
+        assert_eq!(status_refresh_interval(&app), Some(Duration::from_secs(15)));
+        app.status_format = vec!["#[align=right]%T".into()];
+        assert_eq!(status_refresh_interval(&app), Some(Duration::from_secs(1)));
+        app.status_interval = 0;
+        assert_eq!(status_refresh_interval(&app), None);
+    }
+
+    #[test]
+    fn theme_strings() {
+        let mut app = mock_app();
+        app.environment.insert("@thm_blue".into(), "#89b4fa".into());
+        app.environment.insert("@thm_crust".into(), "#11111b".into());
+        // catppuccin
+        assert_eq!(expand_format("#[fg=#{@thm_crust},bg=#{@thm_blue}]#{?client_prefix,#[reverse],} #S ", &app),
+            "#[fg=#11111b,bg=#89b4fa] test_session ");
+        assert_eq!(expand_format("#{?window_zoomed_flag,(),}#{=/6/…:session_name}", &app), "test_s…");
+        // tmux-powerline
+        assert_eq!(expand_format("#{?#{==:#{session_name},test_session},#[fg=colour16],#[fg=colour255]}", &app), "#[fg=colour16]");
+        assert_eq!(expand_format("#{?#{&&:#{session_name},#{!=:#{session_name},main}},a#,b,c}", &app), "a,b");
+        assert_eq!(expand_format("#{||:#{==:#{session_name},x},#{m:*_s*,#{session_name}}}", &app), "1");
+        // Styles are not counted by =N, and all of them stay
+        assert_eq!(expand_format("#{=4:#{l:#[bold]abcdef#[default]}}", &app), "#[bold]abcd#[default]");
+        assert_eq!(expand_format("#{=-2:#{l:#[bold]abcdef}}", &app), "#[bold]ef");
+        // Back-references, numeric comparisons, literal hashes, unknown variables
+        assert_eq!(expand_format("#{s/(test)_(.*)/\\2-\\1/:session_name}", &app), "session-test");
+        assert_eq!(expand_format("#{e|<:9,10} #{<:9,10} #{e|>=|f:2.5,2.50}", &app), "1 0 1");
+        assert_eq!(expand_format("a####b [#{no_such_variable}]", &app), "a##b []");
+    }
+
     #[test]
     fn test_glob_match_fn() {
         assert!(glob_match("*foo*", "barfoobar", false));
@@ -1612,10 +2007,266 @@ mod tests {
         assert!(glob_match("*FOO*", "barfoobar", true));
     }
 
+    /// Names in the `expand_var` match arms, read from this file's source.
+    fn expand_var_arms() -> std::collections::BTreeSet<String> {
+        let src = include_str!("format.rs");
+        let start = src.find("fn var_value(").unwrap();
+        let end = start + src[start..].find("\n}\n").unwrap();
+        let mut names = std::collections::BTreeSet::new();
+        let mut arm = String::new();
+        for line in src[start..end].lines().map(str::trim) {
+            if line.starts_with('"') || (!arm.is_empty() && line.starts_with("| \"")) {
+                arm.push_str(line);
+                if let Some((head, _)) = arm.split_once("=>") {
+                    names.extend(head.split('|').map(|n| n.trim().trim_matches('"').to_string()));
+                    arm.clear();
+                }
+            } else {
+                arm.clear();
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn variable_table_matches_expand_var() {
+        let table: std::collections::BTreeSet<String> = FORMAT_VARIABLES.iter().map(|v| v.to_string()).collect();
+        assert_eq!(table.len(), FORMAT_VARIABLES.len(), "duplicate in FORMAT_VARIABLES");
+        assert!(FORMAT_VARIABLES.windows(2).all(|w| w[0] < w[1]), "FORMAT_VARIABLES not sorted");
+        let arms = expand_var_arms();
+        assert!(arms.len() > 100);
+        assert_eq!(table, arms);
+    }
+
+    #[test]
+    fn display_all_lists_values_for_the_context() {
+        let mut app = mock_app();
+        app.windows.push(crate::types::Window::bare(7, 3, "editor"));
+        app.environment.insert("@theme".into(), "dark".into());
+        set_key_override(Some("C-b".into()));
+        set_hook_context(Some(("after-new-window".into(), vec![("hook_window".into(), "@7".into())])));
+        let out = list_format_variables(&app, 0);
+        set_key_override(None);
+        set_hook_context(None);
+        let lines: Vec<&str> = out.lines().collect();
+        for want in ["session_name=test_session", "window_index=3", "window_name=editor", "window_id=@7",
+                     "window_active=1", "@theme=dark", "key=C-b", "hook=after-new-window", "hook_window=@7", "mouse=on"] {
+            assert!(lines.contains(&want), "missing {}", want);
+        }
+        let names: Vec<&str> = lines.iter().map(|l| l.split_once('=').unwrap().0).collect();
+        assert!(names.windows(2).all(|w| w[0] < w[1]), "not sorted");
+        assert_eq!(names.len(), FORMAT_VARIABLES.len() + 4);
+        // Context variables only appear while set
+        assert!(!list_format_variables(&app, 0).lines().any(|l| l.starts_with("key=") || l.starts_with("hook=")));
+    }
+
+    #[test]
+    fn sizes_and_edges_for_a_two_pane_layout() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = mock_app();
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        app.last_window_area = ratatui::layout::Rect::new(0, 0, 81, 23);
+        crate::tree::resize_all_panes(&mut app);
+
+        const F: &str = "#{pane_width}x#{pane_height} #{pane_left},#{pane_top}-#{pane_right},#{pane_bottom} \
+            #{pane_at_top}#{pane_at_bottom}#{pane_at_left}#{pane_at_right}";
+        assert_eq!(expand_format_for_pane(F, &app, 0, 0), "40x23 0,0-39,22 1110");
+        assert_eq!(expand_format_for_pane(F, &app, 0, 1), "40x23 41,0-80,22 1101");
+        assert_eq!(expand_format_for_window("#{window_width}x#{window_height}", &app, 0), "81x23");
+
+        // The client's own size once it has sent one, the window plus the
+        // status line before that
+        assert_eq!(expand_format("#{client_width}x#{client_height}", &app), "81x24");
+        app.clients.push(crate::types::ClientConn { size: (100, 30), ..crate::types::ClientConn::test(1) });
+        assert_eq!(expand_format("#{client_width}x#{client_height}", &app), "100x30");
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    /// A window-status-format of the kind themes use.
+    const THEMED_TAB: &str = "#{?window_active,#[fg=colour231 bold],#[fg=colour244]} #I:#{=/12/...:#{s/-/ /:window_name}}#{?window_zoomed_flag, Z,}#{?#{==:#{@theme},dark}, ●,}#F ";
+
+    #[test]
+    fn cached_expansions_follow_what_they_read() {
+        let mut app = mock_app();
+        for i in 0..3 { app.windows.push(crate::types::Window::bare(i, i, &format!("win-{}", i))); }
+        let mut cache = FormatCache::default();
+        let tabs = |cache: &mut FormatCache, app: &AppState| -> Vec<String> {
+            (0..app.windows.len()).map(|i| cache.expand(THEMED_TAB, app, i)).collect()
+        };
+        let fresh = |app: &AppState| -> Vec<String> {
+            (0..app.windows.len()).map(|i| expand_format_for_window(THEMED_TAB, app, i)).collect()
+        };
+
+        assert_eq!(tabs(&mut cache, &app), fresh(&app));
+        assert_eq!(tabs(&mut cache, &app), fresh(&app));
+        assert_eq!((cache.hits, cache.misses), (3, 3));
+
+        // Each change is seen by the tabs that read it, and only those
+        app.windows[1].name = "renamed-window-1".into();
+        assert_eq!(tabs(&mut cache, &app), fresh(&app));
+        assert_eq!((cache.hits, cache.misses), (5, 4));
+        app.active_idx = 2;
+        app.last_window_idx = 0;
+        assert_eq!(tabs(&mut cache, &app), fresh(&app));
+        app.environment.insert("@theme".into(), "dark".into());
+        assert_eq!(tabs(&mut cache, &app), fresh(&app));
+        assert!(tabs(&mut cache, &app)[0].contains('●'));
+
+        // Shorthands, and loops over the windows
+        assert_eq!(cache.expand("#S #W #{W:#I}", &app, 0), "test_session win-0 0 1 2");
+        app.windows.push(crate::types::Window::bare(3, 7, "new"));
+        app.session_name = "other".into();
+        assert_eq!(cache.expand("#S #W #{W:#I}", &app, 0), "other win-0 0 1 2 7");
+
+        // A different format is a different entry
+        app.window_status_format = "#I".into();
+        assert_eq!(cache.expand(&app.window_status_format, &app, 3), "7");
+
+        // Pane content is never reused
+        let before = cache.hits;
+        cache.expand("#{C:x}", &app, 0);
+        cache.expand("#{C:x}", &app, 0);
+        assert_eq!(cache.hits, before);
+    }
+
+    /// Expansions per second of 30 themed window tabs, without and with
+    /// the cache:
+    /// `cargo test --release format_cache_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn format_cache_benchmark() {
+        let mut app = mock_app();
+        for i in 0..30 { app.windows.push(crate::types::Window::bare(i, i, &format!("project-window-{}", i))); }
+        let rounds = 2000;
+        let per_second = |f: &mut dyn FnMut()| {
+            let start = std::time::Instant::now();
+            for _ in 0..rounds { f(); }
+            (rounds * app.windows.len()) as f64 / start.elapsed().as_secs_f64()
+        };
+        let uncached = per_second(&mut || for i in 0..30 { std::hint::black_box(expand_format_for_window(THEMED_TAB, &app, i)); });
+        let mut cache = FormatCache::default();
+        let cached = per_second(&mut || for i in 0..30 { std::hint::black_box(cache.expand(THEMED_TAB, &app, i)); });
+        println!("uncached: {:.0} expansions/s, cached: {:.0} expansions/s ({:.1}x)", uncached, cached, cached / uncached);
+        assert!(cached > uncached);
+    }
+
+    #[test]
+    fn version_reports_the_compat_version() {
+        let mut app = mock_app();
+        assert_eq!(expand_format("#{version} #{psmux_version}", &app), format!("{} {}", crate::types::TMUX_COMPAT_VERSION, VERSION));
+        app.compat_version = "3.4".into();
+        // A plugin's `#{version}` check, as in `if -F '#{>=:#{version},3.2}'`
+        assert_eq!(expand_format("#{version}", &app), "3.4");
+        assert_eq!(expand_format("#{>=:#{version},3.2}", &app), "1");
+        assert_eq!(expand_format("#{compat-version}", &app), "3.4");
+    }
+
     #[test]
     fn test_quote() {
         let app = mock_app();
         let val = apply_modifier(&Modifier::Quote, "(hello)", &app, 0);
         assert_eq!(val, "\\(hello\\)");
     }
+
+    mod command_line {
+        use super::*;
+        use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+        static FOREGROUND: AtomicU32 = AtomicU32::new(200);
+        static READS: AtomicUsize = AtomicUsize::new(0);
+
+        fn foreground_pid(_shell: u32) -> u32 { FOREGROUND.load(Ordering::SeqCst) }
+        fn command_line(pid: u32) -> Option<String> {
+            READS.fetch_add(1, Ordering::SeqCst);
+            // pid 300 is elevated: reading its command line is denied
+            (pid == 200).then(|| r#""C:\Python311\Scripts\pytest.exe" tests/foo"#.to_string())
+        }
+        fn name(pid: u32) -> Option<String> { Some(if pid == 300 { "installer" } else { "pytest" }.into()) }
+
+        #[test]
+        fn full_command_is_read_once_per_foreground_pid() {
+            let pty = portable_pty::native_pty_system();
+            let mut app = mock_app();
+            app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+            crate::pane::create_window(&*pty, &mut app, None).unwrap();
+            app.process_lookup = crate::pane::ProcessLookup { foreground_pid, command_line, name, cwd: |_| None };
+            {
+                let win = &mut app.windows[0];
+                crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().child_pid = Some(100);
+            }
+            assert_eq!(expand_format("#{pane_current_command_full}", &app), "pytest tests/foo");
+            assert_eq!(expand_format("#{pane_current_command_full}", &app), "pytest tests/foo");
+            assert_eq!(READS.load(Ordering::SeqCst), 1);
+            // What automatic-rename-format can make of it
+            app.automatic_rename_format = "#{s/ /:/:pane_current_command_full}".into();
+            assert_eq!(expand_format_for_window(&app.automatic_rename_format, &app, 0), "pytest:tests/foo");
+            assert_eq!(READS.load(Ordering::SeqCst), 1);
+            // A new foreground process is read again; a denied read falls back to the name
+            FOREGROUND.store(300, Ordering::SeqCst);
+            assert_eq!(expand_format("#{pane_current_command_full}", &app), "installer");
+            assert_eq!(expand_format("#{pane_current_command_full}", &app), "installer");
+            assert_eq!(READS.load(Ordering::SeqCst), 2);
+
+            for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+        }
+
+        mod current_path {
+            use super::*;
+
+            static CWD_READS: AtomicUsize = AtomicUsize::new(0);
+            static READABLE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+            fn foreground_pid(_shell: u32) -> u32 { 200 }
+            fn cwd(pid: u32) -> Option<String> {
+                CWD_READS.fetch_add(1, Ordering::SeqCst);
+                // The shell (100) never lets its directory be read
+                (pid == 200 && READABLE.load(Ordering::SeqCst)).then(|| r"C:\work\api".to_string())
+            }
+
+            #[test]
+            fn current_path_is_cached_and_falls_back_to_the_reported_directory() {
+                let pty = portable_pty::native_pty_system();
+                let mut app = mock_app();
+                app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+                crate::pane::create_window(&*pty, &mut app, None).unwrap();
+                app.process_lookup = crate::pane::ProcessLookup { foreground_pid, command_line, name, cwd };
+                let reported = {
+                    let win = &mut app.windows[0];
+                    let p = crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap();
+                    p.child_pid = Some(100);
+                    p.reported_cwd.clone()
+                };
+                CWD_READS.store(0, Ordering::SeqCst);
+                assert_eq!(expand_format("#{pane_current_path}", &app), r"C:\work\api");
+                assert_eq!(expand_format("#{pane_current_path}", &app), r"C:\work\api");
+                assert_eq!(CWD_READS.load(Ordering::SeqCst), 1);
+
+                // Neither the foreground process nor the shell can be read: the
+                // directory the shell last reported is used
+                *reported.lock().unwrap() = Some(r"D:\src".to_string());
+                READABLE.store(false, Ordering::SeqCst);
+                {
+                    let win = &mut app.windows[0];
+                    crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().process_info = Default::default();
+                }
+                assert_eq!(expand_format("#{pane_current_path}", &app), r"D:\src");
+
+                for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+            }
+        }
+
+        #[test]
+        fn command_lines_are_shortened_for_display() {
+            use crate::pane::short_command_line;
+            assert_eq!(short_command_line(r#""C:\Program Files\nodejs\node.exe" server.js --port 80"#, 48), "node server.js --port 80");
+            assert_eq!(short_command_line("/usr/bin/python3 -m http.server", 48), "python3 -m http.server");
+            assert_eq!(short_command_line("vim.EXE", 48), "vim");
+            let long = short_command_line("cargo test --workspace --all-features -- --nocapture --test-threads 1", 20);
+            assert_eq!(long, "cargo test --worksp…");
+            assert_eq!(long.chars().count(), 20);
+        }
+    }
 }
@@ -4,6 +4,9 @@
 // bloat existing source files.
 // ─────────────────────────────────────────────────────────────────────
 
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::config::{normalize_key_for_binding, parse_key_string};
+
 /// Default prefix-table keybindings.
 /// Each entry is `(key_string, command_string)`.
 /// The overlay and `list-keys` both use this as the canonical source
@@ -15,7 +18,7 @@ pub const PREFIX_DEFAULTS: &[(&str, &str)] = &[
     ("p",       "previous-window"),
     ("l",       "last-window"),
     ("w",       "choose-tree"),
-    ("&",       "kill-window"),
+    ("&",       crate::prompt::KILL_WINDOW),
     (",",       "rename-window"),
     ("0",       "select-window -t :0"),
     ("1",       "select-window -t :1"),
@@ -42,7 +45,7 @@ pub const PREFIX_DEFAULTS: &[(&str, &str)] = &[
     ("q",       "display-panes"),
 
     // ── Pane management ──
-    ("x",       "kill-pane"),
+    ("x",       crate::prompt::KILL_PANE),
     ("z",       "resize-pane -Z"),
     ("{",       "swap-pane -U"),
     ("}",       "swap-pane -D"),
@@ -70,6 +73,8 @@ pub const PREFIX_DEFAULTS: &[(&str, &str)] = &[
 
     // ── Session ──
     ("d",       "detach-client"),
+    ("D",       "choose-client"),
+    ("C-z",     "suspend-client"),
     ("$",       "rename-session"),
 
     // ── Copy / Paste ──
@@ -82,8 +87,28 @@ pub const PREFIX_DEFAULTS: &[(&str, &str)] = &[
     ("?",       "list-keys"),
     ("i",       "display-message"),
     ("t",       "clock-mode"),
+    ("R",       "refresh-client"),
+    ("F11",     "refresh-client -f no-status"),
+];
+
+/// The defaults bound with `-r`: after one, the next of them within
+/// repeat-time needs no prefix.
+pub const REPEAT_DEFAULTS: &[&str] = &[
+    "C-Up", "C-Down", "C-Left", "C-Right", "M-Up", "M-Down", "M-Left", "M-Right",
 ];
 
+/// Whether the default binding of `key` in the prefix table repeats.
+pub fn default_repeats(key: (KeyCode, KeyModifiers)) -> bool {
+    let key = normalize_key_for_binding(key);
+    REPEAT_DEFAULTS.iter().any(|k| parse_key_string(k).map(normalize_key_for_binding) == Some(key))
+}
+
+/// `bind-key -T prefix KEY CMD`, with `-r` for a repeating default.
+fn default_line(key: &str, cmd: &str) -> String {
+    let r = if REPEAT_DEFAULTS.contains(&key) { " -r" } else { "" };
+    format!("bind-key{} -T prefix {} {}", r, key, cmd)
+}
+
 // ─────────────────────────────────────────────────────────────────────
 // Sections below are used *only* by the overlay — they don't affect
 // key dispatching at all (that lives in input.rs).
@@ -114,6 +139,8 @@ const COPY_MODE_VI: &[(&str, &str)] = &[
     ("Down",      "cursor-down"),
     ("Up",        "cursor-up"),
     ("Right",     "cursor-right"),
+    ("S-Left",    "scroll-left (unwrapped view)"),
+    ("S-Right",   "scroll-right (unwrapped view)"),
     // Words
     ("w",         "next-word"),
     ("b",         "previous-word"),
@@ -210,11 +237,13 @@ const COMMAND_PROMPT: &[(&str, &str)] = &[
     ("End",       "move cursor to end"),
     ("Up",        "history: older command"),
     ("Down",      "history: newer command"),
+    ("Tab",       "complete a command name"),
     ("C-a",       "move cursor to start"),
     ("C-e",       "move cursor to end"),
     ("C-u",       "kill line (clear to start)"),
     ("C-k",       "kill to end of line"),
     ("C-w",       "delete word backwards"),
+    ("C-b/C-f",   "move cursor left/right"),
 ];
 
 /// Section: CLI command quick-reference (user-facing commands only).
@@ -237,16 +266,19 @@ pub fn cli_command_lines() -> Vec<String> {
 const CLI_COMMANDS: &[(&str, &str, &str)] = &[
     // Session
     ("attach-session",    "attach",   "Attach to an existing session"),
-    ("detach-client",     "detach",   "Detach from the current session"),
+    ("choose-session",    "",         "Choose a session from every running server"),
+    ("detach-client",     "detach",   "Detach a client (-t client, -a all others)"),
     ("has-session",       "has",      "Check if a session exists"),
     ("kill-server",       "",         "Kill the server and all sessions"),
     ("kill-session",      "",         "Destroy a session"),
     ("list-sessions",     "ls",       "List sessions"),
     ("new-session",       "new",      "Create a new session"),
     ("rename-session",    "rename",   "Rename the current session"),
-    ("switch-client",     "switchc",  "Switch to another session"),
+    ("restore-session",   "",         "Recreate windows from a saved snapshot"),
+    ("save-session",      "",         "Save windows and panes to a snapshot"),
+    ("switch-client",     "switchc",  "Switch to another session (-t name, -n, -p, -l)"),
     // Window
-    ("choose-tree",       "",         "Interactive session/window chooser"),
+    ("choose-tree",       "",         "Choose a window or pane (-f filter, -O order, -s, -Z, template)"),
     ("find-window",       "findw",    "Search for a window by name"),
     ("kill-window",       "killw",    "Destroy the current window"),
     ("last-window",       "last",     "Select the previous window"),
@@ -257,6 +289,7 @@ const CLI_COMMANDS: &[(&str, &str, &str)] = &[
     ("next-window",       "next",     "Move to the next window"),
     ("previous-window",   "prev",     "Move to the previous window"),
     ("rename-window",     "renamew",  "Rename the current window"),
+    ("resize-window",     "resizew",  "Set the window size without a client"),
     ("rotate-window",     "rotatew",  "Rotate pane positions"),
     ("select-window",     "selectw",  "Select a window by index"),
     ("swap-window",       "swapw",    "Swap two windows"),
@@ -281,7 +314,8 @@ const CLI_COMMANDS: &[(&str, &str, &str)] = &[
     ("select-layout",     "selectl",  "Apply a layout preset"),
     // Copy / Paste
     ("choose-buffer",     "chooseb",  "Interactive buffer chooser"),
-    ("clear-history",     "clearhist","Clear pane scrollback"),
+    ("clear-history",     "clearhist","Clear pane scrollback (-H: and the screen)"),
+    ("clear-screen-and-history", "", "Clear the screen and scrollback, redraw the prompt"),
     ("copy-mode",         "",         "Enter copy mode"),
     ("delete-buffer",     "deleteb",  "Delete a paste buffer"),
     ("list-buffers",      "lsb",      "List paste buffers"),
@@ -311,6 +345,7 @@ const CLI_COMMANDS: &[(&str, &str, &str)] = &[
     ("confirm-before",    "confirm",  "Confirm before running command"),
     ("if-shell",          "if",       "Conditional command execution"),
     ("list-clients",      "lsc",      "List connected clients"),
+    ("choose-client",     "",         "Choose a client to detach"),
     ("refresh-client",    "refresh",  "Refresh the client display"),
     ("run-shell",         "run",      "Run a shell command"),
     ("send-keys",         "send",     "Send keys/text to a pane"),
@@ -340,17 +375,27 @@ const OPTIONS_REF: &[(&str, &str)] = &[
     ("prefix2",                    "none"),
     // Behaviour
     ("escape-time",                "500"),
+    ("repeat-time",                "500"),
+    ("prefix-timeout",             "0"),
+    ("confirm-kill",               "on"),
     ("base-index",                 "0"),
     ("pane-base-index",            "0"),
     ("history-limit",              "2000"),
+    ("pattern-timeout",            "250"),
     ("mouse",                      "on"),
     ("mode-keys",                  "emacs"),
     ("focus-events",               "off"),
     ("remain-on-exit",             "off"),
     ("renumber-windows",           "off"),
     ("aggressive-resize",          "off"),
+    ("window-size",                "smallest"),
     ("automatic-rename",           "on"),
+    ("automatic-rename-format",    "#{pane_current_command}"),
+    ("allow-duplicate-window-names", "on"),
+    ("popup-warm-shell",           "on"),
+    ("popup-close-key",            "C-q"),
     ("synchronize-panes",          "off"),
+    ("sync-paste",                 "off"),
     ("set-titles",                 "off"),
     ("allow-passthrough",          "off"),
     ("default-command",            "(system shell)"),
@@ -363,6 +408,8 @@ const OPTIONS_REF: &[(&str, &str)] = &[
     ("status",                     "on"),
     ("status-position",            "bottom"),
     ("status-justify",             "left"),
+    ("prefix-indicator",           "on"),
+    ("prefix-indicator-style",     "reverse"),
     ("status-left",                "\"[#S] \""),
     ("status-right",               "\"#{?window_bigger,[#{window_offset_x}#,#{window_offset_y}] ,}\"#{=21:pane_title}\" %H:%M %d-%b-%y\""),
     ("status-left-length",         "10"),
@@ -382,22 +429,53 @@ const OPTIONS_REF: &[(&str, &str)] = &[
     // Pane borders
     ("pane-border-style",          "\"\""),
     ("pane-active-border-style",   "fg=green"),
+    ("pane-border-lines",          "single"),
+    ("pane-border-indicators",     "colour"),
+    ("pane-border-status",         "off"),
+    ("pane-border-format",         "#{?pane_active,...}#{pane_index}..."),
+    ("highlight-pattern",          "\"\""),
+    ("pane-encoding",              "utf8"),
     // Messages / Modes
     ("message-style",              "bg=yellow,fg=black"),
     ("message-command-style",      "bg=black,fg=yellow"),
     ("mode-style",                 "bg=yellow,fg=black"),
+    ("copy-mode-match-style",      "bg=cyan,fg=black"),
+    ("copy-mode-current-match-style", "bg=magenta,fg=black"),
     // Monitoring
     ("monitor-activity",           "off"),
     ("monitor-silence",            "0"),
+    ("idle-window-timeout",        "0"),
+    ("idle-window-action",         "flag"),
+    ("monitor-bell",               "on"),
     ("visual-activity",            "off"),
+    ("visual-silence",             "off"),
     ("visual-bell",                "off"),
     ("bell-action",                "any"),
+    // Clients
+    ("client-idle-timeout",        "0"),
+    ("client-idle-warning",        "0"),
+    ("client-idle-exempt-local",   "on"),
+    // Prompts
+    ("history-file",               "\"\" (not saved)"),
+    ("prompt-history-limit",       "100"),
+    // Paste buffers
+    ("buffer-limit",               "50"),
+    // Scrolling
+    ("scroll-without-copy-mode",   "off"),
+    ("scrolled-input",             "snap"),
+    // Keys
+    ("root-any-binding",           "off"),
     // Layout
     ("main-pane-width",            "0 (60% heuristic)"),
     ("main-pane-height",           "0 (60% heuristic)"),
     // Copy / Clipboard
     ("copy-command",               "\"\""),
     ("set-clipboard",              "on"),
+    // Compatibility
+    ("command-alias",              "\"\""),
+    ("compat-version",             "3.3a"),
+    // Remote clients
+    ("local-echo",                 "off"),
     ("set-titles-string",          "\"\""),
 ];
 
@@ -421,13 +499,13 @@ pub fn format_vars_lines() -> Vec<String> {
 const FORMAT_GROUPS: &[(&str, &str)] = &[
     ("Session", "session_name session_id session_windows session_attached session_created session_path ..."),
     ("Window",  "window_index window_name window_active window_panes window_flags window_id window_layout window_zoomed_flag ..."),
-    ("Pane",    "pane_index pane_id pane_title pane_width pane_height pane_active pane_current_command pane_current_path pane_pid pane_dead ..."),
+    ("Pane",    "pane_index pane_id pane_title pane_width pane_height pane_active pane_current_command pane_current_command_full pane_current_path pane_pid pane_dead ..."),
     ("Cursor",  "cursor_x cursor_y cursor_character cursor_flag"),
     ("Copy",    "copy_cursor_x copy_cursor_y copy_cursor_word copy_cursor_line selection_present search_present scroll_position"),
     ("Buffer",  "buffer_name buffer_size buffer_sample buffer_created"),
     ("Client",  "client_width client_height client_name client_session client_prefix client_pid client_termname ..."),
-    ("Server",  "pid version host hostname host_short"),
-    ("Misc",    "history_limit history_size alternate_on pane_mode pane_in_mode"),
+    ("Server",  "pid version psmux_version host hostname host_short"),
+    ("Misc",    "history_limit history_size alternate_on pane_mode pane_in_mode last_shell_exit"),
 ];
 
 /// Section: hooks reference.
@@ -441,7 +519,12 @@ pub fn hooks_lines() -> Vec<String> {
     v.push("  after-select-layout   after-copy-mode        after-set-option".into());
     v.push("  after-bind-key        after-unbind-key       after-source".into());
     v.push("  after-swap-pane       after-swap-window      client-attached".into());
-    v.push("  client-detached".into());
+    v.push("  client-detached       alert-activity         alert-silence".into());
+    v.push("  alert-bell            pane-died             pane-exited".into());
+    v.push("  window-linked         window-unlinked       session-renamed".into());
+    v.push("  client-resized".into());
+    v.push("  -a appends, name[N] sets or -u removes one entry; commands see".into());
+    v.push("  #{hook}, #{hook_pane}, #{hook_window} and the pane's #{pane_id}".into());
     v
 }
 
@@ -454,6 +537,8 @@ pub fn mouse_lines() -> Vec<String> {
     v.push("  Left click pane          focus pane (+ forward to child)".into());
     v.push("  Left click border        begin drag-resize".into());
     v.push("  Left drag border         resize split interactively".into());
+    v.push("  Right click status tab   window menu".into());
+    v.push("  Ctrl+Right click pane    pane menu (swap/join with the marked pane)".into());
     v.push("  Scroll up/down           forward wheel to child (or copy mode scroll)".into());
     v
 }
@@ -479,7 +564,7 @@ pub fn build_overlay_lines(
     lines.push("── prefix table (C-b + key) ───────────────────────────────".into());
     for (k, cmd) in PREFIX_DEFAULTS {
         if !overridden.contains(k) {
-            lines.push(format!("bind-key -T prefix {} {}", k, cmd));
+            lines.push(default_line(k, cmd));
         }
     }
 
@@ -526,7 +611,8 @@ pub fn build_list_keys_output<'a>(
     // Defaults
     for (k, cmd) in PREFIX_DEFAULTS {
         if !overridden.contains(*k) {
-            output.push_str(&format!("bind-key -T prefix {} {}\n", k, cmd));
+            output.push_str(&default_line(k, cmd));
+            output.push('\n');
         }
     }
 
@@ -0,0 +1,149 @@
+//! `highlight-pattern`: text kept highlighted in a pane outside copy mode,
+//! such as the errors and warnings in a log.
+//!
+//! The option is a `;`-separated list of `PATTERN=STYLE` entries, for
+//! example `ERROR=fg=red,bold;WARN=fg=yellow`.  A pattern is a regular
+//! expression (a plain word matches itself) and holds no `=` or `;`; an
+//! entry whose pattern does not compile is left out.  It can be set for the
+//! session, for a window (`-w`) or for a pane (`-p`), and the most specific
+//! setting applies.
+//!
+//! The server matches the rows on screen as it builds each frame and sends
+//! the matches with the pane.  The client merges each match's style over the
+//! cells, later entries over earlier ones where matches overlap.  A match
+//! never runs from one row into the next, even on a wrapped line.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::types::{AppState, Pane, Window};
+
+/// Compiled lists kept before the cache is cleared.
+const MAX_CACHED: usize = 16;
+
+/// Compiled lists by option value, so a frame does not compile them again.
+static COMPILED: Mutex<Option<HashMap<String, Arc<Vec<Pattern>>>>> = Mutex::new(None);
+
+/// One entry of the list.
+pub struct Pattern {
+    re: regex::Regex,
+    pub style: String,
+}
+
+/// A match on screen: row, first column, last column and which entry of the
+/// list it is for.
+pub type Match = (u16, u16, u16, usize);
+
+/// The entries of `spec` that compile, compiled once per distinct value.
+pub fn compile(spec: &str) -> Arc<Vec<Pattern>> {
+    let mut cache = COMPILED.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some(p) = cache.get(spec) { return p.clone(); }
+    if cache.len() >= MAX_CACHED { cache.clear(); }
+    let patterns = Arc::new(parse(spec));
+    cache.insert(spec.to_string(), patterns.clone());
+    patterns
+}
+
+fn parse(spec: &str) -> Vec<Pattern> {
+    spec.split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(pattern, _)| !pattern.is_empty())
+        .filter_map(|(pattern, style)| Some(Pattern { re: regex::Regex::new(pattern).ok()?, style: style.trim().to_string() }))
+        .collect()
+}
+
+/// The pane's `highlight-pattern`: its own, else its window's, else the
+/// session's.
+pub fn effective<'a>(app: &'a AppState, win: &'a Window, pane: &'a Pane) -> &'a str {
+    pane.highlight_pattern.as_deref()
+        .or(win.highlight_pattern.as_deref())
+        .unwrap_or(&app.highlight_pattern)
+}
+
+/// The matches of `patterns` on the `rows` x `cols` screen, row by row and
+/// in list order within a row.
+pub fn screen_matches(screen: &vt100::Screen, rows: u16, cols: u16, patterns: &[Pattern]) -> Vec<Match> {
+    let mut found = Vec::new();
+    if patterns.is_empty() { return found; }
+    for r in 0..rows {
+        let (text, cells) = row_text(screen, r, cols);
+        // The column of the cell holding byte `b` of the row text
+        let col = |b: usize| cells[cells.partition_point(|&(start, _)| start <= b) - 1].1;
+        for (k, p) in patterns.iter().enumerate() {
+            for m in p.re.find_iter(&text).filter(|m| !m.is_empty()) {
+                found.push((r, col(m.start()), col(m.end() - 1), k));
+            }
+        }
+    }
+    found
+}
+
+/// Row `r` as text, one character per cell (wide characters take the cells
+/// they cover), with each cell's starting byte and column.
+fn row_text(screen: &vt100::Screen, r: u16, cols: u16) -> (String, Vec<(usize, u16)>) {
+    let mut text = String::new();
+    let mut cells = Vec::new();
+    let mut c = 0u16;
+    while c < cols {
+        let t = screen.cell(r, c).map(|cell| cell.contents()).filter(|t| !t.is_empty()).unwrap_or(" ");
+        cells.push((text.len(), c));
+        text.push_str(t);
+        c += (UnicodeWidthStr::width(t) as u16).max(1);
+    }
+    (text, cells)
+}
+
+/// The matches of `spec` on the screen and the styles of its entries, both
+/// empty when nothing matches: what a frame sends with the pane.
+pub fn frame_matches(screen: &vt100::Screen, rows: u16, cols: u16, spec: &str) -> (Vec<Match>, Vec<String>) {
+    if spec.is_empty() { return (Vec::new(), Vec::new()); }
+    let patterns = compile(spec);
+    let found = screen_matches(screen, rows, cols, &patterns);
+    if found.is_empty() { return (found, Vec::new()); }
+    (found, patterns.iter().map(|p| p.style.clone()).collect())
+}
+
+/// How many matches the pane's `highlight-pattern` has on its screen
+/// (`#{pane_highlight_count}`).
+pub fn count(app: &AppState, win: &Window, pane: &Pane) -> usize {
+    let spec = effective(app, win, pane);
+    if spec.is_empty() { return 0; }
+    let patterns = compile(spec);
+    match pane.term.lock() {
+        Ok(parser) => screen_matches(parser.screen(), pane.last_rows, pane.last_cols, &patterns).len(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: u16, cols: u16, text: &str) -> vt100::Parser {
+        let mut parser = vt100::Parser::new(rows, cols, 0);
+        parser.process(text.as_bytes());
+        parser
+    }
+
+    #[test]
+    fn matches_at_row_edges_and_after_wide_characters() {
+        let p = screen(3, 10, "ERROR  WAR\r\nN   ERROR\r\n漢字 WARN");
+        let patterns = compile("ERROR=fg=red,bold;WARN=fg=yellow");
+        let styles: Vec<&str> = patterns.iter().map(|p| p.style.as_str()).collect();
+        assert_eq!(styles, ["fg=red,bold", "fg=yellow"]);
+        // WAR|N wraps and is not matched; columns count the wide cells
+        assert_eq!(screen_matches(p.screen(), 3, 10, &patterns), [(0, 0, 4, 0), (1, 4, 8, 0), (2, 5, 8, 1)]);
+    }
+
+    #[test]
+    fn overlapping_entries_all_match_in_list_order() {
+        let p = screen(1, 20, "disk error: fatal");
+        let patterns = compile("error: \\w+=fg=red;[=bg=blue;=fg=green;fatal=bold");
+        // The bad regex and the empty pattern are left out
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(screen_matches(p.screen(), 1, 20, &patterns), [(0, 5, 16, 0), (0, 12, 16, 1)]);
+    }
+}
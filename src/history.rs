@@ -0,0 +1,217 @@
+//! Prompt history persistence (the `history-file` option).
+//!
+//! Command-prompt history is written to the history file itself and
+//! copy-mode search history to a sibling `<file>.search`.  Both use one
+//! entry per line, with `\`, newline and carriage return escaped.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::types::AppState;
+
+/// How long history changes may sit unsaved before the server writes them.
+pub const HISTORY_SAVE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+pub fn escape_entry(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn unescape_entry(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' { out.push(c); continue; }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Append `entry` unless it repeats the previous one, then drop the oldest
+/// entries beyond `limit`.
+pub fn push_entry(list: &mut Vec<String>, entry: &str, limit: usize) {
+    if entry.is_empty() { return; }
+    if list.last().map(|l| l.as_str()) != Some(entry) {
+        list.push(entry.to_string());
+    }
+    if list.len() > limit {
+        list.drain(..list.len() - limit);
+    }
+}
+
+/// Record a command-prompt entry and schedule a save.
+pub fn record_command(app: &mut AppState, entry: &str, now: Instant) {
+    push_entry(&mut app.command_history, entry, app.prompt_history_limit);
+    app.command_history_idx = app.command_history.len();
+    app.history_dirty_since.get_or_insert(now);
+}
+
+/// Record a copy-mode search and schedule a save.
+pub fn record_search(app: &mut AppState, entry: &str, now: Instant) {
+    push_entry(&mut app.search_history, entry, app.prompt_history_limit);
+    app.search_history_idx = app.search_history.len();
+    app.history_dirty_since.get_or_insert(now);
+}
+
+fn search_path(path: &str) -> String {
+    format!("{}.search", path)
+}
+
+fn read_entries(path: &str) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .map(|s| s.lines().filter(|l| !l.is_empty()).map(unescape_entry).collect())
+        .unwrap_or_default()
+}
+
+fn write_entries(path: &str, entries: &[String]) -> io::Result<()> {
+    let mut body = String::new();
+    for e in entries {
+        body.push_str(&escape_entry(e));
+        body.push('\n');
+    }
+    // Write then rename so a crash mid-write leaves the old file intact
+    let tmp = format!("{}.tmp", path);
+    std::fs::write(&tmp, body)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Load the history file (when set) in front of any history already in
+/// memory.
+pub fn load_history(app: &mut AppState) {
+    let Some(path) = app.history_file.clone() else { return };
+    let limit = app.prompt_history_limit;
+    for (list, file) in [(&mut app.command_history, path.clone()), (&mut app.search_history, search_path(&path))] {
+        let current = std::mem::replace(list, read_entries(&file));
+        for e in current { push_entry(list, &e, usize::MAX); }
+        if list.len() > limit { list.drain(..list.len() - limit); }
+    }
+    app.command_history_idx = app.command_history.len();
+    app.search_history_idx = app.search_history.len();
+}
+
+/// Apply the history-file option.  Switching to a new file loads it; an
+/// empty value turns persistence off.
+pub fn set_history_file(app: &mut AppState, value: &str) {
+    let path = if value.is_empty() { None } else { Some(crate::config::expand_home(value)) };
+    if path == app.history_file { return; }
+    app.history_file = path;
+    load_history(app);
+}
+
+/// Write both histories out now (clean shutdown).  A no-op without a
+/// history file.
+pub fn save_history(app: &mut AppState) -> io::Result<()> {
+    app.history_dirty_since = None;
+    let Some(path) = app.history_file.clone() else { return Ok(()) };
+    if let Some(dir) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_entries(&path, &app.command_history)?;
+    write_entries(&search_path(&path), &app.search_history)
+}
+
+/// Has unsaved history been waiting at least [`HISTORY_SAVE_DEBOUNCE`]?
+pub fn save_due(app: &AppState, now: Instant) -> bool {
+    app.history_dirty_since.is_some_and(|t| now.duration_since(t) >= HISTORY_SAVE_DEBOUNCE)
+}
+
+/// Periodic save from the server loop; returns true when a write happened.
+pub fn save_history_if_due(app: &mut AppState, now: Instant) -> bool {
+    if !save_due(app, now) { return false; }
+    save_history(app).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("psmux_history_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("history").to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn entries_round_trip_through_escaping() {
+        for s in ["plain", "back\\slash", "two\nlines", "cr\r\n", "trailing\\"] {
+            assert_eq!(unescape_entry(&escape_entry(s)), s);
+            assert!(!escape_entry(s).contains('\n'));
+        }
+    }
+
+    #[test]
+    fn consecutive_repeats_are_collapsed_and_cap_evicts_oldest() {
+        let mut list = Vec::new();
+        for e in ["a", "a", "b", "a", "c", "d"] { push_entry(&mut list, e, 3); }
+        assert_eq!(list, vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn history_survives_a_server_restart() {
+        let path = temp_history_path("restart");
+        let now = Instant::now();
+        let mut first = AppState::new("hist".to_string());
+        first.history_file = Some(path.clone());
+        record_command(&mut first, "split-window -h", now);
+        record_command(&mut first, "display-message \"a\nb\"", now);
+        record_command(&mut first, "new-window", now);
+        record_search(&mut first, "error", now);
+        save_history(&mut first).unwrap();
+
+        let mut second = AppState::new("hist".to_string());
+        second.history_file = Some(path.clone());
+        load_history(&mut second);
+        assert_eq!(second.command_history, vec!["split-window -h", "display-message \"a\nb\"", "new-window"]);
+        // Up-arrow recall starts from the most recent entry
+        assert_eq!(second.command_history_idx, 3);
+        assert_eq!(second.search_history, vec!["error"]);
+        let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap());
+    }
+
+    #[test]
+    fn load_applies_the_history_limit() {
+        let path = temp_history_path("limit");
+        let mut first = AppState::new("hist".to_string());
+        first.history_file = Some(path.clone());
+        for i in 0..10 { record_command(&mut first, &format!("cmd{}", i), Instant::now()); }
+        save_history(&mut first).unwrap();
+
+        let mut second = AppState::new("hist".to_string());
+        second.history_file = Some(path.clone());
+        second.prompt_history_limit = 4;
+        load_history(&mut second);
+        assert_eq!(second.command_history, vec!["cmd6", "cmd7", "cmd8", "cmd9"]);
+        let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap());
+    }
+
+    #[test]
+    fn periodic_save_waits_for_the_debounce() {
+        let path = temp_history_path("debounce");
+        let start = Instant::now();
+        let mut app = AppState::new("hist".to_string());
+        app.history_file = Some(path.clone());
+        record_command(&mut app, "one", start);
+        // A second change does not push the deadline back
+        record_command(&mut app, "two", start + Duration::from_secs(3));
+        assert!(!save_history_if_due(&mut app, start + Duration::from_secs(4)));
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(save_history_if_due(&mut app, start + HISTORY_SAVE_DEBOUNCE));
+        assert_eq!(read_entries(&path), vec!["one", "two"]);
+        // Nothing pending any more
+        assert!(!save_history_if_due(&mut app, start + Duration::from_secs(60)));
+        let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap());
+    }
+}
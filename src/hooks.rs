@@ -0,0 +1,275 @@
+//! set-hook: commands run when something happens in the session.
+//!
+//! A hook is an array, as in tmux: `set-hook pane-died 'cmd'` replaces
+//! it, `-a` appends, `pane-died[2]` names one entry and `-u` removes the
+//! entry or the whole hook.  Its commands run in order, through the config
+//! parser, and see the pane or window that fired it: `#{hook_pane}`,
+//! `#{hook_window}` and the `pane_id` / `window_*` variables are bound to
+//! it, so `set-hook -g pane-died 'run-shell "notify #{pane_id}"'` names the
+//! pane that died even after it is gone.
+//!
+//! Events the server raises outside a command (a pane exiting, a window
+//! closing, an alert) are queued with their context and run from the
+//! server loop.  A hook whose commands raise events again runs those one
+//! level deeper, and nothing runs past MAX_DEPTH, so a hook cannot keep
+//! firing itself.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+use crate::types::{AppState, ShellJob};
+
+/// How deeply hooks may fire hooks.
+pub const MAX_DEPTH: usize = 8;
+
+thread_local! {
+    /// Depth of the hook running now; 0 outside hooks
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Format variables bound while a hook runs.
+pub type Context = Vec<(String, String)>;
+
+/// An event waiting for the server loop.
+pub struct Pending {
+    pub event: String,
+    pub context: Context,
+    depth: usize,
+}
+
+/// Every hook's commands, by event and index.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    map: BTreeMap<String, BTreeMap<usize, String>>,
+}
+
+/// `pane-died[1]` → ("pane-died", Some(1)).
+pub fn parse_name(spec: &str) -> (&str, Option<usize>) {
+    if let Some((name, rest)) = spec.split_once('[') {
+        if let Some(idx) = rest.strip_suffix(']').and_then(|i| i.parse().ok()) {
+            return (name, Some(idx));
+        }
+    }
+    (spec, None)
+}
+
+impl Hooks {
+    /// Set `spec` to `command`: one entry with an index, else the whole
+    /// hook, or with `append` a new last entry.
+    pub fn set(&mut self, spec: &str, command: &str, append: bool) {
+        let (name, idx) = parse_name(spec);
+        let entries = self.map.entry(name.to_string()).or_default();
+        let idx = match idx {
+            Some(i) => i,
+            None if append => entries.keys().next_back().map_or(0, |i| i + 1),
+            None => { entries.clear(); 0 }
+        };
+        entries.insert(idx, command.to_string());
+    }
+
+    /// Remove one entry, or the whole hook without an index.
+    pub fn unset(&mut self, spec: &str) {
+        let (name, idx) = parse_name(spec);
+        match idx {
+            Some(i) => {
+                if let Some(entries) = self.map.get_mut(name) {
+                    entries.remove(&i);
+                    if entries.is_empty() { self.map.remove(name); }
+                }
+            }
+            None => { self.map.remove(name); }
+        }
+    }
+
+    /// The commands `event` runs, in index order.
+    pub fn commands(&self, event: &str) -> Vec<String> {
+        self.map.get(event).map(|e| e.values().cloned().collect()).unwrap_or_default()
+    }
+
+    /// show-hooks lines, `name[i] command`, for all hooks or one.
+    pub fn show(&self, only: Option<&str>) -> String {
+        let mut out = String::new();
+        for (name, entries) in &self.map {
+            if only.is_some_and(|o| o != name) { continue; }
+            for (i, cmd) in entries {
+                out.push_str(&format!("{}[{}] {}\n", name, i, cmd));
+            }
+        }
+        out
+    }
+}
+
+/// A set-hook command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetHook {
+    Set { spec: String, command: String, append: bool },
+    /// `-u`
+    Unset(String),
+    /// `-R`: run it now
+    Run(String),
+}
+
+/// Parse set-hook's arguments (after the command name, without `-t`).
+/// A command quoted as one word in a config line loses its quotes.
+pub fn parse_set_hook(args: &[&str]) -> Option<SetHook> {
+    let flags: String = args.iter().take_while(|a| a.starts_with('-') && a.len() > 1).map(|a| &a[1..]).collect();
+    let rest: Vec<&str> = args.iter().skip_while(|a| a.starts_with('-') && a.len() > 1).copied().collect();
+    let spec = rest.first()?.to_string();
+    if flags.contains('R') { return Some(SetHook::Run(spec)); }
+    if flags.contains('u') { return Some(SetHook::Unset(spec)); }
+    let command = rest[1..].join(" ");
+    let command = ['\'', '"'].iter()
+        .find_map(|q| command.strip_prefix(*q).and_then(|c| c.strip_suffix(*q)))
+        .unwrap_or(&command);
+    if command.is_empty() { return None; }
+    Some(SetHook::Set { spec, command: command.to_string(), append: flags.contains('a') })
+}
+
+pub fn apply(app: &mut AppState, set: SetHook) {
+    match set {
+        SetHook::Set { spec, command, append } => app.hooks.set(&spec, &command, append),
+        SetHook::Unset(spec) => app.hooks.unset(&spec),
+        SetHook::Run(spec) => run(app, parse_name(&spec).0, active_context(app)),
+    }
+}
+
+fn session_context(app: &AppState) -> Context {
+    vec![
+        ("hook_session".into(), format!("${}", app.session_id)),
+        ("hook_session_name".into(), app.session_name.clone()),
+    ]
+}
+
+/// The context of window `win_idx`.
+pub fn window_context(app: &AppState, win_idx: usize) -> Context {
+    let mut ctx = session_context(app);
+    if let Some(win) = app.windows.get(win_idx) {
+        let id = format!("@{}", win.id);
+        ctx.extend([
+            ("hook_window".into(), id.clone()),
+            ("hook_window_name".into(), win.name.clone()),
+            ("window_id".into(), id),
+            ("window_index".into(), win.index.to_string()),
+            ("window_name".into(), win.name.clone()),
+        ]);
+    }
+    ctx
+}
+
+/// The context of pane `pane_id` in window `win_idx`.
+pub fn pane_context(app: &AppState, win_idx: usize, pane_id: usize) -> Context {
+    with_pane(window_context(app, win_idx), pane_id)
+}
+
+/// A window's context narrowed to one of its panes.
+pub fn with_pane(mut ctx: Context, pane_id: usize) -> Context {
+    ctx.extend([("hook_pane".into(), format!("%{}", pane_id)), ("pane_id".into(), format!("%{}", pane_id))]);
+    ctx
+}
+
+/// The context of the active pane, for hooks after a command.
+pub fn active_context(app: &AppState) -> Context {
+    let Some(win) = app.windows.get(app.active_idx) else { return session_context(app) };
+    match crate::tree::active_pane(&win.root, &win.active_path) {
+        Some(p) => pane_context(app, app.active_idx, p.id),
+        None => window_context(app, app.active_idx),
+    }
+}
+
+/// Queue `event` for the server loop; it runs one level below the hook
+/// running now, if any.
+pub fn queue(app: &mut AppState, event: &str, context: Context) {
+    app.pending_hooks.push(Pending { event: event.to_string(), context, depth: DEPTH.get() });
+}
+
+/// Run `event`'s commands now.
+pub fn run(app: &mut AppState, event: &str, context: Context) {
+    run_at(app, event, context, DEPTH.get());
+}
+
+/// Run the queued events, and those they queue in turn.
+pub fn run_pending(app: &mut AppState) {
+    while !app.pending_hooks.is_empty() {
+        for p in std::mem::take(&mut app.pending_hooks) {
+            run_at(app, &p.event, p.context, p.depth);
+        }
+    }
+}
+
+fn run_at(app: &mut AppState, event: &str, context: Context, depth: usize) {
+    let commands = app.hooks.commands(event);
+    if commands.is_empty() { return; }
+    if depth >= MAX_DEPTH {
+        app.pending_message = Some(format!("hook {} not run: hooks nested too deep", event));
+        return;
+    }
+    let saved_depth = DEPTH.replace(depth + 1);
+    let saved = crate::format::set_hook_context(Some((event.to_string(), context)));
+    for cmd in commands {
+        let queued = app.pending_shell_jobs.len();
+        crate::config::parse_config_line(app, &cmd);
+        // Shell jobs start later; expand them while the hook's context holds
+        let mut jobs = std::mem::take(&mut app.pending_shell_jobs);
+        for job in jobs.iter_mut().skip(queued) {
+            match job {
+                ShellJob::Run { command, .. } => *command = expanded(app, command),
+                ShellJob::IfShell { condition, .. } => *condition = expanded(app, condition),
+            }
+        }
+        app.pending_shell_jobs = jobs;
+    }
+    crate::format::set_hook_context(saved);
+    DEPTH.set(saved_depth);
+}
+
+/// `text` expanded, escaped so expanding it again leaves it as it is.
+fn expanded(app: &AppState, text: &str) -> String {
+    crate::format::expand_format(text, app).replace('#', "##").replace('%', "%%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hook_arrays_set_append_and_unset() {
+        let mut hooks = Hooks::default();
+        hooks.set("pane-died", "a", false);
+        hooks.set("pane-died", "b", true);
+        hooks.set("pane-died[5]", "c", false);
+        assert_eq!(hooks.commands("pane-died"), vec!["a", "b", "c"]);
+        assert_eq!(hooks.show(None), "pane-died[0] a\npane-died[1] b\npane-died[5] c\n");
+        hooks.unset("pane-died[1]");
+        assert_eq!(hooks.commands("pane-died"), vec!["a", "c"]);
+        // Without an index or -a the hook is replaced
+        hooks.set("pane-died", "d", false);
+        assert_eq!(hooks.commands("pane-died"), vec!["d"]);
+        hooks.unset("pane-died");
+        assert!(hooks.show(None).is_empty());
+
+        assert_eq!(parse_set_hook(&["-g", "pane-died", "'run-shell", "\"notify", "#{pane_id}\"'"]),
+            Some(SetHook::Set { spec: "pane-died".into(), command: "run-shell \"notify #{pane_id}\"".into(), append: false }));
+        assert_eq!(parse_set_hook(&["-gu", "pane-died[1]"]), Some(SetHook::Unset("pane-died[1]".into())));
+        assert_eq!(parse_set_hook(&["-R", "x"]), Some(SetHook::Run("x".into())));
+        assert_eq!(parse_set_hook(&["-g", "pane-died"]), None);
+    }
+
+    #[test]
+    fn hooks_see_their_pane_and_cannot_loop() {
+        let mut app = AppState::new("t".to_string());
+        app.hooks.set("pane-exited", "run-shell \"echo #{hook_pane} #{pane_id} #{hook}\"", false);
+        queue(&mut app, "pane-exited", vec![("hook_pane".into(), "%7".into()), ("pane_id".into(), "%7".into())]);
+        run_pending(&mut app);
+        let Some(ShellJob::Run { command, .. }) = app.pending_shell_jobs.first() else { panic!("no job queued") };
+        // The job's later expansion gives back what the hook saw
+        assert_eq!(crate::format::expand_format(command, &app), "echo %7 %7 pane-exited");
+
+        // A hook that fires itself stops at MAX_DEPTH
+        app.pending_shell_jobs.clear();
+        app.hooks.set("@loop", "set-hook -R @loop", false);
+        app.hooks.set("@loop", "run-shell -b true", true);
+        run(&mut app, "@loop", Vec::new());
+        assert_eq!(app.pending_shell_jobs.len(), MAX_DEPTH);
+        assert!(app.pending_message.as_deref().is_some_and(|m| m.contains("nested too deep")));
+    }
+}
@@ -1,988 +1,160 @@
 use std::io::{self, Write};
 use std::time::Instant;
 
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
-use portable_pty::native_pty_system;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::prelude::*;
 
-use crate::types::{AppState, Mode, FocusDir, LayoutKind, DragState, Node};
-use crate::tree::{active_pane, active_pane_mut, compute_rects, compute_split_borders,
-    split_sizes_at, adjust_split_sizes, path_exists, resize_all_panes};
-use crate::pane::{create_window, split_active};
-use crate::commands::{execute_action, execute_command_prompt, execute_command_string};
-use crate::config::normalize_key_for_binding;
-use crate::copy_mode::{enter_copy_mode, exit_copy_mode, switch_with_copy_save, move_copy_cursor,
-    scroll_copy_up, scroll_copy_down, paste_latest, yank_selection,
-    search_copy_mode, search_next, search_prev, scroll_to_top, scroll_to_bottom,
-    save_copy_state_to_pane, restore_copy_state_from_pane};
-use crate::layout::{cycle_top_layout, apply_layout};
-use crate::window_ops::{toggle_zoom, swap_pane, break_pane_to_window};
+use crate::types::{Action, AppState, Mode, FocusDir, Node};
+use crate::tree::{active_pane_mut, compute_rects};
+use crate::commands::execute_action;
+use crate::config::{normalize_key_for_binding, lookup_key_binding, action_with_key, parse_key_name};
+use crate::types::Bind;
+use crate::copy_mode::search_copy_mode;
+
+/// Run a key binding.  `Any` matches expose the pressed key as `#{key}`.
+fn run_binding(app: &mut AppState, bind: &Bind, is_any: bool, key: &KeyEvent) -> io::Result<bool> {
+    if !is_any {
+        return execute_action(app, &bind.action);
+    }
+    let name = crate::config::key_name_for_binding((key.code, key.modifiers));
+    let action = action_with_key(&bind.action, &name);
+    crate::format::set_key_override(Some(name));
+    let res = execute_action(app, &action);
+    crate::format::set_key_override(None);
+    res
+}
 
-/// Write a mouse event to the child PTY using the encoding the child requested.
-fn write_mouse_event(master: &mut dyn std::io::Write, button: u8, col: u16, row: u16, press: bool, enc: vt100::MouseProtocolEncoding) {
-    match enc {
-        vt100::MouseProtocolEncoding::Sgr => {
-            let ch = if press { 'M' } else { 'm' };
-            let _ = write!(master, "\x1b[<{};{};{}{}", button, col, row, ch);
-            let _ = master.flush();
-        }
-        _ => {
-            // Default / Utf8 X10-style encoding: \x1b[M Cb Cx Cy (all + 32)
-            if press {
-                let cb = (button + 32) as u8;
-                let cx = ((col as u8).min(223)) + 32;
-                let cy = ((row as u8).min(223)) + 32;
-                let _ = master.write_all(&[0x1b, b'[', b'M', cb, cx, cy]);
-                let _ = master.flush();
-            }
-            // X10-style has no release encoding for individual buttons
-        }
+/// The copy-mode command of a `send-keys -X <command>` binding.
+fn copy_command_of(action: &Action) -> Option<&str> {
+    let Action::Command(cmd) = action else { return None };
+    let mut parts = cmd.splitn(3, ' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("send-keys" | "send"), Some("-X"), Some(rest)) => Some(rest.trim()),
+        _ => None,
     }
 }
 
-pub fn handle_key(app: &mut AppState, key: KeyEvent) -> io::Result<bool> {
-    match app.mode {
-        Mode::Passthrough => {
-            // Check switch-client -T key table first
-            if let Some(table_name) = app.current_key_table.take() {
-                let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-                if let Some(bind) = app.key_tables.get(&table_name)
-                    .and_then(|t| t.iter().find(|b| b.key == key_tuple))
-                    .cloned()
-                {
-                    return execute_action(app, &bind.action);
-                }
-                // Key not found in table — fall through to normal dispatch
-            }
-            let is_prefix = (key.code, key.modifiers) == app.prefix_key
-                || matches!(key.code, KeyCode::Char(c) if c == '\u{0002}')
-                || app.prefix2_key.map_or(false, |p2| (key.code, key.modifiers) == p2);
-            if is_prefix {
-                app.mode = Mode::Prefix { armed_at: Instant::now() };
-                return Ok(false);
-            }
-            // Check root key table for bindings (bind-key -n / bind-key -T root)
-            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-            if let Some(bind) = app.key_tables.get("root").and_then(|t| t.iter().find(|b| b.key == key_tuple)).cloned() {
-                return execute_action(app, &bind.action);
-            }
-            forward_key_to_active(app, key)?;
-            Ok(false)
-        }
-        Mode::Prefix { armed_at } => {
-            let elapsed = armed_at.elapsed().as_millis() as u64;
-            
-            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-            if let Some(bind) = app.key_tables.get("prefix").and_then(|t| t.iter().find(|b| b.key == key_tuple)).cloned() {
-                if bind.repeat {
-                    // Stay in prefix mode for repeat-time window
-                    app.mode = Mode::Prefix { armed_at: Instant::now() };
-                } else {
-                    app.mode = Mode::Passthrough;
-                }
-                return execute_action(app, &bind.action);
-            }
-            
-            let handled = match key.code {
-                // Alt+Arrow: resize pane by 5 (must be before plain arrows)
-                KeyCode::Up if key.modifiers.contains(KeyModifiers::ALT) => {
-                    crate::window_ops::resize_pane_vertical(app, -5); true
-                }
-                KeyCode::Down if key.modifiers.contains(KeyModifiers::ALT) => {
-                    crate::window_ops::resize_pane_vertical(app, 5); true
-                }
-                KeyCode::Left if key.modifiers.contains(KeyModifiers::ALT) => {
-                    crate::window_ops::resize_pane_horizontal(app, -5); true
-                }
-                KeyCode::Right if key.modifiers.contains(KeyModifiers::ALT) => {
-                    crate::window_ops::resize_pane_horizontal(app, 5); true
-                }
-                // Ctrl+Arrow: resize pane by 1 (must be before plain arrows)
-                KeyCode::Up if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    crate::window_ops::resize_pane_vertical(app, -1); true
-                }
-                KeyCode::Down if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    crate::window_ops::resize_pane_vertical(app, 1); true
-                }
-                KeyCode::Left if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    crate::window_ops::resize_pane_horizontal(app, -1); true
-                }
-                KeyCode::Right if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    crate::window_ops::resize_pane_horizontal(app, 1); true
-                }
-                KeyCode::Left => { switch_with_copy_save(app, |app| move_focus(app, FocusDir::Left)); true }
-                KeyCode::Right => { switch_with_copy_save(app, |app| move_focus(app, FocusDir::Right)); true }
-                KeyCode::Up => { switch_with_copy_save(app, |app| move_focus(app, FocusDir::Up)); true }
-                KeyCode::Down => { switch_with_copy_save(app, |app| move_focus(app, FocusDir::Down)); true }
-                KeyCode::Char(d) if d.is_ascii_digit() => {
-                    let idx = d.to_digit(10).unwrap() as usize;
-                    if idx >= app.window_base_index {
-                        let internal_idx = idx - app.window_base_index;
-                        if internal_idx < app.windows.len() {
-                            switch_with_copy_save(app, |app| {
-                                app.last_window_idx = app.active_idx;
-                                app.active_idx = internal_idx;
-                            });
-                        }
-                    }
-                    true
-                }
-                KeyCode::Char('c') => {
-                    let pty_system = native_pty_system();
-                    create_window(&*pty_system, app, None)?;
-                    true
-                }
-                KeyCode::Char('n') => {
-                    if !app.windows.is_empty() {
-                        switch_with_copy_save(app, |app| {
-                            app.last_window_idx = app.active_idx;
-                            app.active_idx = (app.active_idx + 1) % app.windows.len();
-                        });
-                    }
-                    true
-                }
-                KeyCode::Char('p') => {
-                    if !app.windows.is_empty() {
-                        switch_with_copy_save(app, |app| {
-                            app.last_window_idx = app.active_idx;
-                            app.active_idx = (app.active_idx + app.windows.len() - 1) % app.windows.len();
-                        });
-                    }
-                    true
-                }
-                KeyCode::Char('%') => {
-                    split_active(app, LayoutKind::Horizontal)?;
-                    true
-                }
-                KeyCode::Char('"') => {
-                    split_active(app, LayoutKind::Vertical)?;
-                    true
-                }
-                KeyCode::Char('x') => {
-                    app.mode = Mode::ConfirmMode {
-                        prompt: "kill-pane? (y/n)".into(),
-                        command: "kill-pane".into(),
-                        input: String::new(),
-                    };
-                    true
-                }
-                KeyCode::Char('d') => {
-                    return Ok(true);
-                }
-                KeyCode::Char('w') => {
-                    let tree = crate::commands::build_choose_tree(app);
-                    let selected = tree.iter().position(|e| e.is_current_session && e.is_active_window && !e.is_session_header).unwrap_or(0);
-                    app.mode = Mode::WindowChooser { selected, tree };
-                    true
-                }
-                KeyCode::Char(',') => { app.mode = Mode::RenamePrompt { input: String::new() }; true }
-                KeyCode::Char(' ') => { cycle_top_layout(app); true }
-                KeyCode::Char('[') => { enter_copy_mode(app); true }
-                KeyCode::Char(']') => { paste_latest(app)?; app.mode = Mode::Passthrough; true }
-                KeyCode::Char(':') => {
-                    app.mode = Mode::CommandPrompt { input: String::new(), cursor: 0 };
-                    true
-                }
-                KeyCode::Char('q') => {
-                    let win = &app.windows[app.active_idx];
-                    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
-                    compute_rects(&win.root, app.last_window_area, &mut rects);
-                    app.display_map.clear();
-                    for (i, (path, _)) in rects.into_iter().enumerate() {
-                        let n = i + 1;
-                        if n <= 10 { app.display_map.push((n, path)); } else { break; }
-                    }
-                    app.mode = Mode::PaneChooser { opened_at: Instant::now() };
-                    true
-                }
-                // --- zoom pane (z) ---
-                KeyCode::Char('z') => { toggle_zoom(app); true }
-                // --- next pane (o) ---
-                KeyCode::Char('o') => {
-                    switch_with_copy_save(app, |app| {
-                        let win = &app.windows[app.active_idx];
-                        let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
-                        compute_rects(&win.root, app.last_window_area, &mut rects);
-                        if let Some(cur) = rects.iter().position(|r| r.0 == win.active_path) {
-                            let next = (cur + 1) % rects.len();
-                            let new_path = rects[next].0.clone();
-                            let win = &mut app.windows[app.active_idx];
-                            app.last_pane_path = win.active_path.clone();
-                            win.active_path = new_path;
-                        }
-                    });
-                    true
-                }
-                // --- last pane (;) ---
-                KeyCode::Char(';') => {
-                    switch_with_copy_save(app, |app| {
-                        let win = &mut app.windows[app.active_idx];
-                        if !app.last_pane_path.is_empty() && path_exists(&win.root, &app.last_pane_path) {
-                            let tmp = win.active_path.clone();
-                            win.active_path = app.last_pane_path.clone();
-                            app.last_pane_path = tmp;
-                        }
-                    });
-                    true
-                }
-                // --- last window (l) ---
-                KeyCode::Char('l') => {
-                    if app.last_window_idx < app.windows.len() {
-                        switch_with_copy_save(app, |app| {
-                            let tmp = app.active_idx;
-                            app.active_idx = app.last_window_idx;
-                            app.last_window_idx = tmp;
-                        });
-                    }
-                    true
-                }
-                // --- swap pane up/left ({) ---
-                KeyCode::Char('{') => { swap_pane(app, FocusDir::Up); true }
-                // --- swap pane down/right (}) ---
-                KeyCode::Char('}') => { swap_pane(app, FocusDir::Down); true }
-                // --- break pane to new window (!) ---
-                KeyCode::Char('!') => { break_pane_to_window(app); true }
-                // --- kill window (&) with confirmation ---
-                KeyCode::Char('&') => {
-                    app.mode = Mode::ConfirmMode {
-                        prompt: "kill-window? (y/n)".into(),
-                        command: "kill-window".into(),
-                        input: String::new(),
-                    };
-                    true
-                }
-                // --- rename session ($) ---
-                KeyCode::Char('$') => {
-                    app.mode = Mode::RenameSessionPrompt { input: String::new() };
-                    true
-                }
-                // --- Meta+1..5 preset layouts (like tmux) ---
-                KeyCode::Char('1') if key.modifiers.contains(KeyModifiers::ALT) => {
-                    apply_layout(app, "even-horizontal"); true
-                }
-                KeyCode::Char('2') if key.modifiers.contains(KeyModifiers::ALT) => {
-                    apply_layout(app, "even-vertical"); true
-                }
-                KeyCode::Char('3') if key.modifiers.contains(KeyModifiers::ALT) => {
-                    apply_layout(app, "main-horizontal"); true
-                }
-                KeyCode::Char('4') if key.modifiers.contains(KeyModifiers::ALT) => {
-                    apply_layout(app, "main-vertical"); true
-                }
-                KeyCode::Char('5') if key.modifiers.contains(KeyModifiers::ALT) => {
-                    apply_layout(app, "tiled"); true
-                }
-                // --- display pane info (i) ---
-                KeyCode::Char('i') => {
-                    // Display window/pane info in status bar (tmux prefix+i)
-                    let win = &app.windows[app.active_idx];
-                    let pane_count = crate::tree::count_panes(&win.root);
-                    app.status_right = format!(
-                        "#{} ({}) [{}x{}] panes:{}", 
-                        app.active_idx, win.name,
-                        app.last_window_area.width, app.last_window_area.height,
-                        pane_count
-                    );
-                    true
-                }
-                // --- clock mode (t) ---
-                KeyCode::Char('t') => {
-                    app.mode = Mode::ClockMode;
-                    true
-                }
-                // --- buffer chooser (=) ---
-                KeyCode::Char('=') => {
-                    app.mode = Mode::BufferChooser { selected: 0 };
-                    true
-                }
-                _ => false,
-            };
+/// The copy-mode key table `mode-keys` selects.
+pub fn copy_mode_table(app: &AppState) -> &'static str {
+    if app.mode_keys == "vi" { "copy-mode-vi" } else { "copy-mode" }
+}
 
-            if matches!(app.mode, Mode::Prefix { .. }) {
-                if !handled && elapsed < app.escape_time_ms {
-                    return Ok(false);
-                }
-                app.mode = Mode::Passthrough;
-            }
-            Ok(false)
-        }
-        Mode::CommandPrompt { .. } => {
-            match key.code {
-                KeyCode::Esc => { app.mode = Mode::Passthrough; }
-                KeyCode::Enter => {
-                    // Save to history before executing
-                    if let Mode::CommandPrompt { input, .. } = &app.mode {
-                        if !input.is_empty() {
-                            let cmd = input.clone();
-                            app.command_history.push(cmd);
-                            app.command_history_idx = app.command_history.len();
-                        }
-                    }
-                    execute_command_prompt(app)?;
-                }
-                KeyCode::Backspace => {
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        if *cursor > 0 {
-                            input.remove(*cursor - 1);
-                            *cursor -= 1;
-                        }
-                    }
-                }
-                KeyCode::Delete => {
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        if *cursor < input.len() {
-                            input.remove(*cursor);
-                        }
-                    }
-                }
-                KeyCode::Left => {
-                    if let Mode::CommandPrompt { cursor, .. } = &mut app.mode {
-                        if *cursor > 0 { *cursor -= 1; }
-                    }
-                }
-                KeyCode::Right => {
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        if *cursor < input.len() { *cursor += 1; }
-                    }
-                }
-                KeyCode::Home => {
-                    if let Mode::CommandPrompt { cursor, .. } = &mut app.mode {
-                        *cursor = 0;
-                    }
-                }
-                KeyCode::End => {
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        *cursor = input.len();
-                    }
-                }
-                KeyCode::Up => {
-                    // Cycle through command history (older)
-                    if app.command_history_idx > 0 {
-                        app.command_history_idx -= 1;
-                        let cmd = app.command_history[app.command_history_idx].clone();
-                        let len = cmd.len();
-                        if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                            *input = cmd;
-                            *cursor = len;
-                        }
-                    }
-                }
-                KeyCode::Down => {
-                    // Cycle through command history (newer)
-                    if app.command_history_idx < app.command_history.len() {
-                        app.command_history_idx += 1;
-                        let cmd = if app.command_history_idx < app.command_history.len() {
-                            app.command_history[app.command_history_idx].clone()
-                        } else {
-                            String::new()
-                        };
-                        let len = cmd.len();
-                        if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                            *input = cmd;
-                            *cursor = len;
-                        }
-                    }
-                }
-                KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Ctrl+A: move to beginning
-                    if let Mode::CommandPrompt { cursor, .. } = &mut app.mode {
-                        *cursor = 0;
-                    }
-                }
-                KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Ctrl+E: move to end
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        *cursor = input.len();
-                    }
-                }
-                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Ctrl+U: kill line (clear from cursor to start)
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        input.drain(..*cursor);
-                        *cursor = 0;
-                    }
-                }
-                KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Ctrl+K: kill to end of line
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        input.truncate(*cursor);
-                    }
-                }
-                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Ctrl+W: delete word backwards
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        let mut pos = *cursor;
-                        while pos > 0 && input.as_bytes().get(pos - 1) == Some(&b' ') { pos -= 1; }
-                        while pos > 0 && input.as_bytes().get(pos - 1) != Some(&b' ') { pos -= 1; }
-                        input.drain(pos..*cursor);
-                        *cursor = pos;
-                    }
-                }
-                KeyCode::Char(c) => {
-                    if let Mode::CommandPrompt { input, cursor } = &mut app.mode {
-                        input.insert(*cursor, c);
-                        *cursor += 1;
-                    }
-                }
-                _ => {}
-            }
-            Ok(false)
-        }
-        Mode::WindowChooser { selected, ref tree } => {
-            let tree_len = tree.len();
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('q') => { app.mode = Mode::Passthrough; }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if selected > 0 { if let Mode::WindowChooser { selected: s, .. } = &mut app.mode { *s -= 1; } }
-                }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if selected + 1 < tree_len { if let Mode::WindowChooser { selected: s, .. } = &mut app.mode { *s += 1; } }
-                }
-                KeyCode::Enter => {
-                    if let Mode::WindowChooser { selected: s, ref tree } = &app.mode {
-                        let entry = &tree[*s];
-                        if entry.is_current_session {
-                            // Same session: switch window directly
-                            if let Some(wi) = entry.window_index {
-                                app.last_window_idx = app.active_idx;
-                                app.active_idx = wi;
-                            }
-                        } else {
-                            // Different session: set env and trigger switch
-                            std::env::set_var("PSMUX_SWITCH_TO", &entry.session_name);
-                        }
-                    }
-                    app.mode = Mode::Passthrough;
-                }
-                KeyCode::Char(c) if c.is_ascii_digit() => {
-                    // Quick-select by window number
-                    let n = c.to_digit(10).unwrap_or(0) as usize;
-                    if let Some(idx) = tree.iter().position(|e| !e.is_session_header && e.window_index == Some(n) && e.is_current_session) {
-                        if let Mode::WindowChooser { selected: s, .. } = &mut app.mode { *s = idx; }
-                    }
-                }
-                _ => {}
-            }
-            Ok(false)
+/// A key pressed in copy mode, from the local terminal or an attached
+/// client.  The copy-mode key table (defaults plus config edits) decides
+/// what a key does; only count digits, f/F/t/T targets, registers and text
+/// objects are built in.
+fn copy_mode_key(app: &mut AppState, key: KeyEvent) -> io::Result<bool> {
+    // Handle register pending state (waiting for a-z after "); Escape
+    // drops the register, any other key leaves it as it was
+    if app.copy_register_pending {
+        app.copy_register_pending = false;
+        match key.code {
+            KeyCode::Char(ch) if ch.is_ascii_lowercase() => app.copy_register = Some(ch),
+            KeyCode::Esc => app.copy_register = None,
+            _ => {}
         }
-        Mode::RenamePrompt { .. } => {
-            match key.code {
-                KeyCode::Esc => { app.mode = Mode::Passthrough; }
-                KeyCode::Enter => { if let Mode::RenamePrompt { input } = &mut app.mode { app.windows[app.active_idx].name = input.clone(); app.mode = Mode::Passthrough; } }
-                KeyCode::Backspace => { if let Mode::RenamePrompt { input } = &mut app.mode { let _ = input.pop(); } }
-                KeyCode::Char(c) => { if let Mode::RenamePrompt { input } = &mut app.mode { input.push(c); } }
+        return Ok(false);
+    }
+    // Handle text-object pending state (waiting for w/W after a/i)
+    if let Some(prefix) = app.copy_text_object_pending.take() {
+        if let KeyCode::Char(ch) = key.code {
+            match (prefix, ch) {
+                (0, 'w') => { crate::copy_mode::select_a_word(app); }
+                (1, 'w') => { crate::copy_mode::select_inner_word(app); }
+                (0, 'W') => { crate::copy_mode::select_a_word_big(app); }
+                (1, 'W') => { crate::copy_mode::select_inner_word_big(app); }
                 _ => {}
             }
-            Ok(false)
         }
-        Mode::RenameSessionPrompt { .. } => {
-            match key.code {
-                KeyCode::Esc => { app.mode = Mode::Passthrough; }
-                KeyCode::Enter => {
-                    if let Mode::RenameSessionPrompt { input } = &mut app.mode {
-                        app.session_name = input.clone();
-                        app.mode = Mode::Passthrough;
-                    }
-                }
-                KeyCode::Backspace => { if let Mode::RenameSessionPrompt { input } = &mut app.mode { let _ = input.pop(); } }
-                KeyCode::Char(c) => { if let Mode::RenameSessionPrompt { input } = &mut app.mode { input.push(c); } }
+        return Ok(false);
+    }
+    // Handle find-char pending state (waiting for char after f/F/t/T)
+    if let Some(pending) = app.copy_find_char_pending.take() {
+        let n = app.copy_count.take().unwrap_or(1);
+        if let KeyCode::Char(ch) = key.code {
+            match pending {
+                0 => { for _ in 0..n { crate::copy_mode::find_char_forward(app, ch); } }
+                1 => { for _ in 0..n { crate::copy_mode::find_char_backward(app, ch); } }
+                2 => { for _ in 0..n { crate::copy_mode::find_char_to_forward(app, ch); } }
+                3 => { for _ in 0..n { crate::copy_mode::find_char_to_backward(app, ch); } }
                 _ => {}
             }
-            Ok(false)
         }
-        Mode::CopyMode => {
-            // Check copy-mode key table for user bindings first (used by plugins like tmux-yank)
-            let table_name = if app.mode_keys == "vi" { "copy-mode-vi" } else { "copy-mode" };
-            let key_tuple = normalize_key_for_binding((key.code, key.modifiers));
-            if let Some(bind) = app.key_tables.get(table_name)
-                .and_then(|t| t.iter().find(|b| b.key == key_tuple))
-                .cloned()
-            {
-                return execute_action(app, &bind.action);
-            }
-            // Handle register pending state (waiting for a-z after ")
-            if app.copy_register_pending {
-                app.copy_register_pending = false;
-                if let KeyCode::Char(ch) = key.code {
-                    if ch.is_ascii_lowercase() {
-                        app.copy_register = Some(ch);
-                    }
-                }
+        crate::copy_mode::follow_copy_cursor(app);
+        return Ok(false);
+    }
+    // Handle numeric prefix accumulation for copy-mode motions (vi-style)
+    if let KeyCode::Char(d) = key.code {
+        if d.is_ascii_digit() && !key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(KeyModifiers::ALT) {
+            let digit = d.to_digit(10).unwrap() as usize;
+            if let Some(count) = app.copy_count {
+                // Accumulate: multiply by 10 and add digit (cap at 9999)
+                app.copy_count = Some((count * 10 + digit).min(9999));
                 return Ok(false);
-            }
-            // Handle text-object pending state (waiting for w/W after a/i)
-            if let Some(prefix) = app.copy_text_object_pending.take() {
-                if let KeyCode::Char(ch) = key.code {
-                    match (prefix, ch) {
-                        (0, 'w') => { crate::copy_mode::select_a_word(app); }
-                        (1, 'w') => { crate::copy_mode::select_inner_word(app); }
-                        (0, 'W') => { crate::copy_mode::select_a_word_big(app); }
-                        (1, 'W') => { crate::copy_mode::select_inner_word_big(app); }
-                        _ => {}
-                    }
-                }
+            } else if digit >= 1 {
+                // Start new count with 1-9
+                app.copy_count = Some(digit);
                 return Ok(false);
             }
-            // Handle find-char pending state (waiting for char after f/F/t/T)
-            if let Some(pending) = app.copy_find_char_pending.take() {
+            // digit == 0 with no existing count → fall through to the table
+        }
+    }
+    match lookup_key_binding(app, copy_mode_table(app), (key.code, key.modifiers)) {
+        Some((bind, is_any)) => {
+            if let Some(cmd) = copy_command_of(&bind.action) {
                 let n = app.copy_count.take().unwrap_or(1);
-                if let KeyCode::Char(ch) = key.code {
-                    match pending {
-                        0 => { for _ in 0..n { crate::copy_mode::find_char_forward(app, ch); } }
-                        1 => { for _ in 0..n { crate::copy_mode::find_char_backward(app, ch); } }
-                        2 => { for _ in 0..n { crate::copy_mode::find_char_to_forward(app, ch); } }
-                        3 => { for _ in 0..n { crate::copy_mode::find_char_to_backward(app, ch); } }
-                        _ => {}
-                    }
-                }
+                for _ in 0..n { crate::copy_mode::run_copy_command(app, cmd); }
+                // f/F/t/T wait for their character with the count kept
+                if app.copy_find_char_pending.is_some() { app.copy_count = Some(n); }
                 return Ok(false);
             }
-            // Handle numeric prefix accumulation for copy-mode motions (vi-style)
-            if let KeyCode::Char(d) = key.code {
-                if d.is_ascii_digit() && !key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(KeyModifiers::ALT) {
-                    let digit = d.to_digit(10).unwrap() as usize;
-                    if let Some(count) = app.copy_count {
-                        // Accumulate: multiply by 10 and add digit (cap at 9999)
-                        app.copy_count = Some((count * 10 + digit).min(9999));
-                        return Ok(false);
-                    } else if digit >= 1 {
-                        // Start new count with 1-9
-                        app.copy_count = Some(digit);
-                        return Ok(false);
-                    }
-                    // digit == 0 with no existing count → fall through to line-start handler
-                }
-            }
-            let copy_repeat = app.copy_count.take().unwrap_or(1);
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char(']') => { 
-                    exit_copy_mode(app);
-                }
-                // Ctrl+C exits copy mode (tmux parity, fixes #25)
-                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    exit_copy_mode(app);
-                }
-                KeyCode::Left | KeyCode::Char('h') => { for _ in 0..copy_repeat { move_copy_cursor(app, -1, 0); } }
-                KeyCode::Right | KeyCode::Char('l') => { for _ in 0..copy_repeat { move_copy_cursor(app, 1, 0); } }
-                KeyCode::Up | KeyCode::Char('k') => { for _ in 0..copy_repeat { move_copy_cursor(app, 0, -1); } }
-                KeyCode::Down | KeyCode::Char('j') => { for _ in 0..copy_repeat { move_copy_cursor(app, 0, 1); } }
-                // Page scroll: C-b / PageUp = page up, C-f / PageDown = page down
-                KeyCode::PageUp => { scroll_copy_up(app, 10); }
-                KeyCode::PageDown => { scroll_copy_down(app, 10); }
-                KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    if app.mode_keys == "emacs" { move_copy_cursor(app, -1, 0); }
-                    else { scroll_copy_up(app, 10); }
-                }
-                KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    if app.mode_keys == "emacs" { move_copy_cursor(app, 1, 0); }
-                    else { scroll_copy_down(app, 10); }
-                }
-                // Half-page scroll: C-u / C-d
-                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    let half = app.windows.get(app.active_idx)
-                        .and_then(|w| active_pane(&w.root, &w.active_path))
-                        .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                    scroll_copy_up(app, half);
-                }
-                KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    let half = app.windows.get(app.active_idx)
-                        .and_then(|w| active_pane(&w.root, &w.active_path))
-                        .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                    scroll_copy_down(app, half);
-                }
-                // Emacs copy-mode keys (must be before unqualified char matches)
-                KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => { scroll_copy_down(app, 1); }
-                KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => { scroll_copy_up(app, 1); }
-                KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => { crate::copy_mode::move_to_line_start(app); }
-                KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => { crate::copy_mode::move_to_line_end(app); }
-                KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::ALT) => { scroll_copy_up(app, 10); }
-                KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::ALT) => { crate::copy_mode::move_word_forward(app); }
-                KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::ALT) => { crate::copy_mode::move_word_backward(app); }
-                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::ALT) => { yank_selection(app)?; exit_copy_mode(app); }
-                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.mode = Mode::CopySearch { input: String::new(), forward: true };
-                }
-                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.mode = Mode::CopySearch { input: String::new(), forward: false };
-                }
-                KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    exit_copy_mode(app);
-                }
-                KeyCode::Char('g') => { scroll_to_top(app); }
-                KeyCode::Char('G') => { scroll_to_bottom(app); }
-                // Word motions: w = next word, b = prev word, e = end of word
-                KeyCode::Char('w') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_forward(app); } }
-                KeyCode::Char('b') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_backward(app); } }
-                KeyCode::Char('e') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_end(app); } }
-                // WORD motions: W = next WORD, B = prev WORD, E = end WORD
-                KeyCode::Char('W') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_forward_big(app); } }
-                KeyCode::Char('B') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_backward_big(app); } }
-                KeyCode::Char('E') => { for _ in 0..copy_repeat { crate::copy_mode::move_word_end_big(app); } }
-                // Screen position: H = top, M = middle, L = bottom
-                KeyCode::Char('H') => { crate::copy_mode::move_to_screen_top(app); }
-                KeyCode::Char('M') => { crate::copy_mode::move_to_screen_middle(app); }
-                KeyCode::Char('L') => { crate::copy_mode::move_to_screen_bottom(app); }
-                // Find char: f/F/t/T — sets pending state for next char
-                KeyCode::Char('f') => { app.copy_find_char_pending = Some(0); app.copy_count = Some(copy_repeat); }
-                KeyCode::Char('F') => { app.copy_find_char_pending = Some(1); app.copy_count = Some(copy_repeat); }
-                KeyCode::Char('t') => { app.copy_find_char_pending = Some(2); app.copy_count = Some(copy_repeat); }
-                KeyCode::Char('T') => { app.copy_find_char_pending = Some(3); app.copy_count = Some(copy_repeat); }
-                // D = copy from cursor to end of line
-                KeyCode::Char('D') => { crate::copy_mode::copy_end_of_line(app)?; exit_copy_mode(app); }
-                // Line motions: 0 = start, $ = end, ^ = first non-blank
-                KeyCode::Char('0') => { crate::copy_mode::move_to_line_start(app); }
-                KeyCode::Char('$') => { crate::copy_mode::move_to_line_end(app); }
-                KeyCode::Char('^') => { crate::copy_mode::move_to_first_nonblank(app); }
-                KeyCode::Home => { crate::copy_mode::move_to_line_start(app); }
-                KeyCode::End => { crate::copy_mode::move_to_line_end(app); }
-                KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // vi: toggle rectangle selection, emacs: page down
-                    if app.mode_keys == "emacs" {
-                        scroll_copy_down(app, 10);
-                    } else {
-                        app.copy_selection_mode = crate::types::SelectionMode::Rect;
-                    }
-                }
-                KeyCode::Char('v') => {
-                    // Start char-wise selection (vi visual mode)
-                    if let Some((r,c)) = crate::copy_mode::get_copy_pos(app) {
-                        app.copy_anchor = Some((r,c));
-                        app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                        app.copy_pos = Some((r,c));
-                        app.copy_selection_mode = crate::types::SelectionMode::Char;
-                    }
-                }
-                KeyCode::Char('V') => {
-                    // Start line-wise selection (vi visual-line mode)
-                    if let Some((r,c)) = crate::copy_mode::get_copy_pos(app) {
-                        app.copy_anchor = Some((r,c));
-                        app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                        app.copy_pos = Some((r,c));
-                        app.copy_selection_mode = crate::types::SelectionMode::Line;
-                    }
-                }
-                KeyCode::Char('o') => {
-                    // Swap cursor and anchor
-                    if let (Some(a), Some(p)) = (app.copy_anchor, app.copy_pos) {
-                        app.copy_anchor = Some(p);
-                        app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                        app.copy_pos = Some(a);
-                    }
-                }
-                KeyCode::Char('A') => {
-                    // Append to buffer (yank + append to buffer 0)
-                    if let (Some(_), Some(_)) = (app.copy_anchor, app.copy_pos) {
-                        // Save current buffer 0
-                        let prev = app.paste_buffers.first().cloned().unwrap_or_default();
-                        yank_selection(app)?;
-                        // buffer 0 is now the new yank; prepend old text
-                        if let Some(buf) = app.paste_buffers.first_mut() {
-                            let new_text = buf.clone();
-                            *buf = format!("{}{}", prev, new_text);
-                        }
-                        exit_copy_mode(app);
-                    }
-                }
-                // Space = begin selection (vi mode), Enter = copy-selection-and-cancel
-                KeyCode::Char(' ') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    if let Some((r,c)) = crate::copy_mode::get_copy_pos(app) {
-                        app.copy_anchor = Some((r,c));
-                        app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                        app.copy_pos = Some((r,c));
-                        app.copy_selection_mode = crate::types::SelectionMode::Char;
-                    }
-                }
-                KeyCode::Enter => {
-                    // Copy selection and exit copy mode (vi Enter)
-                    if app.copy_anchor.is_some() {
-                        yank_selection(app)?;
-                    }
-                    exit_copy_mode(app);
-                }
-                KeyCode::Char('y') => { yank_selection(app)?; exit_copy_mode(app); }
-                // --- copy-mode search ---
-                KeyCode::Char('/') => {
-                    app.mode = Mode::CopySearch { input: String::new(), forward: true };
-                }
-                KeyCode::Char('?') => {
-                    app.mode = Mode::CopySearch { input: String::new(), forward: false };
-                }
-                KeyCode::Char('n') => { search_next(app); }
-                KeyCode::Char('N') => { search_prev(app); }
-                KeyCode::Char(' ') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    // Set mark (anchor)
-                    if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                        app.copy_anchor = Some((r, c));
-                        app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                        app.copy_pos = Some((r, c));
-                    }
-                }
+            app.copy_count = None;
+            run_binding(app, &bind, is_any, &key)
+        }
+        None => {
+            app.copy_count = None;
+            match normalize_key_for_binding((key.code, key.modifiers)) {
                 // Named register prefix: " then a-z
-                KeyCode::Char('"') => { app.copy_register_pending = true; }
+                (KeyCode::Char('"'), KeyModifiers::NONE) => { app.copy_register_pending = true; }
                 // Text-object prefixes: a/i then w/W
-                KeyCode::Char('a') if !key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(KeyModifiers::ALT) => {
-                    app.copy_text_object_pending = Some(0);
-                }
-                KeyCode::Char('i') if !key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(KeyModifiers::ALT) => {
-                    app.copy_text_object_pending = Some(1);
-                }
+                (KeyCode::Char('a'), KeyModifiers::NONE) => { app.copy_text_object_pending = Some(0); }
+                (KeyCode::Char('i'), KeyModifiers::NONE) => { app.copy_text_object_pending = Some(1); }
                 _ => {}
             }
             Ok(false)
         }
-        Mode::CopySearch { .. } => {
-            match key.code {
-                KeyCode::Esc => {
-                    // Cancel search, return to copy mode
-                    app.mode = Mode::CopyMode;
-                }
-                KeyCode::Enter => {
-                    // Execute search
-                    if let Mode::CopySearch { ref input, forward } = app.mode {
-                        let query = input.clone();
-                        let fwd = forward;
-                        app.copy_search_query = query.clone();
-                        app.copy_search_forward = fwd;
-                        search_copy_mode(app, &query, fwd);
-                        // Jump to first match
-                        if !app.copy_search_matches.is_empty() {
-                            let (r, c, _) = app.copy_search_matches[0];
-                            app.copy_pos = Some((r, c));
-                        }
-                    }
-                    app.mode = Mode::CopyMode;
-                }
-                KeyCode::Backspace => {
-                    if let Mode::CopySearch { ref mut input, .. } = app.mode { let _ = input.pop(); }
-                }
-                KeyCode::Char(c) => {
-                    if let Mode::CopySearch { ref mut input, .. } = app.mode { input.push(c); }
-                }
-                _ => {}
-            }
-            Ok(false)
-        }
-        Mode::PaneChooser { .. } => {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('q') => { app.mode = Mode::Passthrough; }
-                KeyCode::Char(d) if d.is_ascii_digit() => {
-                    let raw = d.to_digit(10).unwrap() as usize;
-                    let choice = if raw == 0 { 10 } else { raw };
-                    if let Some((_, path)) = app.display_map.iter().find(|(n, _)| *n == choice) {
-                        let win = &mut app.windows[app.active_idx];
-                        win.active_path = path.clone();
-                        app.mode = Mode::Passthrough;
-                    }
-                }
-                _ => {}
-            }
-            Ok(false)
-        }
-        Mode::MenuMode { ref mut menu } => {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('q') => { 
-                    app.mode = Mode::Passthrough; 
-                }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if menu.selected > 0 {
-                        menu.selected -= 1;
-                        while menu.selected > 0 && menu.items.get(menu.selected).map(|i| i.is_separator).unwrap_or(false) {
-                            menu.selected -= 1;
-                        }
-                    }
-                }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if menu.selected + 1 < menu.items.len() {
-                        menu.selected += 1;
-                        while menu.selected + 1 < menu.items.len() && menu.items.get(menu.selected).map(|i| i.is_separator).unwrap_or(false) {
-                            menu.selected += 1;
-                        }
-                    }
-                }
-                KeyCode::Enter => {
-                    if let Some(item) = menu.items.get(menu.selected) {
-                        if !item.is_separator && !item.command.is_empty() {
-                            let cmd = item.command.clone();
-                            app.mode = Mode::Passthrough;
-                            let _ = execute_command_string(app, &cmd);
-                        } else {
-                            app.mode = Mode::Passthrough;
-                        }
-                    } else {
-                        app.mode = Mode::Passthrough;
-                    }
-                }
-                KeyCode::Char(c) => {
-                    if let Some((_idx, item)) = menu.items.iter().enumerate().find(|(_, i)| i.key == Some(c)) {
-                        if !item.is_separator && !item.command.is_empty() {
-                            let cmd = item.command.clone();
-                            app.mode = Mode::Passthrough;
-                            let _ = execute_command_string(app, &cmd);
-                        } else {
-                            app.mode = Mode::Passthrough;
-                        }
-                    }
-                }
-                _ => {}
-            }
-            Ok(false)
-        }
-        Mode::PopupMode { ref mut output, ref mut process, close_on_exit, ref mut popup_pty, .. } => {
-            let mut should_close = false;
-            let mut exit_status: Option<std::process::ExitStatus> = None;
-            
-            // If we have a PTY popup, forward keys to it
-            if let Some(ref mut pty) = popup_pty {
-                match key.code {
-                    KeyCode::Esc => {
-                        // Check if the child has exited
-                        if let Ok(Some(_)) = pty.child.try_wait() {
-                            should_close = true;
-                        } else {
-                            // Forward Escape to the PTY
-                            let _ = pty.writer.write_all(b"\x1b");
-                        }
-                    }
-                    KeyCode::Char(c) => {
-                        if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) {
-                            let ctrl = (c as u8).wrapping_sub(b'a').wrapping_add(1);
-                            let _ = pty.writer.write_all(&[ctrl]);
-                        } else {
-                            let mut buf = [0u8; 4];
-                            let s = c.encode_utf8(&mut buf);
-                            let _ = pty.writer.write_all(s.as_bytes());
-                        }
-                    }
-                    KeyCode::Enter => { let _ = pty.writer.write_all(b"\r"); }
-                    KeyCode::Backspace => { let _ = pty.writer.write_all(b"\x7f"); }
-                    KeyCode::Tab => { let _ = pty.writer.write_all(b"\t"); }
-                    KeyCode::BackTab => { let _ = pty.writer.write_all(b"\x1b[Z"); }
-                    KeyCode::Up => { let _ = pty.writer.write_all(b"\x1b[A"); }
-                    KeyCode::Down => { let _ = pty.writer.write_all(b"\x1b[B"); }
-                    KeyCode::Right => { let _ = pty.writer.write_all(b"\x1b[C"); }
-                    KeyCode::Left => { let _ = pty.writer.write_all(b"\x1b[D"); }
-                    KeyCode::Home => { let _ = pty.writer.write_all(b"\x1b[H"); }
-                    KeyCode::End => { let _ = pty.writer.write_all(b"\x1b[F"); }
-                    KeyCode::PageUp => { let _ = pty.writer.write_all(b"\x1b[5~"); }
-                    KeyCode::PageDown => { let _ = pty.writer.write_all(b"\x1b[6~"); }
-                    KeyCode::Delete => { let _ = pty.writer.write_all(b"\x1b[3~"); }
-                    _ => {}
-                }
-                // Check if child exited
-                if let Ok(Some(_status)) = pty.child.try_wait() {
-                    if close_on_exit {
-                        should_close = true;
-                    }
-                }
-            } else {
-                // Non-PTY popup (static output)
-                match key.code {
-                    KeyCode::Esc | KeyCode::Char('q') => {
-                        if let Some(ref mut proc) = process {
-                            let _ = proc.kill();
-                        }
-                        should_close = true;
-                    }
-                    KeyCode::Char(c) => {
-                        output.push(c);
-                    }
-                    KeyCode::Enter => {
-                        output.push('\n');
-                    }
-                    _ => {}
-                }
-                
-                if let Some(ref mut proc) = process {
-                    if let Ok(Some(status)) = proc.try_wait() {
-                        exit_status = Some(status);
-                        if close_on_exit {
-                            should_close = true;
-                        }
-                    }
-                }
-                
-                if let Some(status) = exit_status {
-                    if !close_on_exit {
-                        output.push_str(&format!("\n[Process exited with status: {}]", status));
-                    }
-                }
-            }
-            
-            if should_close {
-                app.mode = Mode::Passthrough;
-            }
-            
-            Ok(false)
-        }
-        Mode::ConfirmMode { prompt: _, ref command, ref mut input } => {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
-                    app.mode = Mode::Passthrough;
-                }
-                KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
-                    let cmd = command.clone();
-                    app.mode = Mode::Passthrough;
-                    let _ = execute_command_string(app, &cmd);
-                }
-                KeyCode::Char(c) => {
-                    input.push(c);
-                }
-                KeyCode::Backspace => {
-                    input.pop();
-                }
-                _ => {}
-            }
-            Ok(false)
-        }
-        Mode::ClockMode => {
-            // Any key exits clock mode
-            app.mode = Mode::Passthrough;
-            Ok(false)
-        }
-        Mode::BufferChooser { selected } => {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('q') => { app.mode = Mode::Passthrough; }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if selected > 0 {
-                        if let Mode::BufferChooser { selected: s } = &mut app.mode { *s -= 1; }
-                    }
-                }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    let max = app.paste_buffers.len().saturating_sub(1);
-                    if selected < max {
-                        if let Mode::BufferChooser { selected: s } = &mut app.mode { *s += 1; }
-                    }
-                }
-                KeyCode::Enter => {
-                    // Paste selected buffer
-                    if selected < app.paste_buffers.len() {
-                        let text = app.paste_buffers[selected].clone();
-                        app.mode = Mode::Passthrough;
-                        let win = &mut app.windows[app.active_idx];
-                        if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
-                            let _ = write!(p.writer, "{}", text);
-                        }
-                    } else {
-                        app.mode = Mode::Passthrough;
-                    }
-                }
-                KeyCode::Char('d') | KeyCode::Delete => {
-                    // Delete selected buffer
-                    if selected < app.paste_buffers.len() {
-                        app.paste_buffers.remove(selected);
-                        if let Mode::BufferChooser { selected: s } = &mut app.mode {
-                            if *s >= app.paste_buffers.len() && *s > 0 { *s -= 1; }
-                        }
-                        if app.paste_buffers.is_empty() { app.mode = Mode::Passthrough; }
-                    }
-                }
-                _ => {}
-            }
-            Ok(false)
+    }
+}
+
+/// The key an attached client names in a `send-key` request (`c-b`,
+/// `m-w`, `s-left`, `pageup`), in the form key tables use.
+fn client_key(name: &str) -> Option<KeyEvent> {
+    let name = match name.split_once('-') {
+        Some((m, rest)) if m.len() == 1 && !rest.is_empty() => format!("{}-{}", m.to_ascii_uppercase(), rest),
+        _ => name.to_string(),
+    };
+    parse_key_name(&name).map(|(code, mods)| KeyEvent::new(code, mods))
+}
+
+/// switch-client -T: the next key is looked up in `table`.  `prefix` arms
+/// the prefix and `root` goes back to no table.
+pub fn switch_key_table(app: &mut AppState, table: &str) {
+    app.current_key_table = None;
+    match table {
+        "prefix" => app.mode = Mode::Prefix { armed_at: Instant::now(), repeating: false },
+        "root" => if matches!(app.mode, Mode::Prefix { .. }) { app.mode = Mode::Passthrough },
+        _ => {
+            app.current_key_table = Some(table.to_string());
+            app.key_table_at = Instant::now();
         }
     }
 }
 
+/// Whether prefix-timeout has passed since `since`.
+pub fn prefix_timed_out(app: &AppState, since: Instant) -> bool {
+    app.prefix_timeout_ms > 0 && since.elapsed().as_millis() as u64 >= app.prefix_timeout_ms
+}
+
 pub fn move_focus(app: &mut AppState, dir: FocusDir) {
     let win = &mut app.windows[app.active_idx];
     let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
@@ -1084,332 +256,19 @@ pub fn find_best_pane_in_direction(
     best.map(|(idx, _, _, _)| idx)
 }
 
-pub fn forward_key_to_active(app: &mut AppState, key: KeyEvent) -> io::Result<()> {
-    // Encode the key into bytes
-    let encoded: Vec<u8> = match key.code {
-        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) && key.modifiers.contains(KeyModifiers::ALT) => {
-            let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
-            vec![0x1b, ctrl_char]
-        }
-        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::ALT) => {
-            format!("\x1b{}", c).into_bytes()
-        }
-        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
-            let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
-            vec![ctrl_char]
-        }
-        KeyCode::Char(c) if (c as u8) >= 0x01 && (c as u8) <= 0x1A => {
-            vec![c as u8]
-        }
-        KeyCode::Char(c) => {
-            format!("{}", c).into_bytes()
-        }
-        KeyCode::Enter => b"\r".to_vec(),
-        KeyCode::Tab => b"\t".to_vec(),
-        KeyCode::BackTab => b"\x1b[Z".to_vec(),
-        KeyCode::Backspace => b"\x7f".to_vec(),
-        KeyCode::Esc => b"\x1b".to_vec(),
-        KeyCode::Left => b"\x1b[D".to_vec(),
-        KeyCode::Right => b"\x1b[C".to_vec(),
-        KeyCode::Up => b"\x1b[A".to_vec(),
-        KeyCode::Down => b"\x1b[B".to_vec(),
-        _ => return Ok(()),
-    };
-
-    if app.sync_input {
-        // Fan out to ALL panes in the current window
-        let win = &mut app.windows[app.active_idx];
-        fn write_all_panes(node: &mut Node, data: &[u8]) {
-            match node {
-                Node::Leaf(p) if !p.dead => { let _ = p.writer.write_all(data); let _ = p.writer.flush(); }
-                Node::Leaf(_) => {}
-                Node::Split { children, .. } => { for c in children { write_all_panes(c, data); } }
-            }
-        }
-        write_all_panes(&mut win.root, &encoded);
-    } else {
-        let win = &mut app.windows[app.active_idx];
-        if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-            if !active.dead {
-                let _ = active.writer.write_all(&encoded);
-                let _ = active.writer.flush();
-            }
-        }
-    }
-    Ok(())
-}
-
-fn wheel_cell_for_area(area: Rect, x: u16, y: u16) -> (u16, u16) {
-    // Convert global terminal coordinates to 1-based pane-local coordinates.
-    let inner_x = area.x.saturating_add(1);
-    let inner_y = area.y.saturating_add(1);
-    let inner_w = area.width.saturating_sub(2).max(1);
-    let inner_h = area.height.saturating_sub(2).max(1);
-
-    let col = x
-        .saturating_sub(inner_x)
-        .min(inner_w.saturating_sub(1))
-        .saturating_add(1);
-    let row = y
-        .saturating_sub(inner_y)
-        .min(inner_h.saturating_sub(1))
-        .saturating_add(1);
-    (col, row)
-}
-
-pub fn handle_mouse(app: &mut AppState, me: MouseEvent, window_area: Rect) -> io::Result<()> {
-    use crossterm::event::{MouseEventKind, MouseButton};
-
-    // --- Tab click: check if click is on the status bar row ---
-    let status_row = window_area.y + window_area.height; // status bar is 1 row below window area
-    if matches!(me.kind, MouseEventKind::Down(MouseButton::Left)) && me.row == status_row {
-        for &(win_idx, x_start, x_end) in app.tab_positions.iter() {
-            if me.column >= x_start && me.column < x_end {
-                if win_idx < app.windows.len() {
-                    switch_with_copy_save(app, |app| {
-                        app.last_window_idx = app.active_idx;
-                        app.active_idx = win_idx;
-                    });
-                }
-                return Ok(());
-            }
-        }
-        // Click was on status bar but not on a tab — ignore
-        return Ok(());
-    }
-
-    // If a left-click lands on a different pane while in copy mode, save
-    // copy state to the current pane and restore from the new pane (tmux parity #43).
-    if matches!(me.kind, crossterm::event::MouseEventKind::Down(crossterm::event::MouseButton::Left))
-        && matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. })
-    {
-        let win = &app.windows[app.active_idx];
-        let mut rects_check: Vec<(Vec<usize>, Rect)> = Vec::new();
-        compute_rects(&win.root, window_area, &mut rects_check);
-        let mut clicked_new_path: Option<Vec<usize>> = None;
-        for (path, area) in rects_check.iter() {
-            if area.contains(ratatui::layout::Position { x: me.column, y: me.row }) {
-                if *path != win.active_path {
-                    clicked_new_path = Some(path.clone());
-                }
-                break;
-            }
-        }
-        if let Some(np) = clicked_new_path {
-            // Save copy state to current pane, reset its scrollback
-            save_copy_state_to_pane(app);
-            // Switch active pane path
-            {
-                let win = &mut app.windows[app.active_idx];
-                app.last_pane_path = win.active_path.clone();
-                win.active_path = np;
-            }
-            // Restore from new pane (likely Passthrough if it wasn't in copy mode)
-            restore_copy_state_from_pane(app);
-        }
-    }
-
+/// Gate input for the active pane while its view is scrolled back
+/// (scroll-without-copy-mode).  By default the view snaps to live output and
+/// the input goes through; with `scrolled-input block` input is dropped and
+/// only Escape or `q` bring the view back.  Returns whether the input should
+/// reach the pane.
+pub fn admit_scrolled_input(app: &mut AppState, releases: bool) -> bool {
+    let block = app.scrolled_input_block;
     let win = &mut app.windows[app.active_idx];
-    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
-    compute_rects(&win.root, window_area, &mut rects);
-    let mut borders: Vec<(Vec<usize>, LayoutKind, usize, u16, u16)> = Vec::new();
-    compute_split_borders(&win.root, window_area, &mut borders);
-    let mut active_area = rects
-        .iter()
-        .find(|(path, _)| *path == win.active_path)
-        .map(|(_, area)| *area);
-
-    /// Convert absolute screen coordinates to pane-local 1-based cell coordinates,
-    /// accounting for the 1px border on each side (Block with Borders::ALL).
-    fn pane_cell(area: Rect, abs_x: u16, abs_y: u16) -> (u16, u16) {
-        let col = abs_x.saturating_sub(area.x + 1) + 1;
-        let row = abs_y.saturating_sub(area.y + 1) + 1;
-        (col, row)
-    }
-
-    match me.kind {
-        MouseEventKind::Down(MouseButton::Left) => {
-            // Check if click is on a split border (for dragging)
-            let mut on_border = false;
-            let tol = 1u16;
-            for (path, kind, idx, pos, total_px) in borders.iter() {
-                match kind {
-                    LayoutKind::Horizontal => {
-                        if me.column >= pos.saturating_sub(tol) && me.column <= pos + tol {
-                            if let Some((left,right)) = split_sizes_at(&win.root, path.clone(), *idx) {
-                                app.drag = Some(DragState { split_path: path.clone(), kind: *kind, index: *idx, start_x: *pos, start_y: me.row, left_initial: left, _right_initial: right, total_pixels: *total_px });
-                            }
-                            on_border = true;
-                            break;
-                        }
-                    }
-                    LayoutKind::Vertical => {
-                        if me.row >= pos.saturating_sub(tol) && me.row <= pos + tol {
-                            if let Some((left,right)) = split_sizes_at(&win.root, path.clone(), *idx) {
-                                app.drag = Some(DragState { split_path: path.clone(), kind: *kind, index: *idx, start_x: me.column, start_y: *pos, left_initial: left, _right_initial: right, total_pixels: *total_px });
-                            }
-                            on_border = true;
-                            break;
-                        }
-                    }
-                }
-            }
-
-            // Switch pane focus if clicking inside a pane
-            for (path, area) in rects.iter() {
-                if area.contains(ratatui::layout::Position { x: me.column, y: me.row }) {
-                    win.active_path = path.clone();
-                    active_area = Some(*area);
-                }
-            }
-
-            // Forward left-click to child pane
-            if !on_border {
-                if let Some(area) = active_area {
-                    if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-                        let col = me.column.saturating_sub(area.x + 1) as i16;
-                        let row = me.row.saturating_sub(area.y + 1) as i16;
-                        // Check if child has requested VT mouse protocol
-                        let (mode, enc) = {
-                            if let Ok(parser) = active.term.lock() {
-                                let s = parser.screen();
-                                (s.mouse_protocol_mode(), s.mouse_protocol_encoding())
-                            } else {
-                                (vt100::MouseProtocolMode::None, vt100::MouseProtocolEncoding::Default)
-                            }
-                        };
-                        if mode != vt100::MouseProtocolMode::None {
-                            let vt_col = (col + 1).max(1) as u16;
-                            let vt_row = (row + 1).max(1) as u16;
-                            write_mouse_event(&mut active.writer, 0, vt_col, vt_row, true, enc);
-                        } else {
-                            if active.child_pid.is_none() {
-                                active.child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*active.child) };
-                            }
-                            if let Some(pid) = active.child_pid {
-                                crate::platform::mouse_inject::send_mouse_event(
-                                    pid, col, row,
-                                    crate::platform::mouse_inject::FROM_LEFT_1ST_BUTTON_PRESSED, 0,
-                                    true,
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-
-        }
-        MouseEventKind::Down(MouseButton::Right) => {
-            // Right-click not forwarded - reserved for psmux context menu
-        }
-        MouseEventKind::Down(MouseButton::Middle) => {
-            // Middle-click not forwarded - reserved for paste
-        }
-        MouseEventKind::Up(MouseButton::Left) => {
-            let was_dragging = app.drag.is_some();
-            app.drag = None;
-            if was_dragging {
-                resize_all_panes(app);
-            } else if let Some(area) = active_area {
-                // Forward mouse release
-                if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-                    let col = me.column.saturating_sub(area.x + 1) as i16;
-                    let row = me.row.saturating_sub(area.y + 1) as i16;
-                    let (mode, enc) = {
-                        if let Ok(parser) = active.term.lock() {
-                            let s = parser.screen();
-                            (s.mouse_protocol_mode(), s.mouse_protocol_encoding())
-                        } else {
-                            (vt100::MouseProtocolMode::None, vt100::MouseProtocolEncoding::Default)
-                        }
-                    };
-                    if mode != vt100::MouseProtocolMode::None {
-                        let vt_col = (col + 1).max(1) as u16;
-                        let vt_row = (row + 1).max(1) as u16;
-                        write_mouse_event(&mut active.writer, 0, vt_col, vt_row, false, enc);
-                    } else if let Some(pid) = active.child_pid {
-                        crate::platform::mouse_inject::send_mouse_event(pid, col, row, 0, 0, true);
-                    }
-                }
-            }
-        }
-        MouseEventKind::Up(MouseButton::Right) => {
-            // Not forwarded
-        }
-        MouseEventKind::Up(MouseButton::Middle) => {
-            // Not forwarded
-        }
-        MouseEventKind::Drag(MouseButton::Left) => {
-            if let Some(d) = &app.drag {
-                adjust_split_sizes(&mut win.root, d, me.column, me.row);
-            } else {
-                // Forward drag to child pane
-                if let Some(area) = active_area {
-                    if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-                        let col = me.column.saturating_sub(area.x + 1) as i16;
-                        let row = me.row.saturating_sub(area.y + 1) as i16;
-                        let (mode, enc) = {
-                            if let Ok(parser) = active.term.lock() {
-                                let s = parser.screen();
-                                (s.mouse_protocol_mode(), s.mouse_protocol_encoding())
-                            } else {
-                                (vt100::MouseProtocolMode::None, vt100::MouseProtocolEncoding::Default)
-                            }
-                        };
-                        if mode != vt100::MouseProtocolMode::None {
-                            let vt_col = (col + 1).max(1) as u16;
-                            let vt_row = (row + 1).max(1) as u16;
-                            // button 0 + 32 = drag modifier
-                            write_mouse_event(&mut active.writer, 32, vt_col, vt_row, true, enc);
-                        } else {
-                            if let Some(pid) = active.child_pid {
-                                crate::platform::mouse_inject::send_mouse_event(
-                                    pid, col, row,
-                                    crate::platform::mouse_inject::FROM_LEFT_1ST_BUTTON_PRESSED,
-                                    crate::platform::mouse_inject::MOUSE_MOVED,
-                                    true,
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        MouseEventKind::Moved => {
-            // Don't forward bare motion - only forward drag events
-            // Most TUI apps don't want constant mouse position updates
-        }
-        MouseEventKind::ScrollUp => {
-            if matches!(app.mode, Mode::CopyMode) {
-                scroll_copy_up(app, 3);
-                return Ok(());
-            }
-            if let Some((path, area)) = rects.iter().find(|(_, area)| area.contains(ratatui::layout::Position { x: me.column, y: me.row })) {
-                win.active_path = path.clone();
-                active_area = Some(*area);
-            }
-            let (col, row) = active_area.map_or((1, 1), |area| wheel_cell_for_area(area, me.column, me.row));
-            if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-                let _ = write!(active.writer, "\x1b[<64;{};{}M", col, row);
-            }
-        }
-        MouseEventKind::ScrollDown => {
-            if matches!(app.mode, Mode::CopyMode) {
-                scroll_copy_down(app, 3);
-                return Ok(());
-            }
-            if let Some((path, area)) = rects.iter().find(|(_, area)| area.contains(ratatui::layout::Position { x: me.column, y: me.row })) {
-                win.active_path = path.clone();
-                active_area = Some(*area);
-            }
-            let (col, row) = active_area.map_or((1, 1), |area| wheel_cell_for_area(area, me.column, me.row));
-            if let Some(active) = active_pane_mut(&mut win.root, &win.active_path) {
-                let _ = write!(active.writer, "\x1b[<65;{};{}M", col, row);
-            }
-        }
-        _ => {}
-    }
-    Ok(())
+    let Some(p) = active_pane_mut(&mut win.root, &win.active_path) else { return true };
+    if !p.view_scrolled { return true; }
+    let (snap, forward) = crate::window_ops::scrolled_input_outcome(block, releases);
+    if snap { crate::window_ops::snap_pane_view(p); }
+    forward
 }
 
 pub fn send_text_to_active(app: &mut AppState, text: &str) -> io::Result<()> {
@@ -1432,132 +291,248 @@ pub fn send_text_to_active(app: &mut AppState, text: &str) -> io::Result<()> {
                 input.push(c);
             }
         }
+        crate::copy_mode::schedule_incremental_search(app, Instant::now());
         return Ok(());
     }
+    if !admit_scrolled_input(app, text == "q") { return Ok(()); }
 
-    if app.sync_input {
+    if crate::scoped::active_synchronized(app) {
         // Fan out to ALL panes in the current window
         let win = &mut app.windows[app.active_idx];
-        fn write_all_panes(node: &mut Node, text: &[u8]) {
-            match node {
-                Node::Leaf(p) => { let _ = p.writer.write_all(text); let _ = p.writer.flush(); }
-                Node::Split { children, .. } => { for c in children { write_all_panes(c, text); } }
-            }
-        }
-        write_all_panes(&mut win.root, text.as_bytes());
+        let mut writers = Vec::new();
+        collect_pane_writers(&mut win.root, &mut writers);
+        write_input_batch(writers, text.as_bytes());
     } else {
         let win = &mut app.windows[app.active_idx];
         if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
-            let _ = p.writer.write_all(text.as_bytes());
-            let _ = p.writer.flush();
+            write_pane_input(&mut *p.writer, text.as_bytes());
         }
     }
     Ok(())
 }
 
-/// Dispatch a single character as a copy-mode action.
-fn handle_copy_mode_char(app: &mut AppState, c: char) -> io::Result<()> {
-    // Handle text-object pending state (waiting for w/W after a/i)
-    if let Some(prefix) = app.copy_text_object_pending.take() {
-        match (prefix, c) {
-            (0, 'w') => { crate::copy_mode::select_a_word(app); }
-            (1, 'w') => { crate::copy_mode::select_inner_word(app); }
-            (0, 'W') => { crate::copy_mode::select_a_word_big(app); }
-            (1, 'W') => { crate::copy_mode::select_inner_word_big(app); }
-            _ => {}
-        }
-        return Ok(());
+/// send-bytes: write `bytes` to the active pane's PTY as they are, with no
+/// key-name translation, copy-mode handling or synchronize-panes fan-out.
+pub fn send_bytes_to_active(app: &mut AppState, bytes: &[u8]) {
+    let Some(win) = app.windows.get_mut(app.active_idx) else { return };
+    if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+        if !p.dead { write_pane_input(&mut *p.writer, bytes); }
     }
-    // Handle find-char pending state (waiting for char after f/F/t/T)
-    if let Some(pending) = app.copy_find_char_pending.take() {
-        match pending {
-            0 => crate::copy_mode::find_char_forward(app, c),
-            1 => crate::copy_mode::find_char_backward(app, c),
-            2 => crate::copy_mode::find_char_to_forward(app, c),
-            3 => crate::copy_mode::find_char_to_backward(app, c),
-            _ => {}
-        }
-        return Ok(());
+}
+
+/// Does a paste go to every pane of the window?  Only when asked for
+/// (`paste-buffer -s`), or with both synchronize-panes and sync-paste on;
+/// typing follows synchronize-panes alone.
+pub fn paste_broadcasts(app: &AppState, sync: bool) -> bool {
+    sync || (crate::scoped::active_synchronized(app) && app.sync_paste)
+}
+
+/// Paste `text` into the active pane, or into every pane of the window when
+/// `broadcast`.
+pub fn paste_to_panes(app: &mut AppState, text: &str, broadcast: bool) {
+    let Some(win) = app.windows.get_mut(app.active_idx) else { return };
+    if broadcast {
+        crate::tree::for_each_pane_mut(&mut win.root, &mut |p| paste_into_pane(p, text));
+    } else if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+        paste_into_pane(p, text);
     }
-    match c {
-        'q' | ']' | '\x1b' => {
-            exit_copy_mode(app);
-        }
-        'h' => { move_copy_cursor(app, -1, 0); }
-        'l' => { move_copy_cursor(app, 1, 0); }
-        'k' => { move_copy_cursor(app, 0, -1); }
-        'j' => { move_copy_cursor(app, 0, 1); }
-        'g' => { scroll_to_top(app); }
-        'G' => { scroll_to_bottom(app); }
-        'w' => { crate::copy_mode::move_word_forward(app); }
-        'b' => { crate::copy_mode::move_word_backward(app); }
-        'e' => { crate::copy_mode::move_word_end(app); }
-        'W' => { crate::copy_mode::move_word_forward_big(app); }
-        'B' => { crate::copy_mode::move_word_backward_big(app); }
-        'E' => { crate::copy_mode::move_word_end_big(app); }
-        'H' => { crate::copy_mode::move_to_screen_top(app); }
-        'M' => { crate::copy_mode::move_to_screen_middle(app); }
-        'L' => { crate::copy_mode::move_to_screen_bottom(app); }
-        'f' => { app.copy_find_char_pending = Some(0); }
-        'F' => { app.copy_find_char_pending = Some(1); }
-        't' => { app.copy_find_char_pending = Some(2); }
-        'T' => { app.copy_find_char_pending = Some(3); }
-        'D' => { crate::copy_mode::copy_end_of_line(app)?; exit_copy_mode(app); }
-        '0' => { crate::copy_mode::move_to_line_start(app); }
-        '$' => { crate::copy_mode::move_to_line_end(app); }
-        '^' => { crate::copy_mode::move_to_first_nonblank(app); }
-        ' ' => {
-            if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                app.copy_anchor = Some((r, c));
-                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                app.copy_pos = Some((r, c));
-                app.copy_selection_mode = crate::types::SelectionMode::Char;
-            }
-        }
-        'v' => {
-            if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                app.copy_anchor = Some((r, c));
-                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                app.copy_pos = Some((r, c));
-                app.copy_selection_mode = crate::types::SelectionMode::Char;
-            }
-        }
-        'V' => {
-            if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                app.copy_anchor = Some((r, c));
-                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                app.copy_pos = Some((r, c));
-                app.copy_selection_mode = crate::types::SelectionMode::Line;
-            }
-        }
-        'o' => {
-            if let (Some(a), Some(p)) = (app.copy_anchor, app.copy_pos) {
-                app.copy_anchor = Some(p);
-                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                app.copy_pos = Some(a);
-            }
-        }
-        'A' => {
-            if let (Some(_), Some(_)) = (app.copy_anchor, app.copy_pos) {
-                let prev = app.paste_buffers.first().cloned().unwrap_or_default();
-                yank_selection(app)?;
-                if let Some(buf) = app.paste_buffers.first_mut() {
-                    let new_text = buf.clone();
-                    *buf = format!("{}{}", prev, new_text);
+}
+
+/// Write a paste to one pane, bracketed if its application turned on
+/// bracketed paste mode.
+pub fn paste_into_pane(p: &mut crate::types::Pane, text: &str) {
+    if p.dead { return; }
+    let bracketed = p.term.lock().map_or(false, |t| t.screen().bracketed_paste());
+    if bracketed {
+        write_pane_input(&mut *p.writer, format!("\x1b[200~{}\x1b[201~", text).as_bytes());
+    } else {
+        write_pane_input(&mut *p.writer, text.as_bytes());
+    }
+}
+
+/// Write one complete input sequence to a pane: a single `write_all` and a
+/// flush, so ConPTY never sees part of a sequence on its own.
+pub fn write_pane_input(writer: &mut dyn Write, bytes: &[u8]) {
+    let _ = writer.write_all(bytes);
+    let _ = writer.flush();
+}
+
+/// Write the same sequence to several panes.  Each pane gets the whole
+/// sequence before the next pane is touched, and all pane writers belong to
+/// the server loop, so no other command can land in the middle of it.
+pub fn write_input_batch(writers: Vec<&mut (dyn Write + Send)>, bytes: &[u8]) {
+    for w in writers {
+        write_pane_input(w, bytes);
+    }
+}
+
+fn collect_pane_writers<'a>(node: &'a mut Node, out: &mut Vec<&'a mut (dyn Write + Send)>) {
+    match node {
+        Node::Leaf(p) => out.push(&mut *p.writer),
+        Node::Split { children, .. } => { for c in children { collect_pane_writers(c, out); } }
+    }
+}
+
+/// Is `key` a send-keys key name (rather than literal text)?
+fn is_send_keys_name(key_upper: &str) -> bool {
+    matches!(key_upper,
+        "ENTER" | "TAB" | "BTAB" | "BACKTAB" | "ESCAPE" | "ESC" | "SPACE" | "BSPACE" | "BACKSPACE" |
+        "UP" | "DOWN" | "RIGHT" | "LEFT" | "HOME" | "END" |
+        "PAGEUP" | "PPAGE" | "PAGEDOWN" | "NPAGE" | "DELETE" | "DC" | "INSERT" | "IC" |
+        "F1" | "F2" | "F3" | "F4" | "F5" | "F6" | "F7" | "F8" | "F9" | "F10" | "F11" | "F12"
+    ) || key_upper.starts_with("C-") || key_upper.starts_with("M-")
+}
+
+/// A send-keys command's flags and keys.
+#[derive(Debug, Default, PartialEq)]
+pub struct SendKeys {
+    /// `-l`: the keys are text, not key names
+    pub literal: bool,
+    /// `-H`: each key is one byte in hex (`0x03`, `1b`)
+    pub hex: bool,
+    /// `-R`: reset the pane's terminal state first
+    pub reset: bool,
+    /// `-X`: the keys are a copy-mode command
+    pub copy_command: bool,
+    /// `-N`: how many times to send them
+    pub repeat: usize,
+    pub keys: Vec<String>,
+}
+
+/// Parse send-keys' arguments (after the command name).  Flags come
+/// before the first key; `--` ends them.
+pub fn parse_send_keys(args: &[&str]) -> Result<SendKeys, String> {
+    let mut sk = SendKeys { repeat: 1, ..Default::default() };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "--" => { i += 1; break; }
+            "-t" => i += 1,
+            "-N" => {
+                let n = args.get(i + 1).ok_or("-N needs a count")?;
+                sk.repeat = n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("bad repeat count '{}'", n))?;
+                i += 1;
+            }
+            a if a.len() > 1 && a.starts_with('-') && a[1..].chars().all(|c| "lHRX".contains(c)) => {
+                sk.literal |= a.contains('l');
+                sk.hex |= a.contains('H');
+                sk.reset |= a.contains('R');
+                sk.copy_command |= a.contains('X');
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    sk.keys = args[i.min(args.len())..].iter().map(|k| k.to_string()).collect();
+    Ok(sk)
+}
+
+/// `send-keys -H` keys as bytes: one hex byte per key, `0x` optional.
+pub fn hex_keys(keys: &[String]) -> Result<Vec<u8>, String> {
+    keys.iter().map(|k| {
+        let digits = k.strip_prefix("0x").or_else(|| k.strip_prefix("0X")).unwrap_or(k);
+        u8::from_str_radix(digits, 16).map_err(|_| format!("invalid hex byte '{}'", k))
+    }).collect()
+}
+
+/// What `send-keys -R` feeds a pane's parser after leaving the alternate
+/// screen: the scroll region (keeping the cursor where it is), attributes,
+/// cursor visibility and the key and mouse modes a crashed program left set
+/// go back to their defaults.  The screen's text stays.
+const TERMINAL_RESET: &[u8] =
+    b"\x1b7\x1b[r\x1b8\x1b[0m\x1b[?25h\x1b[?1l\x1b>\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?2004l";
+
+/// Reset a parser's terminal state.  CAN first ends a half-read escape
+/// sequence.
+pub fn reset_terminal(parser: &mut vt100::Parser) {
+    parser.process(b"\x18");
+    if parser.screen().alternate_screen() { parser.process(b"\x1b[?1049l"); }
+    parser.process(TERMINAL_RESET);
+}
+
+/// send-keys -R: reset the active pane's terminal state.
+pub fn reset_active_terminal(app: &mut AppState) {
+    let Some(win) = app.windows.get_mut(app.active_idx) else { return };
+    if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+        if let Ok(mut parser) = p.term.lock() { reset_terminal(&mut parser); }
+        p.data_version.fetch_add(1, std::sync::atomic::Ordering::Release);
+    }
+}
+
+/// Translate a send-keys argument list ("echo hi Enter", "C-c") into the
+/// full byte sequence for the PTY, so it can be written in one go.
+pub fn encode_send_keys(keys: &str) -> String {
+    let parts: Vec<&str> = keys.split_whitespace().collect();
+    let mut out = String::new();
+    for (i, key) in parts.iter().enumerate() {
+        let key_upper = key.to_uppercase();
+        match key_upper.as_str() {
+            "ENTER" => out.push('\r'),
+            "TAB" => out.push('\t'),
+            "BTAB" | "BACKTAB" => out.push_str("\x1b[Z"),
+            "ESCAPE" | "ESC" => out.push('\x1b'),
+            "SPACE" => out.push(' '),
+            "BSPACE" | "BACKSPACE" => out.push('\x7f'),
+            "UP" => out.push_str("\x1b[A"),
+            "DOWN" => out.push_str("\x1b[B"),
+            "RIGHT" => out.push_str("\x1b[C"),
+            "LEFT" => out.push_str("\x1b[D"),
+            "HOME" => out.push_str("\x1b[H"),
+            "END" => out.push_str("\x1b[F"),
+            "PAGEUP" | "PPAGE" => out.push_str("\x1b[5~"),
+            "PAGEDOWN" | "NPAGE" => out.push_str("\x1b[6~"),
+            "DELETE" | "DC" => out.push_str("\x1b[3~"),
+            "INSERT" | "IC" => out.push_str("\x1b[2~"),
+            "F1" => out.push_str("\x1bOP"),
+            "F2" => out.push_str("\x1bOQ"),
+            "F3" => out.push_str("\x1bOR"),
+            "F4" => out.push_str("\x1bOS"),
+            "F5" => out.push_str("\x1b[15~"),
+            "F6" => out.push_str("\x1b[17~"),
+            "F7" => out.push_str("\x1b[18~"),
+            "F8" => out.push_str("\x1b[19~"),
+            "F9" => out.push_str("\x1b[20~"),
+            "F10" => out.push_str("\x1b[21~"),
+            "F11" => out.push_str("\x1b[23~"),
+            "F12" => out.push_str("\x1b[24~"),
+            s if s.starts_with("C-M-") => {
+                if let Some(c) = key.chars().nth(4) {
+                    let ctrl = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a').wrapping_add(1);
+                    out.push('\x1b');
+                    out.push(ctrl as char);
+                }
+            }
+            s if s.starts_with("C-") => {
+                if let Some(c) = s.chars().nth(2) {
+                    let ctrl = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a').wrapping_add(1);
+                    out.push(ctrl as char);
+                }
+            }
+            s if s.starts_with("M-") => {
+                if let Some(c) = key.chars().nth(2) {
+                    out.push('\x1b');
+                    out.push(c);
+                }
+            }
+            _ => {
+                out.push_str(key);
+                // Words are separated by spaces unless a key name follows
+                if i + 1 < parts.len() && !is_send_keys_name(&parts[i + 1].to_uppercase()) {
+                    out.push(' ');
                 }
-                exit_copy_mode(app);
             }
         }
-        'y' => { yank_selection(app)?; exit_copy_mode(app); }
-        '/' => { app.mode = Mode::CopySearch { input: String::new(), forward: true }; }
-        '?' => { app.mode = Mode::CopySearch { input: String::new(), forward: false }; }
-        'n' => { search_next(app); }
-        'N' => { search_prev(app); }
-        'i' => { app.copy_text_object_pending = Some(1); }  // inner text object
-        'a' => { app.copy_text_object_pending = Some(0); }  // a text object
-        _ => {} // Swallow unrecognized characters in copy mode
     }
-    Ok(())
+    out
+}
+
+/// Dispatch a single character as a copy-mode action.
+fn handle_copy_mode_char(app: &mut AppState, c: char) -> io::Result<()> {
+    let key = match c {
+        '\x1b' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+        c => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+    };
+    copy_mode_key(app, key).map(|_| ())
 }
 
 pub fn send_key_to_active(app: &mut AppState, k: &str) -> io::Result<()> {
@@ -1569,165 +544,290 @@ pub fn send_key_to_active(app: &mut AppState, k: &str) -> io::Result<()> {
     // --- Copy-search mode: handle esc/enter/backspace ---
     if matches!(app.mode, Mode::CopySearch { .. }) {
         match k {
-            "esc" => { app.mode = Mode::CopyMode; }
+            "esc" => { app.copy_search_due = None; app.mode = Mode::CopyMode; }
             "enter" => {
+                app.copy_search_due = None;
                 if let Mode::CopySearch { ref input, forward } = app.mode {
                     let query = input.clone();
                     let fwd = forward;
+                    crate::history::record_search(app, &query, Instant::now());
                     app.copy_search_query = query.clone();
                     app.copy_search_forward = fwd;
                     search_copy_mode(app, &query, fwd);
-                    if !app.copy_search_matches.is_empty() {
-                        let (r, c, _) = app.copy_search_matches[0];
-                        app.copy_pos = Some((r, c));
-                    }
+                    crate::copy_mode::jump_to_search_match(app);
                 }
                 app.mode = Mode::CopyMode;
             }
             "backspace" => {
                 if let Mode::CopySearch { ref mut input, .. } = app.mode { input.pop(); }
+                crate::copy_mode::schedule_incremental_search(app, Instant::now());
             }
             _ => {}
         }
         return Ok(());
     }
 
-    // --- Copy mode: full vi-style key table ---
+    // --- Copy mode: the key table mode-keys selects ---
     if matches!(app.mode, Mode::CopyMode) {
-        match k {
-            "esc" | "q" => {
-                exit_copy_mode(app);
-            }
-            "enter" => {
-                // Copy selection and exit copy mode (vi Enter)
-                if app.copy_anchor.is_some() {
-                    yank_selection(app)?;
-                }
-                exit_copy_mode(app);
-            }
-            "space" => {
-                // Begin selection (like v in vi mode)
-                if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                    app.copy_anchor = Some((r, c));
-                    app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                    app.copy_pos = Some((r, c));
-                    app.copy_selection_mode = crate::types::SelectionMode::Char;
-                }
-            }
-            "up" => { move_copy_cursor(app, 0, -1); }
-            "down" => { move_copy_cursor(app, 0, 1); }
-            "pageup" => { scroll_copy_up(app, 10); }
-            "pagedown" => { scroll_copy_down(app, 10); }
-            "left" => { move_copy_cursor(app, -1, 0); }
-            "right" => { move_copy_cursor(app, 1, 0); }
-            "home" => { crate::copy_mode::move_to_line_start(app); }
-            "end" => { crate::copy_mode::move_to_line_end(app); }
-            "C-b" | "c-b" => {
-                if app.mode_keys == "emacs" { move_copy_cursor(app, -1, 0); }
-                else { scroll_copy_up(app, 10); }
-            }
-            "C-f" | "c-f" => {
-                if app.mode_keys == "emacs" { move_copy_cursor(app, 1, 0); }
-                else { scroll_copy_down(app, 10); }
-            }
-            "C-n" | "c-n" => { move_copy_cursor(app, 0, 1); }
-            "C-p" | "c-p" => { move_copy_cursor(app, 0, -1); }
-            "C-a" | "c-a" => { crate::copy_mode::move_to_line_start(app); }
-            "C-e" | "c-e" => { crate::copy_mode::move_to_line_end(app); }
-            "C-v" | "c-v" => { scroll_copy_down(app, 10); }
-            "M-v" | "m-v" => { scroll_copy_up(app, 10); }
-            "M-f" | "m-f" => { crate::copy_mode::move_word_forward(app); }
-            "M-b" | "m-b" => { crate::copy_mode::move_word_backward(app); }
-            "M-w" | "m-w" => { yank_selection(app)?; exit_copy_mode(app); }
-            "C-s" | "c-s" => { app.mode = Mode::CopySearch { input: String::new(), forward: true }; }
-            "C-r" | "c-r" => { app.mode = Mode::CopySearch { input: String::new(), forward: false }; }
-            "C-c" | "c-c" => {
-                exit_copy_mode(app);
-            }
-            "C-g" | "c-g" => {
-                exit_copy_mode(app);
-            }
-            "c-space" | "C-space" => {
-                // Set mark (anchor) at current position
-                if let Some((r, c)) = crate::copy_mode::get_copy_pos(app) {
-                    app.copy_anchor = Some((r, c));
-                    app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                    app.copy_pos = Some((r, c));
-                }
-            }
-            "C-u" | "c-u" => {
-                let half = app.windows.get(app.active_idx)
-                    .and_then(|w| active_pane(&w.root, &w.active_path))
-                    .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                scroll_copy_up(app, half);
-            }
-            "C-d" | "c-d" => {
-                let half = app.windows.get(app.active_idx)
-                    .and_then(|w| active_pane(&w.root, &w.active_path))
-                    .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                scroll_copy_down(app, half);
-            }
-            _ => {}
-        }
+        if let Some(key) = client_key(k) { copy_mode_key(app, key)?; }
         return Ok(());
     }
     
+    if !admit_scrolled_input(app, k == "esc" || k == "q") { return Ok(()); }
     let win = &mut app.windows[app.active_idx];
     if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
-        match k {
-            "enter" => { let _ = write!(p.writer, "\r"); }
-            "tab" => { let _ = write!(p.writer, "\t"); }
-            "btab" | "backtab" => { let _ = write!(p.writer, "\x1b[Z"); }
-            "backspace" => { let _ = p.writer.write_all(&[0x7F]); }
-            "delete" => { let _ = write!(p.writer, "\x1b[3~"); }
-            "esc" => { let _ = write!(p.writer, "\x1b"); }
-            "left" => { let _ = write!(p.writer, "\x1b[D"); }
-            "right" => { let _ = write!(p.writer, "\x1b[C"); }
-            "up" => { let _ = write!(p.writer, "\x1b[A"); }
-            "down" => { let _ = write!(p.writer, "\x1b[B"); }
-            "pageup" => { let _ = write!(p.writer, "\x1b[5~"); }
-            "pagedown" => { let _ = write!(p.writer, "\x1b[6~"); }
-            "home" => { let _ = write!(p.writer, "\x1b[H"); }
-            "end" => { let _ = write!(p.writer, "\x1b[F"); }
-            "insert" => { let _ = write!(p.writer, "\x1b[2~"); }
-            "space" => { let _ = write!(p.writer, " "); }
-            s if s.starts_with("f") && s.len() >= 2 && s.len() <= 3 => {
-                if let Ok(n) = s[1..].parse::<u8>() {
-                    let seq = match n {
-                        1 => "\x1bOP",
-                        2 => "\x1bOQ",
-                        3 => "\x1bOR",
-                        4 => "\x1bOS",
-                        5 => "\x1b[15~",
-                        6 => "\x1b[17~",
-                        7 => "\x1b[18~",
-                        8 => "\x1b[19~",
-                        9 => "\x1b[20~",
-                        10 => "\x1b[21~",
-                        11 => "\x1b[23~",
-                        12 => "\x1b[24~",
-                        _ => "",
-                    };
-                    if !seq.is_empty() { let _ = write!(p.writer, "{}", seq); }
-                }
-            }
-            s if s.starts_with("C-") && s.len() == 3 => {
-                let c = s.chars().nth(2).unwrap_or('c');
-                let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
-                let _ = p.writer.write_all(&[ctrl_char]);
-            }
-            s if (s.starts_with("M-") || s.starts_with("m-")) && s.len() == 3 => {
-                let c = s.chars().nth(2).unwrap_or('a');
-                let _ = write!(p.writer, "\x1b{}", c);
-            }
-            s if (s.starts_with("C-M-") || s.starts_with("c-m-")) && s.len() == 5 => {
-                let c = s.chars().nth(4).unwrap_or('c');
-                let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
-                let _ = p.writer.write_all(&[0x1b, ctrl_char]);
-            }
-            _ => {}
-        }
+        write_key(&mut p.writer, k);
         let _ = p.writer.flush();
     }
     Ok(())
 }
+
+/// Write the bytes a terminal sends for key `k` (as the client names it:
+/// "enter", "C-c", "f5", ...).
+pub(crate) fn write_key(w: &mut dyn Write, k: &str) {
+    match k {
+        "enter" => { let _ = write!(w, "\r"); }
+        "tab" => { let _ = write!(w, "\t"); }
+        "btab" | "backtab" => { let _ = write!(w, "\x1b[Z"); }
+        "backspace" => { let _ = w.write_all(&[0x7F]); }
+        "delete" => { let _ = write!(w, "\x1b[3~"); }
+        "esc" => { let _ = write!(w, "\x1b"); }
+        "left" => { let _ = write!(w, "\x1b[D"); }
+        "right" => { let _ = write!(w, "\x1b[C"); }
+        "s-left" => { let _ = write!(w, "\x1b[1;2D"); }
+        "s-right" => { let _ = write!(w, "\x1b[1;2C"); }
+        "up" => { let _ = write!(w, "\x1b[A"); }
+        "down" => { let _ = write!(w, "\x1b[B"); }
+        "pageup" => { let _ = write!(w, "\x1b[5~"); }
+        "pagedown" => { let _ = write!(w, "\x1b[6~"); }
+        "home" => { let _ = write!(w, "\x1b[H"); }
+        "end" => { let _ = write!(w, "\x1b[F"); }
+        "insert" => { let _ = write!(w, "\x1b[2~"); }
+        "space" => { let _ = write!(w, " "); }
+        s if s.starts_with("f") && s.len() >= 2 && s.len() <= 3 => {
+            if let Ok(n) = s[1..].parse::<u8>() {
+                let seq = match n {
+                    1 => "\x1bOP",
+                    2 => "\x1bOQ",
+                    3 => "\x1bOR",
+                    4 => "\x1bOS",
+                    5 => "\x1b[15~",
+                    6 => "\x1b[17~",
+                    7 => "\x1b[18~",
+                    8 => "\x1b[19~",
+                    9 => "\x1b[20~",
+                    10 => "\x1b[21~",
+                    11 => "\x1b[23~",
+                    12 => "\x1b[24~",
+                    _ => "",
+                };
+                if !seq.is_empty() { let _ = write!(w, "{}", seq); }
+            }
+        }
+        s if s.starts_with("C-") && s.len() == 3 => {
+            let c = s.chars().nth(2).unwrap_or('c');
+            let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
+            let _ = w.write_all(&[ctrl_char]);
+        }
+        s if (s.starts_with("M-") || s.starts_with("m-")) && s.len() == 3 => {
+            let c = s.chars().nth(2).unwrap_or('a');
+            let _ = write!(w, "\x1b{}", c);
+        }
+        s if (s.starts_with("C-M-") || s.starts_with("c-m-")) && s.len() == 5 => {
+            let c = s.chars().nth(4).unwrap_or('c');
+            let ctrl_char = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a' - 1);
+            let _ = w.write_all(&[0x1b, ctrl_char]);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use portable_pty::native_pty_system;
+    use crate::pane::create_window;
+    use crate::copy_mode::enter_copy_mode;
+
+    /// Accepts at most 3 bytes per write() and sleeps, recording every
+    /// chunk in a log shared by all fake panes.
+    struct SlowWriter { id: usize, log: Arc<Mutex<Vec<(usize, Vec<u8>)>>> }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            let n = buf.len().min(3);
+            self.log.lock().unwrap().push((self.id, buf[..n].to_vec()));
+            Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn send_keys_encoded_as_one_sequence() {
+        assert_eq!(encode_send_keys("deploy --force prod Enter"), "deploy --force prod\r");
+        assert_eq!(encode_send_keys("C-c"), "\x03");
+        assert_eq!(encode_send_keys("ls Space -la Up M-f"), "ls -la\x1b[A\x1bf");
+    }
+
+    #[test]
+    fn send_keys_flags_repeat_hex_and_reset() {
+        let sk = parse_send_keys(&["-N", "3", "-R", "Down"]).unwrap();
+        assert_eq!((sk.repeat, sk.reset, sk.keys.clone()), (3, true, vec!["Down".to_string()]));
+        let sk = parse_send_keys(&["-lR", "--", "-x", "-N"]).unwrap();
+        assert!(sk.literal && sk.reset);
+        assert_eq!(sk.keys, vec!["-x", "-N"]);
+        // Flags stop at the first key
+        assert_eq!(parse_send_keys(&["ls", "-l"]).unwrap().keys, vec!["ls", "-l"]);
+        assert!(parse_send_keys(&["-N", "0", "x"]).is_err());
+        assert!(parse_send_keys(&["-N"]).is_err());
+
+        let sk = parse_send_keys(&["-H", "0x03", "1b", "0D"]).unwrap();
+        assert_eq!(hex_keys(&sk.keys), Ok(vec![0x03, 0x1b, 0x0d]));
+        assert!(hex_keys(&["0x1ff".to_string()]).is_err());
+        assert!(hex_keys(&["zz".to_string()]).is_err());
+
+        // A half-read sequence and leftover modes are cleared; the text stays
+        let mut p = vt100::Parser::new(4, 20, 0);
+        p.process(b"wedged\x1b[?25l\x1b[?1000h\x1b[1;31m\x1b[3");
+        reset_terminal(&mut p);
+        p.process(b"ok");
+        assert!(!p.screen().hide_cursor());
+        assert_eq!(p.screen().mouse_protocol_mode(), vt100::MouseProtocolMode::None);
+        assert!(p.screen().contents().starts_with("wedgedok"));
+        let cell = p.screen().cell(0, 7).unwrap();
+        assert_eq!((cell.contents(), cell.bold()), ("k", false));
+        // A program that died in the alternate screen leaves it
+        p.process(b"\x1b[?1049hfull screen");
+        reset_terminal(&mut p);
+        assert!(!p.screen().alternate_screen());
+        assert!(p.screen().contents().starts_with("wedgedok"));
+    }
+
+    #[test]
+    fn each_pane_receives_full_sequence_contiguously() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut a = SlowWriter { id: 0, log: log.clone() };
+        let mut b = SlowWriter { id: 1, log: log.clone() };
+        let seq = encode_send_keys("kubectl rollout restart deploy/web --namespace prod Enter");
+        write_input_batch(vec![&mut a, &mut b], seq.as_bytes());
+
+        let log = log.lock().unwrap();
+        // All of pane 0's chunks come before any of pane 1's: no interleaving
+        let first_b = log.iter().position(|(id, _)| *id == 1).unwrap();
+        assert!(log[..first_b].iter().all(|(id, _)| *id == 0));
+        assert!(log[first_b..].iter().all(|(id, _)| *id == 1));
+        for id in 0..2 {
+            let got: Vec<u8> = log.iter().filter(|(i, _)| *i == id).flat_map(|(_, c)| c.clone()).collect();
+            assert_eq!(got, seq.as_bytes());
+        }
+    }
+
+    #[test]
+    fn copy_mode_keys_go_through_the_mode_keys_table() {
+        let pty = native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        create_window(&*pty, &mut app, None).unwrap();
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            let mut screen = vt100::Parser::new(4, 20, 0);
+            screen.process(b"hello big world");
+            p.term = Arc::new(Mutex::new(screen));
+            p.last_rows = 4;
+            p.last_cols = 20;
+        }
+        crate::config::install_default_bindings(&mut app);
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((0, 0));
+
+        // emacs (the default) has no w; M-f from a client is next-word-end
+        send_text_to_active(&mut app, "w").unwrap();
+        assert_eq!(app.copy_pos, Some((0, 0)));
+        send_key_to_active(&mut app, "m-f").unwrap();
+        assert_eq!(app.copy_pos, Some((0, 4)));
+
+        // vi: a count, then w; an edited binding replaces the default
+        app.mode_keys = "vi".to_string();
+        send_text_to_active(&mut app, "0").unwrap();
+        send_text_to_active(&mut app, "2w").unwrap();
+        assert_eq!(app.copy_pos, Some((0, 10)));
+        crate::config::parse_config_content(&mut app, "bind -T copy-mode-vi v send -X select-line");
+        send_text_to_active(&mut app, "v").unwrap();
+        assert!(matches!(app.copy_selection_mode, crate::types::SelectionMode::Line));
+        send_text_to_active(&mut app, "y").unwrap();
+        assert!(matches!(app.mode, Mode::Passthrough));
+        assert_eq!(app.paste_buffers.top().map(|t| t.trim_end()), Some("hello big world"));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn switched_tables_show_and_time_out_on_the_server() {
+        let pty = native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        create_window(&*pty, &mut app, None).unwrap();
+        switch_key_table(&mut app, "nav");
+        assert_eq!(crate::format::expand_format("#{client_key_table}", &app), "nav");
+        assert!(!prefix_timed_out(&app, app.key_table_at));
+        app.prefix_timeout_ms = 1;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(prefix_timed_out(&app, app.key_table_at));
+        switch_key_table(&mut app, "prefix");
+        assert!(matches!(app.mode, Mode::Prefix { .. }) && app.current_key_table.is_none());
+        switch_key_table(&mut app, "root");
+        assert!(matches!(app.mode, Mode::Passthrough));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn registers_are_chosen_with_a_quote_and_cancelled_with_escape() {
+        let pty = native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        create_window(&*pty, &mut app, None).unwrap();
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            let mut screen = vt100::Parser::new(4, 20, 0);
+            screen.process(b"hello big world");
+            p.term = Arc::new(Mutex::new(screen));
+            p.last_rows = 4;
+            p.last_cols = 20;
+        }
+        crate::config::install_default_bindings(&mut app);
+        app.mode_keys = "vi".to_string();
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+
+        // " waits for a letter; Escape drops the register and stays in copy mode
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((0, 0));
+        send_text_to_active(&mut app, "\"b").unwrap();
+        assert_eq!(app.copy_register, Some('b'));
+        send_text_to_active(&mut app, "\"").unwrap();
+        assert!(app.copy_register_pending);
+        copy_mode_key(&mut app, esc).unwrap();
+        assert!(!app.copy_register_pending && app.copy_register.is_none());
+        assert!(matches!(app.mode, Mode::CopyMode));
+
+        // "ay yanks into buffer a only
+        send_text_to_active(&mut app, "\"aVy").unwrap();
+        assert!(matches!(app.mode, Mode::Passthrough));
+        assert_eq!(app.paste_buffers.len(), 1);
+        assert_eq!(app.paste_buffers.get(Some("a")).map(|b| b.text.trim_end()), Some("hello big world"));
+
+        // A plain y goes onto the automatic stack, leaving a as it was
+        enter_copy_mode(&mut app);
+        app.copy_pos = Some((0, 6));
+        send_text_to_active(&mut app, "vey").unwrap();
+        assert_eq!(app.paste_buffers.top().map(String::as_str), Some("big"));
+        assert!(app.paste_buffers.at(0).unwrap().automatic);
+        assert_eq!(app.paste_buffers.get(Some("a")).map(|b| b.text.trim_end()), Some("hello big world"));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+}
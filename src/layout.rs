@@ -3,9 +3,11 @@ use std::io;
 use serde::{Serialize, Deserialize};
 use unicode_width::UnicodeWidthStr;
 
-use crate::types::{AppState, Node, LayoutKind, Mode};
+use crate::types::{AppState, Node, LayoutKind, Mode, Window};
+use crate::bandwidth::FrameKind;
 use crate::tree::get_split_mut;
 use crate::util::infer_title_from_prompt;
+use crate::copy_mode::{HScrollView, ViewCell};
 
 pub fn cycle_top_layout(app: &mut AppState) {
     let win = &mut app.windows[app.active_idx];
@@ -24,16 +26,23 @@ pub fn cycle_top_layout(app: &mut AppState) {
 #[derive(Serialize, Deserialize)]
 pub struct CellJson { pub text: String, pub fg: String, pub bg: String, pub bold: bool, pub italic: bool, pub underline: bool, pub inverse: bool, pub dim: bool }
 
-#[derive(Serialize, Deserialize)]
+fn default_color() -> String { "default".to_string() }
+
+/// A run of cells sharing one style.  Plain (low-bandwidth) frames send
+/// text and width only.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CellRunJson {
     pub text: String,
+    #[serde(default = "default_color")]
     pub fg: String,
+    #[serde(default = "default_color")]
     pub bg: String,
+    #[serde(default)]
     pub flags: u8,
     pub width: u16,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RowRunsJson {
     pub runs: Vec<CellRunJson>,
 }
@@ -50,11 +59,29 @@ pub enum LayoutJson {
         cols: u16,
         cursor_row: u16,
         cursor_col: u16,
+        /// The pane's app has hidden its cursor (DECTCEM, `ESC[?25l`)
+        #[serde(default)]
+        cursor_hidden: bool,
         #[serde(default)]
         alternate_screen: bool,
         active: bool,
         copy_mode: bool,
         scroll_offset: usize,
+        /// Lines the view is scrolled back outside copy mode (0 = live)
+        #[serde(default)]
+        view_offset: usize,
+        /// Column offset of copy mode's unwrapped view; `content` and the
+        /// copy positions are already in view coordinates
+        #[serde(default)]
+        hscroll: Option<usize>,
+        /// Copy mode's register: `"` while one is being chosen, then its
+        /// letter until the next yank
+        #[serde(default)]
+        copy_register: Option<char>,
+        /// `pane-border-format` expanded for this pane, when
+        /// `pane-border-status` draws it
+        #[serde(default)]
+        border_text: Option<String>,
         sel_start_row: Option<u16>,
         sel_start_col: Option<u16>,
         sel_end_row: Option<u16>,
@@ -65,10 +92,41 @@ pub enum LayoutJson {
         copy_cursor_row: Option<u16>,
         #[serde(default)]
         copy_cursor_col: Option<u16>,
+        /// Copy-mode search matches on screen: (row, first column, last
+        /// column) in view coordinates
+        #[serde(default)]
+        search_matches: Vec<(u16, u16, u16)>,
+        /// Which of `search_matches` is the current match
+        #[serde(default)]
+        search_current: Option<usize>,
+        /// The current match's number and the match count, over the whole
+        /// history
+        #[serde(default)]
+        search_position: Option<(usize, usize)>,
+        /// highlight-pattern matches on screen: (row, first column, last
+        /// column, index into `highlight_styles`)
+        #[serde(default)]
+        highlights: Vec<crate::highlight::Match>,
+        #[serde(default)]
+        highlight_styles: Vec<String>,
         #[serde(default)]
         content: Vec<Vec<CellJson>>,
         #[serde(default)]
         rows_v2: Vec<RowRunsJson>,
+        /// rows_v2 packed, one base64 string per row (see crate::cells)
+        #[serde(default)]
+        rows_bin: Vec<String>,
+        /// Left out of a plain frame because it is not the active pane; the
+        /// client keeps showing the rows it last received for it
+        #[serde(default)]
+        deferred: bool,
+        /// The pane's program exited and remain-on-exit kept the pane; the
+        /// client dims its last screen under a banner
+        #[serde(default)]
+        dead: bool,
+        /// A dead pane's exit status, when known
+        #[serde(default)]
+        dead_status: Option<u32>,
     },
 }
 
@@ -76,14 +134,14 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
     let in_copy_mode = matches!(app.mode, Mode::CopyMode);
     let scroll_offset = app.copy_scroll_offset;
     
-    fn build(node: &mut Node, cur_path: &mut Vec<usize>, active_path: &[usize], include_full_content: bool) -> LayoutJson {
+    fn build(node: &mut Node, cur_path: &mut Vec<usize>, active_path: &[usize], include_full_content: bool, highlight: &str) -> LayoutJson {
         match node {
             Node::Split { kind, sizes, children } => {
                 let k = match *kind { LayoutKind::Horizontal => "Horizontal".to_string(), LayoutKind::Vertical => "Vertical".to_string() };
                 let mut ch: Vec<LayoutJson> = Vec::new();
                 for (i, c) in children.iter_mut().enumerate() {
                     cur_path.push(i);
-                    ch.push(build(c, cur_path, active_path, include_full_content));
+                    ch.push(build(c, cur_path, active_path, include_full_content, highlight));
                     cur_path.pop();
                 }
                 LayoutJson::Split { kind: k, sizes: sizes.clone(), children: ch }
@@ -98,14 +156,17 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
                 let Ok(parser) = p.term.lock() else {
                     return LayoutJson::Leaf {
                         id: p.id, rows: p.last_rows, cols: p.last_cols,
-                        cursor_row: 0, cursor_col: 0, alternate_screen: false,
+                        cursor_row: 0, cursor_col: 0, cursor_hidden: false, alternate_screen: false,
                         active: *cur_path == active_path, copy_mode: false,
-                        scroll_offset: 0,
+                        scroll_offset: 0, view_offset: 0, hscroll: None, copy_register: None, border_text: None,
                         sel_start_row: None, sel_start_col: None,
                         sel_end_row: None, sel_end_col: None,
                         sel_mode: None,
                         copy_cursor_row: None, copy_cursor_col: None,
-                        content: vec![], rows_v2: vec![],
+                        search_matches: vec![], search_current: None, search_position: None,
+                        highlights: vec![], highlight_styles: vec![],
+                        content: vec![], rows_v2: vec![], rows_bin: vec![], deferred: false,
+                        dead: p.dead, dead_status: p.exit_status.filter(|_| p.dead),
                     };
                 };
                 let screen = parser.screen();
@@ -134,6 +195,8 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
                     p.last_infer_title = now;
                 }
                 let need_full_content = include_full_content && *cur_path == active_path;
+                let (highlights, highlight_styles) = crate::highlight::frame_matches(
+                    screen, p.last_rows, p.last_cols, p.highlight_pattern.as_deref().unwrap_or(highlight));
                 let mut lines: Vec<Vec<CellJson>> = if need_full_content {
                     Vec::with_capacity(p.last_rows as usize)
                 } else {
@@ -252,10 +315,15 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
                     cols: p.last_cols,
                     cursor_row: cr,
                     cursor_col: cc,
+                    cursor_hidden: screen.hide_cursor() || p.dead,
                     alternate_screen,
                     active: false,
                     copy_mode: false,
                     scroll_offset: 0,
+                    view_offset: if p.view_scrolled { screen.scrollback() } else { 0 },
+                    hscroll: None,
+                    copy_register: None,
+                    border_text: None,
                     sel_start_row: None,
                     sel_start_col: None,
                     sel_end_row: None,
@@ -263,15 +331,25 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
                     sel_mode: None,
                     copy_cursor_row: None,
                     copy_cursor_col: None,
+                    search_matches: vec![],
+                    search_current: None,
+                    search_position: None,
+                    highlights,
+                    highlight_styles,
                     content: lines,
                     rows_v2,
+                    rows_bin: vec![],
+                    deferred: false,
+                    dead: p.dead,
+                    dead_status: p.exit_status.filter(|_| p.dead),
                 }
             }
         }
     }
     let win = &mut app.windows[app.active_idx];
+    let highlight = win.highlight_pattern.clone().unwrap_or_else(|| app.highlight_pattern.clone());
     let mut path = Vec::new();
-    let mut root = build(&mut win.root, &mut path, &win.active_path, in_copy_mode);
+    let mut root = build(&mut win.root, &mut path, &win.active_path, in_copy_mode, &highlight);
     // Mark the active pane and set copy mode info
     fn mark_active(
         node: &mut LayoutJson,
@@ -351,60 +429,219 @@ pub fn dump_layout_json(app: &mut AppState) -> io::Result<String> {
     Ok(s)
 }
 
-/// Direct JSON serialisation of the layout tree – writes JSON straight into
-/// a pre-allocated `String`, avoiding the intermediate `LayoutJson` / `CellRunJson`
-/// allocations **and** the `serde_json::to_string` traversal.  Produces the
-/// identical JSON format that the client deserialises into `LayoutJson`.
-pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
-    let in_copy = matches!(app.mode, Mode::CopyMode);
-    let scroll_off = app.copy_scroll_offset;
-    let anchor = app.copy_anchor;
-    let anchor_scroll = app.copy_anchor_scroll_offset;
-    let cpos = app.copy_pos;
-    let sel_mode = app.copy_selection_mode;
+// ── frame row serialisation ──────────────────────────────────────────
 
-    // ── tiny helpers (no captures needed, so plain `fn` items) ───────
+const FLAG_DIM: u8       = 1;
+const FLAG_BOLD: u8      = 2;
+const FLAG_ITALIC: u8    = 4;
+const FLAG_UNDERLINE: u8 = 8;
+const FLAG_INVERSE: u8   = 16;
+
+/// Append the JSON-escaped form of `s` into `out`.
+fn json_esc(s: &str, out: &mut String) {
+    // Fast path – most cell text needs no escaping.
+    if !s.bytes().any(|b| b == b'"' || b == b'\\' || b < 0x20) {
+        out.push_str(s);
+        return;
+    }
+    for ch in s.chars() {
+        match ch {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = std::fmt::Write::write_fmt(out, format_args!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+}
 
-    /// Append the JSON-escaped form of `s` into `out`.
-    fn json_esc(s: &str, out: &mut String) {
-        // Fast path – most cell text needs no escaping.
-        if !s.bytes().any(|b| b == b'"' || b == b'\\' || b < 0x20) {
-            out.push_str(s);
-            return;
+/// Append a `vt100::Color` as its JSON string value (**no** surrounding quotes).
+fn push_color(c: vt100::Color, out: &mut String) {
+    match c {
+        vt100::Color::Default => out.push_str("default"),
+        vt100::Color::Idx(i) => {
+            let _ = std::fmt::Write::write_fmt(out, format_args!("idx:{}", i));
+        }
+        vt100::Color::Rgb(r, g, b) => {
+            let _ = std::fmt::Write::write_fmt(out, format_args!("rgb:{},{},{}", r, g, b));
         }
-        for ch in s.chars() {
-            match ch {
-                '"'  => out.push_str("\\\""),
-                '\\' => out.push_str("\\\\"),
-                c if (c as u32) < 0x20 => {
-                    let _ = std::fmt::Write::write_fmt(out, format_args!("\\u{:04x}", c as u32));
+    }
+}
+
+/// Close the currently-open run: closing `"` for text, then fg/bg/flags/width, then `}`.
+fn close_run(fg: vt100::Color, bg: vt100::Color, fl: u8, w: u16, out: &mut String) {
+    out.push_str("\",\"fg\":\"");
+    push_color(fg, out);
+    out.push_str("\",\"bg\":\"");
+    push_color(bg, out);
+    let _ = std::fmt::Write::write_fmt(out, format_args!("\",\"flags\":{},\"width\":{}}}", fl, w));
+}
+
+/// A pane's rows as last written into a frame, with what they were built
+/// from: the pane's data_version, size and scrollback offset, and the
+/// frame kind.  Panes that printed nothing since are not walked again.
+#[derive(Default)]
+pub struct RowsCache {
+    key: Option<(u64, u16, u16, usize, FrameKind)>,
+    text: String,
+}
+
+pub(crate) struct Run { text: String, fg: vt100::Color, bg: vt100::Color, flags: u8, width: u16 }
+pub(crate) struct RowSnap { runs: Vec<Run> }
+
+/// Snapshot the visible rows as style runs (cells merged while fg, bg and
+/// attributes stay the same).
+pub(crate) fn snapshot_runs(screen: &vt100::Screen, rows: u16, cols: u16) -> Vec<RowSnap> {
+    let mut snap_rows: Vec<RowSnap> = Vec::with_capacity(rows as usize);
+    for r in 0..rows {
+        let mut runs: Vec<Run> = Vec::new();
+        let mut c = 0u16;
+        let mut prev_fg: Option<vt100::Color> = None;
+        let mut prev_bg: Option<vt100::Color> = None;
+        let mut prev_fl: u8 = 0;
+
+        while c < cols {
+            if let Some(cell) = screen.cell(r, c) {
+                let t = cell.contents();
+                let t = if t.is_empty() { " " } else { t };
+                let cfg = cell.fgcolor();
+                let cbg = cell.bgcolor();
+                let mut w = UnicodeWidthStr::width(t) as u16;
+                if w == 0 { w = 1; }
+                let mut fl = 0u8;
+                if cell.dim()   { fl |= FLAG_DIM; }
+                if cell.bold()  { fl |= FLAG_BOLD; }
+                if cell.italic(){ fl |= FLAG_ITALIC; }
+                if cell.underline() { fl |= FLAG_UNDERLINE; }
+                if cell.inverse()   { fl |= FLAG_INVERSE; }
+
+                if prev_fg == Some(cfg) && prev_bg == Some(cbg) && prev_fl == fl {
+                    if let Some(last) = runs.last_mut() {
+                        last.text.push_str(t);
+                        last.width += w;
+                    }
+                } else {
+                    runs.push(Run { text: t.to_string(), fg: cfg, bg: cbg, flags: fl, width: w });
                 }
-                c => out.push(c),
+                prev_fg = Some(cfg);
+                prev_bg = Some(cbg);
+                prev_fl = fl;
+                c += w.max(1);
+            } else {
+                let cfg = vt100::Color::Default;
+                let cbg = vt100::Color::Default;
+                let fl  = 0u8;
+                if prev_fg == Some(cfg) && prev_bg == Some(cbg) && prev_fl == fl {
+                    if let Some(last) = runs.last_mut() {
+                        last.text.push(' ');
+                        last.width += 1;
+                    }
+                } else {
+                    runs.push(Run { text: " ".to_string(), fg: cfg, bg: cbg, flags: fl, width: 1 });
+                }
+                prev_fg = Some(cfg);
+                prev_bg = Some(cbg);
+                prev_fl = fl;
+                c += 1;
             }
         }
+        snap_rows.push(RowSnap { runs });
     }
+    snap_rows
+}
 
-    /// Append a `vt100::Color` as its JSON string value (**no** surrounding quotes).
-    fn push_color(c: vt100::Color, out: &mut String) {
-        match c {
-            vt100::Color::Default => out.push_str("default"),
-            vt100::Color::Idx(i) => {
-                let _ = std::fmt::Write::write_fmt(out, format_args!("idx:{}", i));
-            }
-            vt100::Color::Rgb(r, g, b) => {
-                let _ = std::fmt::Write::write_fmt(out, format_args!("rgb:{},{},{}", r, g, b));
-            }
+/// Snapshot the visible rows as bare text for a plain frame, trailing
+/// blanks dropped.
+pub(crate) fn snapshot_plain(screen: &vt100::Screen, rows: u16, cols: u16) -> Vec<String> {
+    screen.rows(0, cols).take(rows as usize).map(|r| r.trim_end().to_string()).collect()
+}
+
+/// Append `"rows_v2":[...]` built from style runs.
+pub(crate) fn write_runs(rows: &[RowSnap], out: &mut String) {
+    out.push_str("\"rows_v2\":[");
+    for (ri, row) in rows.iter().enumerate() {
+        if ri > 0 { out.push(','); }
+        out.push_str("{\"runs\":[");
+        for (i, run) in row.runs.iter().enumerate() {
+            if i > 0 { out.push(','); }
+            out.push_str("{\"text\":\"");
+            json_esc(&run.text, out);
+            close_run(run.fg, run.bg, run.flags, run.width, out);
+        }
+        out.push_str("]}");
+    }
+    out.push(']');
+}
+
+/// Append `"rows_bin":[...]`: the runs of each row packed (see crate::cells).
+pub(crate) fn write_runs_bin(rows: &[RowSnap], out: &mut String) {
+    out.push_str("\"rows_bin\":[");
+    let mut buf = Vec::new();
+    for (ri, row) in rows.iter().enumerate() {
+        if ri > 0 { out.push(','); }
+        buf.clear();
+        crate::cells::begin_row(row.runs.len(), &mut buf);
+        for run in &row.runs {
+            crate::cells::push_run(&run.text, run.fg, run.bg, run.flags, run.width, &mut buf);
         }
+        out.push('"');
+        out.push_str(&crate::cells::row_text(&buf));
+        out.push('"');
+    }
+    out.push(']');
+}
+
+/// Append `"rows_v2":[...]` for a plain frame: one unstyled run per row.
+pub(crate) fn write_plain(rows: &[String], out: &mut String) {
+    out.push_str("\"rows_v2\":[");
+    for (ri, row) in rows.iter().enumerate() {
+        if ri > 0 { out.push(','); }
+        out.push_str("{\"runs\":[{\"text\":\"");
+        json_esc(row, out);
+        let _ = std::fmt::Write::write_fmt(out, format_args!("\",\"width\":{}}}]}}", UnicodeWidthStr::width(row.as_str())));
     }
+    out.push(']');
+}
 
-    /// Close the currently-open run: closing `"` for text, then fg/bg/flags/width, then `}`.
-    fn close_run(fg: vt100::Color, bg: vt100::Color, fl: u8, w: u16, out: &mut String) {
-        out.push_str("\",\"fg\":\"");
-        push_color(fg, out);
-        out.push_str("\",\"bg\":\"");
-        push_color(bg, out);
-        let _ = std::fmt::Write::write_fmt(out, format_args!("\",\"flags\":{},\"width\":{}}}", fl, w));
+/// Copy-mode search matches on screen, as (row, first column, last column)
+/// in view coordinates, and which of them is the `current` match.  In the
+/// unwrapped view a match is shown only while both its ends are in view.
+fn visible_matches(matches: &[(i64, u16, u16)], current: usize, scroll: usize, rows: u16, hview: Option<&HScrollView>) -> (Vec<(u16, u16, u16)>, Option<usize>) {
+    let mut shown = Vec::new();
+    let mut shown_current = None;
+    for (i, &(line, c0, c1)) in matches.iter().enumerate() {
+        let row = line + scroll as i64;
+        if row < 0 || row >= rows as i64 { continue; }
+        let row = row as u16;
+        let m = match hview {
+            Some(v) => match (v.view_pos((row, c0)), v.view_pos((row, c1))) {
+                (Some((r, a)), Some((r2, b))) if r == r2 => (r, a, b),
+                _ => continue,
+            },
+            None => (row, c0, c1),
+        };
+        if i == current { shown_current = Some(shown.len()); }
+        shown.push(m);
     }
+    (shown, shown_current)
+}
+
+/// Direct JSON serialisation of the layout tree – writes JSON straight into
+/// a pre-allocated `String`, avoiding the intermediate `LayoutJson` / `CellRunJson`
+/// allocations **and** the `serde_json::to_string` traversal.  Produces the
+/// identical JSON format that the client deserialises into `LayoutJson`.
+/// `kind` selects the styled frame or a plain (low-bandwidth) one.
+pub fn dump_layout_json_fast(app: &mut AppState, kind: FrameKind) -> io::Result<String> {
+    let in_copy = matches!(app.mode, Mode::CopyMode);
+    let scroll_off = app.copy_scroll_offset;
+    let anchor = app.copy_anchor;
+    let anchor_scroll = app.copy_anchor_scroll_offset;
+    let cpos = app.copy_pos;
+    let sel_mode = app.copy_selection_mode;
+    let hscroll = if in_copy { app.copy_hscroll } else { None };
+    let register = if app.copy_register_pending { Some('"') } else { app.copy_register };
+    let search = (app.copy_search_matches.as_slice(), app.copy_search_idx);
 
     // ── recursive tree walker ────────────────────────────────────────
 
@@ -418,6 +655,12 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
         anchor_scroll: usize,
         cpos: Option<(u16, u16)>,
         sel_mode: crate::types::SelectionMode,
+        hscroll: Option<usize>,
+        register: Option<char>,
+        borders: &[(usize, String)],
+        search: (&[(i64, u16, u16)], usize),
+        highlight: &str,
+        frame: FrameKind,
         out: &mut String,
     ) {
         match node {
@@ -436,40 +679,50 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                 for (i, c) in children.iter_mut().enumerate() {
                     if i > 0 { out.push(','); }
                     cur_path.push(i);
-                    write_node(c, cur_path, active_path, in_copy, scroll_off, anchor, anchor_scroll, cpos, sel_mode, out);
+                    write_node(c, cur_path, active_path, in_copy, scroll_off, anchor, anchor_scroll, cpos, sel_mode, hscroll, register, borders, search, highlight, frame, out);
                     cur_path.pop();
                 }
                 out.push_str("]}");
             }
 
             Node::Leaf(p) => {
-                const FLAG_DIM: u8      = 1;
-                const FLAG_BOLD: u8     = 2;
-                const FLAG_ITALIC: u8   = 4;
-                const FLAG_UNDERLINE: u8 = 8;
-                const FLAG_INVERSE: u8  = 16;
-
                 let is_active    = cur_path.as_slice() == active_path;
                 let need_content = in_copy && is_active;
+                let plain        = frame.is_plain();
+
+                // A plain frame leaves inactive panes out entirely
+                if frame == FrameKind::Plain && !is_active {
+                    let _ = std::fmt::Write::write_fmt(out, format_args!(
+                        concat!(
+                            "{{\"type\":\"leaf\",\"id\":{},\"rows\":{},\"cols\":{},",
+                            "\"cursor_row\":0,\"cursor_col\":0,\"active\":false,\"copy_mode\":false,",
+                            "\"scroll_offset\":0,\"deferred\":true}}"),
+                        p.id, p.last_rows, p.last_cols,
+                    ));
+                    return;
+                }
 
                 // ── Snapshot cell data under the mutex, then release ──
                 // This minimises the time we block the reader thread (which
                 // also holds p.term's mutex while processing ConPTY output).
                 // Without this, WSL echo gets starved because its output sits
                 // in the ConPTY pipe while we build the JSON string.
-                struct Run { text: String, fg: vt100::Color, bg: vt100::Color, flags: u8, width: u16 }
-                struct RowSnap { runs: Vec<Run> }
                 struct CopyCell { text: String, fg: vt100::Color, bg: vt100::Color, bold: bool, italic: bool, underline: bool, inverse: bool, dim: bool, width: u16 }
                 struct LeafSnap {
-                    cr: u16, cc: u16, alt: bool,
+                    cr: u16, cc: u16, hidden: bool, alt: bool, view: usize,
                     rows_v2: Vec<RowSnap>,
+                    plain: Vec<String>,
                     content: Vec<Vec<CopyCell>>,
+                    hview: Option<HScrollView>,
+                    highlights: (Vec<crate::highlight::Match>, Vec<String>),
+                    rows_key: Option<(u64, u16, u16, usize, FrameKind)>,
+                    reuse_rows: bool,
                 }
 
                 let snap = 'snap: {
                     let parser = match p.term.lock() {
                         Ok(g) => g,
-                        Err(_) => break 'snap LeafSnap { cr: 0, cc: 0, alt: false, rows_v2: vec![], content: vec![] },
+                        Err(_) => break 'snap LeafSnap { cr: 0, cc: 0, hidden: false, alt: false, view: 0, rows_v2: vec![], plain: vec![], content: vec![], hview: None, highlights: Default::default(), rows_key: None, reuse_rows: false },
                     };
                     let screen = parser.screen();
                     let (cr, cc) = screen.cursor_position();
@@ -494,71 +747,42 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                         p.last_infer_title = now;
                     }
 
-                    // Snapshot rows_v2 (run-merged)
-                    let mut snap_rows: Vec<RowSnap> = Vec::with_capacity(p.last_rows as usize);
-                    for r in 0..p.last_rows {
-                        let mut runs: Vec<Run> = Vec::new();
-                        let mut c = 0u16;
-                        let mut prev_fg: Option<vt100::Color> = None;
-                        let mut prev_bg: Option<vt100::Color> = None;
-                        let mut prev_fl: u8 = 0;
-
-                        while c < p.last_cols {
-                            if let Some(cell) = screen.cell(r, c) {
-                                let t = cell.contents();
-                                let t = if t.is_empty() { " " } else { t };
-                                let cfg = cell.fgcolor();
-                                let cbg = cell.bgcolor();
-                                let mut w = UnicodeWidthStr::width(t) as u16;
-                                if w == 0 { w = 1; }
-                                let mut fl = 0u8;
-                                if cell.dim()   { fl |= FLAG_DIM; }
-                                if cell.bold()  { fl |= FLAG_BOLD; }
-                                if cell.italic(){ fl |= FLAG_ITALIC; }
-                                if cell.underline() { fl |= FLAG_UNDERLINE; }
-                                if cell.inverse()   { fl |= FLAG_INVERSE; }
-
-                                if prev_fg == Some(cfg) && prev_bg == Some(cbg) && prev_fl == fl {
-                                    if let Some(last) = runs.last_mut() {
-                                        last.text.push_str(t);
-                                        last.width += w;
-                                    }
-                                } else {
-                                    runs.push(Run { text: t.to_string(), fg: cfg, bg: cbg, flags: fl, width: w });
-                                }
-                                prev_fg = Some(cfg);
-                                prev_bg = Some(cbg);
-                                prev_fl = fl;
-                                c += w.max(1);
-                            } else {
-                                let cfg = vt100::Color::Default;
-                                let cbg = vt100::Color::Default;
-                                let fl  = 0u8;
-                                if prev_fg == Some(cfg) && prev_bg == Some(cbg) && prev_fl == fl {
-                                    if let Some(last) = runs.last_mut() {
-                                        last.text.push(' ');
-                                        last.width += 1;
-                                    }
-                                } else {
-                                    runs.push(Run { text: " ".to_string(), fg: cfg, bg: cbg, flags: fl, width: 1 });
-                                }
-                                prev_fg = Some(cfg);
-                                prev_bg = Some(cbg);
-                                prev_fl = fl;
-                                c += 1;
-                            }
-                        }
-                        snap_rows.push(RowSnap { runs });
-                    }
+                    // Snapshot rows_v2 (run-merged), or bare text for a plain
+                    // frame, unless the rows last written still hold
+                    let rows_kind = if plain { FrameKind::Plain } else { frame };
+                    let rows_key = (p.data_version.load(std::sync::atomic::Ordering::Acquire), p.last_rows, p.last_cols, screen.scrollback(), rows_kind);
+                    let reuse_rows = p.rows_cache.key == Some(rows_key);
+                    let (snap_rows, snap_plain) = if reuse_rows {
+                        (Vec::new(), Vec::new())
+                    } else if plain {
+                        (Vec::new(), snapshot_plain(screen, p.last_rows, p.last_cols))
+                    } else {
+                        (snapshot_runs(screen, p.last_rows, p.last_cols), Vec::new())
+                    };
 
-                    // Snapshot content (copy-mode only)
+                    // Snapshot content (copy-mode only), through the unwrapped
+                    // view when copy mode is scrolled sideways
+                    let hview = hscroll.filter(|_| need_content).map(|off| HScrollView::new(screen, p.last_rows, p.last_cols, off));
                     let mut snap_content: Vec<Vec<CopyCell>> = Vec::new();
                     if need_content {
                         for r in 0..p.last_rows {
                             let mut row_cells: Vec<CopyCell> = Vec::new();
                             let mut c = 0u16;
                             while c < p.last_cols {
-                                if let Some(cell) = screen.cell(r, c) {
+                                let src = match hview.as_ref().map(|v| v.cell(r, c)) {
+                                    None => Some((r, c)),
+                                    Some(ViewCell::Screen(sr, sc)) => Some((sr, sc)),
+                                    Some(ViewCell::Edge(ch)) => {
+                                        row_cells.push(CopyCell {
+                                            text: ch.to_string(), fg: vt100::Color::Default, bg: vt100::Color::Default,
+                                            bold: false, italic: false, underline: false, inverse: true, dim: false, width: 1,
+                                        });
+                                        c += 1;
+                                        continue;
+                                    }
+                                    Some(ViewCell::Blank) => None,
+                                };
+                                if let Some(cell) = src.and_then(|(sr, sc)| screen.cell(sr, sc)) {
                                     let t = cell.contents();
                                     let t = if t.is_empty() { " " } else { t };
                                     let w = UnicodeWidthStr::width(t).max(1) as u16;
@@ -580,7 +804,14 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                         }
                     }
 
-                    LeafSnap { cr, cc, alt, rows_v2: snap_rows, content: snap_content }
+                    // highlight-pattern matches, left out of a plain frame and
+                    // of the unwrapped view, whose columns are not the screen's
+                    let highlights = if plain || hview.is_some() { Default::default() } else {
+                        crate::highlight::frame_matches(screen, p.last_rows, p.last_cols, p.highlight_pattern.as_deref().unwrap_or(highlight))
+                    };
+
+                    let view = if p.view_scrolled { screen.scrollback() } else { 0 };
+                    LeafSnap { cr, cc, hidden: screen.hide_cursor(), alt, view, rows_v2: snap_rows, plain: snap_plain, content: snap_content, hview, highlights, rows_key: Some(rows_key), reuse_rows }
                 };
                 // ── Parser mutex is now RELEASED ──
                 // All JSON string building below happens without holding the lock,
@@ -593,12 +824,30 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                         "{{\"type\":\"leaf\",\"id\":{},",
                         "\"rows\":{},\"cols\":{},",
                         "\"cursor_row\":{},\"cursor_col\":{},",
+                        "\"cursor_hidden\":{},",
                         "\"alternate_screen\":{},",
                         "\"active\":{},\"copy_mode\":{},",
-                        "\"scroll_offset\":{},"),
+                        "\"scroll_offset\":{},\"view_offset\":{},"),
                     p.id, p.last_rows, p.last_cols,
-                    snap.cr, snap.cc, snap.alt, is_active, need_content, so,
+                    snap.cr, snap.cc, snap.hidden || p.dead, snap.alt, is_active, need_content, so, snap.view,
                 ));
+                if let (true, Some(off)) = (need_content, hscroll) {
+                    let _ = std::fmt::Write::write_fmt(out, format_args!("\"hscroll\":{},", off));
+                }
+                if let Some((_, text)) = borders.iter().find(|(id, _)| *id == p.id) {
+                    let text = serde_json::to_string(text).unwrap_or_default();
+                    let _ = std::fmt::Write::write_fmt(out, format_args!("\"border_text\":{},", text));
+                }
+                if let (true, Some(r)) = (need_content, register) {
+                    let r = serde_json::to_string(&r).unwrap_or_default();
+                    let _ = std::fmt::Write::write_fmt(out, format_args!("\"copy_register\":{},", r));
+                }
+                if p.dead {
+                    out.push_str("\"dead\":true,");
+                    if let Some(status) = p.exit_status {
+                        let _ = std::fmt::Write::write_fmt(out, format_args!("\"dead_status\":{},", status));
+                    }
+                }
 
                 // selection bounds + copy cursor position
                 if is_active && in_copy {
@@ -609,6 +858,12 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                         let display_ar = (ar as i32 + scroll_off as i32 - anchor_scroll as i32)
                             .max(0)
                             .min(p.last_rows as i32 - 1) as u16;
+                        // In the unwrapped view both ends are drawn where
+                        // their logical lines are, pulled in from off-view columns
+                        let ((display_ar, ac), (pr, pc)) = match &snap.hview {
+                            Some(v) => (v.clamped_pos((display_ar, ac)), v.clamped_pos((pr, pc))),
+                            None => ((display_ar, ac), (pr, pc)),
+                        };
                         // For char mode: send directional start/end so the
                         // client can render flow selection (first line from
                         // start_col to EOL, middle full, last line to end_col).
@@ -644,7 +899,11 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                     } else {
                         out.push_str("\"sel_start_row\":null,\"sel_start_col\":null,\"sel_end_row\":null,\"sel_end_col\":null,\"sel_mode\":null,");
                     }
-                    if let Some((pr, pc)) = cpos {
+                    let shown = match &snap.hview {
+                        Some(v) => cpos.and_then(|pos| v.view_pos(pos)),
+                        None => cpos,
+                    };
+                    if let Some((pr, pc)) = shown {
                         let _ = std::fmt::Write::write_fmt(out, format_args!(
                             "\"copy_cursor_row\":{},\"copy_cursor_col\":{},",
                             pr, pc,
@@ -652,11 +911,42 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                     } else {
                         out.push_str("\"copy_cursor_row\":null,\"copy_cursor_col\":null,");
                     }
+                    let (matches, current) = visible_matches(search.0, search.1, scroll_off, p.last_rows, snap.hview.as_ref());
+                    out.push_str("\"search_matches\":[");
+                    for (i, (r, c0, c1)) in matches.iter().enumerate() {
+                        if i > 0 { out.push(','); }
+                        let _ = std::fmt::Write::write_fmt(out, format_args!("[{},{},{}]", r, c0, c1));
+                    }
+                    out.push_str("],");
+                    if let Some(i) = current {
+                        let _ = std::fmt::Write::write_fmt(out, format_args!("\"search_current\":{},", i));
+                    }
+                    if !search.0.is_empty() {
+                        let _ = std::fmt::Write::write_fmt(out, format_args!(
+                            "\"search_position\":[{},{}],", search.1.min(search.0.len() - 1) + 1, search.0.len(),
+                        ));
+                    }
                 } else {
                     out.push_str("\"sel_start_row\":null,\"sel_start_col\":null,\"sel_end_row\":null,\"sel_end_col\":null,\"sel_mode\":null,");
                     out.push_str("\"copy_cursor_row\":null,\"copy_cursor_col\":null,");
                 }
 
+                if !snap.highlights.0.is_empty() {
+                    out.push_str("\"highlights\":[");
+                    for (i, (r, c0, c1, k)) in snap.highlights.0.iter().enumerate() {
+                        if i > 0 { out.push(','); }
+                        let _ = std::fmt::Write::write_fmt(out, format_args!("[{},{},{},{}]", r, c0, c1, k));
+                    }
+                    out.push_str("],\"highlight_styles\":[");
+                    for (i, s) in snap.highlights.1.iter().enumerate() {
+                        if i > 0 { out.push(','); }
+                        out.push('"');
+                        json_esc(s, out);
+                        out.push('"');
+                    }
+                    out.push_str("],");
+                }
+
                 // ── content (per-cell, only in copy-mode active pane) ──
                 if need_content && !snap.content.is_empty() {
                     out.push_str("\"content\":[");
@@ -700,39 +990,66 @@ pub fn dump_layout_json_fast(app: &mut AppState) -> io::Result<String> {
                 }
 
                 // ── rows_v2 (from snapshot, no mutex held) ───────────
-                out.push_str("\"rows_v2\":[");
-                for (ri, row) in snap.rows_v2.iter().enumerate() {
-                    if ri > 0 { out.push(','); }
-                    out.push_str("{\"runs\":[");
-                    for (i, run) in row.runs.iter().enumerate() {
-                        if i > 0 { out.push(','); }
-                        out.push_str("{\"text\":\"");
-                        json_esc(&run.text, out);
-                        close_run(run.fg, run.bg, run.flags, run.width, out);
-                    }
-                    out.push_str("]}");
+                if snap.reuse_rows {
+                    out.push_str(&p.rows_cache.text);
+                } else {
+                    let start = out.len();
+                    if plain { write_plain(&snap.plain, out); }
+                    else if frame == FrameKind::Binary { write_runs_bin(&snap.rows_v2, out); }
+                    else { write_runs(&snap.rows_v2, out); }
+                    p.rows_cache.key = snap.rows_key;
+                    p.rows_cache.text.clear();
+                    p.rows_cache.text.push_str(&out[start..]);
                 }
-                out.push_str("]}");
+                out.push('}');
             }
         }
     }
 
+    let borders = border_texts(app);
     let win = &mut app.windows[app.active_idx];
     let active_path = win.active_path.clone();
+    let highlight = win.highlight_pattern.as_deref().unwrap_or(&app.highlight_pattern);
     let mut path = Vec::new();
     let mut out = String::with_capacity(32768);
     write_node(
         &mut win.root, &mut path, &active_path,
-        in_copy, scroll_off, anchor, anchor_scroll, cpos, sel_mode, &mut out,
+        in_copy, scroll_off, anchor, anchor_scroll, cpos, sel_mode, hscroll, register, &borders, search, highlight, kind, &mut out,
     );
     Ok(out)
 }
 
-/// Apply a named layout to the current window.
-/// Collects ALL leaf panes and rebuilds the tree structure from scratch.
-pub fn apply_layout(app: &mut AppState, layout: &str) {
+/// Apply a named layout, or a layout string from `#{window_layout}`, to the
+/// current window.
+/// Collects ALL leaf panes and rebuilds the tree structure from scratch,
+/// keeping the panes' order and the active pane.  A layout string must have
+/// a cell for each pane.
+pub fn apply_layout(app: &mut AppState, layout: &str) -> Result<(), String> {
+    let name = match layout.to_lowercase().as_str() {
+        "even-h" => "even-horizontal".to_string(),
+        "even-v" => "even-vertical".to_string(),
+        "main-h" => "main-horizontal".to_string(),
+        "main-v" => "main-vertical".to_string(),
+        other => other.to_string(),
+    };
+    // Determine main-pane percentage
+    let main_h_pct = if app.main_pane_height > 0 { app.main_pane_height.min(95) } else { 60 };
+    let main_v_pct = if app.main_pane_width > 0 { app.main_pane_width.min(95) } else { 60 };
     let win = &mut app.windows[app.active_idx];
-    
+    let custom = match LAYOUT_NAMES.iter().position(|n| *n == name) {
+        Some(i) => { win.layout_index = i; None }
+        None => {
+            let cell = parse_layout_string(layout)?;
+            let panes = crate::tree::count_panes(&win.root);
+            if cell.panes() != panes {
+                return Err(format!("invalid layout: {} cells for {} panes", cell.panes(), panes));
+            }
+            Some(cell)
+        }
+    };
+    win.tiled = name == "tiled";
+    let active_id = crate::tree::get_active_pane_id(&win.root, &win.active_path);
+
     // Collect all leaf panes from the current tree
     let old_root = std::mem::replace(&mut win.root, Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
     let mut leaves = crate::tree::collect_leaves(old_root);
@@ -742,35 +1059,21 @@ pub fn apply_layout(app: &mut AppState, layout: &str) {
         if let Some(leaf) = leaves.into_iter().next() {
             win.root = leaf;
         }
-        return;
-    }
-
-    // Helper: compute equal sizes summing to 100
-    fn equal_sizes(n: usize) -> Vec<u16> {
-        if n == 0 { return vec![]; }
-        let base = 100 / n as u16;
-        let mut sizes = vec![base; n];
-        let rem = 100 - base * n as u16;
-        if let Some(last) = sizes.last_mut() { *last += rem; }
-        sizes
+        return Ok(());
     }
 
-    // Determine main-pane percentage
-    let main_h_pct = if app.main_pane_height > 0 { app.main_pane_height.min(95) } else { 60 };
-    let main_v_pct = if app.main_pane_width > 0 { app.main_pane_width.min(95) } else { 60 };
-
-    match layout.to_lowercase().as_str() {
-        "even-horizontal" | "even-h" => {
+    match name.as_str() {
+        "even-horizontal" => {
             // Single horizontal split with N equal children
             let sizes = equal_sizes(pane_count);
             win.root = Node::Split { kind: LayoutKind::Horizontal, sizes, children: leaves };
         }
-        "even-vertical" | "even-v" => {
+        "even-vertical" => {
             // Single vertical split with N equal children
             let sizes = equal_sizes(pane_count);
             win.root = Node::Split { kind: LayoutKind::Vertical, sizes, children: leaves };
         }
-        "main-horizontal" | "main-h" => {
+        "main-horizontal" => {
             // Vertical split: top pane (main) + bottom horizontal split of remaining
             let main_pane = leaves.remove(0);
             if leaves.len() == 1 {
@@ -790,7 +1093,7 @@ pub fn apply_layout(app: &mut AppState, layout: &str) {
                 };
             }
         }
-        "main-vertical" | "main-v" => {
+        "main-vertical" => {
             // Horizontal split: left pane (main) + right vertical split of remaining
             let main_pane = leaves.remove(0);
             if leaves.len() == 1 {
@@ -810,44 +1113,58 @@ pub fn apply_layout(app: &mut AppState, layout: &str) {
                 };
             }
         }
-        "tiled" => {
-            // Balanced binary tree of splits
-            fn build_tiled(mut panes: Vec<Node>) -> Node {
-                if panes.len() == 1 { return panes.remove(0); }
-                if panes.len() == 2 {
-                    return Node::Split {
-                        kind: LayoutKind::Horizontal,
-                        sizes: vec![50, 50],
-                        children: panes,
-                    };
-                }
-                let mid = panes.len() / 2;
-                let right_panes = panes.split_off(mid);
-                let left = build_tiled(panes);
-                let right = build_tiled(right_panes);
-                // Alternate between vertical and horizontal at each level
-                Node::Split {
-                    kind: LayoutKind::Vertical,
-                    sizes: vec![50, 50],
-                    children: vec![left, right],
-                }
-            }
-            win.root = build_tiled(leaves);
-        }
-        _ => {
-            // Unknown layout name — try to parse as tmux layout string
-            let new_root = parse_tmux_layout_string(layout, &mut leaves);
-            if let Some(root) = new_root {
-                win.root = root;
-            } else {
-                // Parsing failed; put panes back as even-horizontal fallback
-                let sizes = equal_sizes(pane_count);
-                win.root = Node::Split { kind: LayoutKind::Horizontal, sizes, children: leaves };
-            }
-        }
+        "tiled" => { win.root = tiled(leaves); }
+        // A layout string, checked above to have a cell for every pane
+        _ => if let Some(cell) = custom { win.root = cell.build(&mut leaves.into_iter()).expect("a cell per pane"); },
     }
-    // Reset active_path to first leaf
-    win.active_path = crate::tree::first_leaf_path(&win.root);
+    win.active_path = active_id.and_then(|id| crate::tree::pane_path(&win.root, id))
+        .unwrap_or_else(|| crate::tree::first_leaf_path(&win.root));
+    Ok(())
+}
+
+/// Equal sizes summing to 100.
+fn equal_sizes(n: usize) -> Vec<u16> {
+    if n == 0 { return vec![]; }
+    let base = 100 / n as u16;
+    let mut sizes = vec![base; n];
+    let rem = 100 - base * n as u16;
+    if let Some(last) = sizes.last_mut() { *last += rem; }
+    sizes
+}
+
+/// `tiled`: the panes in a grid of rows, filled left to right and top to
+/// bottom.  As in tmux the grid gains a row, then a column, until the panes
+/// fit; a last row with fewer panes shares the full width between them.
+fn tiled(mut panes: Vec<Node>) -> Node {
+    let n = panes.len();
+    let (mut rows, mut cols) = (1, 1);
+    while rows * cols < n {
+        rows += 1;
+        if rows * cols < n { cols += 1; }
+    }
+    let row = |panes: Vec<Node>| match panes.len() {
+        1 => panes.into_iter().next().unwrap(),
+        k => Node::Split { kind: LayoutKind::Horizontal, sizes: equal_sizes(k), children: panes },
+    };
+    let mut grid = Vec::new();
+    while !panes.is_empty() {
+        let rest = panes.split_off(cols.min(panes.len()));
+        grid.push(row(std::mem::replace(&mut panes, rest)));
+    }
+    match grid.len() {
+        1 => grid.into_iter().next().unwrap(),
+        k => Node::Split { kind: LayoutKind::Vertical, sizes: equal_sizes(k), children: grid },
+    }
+}
+
+/// Tile the panes of a window laid out `tiled` again after it lost one.
+pub fn retile(win: &mut Window) {
+    if !win.tiled || crate::tree::count_panes(&win.root) < 2 { return; }
+    let active_id = crate::tree::get_active_pane_id(&win.root, &win.active_path);
+    let old_root = std::mem::replace(&mut win.root, Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
+    win.root = tiled(crate::tree::collect_leaves(old_root));
+    win.active_path = active_id.and_then(|id| crate::tree::pane_path(&win.root, id))
+        .unwrap_or_else(|| crate::tree::first_leaf_path(&win.root));
 }
 
 const LAYOUT_NAMES: [&str; 5] = ["even-horizontal", "even-vertical", "main-horizontal", "main-vertical", "tiled"];
@@ -857,8 +1174,7 @@ pub fn cycle_layout(app: &mut AppState) {
     let win = &mut app.windows[app.active_idx];
     if matches!(win.root, Node::Leaf(_)) { return; }
     let next_idx = (win.layout_index + 1) % LAYOUT_NAMES.len();
-    win.layout_index = next_idx;
-    apply_layout(app, LAYOUT_NAMES[next_idx]);
+    let _ = apply_layout(app, LAYOUT_NAMES[next_idx]);
 }
 
 /// Cycle through available layouts (reverse)
@@ -866,140 +1182,512 @@ pub fn cycle_layout_reverse(app: &mut AppState) {
     let win = &mut app.windows[app.active_idx];
     if matches!(win.root, Node::Leaf(_)) { return; }
     let prev_idx = (win.layout_index + LAYOUT_NAMES.len() - 1) % LAYOUT_NAMES.len();
-    win.layout_index = prev_idx;
-    apply_layout(app, LAYOUT_NAMES[prev_idx]);
+    let _ = apply_layout(app, LAYOUT_NAMES[prev_idx]);
 }
 
-/// Parse a tmux layout string into a Node tree.
-///
-/// Format: `checksum,WxH,X,Y{child1,child2,...}` or `checksum,WxH,X,Y[child1,child2,...]`
+/// A cell of a tmux layout string: a pane, or a split of cells.
+enum LayoutCell {
+    Pane { w: u16, h: u16 },
+    Split { kind: LayoutKind, w: u16, h: u16, children: Vec<LayoutCell> },
+}
+
+impl LayoutCell {
+    fn size(&self) -> (u16, u16) {
+        match self { LayoutCell::Pane { w, h } | LayoutCell::Split { w, h, .. } => (*w, *h) }
+    }
+
+    fn panes(&self) -> usize {
+        match self {
+            LayoutCell::Pane { .. } => 1,
+            LayoutCell::Split { children, .. } => children.iter().map(LayoutCell::panes).sum(),
+        }
+    }
+
+    /// The split tree with `panes` placed on the cells in order.  Sizes are
+    /// the cells' widths or heights, so the same window size gives back the
+    /// same geometry and another size scales it.
+    fn build(self, panes: &mut std::vec::IntoIter<Node>) -> Option<Node> {
+        match self {
+            LayoutCell::Pane { .. } => panes.next(),
+            LayoutCell::Split { kind, children, .. } => {
+                let sizes = children.iter()
+                    .map(|c| if kind == LayoutKind::Horizontal { c.size().0 } else { c.size().1 }.max(1))
+                    .collect();
+                let children = children.into_iter().map(|c| c.build(panes)).collect::<Option<Vec<_>>>()?;
+                Some(Node::Split { kind, sizes, children })
+            }
+        }
+    }
+}
+
+/// Parse a tmux layout string, `checksum,WxH,X,Y...`, as printed by
+/// `#{window_layout}`:
 /// - `{...}` = horizontal split (children side-by-side)
 /// - `[...]` = vertical split (children stacked)
-/// - Each child is either a leaf `WxH,X,Y,pane_id` or a nested split `WxH,X,Y{...}` / `WxH,X,Y[...]`
+/// - a pane is `WxH,X,Y,pane_id` (the id is ignored: panes are placed in
+///   order)
 ///
-/// The `panes` vec provides existing pane nodes to fill the tree leaves.
-/// Returns `None` if parsing fails.
-pub fn parse_tmux_layout_string(layout_str: &str, panes: &mut Vec<Node>) -> Option<Node> {
-    // Skip the 4-hex-char checksum + comma prefix
-    let s = layout_str.trim();
-    if s.len() < 5 { return None; }
-    // Find the first comma after the checksum
-    let after_checksum = s.find(',')? + 1;
-    let body = &s[after_checksum..];
-    
-    let (node, _) = parse_node(body, panes)?;
-    Some(node)
+/// The checksum must match, as tmux requires.
+fn parse_layout_string(layout_str: &str) -> Result<LayoutCell, String> {
+    let invalid = || format!("invalid layout: {}", layout_str);
+    let (csum, body) = layout_str.trim().split_once(',').ok_or_else(invalid)?;
+    let csum = u16::from_str_radix(csum, 16).map_err(|_| invalid())?;
+    if csum != crate::format::tmux_layout_checksum(body) { return Err(invalid()); }
+    let bytes = body.as_bytes();
+    let mut pos = 0;
+    let cell = parse_cell(bytes, &mut pos).ok_or_else(invalid)?;
+    if pos != bytes.len() { return Err(invalid()); }
+    Ok(cell)
 }
 
-/// Parse a single node from position in the string, returns (Node, chars_consumed)
-fn parse_node(s: &str, panes: &mut Vec<Node>) -> Option<(Node, usize)> {
-    // Parse WxH,X,Y first
-    let (_w, _h, consumed_dims) = parse_dimensions(s)?;
-    let rest = &s[consumed_dims..];
-    
-    // After dimensions, we have either:
-    // - '{' for horizontal split
-    // - '[' for vertical split  
-    // - ',' followed by pane_id (leaf)
-    // - end of string (leaf with no pane_id)
-    
-    if rest.starts_with('{') {
-        // Horizontal split
-        let (children, consumed_bracket) = parse_children(&rest[1..], '}', panes)?;
-        let total_w: u32 = children.iter().map(|(cw, _, _)| *cw as u32).sum();
-        let sizes: Vec<u16> = if total_w == 0 {
-            vec![100 / children.len().max(1) as u16; children.len()]
-        } else {
-            let mut szs: Vec<u16> = children.iter().map(|(cw, _, _)| ((*cw as u32) * 100 / total_w) as u16).collect();
-            let sum: u16 = szs.iter().sum();
-            if sum < 100 { if let Some(last) = szs.last_mut() { *last += 100 - sum; } }
-            szs
-        };
-        let nodes: Vec<Node> = children.into_iter().map(|(_, _, n)| n).collect();
-        Some((
-            Node::Split { kind: LayoutKind::Horizontal, sizes, children: nodes },
-            consumed_dims + 1 + consumed_bracket,
-        ))
-    } else if rest.starts_with('[') {
-        // Vertical split
-        let (children, consumed_bracket) = parse_children(&rest[1..], ']', panes)?;
-        let total_h: u32 = children.iter().map(|(_, ch, _)| *ch as u32).sum();
-        let sizes: Vec<u16> = if total_h == 0 {
-            vec![100 / children.len().max(1) as u16; children.len()]
-        } else {
-            let mut szs: Vec<u16> = children.iter().map(|(_, ch, _)| ((*ch as u32) * 100 / total_h) as u16).collect();
-            let sum: u16 = szs.iter().sum();
-            if sum < 100 { if let Some(last) = szs.last_mut() { *last += 100 - sum; } }
-            szs
-        };
-        let nodes: Vec<Node> = children.into_iter().map(|(_, _, n)| n).collect();
-        Some((
-            Node::Split { kind: LayoutKind::Vertical, sizes, children: nodes },
-            consumed_dims + 1 + consumed_bracket,
-        ))
+fn parse_number(s: &[u8], pos: &mut usize) -> Option<u16> {
+    let start = *pos;
+    while *pos < s.len() && s[*pos].is_ascii_digit() { *pos += 1; }
+    std::str::from_utf8(&s[start..*pos]).ok()?.parse().ok()
+}
+
+fn expect(s: &[u8], pos: &mut usize, c: u8) -> Option<()> {
+    (s.get(*pos) == Some(&c)).then(|| *pos += 1)
+}
+
+/// One cell at `pos`: `WxH,X,Y` then `{cells}`, `[cells]` or `,id`.
+fn parse_cell(s: &[u8], pos: &mut usize) -> Option<LayoutCell> {
+    let w = parse_number(s, pos)?;
+    expect(s, pos, b'x')?;
+    let h = parse_number(s, pos)?;
+    expect(s, pos, b',')?;
+    parse_number(s, pos)?;
+    expect(s, pos, b',')?;
+    parse_number(s, pos)?;
+    let (kind, close) = match s.get(*pos) {
+        Some(b'{') => (LayoutKind::Horizontal, b'}'),
+        Some(b'[') => (LayoutKind::Vertical, b']'),
+        _ => {
+            // The pane id, unless the comma starts the next cell
+            let mut id = *pos + 1;
+            if s.get(*pos) == Some(&b',') && parse_number(s, &mut id).is_some() && s.get(id) != Some(&b'x') {
+                *pos = id;
+            }
+            return Some(LayoutCell::Pane { w, h });
+        }
+    };
+    *pos += 1;
+    let mut children = vec![parse_cell(s, pos)?];
+    while expect(s, pos, b',').is_some() { children.push(parse_cell(s, pos)?); }
+    expect(s, pos, close)?;
+    Some(LayoutCell::Split { kind, w, h, children })
+}
+
+/// Each pane of the active window with its `pane-border-format` line, by
+/// pane id; none while `pane-border-status` is off.
+pub fn border_texts(app: &AppState) -> Vec<(usize, String)> {
+    if crate::tree::border_status_rows(app) == 0 { return Vec::new(); }
+    let root = &app.windows[app.active_idx].root;
+    (0..crate::tree::count_panes(root))
+        .filter_map(|pos| crate::tree::get_nth_pane(root, pos).map(|p| p.id).map(|id| {
+            (id, crate::format::expand_format_for_pane(&app.pane_border_format, app, app.active_idx, pos))
+        }))
+        .collect()
+}
+
+/// Split a client's terminal into the pane area and the status rows.
+/// `status_rows` is 0 for a client that hides the status line
+/// (`attach --no-status`), which then gives panes every row.
+pub fn client_areas(term: ratatui::layout::Rect, status_rows: u16, at_top: bool) -> (ratatui::layout::Rect, ratatui::layout::Rect) {
+    use ratatui::layout::Rect;
+    let rows = status_rows.min(term.height.saturating_sub(1));
+    let content_h = term.height - rows;
+    if at_top {
+        (Rect { y: term.y + rows, height: content_h, ..term }, Rect { height: rows, ..term })
+    } else {
+        (Rect { height: content_h, ..term }, Rect { y: term.y + content_h, height: rows, ..term })
+    }
+}
+
+/// The part of a window's `area` its panes get: all of it but the row
+/// `pane-border-status` (`top` or `bottom`) draws their border line on.
+pub fn panes_area(area: ratatui::layout::Rect, border_status: &str) -> ratatui::layout::Rect {
+    use ratatui::layout::Rect;
+    let rows = u16::from(matches!(border_status, "top" | "bottom")).min(area.height.saturating_sub(1));
+    let y = if border_status == "top" { area.y + rows } else { area.y };
+    Rect { y, height: area.height - rows, ..area }
+}
+
+/// Translate a mouse row on a client's terminal to the server's
+/// coordinates, where panes start at row 0 and the status line sits
+/// directly below them.
+pub fn mouse_row_to_server(row: u16, content: ratatui::layout::Rect, status: ratatui::layout::Rect) -> u16 {
+    if status.height > 0 && row >= status.y && row < status.y + status.height {
+        content.height + (row - status.y)
+    } else {
+        row.saturating_sub(content.y)
+    }
+}
+
+/// The inverse of `mouse_row_to_server`: a server row on the client's
+/// terminal.
+pub fn server_row_to_client(row: u16, content: ratatui::layout::Rect, status: ratatui::layout::Rect) -> u16 {
+    if row >= content.height && status.height > 0 {
+        status.y + (row - content.height).min(status.height - 1)
     } else {
-        // Leaf node — may have ,pane_id suffix
-        let mut extra = 0;
-        if rest.starts_with(',') {
-            // Skip pane_id
-            let id_str = &rest[1..];
-            let end = id_str.find(|c: char| c == ',' || c == '{' || c == '[' || c == '}' || c == ']').unwrap_or(id_str.len());
-            extra = 1 + end;
+        content.y + row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 40x120 pane (2000 lines of history) after a program printed one
+    /// `len`-byte line, fed in reader-thread sized chunks.
+    fn pane_after_long_line(len: usize) -> vt100::Parser {
+        let mut parser = vt100::Parser::new(40, 120, 2000);
+        let chunk = [b'x'; 8192];
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(chunk.len());
+            parser.process(&chunk[..n]);
+            left -= n;
         }
-        // Consume a pane from the provided vec
-        let leaf = if !panes.is_empty() { panes.remove(0) } else { return None; };
-        Some((leaf, consumed_dims + extra))
-    }
-}
-
-/// Parse WxH,X,Y — returns (width, height, chars_consumed)
-fn parse_dimensions(s: &str) -> Option<(u16, u16, usize)> {
-    // Parse W (digits)
-    let x_pos = s.find('x')?;
-    let w: u16 = s[..x_pos].parse().ok()?;
-    let after_x = &s[x_pos + 1..];
-    // Parse H (digits until ',')
-    let comma1 = after_x.find(',')?;
-    let h: u16 = after_x[..comma1].parse().ok()?;
-    let after_h = &after_x[comma1 + 1..];
-    // Parse X (digits until ',')
-    let comma2 = after_h.find(',')?;
-    // _x coordinate (skip)
-    let after_xcoord = &after_h[comma2 + 1..];
-    // Parse Y (digits until next non-digit)
-    let y_end = after_xcoord.find(|c: char| !c.is_ascii_digit()).unwrap_or(after_xcoord.len());
-    // Total consumed: W + 'x' + H + ',' + X + ',' + Y
-    let total = x_pos + 1 + comma1 + 1 + comma2 + 1 + y_end;
-    Some((w, h, total))
-}
-
-/// Parse comma-separated children inside brackets.
-/// Returns vec of (width, height, Node) and total chars consumed including closing bracket.
-fn parse_children(s: &str, closing: char, panes: &mut Vec<Node>) -> Option<(Vec<(u16, u16, Node)>, usize)> {
-    let mut children = Vec::new();
-    let mut pos = 0;
-    
-    loop {
-        if pos >= s.len() { return None; }
-        if s.as_bytes()[pos] == closing as u8 {
-            pos += 1; // consume closing bracket
-            break;
+        parser
+    }
+
+    fn frame(parser: &vt100::Parser, plain: bool) -> String {
+        let mut out = String::new();
+        if plain {
+            write_plain(&snapshot_plain(parser.screen(), 40, 120), &mut out);
+        } else {
+            write_runs(&snapshot_runs(parser.screen(), 40, 120), &mut out);
+        }
+        out
+    }
+
+    /// A tree as `H(a,b)` / `V(a,b)`, with panes shown as their ids and the
+    /// childless splits standing in for panes as `*`.
+    fn shape(n: &Node) -> String {
+        match n {
+            Node::Leaf(p) => p.id.to_string(),
+            Node::Split { children, .. } if children.is_empty() => "*".to_string(),
+            Node::Split { kind, children, .. } => format!("{}({})",
+                if *kind == LayoutKind::Horizontal { "H" } else { "V" },
+                children.iter().map(shape).collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    #[test]
+    fn tiled_fills_rows_of_a_grid() {
+        let panes = |n| (0..n).map(|_| Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] }).collect::<Vec<_>>();
+        assert_eq!(shape(&tiled(panes(2))), "V(*,*)");
+        assert_eq!(shape(&tiled(panes(3))), "V(H(*,*),*)");
+        assert_eq!(shape(&tiled(panes(4))), "V(H(*,*),H(*,*))");
+        assert_eq!(shape(&tiled(panes(5))), "V(H(*,*),H(*,*),*)");
+        assert_eq!(shape(&tiled(panes(7))), "V(H(*,*,*),H(*,*,*),*)");
+        if let Node::Split { sizes, .. } = tiled(panes(3)) { assert_eq!(sizes, [50, 50]); }
+    }
+
+    #[test]
+    fn presets_keep_pane_order_and_the_active_pane() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        app.last_window_area = ratatui::layout::Rect::new(0, 0, 400, 100);
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        for _ in 0..4 {
+            crate::pane::split_active_with_command(&mut app, LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
         }
-        if !children.is_empty() {
-            // Expect comma separator between children
-            if s.as_bytes()[pos] == b',' {
-                pos += 1;
+        let active = crate::tree::get_active_pane_id(&app.windows[0].root, &app.windows[0].active_path);
+
+        apply_layout(&mut app, "main-vertical").unwrap();
+        assert_eq!(shape(&app.windows[0].root), "H(1,V(2,3,4,5))");
+        assert_eq!(crate::tree::get_active_pane_id(&app.windows[0].root, &app.windows[0].active_path), active);
+        apply_layout(&mut app, "tiled").unwrap();
+        assert_eq!(shape(&app.windows[0].root), "V(H(1,2),H(3,4),5)");
+
+        // next-layout goes on from the layout last selected, through all five
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            cycle_layout(&mut app);
+            seen.push(shape(&app.windows[0].root));
+        }
+        assert_eq!(seen, ["H(1,2,3,4,5)", "V(1,2,3,4,5)", "V(1,H(2,3,4,5))", "H(1,V(2,3,4,5))", "V(H(1,2),H(3,4),5)"]);
+        cycle_layout_reverse(&mut app);
+        assert_eq!(shape(&app.windows[0].root), "H(1,V(2,3,4,5))");
+
+        // Killing a pane of a tiled window tiles the rest again
+        apply_layout(&mut app, "tiled").unwrap();
+        crate::tree::focus_pane_by_id(&mut app, 3);
+        crate::pane::kill_active_pane(&mut app).unwrap();
+        assert_eq!(shape(&app.windows[0].root), "V(H(1,2),H(4,5))");
+        // but not once it was split
+        crate::tree::focus_pane_by_id(&mut app, 1);
+        crate::pane::split_active_with_command(&mut app, LayoutKind::Vertical, None, Some(&*pty)).unwrap();
+        crate::tree::focus_pane_by_id(&mut app, 4);
+        crate::pane::kill_active_pane(&mut app).unwrap();
+        assert_eq!(shape(&app.windows[0].root), "V(H(V(1,6),2),5)");
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn split_sizes_sides_and_full_width_splits() {
+        use crate::types::{PaneSize, SplitOpts};
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        app.last_window_area = ratatui::layout::Rect::new(0, 0, 121, 41);
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        let split = |app: &mut AppState, kind, size, before, full| {
+            crate::pane::split_pane(app, kind, None, Some(&*pty), SplitOpts { size, before, full }).unwrap();
+            crate::tree::resize_all_panes(app);
+        };
+        let size_of = |app: &AppState, id| {
+            let mut rects = Vec::new();
+            crate::tree::compute_rects(&app.windows[0].root, app.last_window_area, &mut rects);
+            rects.into_iter().find(|(p, _)| crate::tree::get_active_pane_id(&app.windows[0].root, p) == Some(id))
+                .map(|(_, r)| (r.width, r.height)).unwrap()
+        };
+
+        split(&mut app, LayoutKind::Horizontal, Some(PaneSize::Cells(30)), false, false);
+        assert_eq!((size_of(&app, 1), size_of(&app, 2)), ((90, 41), (30, 41)));
+        // Inside a parent with unequal sizes, which keeps them
+        crate::tree::focus_pane_by_id(&mut app, 1);
+        split(&mut app, LayoutKind::Vertical, Some(PaneSize::Percent(25)), false, false);
+        assert_eq!((size_of(&app, 1), size_of(&app, 3), size_of(&app, 2)), ((90, 30), (90, 10), (30, 41)));
+        // -b: left of the active pane
+        split(&mut app, LayoutKind::Horizontal, Some(PaneSize::Cells(12)), true, false);
+        assert_eq!((size_of(&app, 4), size_of(&app, 3)), ((12, 10), (77, 10)));
+        assert_eq!(crate::tree::get_active_pane_id(&app.windows[0].root, &app.windows[0].active_path), Some(4));
+        // -f -b: a row across the top of the whole window
+        split(&mut app, LayoutKind::Vertical, Some(PaneSize::Cells(5)), true, true);
+        assert_eq!(shape(&app.windows[0].root), "V(5,H(V(1,H(4,3)),2))");
+        assert_eq!((size_of(&app, 5), size_of(&app, 2)), ((121, 5), (30, 35)));
+        assert_eq!(app.windows[0].active_path, [0]);
+        // Too large a size still leaves the old place two cells
+        split(&mut app, LayoutKind::Horizontal, Some(PaneSize::Cells(500)), false, false);
+        assert_eq!(size_of(&app, 5).0, 2);
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn highlights_go_out_with_the_pane_and_are_counted() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        let win = &mut app.windows[0];
+        let p = crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap();
+        let mut parser = vt100::Parser::new(p.last_rows, p.last_cols, 0);
+        parser.process(b"ok\r\nWARN disk\r\nERROR: WARN twice");
+        p.term = std::sync::Arc::new(std::sync::Mutex::new(parser));
+        let pane_pattern = |app: &mut AppState, spec: Option<&str>| {
+            let win = &mut app.windows[0];
+            crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().highlight_pattern = spec.map(str::to_string);
+        };
+
+        let leaf = |app: &mut AppState| match serde_json::from_str::<LayoutJson>(&dump_layout_json_fast(app, FrameKind::Styled).unwrap()).unwrap() {
+            LayoutJson::Leaf { highlights, highlight_styles, .. } => (highlights, highlight_styles),
+            LayoutJson::Split { .. } => unreachable!(),
+        };
+        assert_eq!(leaf(&mut app), (vec![], vec![]));
+        assert_eq!(crate::format::expand_format("#{pane_highlight_count}", &app), "0");
+
+        // The pane's setting overrides the window's, which overrides the session's
+        app.highlight_pattern = "WARN=fg=yellow".into();
+        assert_eq!(leaf(&mut app).0, [(1, 0, 3, 0), (2, 7, 10, 0)]);
+        app.windows[0].highlight_pattern = Some("ERROR=fg=red".into());
+        assert_eq!(leaf(&mut app), (vec![(2, 0, 4, 0)], vec!["fg=red".to_string()]));
+        pane_pattern(&mut app, Some("ERROR=fg=red;WARN=bold"));
+        assert_eq!(leaf(&mut app).0, [(1, 0, 3, 1), (2, 0, 4, 0), (2, 7, 10, 1)]);
+        assert_eq!(crate::format::expand_format("#{pane_highlight_count}", &app), "3");
+        pane_pattern(&mut app, None);
+        assert_eq!(crate::format::expand_format("#{pane_highlight_count}", &app), "1");
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn quiet_panes_reuse_the_rows_they_last_went_out_with() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        let win = &mut app.windows[0];
+        let p = crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap();
+        let mut parser = vt100::Parser::new(p.last_rows, p.last_cols, 0);
+        parser.process(b"one");
+        let term = std::sync::Arc::new(std::sync::Mutex::new(parser));
+        p.term = term.clone();
+        // Out of reach of the shell's reader thread
+        let version = std::sync::Arc::new(AtomicU64::new(0));
+        p.data_version = version.clone();
+
+        let first_row = |app: &mut AppState, kind: FrameKind| match serde_json::from_str::<LayoutJson>(&dump_layout_json_fast(app, kind).unwrap()).unwrap() {
+            LayoutJson::Leaf { mut rows_v2, mut rows_bin, .. } => {
+                if !rows_bin.is_empty() { rows_v2 = rows_bin.drain(..).map(|r| RowRunsJson { runs: crate::cells::decode_row(&r).unwrap() }).collect(); }
+                rows_v2[0].runs.iter().map(|r| r.text.as_str()).collect::<String>().trim_end().to_string()
             }
+            LayoutJson::Split { .. } => unreachable!(),
+        };
+        assert_eq!(first_row(&mut app, FrameKind::Styled), "one");
+        // Written without moving data_version: the rows already built go out again
+        term.lock().unwrap().process(b"\rtwo");
+        assert_eq!(first_row(&mut app, FrameKind::Styled), "one");
+        // Another encoding is built afresh
+        assert_eq!(first_row(&mut app, FrameKind::Binary), "two");
+        term.lock().unwrap().process(b"\rsix");
+        assert_eq!(first_row(&mut app, FrameKind::Binary), "two");
+        version.fetch_add(1, Ordering::Release);
+        assert_eq!(first_row(&mut app, FrameKind::Binary), "six");
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn layout_strings_are_parsed_and_checked() {
+        let cell = parse_layout_string("d5e1,208x58,0,0{104x58,0,0,1,103x58,105,0,2}").unwrap();
+        assert_eq!((cell.size(), cell.panes()), ((208, 58), 2));
+        // Nested, without pane ids
+        let body = "80x24,0,0[80x12,0,0,80x11,0,13{40x11,0,13,39x11,41,13}]";
+        let csum = crate::format::tmux_layout_checksum(body);
+        assert_eq!(parse_layout_string(&format!("{:04x},{}", csum, body)).unwrap().panes(), 3);
+
+        assert!(parse_layout_string("d5e2,208x58,0,0{104x58,0,0,1,103x58,105,0,2}").is_err());
+        assert!(parse_layout_string(&format!("{:04x},{}", csum, &body[..body.len() - 1])).is_err());
+        assert!(parse_layout_string("tiledx").is_err());
+    }
+
+    #[test]
+    fn a_window_layout_is_restored_from_its_string() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        app.last_window_area = ratatui::layout::Rect::new(0, 0, 200, 50);
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        for _ in 0..2 {
+            crate::pane::split_active_with_command(&mut app, LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
         }
-        
-        // Parse child dimensions first to get w,h
-        let child_str = &s[pos..];
-        let (cw, ch, _) = parse_dimensions(child_str)?;
-        // Now parse full node
-        let (node, consumed) = parse_node(child_str, panes)?;
-        children.push((cw, ch, node));
-        pos += consumed;
+        apply_layout(&mut app, "main-horizontal").unwrap();
+        let saved = crate::format::generate_window_layout(&app.windows[0].root, app.last_window_area);
+
+        apply_layout(&mut app, "even-horizontal").unwrap();
+        apply_layout(&mut app, &saved).unwrap();
+        assert_eq!(shape(&app.windows[0].root), "V(1,H(2,3))");
+        assert_eq!(crate::format::generate_window_layout(&app.windows[0].root, app.last_window_area), saved);
+
+        // A layout for another number of panes leaves the window alone
+        let err = apply_layout(&mut app, "d5e1,208x58,0,0{104x58,0,0,1,103x58,105,0,2}").unwrap_err();
+        assert_eq!(err, "invalid layout: 2 cells for 3 panes");
+        assert_eq!(shape(&app.windows[0].root), "V(1,H(2,3))");
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn search_matches_are_sent_where_they_are_drawn() {
+        let matches = [(-12, 0, 3), (-2, 4, 6), (0, 0, 1), (9, 2, 2)];
+        // Scrolled back 3 lines in a 10-row pane: lines -3 to 6 are shown
+        assert_eq!(visible_matches(&matches, 1, 3, 10, None), (vec![(1, 4, 6), (3, 0, 1)], Some(0)));
+        assert_eq!(visible_matches(&matches, 0, 3, 10, None).1, None);
+        assert_eq!(visible_matches(&matches, 3, 0, 10, None), (vec![(0, 0, 1), (9, 2, 2)], Some(1)));
+    }
+
+    #[test]
+    fn megabyte_line_costs_one_screen_per_frame() {
+        let short = pane_after_long_line(64 * 1024);
+        let mut huge = pane_after_long_line(10 * 1024 * 1024);
+        for plain in [false, true] {
+            let start = std::time::Instant::now();
+            let big = frame(&huge, plain);
+            let took = start.elapsed();
+            assert!(took < std::time::Duration::from_millis(250), "frame took {:?}", took);
+            // Frame size follows the pane size, not the line length (the
+            // partly filled last row may differ)
+            assert!(big.len().abs_diff(frame(&short, plain).len()) <= 120 * 8);
+            assert!(big.len() < 40 * 120 * 8);
+        }
+        // The parser keeps the line wrapped at the pane width and only
+        // history-limit rows of it
+        huge.screen_mut().set_scrollback(usize::MAX);
+        assert_eq!(huge.screen().scrollback(), 2000);
+    }
+
+    #[test]
+    fn hidden_status_gives_panes_the_whole_terminal() {
+        use ratatui::layout::Rect;
+        let term = Rect::new(0, 0, 120, 30);
+        // Two clients on the same session, one with --no-status
+        let mut hidden = true;
+        let shown = client_areas(term, 1, false);
+        let (content, status) = client_areas(term, if hidden { 0 } else { 1 }, false);
+        assert_eq!((content, status.height), (term, 0));
+        assert_eq!((shown.0.height, shown.1), (29, Rect::new(0, 29, 120, 1)));
+        // Toggling re-lays out that client only
+        hidden = !hidden;
+        assert_eq!(client_areas(term, if hidden { 0 } else { 1 }, false), shown);
+        // status-lines 2 at the top, and a status line never takes the last pane row
+        let (content, status) = client_areas(term, 2, true);
+        assert_eq!((content, status), (Rect::new(0, 2, 120, 28), Rect::new(0, 0, 120, 2)));
+        assert_eq!(client_areas(Rect::new(0, 0, 80, 1), 1, false).0.height, 1);
+    }
+
+    #[test]
+    fn mouse_rows_map_to_server_coordinates() {
+        use ratatui::layout::Rect;
+        let term = Rect::new(0, 0, 120, 30);
+        let (c, s) = client_areas(term, 0, false);
+        assert_eq!(mouse_row_to_server(29, c, s), 29);
+        let (c, s) = client_areas(term, 1, false);
+        assert_eq!((mouse_row_to_server(5, c, s), mouse_row_to_server(29, c, s)), (5, 29));
+        // Status on top: pane rows shift up, the status row lands below the panes
+        let (c, s) = client_areas(term, 1, true);
+        assert_eq!((mouse_row_to_server(1, c, s), mouse_row_to_server(0, c, s)), (0, 29));
+        assert_eq!((server_row_to_client(0, c, s), server_row_to_client(29, c, s)), (1, 0));
+        // Three status lines either way: each keeps its own row below the panes
+        let (c, s) = client_areas(term, 3, true);
+        assert_eq!((mouse_row_to_server(0, c, s), mouse_row_to_server(2, c, s), mouse_row_to_server(3, c, s)), (27, 29, 0));
+        let (c, s) = client_areas(term, 3, false);
+        assert_eq!((mouse_row_to_server(27, c, s), mouse_row_to_server(28, c, s)), (27, 28));
+        assert_eq!(server_row_to_client(28, c, s), 28);
+    }
+
+    #[test]
+    fn pane_border_status_takes_a_row_and_sends_each_panes_text() {
+        use ratatui::layout::Rect;
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::tree::set_window_size(&mut app, 80, 24);
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, LayoutKind::Vertical, None, Some(&*pty)).unwrap();
+        crate::tree::resize_all_panes(&mut app);
+        app.pane_border_format = "#{pane_index}:#{?pane_active,on,off}".to_string();
+
+        let borders = |app: &mut AppState| {
+            let json = dump_layout_json_fast(app, FrameKind::Styled).unwrap();
+            let LayoutJson::Split { children, .. } = serde_json::from_str::<LayoutJson>(&json).unwrap() else { panic!("{json}") };
+            children.into_iter().map(|c| match c {
+                LayoutJson::Leaf { border_text, rows, .. } => (border_text, rows),
+                _ => panic!("nested split"),
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(borders(&mut app), [(None, 11), (None, 12)]);
+
+        // The line above the panes comes out of the window; the splitter
+        // is the second pane's top border
+        crate::tree::set_pane_border_status(&mut app, "top");
+        assert_eq!(app.last_window_area, Rect::new(0, 1, 80, 23));
+        assert_eq!(borders(&mut app), [(Some("0:off".to_string()), 11), (Some("1:on".to_string()), 11)]);
+        assert_eq!(crate::tree::window_size(&app), (80, 24));
+
+        crate::tree::set_pane_border_status(&mut app, "bottom");
+        assert_eq!(app.last_window_area, Rect::new(0, 0, 80, 23));
+        assert_eq!(crate::tree::window_size(&app), (80, 24));
+        crate::tree::set_pane_border_status(&mut app, "off");
+        assert_eq!(app.last_window_area, Rect::new(0, 0, 80, 24));
+        assert_eq!(panes_area(Rect::new(0, 2, 80, 1), "top"), Rect::new(0, 2, 80, 1));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
     }
-    
-    Some((children, pos))
 }
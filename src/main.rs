@@ -5,6 +5,8 @@
 mod types;
 mod platform;
 mod pipe;
+mod afunix;
+mod transport;
 mod cli;
 mod session;
 mod tree;
@@ -12,6 +14,7 @@ mod style;
 mod rendering;
 mod config;
 mod commands;
+mod alias;
 mod pane;
 mod copy_mode;
 mod input;
@@ -20,9 +23,30 @@ mod window_ops;
 mod util;
 mod format;
 mod help;
+mod history;
+mod pattern;
+mod bandwidth;
+mod cells;
+mod delta;
+mod suspend;
+mod popup;
+mod buffers;
+mod chooser;
+mod clipboard;
+mod highlight;
+mod hooks;
+mod alerts;
+mod menu;
+mod prompt;
+mod encoding;
+mod environ;
+mod scoped;
+mod pipe_pane;
+mod sanitize;
+mod resurrect;
+mod predict;
 mod server;
 mod client;
-mod app;
 mod ssh_input;
 
 use std::io::{self, Write, Read as _, BufRead as _};
@@ -37,8 +61,9 @@ use crossterm::cursor::{EnableBlinking, DisableBlinking};
 use crossterm::event::{EnableMouseCapture, DisableMouseCapture, EnableBracketedPaste, DisableBracketedPaste};
 
 use crate::platform::enable_virtual_terminal_processing;
-use crate::cli::{print_help, print_version, print_commands, extract_session_from_target};
-use crate::session::{cleanup_stale_port_files, read_session_key, send_control,
+use crate::cli::{print_help, print_version, print_commands, extract_session_from_target, parse_target, split_command_sequence,
+    target_is_destination, target_names_pane};
+use crate::session::{cleanup_stale_port_files, send_control,
     send_control_with_response, resolve_last_session_name, resolve_default_session_name,
     kill_remaining_server_processes};
 use crate::rendering::apply_cursor_style;
@@ -59,15 +84,13 @@ fn main() {
 fn run_main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     
-    // Clean up any stale port files at startup
-    cleanup_stale_port_files();
-    
     // Parse -L flag early (tmux-compatible: names the server socket for namespace isolation)
     // In psmux, -L <name> creates a namespace prefix for session port/key files.
     // Sessions under -L "foo" are stored as "foo__sessionname.port".
     // IMPORTANT: Only recognize -L as a global flag when it appears BEFORE the subcommand.
     // This avoids conflict with subcommand flags (e.g. select-pane -L, resize-pane -L).
     let mut l_socket_name: Option<String> = None;
+    let mut no_daemon = false;
     {
         let mut i = 1; // skip binary name
         while i < args.len() {
@@ -75,6 +98,22 @@ fn run_main() -> io::Result<()> {
             if arg == "-L" && i + 1 < args.len() {
                 l_socket_name = Some(args[i + 1].clone());
                 i += 2;
+            } else if arg == "--socket-dir" && i + 1 < args.len() {
+                // Exported so a spawned server (and its panes) use the same registry
+                env::set_var("PSMUX_DIR", &args[i + 1]);
+                i += 2;
+            } else if arg == "--no-daemon" {
+                // Host the session on a thread of this process (debugging)
+                no_daemon = true;
+                i += 1;
+            } else if arg == "--timeout" && i + 1 < args.len() {
+                // Limit on each attach handshake step, read by the client
+                env::set_var("PSMUX_ATTACH_TIMEOUT", &args[i + 1]);
+                i += 2;
+            } else if arg == "--low-bandwidth" {
+                // Read by the attaching client (see bandwidth.rs)
+                env::set_var("PSMUX_LOW_BANDWIDTH", "1");
+                i += 1;
             } else if (arg == "-S" || arg == "-f" || arg == "-t") && i + 1 < args.len() {
                 i += 2; // skip other global flag-value pairs
             } else if arg.starts_with('-') {
@@ -85,55 +124,38 @@ fn run_main() -> io::Result<()> {
         }
     }
 
+    // With --no-daemon: the session's port file base and its server thread,
+    // stopped when the client is done
+    let mut in_process_server = None;
+
+    // Clean up any stale port files at startup (after --socket-dir is known)
+    cleanup_stale_port_files();
+
     // Parse -t flag early to set target session for all commands
     // Supports session:window.pane format (e.g., "dev:0.1")
     // PSMUX_TARGET_SESSION stores the port file base name (for port file lookup)
     // PSMUX_TARGET_FULL stores the full target (session:window.pane) for the server
-    if let Some(pos) = args.iter().position(|a| a == "-t") {
-        if let Some(target) = args.get(pos + 1) {
-            // Store the full target for the server to parse
-            env::set_var("PSMUX_TARGET_FULL", target);
-            // Extract just the session name for port file lookup
-            let session = extract_session_from_target(target);
-            // Apply -L namespace prefix for port file lookup
-            let port_file_base = if let Some(ref l) = l_socket_name {
-                format!("{}__{}", l, session)
-            } else {
-                session.clone()
-            };
-            env::set_var("PSMUX_TARGET_SESSION", &port_file_base);
-        }
+    // A target without a session part (":2.1", "%3") is in the current
+    // session, found through TMUX below like no -t at all
+    let target_session = args.iter().position(|a| a == "-t").and_then(|pos| args.get(pos + 1)).and_then(|target| {
+        // Store the full target for the server to parse
+        env::set_var("PSMUX_TARGET_FULL", target);
+        parse_target(target).session.map(|_| extract_session_from_target(target))
+    });
+    if let Some(session) = target_session {
+        // Apply -L namespace prefix for port file lookup
+        let port_file_base = if let Some(ref l) = l_socket_name {
+            format!("{}__{}", l, session)
+        } else {
+            session.clone()
+        };
+        env::set_var("PSMUX_TARGET_SESSION", &port_file_base);
     } else if env::var("PSMUX_TARGET_SESSION").is_err() {
         // No -t flag: try to resolve session from TMUX env var (set inside psmux panes)
-        // TMUX format: /tmp/psmux-<pid>/<socket_name>,<port>,<session_idx>
-        if let Ok(tmux_val) = env::var("TMUX") {
-            // Extract the port from the TMUX value
-            let parts: Vec<&str> = tmux_val.split(',').collect();
-            if parts.len() >= 2 {
-                if let Ok(port) = parts[1].trim().parse::<u16>() {
-                    // Look up which session owns this port (port file base
-                    // already includes -L namespace prefix if applicable)
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let psmux_dir = format!("{}\\.psmux", home);
-                    if let Ok(entries) = std::fs::read_dir(&psmux_dir) {
-                        for entry in entries.flatten() {
-                            let path = entry.path();
-                            if path.extension().map(|e| e == "port").unwrap_or(false) {
-                                if let Ok(port_str) = std::fs::read_to_string(&path) {
-                                    if let Ok(file_port) = port_str.trim().parse::<u16>() {
-                                        if file_port == port {
-                                            if let Some(port_file_base) = path.file_stem().and_then(|s| s.to_str()) {
-                                                env::set_var("PSMUX_TARGET_SESSION", port_file_base);
-                                            }
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        // TMUX format: /tmp/psmux-<pid>/<socket_name>,0,0; the registry
+        // record with that server pid names the session
+        if let Some(base) = env::var("TMUX").ok().and_then(|t| crate::session::server_from_tmux_env(&t)) {
+            env::set_var("PSMUX_TARGET_SESSION", base);
         }
     }
     
@@ -148,7 +170,7 @@ fn run_main() -> io::Result<()> {
         while i < args.len() {
             if !found_subcommand {
                 // Before subcommand: skip global flags with values
-                if (args[i] == "-t" || args[i] == "-L") && i + 1 < args.len() {
+                if (args[i] == "-t" || args[i] == "-L" || args[i] == "--socket-dir") && i + 1 < args.len() {
                     i += 2; // skip flag and its value
                     continue;
                 } else if args[i] == "-h" || args[i] == "--help"
@@ -176,6 +198,20 @@ fn run_main() -> io::Result<()> {
         result
     };
     
+    // `cmd1 \; cmd2`: run each command in turn through this binary.  The
+    // target session is already in the environment the children inherit.
+    let sequence = split_command_sequence(&cmd_args.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    if sequence.len() > 1 {
+        let exe = env::current_exe()?;
+        for group in sequence {
+            let mut c = std::process::Command::new(&exe);
+            if let Some(ref l) = l_socket_name { c.args(["-L", l]); }
+            let status = c.args(&group).status()?;
+            if !status.success() { std::process::exit(status.code().unwrap_or(1)); }
+        }
+        return Ok(());
+    }
+
     let cmd = cmd_args.first().map(|s| s.as_str()).unwrap_or("");
     
     // Handle help and version flags first
@@ -195,15 +231,25 @@ fn run_main() -> io::Result<()> {
         _ => {}
     }
 
+    // A -t naming a window or pane that does not exist fails before the
+    // command runs, on stderr with a non-zero exit like tmux
+    if let Ok(full) = env::var("PSMUX_TARGET_FULL") {
+        if target_names_pane(&parse_target(&full)) && !target_is_destination(cmd) {
+            if let Some(err) = crate::session::target_error() {
+                eprintln!("psmux: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     match cmd {
         // kill-server MUST be handled early before any potential fall-through
         "kill-server" => {
-            let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-            let psmux_dir = format!("{}\\.psmux", home);
+            let psmux_dir = crate::session::psmux_dir();
             // Compute namespace prefix for -L filtering (matches list-sessions behavior)
             let ns_prefix = l_socket_name.as_ref().map(|l| format!("{l}__"));
-            let mut streams: Vec<crate::pipe::PipeStream> = Vec::new();
-            let mut stale_keys: Vec<std::path::PathBuf> = Vec::new();
+            let mut streams: Vec<crate::transport::Stream> = Vec::new();
+            let mut stale: Vec<String> = Vec::new();
             if let Ok(entries) = std::fs::read_dir(&psmux_dir) {
                 for entry in entries.flatten() {
                     let path = entry.path();
@@ -215,17 +261,13 @@ fn run_main() -> io::Result<()> {
                             if let Some(ref pfx) = ns_prefix {
                                 if !session_name.starts_with(pfx.as_str()) { continue; }
                             }
-                            let sess_key = read_session_key(session_name).unwrap_or_default();
-                            if let Ok(handle) = crate::pipe::connect_to_pipe(session_name, 1000) {
-                                let mut stream = crate::pipe::PipeStream::from_handle(handle);
-                                let _ = write!(stream, "AUTH {}\n", sess_key);
-                                let _ = stream.flush();
+                            if let Ok(mut stream) = crate::transport::connect(session_name, 1000) {
                                 let _ = std::io::Write::write_all(&mut stream, b"kill-server\n");
                                 let _ = stream.flush();
                                 streams.push(stream);
                             } else {
                                 // Server not reachable — stale key file
-                                stale_keys.push(path.clone());
+                                stale.push(session_name.to_string());
                             }
                         }
                     }
@@ -243,9 +285,9 @@ fn run_main() -> io::Result<()> {
                     }
                 }
             }
-            // Clean up stale key files
-            for path in &stale_keys {
-                let _ = std::fs::remove_file(path);
+            // Clean up stale key (and socket) files
+            for base in &stale {
+                crate::session::remove_session_files(base);
             }
             // Brief sleep then verify no processes remain; if any do, force-kill them.
             // Only do the nuclear fallback when not using -L namespace filtering,
@@ -257,8 +299,12 @@ fn run_main() -> io::Result<()> {
             return Ok(());
         }
         "ls" | "list-sessions" => {
-                let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                let dir = format!("{}\\.psmux", home);
+                if args.iter().any(|a| a == "--json") {
+                    let infos = crate::session::collect_session_infos(l_socket_name.as_deref());
+                    println!("{}", serde_json::to_string(&infos).unwrap_or_else(|_| "[]".to_string()));
+                    return Ok(());
+                }
+                let dir = crate::session::psmux_dir();
                 // Compute namespace prefix for -L filtering
                 let ns_prefix = l_socket_name.as_ref().map(|l| format!("{l}__"));
                 if let Ok(entries) = std::fs::read_dir(&dir) {
@@ -274,21 +320,13 @@ fn run_main() -> io::Result<()> {
                                     } else {
                                         if base.contains("__") { continue; }
                                     }
-                                    if crate::pipe::pipe_exists(base) {
-                                        let sess_key = read_session_key(base).unwrap_or_default();
-                                        if let Ok(handle) = crate::pipe::connect_to_pipe(base, 1000) {
-                                            let mut s = crate::pipe::PipeStream::from_handle(handle);
-                                            let _ = std::io::Write::write_all(&mut s, format!("AUTH {}\n", sess_key).as_bytes());
+                                    if crate::transport::exists(base) {
+                                        if let Ok(mut s) = crate::transport::connect(base, 1000) {
                                             let _ = std::io::Write::write_all(&mut s, b"session-info\n");
                                             let _ = std::io::Write::flush(&mut s);
                                             let mut br = std::io::BufReader::new(s);
                                             let mut line = String::new();
-                                            // Skip "OK" response from AUTH
                                             let _ = br.read_line(&mut line);
-                                            if line.trim() == "OK" {
-                                                line.clear();
-                                                let _ = br.read_line(&mut line);
-                                            }
                                             if !line.trim().is_empty() && line.trim() != "ERROR: Authentication required" {
                                                 println!("{}", line.trim_end());
                                             } else {
@@ -297,7 +335,7 @@ fn run_main() -> io::Result<()> {
                                         }
                                     } else {
                                         // stale key file - remove it
-                                        let _ = std::fs::remove_file(e.path());
+                                        crate::session::remove_session_files(base);
                                     }
                                 }
                             }
@@ -317,6 +355,10 @@ fn run_main() -> io::Result<()> {
                     .unwrap_or_else(|| "default".to_string());
                 env::set_var("PSMUX_SESSION_NAME", name);
                 env::set_var("PSMUX_REMOTE_ATTACH", "1");
+                if args.iter().any(|a| a == "--no-status") { env::set_var("PSMUX_NO_STATUS", "1"); }
+                if let Some(secs) = args.iter().position(|a| a == "--timeout").and_then(|i| args.get(i + 1)) {
+                    env::set_var("PSMUX_ATTACH_TIMEOUT", secs);
+                }
             }
             "server" => {
                 // Internal command - run headless server (used when spawning background server)
@@ -399,9 +441,8 @@ fn run_main() -> io::Result<()> {
                 };
                 
                 // Check if session already exists AND is actually running
-                let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                let key_path = format!("{}\\.psmux\\{}.key", home, port_file_base);
-                if crate::pipe::pipe_exists(&port_file_base) {
+                let key_path = crate::session::key_file_path(&port_file_base);
+                if crate::transport::exists(&port_file_base) {
                     eprintln!("psmux: session '{}' already exists", name);
                     return Ok(());
                 } else if std::path::Path::new(&key_path).exists() {
@@ -409,60 +450,69 @@ fn run_main() -> io::Result<()> {
                     let _ = std::fs::remove_file(&key_path);
                 }
                 
-                // Always spawn a background server first
-                let exe = std::env::current_exe().unwrap_or_else(|_| std::path::PathBuf::from("psmux"));
-                let mut server_args: Vec<String> = vec!["server".into(), "-s".into(), name.clone()];
-                // Pass -L socket name to server for namespace isolation
-                if let Some(ref l) = l_socket_name {
-                    server_args.push("-L".into());
-                    server_args.push(l.clone());
-                }
-                // Pass initial command if provided
-                if let Some(ref init_cmd) = initial_cmd {
-                    server_args.push("-c".into());
-                    server_args.push(init_cmd.clone());
-                }
-                // Pass raw command args (direct execution) if -- was used
-                if let Some(ref raw_args) = raw_cmd_args {
-                    server_args.push("--".into());
-                    for a in raw_args {
-                        server_args.push(a.clone());
-                    }
-                }
-                // On Windows, mark parent's stdout/stderr as non-inheritable before
-                // spawning the server. This prevents the server from inheriting
-                // PowerShell's redirect pipes (which would cause the parent to hang
-                // waiting for the pipe to close). The server creates its own ConPTY
-                // handles so it doesn't need the parent's stdio.
-                #[cfg(windows)]
-                {
-                    #[link(name = "kernel32")]
-                    extern "system" {
-                        fn GetStdHandle(nStdHandle: u32) -> *mut std::ffi::c_void;
-                        fn SetHandleInformation(hObject: *mut std::ffi::c_void, dwMask: u32, dwFlags: u32) -> i32;
-                    }
-                    const STD_OUTPUT_HANDLE: u32 = 0xFFFFFFF5u32; // -11i32 as u32
-                    const STD_ERROR_HANDLE: u32 = 0xFFFFFFF4u32;  // -12i32 as u32
-                    const HANDLE_FLAG_INHERIT: u32 = 0x00000001;
-                    unsafe {
-                        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
-                        let stderr = GetStdHandle(STD_ERROR_HANDLE);
-                        SetHandleInformation(stdout, HANDLE_FLAG_INHERIT, 0);
-                        SetHandleInformation(stderr, HANDLE_FLAG_INHERIT, 0);
-                    }
-                }
-                // Spawn server with a hidden console window via CreateProcessW.
-                // This gives ConPTY a real console while keeping the window invisible.
-                #[cfg(windows)]
-                crate::platform::spawn_server_hidden(&exe, &server_args)?;
-                #[cfg(not(windows))]
-                {
-                    let mut cmd = std::process::Command::new(&exe);
-                    for a in &server_args { cmd.arg(a); }
-                    cmd.stdin(std::process::Stdio::null());
-                    cmd.stdout(std::process::Stdio::null());
-                    cmd.stderr(std::process::Stdio::null());
-                    let _child = cmd.spawn().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("failed to spawn server: {e}")))?;
+                // Spawn a background server, or with --no-daemon host it here
+                if no_daemon {
+                    if detached {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                            "--no-daemon: a detached session would end with this process"));
+                    }
+                    in_process_server = Some((port_file_base.clone(), crate::server::spawn_in_process(
+                        name.clone(), l_socket_name.clone(), initial_cmd.clone(), raw_cmd_args.clone())));
+                } else {
+                    let exe = std::env::current_exe().unwrap_or_else(|_| std::path::PathBuf::from("psmux"));
+                    let mut server_args: Vec<String> = vec!["server".into(), "-s".into(), name.clone()];
+                    // Pass -L socket name to server for namespace isolation
+                    if let Some(ref l) = l_socket_name {
+                        server_args.push("-L".into());
+                        server_args.push(l.clone());
+                    }
+                    // Pass initial command if provided
+                    if let Some(ref init_cmd) = initial_cmd {
+                        server_args.push("-c".into());
+                        server_args.push(init_cmd.clone());
+                    }
+                    // Pass raw command args (direct execution) if -- was used
+                    if let Some(ref raw_args) = raw_cmd_args {
+                        server_args.push("--".into());
+                        for a in raw_args {
+                            server_args.push(a.clone());
+                        }
+                    }
+                    // On Windows, mark parent's stdout/stderr as non-inheritable before
+                    // spawning the server. This prevents the server from inheriting
+                    // PowerShell's redirect pipes (which would cause the parent to hang
+                    // waiting for the pipe to close). The server creates its own ConPTY
+                    // handles so it doesn't need the parent's stdio.
+                    #[cfg(windows)]
+                    {
+                        #[link(name = "kernel32")]
+                        extern "system" {
+                            fn GetStdHandle(nStdHandle: u32) -> *mut std::ffi::c_void;
+                            fn SetHandleInformation(hObject: *mut std::ffi::c_void, dwMask: u32, dwFlags: u32) -> i32;
+                        }
+                        const STD_OUTPUT_HANDLE: u32 = 0xFFFFFFF5u32; // -11i32 as u32
+                        const STD_ERROR_HANDLE: u32 = 0xFFFFFFF4u32;  // -12i32 as u32
+                        const HANDLE_FLAG_INHERIT: u32 = 0x00000001;
+                        unsafe {
+                            let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+                            let stderr = GetStdHandle(STD_ERROR_HANDLE);
+                            SetHandleInformation(stdout, HANDLE_FLAG_INHERIT, 0);
+                            SetHandleInformation(stderr, HANDLE_FLAG_INHERIT, 0);
+                        }
+                    }
+                    // Spawn server with a hidden console window via CreateProcessW.
+                    // This gives ConPTY a real console while keeping the window invisible.
+                    #[cfg(windows)]
+                    crate::platform::spawn_server_hidden(&exe, &server_args)?;
+                    #[cfg(not(windows))]
+                    {
+                        let mut cmd = std::process::Command::new(&exe);
+                        for a in &server_args { cmd.arg(a); }
+                        cmd.stdin(std::process::Stdio::null());
+                        cmd.stdout(std::process::Stdio::null());
+                        cmd.stderr(std::process::Stdio::null());
+                        let _child = cmd.spawn().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("failed to spawn server: {e}")))?;
+                    }
                 }
                 
                 // Wait for server to create key file and pipe (up to 5 seconds)
@@ -483,7 +533,7 @@ fn run_main() -> io::Result<()> {
                     std::process::exit(1);
                 }
                 {
-                    if !crate::pipe::pipe_exists(&port_file_base) {
+                    if !crate::transport::exists(&port_file_base) {
                         let _ = std::fs::remove_file(&key_path);
                         eprintln!("psmux: session '{}' exited immediately (check shell command)", name);
                         std::process::exit(1);
@@ -525,6 +575,7 @@ fn run_main() -> io::Result<()> {
                 let mut print_info = false;
                 let mut format_str: Option<String> = None;
                 let mut start_dir: Option<String> = None;
+                let mut kill = false;
                 let mut nw_positional: Vec<String> = Vec::new();
                 {
                     let mut i = 1;
@@ -538,7 +589,8 @@ fn run_main() -> io::Result<()> {
                             "-t" | "-e" | "-S" => { i += 1; /* skip value */ }
                             "-d" => { detached = true; }
                             "-P" => { print_info = true; }
-                            "-a" | "-D" | "-k" => { /* ignored for compatibility */ }
+                            "-k" => { kill = true; }
+                            "-a" | "-D" => { /* ignored for compatibility */ }
                             _ if a.starts_with('-') => { /* unknown flag, skip */ }
                             _ => { nw_positional.extend(cmd_args[i..].iter().map(|s| s.to_string())); break; }
                         }
@@ -550,6 +602,7 @@ fn run_main() -> io::Result<()> {
                 let mut cmd_line = "new-window".to_string();
                 if detached { cmd_line.push_str(" -d"); }
                 if print_info { cmd_line.push_str(" -P"); }
+                if kill { cmd_line.push_str(" -k"); }
                 if let Some(ref fmt) = format_str {
                     cmd_line.push_str(&format!(" -F \"{}\"", fmt.replace("\"", "\\\"")));
                 }
@@ -565,9 +618,18 @@ fn run_main() -> io::Result<()> {
                 cmd_line.push('\n');
                 if print_info {
                     let resp = send_control_with_response(cmd_line)?;
+                    if resp.starts_with("psmux: new-window:") {
+                        eprint!("{}", resp);
+                        std::process::exit(1);
+                    }
                     print!("{}", resp);
                 } else {
-                    send_control(cmd_line)?;
+                    // The server answers with an error ("index N in use") or nothing
+                    let resp = send_control_with_response(cmd_line)?;
+                    if !resp.is_empty() {
+                        eprint!("{}", resp);
+                        std::process::exit(1);
+                    }
                 }
                 return Ok(());
             }
@@ -579,7 +641,9 @@ fn run_main() -> io::Result<()> {
                 let mut print_info = false;
                 let mut format_str: Option<String> = None;
                 let mut start_dir: Option<String> = None;
-                let mut size_pct: Option<String> = None;
+                let mut size: Option<(&str, String)> = None;
+                let mut before = false;
+                let mut full = false;
                 let mut sw_positional: Vec<String> = Vec::new();
                 {
                     let mut i = 1;
@@ -589,13 +653,15 @@ fn run_main() -> io::Result<()> {
                         match a {
                             "-F" => { i += 1; if i < cmd_args.len() { format_str = Some(cmd_args[i].trim_matches('"').to_string()); } }
                             "-c" => { i += 1; if i < cmd_args.len() { start_dir = Some(cmd_args[i].trim_matches('"').to_string()); } }
-                            "-p" | "-l" => { i += 1; if i < cmd_args.len() { size_pct = Some(cmd_args[i].to_string()); } }
+                            "-p" | "-l" => { i += 1; if i < cmd_args.len() { size = Some((a, cmd_args[i].to_string())); } }
                             "-t" | "-e" => { i += 1; /* skip value */ }
                             "-h" => { flag = "-h"; }
                             "-v" => { flag = "-v"; }
                             "-d" => { detached = true; }
                             "-P" => { print_info = true; }
-                            "-b" | "-f" | "-I" | "-Z" => { /* ignored for compatibility */ }
+                            "-b" => { before = true; }
+                            "-f" => { full = true; }
+                            "-I" | "-Z" => { /* ignored for compatibility */ }
                             _ if a.starts_with('-') => { /* unknown flag, skip */ }
                             _ => { sw_positional.extend(cmd_args[i..].iter().map(|s| s.to_string())); break; }
                         }
@@ -606,6 +672,8 @@ fn run_main() -> io::Result<()> {
                 let cmd_arg = cmd_arg.as_str();
                 let mut cmd_line = format!("split-window {}", flag);
                 if detached { cmd_line.push_str(" -d"); }
+                if before { cmd_line.push_str(" -b"); }
+                if full { cmd_line.push_str(" -f"); }
                 if print_info { cmd_line.push_str(" -P"); }
                 if let Some(ref fmt) = format_str {
                     cmd_line.push_str(&format!(" -F \"{}\"", fmt.replace("\"", "\\\"")));
@@ -613,8 +681,8 @@ fn run_main() -> io::Result<()> {
                 if let Some(dir) = &start_dir {
                     cmd_line.push_str(&format!(" -c \"{}\"", dir.replace("\"", "\\\"")));
                 }
-                if let Some(pct) = &size_pct {
-                    cmd_line.push_str(&format!(" -p {}", pct));
+                if let Some((flag, value)) = &size {
+                    cmd_line.push_str(&format!(" {} {}", flag, value));
                 }
                 if !cmd_arg.is_empty() {
                     cmd_line.push_str(&format!(" \"{}\"", cmd_arg.replace("\"", "\\\"")));
@@ -684,19 +752,24 @@ fn run_main() -> io::Result<()> {
             "send-keys" | "send" | "send-key" => {
                 let mut literal = false;
                 let mut keys: Vec<String> = Vec::new();
-                // Getopt-style parsing: -t consumes next arg, -l/-R are boolean
+                let mut flags = String::new();
+                // Getopt-style parsing: -t and -N consume the next arg, -l/-H/-R/-X are boolean
                 let mut i = 1;
                 while i < cmd_args.len() {
                     match cmd_args[i].as_str() {
                         "-l" => { literal = true; }
-                        "-R" => { keys.push("__RESET__".to_string()); }
+                        "-H" | "-R" | "-X" => { flags.push(' '); flags.push_str(&cmd_args[i]); }
                         "-t" => { i += 1; } // consume target value (already handled globally)
-                        "-N" => { i += 1; } // repeat count, consume value
+                        "-N" => {
+                            if let Some(n) = cmd_args.get(i + 1) { flags.push_str(&format!(" -N {}", n)); }
+                            i += 1;
+                        }
                         _ => { keys.push(cmd_args[i].to_string()); }
                     }
                     i += 1;
                 }
                 let mut cmd = "send-keys".to_string();
+                cmd.push_str(&flags);
                 if literal { cmd.push_str(" -l"); }
                 // Quote arguments that contain spaces to preserve them
                 for k in keys { 
@@ -712,6 +785,57 @@ fn run_main() -> io::Result<()> {
                 send_control(cmd)?;
                 return Ok(());
             }
+            // send-bytes - Write raw bytes to a pane (protocol debugging)
+            "send-bytes" => {
+                let mut payload: Option<io::Result<Vec<u8>>> = None;
+                let mut newline = false;
+                let mut expect: Option<String> = None;
+                let mut timeout = "2".to_string();
+                let mut i = 1;
+                while i < cmd_args.len() {
+                    let val = cmd_args.get(i + 1).map(|s| s.to_string()).unwrap_or_default();
+                    match cmd_args[i].as_str() {
+                        "--hex" => {
+                            payload = Some(crate::util::parse_hex_bytes(&val)
+                                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("send-bytes: {}", e))));
+                            i += 1;
+                        }
+                        "--base64" => {
+                            payload = Some(crate::util::base64_decode_bytes(&val)
+                                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "send-bytes: invalid base64 payload")));
+                            i += 1;
+                        }
+                        "--newline" => { newline = true; }
+                        "--expect" => { expect = Some(val); i += 1; }
+                        "--timeout" => { timeout = val; i += 1; }
+                        "-t" => { i += 1; } // consume target value (already handled globally)
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                let bytes = match payload {
+                    Some(Ok(b)) => b,
+                    Some(Err(e)) => { eprintln!("{}", e); return Err(e); }
+                    None => {
+                        eprintln!("usage: send-bytes [-t target] (--hex HEX | --base64 B64) [--newline] [--expect PATTERN [--timeout SECS]]");
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "send-bytes: no payload"));
+                    }
+                };
+                // Carried as base64 whatever the flag, so any length and any byte fits one line
+                let mut cmd = format!("send-bytes --base64 {}", crate::util::base64_encode_bytes(&bytes));
+                if newline { cmd.push_str(" --newline"); }
+                if let Some(p) = &expect {
+                    cmd.push_str(&format!(" --expect {} --timeout {}", crate::util::base64_encode(p), timeout));
+                }
+                cmd.push('\n');
+                let resp = send_control_with_response(cmd)?;
+                if resp.starts_with("send-bytes: ") {
+                    eprint!("{}", resp);
+                    std::process::exit(1);
+                }
+                print!("{}", resp);
+                return Ok(());
+            }
             // select-pane - Select the active pane
             "select-pane" | "selectp" => {
                 let mut cmd = "select-pane".to_string();
@@ -878,8 +1002,7 @@ fn run_main() -> io::Result<()> {
                 // Try to send kill command to server
                 if send_control("kill-session\n".to_string()).is_err() {
                     // Server not responding - clean up stale key file
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let key_path = format!("{}\\.psmux\\{}.key", home, session_name);
+                    let key_path = crate::session::key_file_path(&session_name);
                     let _ = std::fs::remove_file(&key_path);
                 }
                 return Ok(());
@@ -908,12 +1031,11 @@ fn run_main() -> io::Result<()> {
                         t
                     }
                 });
-                if crate::pipe::pipe_exists(&target) {
+                if crate::transport::exists(&target) {
                     std::process::exit(0);
                 } else {
                     // Clean up stale key file if it exists
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let key_path = format!("{}\\.psmux\\{}.key", home, target);
+                    let key_path = crate::session::key_file_path(&target);
                     let _ = std::fs::remove_file(&key_path);
                     std::process::exit(1);
                 }
@@ -1023,6 +1145,7 @@ fn run_main() -> io::Result<()> {
                         }
                         "-d" => { cmd.push_str(" -d"); }
                         "-p" => { cmd.push_str(" -p"); }
+                        "-s" => { cmd.push_str(" -s"); }
                         _ => {}
                     }
                     i += 1;
@@ -1035,9 +1158,11 @@ fn run_main() -> io::Result<()> {
             "set-buffer" | "setb" => {
                 let mut buffer_name: Option<String> = None;
                 let mut data: Option<String> = None;
+                let mut append = false;
                 let mut i = 1;
                 while i < cmd_args.len() {
                     match cmd_args[i].as_str() {
+                        "-a" => { append = true; }
                         "-b" => {
                             if let Some(b) = cmd_args.get(i + 1) {
                                 buffer_name = Some(b.to_string());
@@ -1051,9 +1176,11 @@ fn run_main() -> io::Result<()> {
                     }
                     i += 1;
                 }
+                let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
                 let mut cmd = "set-buffer".to_string();
-                if let Some(b) = buffer_name { cmd.push_str(&format!(" -b {}", b)); }
-                if let Some(d) = data { cmd.push_str(&format!(" {}", d)); }
+                if append { cmd.push_str(" -a"); }
+                if let Some(b) = buffer_name { cmd.push_str(&format!(" -b {}", quote(&b))); }
+                if let Some(d) = data { cmd.push_str(&format!(" {}", quote(&d))); }
                 cmd.push('\n');
                 send_control(cmd)?;
                 return Ok(());
@@ -1134,6 +1261,8 @@ fn run_main() -> io::Result<()> {
                 let mut message: Vec<String> = Vec::new();
                 let mut target: Option<String> = None;
                 let mut print_to_stdout = false;
+                let mut list_all = false;
+                let mut forward_stdin = false;
                 let mut i = 1;
                 while i < cmd_args.len() {
                     match cmd_args[i].as_str() {
@@ -1144,6 +1273,8 @@ fn run_main() -> io::Result<()> {
                             }
                         }
                         "-p" => { print_to_stdout = true; }
+                        "-a" => { list_all = true; print_to_stdout = true; }
+                        "-I" => { forward_stdin = true; }
                         s => { message.push(s.to_string()); }
                     }
                     i += 1;
@@ -1151,6 +1282,14 @@ fn run_main() -> io::Result<()> {
                 let msg = message.join(" ");
                 let mut cmd = "display-message".to_string();
                 if let Some(t) = target { cmd.push_str(&format!(" -t {}", t)); }
+                if forward_stdin {
+                    let mut input = String::new();
+                    io::stdin().read_to_string(&mut input)?;
+                    cmd.push_str(&format!(" -I {}\n", crate::util::base64_encode(&input)));
+                    send_control(cmd)?;
+                    return Ok(());
+                }
+                if list_all { cmd.push_str(" -a"); }
                 if print_to_stdout { cmd.push_str(" -p"); }
                 cmd.push_str(&format!(" {}", msg));
                 cmd.push('\n');
@@ -1175,24 +1314,30 @@ fn run_main() -> io::Result<()> {
                     i += 1;
                 }
                 let shell_cmd = cmd_to_run.join(" ");
-                // Run the command using the system shell
+                let target = env::var("PSMUX_TARGET_SESSION").ok();
+                // With a server, it expands the command's formats and runs it
+                if target.as_deref().map_or(false, crate::transport::exists) {
+                    let escaped = shell_cmd.replace('\\', "\\\\").replace('"', "\\\"");
+                    let line = format!("run-shell{} \"{}\"\n", if background { " -b" } else { "" }, escaped);
+                    if background { send_control(line)?; return Ok(()); }
+                    let resp = send_control_with_response(line)?;
+                    let (text, status) = match resp.trim_end_matches('\n').rsplit_once('\n') {
+                        Some((text, last)) if last.starts_with("%exit ") => (format!("{}\n", text), last),
+                        _ if resp.starts_with("%exit ") => (String::new(), resp.trim_end()),
+                        _ => (resp.clone(), "%exit 0"),
+                    };
+                    io::stdout().write_all(text.as_bytes())?;
+                    std::process::exit(status["%exit ".len()..].trim().parse().unwrap_or(1));
+                }
+                // Run the command using the system shell (bash for plugin scripts)
+                let mut command = crate::config::shell_command(&shell_cmd, target.as_deref());
                 if background {
-                    #[cfg(windows)]
-                    {
-                        let _ = std::process::Command::new("pwsh")
-                            .args(["-NoProfile", "-Command", &shell_cmd])
-                            .spawn();
-                    }
+                    let _ = command.spawn();
                 } else {
-                    #[cfg(windows)]
-                    {
-                        let output = std::process::Command::new("pwsh")
-                            .args(["-NoProfile", "-Command", &shell_cmd])
-                            .output()?;
-                        io::stdout().write_all(&output.stdout)?;
-                        io::stderr().write_all(&output.stderr)?;
-                        std::process::exit(output.status.code().unwrap_or(0));
-                    }
+                    let output = command.output()?;
+                    io::stdout().write_all(&output.stdout)?;
+                    io::stderr().write_all(&output.stderr)?;
+                    std::process::exit(output.status.code().unwrap_or(0));
                 }
                 return Ok(());
             }
@@ -1285,8 +1430,8 @@ fn run_main() -> io::Result<()> {
                 send_control(cmd)?;
                 return Ok(());
             }
-            // join-pane - Join a pane to another window
-            "join-pane" | "joinp" => {
+            // join-pane / move-pane - Move a pane next to another
+            "join-pane" | "joinp" | "move-pane" | "movep" => {
                 let mut cmd = "join-pane".to_string();
                 let mut i = 1;
                 while i < cmd_args.len() {
@@ -1294,24 +1439,36 @@ fn run_main() -> io::Result<()> {
                         "-h" => { cmd.push_str(" -h"); }
                         "-v" => { cmd.push_str(" -v"); }
                         "-d" => { cmd.push_str(" -d"); }
-                        "-s" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -s {}", t));
-                                i += 1;
-                            }
-                        }
-                        "-t" => {
+                        flag @ ("-s" | "-t" | "-l" | "-p") => {
                             if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -t {}", t));
+                                cmd.push_str(&format!(" {} {}", flag, t));
                                 i += 1;
                             }
                         }
+                        s if !s.starts_with('-') => { cmd.push_str(&format!(" {}", s)); }
                         _ => {}
                     }
                     i += 1;
                 }
                 cmd.push('\n');
-                send_control(cmd)?;
+                // The server answers with an error or nothing
+                let resp = send_control_with_response(cmd)?;
+                if !resp.is_empty() {
+                    eprint!("{}", resp);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            // save-session / restore-session [snapshot] - Session snapshots
+            "save-session" | "restore-session" => {
+                let mut line = cmd.to_string();
+                if let Some(name) = cmd_args.iter().skip(1).find(|a| !a.starts_with('-')) { line.push_str(&format!(" \"{}\"", name)); }
+                line.push('\n');
+                let resp = send_control_with_response(line)?;
+                if !resp.is_empty() {
+                    eprint!("{}", resp);
+                    std::process::exit(1);
+                }
                 return Ok(());
             }
             // rename-window - Rename current window
@@ -1319,7 +1476,12 @@ fn run_main() -> io::Result<()> {
                 // cmd_args[0] is the command, cmd_args[1] should be the new name
                 if let Some(name) = cmd_args.get(1) {
                     if !name.starts_with('-') {
-                        send_control(format!("rename-window {}\n", name))?;
+                        // The server answers with an error (a duplicate name) or nothing
+                        let resp = send_control_with_response(format!("rename-window {}\n", name))?;
+                        if !resp.is_empty() {
+                            eprint!("{}", resp);
+                            std::process::exit(1);
+                        }
                     }
                 }
                 return Ok(());
@@ -1414,86 +1576,29 @@ fn run_main() -> io::Result<()> {
                 return Ok(());
             }
             // show-options / show / show-window-options / showw - Show options
-            "show-options" | "show" | "show-window-options" | "showw" => {
+            "show-options" | "show" | "show-option" | "show-window-options" | "show-window-option" | "showw" => {
                 let cmd_str: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
                 let resp = send_control_with_response(format!("{}\n", cmd_str))?;
                 print!("{}", resp);
                 return Ok(());
             }
-            // if-shell - Conditional execution
+            // if-shell - Conditional execution.  The server expands the
+            // condition's formats and runs the chosen branch's commands.
             "if-shell" | "if" => {
-                let mut _background = false;
-                let mut condition: Option<String> = None;
-                let mut cmd_true: Option<String> = None;
-                let mut cmd_false: Option<String> = None;
-                let mut format_mode = false;
+                let mut line = "if-shell".to_string();
                 let mut i = 1;
-                
                 while i < cmd_args.len() {
                     match cmd_args[i].as_str() {
-                        "-b" => { _background = true; }
-                        "-F" => { format_mode = true; }
-                        "-t" => { i += 1; } // Skip target
-                        s if !s.starts_with('-') => {
-                            if condition.is_none() {
-                                condition = Some(s.to_string());
-                            } else if cmd_true.is_none() {
-                                cmd_true = Some(s.to_string());
-                            } else if cmd_false.is_none() {
-                                cmd_false = Some(s.to_string());
-                            }
-                        }
-                        _ => {}
+                        "-t" => { i += 1; } // Skip target (sent as TARGET)
+                        a if a.starts_with('-') && a.len() > 1 => { line.push(' '); line.push_str(a); }
+                        a => { line.push_str(&format!(" \"{}\"", a.replace('\\', "\\\\").replace('"', "\\\""))); }
                     }
                     i += 1;
                 }
-                
-                if let (Some(cond), Some(true_cmd)) = (condition, cmd_true) {
-                    let success = if format_mode {
-                        // Treat condition as format string - non-empty and non-zero is true
-                        !cond.is_empty() && cond != "0"
-                    } else {
-                        // Run shell command - suppress stdout/stderr so it doesn't leak to terminal
-                        #[cfg(windows)]
-                        {
-                            std::process::Command::new("pwsh")
-                                .args(["-NoProfile", "-Command", &cond])
-                                .stdout(std::process::Stdio::null())
-                                .stderr(std::process::Stdio::null())
-                                .status()
-                                .map(|s| s.success())
-                                .unwrap_or(false)
-                        }
-                        #[cfg(not(windows))]
-                        {
-                            std::process::Command::new("sh")
-                                .args(["-c", &cond])
-                                .stdout(std::process::Stdio::null())
-                                .stderr(std::process::Stdio::null())
-                                .status()
-                                .map(|s| s.success())
-                                .unwrap_or(false)
-                        }
-                    };
-                    
-                    let cmd_to_run = if success { Some(true_cmd) } else { cmd_false };
-                    
-                    if let Some(cmd) = cmd_to_run {
-                        // Re-quote multi-word arguments for TCP transport
-                        let needs_quoting = cmd.contains(' ');
-                        let tcp_cmd = if needs_quoting {
-                            // The command string may contain spaces (e.g. "display-message -p hello")
-                            // Send it as-is since it's already a full command line
-                            format!("{}\n", cmd)
-                        } else {
-                            format!("{}\n", cmd)
-                        };
-                        // Use send_control_with_response to capture any output from the chosen command
-                        let resp = send_control_with_response(tcp_cmd)?;
-                        if !resp.is_empty() {
-                            print!("{}", resp);
-                        }
-                    }
+                line.push('\n');
+                let resp = send_control_with_response(line)?;
+                if !resp.is_empty() {
+                    print!("{}", resp);
                 }
                 return Ok(());
             }
@@ -1630,6 +1735,8 @@ fn run_main() -> io::Result<()> {
             }
             // switch-client - Switch the current client to another session
             "switch-client" | "switchc" => {
+                // The global -t named the session to switch to
+                let target = crate::session::retarget_to_client_server();
                 let mut cmd = "switch-client".to_string();
                 let mut i = 1;
                 while i < cmd_args.len() {
@@ -1638,15 +1745,9 @@ fn run_main() -> io::Result<()> {
                         "-n" => { cmd.push_str(" -n"); }
                         "-p" => { cmd.push_str(" -p"); }
                         "-r" => { cmd.push_str(" -r"); }
-                        "-c" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -c {}", t));
-                                i += 1;
-                            }
-                        }
-                        "-t" => {
+                        "-c" | "-T" => {
                             if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -t {}", t));
+                                cmd.push_str(&format!(" {} {}", cmd_args[i], t));
                                 i += 1;
                             }
                         }
@@ -1654,8 +1755,13 @@ fn run_main() -> io::Result<()> {
                     }
                     i += 1;
                 }
+                if let Some(t) = target { cmd.push_str(&format!(" -t \"{}\"", t)); }
                 cmd.push('\n');
-                send_control(cmd)?;
+                let resp = send_control_with_response(cmd)?;
+                if !resp.trim().is_empty() {
+                    eprint!("{}", resp);
+                    std::process::exit(1);
+                }
                 return Ok(());
             }
             // copy-mode - Enter copy mode
@@ -1821,8 +1927,8 @@ fn run_main() -> io::Result<()> {
                 return Ok(());
             }
             // clear-history - Clear pane history
-            "clear-history" | "clearhist" => {
-                let mut cmd = "clear-history".to_string();
+            "clear-history" | "clearhist" | "clear-screen-and-history" => {
+                let mut cmd = if cmd_args[0] == "clear-screen-and-history" { cmd_args[0].to_string() } else { "clear-history".to_string() };
                 let mut i = 1;
                 while i < cmd_args.len() {
                     match cmd_args[i].as_str() {
@@ -1913,55 +2019,35 @@ fn run_main() -> io::Result<()> {
             }
             // command-prompt - Open interactive command prompt
             "command-prompt" => {
-                let mut cmd = "command-prompt".to_string();
-                let mut i = 1;
-                while i < cmd_args.len() {
-                    match cmd_args[i].as_str() {
-                        "-I" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -I {}", t));
-                                i += 1;
-                            }
-                        }
-                        "-p" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -p {}", t));
-                                i += 1;
-                            }
-                        }
-                        "-1" => { cmd.push_str(" -1"); }
-                        "-N" => { cmd.push_str(" -N"); }
-                        "-W" => { cmd.push_str(" -W"); }
-                        "-T" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -T {}", t));
-                                i += 1;
-                            }
-                        }
-                        "-t" => {
-                            if let Some(t) = cmd_args.get(i + 1) {
-                                cmd.push_str(&format!(" -t {}", t));
-                                i += 1;
-                            }
-                        }
-                        _ => {}
-                    }
-                    i += 1;
+                // Quote every word but the flags: prompts, values and the template have spaces
+                let mut line = "command-prompt".to_string();
+                for a in cmd_args.iter().skip(1) {
+                    if a.len() == 2 && a.starts_with('-') { line.push(' '); line.push_str(a); }
+                    else { line.push_str(&format!(" \"{}\"", a.replace('\\', "\\\\").replace('"', "\\\""))); }
                 }
-                cmd.push('\n');
-                send_control(cmd)?;
+                send_control(format!("{}\n", line))?;
                 return Ok(());
             }
             // display-menu - Display a menu
             "display-menu" | "menu" => {
-                let joined: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
-                send_control(format!("{}\n", joined))?;
+                // Quote every word but the flags: names have spaces, and "" is a separator
+                let mut line = "display-menu".to_string();
+                for a in cmd_args.iter().skip(1) {
+                    if a.len() == 2 && a.starts_with('-') { line.push(' '); line.push_str(a); }
+                    else { line.push_str(&format!(" \"{}\"", a.replace('\\', "\\\\").replace('"', "\\\""))); }
+                }
+                send_control(format!("{}\n", line))?;
                 return Ok(());
             }
             // display-popup - Display a popup window
             "display-popup" | "popup" => {
-                let joined: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
-                send_control(format!("{}\n", joined))?;
+                // Quote arguments so a -d directory or the command keeps its spaces
+                let mut line = "display-popup".to_string();
+                for a in cmd_args.iter().skip(1) {
+                    if a.starts_with('-') && a.len() > 1 { line.push(' '); line.push_str(a); }
+                    else { line.push_str(&format!(" \"{}\"", a.replace('\\', "\\\\").replace('"', "\\\""))); }
+                }
+                send_control(format!("{}\n", line))?;
                 return Ok(());
             }
             // server-info - Show server information
@@ -1978,8 +2064,13 @@ fn run_main() -> io::Result<()> {
             }
             // confirm-before - Ask for confirmation before running a command
             "confirm-before" | "confirm" => {
-                let joined: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
-                send_control(format!("{}\n", joined))?;
+                // Quote every word but the flags so the -p prompt keeps its spaces
+                let mut line = "confirm-before".to_string();
+                for a in cmd_args.iter().skip(1) {
+                    if a.len() == 2 && a.starts_with('-') { line.push(' '); line.push_str(a); }
+                    else { line.push_str(&format!(" \"{}\"", a.replace('\\', "\\\\").replace('"', "\\\""))); }
+                }
+                send_control(format!("{}\n", line))?;
                 return Ok(());
             }
             // refresh-client - Refresh the client display
@@ -1990,6 +2081,12 @@ fn run_main() -> io::Result<()> {
                     match cmd_args[i].as_str() {
                         "-S" => { cmd.push_str(" -S"); }
                         "-l" => { cmd.push_str(" -l"); }
+                        "-f" => {
+                            if let Some(f) = cmd_args.get(i + 1) {
+                                cmd.push_str(&format!(" -f {}", f));
+                                i += 1;
+                            }
+                        }
                         "-C" => {
                             if let Some(t) = cmd_args.get(i + 1) {
                                 cmd.push_str(&format!(" -C {}", t));
@@ -2023,9 +2120,9 @@ fn run_main() -> io::Result<()> {
                 }
                 return Ok(());
             }
-            // suspend-client - Suspend client (no-op on Windows)
+            // suspend-client - The attached client drops to its parent shell
             "suspend-client" | "suspendc" => {
-                // No-op on Windows — no SIGTSTP concept
+                send_control("suspend-client\n".to_string())?;
                 return Ok(());
             }
             // lock-client / lock-server / lock-session (no-op on Windows)
@@ -2033,9 +2130,10 @@ fn run_main() -> io::Result<()> {
                 // No-op on Windows — no terminal locking concept
                 return Ok(());
             }
-            // resize-window - Resize window (no-op on Windows)
+            // resize-window - Set the window size used without a client
             "resize-window" | "resizew" => {
-                // On Windows, window size is controlled by the terminal emulator
+                let joined: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
+                send_control(format!("{}\n", joined))?;
                 return Ok(());
             }
             // customize-mode - tmux 3.2+ customize mode (stub)
@@ -2043,11 +2141,20 @@ fn run_main() -> io::Result<()> {
                 // Stub for compatibility
                 return Ok(());
             }
-            // choose-client - List clients interactively
-            "choose-client" => {
-                // Single-client model — returns current client info
-                let resp = send_control_with_response("list-clients\n".to_string())?;
-                print!("{}", resp);
+            // detach-client [-a] [-t client] / choose-client [template] -
+            // act on the attached clients.  The server answers with an
+            // error or nothing
+            "detach-client" | "detach" | "choose-client" => {
+                let mut cmd_str: String = cmd_args.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(" ");
+                // -t is a client here, not a session
+                if let Some(client) = crate::session::retarget_to_client_server() {
+                    cmd_str.push_str(&format!(" -t {}", client));
+                }
+                let resp = send_control_with_response(format!("{}\n", cmd_str))?;
+                if !resp.is_empty() {
+                    eprint!("{}", resp);
+                    std::process::exit(1);
+                }
                 return Ok(());
             }
             // respawn-window - Respawn active pane in window
@@ -2078,20 +2185,47 @@ fn run_main() -> io::Result<()> {
     // Default behavior: If no PSMUX_REMOTE_ATTACH is set and no specific command matched,
     // we need to either attach to an existing session or create a new one.
     // This ensures sessions persist after detach.
+    // With --no-daemon the server runs on a thread of this process instead,
+    // through the same handlers and pipe, and goes when the client does.
     if env::var("PSMUX_REMOTE_ATTACH").ok().as_deref() != Some("1") {
-        let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-        let session_name = env::var("PSMUX_SESSION_NAME").unwrap_or_else(|_| "default".to_string());
-        let key_path = format!("{}\\.psmux\\{}.key", home, session_name);
+        // PSMUX_SESSION_NAME is already a port file base; otherwise the
+        // "default" session goes under the -L namespace
+        let (base_name, socket_name) = match env::var("PSMUX_SESSION_NAME") {
+            Ok(base) => (base, None),
+            Err(_) => ("default".to_string(), l_socket_name.clone()),
+        };
+        let session_name = match socket_name {
+            Some(ref l) => format!("{}__{}", l, base_name),
+            None => base_name.clone(),
+        };
+        let key_path = crate::session::key_file_path(&session_name);
 
         // Check if named pipe exists (server is actually alive)
-        let server_alive = crate::pipe::pipe_exists(&session_name);
+        let server_alive = crate::transport::exists(&session_name);
 
-        if !server_alive {
+        if no_daemon {
+            if server_alive {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                    format!("--no-daemon: session {} is already running", session_name)));
+            }
+            let _ = std::fs::remove_file(&key_path);
+            in_process_server = Some((session_name.clone(), crate::server::spawn_in_process(base_name, socket_name, None, None)));
+            for _ in 0..500 {
+                if std::path::Path::new(&key_path).exists() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        } else if !server_alive {
             // Clean up stale key file if it exists
             let _ = std::fs::remove_file(&key_path);
             // No existing session - create one in background
             let exe = std::env::current_exe().unwrap_or_else(|_| std::path::PathBuf::from("psmux"));
-            let server_args: Vec<String> = vec!["server".into(), "-s".into(), session_name.clone()];
+            let mut server_args: Vec<String> = vec!["server".into(), "-s".into(), base_name];
+            if let Some(l) = socket_name {
+                server_args.push("-L".into());
+                server_args.push(l);
+            }
             #[cfg(windows)]
             crate::platform::spawn_server_hidden(&exe, &server_args)?;
             #[cfg(not(windows))]
@@ -2124,6 +2258,20 @@ fn run_main() -> io::Result<()> {
     }
     env::set_var("PSMUX_ACTIVE", "1");
 
+    // Attach before touching the terminal, so a server that never answers
+    // leaves it as it was
+    let mut attached = Some(match crate::client::attach_handshake() {
+        Ok(a) => a,
+        Err(e) => {
+            if let Some((session_name, server)) = in_process_server {
+                env::set_var("PSMUX_TARGET_SESSION", &session_name);
+                let _ = send_control("kill-server\n".to_string());
+                let _ = server.join();
+            }
+            return Err(e);
+        }
+    });
+
     let mut stdout = io::stdout();
     enable_virtual_terminal_processing();
     enable_raw_mode()?;
@@ -2147,15 +2295,19 @@ fn run_main() -> io::Result<()> {
 
     // Loop to handle session switching without spawning new processes
     let result = loop {
-        let result = run_remote(&mut terminal, &input);
+        let result = match attached.take().map_or_else(crate::client::attach_handshake, Ok) {
+            Ok(a) => run_remote(&mut terminal, &input, a),
+            Err(e) => Err(e),
+        };
         
         // Check if we should switch to another session
         if let Ok(switch_to) = env::var("PSMUX_SWITCH_TO") {
             env::remove_var("PSMUX_SWITCH_TO");
+            // Where switch-client -l goes back to
+            if let Ok(from) = env::var("PSMUX_SESSION_NAME") { env::set_var("PSMUX_LAST_SESSION", from); }
             env::set_var("PSMUX_SESSION_NAME", &switch_to);
             // Update last_session file
-            let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-            let last_path = format!("{}\\.psmux\\last_session", home);
+            let last_path = format!("{}\\last_session", crate::session::psmux_dir());
             let _ = std::fs::write(&last_path, &switch_to);
             // Continue loop to attach to new session
             continue;
@@ -2178,5 +2330,11 @@ fn run_main() -> io::Result<()> {
     let _ = execute!(out, crossterm::style::Print("\x1b[0 q"));
     let _ = execute!(out, DisableBlinking, DisableMouseCapture, DisableBracketedPaste, LeaveAlternateScreen);
     let _ = terminal.show_cursor();
+    if let Some((session_name, server)) = in_process_server {
+        env::set_var("PSMUX_TARGET_SESSION", &session_name);
+        env::remove_var("PSMUX_TARGET_FULL");
+        let _ = send_control("kill-server\n".to_string());
+        let _ = server.join();
+    }
     result
 }
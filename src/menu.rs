@@ -0,0 +1,577 @@
+//! Menus: display-menu and the context menus the mouse opens.
+//!
+//! Item names and commands are formats, expanded when the menu opens for
+//! the window it is about (its active pane for `pane_*`).  As in tmux, an
+//! item whose name expands to start with `-` is shown greyed out and cannot
+//! be chosen, and an empty name is a separator.  `{marked}` in a command
+//! becomes the marked pane (`%N`).
+//!
+//! The server sends the current client a `%menu` directive with the
+//! expanded rows; the client draws the menu over the panes and sends the
+//! chosen item's command back like any other.
+//!
+//! An item whose command is another `display-menu` is a submenu: the
+//! client opens it beside its parent (Right, Enter, its key or a click)
+//! and Left goes back.  `-x`/`-y` take a number or one of tmux's
+//! positions (see [`Place`]).
+//!
+//! A right-click on a window's tab opens `WINDOW_MENU` for it, and one on
+//! a pane opens `PANE_MENU`, focusing the pane first.  With
+//! `@pane-right-click paste` a pane's right-click pastes instead (and
+//! copies a selection), and Ctrl+right-click opens the menu.
+
+use crossterm::event::KeyCode;
+use ratatui::layout::{Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+use crate::types::{AppState, Menu};
+
+/// An item of a built-in menu: name, key and command, all formats.
+pub type Item = (&'static str, &'static str, &'static str);
+
+/// Right-click on a pane.  The marked-pane items are enabled while a
+/// pane other than this one is marked.
+pub const PANE_MENU: &[Item] = &[
+    ("Horizontal Split", "h", "split-window -h -t #{pane_id}"),
+    ("Vertical Split", "v", "split-window -v -t #{pane_id}"),
+    ("", "", ""),
+    ("#{?#{>:#{window_panes},1},,-}Swap Up", "u", "swap-pane -U -t #{pane_id}"),
+    ("#{?#{>:#{window_panes},1},,-}Swap Down", "d", "swap-pane -D -t #{pane_id}"),
+    ("#{?pane_marked,Unmark,Mark} this pane", "m", "select-pane -#{?pane_marked,M,m} -t #{pane_id}"),
+    ("#{?pane_marked,-,#{?pane_marked_set,,-}}Swap with marked", "s", "swap-pane -t {marked}"),
+    ("#{?pane_marked,-,#{?pane_marked_set,,-}}Join marked pane here (horizontal)", "j", "join-pane -h -s {marked} -t #{pane_id}"),
+    ("#{?pane_marked,-,#{?pane_marked_set,,-}}Join marked pane here (vertical)", "J", "join-pane -v -s {marked} -t #{pane_id}"),
+    ("", "", ""),
+    ("#{?window_zoomed_flag,Unzoom,Zoom}", "z", "resize-pane -Z -t #{pane_id}"),
+    ("Respawn", "R", "respawn-pane -k -t #{pane_id}"),
+    ("Kill", "X", "kill-pane -t #{pane_id}"),
+];
+
+/// Right-click on a window's tab.
+pub const WINDOW_MENU: &[Item] = &[
+    ("Select", "s", "select-window -t :#{window_index}"),
+    ("#{?pane_marked,-,#{?pane_marked_set,,-}}Move marked pane to this window", "m", "join-pane -s {marked} -t :#{window_index}"),
+    ("", "", ""),
+    ("Kill", "X", "kill-window -t :#{window_index}"),
+];
+
+/// Where display-menu's `-x` or `-y` puts a menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    /// A column or row
+    At(u16),
+    /// `C`: centred
+    Centre,
+    /// `R`: the right edge (or the bottom) of the window
+    Right,
+    /// `P`: the active pane's left edge, or its bottom
+    Pane,
+    /// `M`: where the mouse was last pressed
+    Mouse,
+    /// `W`: the active window's tab on the status line
+    Window,
+    /// `S`: the status line
+    Status,
+}
+
+impl Place {
+    pub fn parse(s: &str) -> Option<Place> {
+        Some(match s {
+            "C" => Place::Centre,
+            "R" => Place::Right,
+            "P" => Place::Pane,
+            "M" => Place::Mouse,
+            "W" => Place::Window,
+            "S" => Place::Status,
+            n => Place::At(n.parse().ok()?),
+        })
+    }
+
+    /// The column (`x`) or row this stands for, in server coordinates;
+    /// None to centre.
+    fn resolve(self, app: &mut AppState, x: bool) -> Option<u16> {
+        let (width, status_row) = crate::tree::window_size(app);
+        let pick = |(a, b): (u16, u16)| if x { a } else { b };
+        match self {
+            Place::At(n) => Some(n),
+            Place::Centre => None,
+            Place::Right => Some(pick((width, status_row))),
+            Place::Mouse => Some(pick(app.mouse_at)),
+            Place::Status => Some(pick((0, status_row))),
+            Place::Pane => {
+                let win = app.windows.get(app.active_idx)?;
+                let mut rects = Vec::new();
+                crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
+                let (_, r) = rects.into_iter().find(|(p, _)| *p == win.active_path)?;
+                Some(pick((r.x, r.y + r.height)))
+            }
+            Place::Window => {
+                crate::window_ops::update_tab_positions(app);
+                let tab = app.tab_positions.iter().find(|&&(w, _, _)| w == app.active_idx).map_or(0, |&(_, start, _)| start);
+                Some(pick((tab, status_row)))
+            }
+        }
+    }
+}
+
+/// One row of a menu as the client shows it; an empty name is a separator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Row {
+    pub name: String,
+    #[serde(default)]
+    pub key: Option<char>,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub enabled: bool,
+    /// Opened beside the menu instead of running `command`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submenu: Option<Box<Shown>>,
+}
+
+/// A menu ready to show: title, rows, and where to put it (server
+/// coordinates, None to centre it).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Shown {
+    pub title: String,
+    pub rows: Vec<Row>,
+    #[serde(default)]
+    pub x: Option<u16>,
+    #[serde(default)]
+    pub y: Option<u16>,
+}
+
+/// Expand `items` for window `win_idx`.
+pub fn rows<'a>(app: &AppState, win_idx: usize, items: impl IntoIterator<Item = (&'a str, Option<char>, &'a str)>) -> Vec<Row> {
+    let marked = crate::tree::marked_pane(app).map(|(_, id)| format!("%{}", id));
+    items.into_iter().map(|(name, key, command)| {
+        if name.is_empty() || name == "-" {
+            return Row { name: String::new(), key: None, command: String::new(), enabled: false, submenu: None };
+        }
+        let name = crate::format::expand_format_for_window(name, app, win_idx);
+        match name.strip_prefix('-') {
+            Some(rest) => Row { name: rest.to_string(), key, command: String::new(), enabled: false, submenu: None },
+            None => {
+                let mut command = crate::format::expand_format_for_window(command, app, win_idx);
+                if let Some(m) = &marked { command = command.replace("{marked}", m); }
+                Row { name, key, command, enabled: true, submenu: None }
+            }
+        }
+    }).collect()
+}
+
+fn builtin(app: &AppState, win_idx: usize, title: &str, items: &[Item], at: (u16, u16)) -> Shown {
+    Shown {
+        title: crate::format::expand_format_for_window(title, app, win_idx),
+        rows: rows(app, win_idx, items.iter().map(|&(n, k, c)| (n, k.chars().next(), c))),
+        x: Some(at.0),
+        y: Some(at.1),
+    }
+}
+
+/// The pane menu for the active pane of window `win_idx`.
+pub fn pane_menu(app: &AppState, win_idx: usize, at: (u16, u16)) -> Shown {
+    builtin(app, win_idx, "#{pane_index} (#{pane_id})", PANE_MENU, at)
+}
+
+/// The menu for window `win_idx`'s tab.
+pub fn window_menu(app: &AppState, win_idx: usize, at: (u16, u16)) -> Shown {
+    builtin(app, win_idx, "#{window_index}:#{window_name}", WINDOW_MENU, at)
+}
+
+/// A display-menu definition, expanded for the current window, with its
+/// position worked out and its submenus with it.
+pub fn from_definition(app: &mut AppState, menu: &Menu) -> Shown {
+    let items = menu.items.iter().map(|i| (if i.is_separator { "" } else { i.name.as_str() }, i.key, i.command.as_str()));
+    let mut rows = rows(app, app.active_idx, items);
+    for (row, item) in rows.iter_mut().zip(&menu.items) {
+        if let (true, Some(sub)) = (row.enabled, &item.submenu) {
+            row.submenu = Some(Box::new(from_definition(app, sub)));
+        }
+    }
+    Shown {
+        title: crate::format::expand_format(&menu.title, app),
+        rows,
+        x: menu.x.and_then(|p| p.resolve(app, true)),
+        y: menu.y.and_then(|p| p.resolve(app, false)),
+    }
+}
+
+/// The `%menu` directive that shows `menu` on a client.
+pub fn directive(menu: &Shown) -> String {
+    format!("%menu {}", serde_json::to_string(menu).unwrap_or_default())
+}
+
+/// Show `menu` on the current client.
+pub fn show(app: &AppState, menu: &Shown) -> Result<(), String> {
+    if menu.rows.is_empty() { return Ok(()); }
+    let client = crate::bandwidth::current_client(app).ok_or_else(|| "no current client".to_string())?;
+    client.send_directive(directive(menu));
+    Ok(())
+}
+
+/// The menu a right-click at `x`,`y` opens: a tab's window menu, or the
+/// menu of the pane under the pointer, which is focused.  A pane whose
+/// application asked for the mouse gets the click instead, as in tmux.
+pub fn at_pointer(app: &mut AppState, x: u16, y: u16) -> Option<Shown> {
+    if app.windows.is_empty() { return None; }
+    crate::window_ops::update_tab_positions(app);
+    let status_row = crate::tree::window_size(app).1;
+    if y == status_row {
+        let &(win_idx, _, _) = app.tab_positions.iter().find(|&&(w, start, end)| x >= start && x < end && w < app.windows.len())?;
+        return Some(window_menu(app, win_idx, (x, y)));
+    }
+    let win = &mut app.windows[app.active_idx];
+    let mut rects = Vec::new();
+    crate::tree::compute_rects(&win.root, app.last_window_area, &mut rects);
+    let (path, _) = rects.into_iter().find(|(_, r)| r.contains(ratatui::layout::Position { x, y }))?;
+    if path != win.active_path {
+        app.last_pane_path = std::mem::replace(&mut win.active_path, path);
+    }
+    if crate::window_ops::right_click_to_app(app, x, y) { return None; }
+    Some(pane_menu(app, app.active_idx, (x, y)))
+}
+
+/// What a key or click does to an open menu.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Stay,
+    Close,
+    Run(String),
+}
+
+/// A menu open on the client, with the submenu open beside it.
+pub struct Open {
+    pub menu: Shown,
+    pub selected: usize,
+    pub sub: Option<Box<Open>>,
+    /// Where it was last drawn (client coordinates)
+    rect: Rect,
+}
+
+impl Open {
+    pub fn new(menu: Shown) -> Self {
+        let selected = menu.rows.iter().position(|r| r.enabled).unwrap_or(0);
+        Open { menu, selected, sub: None, rect: Rect::default() }
+    }
+
+    /// Move the selection to the next enabled row up (`-1`) or down (`1`).
+    fn step(&mut self, dir: isize) {
+        let n = self.menu.rows.len() as isize;
+        let mut i = self.selected as isize;
+        for _ in 0..n {
+            i = (i + dir).rem_euclid(n);
+            if self.menu.rows[i as usize].enabled { self.selected = i as usize; return; }
+        }
+    }
+
+    /// Choose row `i`: open its submenu or run its command.
+    fn choose(&mut self, i: usize) -> Outcome {
+        let Some(r) = self.menu.rows.get(i).filter(|r| r.enabled) else { return Outcome::Stay };
+        self.selected = i;
+        match &r.submenu {
+            Some(sub) => { self.sub = Some(Box::new(Open::new((**sub).clone()))); Outcome::Stay }
+            None => Outcome::Run(r.command.clone()),
+        }
+    }
+
+    pub fn key(&mut self, code: KeyCode) -> Outcome {
+        if let Some(sub) = self.sub.as_mut() {
+            if code == KeyCode::Left && sub.sub.is_none() {
+                self.sub = None;
+                return Outcome::Stay;
+            }
+            return sub.key(code);
+        }
+        if let KeyCode::Char(c) = code {
+            if let Some(i) = self.menu.rows.iter().position(|r| r.key == Some(c)) { return self.choose(i); }
+        }
+        match code {
+            KeyCode::Esc | KeyCode::Char('q') => Outcome::Close,
+            KeyCode::Up | KeyCode::Char('k') => { self.step(-1); Outcome::Stay }
+            KeyCode::Down | KeyCode::Char('j') => { self.step(1); Outcome::Stay }
+            KeyCode::Enter => self.choose(self.selected),
+            KeyCode::Right if self.menu.rows.get(self.selected).is_some_and(|r| r.submenu.is_some()) => self.choose(self.selected),
+            _ => Outcome::Stay,
+        }
+    }
+
+    /// Whether `x`,`y` is on this menu or an open submenu.
+    fn contains(&self, x: u16, y: u16) -> bool {
+        self.rect.contains(Position { x, y }) || self.sub.as_ref().is_some_and(|s| s.contains(x, y))
+    }
+
+    /// The row at `y`, if `x`,`y` is on one of this menu's rows.
+    fn row_at(&self, x: u16, y: u16) -> Option<usize> {
+        let r = self.rect;
+        if !r.contains(Position { x, y }) || y <= r.y || y + 1 >= r.bottom() { return None; }
+        Some((y - r.y - 1) as usize)
+    }
+
+    /// The pointer moved to `x`,`y`: select the enabled row under it.
+    /// Returns whether the selection changed.
+    pub fn hover(&mut self, x: u16, y: u16) -> bool {
+        if let Some(sub) = self.sub.as_mut() {
+            if sub.contains(x, y) { return sub.hover(x, y); }
+        }
+        match self.row_at(x, y) {
+            Some(i) if i != self.selected && self.menu.rows[i].enabled => {
+                self.selected = i;
+                self.sub = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A click at `x`,`y`: on a row it chooses it, anywhere off the
+    /// menus it closes them.
+    pub fn click(&mut self, x: u16, y: u16) -> Outcome {
+        if let Some(sub) = self.sub.as_mut() {
+            if sub.contains(x, y) { return sub.click(x, y); }
+        }
+        if !self.rect.contains(Position { x, y }) { return Outcome::Close; }
+        match self.row_at(x, y) {
+            Some(i) => { self.sub = None; self.choose(i) }
+            None => Outcome::Stay,
+        }
+    }
+
+    /// The right-hand side of row `r`: its key, and a mark for a submenu.
+    fn suffix(r: &Row) -> String {
+        let key = r.key.map(|k| format!("({})", k)).unwrap_or_default();
+        if r.submenu.is_some() { format!("{} >", key) } else { key }
+    }
+
+    fn size(&self, area: Rect) -> (u16, u16) {
+        let inner = self.menu.rows.iter()
+            .map(|r| r.name.width() + Self::suffix(r).width() + 3)
+            .chain([self.menu.title.width() + 2])
+            .max().unwrap_or(0);
+        ((inner as u16 + 2).min(area.width), (self.menu.rows.len() as u16 + 2).min(area.height))
+    }
+
+    /// Lay the menu out in `area` at `x`,`y` (client coordinates, None to
+    /// centre on that axis), moved up or left to fit, and any submenu
+    /// beside the row that opened it: to the right if it fits, else to
+    /// the left.
+    pub fn layout(&mut self, area: Rect, x: Option<u16>, y: Option<u16>) {
+        let (w, h) = self.size(area);
+        let x = match x {
+            Some(x) => x.min(area.right() - w).max(area.x),
+            None => area.x + (area.width - w) / 2,
+        };
+        let y = match y {
+            Some(y) if y + h > area.bottom() => y.saturating_sub(h).max(area.y),
+            Some(y) => y,
+            None => area.y + (area.height - h) / 2,
+        };
+        self.rect = Rect::new(x, y, w, h);
+        let parent = self.rect;
+        let row = parent.y + 1 + self.selected as u16;
+        if let Some(sub) = self.sub.as_mut() {
+            let (sw, sh) = sub.size(area);
+            let sx = if parent.right() + sw <= area.right() { parent.right() } else { parent.x.saturating_sub(sw).max(area.x) };
+            let sy = if row.saturating_sub(1) + sh > area.bottom() { area.bottom().saturating_sub(sh) } else { row.saturating_sub(1) };
+            sub.layout(area, Some(sx), Some(sy));
+        }
+    }
+
+    /// The rows as lines `width` cells wide: name, then the key (and `>`
+    /// for a submenu) at the right edge.
+    pub fn lines(&self, width: u16) -> Vec<(String, bool, bool)> {
+        let width = width as usize;
+        self.menu.rows.iter().enumerate().map(|(i, r)| {
+            if r.name.is_empty() { return ("─".repeat(width), false, false); }
+            let suffix = Self::suffix(r);
+            let pad = width.saturating_sub(r.name.width() + suffix.width() + 2);
+            (format!(" {}{}{} ", r.name, " ".repeat(pad), suffix), r.enabled, i == self.selected)
+        }).collect()
+    }
+
+    /// Draw the menu and its submenus over `area` (see [`Open::layout`]).
+    pub fn render(&mut self, f: &mut Frame, area: Rect, x: Option<u16>, y: Option<u16>) {
+        self.layout(area, x, y);
+        self.draw(f);
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let overlay = Block::default().borders(Borders::ALL).title(self.menu.title.as_str());
+        f.render_widget(Clear, self.rect);
+        f.render_widget(&overlay, self.rect);
+        let inner = overlay.inner(self.rect);
+        let lines: Vec<Line> = self.lines(inner.width).into_iter().map(|(text, enabled, selected)| {
+            let style = if !enabled { Style::default().fg(Color::DarkGray) }
+                else if selected { Style::default().add_modifier(Modifier::REVERSED) }
+                else { Style::default() };
+            Line::styled(text, style)
+        }).collect();
+        f.render_widget(Paragraph::new(lines), inner);
+        if let Some(sub) = &self.sub { sub.draw(f); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> AppState {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        app.active_idx = 0;
+        app.windows[0].active_path = vec![0];
+        app
+    }
+
+    fn item<'a>(menu: &'a Shown, name: &str) -> &'a Row {
+        menu.rows.iter().find(|r| r.name == name).unwrap_or_else(|| panic!("no {name:?} in {:?}", menu.rows))
+    }
+
+    fn mark(app: &mut AppState, id: usize) {
+        fn walk(n: &mut crate::types::Node, id: usize) {
+            match n {
+                crate::types::Node::Leaf(p) => p.marked = p.id == id,
+                crate::types::Node::Split { children, .. } => for c in children { walk(c, id) },
+            }
+        }
+        for w in app.windows.iter_mut() { walk(&mut w.root, id); }
+    }
+
+    #[test]
+    fn marked_pane_items_follow_the_mark() {
+        let mut app = app();
+        let [a, b] = [1, 2];
+        let swap = "Swap with marked";
+        let join = "Join marked pane here (horizontal)";
+
+        // No mark: the mark items are greyed out and run nothing
+        let menu = pane_menu(&app, 0, (0, 0));
+        assert_eq!(item(&menu, "Mark this pane").command, format!("select-pane -m -t %{a}"));
+        assert_eq!((item(&menu, swap).enabled, item(&menu, swap).command.as_str()), (false, ""));
+        assert!(!item(&menu, join).enabled);
+        assert!(!item(&window_menu(&app, 1, (0, 0)), "Move marked pane to this window").enabled);
+
+        // The mark on this very pane
+        mark(&mut app, a);
+        let menu = pane_menu(&app, 0, (0, 0));
+        assert_eq!(item(&menu, "Unmark this pane").command, format!("select-pane -M -t %{a}"));
+        assert!(!item(&menu, swap).enabled);
+
+        // The mark on another pane: the commands take it as the source
+        mark(&mut app, b);
+        let menu = pane_menu(&app, 0, (0, 0));
+        assert_eq!(item(&menu, swap).command, format!("swap-pane -t %{b}"));
+        assert_eq!(item(&menu, join).command, format!("join-pane -h -s %{b} -t %{a}"));
+        assert_eq!(item(&menu, "Join marked pane here (vertical)").command, format!("join-pane -v -s %{b} -t %{a}"));
+        let index = app.windows[1].index;
+        let moved = item(&window_menu(&app, 1, (0, 0)), "Move marked pane to this window").clone();
+        assert_eq!((moved.enabled, moved.command), (true, format!("join-pane -s %{b} -t :{index}")));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn display_menu_items_are_formats_and_a_dash_disables_them() {
+        let mut app = AppState::new("t".to_string());
+        let menu = crate::commands::parse_menu_definition("-T #S #{?pane_marked_set,,-}Swap s swap-pane - Rename r rename-window");
+        let shown = from_definition(&mut app, &menu);
+        assert_eq!(shown.title, "t");
+        let names: Vec<(&str, bool)> = shown.rows.iter().map(|r| (r.name.as_str(), r.enabled)).collect();
+        assert_eq!(names, [("Swap", false), ("", false), ("Rename", true)]);
+
+        // The client skips what cannot be chosen
+        let mut open = Open::new(shown.clone());
+        assert_eq!(open.selected, 2);
+        assert_eq!(open.key(KeyCode::Char('s')), Outcome::Stay);
+        assert_eq!(open.key(KeyCode::Down), Outcome::Stay);
+        assert_eq!(open.key(KeyCode::Enter), Outcome::Run("rename-window".into()));
+        open.layout(Rect::new(0, 0, 80, 24), Some(78), Some(23));
+        let rect = open.rect;
+        assert!(rect.right() <= 80 && rect.bottom() <= 24);
+        assert_eq!(open.click(rect.x + 1, rect.y + 3), Outcome::Run("rename-window".into()));
+        assert_eq!(open.click(rect.x + 1, rect.y + 1), Outcome::Stay);
+        assert_eq!(open.click(0, 0), Outcome::Close);
+        assert_eq!(serde_json::from_str::<Shown>(directive(&shown).strip_prefix("%menu ").unwrap()).unwrap(), shown);
+    }
+
+    #[test]
+    fn quoted_items_separators_and_submenus() {
+        let mut app = AppState::new("t".to_string());
+        let def = r#"-T "My menu" -x R -y 3 "New window" n new-window "" "Layouts >" l "display-menu -T Layouts Tiled t 'select-layout tiled' Even e 'select-layout even-horizontal'" "#;
+        let menu = crate::commands::parse_menu_definition(def);
+        assert_eq!(menu.title, "My menu");
+        assert_eq!((menu.x, menu.y), (Some(Place::Right), Some(Place::At(3))));
+        assert_eq!(menu.items.len(), 3);
+        assert!(menu.items[1].is_separator);
+        let sub = menu.items[2].submenu.as_ref().expect("a submenu");
+        assert_eq!(sub.title, "Layouts");
+        assert_eq!(sub.items.iter().map(|i| i.command.as_str()).collect::<Vec<_>>(), ["select-layout tiled", "select-layout even-horizontal"]);
+
+        let shown = from_definition(&mut app, &menu);
+        assert_eq!((shown.x, shown.y), (Some(app.last_window_area.width), Some(3)));
+        assert_eq!(shown.rows[2].submenu.as_ref().unwrap().rows.len(), 2);
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(Place::parse("12"), Some(Place::At(12)));
+        for (s, p) in [("C", Place::Centre), ("R", Place::Right), ("P", Place::Pane), ("M", Place::Mouse), ("W", Place::Window), ("S", Place::Status)] {
+            assert_eq!(Place::parse(s), Some(p));
+        }
+        assert_eq!(Place::parse("left"), None);
+
+        let mut app = AppState::new("t".to_string());
+        app.mouse_at = (7, 9);
+        assert_eq!((Place::Mouse.resolve(&mut app, true), Place::Mouse.resolve(&mut app, false)), (Some(7), Some(9)));
+        assert_eq!(Place::Centre.resolve(&mut app, true), None);
+        assert_eq!(Place::Status.resolve(&mut app, false), Some(crate::tree::window_size(&app).1));
+    }
+
+    #[test]
+    fn submenus_open_beside_their_row_and_close_again() {
+        let mut app = AppState::new("t".to_string());
+        let menu = crate::commands::parse_menu_definition(r#"One o one "" More m "display-menu Deep d deep" -Off f off"#);
+        let mut open = Open::new(from_definition(&mut app, &menu));
+        let area = Rect::new(0, 0, 80, 24);
+
+        // Right only opens a submenu; Left goes back
+        assert_eq!(open.key(KeyCode::Right), Outcome::Stay);
+        assert!(open.sub.is_none());
+        assert_eq!(open.key(KeyCode::Down), Outcome::Stay);
+        assert_eq!(open.selected, 2);
+        assert_eq!(open.key(KeyCode::Right), Outcome::Stay);
+        assert!(open.sub.is_some());
+        assert_eq!(open.key(KeyCode::Left), Outcome::Stay);
+        assert!(open.sub.is_none());
+
+        // The shortcut opens it too, and keys then go to it
+        assert_eq!(open.key(KeyCode::Char('m')), Outcome::Stay);
+        open.layout(area, Some(10), Some(5));
+        let (parent, sub) = (open.rect, open.sub.as_ref().unwrap().rect);
+        assert_eq!((sub.x, sub.y + 1), (parent.right(), parent.y + 1 + 2));
+        assert_eq!(open.key(KeyCode::Enter), Outcome::Run("deep".into()));
+
+        // Hovering selects enabled rows only; the submenu row is clicked open
+        let mut open = Open::new(open.menu.clone());
+        open.layout(area, Some(75), Some(5));
+        let r = open.rect;
+        assert!(!open.hover(r.x + 1, r.y + 2), "a separator");
+        assert!(!open.hover(r.x + 1, r.y + 4), "disabled");
+        assert!(open.hover(r.x + 1, r.y + 3));
+        assert_eq!(open.click(r.x + 1, r.y + 3), Outcome::Stay);
+        open.layout(area, Some(75), Some(5));
+        let sub = open.sub.as_ref().unwrap().rect;
+        assert!(sub.right() <= open.rect.x, "no room on the right");
+        assert_eq!(open.click(sub.x + 1, sub.y + 1), Outcome::Run("deep".into()));
+        assert_eq!(open.click(0, 0), Outcome::Close);
+    }
+}
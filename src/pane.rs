@@ -1,12 +1,13 @@
 use std::io;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use portable_pty::{CommandBuilder, PtySize, native_pty_system};
 
-use crate::types::{AppState, Pane, Node, LayoutKind, Window};
-use crate::tree::{replace_leaf_with_split, active_pane_mut, kill_leaf};
+use crate::types::{AppState, Pane, Node, LayoutKind, SplitOpts, Window};
+use crate::tree::{replace_leaf_with_split, active_pane_mut, kill_leaf, split_with_gaps};
+use crate::window_ops::{insert_window, smallest_free_index, window_indices};
 
 /// Send a preemptive cursor-position report (\x1b[1;1R) to the ConPTY input pipe.
 ///
@@ -79,6 +80,7 @@ pub fn create_window(pty_system: &dyn portable_pty::PtySystem, app: &mut AppStat
     } else {
         build_command(None)
     };
+    crate::environ::apply_to_pane(&mut shell_cmd, &crate::environ::composed(app));
     set_tmux_env(&mut shell_cmd, app.next_pane_id, app.socket_name.as_deref());
     let child = pair
         .slave
@@ -99,19 +101,25 @@ pub fn create_window(pty_system: &dyn portable_pty::PtySystem, app: &mut AppStat
         .try_clone_reader()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("clone reader error: {e}")))?;
 
-    spawn_reader_thread(reader, term_reader, dv_writer);
+    let bells = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let encoding = crate::encoding::for_new_pane(app, None);
+    let pipe_tap = crate::pipe_pane::new_tap();
+    let reported_cwd = Arc::new(Mutex::new(None));
+    spawn_reader_thread(reader, term_reader, dv_writer, bells.clone(), encoding.clone(), pipe_tap.clone(), reported_cwd.clone());
 
     let configured_shell = if app.default_shell.is_empty() { None } else { Some(app.default_shell.as_str()) };
     let child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*child) };
     let mut pty_writer = pair.master.take_writer()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("take writer error: {e}")))?;
     conpty_preemptive_dsr_response(&mut *pty_writer);
-    let pane = Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None };
+    let pane = Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None, view_scrolled: false, pipe: None, pipe_tap, marked: false, exit_status: None, highlight_pattern: None, pane_encoding: None, options: Default::default(), encoding, cmdline_cache: Default::default(), process_info: Default::default(), reported_cwd, bells, bells_seen: 0, rows_cache: Default::default() };
+    watch_exit(app, pane.id, child_pid);
     app.next_pane_id += 1;
     let win_name = command.map(|c| default_shell_name(Some(c), None)).unwrap_or_else(|| default_shell_name(None, configured_shell));
-    app.windows.push(Window { root: Node::Leaf(pane), active_path: vec![], name: win_name, id: app.next_win_id, activity_flag: false, bell_flag: false, silence_flag: false, last_output_time: std::time::Instant::now(), last_seen_version: 0, manual_rename: false, layout_index: 0 });
+    let index = smallest_free_index(&window_indices(app), app.window_base_index);
+    let pos = insert_window(app, Window { root: Node::Leaf(pane), active_path: vec![], name: win_name, id: app.next_win_id, index, activity_flag: false, bell_flag: false, silence_flag: false, last_output_time: std::time::Instant::now(), last_seen_version: 0, activity_at: chrono::Local::now(), visited_at: chrono::Local::now(), created_at: chrono::Local::now(), idle_flag: false, idle_kill_at: None, manual_rename: false, layout_index: 0, tiled: false, highlight_pattern: None, pane_encoding: None, options: Default::default() });
     app.next_win_id += 1;
-    app.active_idx = app.windows.len() - 1;
+    app.active_idx = pos;
     Ok(())
 }
 
@@ -130,6 +138,7 @@ pub fn create_window_raw(pty_system: &dyn portable_pty::PtySystem, app: &mut App
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("openpty error: {e}")))?;
 
     let mut shell_cmd = build_raw_command(raw_args);
+    crate::environ::apply_to_pane(&mut shell_cmd, &crate::environ::composed(app));
     set_tmux_env(&mut shell_cmd, app.next_pane_id, app.socket_name.as_deref());
     let child = pair
         .slave
@@ -148,18 +157,24 @@ pub fn create_window_raw(pty_system: &dyn portable_pty::PtySystem, app: &mut App
         .try_clone_reader()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("clone reader error: {e}")))?;
 
-    spawn_reader_thread(reader, term_reader, dv_writer);
+    let bells = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let encoding = crate::encoding::for_new_pane(app, None);
+    let pipe_tap = crate::pipe_pane::new_tap();
+    let reported_cwd = Arc::new(Mutex::new(None));
+    spawn_reader_thread(reader, term_reader, dv_writer, bells.clone(), encoding.clone(), pipe_tap.clone(), reported_cwd.clone());
 
     let child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*child) };
     let mut pty_writer = pair.master.take_writer()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("take writer error: {e}")))?;
     conpty_preemptive_dsr_response(&mut *pty_writer);
-    let pane = Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None };
+    let pane = Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None, view_scrolled: false, pipe: None, pipe_tap, marked: false, exit_status: None, highlight_pattern: None, pane_encoding: None, options: Default::default(), encoding, cmdline_cache: Default::default(), process_info: Default::default(), reported_cwd, bells, bells_seen: 0, rows_cache: Default::default() };
+    watch_exit(app, pane.id, child_pid);
     app.next_pane_id += 1;
     let win_name = std::path::Path::new(&raw_args[0]).file_stem().and_then(|s| s.to_str()).unwrap_or(&raw_args[0]).to_string();
-    app.windows.push(Window { root: Node::Leaf(pane), active_path: vec![], name: win_name, id: app.next_win_id, activity_flag: false, bell_flag: false, silence_flag: false, last_output_time: std::time::Instant::now(), last_seen_version: 0, manual_rename: false, layout_index: 0 });
+    let index = smallest_free_index(&window_indices(app), app.window_base_index);
+    let pos = insert_window(app, Window { root: Node::Leaf(pane), active_path: vec![], name: win_name, id: app.next_win_id, index, activity_flag: false, bell_flag: false, silence_flag: false, last_output_time: std::time::Instant::now(), last_seen_version: 0, activity_at: chrono::Local::now(), visited_at: chrono::Local::now(), created_at: chrono::Local::now(), idle_flag: false, idle_kill_at: None, manual_rename: false, layout_index: 0, tiled: false, highlight_pattern: None, pane_encoding: None, options: Default::default() });
     app.next_win_id += 1;
-    app.active_idx = app.windows.len() - 1;
+    app.active_idx = pos;
     Ok(())
 }
 
@@ -174,29 +189,41 @@ const MIN_SPLIT_ROWS: u16 = 4;
 const MIN_SPLIT_COLS: u16 = 10;
 
 pub fn split_active_with_command(app: &mut AppState, kind: LayoutKind, command: Option<&str>, pty_system_ref: Option<&dyn portable_pty::PtySystem>) -> io::Result<()> {
-    // ── Guard: refuse split if the active pane is too small ──────────
+    split_pane(app, kind, command, pty_system_ref, SplitOpts::default())
+}
+
+/// split-window: split the active pane, or the whole window with `-f`,
+/// `kind`-wise and focus the new pane, which goes after the old place or
+/// before it with `-b`.  It gets the size asked (`-l`/`-p`), or half.
+pub fn split_pane(app: &mut AppState, kind: LayoutKind, command: Option<&str>, pty_system_ref: Option<&dyn portable_pty::PtySystem>, opts: SplitOpts) -> io::Result<()> {
+    // The place split: the active pane's (its path and size), or the window's
+    let (place_path, place_rows, place_cols) = {
+        let win = &app.windows[app.active_idx];
+        let area = app.last_window_area;
+        let window = (vec![], if area.height > 1 { area.height } else { 30 }, if area.width > 1 { area.width } else { 120 });
+        match crate::tree::active_pane(&win.root, &win.active_path) {
+            Some(p) if !opts.full => (win.active_path.clone(), p.last_rows, p.last_cols),
+            _ => window,
+        }
+    };
+
+    // ── Guard: refuse split if the place is too small ────────────────
     // After splitting, each half gets roughly (dim / 2) - 1 (for the divider).
     // If that would be below MIN_PANE_DIM, deny the split to avoid crashing
     // the child process (ConPTY cannot function below ~2 rows or cols).
-    {
-        let win = &app.windows[app.active_idx];
-        if let Some(p) = crate::tree::active_pane(&win.root, &win.active_path) {
-            let (cur_rows, cur_cols) = (p.last_rows, p.last_cols);
-            match kind {
-                LayoutKind::Vertical => {
-                    // Splitting vertically divides height; need room for 2 panes + 1 divider
-                    if cur_rows < MIN_SPLIT_ROWS * 2 + 1 {
-                        return Err(io::Error::new(io::ErrorKind::Other,
-                            format!("pane too small to split vertically ({cur_rows} rows, need {})", MIN_SPLIT_ROWS * 2 + 1)));
-                    }
-                }
-                LayoutKind::Horizontal => {
-                    // Splitting horizontally divides width; need room for 2 panes + 1 divider
-                    if cur_cols < MIN_SPLIT_COLS * 2 + 1 {
-                        return Err(io::Error::new(io::ErrorKind::Other,
-                            format!("pane too small to split horizontally ({cur_cols} cols, need {})", MIN_SPLIT_COLS * 2 + 1)));
-                    }
-                }
+    match kind {
+        LayoutKind::Vertical => {
+            // Splitting vertically divides height; need room for 2 panes + 1 divider
+            if place_rows < MIN_SPLIT_ROWS * 2 + 1 {
+                return Err(io::Error::new(io::ErrorKind::Other,
+                    format!("pane too small to split vertically ({place_rows} rows, need {})", MIN_SPLIT_ROWS * 2 + 1)));
+            }
+        }
+        LayoutKind::Horizontal => {
+            // Splitting horizontally divides width; need room for 2 panes + 1 divider
+            if place_cols < MIN_SPLIT_COLS * 2 + 1 {
+                return Err(io::Error::new(io::ErrorKind::Other,
+                    format!("pane too small to split horizontally ({place_cols} cols, need {})", MIN_SPLIT_COLS * 2 + 1)));
             }
         }
     }
@@ -209,29 +236,33 @@ pub fn split_active_with_command(app: &mut AppState, kind: LayoutKind, command:
         owned_pty = native_pty_system();
         &*owned_pty
     };
-    // Compute target pane size from the *active pane's* actual dimensions,
-    // not the full window area — ensures we don't over-estimate and then
-    // immediately resize to a tiny rect.
-    let (pane_rows, pane_cols) = {
-        let win = &app.windows[app.active_idx];
-        if let Some(p) = crate::tree::active_pane(&win.root, &win.active_path) {
-            (p.last_rows, p.last_cols)
-        } else {
-            let area = app.last_window_area;
-            (if area.height > 1 { area.height } else { 30 }, if area.width > 1 { area.width } else { 120 })
-        }
+    // Size the new pane from the place it splits, not the full window
+    // area, so it is not over-estimated and then immediately resized.
+    let horizontal = kind == LayoutKind::Horizontal;
+    let mut sizes = match opts.size {
+        Some(size) => crate::tree::sized_split(if horizontal { place_cols } else { place_rows }, size),
+        None => vec![50, 50],
     };
-    let (rows, cols) = match kind {
-        LayoutKind::Vertical => {
-            let half = (pane_rows.saturating_sub(1)) / 2; // subtract 1 for divider
-            (half.max(MIN_PANE_DIM), pane_cols.max(MIN_PANE_DIM))
-        }
-        LayoutKind::Horizontal => {
-            let half = (pane_cols.saturating_sub(1)) / 2;
-            (pane_rows.max(MIN_PANE_DIM), half.max(MIN_PANE_DIM))
-        }
-    };
-    let size = PtySize { rows, cols, pixel_width: 0, pixel_height: 0 };
+    let new_rect = split_with_gaps(horizontal, &sizes, ratatui::layout::Rect::new(0, 0, place_cols, place_rows))[1];
+    let size = PtySize { rows: new_rect.height.max(MIN_PANE_DIM), cols: new_rect.width.max(MIN_PANE_DIM), pixel_width: 0, pixel_height: 0 };
+    let new_leaf = Node::Leaf(spawn_pane(app, pty_system, size, command)?);
+    let win = &mut app.windows[app.active_idx];
+    replace_leaf_with_split(&mut win.root, &place_path, kind, new_leaf);
+    if opts.before { sizes.reverse(); }
+    if let Some(Node::Split { sizes: s, children, .. }) = crate::tree::get_split_mut(&mut win.root, &place_path) {
+        if opts.before { children.swap(0, 1); }
+        *s = sizes;
+    }
+    win.tiled = false;
+    let mut new_path = place_path;
+    new_path.push(if opts.before { 0 } else { 1 });
+    win.active_path = new_path;
+    Ok(())
+}
+
+/// Start a pane of `size` running `command` (the default shell when None)
+/// in the server's current directory.  The caller places it in a tree.
+pub fn spawn_pane(app: &mut AppState, pty_system: &dyn portable_pty::PtySystem, size: PtySize, command: Option<&str>) -> io::Result<Pane> {
     let pair = pty_system.openpty(size).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("openpty error: {e}")))?;
     // When no explicit command is given, use the configured default-shell.
     let mut shell_cmd = if command.is_some() {
@@ -241,6 +272,7 @@ pub fn split_active_with_command(app: &mut AppState, kind: LayoutKind, command:
     } else {
         build_command(None)
     };
+    crate::environ::apply_to_pane(&mut shell_cmd, &crate::environ::composed(app));
     set_tmux_env(&mut shell_cmd, app.next_pane_id, app.socket_name.as_deref());
     let child = pair.slave.spawn_command(shell_cmd).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("spawn shell error: {e}")))?;
     // Close the slave handle immediately – see create_window() comment.
@@ -250,19 +282,19 @@ pub fn split_active_with_command(app: &mut AppState, kind: LayoutKind, command:
     let reader = pair.master.try_clone_reader().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("clone reader error: {e}")))?;
     let data_version = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
     let dv_writer = data_version.clone();
-    spawn_reader_thread(reader, term_reader, dv_writer);
+    let bells = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    let encoding = crate::encoding::for_new_pane(app, app.windows.get(app.active_idx));
+    let pipe_tap = crate::pipe_pane::new_tap();
+    let reported_cwd = Arc::new(Mutex::new(None));
+    spawn_reader_thread(reader, term_reader, dv_writer, bells.clone(), encoding.clone(), pipe_tap.clone(), reported_cwd.clone());
     let child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*child) };
     let mut pty_writer = pair.master.take_writer()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("take writer error: {e}")))?;
     conpty_preemptive_dsr_response(&mut *pty_writer);
-    let new_leaf = Node::Leaf(Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None });
+    let pane = Pane { master: pair.master, writer: pty_writer, child, term, last_rows: size.rows, last_cols: size.cols, id: app.next_pane_id, title: format!("pane %{}", app.next_pane_id), child_pid, data_version, last_title_check: std::time::Instant::now(), last_infer_title: std::time::Instant::now(), dead: false, vt_bridge_cache: None, copy_state: None, view_scrolled: false, pipe: None, pipe_tap, marked: false, exit_status: None, highlight_pattern: None, pane_encoding: None, options: Default::default(), encoding, cmdline_cache: Default::default(), process_info: Default::default(), reported_cwd, bells, bells_seen: 0, rows_cache: Default::default() };
+    watch_exit(app, pane.id, child_pid);
     app.next_pane_id += 1;
-    let win = &mut app.windows[app.active_idx];
-    replace_leaf_with_split(&mut win.root, &win.active_path, kind, new_leaf);
-    let mut new_path = win.active_path.clone();
-    new_path.push(1);
-    win.active_path = new_path;
-    Ok(())
+    Ok(pane)
 }
 
 pub fn kill_active_pane(app: &mut AppState) -> io::Result<()> {
@@ -272,8 +304,10 @@ pub fn kill_active_pane(app: &mut AppState) -> io::Result<()> {
     // so we must do it here to ensure no orphaned processes.
     if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
         crate::platform::process_kill::kill_process_tree(&mut p.child);
+        p.stop_pipe();
     }
     kill_leaf(&mut win.root, &win.active_path);
+    crate::layout::retile(win);
     Ok(())
 }
 
@@ -433,9 +467,226 @@ pub fn build_raw_command(raw_args: &[String]) -> CommandBuilder {
     builder
 }
 
+/// Hand a new pane's process to the server's exit watcher, so its exit is
+/// reported as it happens rather than found by the next sweep.
+pub fn watch_exit(app: &AppState, pane_id: usize, pid: Option<u32>) {
+    if let (Some(watcher), Some(pid)) = (&app.exit_watcher, pid) {
+        watcher.watch(pane_id, pid);
+    }
+}
+
+/// Process queries behind `#{pane_current_command_full}` and
+/// `#{pane_current_path}`; AppState holds the platform's, tests put their
+/// own in.
+#[derive(Clone, Copy)]
+pub struct ProcessLookup {
+    pub foreground_pid: fn(u32) -> u32,
+    pub command_line: fn(u32) -> Option<String>,
+    pub name: fn(u32) -> Option<String>,
+    pub cwd: fn(u32) -> Option<String>,
+}
+
+impl ProcessLookup {
+    pub const PLATFORM: ProcessLookup = ProcessLookup {
+        foreground_pid: crate::platform::process_info::get_foreground_pid,
+        command_line: crate::platform::process_info::get_process_command_line,
+        name: crate::platform::process_info::get_process_name,
+        cwd: crate::platform::process_info::get_process_cwd,
+    };
+}
+
+/// A pane's `#{pane_current_path}` and `#{pane_current_command}`, kept for
+/// a moment: status lines expand them on every redraw, and each lookup
+/// walks the process tree.
+#[derive(Default)]
+pub struct ProcessInfoCache {
+    path: Option<(String, Instant)>,
+    command: Option<(String, Instant)>,
+    /// Lookups made so far
+    pub lookups: usize,
+}
+
+impl ProcessInfoCache {
+    /// How long a value is used before it is looked up again.
+    pub const TTL: Duration = Duration::from_millis(500);
+
+    pub fn path(&mut self, now: Instant, read: impl FnOnce() -> String) -> String {
+        Self::get(&mut self.path, &mut self.lookups, now, read)
+    }
+
+    pub fn command(&mut self, now: Instant, read: impl FnOnce() -> String) -> String {
+        Self::get(&mut self.command, &mut self.lookups, now, read)
+    }
+
+    fn get(slot: &mut Option<(String, Instant)>, lookups: &mut usize, now: Instant, read: impl FnOnce() -> String) -> String {
+        match slot {
+            Some((value, at)) if now.duration_since(*at) < Self::TTL => value.clone(),
+            _ => {
+                *lookups += 1;
+                let value = read();
+                *slot = Some((value.clone(), now));
+                value
+            }
+        }
+    }
+}
+
+/// Finds the working directory a shell reports with OSC 9;9 (ConEmu's
+/// sequence, which Windows Terminal reads too, and which PowerShell
+/// prompts are commonly set up to print) in a pane's output, which may
+/// split a sequence across reads.
+#[derive(Default)]
+pub struct CwdScanner {
+    /// An unterminated sequence from the previous read, from its ESC on
+    partial: Vec<u8>,
+}
+
+impl CwdScanner {
+    const PREFIX: &'static [u8] = b"\x1b]9;9;";
+
+    /// Scan one read of pane output: the last directory reported in it.
+    pub fn scan(&mut self, bytes: &[u8]) -> Option<String> {
+        if self.partial.is_empty() && !bytes.contains(&0x1b) { return None; }
+        let mut data = std::mem::take(&mut self.partial);
+        data.extend_from_slice(bytes);
+        let mut found = None;
+        let mut i = 0;
+        while let Some(start) = data[i..].iter().position(|&b| b == 0x1b).map(|p| p + i) {
+            let rest = &data[start..];
+            if rest.len() < Self::PREFIX.len() && Self::PREFIX.starts_with(rest) {
+                self.partial = rest.to_vec();
+                break;
+            }
+            if !rest.starts_with(Self::PREFIX) { i = start + 1; continue; }
+            match crate::clipboard::terminator(rest) {
+                Some((body_end, seq_end)) => {
+                    let dir = String::from_utf8_lossy(&rest[Self::PREFIX.len()..body_end]);
+                    let dir = dir.trim().trim_matches('"');
+                    if !dir.is_empty() { found = Some(dir.to_string()); }
+                    i = start + seq_end;
+                }
+                None => {
+                    if rest.len() <= crate::clipboard::MAX_SEQUENCE { self.partial = rest.to_vec(); }
+                    break;
+                }
+            }
+        }
+        found
+    }
+}
+
+/// A pane's foreground command line, cached per pid: reading it out of
+/// another process's memory costs far more than looking up its name.  A
+/// failed read (access denied) is cached too.
+#[derive(Default)]
+pub struct CommandLineCache {
+    entry: Option<(u32, Option<String>, Instant)>,
+    /// Command-line reads made so far
+    pub lookups: usize,
+}
+
+impl CommandLineCache {
+    /// An unchanged pid is read again after this long (the slow rename
+    /// poll), which catches a process that exec'd in place.
+    pub const REFRESH: Duration = Duration::from_secs(5);
+
+    pub fn get(&mut self, pid: u32, now: Instant, read: fn(u32) -> Option<String>) -> Option<String> {
+        match &self.entry {
+            Some((p, line, at)) if *p == pid && now.duration_since(*at) < Self::REFRESH => line.clone(),
+            _ => {
+                self.lookups += 1;
+                let line = read(pid);
+                self.entry = Some((pid, line.clone(), now));
+                line
+            }
+        }
+    }
+}
+
+/// Default `automatic-rename-format`: the foreground process's name.
+pub const DEFAULT_AUTOMATIC_RENAME_FORMAT: &str = "#{pane_current_command}";
+
+/// Longest `#{pane_current_command_full}`, in characters.
+pub const COMMAND_FULL_MAX: usize = 48;
+
+/// Shorten a command line for display: the program's path and extension
+/// are dropped (`"C:\Tools\pytest.exe" tests/foo` is `pytest tests/foo`)
+/// and anything past `max` characters becomes an ellipsis.
+pub fn short_command_line(line: &str, max: usize) -> String {
+    let line = line.trim();
+    let (program, rest) = match line.strip_prefix('"') {
+        Some(quoted) => match quoted.find('"') {
+            Some(end) => (&quoted[..end], &quoted[end + 1..]),
+            None => (quoted, ""),
+        },
+        None => line.split_at(line.find(' ').unwrap_or(line.len())),
+    };
+    let name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+    let name = match name.rsplit_once('.') {
+        Some((stem, ext)) if ext.eq_ignore_ascii_case("exe") || ext.eq_ignore_ascii_case("com") => stem,
+        _ => name,
+    };
+    let mut short = name.to_string();
+    let rest = rest.trim();
+    if !rest.is_empty() { short.push(' '); short.push_str(rest); }
+    if short.chars().count() > max {
+        short = short.chars().take(max.saturating_sub(1)).collect::<String>() + "…";
+    }
+    short
+}
+
+/// `#{pane_current_command}`: the foreground process's name, or the title
+/// without a pid.
+pub fn pane_current_command(p: &Pane) -> String {
+    match p.child_pid {
+        Some(pid) => p.process_info.borrow_mut().command(Instant::now(), || {
+            crate::platform::process_info::get_foreground_process_name(pid).unwrap_or_else(|| "shell".into())
+        }),
+        None if !p.title.is_empty() => p.title.clone(),
+        None => "shell".into(),
+    }
+}
+
+/// `#{pane_current_path}`: the foreground process's working directory, else
+/// the shell's, else the last one the shell reported with OSC 9;9.  Without a pid, the
+/// reported one or the server's.
+pub fn pane_current_path(p: &Pane, lookup: &ProcessLookup) -> String {
+    let reported = || p.reported_cwd.lock().ok().and_then(|d| d.clone());
+    let Some(pid) = p.child_pid else {
+        return reported()
+            .or_else(|| std::env::current_dir().ok().map(|d| d.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+    };
+    p.process_info.borrow_mut().path(Instant::now(), || {
+        let foreground = (lookup.foreground_pid)(pid);
+        let cwd = |pid| (lookup.cwd)(pid).filter(|d: &String| !d.is_empty());
+        cwd(foreground)
+            .or_else(|| if foreground != pid { cwd(pid) } else { None })
+            .or_else(reported)
+            .unwrap_or_default()
+    })
+}
+
+/// `#{pane_current_command_full}`: the foreground process's command line,
+/// shortened.  Falls back to its name when the command line cannot be read
+/// (an elevated process refuses), and to the title without a pid.
+pub fn pane_command_full(p: &Pane, lookup: &ProcessLookup) -> String {
+    let Some(pid) = p.child_pid else {
+        return if p.title.is_empty() { "shell".into() } else { p.title.clone() };
+    };
+    let fg = (lookup.foreground_pid)(pid);
+    let line = p.cmdline_cache.borrow_mut().get(fg, Instant::now(), lookup.command_line);
+    match line {
+        Some(l) if !l.trim().is_empty() => short_command_line(&l, COMMAND_FULL_MAX),
+        _ => (lookup.name)(fg).unwrap_or_else(|| "shell".into()),
+    }
+}
+
 /// Spawn a dedicated PTY reader thread that processes output and updates the
 /// data_version counter. Exits cleanly after 200 consecutive zero-byte reads
-/// (indicating the PTY pipe is closed) or on any I/O error.
+/// (indicating the PTY pipe is closed) or on any I/O error, and without
+/// processing what it read once the pane no longer holds `dv_writer` (it
+/// died under remain-on-exit, or was respawned with a new counter).
 ///
 /// Uses an 8KB read buffer (down from 64KB) to reduce mutex hold time during
 /// `parser.process()`, which improves DumpState latency under heavy output.
@@ -443,16 +694,34 @@ pub fn spawn_reader_thread(
     mut reader: Box<dyn std::io::Read + Send>,
     term_reader: Arc<Mutex<vt100::Parser>>,
     dv_writer: Arc<std::sync::atomic::AtomicU64>,
+    bells: Arc<std::sync::atomic::AtomicU64>,
+    encoding: Arc<std::sync::atomic::AtomicU8>,
+    pipe_tap: crate::pipe_pane::Tap,
+    reported_cwd: Arc<Mutex<Option<String>>>,
 ) {
     thread::spawn(move || {
         let mut local = [0u8; 8192];
         let mut zero_reads: u32 = 0;
+        let mut osc52 = crate::clipboard::Osc52Scanner::default();
+        let mut bell_scanner = crate::alerts::BellScanner::default();
+        let mut cwd_scanner = CwdScanner::default();
+        let mut decoder = crate::encoding::Decoder::default();
         loop {
             match reader.read(&mut local) {
+                // The pane died or was respawned: its screen stays as it was
+                Ok(_) if std::sync::Arc::strong_count(&dv_writer) == 1 => break,
                 Ok(n) if n > 0 => {
                     zero_reads = 0;
+                    osc52.feed(&local[..n]);
+                    crate::pipe_pane::tee(&pipe_tap, &local[..n]);
+                    let rang = bell_scanner.feed(&local[..n]);
+                    if rang > 0 { bells.fetch_add(rang, std::sync::atomic::Ordering::Release); }
+                    if let Some(dir) = cwd_scanner.scan(&local[..n]) {
+                        if let Ok(mut cwd) = reported_cwd.lock() { *cwd = Some(dir); }
+                    }
+                    let text = decoder.decode(crate::encoding::Encoding::load(&encoding), &local[..n]);
                     if let Ok(mut parser) = term_reader.lock() {
-                        parser.process(&local[..n]);
+                        parser.process(&text);
                     }
                     dv_writer.fetch_add(1, std::sync::atomic::Ordering::Release);
                     crate::types::PTY_DATA_READY.store(true, std::sync::atomic::Ordering::Release);
@@ -469,3 +738,35 @@ pub fn spawn_reader_thread(
 }
 
 // reap_children is in tree.rs
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_reported_directories_split_across_reads() {
+        let mut s = CwdScanner::default();
+        let seq = b"\x1b]9;9;\"C:\\Users\\me\\src\"\x07";
+        let (a, b) = seq.split_at(4);
+        assert_eq!(s.scan(&[b"PS> ".as_slice(), a].concat()), None);
+        assert_eq!(s.scan(&[b, b"PS C:\\> ".as_slice()].concat()).as_deref(), Some("C:\\Users\\me\\src"));
+
+        // ST terminator, unquoted; the last of several wins; other OSCs are skipped
+        let many = b"\x1b]0;title\x07\x1b]9;9;C:\\a\x1b\\\x1b]9;9;D:\\b\x1b\\";
+        assert_eq!(s.scan(many).as_deref(), Some("D:\\b"));
+        assert_eq!(s.scan(b"\x1b]9;4;1;50\x07"), None);
+        assert!(s.partial.is_empty());
+    }
+
+    #[test]
+    fn process_info_is_looked_up_again_after_the_ttl() {
+        let mut cache = ProcessInfoCache::default();
+        let t0 = Instant::now();
+        assert_eq!(cache.path(t0, || "C:\\a".into()), "C:\\a");
+        assert_eq!(cache.path(t0 + Duration::from_millis(100), || "C:\\b".into()), "C:\\a");
+        assert_eq!(cache.command(t0, || "pwsh".into()), "pwsh");
+        assert_eq!(cache.lookups, 2);
+        assert_eq!(cache.path(t0 + ProcessInfoCache::TTL, || "C:\\b".into()), "C:\\b");
+        assert_eq!(cache.lookups, 3);
+    }
+}
@@ -0,0 +1,155 @@
+//! Bounded matching of user-supplied regexes: copy-mode search and the
+//! `s///`, `m/r:` and `C:` format modifiers.
+//!
+//! Patterns are compiled with size limits, and haystacks are matched one
+//! line at a time against a deadline (the `pattern-timeout` option), so a
+//! pathological pattern ends in "pattern too slow" instead of stalling the
+//! server loop and every pane with it.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use regex::{Regex, RegexBuilder};
+
+/// Cap on the compiled program of a user pattern.
+pub const SIZE_LIMIT: usize = 1 << 20;
+
+/// Cap on the lazy DFA cache of a user pattern.
+pub const DFA_SIZE_LIMIT: usize = 2 << 20;
+
+/// Default for `pattern-timeout`, in milliseconds.
+pub const DEFAULT_TIMEOUT_MS: u64 = 250;
+
+/// How long copy-mode search waits after the last keystroke before
+/// scanning the pane.
+pub const SEARCH_DEBOUNCE: Duration = Duration::from_millis(120);
+
+#[derive(Debug, PartialEq)]
+pub enum PatternError {
+    /// The pattern does not parse or is over the size limits
+    Invalid(String),
+    /// Matching ran past its time budget and was abandoned
+    TooSlow,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Invalid(e) => write!(f, "bad pattern: {}", e),
+            PatternError::TooSlow => f.write_str("pattern too slow"),
+        }
+    }
+}
+
+/// Compile a user pattern under [`SIZE_LIMIT`] and [`DFA_SIZE_LIMIT`].
+pub fn compile(pattern: &str, case_insensitive: bool) -> Result<Regex, PatternError> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .size_limit(SIZE_LIMIT)
+        .dfa_size_limit(DFA_SIZE_LIMIT)
+        .build()
+        .map_err(|e| PatternError::Invalid(e.to_string().lines().last().unwrap_or("").trim().to_string()))
+}
+
+/// Deadline shared by every chunk of one match operation.
+pub struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    pub fn new(timeout_ms: u64) -> Self {
+        Budget { deadline: Instant::now() + Duration::from_millis(timeout_ms) }
+    }
+
+    pub fn check(&self) -> Result<(), PatternError> {
+        if Instant::now() >= self.deadline { Err(PatternError::TooSlow) } else { Ok(()) }
+    }
+}
+
+/// Every match of `re` in `lines`, as (line index, byte start, byte end),
+/// checking the budget before each line.
+pub fn find_in_lines<'a, I>(re: &Regex, lines: I, budget: &Budget) -> Result<Vec<(usize, usize, usize)>, PatternError>
+where I: IntoIterator<Item = &'a str> {
+    let mut found = Vec::new();
+    for (i, line) in lines.into_iter().enumerate() {
+        budget.check()?;
+        found.extend(re.find_iter(line).map(|m| (i, m.start(), m.end())));
+    }
+    Ok(found)
+}
+
+/// Index of the first of `lines` that `re` matches.
+pub fn first_matching_line<'a, I>(re: &Regex, lines: I, budget: &Budget) -> Result<Option<usize>, PatternError>
+where I: IntoIterator<Item = &'a str> {
+    for (i, line) in lines.into_iter().enumerate() {
+        budget.check()?;
+        if re.is_match(line) { return Ok(Some(i)); }
+    }
+    Ok(None)
+}
+
+/// `re.replace` (first match only) on `text`, matched a line at a time.
+/// A match never spans a newline.
+pub fn replace_first(re: &Regex, text: &str, replacement: &str, budget: &Budget) -> Result<String, PatternError> {
+    let mut out = String::with_capacity(text.len());
+    let mut lines = text.split_inclusive('\n');
+    for line in lines.by_ref() {
+        budget.check()?;
+        let (body, nl) = match line.strip_suffix('\n') { Some(b) => (b, "\n"), None => (line, "") };
+        if re.is_match(body) {
+            out.push_str(&re.replace(body, replacement));
+            out.push_str(nl);
+            break;
+        }
+        out.push_str(line);
+    }
+    for line in lines { out.push_str(line); }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pattern whose lazy DFA blows up on varied text, forcing the slow
+    /// matching engines, against about 10 MB of pseudo-random letters.
+    fn pathological() -> (Regex, Vec<String>) {
+        let mut x: u32 = 12345;
+        let lines = (0..50_000).map(|_| (0..200).map(|_| {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            (b'a' + ((x >> 16) % 26) as u8) as char
+        }).collect()).collect();
+        (compile(r"[a-q][^u-z]{25}[0-9]", false).unwrap(), lines)
+    }
+
+    #[test]
+    fn slow_patterns_give_up_promptly() {
+        let (re, lines) = pathological();
+        let start = Instant::now();
+        let r = find_in_lines(&re, lines.iter().map(String::as_str), &Budget::new(20));
+        assert_eq!(r, Err(PatternError::TooSlow));
+        assert!(start.elapsed() < Duration::from_secs(1), "took {:?}", start.elapsed());
+        assert_eq!(PatternError::TooSlow.to_string(), "pattern too slow");
+
+        let text = lines.join("\n");
+        let start = Instant::now();
+        assert_eq!(replace_first(&re, &text, "", &Budget::new(20)), Err(PatternError::TooSlow));
+        assert!(start.elapsed() < Duration::from_secs(1), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn oversized_patterns_are_rejected() {
+        assert!(matches!(compile(r"\w{50}\s", false), Err(PatternError::Invalid(_))));
+        assert!(matches!(compile(r"(", false), Err(PatternError::Invalid(_))));
+    }
+
+    #[test]
+    fn matching_within_budget() {
+        let re = compile("b+", true).unwrap();
+        let budget = Budget::new(DEFAULT_TIMEOUT_MS);
+        assert_eq!(find_in_lines(&re, ["abba", "x", "B"], &budget), Ok(vec![(0, 1, 3), (2, 0, 1)]));
+        assert_eq!(first_matching_line(&re, ["x", "aB"], &budget), Ok(Some(1)));
+        assert_eq!(replace_first(&re, "one\nabba\nbob", "-", &budget).unwrap(), "one\na-a\nbob");
+        assert_eq!(replace_first(&re, "none", "-", &budget).unwrap(), "none");
+    }
+}
@@ -1,15 +1,20 @@
-/// Named pipe IPC module for Windows.
-///
-/// Replaces TCP with Windows named pipes for cross-session communication.
-/// Uses SDDL security descriptor with Low Integrity SACL for Session 0
-/// (SSH) access — MIC evaluates BEFORE DACL, so without an explicit low
-/// integrity label, processes in Session 0 cannot open pipes created in
-/// the desktop session.
-///
-/// FFI style matches `platform.rs` — raw `#[link]` extern blocks, no new
-/// crate dependencies.
+//! Named pipe transport for Windows, used where AF_UNIX sockets are not
+//! available (see `transport.rs`).
+//!
+//! Uses SDDL security descriptor with Low Integrity SACL for Session 0
+//! (SSH) access — MIC evaluates BEFORE DACL, so without an explicit low
+//! integrity label, processes in Session 0 cannot open pipes created in
+//! the desktop session.  The DACL admits only the server's user and
+//! SYSTEM, so other local users cannot open the pipe at all, and clients
+//! on it skip the session key.
+//!
+//! FFI style matches `platform.rs` — raw `#[link]` extern blocks, no new
+//! crate dependencies.
 
 use std::io;
+use std::sync::OnceLock;
+
+use crate::transport::{Endpoint, Listener, Stream, Transport};
 
 // ── Windows constants ──────────────────────────────────────────────────
 const PIPE_ACCESS_DUPLEX: u32 = 0x00000003;
@@ -28,9 +33,16 @@ const NMPWAIT_USE_DEFAULT_WAIT: u32 = 0;
 const SDDL_REVISION_1: u32 = 1;
 const DUPLICATE_SAME_ACCESS: u32 = 0x00000002;
 
-/// SDDL: Everyone + Anonymous full access, Low integrity label.
-/// This allows Session 0 (SSH) processes to open pipes created in desktop sessions.
-const PIPE_SDDL: &str = "D:(A;;GA;;;WD)(A;;GA;;;AN)S:(ML;;NW;;;LW)";
+const TOKEN_QUERY: u32 = 0x0008;
+const TOKEN_USER_CLASS: u32 = 1;
+
+/// SDDL for the server pipe: full access for the user running the server
+/// and SYSTEM only, with a Low integrity label so the same user's Session 0
+/// (SSH) processes can still open pipes created in the desktop session.
+/// Without the user's SID, the pipe owner (OW) stands in for it.
+fn pipe_sddl(user_sid: Option<&str>) -> String {
+    format!("D:P(A;;GA;;;{})(A;;GA;;;SY)S:(ML;;NW;;;LW)", user_sid.unwrap_or("OW"))
+}
 
 // ── FFI declarations ───────────────────────────────────────────────────
 
@@ -111,6 +123,18 @@ extern "system" {
         SecurityDescriptor: *mut *mut std::ffi::c_void,
         SecurityDescriptorSize: *mut u32,
     ) -> i32;
+
+    fn OpenProcessToken(ProcessHandle: isize, DesiredAccess: u32, TokenHandle: *mut isize) -> i32;
+
+    fn GetTokenInformation(
+        TokenHandle: isize,
+        TokenInformationClass: u32,
+        TokenInformation: *mut std::ffi::c_void,
+        TokenInformationLength: u32,
+        ReturnLength: *mut u32,
+    ) -> i32;
+
+    fn ConvertSidToStringSidW(Sid: *mut std::ffi::c_void, StringSid: *mut *mut u16) -> i32;
 }
 
 #[link(name = "kernel32")]
@@ -120,7 +144,7 @@ extern "system" {
 
 // ── Helper: wide string conversion ─────────────────────────────────────
 
-fn to_wide(s: &str) -> Vec<u16> {
+pub(crate) fn to_wide(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
 
@@ -163,26 +187,27 @@ impl PipeStream {
             Ok(PipeStream { handle: new_handle })
         }
     }
+}
 
-    /// No-op — named pipes don't have Nagle's algorithm.
-    /// Provided for API compatibility with code that calls `stream.set_nodelay(true)`.
-    pub fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
-        Ok(())
+impl Transport for PipeStream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        Ok(Box::new(PipeStream::try_clone(self)?))
     }
 
     /// No-op — named pipes use blocking reads; EOF on disconnect.
-    /// Provided for API compatibility with `stream.set_read_timeout(...)`.
-    pub fn set_read_timeout(&self, _timeout: Option<std::time::Duration>) -> io::Result<()> {
+    fn set_read_timeout(&self, _timeout: Option<std::time::Duration>) -> io::Result<()> {
         Ok(())
     }
 
-    /// Shut down the pipe (close our handle). Named pipes don't have half-close,
-    /// so this just closes the handle entirely.
-    pub fn shutdown(&self) -> io::Result<()> {
-        // This is a no-op since Drop will close the handle.
-        // We don't actually close here to avoid double-close.
+    /// No-op — named pipes don't have Nagle's algorithm.
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
         Ok(())
     }
+
+    /// DisconnectNamedPipe: data the client has not read yet is lost.
+    fn disconnect(&self) {
+        disconnect_pipe(self.handle);
+    }
 }
 
 impl io::Read for PipeStream {
@@ -251,14 +276,49 @@ impl Drop for PipeStream {
 // ── Pipe security descriptor ───────────────────────────────────────────
 
 /// RAII wrapper for a security descriptor allocated by
-/// ConvertStringSecurityDescriptorToSecurityDescriptorW.
-struct PipeSecurityDescriptor {
-    ptr: *mut std::ffi::c_void,
+/// ConvertStringSecurityDescriptorToSecurityDescriptorW.  The AF_UNIX
+/// socket file gets its DACL through one too.
+pub(crate) struct SecurityDescriptor {
+    pub(crate) ptr: *mut std::ffi::c_void,
+}
+
+/// The SID of the user this process runs as (`S-1-5-21-...`), read from
+/// the process token once.
+pub(crate) fn user_sid() -> Option<&'static str> {
+    static SID: OnceLock<Option<String>> = OnceLock::new();
+    SID.get_or_init(current_user_sid).as_deref()
+}
+
+fn current_user_sid() -> Option<String> {
+    unsafe {
+        let mut token: isize = 0;
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return None;
+        }
+        let mut len: u32 = 0;
+        GetTokenInformation(token, TOKEN_USER_CLASS, std::ptr::null_mut(), 0, &mut len);
+        // TOKEN_USER starts with the SID pointer; u64s keep it aligned
+        let mut buf = vec![0u64; (len as usize).div_ceil(8).max(1)];
+        let ok = GetTokenInformation(token, TOKEN_USER_CLASS, buf.as_mut_ptr().cast(), len, &mut len);
+        CloseHandle(token);
+        if ok == 0 {
+            return None;
+        }
+        let sid = *(buf.as_ptr() as *const *mut std::ffi::c_void);
+        let mut wide: *mut u16 = std::ptr::null_mut();
+        if ConvertSidToStringSidW(sid, &mut wide) == 0 || wide.is_null() {
+            return None;
+        }
+        let n = (0..).take_while(|&i| *wide.add(i) != 0).count();
+        let s = String::from_utf16_lossy(std::slice::from_raw_parts(wide, n));
+        LocalFree(wide.cast());
+        Some(s)
+    }
 }
 
-impl PipeSecurityDescriptor {
-    fn new() -> io::Result<Self> {
-        let sddl = to_wide(PIPE_SDDL);
+impl SecurityDescriptor {
+    pub(crate) fn from_sddl(sddl: &str) -> io::Result<Self> {
+        let sddl = to_wide(sddl);
         let mut sd: *mut std::ffi::c_void = std::ptr::null_mut();
         let ok = unsafe {
             ConvertStringSecurityDescriptorToSecurityDescriptorW(
@@ -271,12 +331,12 @@ impl PipeSecurityDescriptor {
         if ok == 0 {
             Err(io::Error::last_os_error())
         } else {
-            Ok(PipeSecurityDescriptor { ptr: sd })
+            Ok(SecurityDescriptor { ptr: sd })
         }
     }
 }
 
-impl Drop for PipeSecurityDescriptor {
+impl Drop for SecurityDescriptor {
     fn drop(&mut self) {
         // The SD was allocated by LocalAlloc inside the API — free with LocalFree.
         // We intentionally do NOT forget this; the old code used mem::forget because
@@ -297,25 +357,40 @@ pub fn sanitize_session_name(name: &str) -> String {
 }
 
 /// Compute the pipe name for a session.
-/// Format: `\\.\pipe\psmux-{name}`
+/// Format: `\\.\pipe\psmux-{user}-{name}`, or `\\.\pipe\psmux-{user}-{tag}-{name}`
+/// when the session registry lives in a non-default directory (see `registry_tag`).
+/// `{user}` is the SID from the process token, which the environment
+/// cannot change.  `name` is the socket base, so `-L` namespaces stay
+/// apart too.
 pub fn pipe_name_for_session(name: &str) -> String {
+    pipe_name(user_sid().unwrap_or_default(), crate::session::registry_tag().as_deref(), name)
+}
+
+fn pipe_name(user: &str, tag: Option<&str>, name: &str) -> String {
+    let user = match sanitize_session_name(user) {
+        u if u.is_empty() => "user".to_string(),
+        u => u,
+    };
     let safe = sanitize_session_name(name);
-    format!("\\\\.\\pipe\\psmux-{}", safe)
+    match tag {
+        Some(tag) => format!("\\\\.\\pipe\\psmux-{}-{}-{}", user, tag, safe),
+        None => format!("\\\\.\\pipe\\psmux-{}-{}", user, safe),
+    }
 }
 
 /// Create a server-side named pipe instance with cross-session security.
 ///
 /// The pipe uses the SDDL descriptor with a Low Integrity SACL so that
-/// Session 0 (SSH) processes can connect.
+/// Session 0 (SSH) processes can connect, and a DACL limited to the
+/// current user (see `pipe_sddl`).
 ///
 /// `first_instance`: set true for the very first pipe instance to ensure
 /// we own the name. Subsequent instances (for handling concurrent clients)
 /// should pass false.
-pub fn create_server_pipe(session_name: &str, first_instance: bool) -> io::Result<isize> {
-    let pipe_path = pipe_name_for_session(session_name);
-    let wide_name = to_wide(&pipe_path);
+fn create_server_pipe(pipe_path: &str, first_instance: bool) -> io::Result<isize> {
+    let wide_name = to_wide(pipe_path);
 
-    let sd = PipeSecurityDescriptor::new()?;
+    let sd = SecurityDescriptor::from_sddl(&pipe_sddl(user_sid()))?;
     let sa = SECURITY_ATTRIBUTES {
         nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
         lpSecurityDescriptor: sd.ptr,
@@ -351,7 +426,7 @@ pub fn create_server_pipe(session_name: &str, first_instance: bool) -> io::Resul
 
 /// Wait for a client to connect to a server pipe instance.
 /// Blocks until a client connects.
-pub fn wait_for_connection(handle: isize) -> io::Result<()> {
+fn wait_for_connection(handle: isize) -> io::Result<()> {
     let ok = unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) };
     if ok == 0 {
         let err = unsafe { GetLastError() };
@@ -367,15 +442,14 @@ pub fn wait_for_connection(handle: isize) -> io::Result<()> {
 }
 
 /// Disconnect a client from a server pipe instance (allows reuse).
-pub fn disconnect_pipe(handle: isize) {
+fn disconnect_pipe(handle: isize) {
     unsafe { DisconnectNamedPipe(handle); }
 }
 
-/// Connect to a named pipe as a client.
-/// Returns the raw HANDLE on success.
-pub fn connect_to_pipe(session_name: &str, timeout_ms: u32) -> io::Result<isize> {
-    let pipe_path = pipe_name_for_session(session_name);
-    let wide_name = to_wide(&pipe_path);
+/// Connect to the named pipe at `pipe_path` as a client, waiting up to
+/// `timeout_ms` while every instance is busy.
+pub fn connect(pipe_path: &str, timeout_ms: u32) -> io::Result<PipeStream> {
+    let wide_name = to_wide(pipe_path);
 
     // Try to open the pipe directly first
     let handle = unsafe {
@@ -391,7 +465,7 @@ pub fn connect_to_pipe(session_name: &str, timeout_ms: u32) -> io::Result<isize>
     };
 
     if handle != INVALID_HANDLE_VALUE {
-        return Ok(handle);
+        return Ok(PipeStream::from_handle(handle));
     }
 
     let err = unsafe { GetLastError() };
@@ -421,16 +495,15 @@ pub fn connect_to_pipe(session_name: &str, timeout_ms: u32) -> io::Result<isize>
     if handle == INVALID_HANDLE_VALUE {
         Err(io::Error::last_os_error())
     } else {
-        Ok(handle)
+        Ok(PipeStream::from_handle(handle))
     }
 }
 
-/// Quick check whether a named pipe exists for a session.
+/// Quick check whether the named pipe at `pipe_path` exists.
 /// Tries to open the pipe; if it gets ERROR_PIPE_BUSY or succeeds, the pipe exists.
 /// Returns false if ERROR_FILE_NOT_FOUND.
-pub fn pipe_exists(session_name: &str) -> bool {
-    let pipe_path = pipe_name_for_session(session_name);
-    let wide_name = to_wide(&pipe_path);
+pub fn exists(pipe_path: &str) -> bool {
+    let wide_name = to_wide(pipe_path);
 
     let handle = unsafe {
         CreateFileW(
@@ -454,3 +527,58 @@ pub fn pipe_exists(session_name: &str) -> bool {
         err == ERROR_PIPE_BUSY
     }
 }
+
+/// Server side of a session's pipe: one instance per client, the next
+/// created as each one connects.
+pub struct PipeListener {
+    path: String,
+    next: isize,
+}
+
+impl PipeListener {
+    /// Claim session `base`'s pipe name with its first instance.
+    pub fn bind(base: &str) -> io::Result<PipeListener> {
+        let path = pipe_name_for_session(base);
+        let next = create_server_pipe(&path, true)?;
+        Ok(PipeListener { path, next })
+    }
+}
+
+impl Listener for PipeListener {
+    fn accept(&mut self) -> io::Result<Stream> {
+        wait_for_connection(self.next)?;
+        let stream = PipeStream::from_handle(self.next);
+        // Without an instance for the next client this one is still
+        // served; the next accept fails instead
+        self.next = create_server_pipe(&self.path, false).unwrap_or(INVALID_HANDLE_VALUE);
+        Ok(Box::new(stream))
+    }
+
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Pipe(self.path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipe_names_carry_the_user_and_socket() {
+        let sid = "S-1-5-21-1004336348-1177238915-682003330-1001";
+        assert_eq!(pipe_name(sid, None, "work"), format!(r"\\.\pipe\psmux-{}-work", sid));
+        assert_eq!(pipe_name(sid, None, "ci__work"), format!(r"\\.\pipe\psmux-{}-ci__work", sid));
+        assert_eq!(pipe_name(sid, Some("0badf00d"), "work"), format!(r"\\.\pipe\psmux-{}-0badf00d-work", sid));
+        // Without the token's SID
+        assert_eq!(pipe_name("", None, "work"), r"\\.\pipe\psmux-user-work");
+    }
+
+    #[test]
+    fn pipe_sddl_admits_the_user_and_system_only() {
+        let sid = "S-1-5-21-1004336348-1177238915-682003330-1001";
+        assert_eq!(pipe_sddl(Some(sid)), format!("D:P(A;;GA;;;{})(A;;GA;;;SY)S:(ML;;NW;;;LW)", sid));
+        // Without a SID the pipe owner stands in, still behind a protected DACL
+        assert_eq!(pipe_sddl(None), "D:P(A;;GA;;;OW)(A;;GA;;;SY)S:(ML;;NW;;;LW)");
+        assert!(!pipe_sddl(None).contains("WD"), "Everyone must not be granted access");
+    }
+}
@@ -0,0 +1,180 @@
+//! pipe-pane: a pane's output streamed to a command (`-O`, the default),
+//! and the command's output typed into the pane (`-I`).
+//!
+//! The pane's reader thread copies every chunk it reads from the PTY into a
+//! bounded queue (the pane's `pipe_tap`); a writer thread drains the queue
+//! into the command's stdin.  When the command falls behind and the queue
+//! is full, chunks are dropped rather than holding up the pane.  The
+//! command's stdout is read by another thread and handed to the server,
+//! which writes it to the pane on its next pass.
+//!
+//! Closing the pipe (`pipe-pane` with no command, `-o` on a piped pane, or
+//! the pane going away) empties the tap, which closes the command's stdin
+//! once the queue is written out.  The command is then waited for in the
+//! background, and killed if it has not exited after `REAP_GRACE`.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::types::{AppState, Node, Pane, PipePaneState};
+
+/// Chunks of pane output queued for a command before more are dropped.
+pub const QUEUE_CHUNKS: usize = 256;
+
+/// How long a closed pipe's command gets to exit before it is killed.
+pub const REAP_GRACE: Duration = Duration::from_secs(2);
+
+/// Where a pane's reader thread copies its output while a pipe is open.
+pub type Tap = Arc<Mutex<Option<SyncSender<Vec<u8>>>>>;
+
+/// A new pane's tap, closed.
+pub fn new_tap() -> Tap {
+    Arc::new(Mutex::new(None))
+}
+
+/// Copy a chunk of pane output into the tap, dropping it when the command
+/// is behind.
+pub fn tee(tap: &Tap, bytes: &[u8]) {
+    if let Ok(tap) = tap.lock() {
+        if let Some(tx) = tap.as_ref() { let _ = tx.try_send(bytes.to_vec()); }
+    }
+}
+
+/// Start `cmd` on the pane: its output goes to the command with `output`,
+/// the command's output comes back as typing with `input`.
+pub fn start(pane: &mut Pane, cmd: &str, input: bool, output: bool) -> io::Result<()> {
+    let mut child = crate::config::shell_command(cmd, None)
+        .stdin(if output { std::process::Stdio::piped() } else { std::process::Stdio::null() })
+        .stdout(if input { std::process::Stdio::piped() } else { std::process::Stdio::null() })
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_CHUNKS);
+        thread::spawn(move || {
+            for chunk in rx {
+                if stdin.write_all(&chunk).and_then(|_| stdin.flush()).is_err() { break; }
+            }
+        });
+        if let Ok(mut tap) = pane.pipe_tap.lock() { *tap = Some(tx); }
+    }
+    let typed = child.stdout.take().map(|mut stdout| {
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = stdout.read(&mut buf) {
+                if n == 0 || tx.send(buf[..n].to_vec()).is_err() { break; }
+            }
+        });
+        rx
+    });
+    pane.pipe = Some(PipePaneState { process: Some(child), stdin: input, stdout: output, input: typed });
+    Ok(())
+}
+
+/// Close the pane's pipe, if it has one.
+pub fn stop(pane: &mut Pane) {
+    let Some(pipe) = pane.pipe.take() else { return };
+    if let Ok(mut tap) = pane.pipe_tap.lock() { *tap = None; }
+    if let Some(child) = pipe.process { reap(child); }
+}
+
+/// Wait for a closed pipe's command in the background.
+fn reap(mut child: std::process::Child) {
+    thread::spawn(move || {
+        let deadline = Instant::now() + REAP_GRACE;
+        while Instant::now() < deadline {
+            if !matches!(child.try_wait(), Ok(None)) { return; }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+}
+
+/// Type what `-I` commands wrote into their panes.  True when anything was
+/// written.
+pub fn feed_input(app: &mut AppState) -> bool {
+    fn walk(node: &mut Node, fed: &mut bool) {
+        match node {
+            Node::Leaf(p) => {
+                let Some(rx) = p.pipe.as_ref().and_then(|pipe| pipe.input.as_ref()) else { return };
+                let typed: Vec<u8> = rx.try_iter().flatten().collect();
+                if !typed.is_empty() && !p.dead {
+                    crate::input::write_pane_input(&mut *p.writer, &typed);
+                    *fed = true;
+                }
+            }
+            Node::Split { children, .. } => for c in children { walk(c, fed) },
+        }
+    }
+    let mut fed = false;
+    for win in app.windows.iter_mut() { walk(&mut win.root, &mut fed); }
+    fed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> AppState {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        app
+    }
+
+    fn pane(app: &mut AppState) -> &mut Pane {
+        let win = &mut app.windows[0];
+        crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap()
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if done() { return true; }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    // The pipe commands here are sh
+    #[cfg(not(windows))]
+    #[test]
+    fn pane_output_streams_to_the_command_until_the_pipe_closes() {
+        let mut app = app();
+        let log = std::env::temp_dir().join(format!("psmux-pipe-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        start(pane(&mut app), &format!("cat > '{}'", log.display()), false, true).unwrap();
+        assert!(pane(&mut app).pipe.as_ref().is_some_and(|p| p.input.is_none()));
+        // A fresh line first, past the cursor report the pane answered at start
+        crate::input::write_pane_input(&mut *pane(&mut app).writer, b"\recho piped-$((6*7))\r");
+        assert!(wait_for(|| std::fs::read_to_string(&log).is_ok_and(|s| s.contains("piped-42"))));
+
+        // Closing the pipe closes the command's stdin, so cat exits
+        stop(pane(&mut app));
+        assert!(pane(&mut app).pipe.is_none() && pane(&mut app).pipe_tap.lock().unwrap().is_none());
+        crate::input::write_pane_input(&mut *pane(&mut app).writer, b"echo after-$((6*7))\r");
+        thread::sleep(Duration::from_millis(300));
+        assert!(!std::fs::read_to_string(&log).unwrap().contains("after-42"));
+        let _ = std::fs::remove_file(&log);
+        crate::tree::kill_all_children(&mut app.windows[0].root);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn input_pipes_type_the_command_output_into_the_pane() {
+        let mut app = app();
+        start(pane(&mut app), "echo echo typed-$((6*7))", true, false).unwrap();
+        assert!(pane(&mut app).pipe_tap.lock().unwrap().is_none());
+        assert!(wait_for(|| {
+            feed_input(&mut app);
+            pane(&mut app).term.lock().unwrap().screen().contents().contains("typed-42")
+        }));
+        stop(pane(&mut app));
+        crate::tree::kill_all_children(&mut app.windows[0].root);
+    }
+}
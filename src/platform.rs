@@ -725,6 +725,7 @@ pub mod process_info {
         fn CreateToolhelp32Snapshot(dw_flags: u32, th32_process_id: u32) -> isize;
         fn Process32FirstW(h_snapshot: isize, lppe: *mut PROCESSENTRY32W) -> i32;
         fn Process32NextW(h_snapshot: isize, lppe: *mut PROCESSENTRY32W) -> i32;
+        fn GetExitCodeProcess(h_process: isize, exit_code: *mut u32) -> i32;
     }
 
     #[link(name = "ntdll")]
@@ -758,20 +759,51 @@ pub mod process_info {
         }
     }
 
+    /// Whether a process with this PID exists and has not exited.
+    pub fn is_process_alive(pid: u32) -> bool {
+        const STILL_ACTIVE: u32 = 259;
+        unsafe {
+            let h = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if h == 0 || h == -1 { return false; }
+            let mut code: u32 = 0;
+            let ok = GetExitCodeProcess(h, &mut code);
+            CloseHandle(h);
+            ok != 0 && code == STILL_ACTIVE
+        }
+    }
+
     /// Get the current working directory of a process by PID.
     /// Reads the PEB → ProcessParameters → CurrentDirectory from the target process.
     pub fn get_process_cwd(pid: u32) -> Option<String> {
+        // x64: CurrentDirectory.DosPath at 0x38; x86: 0x24
+        let path = read_process_parameter(pid, 0x38, 0x24)?;
+        // Remove trailing backslash (tmux convention)
+        Some(path.trim_end_matches('\\').to_string())
+    }
+
+    /// Get the full command line of a process by PID (PEB → ProcessParameters
+    /// → CommandLine).  None when the process denies access, as elevated
+    /// processes do to an unelevated server.
+    pub fn get_process_command_line(pid: u32) -> Option<String> {
+        // x64: CommandLine at 0x70; x86: 0x40
+        read_process_parameter(pid, 0x70, 0x40)
+    }
+
+    /// Read one UNICODE_STRING field of a process's RTL_USER_PROCESS_PARAMETERS.
+    fn read_process_parameter(pid: u32, x64_offset: isize, x86_offset: isize) -> Option<String> {
         unsafe {
             let h = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, pid);
             if h == 0 || h == -1 { return None; }
-            let result = read_process_cwd(h);
+            let offset = if std::mem::size_of::<usize>() == 8 { x64_offset } else { x86_offset };
+            let result = read_parameter_string(h, offset);
             CloseHandle(h);
             result
         }
     }
 
-    /// Read CWD from a process handle via NtQueryInformationProcess + ReadProcessMemory.
-    unsafe fn read_process_cwd(h: isize) -> Option<String> {
+    /// Read a ProcessParameters string from a process handle via
+    /// NtQueryInformationProcess + ReadProcessMemory.
+    unsafe fn read_parameter_string(h: isize, field_offset: isize) -> Option<String> {
         // Step 1: Get PEB address
         let mut pbi: PROCESS_BASIC_INFORMATION = std::mem::zeroed();
         let mut ret_len: u32 = 0;
@@ -801,37 +833,30 @@ pub mod process_info {
         );
         if ok == 0 || process_params_ptr == 0 { return None; }
 
-        // Step 3: Read CurrentDirectory.DosPath (UNICODE_STRING) from RTL_USER_PROCESS_PARAMETERS.
-        // x64 offset: 0x38 = CurrentDirectory.DosPath
-        // x86 offset: 0x24 = CurrentDirectory.DosPath
-        let cwd_offset = if std::mem::size_of::<usize>() == 8 { 0x38 } else { 0x24 };
-        let mut cwd_ustr: UNICODE_STRING = std::mem::zeroed();
+        // Step 3: Read the field's UNICODE_STRING from RTL_USER_PROCESS_PARAMETERS.
+        let mut ustr: UNICODE_STRING = std::mem::zeroed();
         let ok = ReadProcessMemory(
             h,
-            process_params_ptr + cwd_offset,
-            &mut cwd_ustr as *mut UNICODE_STRING as *mut u8,
+            process_params_ptr + field_offset,
+            &mut ustr as *mut UNICODE_STRING as *mut u8,
             std::mem::size_of::<UNICODE_STRING>(),
             &mut bytes_read,
         );
-        if ok == 0 || cwd_ustr.Length == 0 || cwd_ustr.Buffer == 0 { return None; }
+        if ok == 0 || ustr.Length == 0 || ustr.Buffer == 0 { return None; }
 
-        // Step 4: Read the actual CWD wide string
-        let char_count = (cwd_ustr.Length / 2) as usize;
+        // Step 4: Read the actual wide string
+        let char_count = (ustr.Length / 2) as usize;
         let mut wchars: Vec<u16> = vec![0u16; char_count];
         let ok = ReadProcessMemory(
             h,
-            cwd_ustr.Buffer,
+            ustr.Buffer,
             wchars.as_mut_ptr() as *mut u8,
-            cwd_ustr.Length as usize,
+            ustr.Length as usize,
             &mut bytes_read,
         );
         if ok == 0 { return None; }
 
-        let path = OsString::from_wide(&wchars)
-            .to_string_lossy()
-            .into_owned();
-        // Remove trailing backslash (tmux convention)
-        Some(path.trim_end_matches('\\').to_string())
+        Some(OsString::from_wide(&wchars).to_string_lossy().into_owned())
     }
 
     /// Append a line to ~/.psmux/autorename.log (first 100 entries only).
@@ -875,6 +900,12 @@ pub mod process_info {
         shell_name
     }
 
+    /// PID of the foreground process in the pane: the deepest user
+    /// descendant of the shell, or the shell itself.
+    pub fn get_foreground_pid(pid: u32) -> u32 {
+        find_foreground_child_pid(pid).unwrap_or(pid)
+    }
+
     /// Get the CWD of the foreground process in the pane.
     pub fn get_foreground_cwd(pid: u32) -> Option<String> {
         if let Some(target) = find_foreground_child_pid(pid) {
@@ -1063,7 +1094,215 @@ pub mod process_info {
 pub mod process_info {
     pub fn get_process_name(_pid: u32) -> Option<String> { None }
     pub fn get_process_cwd(_pid: u32) -> Option<String> { None }
+    pub fn get_process_command_line(_pid: u32) -> Option<String> { None }
     pub fn get_foreground_process_name(_pid: u32) -> Option<String> { None }
+    pub fn get_foreground_pid(pid: u32) -> u32 { pid }
     pub fn get_foreground_cwd(_pid: u32) -> Option<String> { None }
     pub fn has_vt_bridge_descendant(_root_pid: u32) -> bool { false }
+    pub fn is_process_alive(_pid: u32) -> bool { true }
+}
+
+// ---------------------------------------------------------------------------
+// Windows clipboard
+// ---------------------------------------------------------------------------
+
+/// Text on the Win32 clipboard (CF_UNICODETEXT).  The clipboard can be held
+/// open by another process for a moment, so opening it is retried briefly.
+#[cfg(windows)]
+pub mod clipboard {
+    use std::thread;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{GlobalFree, HGLOBAL};
+    use windows_sys::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard, SetClipboardData};
+    use windows_sys::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+
+    const CF_UNICODETEXT: u32 = 13;
+
+    fn open() -> bool {
+        for _ in 0..5 {
+            if unsafe { OpenClipboard(std::ptr::null_mut()) } != 0 { return true; }
+            thread::sleep(Duration::from_millis(2));
+        }
+        false
+    }
+
+    /// Replace the clipboard's contents with `text`.  Returns false if the
+    /// clipboard could not be opened or set.
+    pub fn set_text(text: &str) -> bool {
+        if !open() { return false; }
+        let mut utf16: Vec<u16> = text.encode_utf16().collect();
+        utf16.push(0); // null terminator required by CF_UNICODETEXT
+        let size_bytes = utf16.len() * std::mem::size_of::<u16>();
+        let mut hmem: HGLOBAL = std::ptr::null_mut();
+        let mut ok = false;
+        unsafe {
+            if EmptyClipboard() != 0 {
+                hmem = GlobalAlloc(GMEM_MOVEABLE, size_bytes);
+                if !hmem.is_null() {
+                    let dst = GlobalLock(hmem) as *mut u16;
+                    if !dst.is_null() {
+                        std::ptr::copy_nonoverlapping(utf16.as_ptr(), dst, utf16.len());
+                        GlobalUnlock(hmem);
+                        if !SetClipboardData(CF_UNICODETEXT, hmem).is_null() {
+                            // Ownership transferred to the OS on success.
+                            hmem = std::ptr::null_mut();
+                            ok = true;
+                        }
+                    }
+                }
+            }
+            if !hmem.is_null() {
+                let _ = GlobalFree(hmem);
+            }
+            let _ = CloseClipboard();
+        }
+        ok
+    }
+
+    /// The clipboard's text, if it holds any.
+    pub fn get_text() -> Option<String> {
+        if !open() { return None; }
+        unsafe {
+            let hmem = GetClipboardData(CF_UNICODETEXT);
+            let ptr = if hmem.is_null() { std::ptr::null() } else { GlobalLock(hmem) as *const u16 };
+            if ptr.is_null() {
+                let _ = CloseClipboard();
+                return None;
+            }
+            // Find null terminator
+            let mut len = 0usize;
+            while *ptr.add(len) != 0 {
+                len += 1;
+                if len > 1_000_000 { break; } // safety limit
+            }
+            let text = String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len));
+            GlobalUnlock(hmem);
+            let _ = CloseClipboard();
+            Some(text)
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub mod clipboard {
+    pub fn set_text(_text: &str) -> bool { false }
+    pub fn get_text() -> Option<String> { None }
+}
+
+// ---------------------------------------------------------------------------
+// Child exit notification — wait threads instead of polling try_wait
+// ---------------------------------------------------------------------------
+
+/// Pane processes are watched by threads blocked in WaitForMultipleObjects
+/// (63 processes plus a wake event per thread), which call `on_exit` with the
+/// pane id, pid and exit code as soon as a process ends.
+#[cfg(windows)]
+pub mod exit_watch {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const SYNCHRONIZE: u32 = 0x0010_0000;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const INFINITE: u32 = 0xFFFF_FFFF;
+    const WAIT_FAILED: u32 = 0xFFFF_FFFF;
+    /// WaitForMultipleObjects takes at most 64 handles; one is the wake event.
+    const BATCH: usize = 63;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(desired_access: u32, inherit_handle: i32, process_id: u32) -> isize;
+        fn CloseHandle(handle: isize) -> i32;
+        fn CreateEventW(attrs: *const c_void, manual_reset: i32, initial_state: i32, name: *const u16) -> isize;
+        fn SetEvent(event: isize) -> i32;
+        fn WaitForMultipleObjects(count: u32, handles: *const isize, wait_all: i32, millis: u32) -> u32;
+        fn WaitForSingleObject(handle: isize, millis: u32) -> u32;
+        fn GetExitCodeProcess(process: isize, exit_code: *mut u32) -> i32;
+    }
+
+    type OnExit = Arc<dyn Fn(usize, u32, Option<u32>) + Send + Sync>;
+
+    /// One wait thread: its wake event, processes queued for it, and how
+    /// many it is watching or about to watch.
+    struct Batch {
+        wake: isize,
+        queued: Arc<Mutex<Vec<(usize, u32, isize)>>>,
+        load: Arc<AtomicUsize>,
+    }
+
+    pub struct ExitWatcher {
+        batches: Mutex<Vec<Batch>>,
+        on_exit: OnExit,
+    }
+
+    impl ExitWatcher {
+        pub fn new(on_exit: impl Fn(usize, u32, Option<u32>) + Send + Sync + 'static) -> Self {
+            ExitWatcher { batches: Mutex::new(Vec::new()), on_exit: Arc::new(on_exit) }
+        }
+
+        /// Start watching process `pid` of pane `pane_id`.  Returns false if
+        /// the process could not be opened (the periodic sweep still sees it).
+        pub fn watch(&self, pane_id: usize, pid: u32) -> bool {
+            let process = unsafe { OpenProcess(SYNCHRONIZE | PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+            if process == 0 { return false; }
+            let mut batches = match self.batches.lock() { Ok(b) => b, Err(_) => { unsafe { CloseHandle(process); } return false; } };
+            let pos = match batches.iter().position(|b| b.load.load(Ordering::Acquire) < BATCH) {
+                Some(pos) => pos,
+                None => {
+                    let wake = unsafe { CreateEventW(std::ptr::null(), 0, 0, std::ptr::null()) };
+                    if wake == 0 { unsafe { CloseHandle(process); } return false; }
+                    let batch = Batch { wake, queued: Arc::new(Mutex::new(Vec::new())), load: Arc::new(AtomicUsize::new(0)) };
+                    let (queued, load, on_exit) = (batch.queued.clone(), batch.load.clone(), self.on_exit.clone());
+                    std::thread::spawn(move || run_batch(wake, queued, load, on_exit));
+                    batches.push(batch);
+                    batches.len() - 1
+                }
+            };
+            let batch = &batches[pos];
+            batch.load.fetch_add(1, Ordering::AcqRel);
+            if let Ok(mut q) = batch.queued.lock() { q.push((pane_id, pid, process)); }
+            unsafe { SetEvent(batch.wake); }
+            true
+        }
+    }
+
+    fn run_batch(wake: isize, queued: Arc<Mutex<Vec<(usize, u32, isize)>>>, load: Arc<AtomicUsize>, on_exit: OnExit) {
+        let mut watched: Vec<(usize, u32, isize)> = Vec::new();
+        let mut handles: Vec<isize> = Vec::with_capacity(BATCH + 1);
+        loop {
+            if let Ok(mut q) = queued.lock() { watched.append(&mut q); }
+            handles.clear();
+            handles.push(wake);
+            handles.extend(watched.iter().map(|w| w.2));
+            let r = unsafe { WaitForMultipleObjects(handles.len() as u32, handles.as_ptr(), 0, INFINITE) };
+            if r == WAIT_FAILED {
+                // A handle went bad: drop it rather than spin on it
+                watched.retain(|&(_, _, h)| {
+                    let bad = unsafe { WaitForSingleObject(h, 0) } == WAIT_FAILED;
+                    if bad { unsafe { CloseHandle(h); } load.fetch_sub(1, Ordering::AcqRel); }
+                    !bad
+                });
+                continue;
+            }
+            let i = r as usize;
+            // 0 is the wake event: more processes were queued
+            if i == 0 || i >= handles.len() { continue; }
+            let (pane_id, pid, process) = watched.swap_remove(i - 1);
+            let mut code = 0u32;
+            let status = if unsafe { GetExitCodeProcess(process, &mut code) } != 0 { Some(code) } else { None };
+            unsafe { CloseHandle(process); }
+            load.fetch_sub(1, Ordering::AcqRel);
+            on_exit(pane_id, pid, status);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub mod exit_watch {
+    pub struct ExitWatcher;
+
+    impl ExitWatcher {
+        pub fn new(_on_exit: impl Fn(usize, u32, Option<u32>) + Send + Sync + 'static) -> Self { ExitWatcher }
+        pub fn watch(&self, _pane_id: usize, _pid: u32) -> bool { false }
+    }
 }
@@ -0,0 +1,713 @@
+//! display-popup processes.
+//!
+//! Most of the wait before a popup shows anything is ConPTY and pwsh
+//! starting up.  With `popup-warm-shell on` the server keeps a spare: a
+//! shell already running in a hidden PTY, blocked reading one line.  A
+//! popup takes the spare, resizes it and writes its command (base64, not
+//! echoed); the server starts the next spare once that command has exited.
+//! A spare runs exactly one command and exits, so no directory or
+//! environment change survives into the next popup.  Popups given `-d` or
+//! `-e` start cold, as does any popup when no spare is ready.
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use portable_pty::{CommandBuilder, PtySize};
+
+use crate::types::{AppState, Mode, PopupPty};
+
+/// Size a spare is started at; it is resized when taken.
+const SPARE_ROWS: u16 = 24;
+const SPARE_COLS: u16 = 80;
+
+/// Wait before trying again when a spare could not be started.
+const SPARE_RETRY: Duration = Duration::from_secs(30);
+
+/// What a popup runs, and where.
+#[derive(Clone, Debug, Default)]
+pub struct PopupSpec {
+    pub command: String,
+    pub rows: u16,
+    pub cols: u16,
+    /// `-d`: start directory
+    pub start_dir: Option<String>,
+    /// `-e VAR=value`, in order
+    pub env: Vec<(String, String)>,
+}
+
+impl PopupSpec {
+    /// Only a popup that asks for nothing beyond the server's own
+    /// directory and environment can use the spare.
+    fn fits_spare(&self) -> bool {
+        self.start_dir.is_none() && self.env.is_empty()
+    }
+}
+
+/// Key that closes a popup while its command runs (`popup-close-key`).
+pub const DEFAULT_CLOSE_KEY: &str = "C-q";
+
+/// A size or position: a number of cells or a percentage of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Extent {
+    Cells(u16),
+    Percent(u16),
+}
+
+impl Extent {
+    fn parse(v: &str) -> Option<Extent> {
+        match v.strip_suffix('%') {
+            Some(p) => p.parse().ok().filter(|p| *p <= 100).map(Extent::Percent),
+            None => v.parse().ok().map(Extent::Cells),
+        }
+    }
+
+    /// Cells out of `total`.
+    fn cells(self, total: u16) -> u16 {
+        match self {
+            Extent::Cells(n) => n,
+            Extent::Percent(p) => (total as u32 * p as u32 / 100) as u16,
+        }
+    }
+}
+
+/// When a popup closes by itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CloseOn {
+    /// Stays open once its command exits, to be read
+    #[default]
+    Never,
+    /// `-E`: when the command exits
+    Exit,
+    /// `-EE`: when the command exits successfully
+    Success,
+}
+
+impl CloseOn {
+    pub fn closes(self, success: bool) -> bool {
+        match self {
+            CloseOn::Never => false,
+            CloseOn::Exit => true,
+            CloseOn::Success => success,
+        }
+    }
+}
+
+/// `-b` border styles, as in tmux's popup-border-lines.
+const BORDERS: &[&str] = &["single", "rounded", "double", "heavy", "simple", "padded", "none"];
+
+/// The parsed arguments of `display-popup`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupArgs {
+    pub command: String,
+    pub width: Extent,
+    pub height: Extent,
+    /// `-x`/`-y`; None centres the popup (also `-x C`)
+    pub x: Option<Extent>,
+    pub y: Option<Extent>,
+    pub close_on_exit: CloseOn,
+    /// `-T`, format-expanded when the popup opens
+    pub title: String,
+    /// `-b`, one of BORDERS
+    pub border: String,
+    /// `-C`: close the open popup instead
+    pub close: bool,
+    pub start_dir: Option<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl Default for PopupArgs {
+    fn default() -> Self {
+        PopupArgs {
+            command: String::new(),
+            width: Extent::Cells(80),
+            height: Extent::Cells(24),
+            x: None,
+            y: None,
+            close_on_exit: CloseOn::Never,
+            title: String::new(),
+            border: "single".to_string(),
+            close: false,
+            start_dir: None,
+            env: Vec::new(),
+        }
+    }
+}
+
+/// Parse `display-popup` arguments (without the command name).  Flags
+/// that take a value consume it, so `-w 60` never ends up in the command,
+/// and everything from the first other word on is the command.
+pub fn parse_args(args: &[&str]) -> Result<PopupArgs, String> {
+    let mut p = PopupArgs::default();
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).copied();
+        if !words.is_empty() { words.push(args[i]); i += 1; continue; }
+        let flag = args[i];
+        let extent = |v: Option<&str>| v.and_then(Extent::parse).ok_or_else(|| format!("bad {}: {}", flag, v.unwrap_or("")));
+        match flag {
+            "-E" => p.close_on_exit = if p.close_on_exit == CloseOn::Never { CloseOn::Exit } else { CloseOn::Success },
+            "-EE" => p.close_on_exit = CloseOn::Success,
+            "-C" => p.close = true,
+            "-w" => { p.width = extent(value)?; i += 1; }
+            "-h" => { p.height = extent(value)?; i += 1; }
+            "-x" | "-y" => {
+                let at = if value == Some("C") { None } else { Some(extent(value)?) };
+                if flag == "-x" { p.x = at; } else { p.y = at; }
+                i += 1;
+            }
+            "-T" => { p.title = value.unwrap_or("").to_string(); i += 1; }
+            "-b" => {
+                let v = value.unwrap_or("");
+                if !BORDERS.contains(&v) { return Err(format!("unknown border style: {}", v)); }
+                p.border = v.to_string();
+                i += 1;
+            }
+            "-d" | "-c" => { if let Some(v) = value { p.start_dir = Some(v.to_string()); i += 1; } }
+            "-e" => {
+                if let Some((k, v)) = value.and_then(|v| v.split_once('=')) { p.env.push((k.to_string(), v.to_string())); }
+                i += 1;
+            }
+            "-t" | "-s" | "-S" => i += 1,
+            a if a.starts_with('-') => {}
+            a => words.push(a),
+        }
+        i += 1;
+    }
+    p.command = words.join(" ");
+    Ok(p)
+}
+
+/// Where a popup sits in a window of `window` (cols, rows): x, y, width
+/// and height, border included, kept inside the window.
+pub fn place(args: &PopupArgs, window: (u16, u16)) -> (u16, u16, u16, u16) {
+    let (ww, wh) = window;
+    let min = if args.border == "none" { 1 } else { 3 };
+    let width = args.width.cells(ww).clamp(min.min(ww), ww.max(1));
+    let height = args.height.cells(wh).clamp(min.min(wh), wh.max(1));
+    let x = args.x.map_or(ww.saturating_sub(width) / 2, |x| x.cells(ww)).min(ww.saturating_sub(width));
+    let y = args.y.map_or(wh.saturating_sub(height) / 2, |y| y.cells(wh)).min(wh.saturating_sub(height));
+    (x, y, width, height)
+}
+
+/// Rows and columns the border takes from a popup's size.
+fn border_cells(border: &str) -> u16 {
+    if border == "none" { 0 } else { 2 }
+}
+
+impl PopupArgs {
+    /// What to start for these arguments inside a `width`x`height` frame.
+    pub fn spec(&self, width: u16, height: u16) -> PopupSpec {
+        let b = border_cells(&self.border);
+        PopupSpec {
+            command: self.command.clone(),
+            rows: height.saturating_sub(b).max(1),
+            cols: width.saturating_sub(b).max(1),
+            start_dir: self.start_dir.clone(),
+            env: self.env.clone(),
+        }
+    }
+}
+
+/// The spare's script: read a base64 line without echo, then run it.
+fn spare_script() -> &'static str {
+    if cfg!(windows) {
+        "$c = ''; while (($k = [Console]::ReadKey($true)).Key -ne 'Enter') { $c += $k.KeyChar }; \
+         Invoke-Expression ([Text.Encoding]::UTF8.GetString([Convert]::FromBase64String($c)))"
+    } else {
+        "stty -echo; IFS= read -r c; stty echo; printf '\\033[H\\033[2J'; eval \"$(printf %s \"$c\" | base64 -d)\""
+    }
+}
+
+fn shell_builder(script: &str) -> CommandBuilder {
+    let mut cmd = CommandBuilder::new(if cfg!(windows) { "pwsh" } else { "sh" });
+    if cfg!(windows) { cmd.args(["-NoProfile", "-Command", script]); } else { cmd.args(["-c", script]); }
+    if let Ok(dir) = std::env::current_dir() { cmd.cwd(dir); }
+    cmd
+}
+
+fn open_pty(cmd: CommandBuilder, rows: u16, cols: u16) -> Option<PopupPty> {
+    let pty_size = PtySize { rows, cols, pixel_width: 0, pixel_height: 0 };
+    let pair = portable_pty::native_pty_system().openpty(pty_size).ok()?;
+    let child = pair.slave.spawn_command(cmd).ok()?;
+    // Close the slave handle immediately – required for ConPTY.
+    drop(pair.slave);
+    let term = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+    let term_reader = term.clone();
+    if let Ok(mut reader) = pair.master.try_clone_reader() {
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        if let Ok(mut p) = term_reader.lock() { p.process(&buf[..n]); }
+                        crate::types::PTY_DATA_READY.store(true, std::sync::atomic::Ordering::Release);
+                    }
+                    _ => break,
+                }
+            }
+        });
+    }
+    let mut pty_writer = pair.master.take_writer().ok()?;
+    // Only ConPTY consumes the reply; a Unix pty would hand it to the
+    // spare's `read` ahead of the command.
+    if cfg!(windows) { crate::pane::conpty_preemptive_dsr_response(&mut *pty_writer); }
+    Some(PopupPty { master: pair.master, writer: pty_writer, child, term })
+}
+
+/// Start `spec` in a new shell.
+pub fn spawn_cold(spec: &PopupSpec) -> Option<PopupPty> {
+    let mut cmd = shell_builder(&spec.command);
+    if let Some(dir) = &spec.start_dir { cmd.cwd(dir); }
+    for (k, v) in &spec.env { cmd.env(k, v); }
+    open_pty(cmd, spec.rows, spec.cols)
+}
+
+/// Start a spare shell waiting for its command.
+pub fn spawn_spare() -> Option<PopupPty> {
+    open_pty(shell_builder(spare_script()), SPARE_ROWS, SPARE_COLS)
+}
+
+/// Run `spec` in `spare`, or hand the spare back if it has died.
+pub fn start_in_spare(mut spare: PopupPty, spec: &PopupSpec) -> Result<PopupPty, PopupPty> {
+    if !matches!(spare.child.try_wait(), Ok(None)) { return Err(spare); }
+    let size = PtySize { rows: spec.rows, cols: spec.cols, pixel_width: 0, pixel_height: 0 };
+    let _ = spare.master.resize(size);
+    if let Ok(mut p) = spare.term.lock() { p.screen_mut().set_size(spec.rows, spec.cols); }
+    let line = format!("{}\r", crate::util::base64_encode(&spec.command));
+    if spare.writer.write_all(line.as_bytes()).and_then(|_| spare.writer.flush()).is_err() { return Err(spare); }
+    Ok(spare)
+}
+
+/// The PTY for a new popup: the spare when it can be used, else a cold start.
+pub fn start(app: &mut AppState, spec: &PopupSpec) -> Option<PopupPty> {
+    if app.popup_warm_shell && spec.fits_spare() {
+        if let Some(spare) = app.popup_spare.take() {
+            match start_in_spare(spare, spec) {
+                Ok(pty) => return Some(pty),
+                Err(mut dead) => crate::platform::process_kill::kill_process_tree(&mut dead.child),
+            }
+        }
+    }
+    spawn_cold(spec)
+}
+
+/// Stop the spare, if one is running.
+pub fn stop_spare(app: &mut AppState) {
+    if let Some(mut spare) = app.popup_spare.take() {
+        crate::platform::process_kill::kill_process_tree(&mut spare.child);
+    }
+}
+
+/// Keep a spare running while `popup-warm-shell` is on, and stop it once
+/// the option is turned off.  The next spare waits until the open popup's
+/// command has exited, so the two shells never compete for startup time.
+/// A spare that fails to start is retried after [`SPARE_RETRY`].
+pub fn keep_spare_ready(app: &mut AppState) {
+    if !app.popup_warm_shell { stop_spare(app); return; }
+    if app.popup_spare.is_some() { return; }
+    if let Mode::PopupMode { popup_pty: Some(pty), .. } = &mut app.mode {
+        if matches!(pty.child.try_wait(), Ok(None)) { return; }
+    }
+    if app.popup_spare_retry.is_some_and(|t| Instant::now() < t) { return; }
+    app.popup_spare = spawn_spare();
+    app.popup_spare_retry = app.popup_spare.is_none().then(|| Instant::now() + SPARE_RETRY);
+}
+
+/// Open a popup for `args` over the active window, closing the one
+/// already open.
+pub fn open(app: &mut AppState, args: &PopupArgs) {
+    close(app);
+    let (x, y, width, height) = place(args, crate::tree::window_size(app));
+    let title = crate::format::expand_format(&args.title, app);
+    let (output, popup_pty, close_on_exit) = if args.command.is_empty() {
+        ("Press 'q' or Escape to close\n".to_string(), None, CloseOn::Exit)
+    } else {
+        // PTY for interactive programs (fzf, etc.), in the warm spare when possible
+        (String::new(), start(app, &args.spec(width, height)), args.close_on_exit)
+    };
+    app.mode = Mode::PopupMode {
+        command: args.command.clone(),
+        output,
+        process: None,
+        x,
+        y,
+        width,
+        height,
+        close_on_exit,
+        title,
+        border: args.border.clone(),
+        exit_code: None,
+        popup_pty,
+    };
+}
+
+pub fn is_open(app: &AppState) -> bool {
+    matches!(app.mode, Mode::PopupMode { .. })
+}
+
+/// Close the open popup, killing its command and everything it started.
+pub fn close(app: &mut AppState) {
+    if let Mode::PopupMode { popup_pty, process, .. } = &mut app.mode {
+        if let Some(pty) = popup_pty { crate::platform::process_kill::kill_process_tree(&mut pty.child); }
+        if let Some(p) = process { let _ = p.kill(); }
+        app.mode = Mode::Passthrough;
+    }
+}
+
+/// Notice the popup's command exiting: close the popup if `-E`/`-EE` say
+/// so, else keep it open with the exit code shown.  Returns true when the
+/// popup changed.
+pub fn reap(app: &mut AppState) -> bool {
+    let Mode::PopupMode { popup_pty: Some(pty), close_on_exit, exit_code: exit_code @ None, .. } = &mut app.mode else { return false };
+    let Ok(Some(status)) = pty.child.try_wait() else { return false };
+    if close_on_exit.closes(status.success()) {
+        app.mode = Mode::Passthrough;
+    } else {
+        *exit_code = Some(status.exit_code());
+    }
+    true
+}
+
+/// Whether `k` (a key as the client sends it) is `close`.
+fn is_close_key(k: &str, close: &str) -> bool {
+    use crate::config::{normalize_key_for_binding, parse_key_string};
+    match (parse_key_string(k), parse_key_string(close)) {
+        (Some(a), Some(b)) => normalize_key_for_binding(a) == normalize_key_for_binding(b),
+        _ => k.eq_ignore_ascii_case(close),
+    }
+}
+
+/// A key while the popup is open: `popup-close-key` closes it, as does
+/// Escape, `q` or Enter once its command has exited; every other key goes
+/// to the command.
+pub fn key(app: &mut AppState, k: &str) {
+    let Mode::PopupMode { popup_pty, exit_code, .. } = &mut app.mode else { return };
+    let done = popup_pty.is_none() || exit_code.is_some();
+    if is_close_key(k, &app.popup_close_key) || (done && matches!(k, "esc" | "q" | "enter")) {
+        close(app);
+    } else if let Some(pty) = popup_pty.as_mut().filter(|_| !done) {
+        crate::input::write_key(&mut pty.writer, k);
+        let _ = pty.writer.flush();
+    }
+}
+
+/// Text typed while the popup is open goes to its command.
+pub fn text(app: &mut AppState, text: &str) {
+    if is_close_key(text, &app.popup_close_key) { return key(app, text); }
+    let Mode::PopupMode { popup_pty, exit_code, .. } = &mut app.mode else { return };
+    match popup_pty {
+        Some(pty) if exit_code.is_none() => {
+            let _ = pty.writer.write_all(text.as_bytes());
+            let _ = pty.writer.flush();
+        }
+        _ => if text == "q" { close(app); },
+    }
+}
+
+/// A mouse event at window cell (`x`, `y`), for the popup's command when
+/// it is inside the popup and the command asked for the mouse.  Events
+/// outside the popup are dropped, not passed to the panes beneath.
+pub fn mouse(app: &mut AppState, button: u8, x: u16, y: u16, press: bool) {
+    let Mode::PopupMode { popup_pty: Some(pty), x: px, y: py, width, height, border, exit_code: None, .. } = &mut app.mode else { return };
+    let b = border_cells(border) / 2;
+    let (left, top) = (*px + b, *py + b);
+    let (cols, rows) = (width.saturating_sub(2 * b), height.saturating_sub(2 * b));
+    if x < left || y < top || x >= left + cols || y >= top + rows { return; }
+    let Ok(term) = pty.term.lock() else { return };
+    let screen = term.screen();
+    if screen.mouse_protocol_mode() == vt100::MouseProtocolMode::None { return; }
+    let enc = screen.mouse_protocol_encoding();
+    drop(term);
+    crate::window_ops::write_mouse_event_remote(&mut *pty.writer, button, x - left + 1, y - top + 1, press, enc);
+}
+
+/// The popup as it goes into a frame's `"popup"`: null when none is
+/// open, else where it sits (window cells, border included), its title
+/// and border, and its screen.
+pub fn frame_json(app: &AppState) -> String {
+    let Mode::PopupMode { command, output, x, y, width, height, title, border, exit_code, popup_pty, .. } = &app.mode else {
+        return "null".to_string();
+    };
+    let b = border_cells(border);
+    let (rows, cols) = (height.saturating_sub(b), width.saturating_sub(b));
+    let mut title = if title.is_empty() { command.clone() } else { title.clone() };
+    if let Some(code) = exit_code {
+        title = format!("{} [exited {}]", title, code).trim_start().to_string();
+    }
+    let mut out = format!("{{\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"title\":{},\"border\":\"{}\",",
+        x, y, width, height, serde_json::Value::from(title), border);
+    let (cursor_row, cursor_col, cursor_hidden) = match popup_pty.as_ref().and_then(|p| p.term.lock().ok()) {
+        Some(term) => {
+            let screen = term.screen();
+            crate::layout::write_runs(&crate::layout::snapshot_runs(screen, rows, cols), &mut out);
+            let (r, c) = screen.cursor_position();
+            (r, c, screen.hide_cursor() || exit_code.is_some())
+        }
+        None => {
+            let lines: Vec<String> = output.lines().take(rows as usize).map(str::to_string).collect();
+            crate::layout::write_plain(&lines, &mut out);
+            (0, 0, true)
+        }
+    };
+    let _ = std::fmt::Write::write_fmt(&mut out, format_args!(",\"cursor_row\":{},\"cursor_col\":{},\"cursor_hidden\":{}}}", cursor_row, cursor_col, cursor_hidden));
+    out
+}
+
+/// A frame's `"popup"`, as the client reads it.
+#[derive(serde::Deserialize)]
+pub struct PopupJson {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub border: String,
+    #[serde(default)]
+    pub rows_v2: Vec<crate::layout::RowRunsJson>,
+    #[serde(default)]
+    pub cursor_row: u16,
+    #[serde(default)]
+    pub cursor_col: u16,
+    #[serde(default)]
+    pub cursor_hidden: bool,
+}
+
+/// The lines of a `-b` border style.
+fn border_set(border: &str) -> Option<ratatui::symbols::border::Set<'static>> {
+    use ratatui::symbols::border;
+    Some(match border {
+        "none" => return None,
+        "rounded" => border::ROUNDED,
+        "double" => border::DOUBLE,
+        "heavy" => border::THICK,
+        "padded" => border::EMPTY,
+        "simple" => border::Set {
+            top_left: "+", top_right: "+", bottom_left: "+", bottom_right: "+",
+            vertical_left: "|", vertical_right: "|", horizontal_top: "-", horizontal_bottom: "-",
+        },
+        _ => border::PLAIN,
+    })
+}
+
+/// Draw the popup at `area` on the client's terminal.  Returns where its
+/// cursor goes, if it shows one.
+pub fn render(f: &mut ratatui::Frame, p: &PopupJson, area: ratatui::layout::Rect) -> Option<(u16, u16)> {
+    use ratatui::style::{Modifier, Style};
+    use ratatui::text::{Line, Span};
+    use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+    use crate::rendering::map_color;
+
+    let area = area.intersection(f.area());
+    f.render_widget(Clear, area);
+    let inner = match border_set(&p.border) {
+        Some(set) => {
+            let mut block = Block::default().borders(Borders::ALL).border_set(set);
+            if !p.title.is_empty() { block = block.title(p.title.as_str()); }
+            let inner = block.inner(area);
+            f.render_widget(block, area);
+            inner
+        }
+        None => area,
+    };
+    let lines: Vec<Line> = p.rows_v2.iter().take(inner.height as usize).map(|row| {
+        Line::from(row.runs.iter().map(|run| {
+            let (mut fg, mut bg) = (map_color(&run.fg), map_color(&run.bg));
+            if run.flags & 16 != 0 { std::mem::swap(&mut fg, &mut bg); }
+            let mut style = Style::default().fg(fg).bg(bg);
+            for (bit, m) in [(1, Modifier::DIM), (2, Modifier::BOLD), (4, Modifier::ITALIC), (8, Modifier::UNDERLINED)] {
+                if run.flags & bit != 0 { style = style.add_modifier(m); }
+            }
+            Span::styled(if run.text.is_empty() { " " } else { run.text.as_str() }, style)
+        }).collect::<Vec<_>>())
+    }).collect();
+    f.render_widget(Paragraph::new(lines), inner);
+    (!p.cursor_hidden && p.cursor_row < inner.height && p.cursor_col < inner.width)
+        .then_some((inner.x + p.cursor_col, inner.y + p.cursor_row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(command: &str) -> PopupSpec {
+        PopupSpec { command: command.to_string(), rows: 10, cols: 60, ..Default::default() }
+    }
+
+    /// Wait for the popup's command to finish and return its screen.
+    fn finish(mut pty: PopupPty) -> String {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while matches!(pty.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+        let screen = pty.term.lock().unwrap().screen().contents();
+        screen
+    }
+
+    #[test]
+    fn popup_arguments() {
+        let p = parse_args(&["-E", "-w", "60", "-h", "50%", "-d", "C:\\src", "-e", "A=1", "-e", "B=x=y", "fzf", "-e", "-m"]).unwrap();
+        assert_eq!(p.command, "fzf -e -m");
+        assert_eq!((p.width, p.height, p.close_on_exit), (Extent::Cells(60), Extent::Percent(50), CloseOn::Exit));
+        assert_eq!(p.start_dir.as_deref(), Some("C:\\src"));
+        assert_eq!(p.env, [("A".to_string(), "1".to_string()), ("B".to_string(), "x=y".to_string())]);
+        assert!(!p.spec(60, 20).fits_spare());
+        let p = parse_args(&["-x", "10", "htop"]).unwrap();
+        assert_eq!((p.command.as_str(), p.width, p.x, p.close_on_exit), ("htop", Extent::Cells(80), Some(Extent::Cells(10)), CloseOn::Never));
+        assert!(p.spec(80, 24).fits_spare());
+
+        let p = parse_args(&["-EE", "-T", "pick a file", "-b", "rounded", "-x", "C", "-y", "0", "fzf"]).unwrap();
+        assert_eq!((p.close_on_exit, p.title.as_str(), p.border.as_str()), (CloseOn::Success, "pick a file", "rounded"));
+        assert_eq!((p.x, p.y), (None, Some(Extent::Cells(0))));
+        assert_eq!(parse_args(&["-E", "-E", "x"]).unwrap().close_on_exit, CloseOn::Success);
+        assert!(parse_args(&["-C"]).unwrap().close);
+        assert!(parse_args(&["-b", "wavy", "x"]).is_err());
+        assert!(parse_args(&["-w", "120%", "x"]).is_err());
+        assert!(parse_args(&["-h", "tall", "x"]).is_err());
+    }
+
+    #[test]
+    fn popups_are_placed_inside_the_window() {
+        let args = |a: &[&str]| parse_args(a).unwrap();
+        // Centred by default, 80x24 when it fits
+        assert_eq!(place(&args(&["x"]), (120, 40)), (20, 8, 80, 24));
+        // Percentages of the window
+        assert_eq!(place(&args(&["-w", "50%", "-h", "80%", "x"]), (120, 40)), (30, 4, 60, 32));
+        // Too big for a small window: shrunk to it
+        assert_eq!(place(&args(&["x"]), (40, 10)), (0, 0, 40, 10));
+        // A position that would push it off the edge is pulled back
+        assert_eq!(place(&args(&["-w", "20", "-h", "5", "-x", "110", "-y", "2", "x"]), (120, 40)), (100, 2, 20, 5));
+        // Never smaller than its border
+        assert_eq!(place(&args(&["-w", "0", "-h", "1", "x"]), (120, 40)).2, 3);
+        // The command gets the cells inside the border
+        let p = args(&["-w", "30", "-h", "10", "x"]);
+        assert_eq!((p.spec(30, 10).cols, p.spec(30, 10).rows), (28, 8));
+        let p = args(&["-b", "none", "x"]);
+        assert_eq!((p.spec(30, 10).cols, p.spec(30, 10).rows), (30, 10));
+    }
+
+    #[test]
+    fn close_key_matches_as_the_client_sends_it() {
+        assert!(is_close_key("C-q", "C-q"));
+        assert!(is_close_key("C-q", "C-Q"));
+        assert!(is_close_key("esc", "Escape"));
+        assert!(is_close_key("f12", "F12"));
+        assert!(!is_close_key("q", "C-q"));
+        assert!(!is_close_key("M-q", "C-q"));
+    }
+
+    #[test]
+    fn exit_closes_only_when_asked_to() {
+        assert!(!CloseOn::Never.closes(true));
+        assert!(CloseOn::Exit.closes(false));
+        assert!(CloseOn::Success.closes(true));
+        assert!(!CloseOn::Success.closes(false));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn spare_shells_do_not_leak_state_between_popups() {
+        let spare = spawn_spare().unwrap();
+        let first_pid = spare.child.process_id();
+        std::thread::sleep(Duration::from_millis(300));
+        let first = "cd / && export POPUP_LEAK=1 && echo \"first:$PWD:$POPUP_LEAK\"";
+        let screen = finish(start_in_spare(spare, &spec(first)).ok().unwrap());
+        assert!(screen.contains("first:/:1"), "{}", screen);
+        // The command arrives hidden, not echoed onto the popup
+        assert!(!screen.contains(&crate::util::base64_encode(first)[..8]), "{}", screen);
+
+        let spare = spawn_spare().unwrap();
+        assert_ne!(spare.child.process_id(), first_pid);
+        std::thread::sleep(Duration::from_millis(300));
+        let screen = finish(start_in_spare(spare, &spec("echo \"second:$PWD:[$POPUP_LEAK]\"")).ok().unwrap());
+        let cwd = std::env::current_dir().unwrap();
+        assert!(screen.contains(&format!("second:{}:[]", cwd.display())), "{}", screen);
+    }
+
+    #[cfg(not(windows))]
+    fn app_with_window() -> AppState {
+        let mut app = AppState::new("t".to_string());
+        app.popup_warm_shell = false;
+        app.last_window_area = ratatui::layout::Rect::new(0, 0, 100, 30);
+        app
+    }
+
+    /// Wait for the open popup's command to exit (or the popup to close).
+    #[cfg(not(windows))]
+    fn reaped(app: &mut AppState) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if reap(app) { return true; }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn keys_go_to_the_popup_until_the_close_key() {
+        let mut app = app_with_window();
+        open(&mut app, &parse_args(&["-w", "40", "-h", "10", "-T", "#S popup", "-b", "double", "cat"]).unwrap());
+        assert!(is_open(&app));
+        text(&mut app, "typed");
+        key(&mut app, "enter");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut frame = serde_json::Value::Null;
+        while Instant::now() < deadline {
+            frame = serde_json::from_str(&frame_json(&app)).unwrap();
+            if frame["rows_v2"][1]["runs"][0]["text"].as_str().is_some_and(|t| t.starts_with("typed")) { break; }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!((frame["x"].as_u64(), frame["y"].as_u64(), frame["width"].as_u64(), frame["height"].as_u64()), (Some(30), Some(10), Some(40), Some(10)));
+        assert_eq!((frame["title"].as_str(), frame["border"].as_str()), (Some("t popup"), Some("double")));
+        assert_eq!(frame["rows_v2"].as_array().unwrap().len(), 8);
+        // cat echoed the line back: typed, then its copy
+        assert!(frame["rows_v2"][1]["runs"][0]["text"].as_str().unwrap().starts_with("typed"), "{}", frame);
+        // Escape and q are the command's while it runs
+        key(&mut app, "esc");
+        text(&mut app, "q");
+        assert!(is_open(&app));
+        key(&mut app, "C-q");
+        assert!(!is_open(&app));
+        assert_eq!(frame_json(&app), "null");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn exit_status_decides_whether_the_popup_stays() {
+        let mut app = app_with_window();
+        open(&mut app, &parse_args(&["-EE", "exit 3"]).unwrap());
+        assert!(reaped(&mut app));
+        assert!(is_open(&app), "-EE keeps a failed command's popup");
+        let frame: serde_json::Value = serde_json::from_str(&frame_json(&app)).unwrap();
+        assert!(frame["title"].as_str().unwrap().ends_with("[exited 3]"), "{}", frame);
+        // Once the command is gone, q closes it
+        text(&mut app, "q");
+        assert!(!is_open(&app));
+
+        open(&mut app, &parse_args(&["-EE", "exit 0"]).unwrap());
+        assert!(reaped(&mut app));
+        assert!(!is_open(&app));
+        open(&mut app, &parse_args(&["exit 0"]).unwrap());
+        assert!(reaped(&mut app));
+        assert!(is_open(&app), "without -E the popup stays to be read");
+        key(&mut app, "esc");
+        assert!(!is_open(&app));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn directory_and_environment_overrides_start_cold() {
+        let mut s = spec("echo \"cold:$PWD:$POPUP_VAR\"");
+        s.start_dir = Some("/".into());
+        s.env.push(("POPUP_VAR".into(), "x".into()));
+        assert!(!s.fits_spare());
+        assert!(spec("true").fits_spare());
+        let screen = finish(spawn_cold(&s).unwrap());
+        assert!(screen.contains("cold:/:x"), "{}", screen);
+    }
+}
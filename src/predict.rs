@@ -0,0 +1,310 @@
+//! Local echo: the client draws what the user types before the server
+//! echoes it back (`local-echo`), so typing over a slow link does not wait a
+//! round trip per key.
+//!
+//! Each forwarded key that can be predicted becomes a [`Step`]: the cell it
+//! is expected to change and where it should leave the cursor.  The client
+//! draws the pending steps dimmed over the last frame.  When a frame
+//! arrives, the newest step it agrees with (cursor in the same place, cell
+//! showing the same text) is confirmed and drops out along with every step
+//! before it.  A step the server has not confirmed within the timeout means
+//! the prediction was wrong — most likely echo is off, as at a password
+//! prompt — so all predictions are dropped and no more are made until Enter.
+//!
+//! Only typing at the end of the input on one row is predicted: printable
+//! characters, Backspace and Left back to where the typing started.  Any
+//! other key pauses prediction until the frames have caught up with it, and
+//! nothing is predicted on the alternate screen, in copy mode, or after a
+//! prompt that asks for a password.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Round trip above which `local-echo auto` predicts.
+pub const AUTO_RTT: Duration = Duration::from_millis(100);
+
+/// How long a prediction may go unconfirmed with no round-trip estimate.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One predicted key.
+#[derive(Clone, Debug, PartialEq)]
+struct Step {
+    /// (row, col, char) the key is expected to draw, if any
+    cell: Option<(u16, u16, char)>,
+    /// Cursor position after the key
+    cursor: (u16, u16),
+    at: Instant,
+}
+
+/// What the client knows of the active pane from the latest frame.
+pub struct PaneFrame<'a> {
+    pub pane: usize,
+    pub cursor: (u16, u16),
+    pub cols: u16,
+    /// Typing here can be predicted: main screen, live view, visible
+    /// cursor, not in copy mode
+    pub predictable: bool,
+    /// Character at (row, col)
+    pub cell: &'a dyn Fn(u16, u16) -> char,
+}
+
+#[derive(Default)]
+pub struct Predictor {
+    steps: VecDeque<Step>,
+    pane: Option<usize>,
+    /// Cursor after the pending steps (the server's when there are none)
+    cursor: (u16, u16),
+    cols: u16,
+    predictable: bool,
+    /// Where the typing being predicted started; Backspace and Left stop here
+    start: Option<(u16, u16)>,
+    /// Rightmost column the typing has reached
+    end: u16,
+    /// No predictions before this time (after a key that was not predicted)
+    paused_until: Option<Instant>,
+    /// A prediction failed; none until Enter
+    suppressed: bool,
+    timeout: Option<Duration>,
+}
+
+impl Predictor {
+    /// Base the confirmation timeout on the link's round trip.
+    pub fn set_rtt(&mut self, rtt: Option<Duration>) {
+        self.timeout = rtt.map(|r| (r * 3).clamp(Duration::from_millis(300), Duration::from_secs(2)));
+    }
+
+    fn timeout(&self) -> Duration { self.timeout.unwrap_or(DEFAULT_TIMEOUT) }
+
+    /// Take in a new frame from the server.
+    pub fn frame(&mut self, now: Instant, f: &PaneFrame) {
+        if self.pane != Some(f.pane) || !f.predictable {
+            self.reset();
+            self.pane = Some(f.pane);
+        }
+        self.cols = f.cols;
+        self.predictable = f.predictable;
+        let confirmed = self.steps.iter().rposition(|s| {
+            s.cursor == f.cursor && s.cell.is_none_or(|(r, c, ch)| (f.cell)(r, c) == ch)
+        });
+        if let Some(k) = confirmed { self.steps.drain(..=k); }
+        if self.steps.is_empty() { self.cursor = f.cursor; }
+        self.expire(now);
+    }
+
+    /// Drop everything if the oldest prediction has gone unconfirmed for too
+    /// long.  Returns true when predictions were dropped.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let stale = self.steps.front().is_some_and(|s| now.duration_since(s.at) > self.timeout());
+        if stale {
+            self.steps.clear();
+            self.start = None;
+            self.suppressed = true;
+        }
+        stale
+    }
+
+    fn can_predict(&self, now: Instant) -> bool {
+        self.pane.is_some() && self.predictable && !self.suppressed
+            && self.paused_until.is_none_or(|t| now >= t)
+    }
+
+    fn push(&mut self, now: Instant, cell: Option<(u16, u16, char)>, cursor: (u16, u16)) {
+        self.steps.push_back(Step { cell, cursor, at: now });
+        self.cursor = cursor;
+    }
+
+    /// A printable character was sent.  Returns true if it was predicted.
+    pub fn type_char(&mut self, now: Instant, ch: char) -> bool {
+        let (row, col) = self.cursor;
+        let narrow = !ch.is_control() && unicode_width::UnicodeWidthChar::width(ch) == Some(1);
+        // Typing over text in the middle of a line would shift it; not predicted
+        let at_end = self.start.is_none_or(|s| s.0 == row && col >= self.end);
+        if !self.can_predict(now) || !narrow || !at_end || col + 1 >= self.cols {
+            self.pause(now);
+            return false;
+        }
+        if self.start.is_none() { self.start = Some((row, col)); }
+        self.end = col + 1;
+        self.push(now, Some((row, col, ch)), (row, col + 1));
+        true
+    }
+
+    /// Backspace was sent.  Returns true if it was predicted.
+    pub fn backspace(&mut self, now: Instant) -> bool {
+        let (row, col) = self.cursor;
+        match self.start {
+            Some(s) if self.can_predict(now) && s.0 == row && col > s.1 && col == self.end => {
+                self.end = col - 1;
+                self.push(now, Some((row, col - 1, ' ')), (row, col - 1));
+                true
+            }
+            _ => { self.pause(now); false }
+        }
+    }
+
+    /// Left was sent.  Returns true if it was predicted.
+    pub fn left(&mut self, now: Instant) -> bool {
+        let (row, col) = self.cursor;
+        match self.start {
+            Some(s) if self.can_predict(now) && s.0 == row && col > s.1 => {
+                self.push(now, None, (row, col - 1));
+                true
+            }
+            _ => { self.pause(now); false }
+        }
+    }
+
+    /// Enter was sent: a new line of input, which may predict again.
+    pub fn enter(&mut self, now: Instant) {
+        self.suppressed = false;
+        self.pause(now);
+    }
+
+    /// Any other key: its effect is unknown, so stop predicting until the
+    /// frames have had a round trip to catch up.
+    pub fn pause(&mut self, now: Instant) {
+        self.start = None;
+        self.paused_until = Some(now + self.timeout.map_or(Duration::from_millis(300), |t| t / 3));
+    }
+
+    /// Forget every prediction, e.g. when the active pane changes.
+    pub fn reset(&mut self) {
+        self.steps.clear();
+        self.start = None;
+        self.paused_until = None;
+    }
+
+    /// Cells to draw over the frame, oldest first.
+    pub fn cells(&self) -> impl Iterator<Item = (u16, u16, char)> + '_ {
+        self.steps.iter().filter_map(|s| s.cell)
+    }
+
+    /// Where to show the cursor while predictions are pending.
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        (!self.steps.is_empty()).then_some(self.cursor)
+    }
+
+    pub fn is_empty(&self) -> bool { self.steps.is_empty() }
+}
+
+/// Does the text before the cursor look like a prompt for a secret, which
+/// is typed without echo?
+pub fn secret_prompt(line: &str) -> bool {
+    let line = line.trim_end().to_lowercase();
+    line.ends_with(':') && ["password", "passphrase", "passcode", "pin", "secret", "token"].iter().any(|w| line.contains(w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pane showing `rows` with the cursor at `cursor`.
+    fn frame(now: Instant, p: &mut Predictor, rows: &[&str], cursor: (u16, u16)) {
+        let grid: Vec<Vec<char>> = rows.iter().map(|r| r.chars().collect()).collect();
+        let cell = move |r: u16, c: u16| grid.get(r as usize).and_then(|l| l.get(c as usize)).copied().unwrap_or(' ');
+        p.frame(now, &PaneFrame { pane: 1, cursor, cols: 20, predictable: true, cell: &cell });
+    }
+
+    /// The frame's rows with the predictions drawn over them, and the cursor.
+    fn composite(p: &Predictor, rows: &[&str], cursor: (u16, u16)) -> (Vec<String>, (u16, u16)) {
+        let mut grid: Vec<Vec<char>> = rows.iter().map(|r| format!("{:20}", r).chars().collect()).collect();
+        for (r, c, ch) in p.cells() { grid[r as usize][c as usize] = ch; }
+        (grid.iter().map(|l| l.iter().collect::<String>().trim_end().to_string()).collect(), p.cursor().unwrap_or(cursor))
+    }
+
+    #[test]
+    fn typed_characters_show_until_echoed() {
+        let t = Instant::now();
+        let mut p = Predictor::default();
+        frame(t, &mut p, &["$ "], (0, 2));
+        assert!(p.type_char(t, 'l'));
+        assert!(p.type_char(t, 's'));
+        assert_eq!(composite(&p, &["$ "], (0, 2)), (vec!["$ ls".to_string()], (0, 4)));
+
+        // The echo of the first key arrives: only the second is still predicted
+        frame(t, &mut p, &["$ l"], (0, 3));
+        assert_eq!(p.cells().collect::<Vec<_>>(), [(0, 3, 's')]);
+        assert_eq!(composite(&p, &["$ l"], (0, 3)), (vec!["$ ls".to_string()], (0, 4)));
+
+        // A frame that is behind (cursor not at either step) confirms nothing
+        frame(t, &mut p, &["$ l"], (0, 3));
+        assert_eq!(p.cells().count(), 1);
+        frame(t, &mut p, &["$ ls"], (0, 4));
+        assert!(p.is_empty());
+        assert_eq!(composite(&p, &["$ ls"], (0, 4)), (vec!["$ ls".to_string()], (0, 4)));
+    }
+
+    #[test]
+    fn backspace_and_left_stop_at_the_start_of_the_typing() {
+        let t = Instant::now();
+        let mut p = Predictor::default();
+        frame(t, &mut p, &["> "], (0, 2));
+        for ch in "cat".chars() { assert!(p.type_char(t, ch)); }
+        assert!(p.backspace(t));
+        assert_eq!(composite(&p, &["> "], (0, 2)), (vec!["> ca".to_string()], (0, 4)));
+        assert!(p.left(t));
+        assert!(p.left(t));
+        assert!(!p.left(t), "never left of where typing started");
+        // Typing in the middle of the line would insert; not predicted
+        assert!(!p.type_char(t, 'x'));
+
+        // The server catches up with all of it at once
+        frame(t, &mut p, &["> ca"], (0, 2));
+        assert!(p.is_empty());
+        assert_eq!(p.cursor(), None);
+    }
+
+    #[test]
+    fn an_unechoed_prediction_times_out_and_suppresses_until_enter() {
+        let t = Instant::now();
+        let mut p = Predictor::default();
+        p.set_rtt(Some(Duration::from_millis(200)));
+        frame(t, &mut p, &["Password: "], (0, 10));
+        assert!(p.type_char(t, 'h'));
+        // No echo comes back
+        let later = t + Duration::from_millis(700);
+        frame(later, &mut p, &["Password: "], (0, 10));
+        assert!(p.is_empty());
+        assert_eq!(composite(&p, &["Password: "], (0, 10)), (vec!["Password:".to_string()], (0, 10)));
+        assert!(!p.type_char(later, 'u'));
+        assert!(!p.expire(later + Duration::from_secs(5)));
+
+        p.enter(later);
+        let next = later + Duration::from_secs(1);
+        frame(next, &mut p, &["Password: ", "$ "], (1, 2));
+        assert!(p.type_char(next, 'x'));
+    }
+
+    #[test]
+    fn other_keys_pause_and_panes_reset() {
+        let t = Instant::now();
+        let mut p = Predictor::default();
+        frame(t, &mut p, &["$ gi"], (0, 4));
+        p.pause(t); // Tab: completion output is unknown
+        assert!(!p.type_char(t, 't'));
+        let later = t + Duration::from_millis(400);
+        frame(later, &mut p, &["$ git "], (0, 6));
+        assert!(p.type_char(later, 's'));
+        assert_eq!(p.cells().collect::<Vec<_>>(), [(0, 6, 's')]);
+
+        // Another pane: predictions for the old one go
+        let cell = |_: u16, _: u16| ' ';
+        p.frame(later, &PaneFrame { pane: 2, cursor: (0, 0), cols: 20, predictable: true, cell: &cell });
+        assert!(p.is_empty());
+        // Nor are any made where typing is not predictable
+        p.frame(later, &PaneFrame { pane: 2, cursor: (0, 0), cols: 20, predictable: false, cell: &cell });
+        assert!(!p.type_char(later, 'a'));
+        // Or at the right edge, where the shell would wrap
+        p.frame(later, &PaneFrame { pane: 2, cursor: (0, 19), cols: 20, predictable: true, cell: &cell });
+        assert!(!p.type_char(later + Duration::from_secs(1), 'a'));
+    }
+
+    #[test]
+    fn secret_prompts() {
+        assert!(secret_prompt("[sudo] password for wojtek: "));
+        assert!(secret_prompt("Enter passphrase for key '/home/u/.ssh/id_ed25519':"));
+        assert!(secret_prompt("Enter PIN:"));
+        assert!(!secret_prompt("PS C:\\> "));
+        assert!(!secret_prompt("$ echo password"));
+    }
+}
@@ -0,0 +1,492 @@
+//! command-prompt: asking for one or more answers and running a command
+//! made from them.
+//!
+//! `command-prompt -p "name:,dir:" -I "#{window_name},~" "new-window -n '%1' -c '%2'"`
+//! asks each prompt of `-p` in turn, with the matching value of `-I`
+//! already typed, then runs the template with `%1`...`%9` replaced by the
+//! answers (`%%` by the first).  Without a template the answer is the
+//! command.  Prompts and initial values are formats, expanded when the
+//! prompt opens.
+//!
+//! The server sends the current client a `%prompt` directive with the
+//! expanded prompts and the session's command history; the client edits
+//! the line (see [`Open::key`]), sends each answer back with
+//! `client-prompt-history` for the history, then the command.
+//!
+//! confirm-before is a prompt too: a single key, `y` or `Y` runs the
+//! command and any other key cancels (with `-y`, Enter runs it as well).
+//! The default `x` and `&` bindings go through it while `confirm-kill` is
+//! on.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Position, Rect};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+use crate::types::AppState;
+
+/// A command-prompt ready to show.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Prompt {
+    /// Each prompt and the value it starts with
+    pub prompts: Vec<(String, String)>,
+    /// The command to run with the answers in it; None runs the answer
+    #[serde(default)]
+    pub template: Option<String>,
+    /// `-1`: the first key pressed is the answer
+    #[serde(default)]
+    pub single_key: bool,
+    /// `-N`: only digits may be typed
+    #[serde(default)]
+    pub numeric: bool,
+    /// Earlier answers, oldest first, for Up and Down
+    #[serde(default)]
+    pub history: Vec<String>,
+    /// confirm-before: `y` runs the template, anything else cancels
+    #[serde(default)]
+    pub confirm: bool,
+    /// confirm-before `-y`: Enter confirms too
+    #[serde(default)]
+    pub default_yes: bool,
+}
+
+/// The `x` binding while `confirm-kill` is on.
+pub const KILL_PANE: &str = "confirm-before -p \"kill-pane #P? (y/n)\" kill-pane";
+/// The `&` binding while `confirm-kill` is on.
+pub const KILL_WINDOW: &str = "confirm-before -p \"kill-window #W? (y/n)\" kill-window";
+
+/// command-prompt's arguments: `-p` prompts and `-I` initial values, both
+/// comma-separated, `-1`, `-N`, and the template.
+pub fn parse_args(args: &[&str]) -> Result<Prompt, String> {
+    let (mut labels, mut initials) = (None, None);
+    let mut p = Prompt::default();
+    let mut template = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "-p" | "-I" | "-T" => {
+                let v = args.get(i + 1).ok_or_else(|| format!("{} needs a value", args[i]))?;
+                match args[i] {
+                    "-p" => labels = Some(v.to_string()),
+                    "-I" => initials = Some(v.to_string()),
+                    _ => {}
+                }
+                i += 1;
+            }
+            "-1" => p.single_key = true,
+            "-N" => p.numeric = true,
+            "-b" | "-F" | "-k" | "-W" => {}
+            a if a.starts_with('-') && a.len() > 1 && template.is_empty() => return Err(format!("unknown flag {}", a)),
+            a => template.push(a),
+        }
+        i += 1;
+    }
+    if !template.is_empty() { p.template = Some(template.join(" ")); }
+    let labels: Vec<String> = match labels {
+        Some(l) => l.split(',').map(|s| format!("{} ", s)).collect(),
+        // As tmux: the template's command in brackets, or a bare colon
+        None => vec![match p.template.as_deref().and_then(|t| t.split_whitespace().next()) {
+            Some(cmd) => format!("({}) ", cmd),
+            None => ":".to_string(),
+        }],
+    };
+    let initials: Vec<&str> = initials.as_deref().map_or(Vec::new(), |s| s.split(',').collect());
+    let n = labels.len().max(initials.len());
+    p.prompts = (0..n).map(|i| {
+        let label = labels.get(i).or(labels.last()).cloned().unwrap_or_default();
+        (label, initials.get(i).unwrap_or(&"").to_string())
+    }).collect();
+    Ok(p)
+}
+
+/// confirm-before's arguments: `-p` prompt, `-y`, `-b` (accepted: no
+/// prompt holds up the command that opened it) and the command.
+pub fn parse_confirm(args: &[&str]) -> Result<Prompt, String> {
+    let (mut label, mut default_yes) = (None, false);
+    let mut i = 0;
+    while i < args.len() {
+        match args[i] {
+            "-p" => {
+                label = Some(args.get(i + 1).ok_or("-p needs a value")?.to_string());
+                i += 1;
+            }
+            "-y" => default_yes = true,
+            "-b" => {}
+            "-t" => i += 1,
+            a if a.starts_with('-') && a.len() > 1 => return Err(format!("unknown flag {}", a)),
+            _ => break,
+        }
+        i += 1;
+    }
+    let command = args[i..].join(" ");
+    if command.is_empty() { return Err("no command to confirm".to_string()); }
+    let label = label.unwrap_or_else(|| format!("Confirm '{}'? (y/n)", command));
+    Ok(Prompt { default_yes, ..confirm(label, command) })
+}
+
+/// A confirm-before prompt asking `label` before running `command`.
+pub fn confirm(label: impl Into<String>, command: impl Into<String>) -> Prompt {
+    Prompt { prompts: vec![(label.into(), String::new())], template: Some(command.into()), confirm: true, ..Prompt::default() }
+}
+
+/// `template` with `%1`...`%9` replaced by the answers and `%%` by the
+/// first; a missing answer is empty.
+pub fn substitute(template: &str, answers: &[String]) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        let n = match (c, chars.peek()) {
+            ('%', Some('%')) => 1,
+            ('%', Some(d @ '1'..='9')) => *d as usize - '0' as usize,
+            _ => { out.push(c); continue; }
+        };
+        chars.next();
+        out.push_str(answers.get(n - 1).map_or("", |a| a.as_str()));
+    }
+    out
+}
+
+/// The command the answers make: the template filled in, or the answer.
+pub fn command(p: &Prompt, answers: &[String]) -> String {
+    match &p.template {
+        Some(t) => substitute(t, answers),
+        None => answers.first().cloned().unwrap_or_default(),
+    }
+}
+
+/// Expand the prompts and initial values and attach the history.
+pub fn expand(app: &mut AppState, mut p: Prompt) -> Prompt {
+    for (label, initial) in p.prompts.iter_mut() {
+        *label = crate::format::expand_format(label, app);
+        *initial = crate::format::expand_format(initial, app);
+    }
+    if !p.confirm { p.history = app.command_history.clone(); }
+    p
+}
+
+/// The `%prompt` directive that opens `p` on a client.
+pub fn directive(p: &Prompt) -> String {
+    format!("%prompt {}", serde_json::to_string(p).unwrap_or_default())
+}
+
+/// Open `p` on the current client; without one, the server's own prompt
+/// opens with the first initial value, or its confirmation.
+pub fn show(app: &mut AppState, p: Prompt) {
+    let p = expand(app, p);
+    match crate::bandwidth::current_client(app) {
+        Some(client) => { client.send_directive(directive(&p)); }
+        None if p.confirm => {
+            let prompt = p.prompts.first().map(|(l, _)| l.clone()).unwrap_or_default();
+            app.mode = crate::types::Mode::ConfirmMode { prompt, command: p.template.unwrap_or_default(), default_yes: p.default_yes };
+        }
+        None => {
+            let input = p.prompts.first().map(|(_, i)| i.clone()).unwrap_or_default();
+            app.command_history_idx = app.command_history.len();
+            app.mode = crate::types::Mode::CommandPrompt { cursor: input.len(), input };
+        }
+    }
+}
+
+/// Command names, for Tab.
+fn command_names() -> impl Iterator<Item = &'static str> {
+    crate::server::TMUX_COMMANDS.iter().map(|c| c.split(' ').next().unwrap_or(c))
+}
+
+/// One line being edited; `cursor` is a byte offset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineEditor {
+    pub text: String,
+    pub cursor: usize,
+}
+
+impl LineEditor {
+    pub fn new(text: &str) -> Self {
+        LineEditor { text: text.to_string(), cursor: text.len() }
+    }
+
+    fn prev(&self, at: usize) -> usize {
+        self.text[..at].char_indices().next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next(&self, at: usize) -> usize {
+        self.text[at..].chars().next().map_or(at, |c| at + c.len_utf8())
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Where the word before the cursor starts, skipping spaces first.
+    fn word_start(&self) -> usize {
+        let before = &self.text[..self.cursor];
+        let trimmed = before.trim_end_matches(' ');
+        trimmed.rfind(' ').map_or(0, |i| i + 1)
+    }
+
+    /// Complete the command name under the cursor: a single match is
+    /// filled in with a space after it, several to their common prefix.
+    pub fn complete<'a>(&mut self, names: impl Iterator<Item = &'a str>) {
+        if self.text[..self.cursor].contains(' ') { return; }
+        let word = &self.text[..self.cursor];
+        let matches: Vec<&str> = names.filter(|n| n.starts_with(word)).collect();
+        let Some(first) = matches.first() else { return };
+        let common = matches.iter().fold(first.len(), |len, m| {
+            first.bytes().zip(m.bytes()).take(len).take_while(|(a, b)| a == b).count()
+        });
+        let mut done = first[..common].to_string();
+        if matches.len() == 1 { done.push(' '); }
+        let rest = self.text[self.cursor..].trim_start_matches(' ').to_string();
+        self.cursor = done.len();
+        self.text = done + &rest;
+    }
+
+    /// Apply an editing key; false if it is not one.
+    pub fn key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match (key.code, ctrl) {
+            (KeyCode::Left, _) | (KeyCode::Char('b'), true) => self.cursor = self.prev(self.cursor),
+            (KeyCode::Right, _) | (KeyCode::Char('f'), true) => self.cursor = self.next(self.cursor),
+            (KeyCode::Home, _) | (KeyCode::Char('a'), true) => self.cursor = 0,
+            (KeyCode::End, _) | (KeyCode::Char('e'), true) => self.cursor = self.text.len(),
+            (KeyCode::Char('u'), true) => { self.text.drain(..self.cursor); self.cursor = 0; }
+            (KeyCode::Char('k'), true) => self.text.truncate(self.cursor),
+            (KeyCode::Char('w'), true) => {
+                let start = self.word_start();
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            (KeyCode::Backspace, _) | (KeyCode::Char('h'), true) => {
+                let start = self.prev(self.cursor);
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            (KeyCode::Delete, _) => {
+                let end = self.next(self.cursor);
+                self.text.drain(self.cursor..end);
+            }
+            (KeyCode::Char(c), false) => self.insert(c),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// What a key does to an open prompt.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Stay,
+    Close,
+    /// The answers given, then the command to run
+    Run(Vec<String>, String),
+}
+
+/// A prompt open on the client.
+pub struct Open {
+    pub prompt: Prompt,
+    pub answers: Vec<String>,
+    pub line: LineEditor,
+    /// The history entry Up and Down are on; `history.len()` is the line
+    /// being typed, kept in `draft`
+    hist: usize,
+    draft: String,
+}
+
+impl Open {
+    pub fn new(prompt: Prompt) -> Self {
+        let line = LineEditor::new(prompt.prompts.first().map_or("", |(_, i)| i.as_str()));
+        let hist = prompt.history.len();
+        Open { prompt, answers: Vec::new(), line, hist, draft: String::new() }
+    }
+
+    /// The prompt being answered.
+    pub fn label(&self) -> &str {
+        self.prompt.prompts.get(self.answers.len()).map_or("", |(l, _)| l.as_str())
+    }
+
+    /// Recall history entry `to` (or the draft past the newest).
+    fn recall(&mut self, to: usize) {
+        if self.hist == self.prompt.history.len() { self.draft = self.line.text.clone(); }
+        self.hist = to;
+        let text = self.prompt.history.get(to).unwrap_or(&self.draft).clone();
+        self.line = LineEditor::new(&text);
+    }
+
+    /// Take the answer typed and go on to the next prompt, or run.
+    fn answer(&mut self, text: String) -> Outcome {
+        self.answers.push(text);
+        match self.prompt.prompts.get(self.answers.len()) {
+            Some((_, initial)) => {
+                self.line = LineEditor::new(initial);
+                self.hist = self.prompt.history.len();
+                Outcome::Stay
+            }
+            None => Outcome::Run(self.answers.clone(), command(&self.prompt, &self.answers)),
+        }
+    }
+
+    /// A confirmation takes one key (see the module docs).  Otherwise
+    /// Enter answers; Esc (or C-c, C-g) closes; Up and Down go through
+    /// the history; Tab completes a command name when there is no
+    /// template; the rest edit the line (see [`LineEditor::key`]).
+    pub fn key(&mut self, key: KeyEvent) -> Outcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.prompt.confirm {
+            return match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') if !ctrl => Outcome::Run(Vec::new(), command(&self.prompt, &[])),
+                KeyCode::Enter if self.prompt.default_yes => Outcome::Run(Vec::new(), command(&self.prompt, &[])),
+                _ => Outcome::Close,
+            };
+        }
+        if self.prompt.single_key {
+            return match key.code {
+                KeyCode::Esc => Outcome::Close,
+                KeyCode::Char(c) if !ctrl => self.answer(c.to_string()),
+                KeyCode::Enter => self.answer(String::new()),
+                _ => Outcome::Stay,
+            };
+        }
+        match key.code {
+            KeyCode::Esc => return Outcome::Close,
+            KeyCode::Char('c') | KeyCode::Char('g') if ctrl => return Outcome::Close,
+            KeyCode::Enter => return self.answer(self.line.text.clone()),
+            KeyCode::Up if self.hist > 0 => self.recall(self.hist - 1),
+            KeyCode::Down if self.hist < self.prompt.history.len() => self.recall(self.hist + 1),
+            KeyCode::Tab if self.prompt.template.is_none() => self.line.complete(command_names()),
+            KeyCode::Char(c) if self.prompt.numeric && !ctrl && !c.is_ascii_digit() => {}
+            _ => { self.line.key(key); }
+        }
+        Outcome::Stay
+    }
+
+    /// Draw the prompt in a box over `area`, scrolled to keep the cursor
+    /// in view, and put the cursor on it.
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let overlay = Block::default().borders(Borders::ALL).title(if self.prompt.confirm { "confirm" } else { "command" });
+        f.render_widget(Clear, area);
+        f.render_widget(&overlay, area);
+        let inner = overlay.inner(area);
+        let label = self.label();
+        if self.prompt.confirm {
+            f.render_widget(Paragraph::new(label), inner);
+            return;
+        }
+        let room = (inner.width as usize).saturating_sub(label.width() + 1);
+        let (before, after) = self.line.text.split_at(self.line.cursor);
+        let mut shown_before = before;
+        while shown_before.width() > room {
+            let mut chars = shown_before.chars();
+            chars.next();
+            shown_before = chars.as_str();
+        }
+        let text = format!("{}{}{}", label, shown_before, after);
+        f.render_widget(Paragraph::new(text), inner);
+        let x = inner.x + (label.width() + shown_before.width()) as u16;
+        if x < inner.right() { f.set_cursor_position(Position { x, y: inner.y }); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent { KeyEvent::new(code, KeyModifiers::NONE) }
+    fn ctrl(c: char) -> KeyEvent { KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL) }
+    fn typed(open: &mut Open, s: &str) -> Outcome {
+        let mut last = Outcome::Stay;
+        for c in s.chars() { last = open.key(key(KeyCode::Char(c))); }
+        last
+    }
+
+    #[test]
+    fn prompts_initial_values_and_templates() {
+        let p = parse_args(&["-p", "name:,dir:", "-I", "#{window_name},~", "new-window -n '%1' -c '%2'"]).unwrap();
+        assert_eq!(p.prompts, vec![("name: ".to_string(), "#{window_name}".to_string()), ("dir: ".to_string(), "~".to_string())]);
+        assert_eq!(p.template.as_deref(), Some("new-window -n '%1' -c '%2'"));
+
+        // Without -p: the template's command, or a colon
+        assert_eq!(parse_args(&["rename-window", "'%%'"]).unwrap().prompts, vec![("(rename-window) ".to_string(), String::new())]);
+        assert_eq!(parse_args(&["-I", "neww"]).unwrap().prompts, vec![(":".to_string(), "neww".to_string())]);
+        assert!(parse_args(&["-x"]).is_err());
+
+        let answers = ["a b".to_string(), "c".to_string()];
+        assert_eq!(substitute("rename-window '%%'", &answers), "rename-window 'a b'");
+        assert_eq!(substitute("x %2 %1 %3 50%", &answers), "x c a b  50%");
+    }
+
+    #[test]
+    fn answers_are_collected_in_turn_then_run() {
+        let p = parse_args(&["-p", "one,two", "-I", "x", "display '%1-%2'"]).unwrap();
+        let mut open = Open::new(p);
+        assert_eq!(open.line.text, "x");
+        assert_eq!(open.label(), "one ");
+        typed(&mut open, "y");
+        assert_eq!(open.key(key(KeyCode::Enter)), Outcome::Stay);
+        assert_eq!((open.label(), open.line.text.as_str()), ("two ", ""));
+        typed(&mut open, "z");
+        let run = open.key(key(KeyCode::Enter));
+        assert_eq!(run, Outcome::Run(vec!["xy".into(), "z".into()], "display 'xy-z'".into()));
+
+        // -1: a key is the answer; -N: only digits go in
+        let mut open = Open::new(parse_args(&["-1", "select-window -t %%"]).unwrap());
+        assert_eq!(typed(&mut open, "3"), Outcome::Run(vec!["3".into()], "select-window -t 3".into()));
+        let mut open = Open::new(parse_args(&["-N"]).unwrap());
+        typed(&mut open, "1a2");
+        assert_eq!(open.line.text, "12");
+        assert_eq!(open.key(ctrl('g')), Outcome::Close);
+    }
+
+    #[test]
+    fn confirmations_take_a_single_key() {
+        let p = parse_confirm(&["-p", "kill #W? (y/n)", "kill-window", "-a"]).unwrap();
+        assert_eq!((p.prompts[0].0.as_str(), p.template.as_deref()), ("kill #W? (y/n)", Some("kill-window -a")));
+        assert_eq!(parse_confirm(&["-b", "kill-pane"]).unwrap().prompts[0].0, "Confirm 'kill-pane'? (y/n)");
+        assert!(parse_confirm(&["-p", "sure?"]).is_err());
+
+        let run = Outcome::Run(Vec::new(), "kill-pane".into());
+        assert_eq!(Open::new(confirm("?", "kill-pane")).key(key(KeyCode::Char('Y'))), run);
+        for k in [KeyCode::Char('n'), KeyCode::Char('x'), KeyCode::Enter, KeyCode::Esc] {
+            assert_eq!(Open::new(confirm("?", "kill-pane")).key(key(k)), Outcome::Close);
+        }
+        let mut yes = parse_confirm(&["-y", "kill-pane"]).unwrap();
+        assert_eq!(Open::new(yes.clone()).key(key(KeyCode::Enter)), run);
+        yes.default_yes = false;
+        assert_eq!(Open::new(yes).key(key(KeyCode::Enter)), Outcome::Close);
+    }
+
+    #[test]
+    fn line_editing_history_and_completion() {
+        let mut line = LineEditor::new("split-window -h");
+        line.key(ctrl('w'));
+        assert_eq!(line.text, "split-window ");
+        line.key(ctrl('a'));
+        line.key(key(KeyCode::Right));
+        line.key(key(KeyCode::Delete));
+        assert_eq!((line.text.as_str(), line.cursor), ("slit-window ", 1));
+        line.key(ctrl('e'));
+        line.insert('é');
+        line.key(key(KeyCode::Left));
+        line.key(ctrl('u'));
+        assert_eq!((line.text.as_str(), line.cursor), ("é", 0));
+
+        let mut open = Open::new(Prompt { prompts: vec![(":".into(), String::new())], history: vec!["a".into(), "b".into()], ..Prompt::default() });
+        typed(&mut open, "dr");
+        open.key(key(KeyCode::Up));
+        assert_eq!(open.line.text, "b");
+        open.key(key(KeyCode::Up));
+        open.key(key(KeyCode::Up));
+        assert_eq!(open.line.text, "a");
+        open.key(key(KeyCode::Down));
+        open.key(key(KeyCode::Down));
+        assert_eq!(open.line.text, "dr");
+
+        open.line = LineEditor::new("ren");
+        open.key(key(KeyCode::Tab));
+        assert_eq!(open.line.text, "rename-");
+        typed(&mut open, "w");
+        open.key(key(KeyCode::Tab));
+        assert_eq!((open.line.text.as_str(), open.line.cursor), ("rename-window ", 14));
+    }
+}
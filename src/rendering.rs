@@ -1,4 +1,5 @@
-//! TUI rendering — pane tree rendering, separator drawing, cursor positioning.
+//! TUI rendering helpers shared with the client — colors, cursor style and
+//! positioning, popup layout.
 //!
 //! Style/color parsing is in `style.rs`; this module re-exports it for
 //! backward compatibility so `use crate::rendering::*` still works.
@@ -6,30 +7,15 @@
 use std::io::{self, Write};
 use std::env;
 use ratatui::prelude::*;
-use ratatui::widgets::*;
-use ratatui::style::{Style, Modifier};
-use unicode_width::UnicodeWidthStr;
 use crossterm::style::Print;
 use crossterm::execute;
-use portable_pty::PtySize;
-
-use crate::types::{AppState, Mode, Node, LayoutKind};
-use crate::tree::split_with_gaps;
 
 // Re-export style utilities so existing `use crate::rendering::*` still works.
 pub use crate::style::{
     map_color, parse_tmux_style, parse_inline_styles,
 };
 
-// ─── VT color helpers ───────────────────────────────────────────────────────
-
-pub fn vt_to_color(c: vt100::Color) -> Color {
-    match c {
-        vt100::Color::Default => Color::Reset,
-        vt100::Color::Idx(i) => Color::Indexed(i),
-        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
-    }
-}
+// ─── Color helpers ──────────────────────────────────────────────────────────
 
 pub fn dim_color(c: Color) -> Color {
     match c {
@@ -65,236 +51,58 @@ pub fn apply_cursor_style<W: Write>(out: &mut W) -> io::Result<()> {
     Ok(())
 }
 
-// ─── Pane tree rendering ────────────────────────────────────────────────────
-
-pub fn render_window(f: &mut Frame, app: &mut AppState, area: Rect) {
-    let dim_preds = app.prediction_dimming;
-    let border_style = parse_tmux_style(&app.pane_border_style);
-    let active_border_style = parse_tmux_style(&app.pane_active_border_style);
-    let copy_cursor = if matches!(app.mode, Mode::CopyMode) { app.copy_pos } else { None };
-    let win = &mut app.windows[app.active_idx];
-    let active_rect = compute_active_rect(&win.root, &win.active_path, area);
-    render_node(f, &mut win.root, &win.active_path, &mut Vec::new(), area, dim_preds, border_style, active_border_style, copy_cursor, active_rect);
+/// Where the terminal cursor goes for a pane, or `None` to leave it hidden.
+/// Only the active pane shows a cursor; in copy mode it is the copy cursor
+/// (never the app's), otherwise the app's cursor unless the app hid it with
+/// DECTCEM.  Both the local and the remote renderer decide through this.
+pub fn pane_cursor_position(
+    active: bool,
+    copy_mode: bool,
+    app_cursor_hidden: bool,
+    copy_cursor: Option<(u16, u16)>,
+    app_cursor: (u16, u16),
+) -> Option<(u16, u16)> {
+    if !active { return None; }
+    if copy_mode { return copy_cursor; }
+    if app_cursor_hidden { return None; }
+    Some(app_cursor)
 }
 
-pub fn render_node(
-    f: &mut Frame,
-    node: &mut Node,
-    active_path: &Vec<usize>,
-    cur_path: &mut Vec<usize>,
-    area: Rect,
-    dim_preds: bool,
-    border_style: Style,
-    active_border_style: Style,
-    copy_cursor: Option<(u16, u16)>,
-    active_rect: Option<Rect>,
-) {
-    match node {
-        Node::Leaf(pane) => {
-            let is_active = *cur_path == *active_path;
-            let inner = area;
-            let target_rows = inner.height.max(1);
-            let target_cols = inner.width.max(1);
-            if pane.last_rows != target_rows || pane.last_cols != target_cols {
-                let _ = pane.master.resize(PtySize { rows: target_rows, cols: target_cols, pixel_width: 0, pixel_height: 0 });
-                if let Ok(mut parser) = pane.term.lock() {
-                    parser.screen_mut().set_size(target_rows, target_cols);
-                }
-                pane.last_rows = target_rows;
-                pane.last_cols = target_cols;
-            }
-            let parser_guard = pane.term.lock();
-            let Ok(parser) = parser_guard else { return; };
-            let screen = parser.screen();
-            let (cur_r, cur_c) = screen.cursor_position();
-            let mut lines: Vec<Line> = Vec::with_capacity(target_rows as usize);
-            for r in 0..target_rows {
-                let mut spans: Vec<Span> = Vec::with_capacity(target_cols as usize);
-                let mut c = 0;
-                while c < target_cols {
-                    if let Some(cell) = screen.cell(r, c) {
-                        let mut fg = vt_to_color(cell.fgcolor());
-                        let mut bg = vt_to_color(cell.bgcolor());
-                        if cell.inverse() { std::mem::swap(&mut fg, &mut bg); }
-                        if dim_preds && !screen.alternate_screen()
-                            && (r > cur_r || (r == cur_r && c >= cur_c))
-                        {
-                            fg = dim_color(fg);
-                        }
-                        let mut style = Style::default().fg(fg).bg(bg);
-                        if cell.dim() { style = style.add_modifier(Modifier::DIM); }
-                        if cell.bold() { style = style.add_modifier(Modifier::BOLD); }
-                        if cell.italic() { style = style.add_modifier(Modifier::ITALIC); }
-                        if cell.underline() { style = style.add_modifier(Modifier::UNDERLINED); }
-                        let text = cell.contents().to_string();
-                        let w = UnicodeWidthStr::width(text.as_str()) as u16;
-                        if w == 0 {
-                            spans.push(Span::styled(" ", style));
-                            c += 1;
-                        } else if w >= 2 {
-                            spans.push(Span::styled(text, style));
-                            c += 2;
-                        } else {
-                            spans.push(Span::styled(text, style));
-                            c += 1;
-                        }
-                    } else {
-                        spans.push(Span::raw(" "));
-                        c += 1;
-                    }
-                }
-                lines.push(Line::from(spans));
-            }
-            f.render_widget(Clear, inner);
-            let para = Paragraph::new(Text::from(lines));
-            f.render_widget(para, inner);
-            if is_active {
-                let (cr, cc) = copy_cursor.unwrap_or_else(|| screen.cursor_position());
-                let cr = cr.min(target_rows.saturating_sub(1));
-                let cc = cc.min(target_cols.saturating_sub(1));
-                let cx = inner.x + cc;
-                let cy = inner.y + cr;
-                f.set_cursor_position((cx, cy));
-            }
-        }
-        Node::Split { kind, sizes, children } => {
-            let effective_sizes: Vec<u16> = if sizes.len() == children.len() {
-                sizes.clone()
-            } else { vec![100 / children.len().max(1) as u16; children.len()] };
-            let is_horizontal = *kind == LayoutKind::Horizontal;
-            let rects = split_with_gaps(is_horizontal, &effective_sizes, area);
-            for (i, child) in children.iter_mut().enumerate() {
-                cur_path.push(i);
-                if i < rects.len() {
-                    render_node(f, child, active_path, cur_path, rects[i], dim_preds, border_style, active_border_style, copy_cursor, active_rect);
-                }
-                cur_path.pop();
-            }
-            // Draw separator lines
-            let buf = f.buffer_mut();
-            for i in 0..children.len().saturating_sub(1) {
-                if i >= rects.len() { break; }
-                let both_leaves = matches!(&children[i], Node::Leaf(_))
-                    && matches!(children.get(i + 1), Some(Node::Leaf(_)));
+/// Indicator shown on a pane whose view is scrolled back without copy mode.
+pub fn view_scroll_label(lines_below: usize) -> String {
+    format!("[following: off ({} lines below)]", lines_below)
+}
 
-                if is_horizontal {
-                    let sep_x = rects[i].x + rects[i].width;
-                    if sep_x < buf.area.x + buf.area.width {
-                        if both_leaves {
-                            let left_active = cur_path.len() < active_path.len()
-                                && active_path[..cur_path.len()] == cur_path[..]
-                                && active_path[cur_path.len()] == i;
-                            let right_active = cur_path.len() < active_path.len()
-                                && active_path[..cur_path.len()] == cur_path[..]
-                                && active_path[cur_path.len()] == i + 1;
-                            let left_sty = if left_active { active_border_style } else { border_style };
-                            let right_sty = if right_active { active_border_style } else { border_style };
-                            let mid_y = area.y + area.height / 2;
-                            for y in area.y..area.y + area.height {
-                                let sty = if y < mid_y { left_sty } else { right_sty };
-                                let idx = (y - buf.area.y) as usize * buf.area.width as usize + (sep_x - buf.area.x) as usize;
-                                if idx < buf.content.len() {
-                                    buf.content[idx].set_char('│');
-                                    buf.content[idx].set_style(sty);
-                                }
-                            }
-                        } else {
-                            for y in area.y..area.y + area.height {
-                                let active = active_rect.map_or(false, |ar| {
-                                    y >= ar.y && y < ar.y + ar.height
-                                    && (sep_x == ar.x + ar.width || sep_x + 1 == ar.x)
-                                });
-                                let sty = if active { active_border_style } else { border_style };
-                                let idx = (y - buf.area.y) as usize * buf.area.width as usize + (sep_x - buf.area.x) as usize;
-                                if idx < buf.content.len() {
-                                    buf.content[idx].set_char('│');
-                                    buf.content[idx].set_style(sty);
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    let sep_y = rects[i].y + rects[i].height;
-                    if sep_y < buf.area.y + buf.area.height {
-                        if both_leaves {
-                            let top_active = cur_path.len() < active_path.len()
-                                && active_path[..cur_path.len()] == cur_path[..]
-                                && active_path[cur_path.len()] == i;
-                            let bot_active = cur_path.len() < active_path.len()
-                                && active_path[..cur_path.len()] == cur_path[..]
-                                && active_path[cur_path.len()] == i + 1;
-                            let top_sty = if top_active { active_border_style } else { border_style };
-                            let bot_sty = if bot_active { active_border_style } else { border_style };
-                            let mid_x = area.x + area.width / 2;
-                            for x in area.x..area.x + area.width {
-                                let sty = if x < mid_x { top_sty } else { bot_sty };
-                                let idx = (sep_y - buf.area.y) as usize * buf.area.width as usize + (x - buf.area.x) as usize;
-                                if idx < buf.content.len() {
-                                    buf.content[idx].set_char('─');
-                                    buf.content[idx].set_style(sty);
-                                }
-                            }
-                        } else {
-                            for x in area.x..area.x + area.width {
-                                let active = active_rect.map_or(false, |ar| {
-                                    x >= ar.x && x < ar.x + ar.width
-                                    && (sep_y == ar.y + ar.height || sep_y + 1 == ar.y)
-                                });
-                                let sty = if active { active_border_style } else { border_style };
-                                let idx = (sep_y - buf.area.y) as usize * buf.area.width as usize + (x - buf.area.x) as usize;
-                                if idx < buf.content.len() {
-                                    buf.content[idx].set_char('─');
-                                    buf.content[idx].set_style(sty);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Banner on the last row of a pane kept by remain-on-exit.
+pub fn dead_pane_banner(status: Option<u32>) -> String {
+    match status {
+        Some(n) => format!("Pane is dead (exited with status {}), press Enter to respawn or x to kill", n),
+        None => "Pane is dead, press Enter to respawn or x to kill".to_string(),
     }
 }
 
-// ─── Layout helpers ─────────────────────────────────────────────────────────
-
-/// Compute the rectangle of the active pane by following the active_path through the tree.
-fn compute_active_rect(node: &Node, active_path: &[usize], area: Rect) -> Option<Rect> {
-    match node {
-        Node::Leaf(_) => Some(area),
-        Node::Split { kind, sizes, children } => {
-            if active_path.is_empty() || children.is_empty() { return None; }
-            let idx = active_path[0];
-            if idx >= children.len() { return None; }
-            let effective_sizes: Vec<u16> = if sizes.len() == children.len() {
-                sizes.clone()
-            } else {
-                vec![100 / children.len().max(1) as u16; children.len()]
-            };
-            let is_horizontal = *kind == LayoutKind::Horizontal;
-            let rects = split_with_gaps(is_horizontal, &effective_sizes, area);
-            if idx < rects.len() {
-                compute_active_rect(&children[idx], &active_path[1..], rects[idx])
-            } else {
-                None
-            }
+/// A `pane-border-status` line in `width` columns: the expanded
+/// `pane-border-format` with its `#[...]` styles over `base`, cut short with
+/// `…` when it does not fit.
+pub fn border_status_spans(text: &str, width: u16, base: Style) -> Vec<Span<'static>> {
+    let spans = parse_inline_styles(text, base);
+    if spans.iter().map(|s| s.width()).sum::<usize>() <= width as usize { return spans; }
+    if width == 0 { return Vec::new(); }
+    let mut left = width as usize - 1;
+    let mut out = Vec::new();
+    for s in spans {
+        let mut cut = String::new();
+        for ch in s.content.chars() {
+            let w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+            if w > left { left = 0; break; }
+            left -= w;
+            cut.push(ch);
         }
+        out.push(Span::styled(cut, s.style));
+        if left == 0 { break; }
     }
-}
-
-// ─── Status bar convenience wrappers (delegate to style.rs) ─────────────────
-
-/// Expand simple status variables using AppState context.
-pub fn expand_status(fmt: &str, app: &AppState, time_str: &str) -> String {
-    let window = &app.windows[app.active_idx];
-    let win_idx = app.active_idx + app.window_base_index;
-    crate::style::expand_status(fmt, &app.session_name, &window.name, win_idx, time_str)
-}
-
-/// Parse a status format string with AppState context into styled spans.
-pub fn parse_status(fmt: &str, app: &AppState, time_str: &str) -> Vec<Span<'static>> {
-    let window = &app.windows[app.active_idx];
-    let win_idx = app.active_idx + app.window_base_index;
-    crate::style::parse_status(fmt, &app.session_name, &window.name, win_idx, time_str)
+    out.push(Span::styled("…", base));
+    out
 }
 
 // ─── UI layout helpers ──────────────────────────────────────────────────────
@@ -313,3 +121,74 @@ pub fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
     let x = middle.x + (middle.width - width) / 2;
     Rect { x, y: middle.y, width, height }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pane parser with the cursor moved off the origin, optionally hidden.
+    fn parser_with_cursor(hidden: bool) -> vt100::Parser {
+        let mut parser = vt100::Parser::new(10, 40, 0);
+        parser.process(b"prompt> \x1b[3;5H");
+        if hidden { parser.process(b"\x1b[?25l"); }
+        parser
+    }
+
+    fn request(parser: &vt100::Parser, active: bool, copy_mode: bool, copy_cursor: Option<(u16, u16)>) -> Option<(u16, u16)> {
+        let screen = parser.screen();
+        pane_cursor_position(active, copy_mode, screen.hide_cursor(), copy_cursor, screen.cursor_position())
+    }
+
+    #[test]
+    fn visible_app_cursor_is_requested_for_active_pane_only() {
+        let parser = parser_with_cursor(false);
+        assert_eq!(request(&parser, true, false, None), Some((2, 4)));
+        assert_eq!(request(&parser, false, false, None), None);
+    }
+
+    #[test]
+    fn dectcem_hidden_cursor_is_not_requested() {
+        let mut parser = parser_with_cursor(true);
+        assert_eq!(request(&parser, true, false, None), None);
+        // A zoomed pane is still just the active pane
+        assert_eq!(request(&parser, true, false, Some((0, 0))), None);
+        parser.process(b"\x1b[?25h");
+        assert_eq!(request(&parser, true, false, None), Some((2, 4)));
+    }
+
+    #[test]
+    fn copy_mode_uses_copy_cursor_regardless_of_app_visibility() {
+        for hidden in [false, true] {
+            let parser = parser_with_cursor(hidden);
+            assert_eq!(request(&parser, true, true, Some((7, 1))), Some((7, 1)));
+            // No copy cursor yet: never fall back to the app cursor
+            assert_eq!(request(&parser, true, true, None), None);
+            assert_eq!(request(&parser, false, true, Some((7, 1))), None);
+        }
+    }
+
+    #[test]
+    fn leaving_copy_mode_drops_stale_copy_cursor() {
+        let parser = parser_with_cursor(true);
+        // The last copy position must not be shown as the app cursor
+        assert_eq!(request(&parser, true, false, Some((7, 1))), None);
+        let parser = parser_with_cursor(false);
+        assert_eq!(request(&parser, true, false, Some((7, 1))), Some((2, 4)));
+    }
+
+    #[test]
+    fn border_status_lines_are_cut_with_an_ellipsis() {
+        let text = |spans: &[Span]| spans.iter().map(|s| s.content.to_string()).collect::<String>();
+        let base = Style::default().fg(Color::Green);
+        let fits = border_status_spans("#[reverse]1#[default] \"sh\"", 10, base);
+        assert_eq!(text(&fits), "1 \"sh\"");
+        assert!(fits[0].style.add_modifier.contains(Modifier::REVERSED));
+
+        let cut = border_status_spans("#[reverse]1#[default] \"a long title\"", 8, base);
+        assert_eq!(text(&cut), "1 \"a lo…");
+        assert_eq!(cut.last().unwrap().style, base);
+        // A wide character that would straddle the edge goes too
+        assert_eq!(text(&border_status_spans("ab漢字", 4, base)), "ab…");
+        assert!(border_status_spans("abc", 0, base).is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+//! Session snapshots (save-session / restore-session).
+//!
+//! A snapshot records each window's index, name, split tree with sizes
+//! and active pane, and every pane's title and working directory (found by
+//! inspecting the pane's foreground process).  It is written to
+//! `~/.psmux/resurrect/<session>.json`.  Restoring starts a fresh shell in
+//! each saved directory; scrollback is not kept.  With the
+//! `@resurrect-on-start` option on, a new server restores the most recent
+//! snapshot in place of its first window.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::tree::active_pane;
+use crate::types::{AppState, LayoutKind, Node, Pane};
+use crate::window_ops::{remove_window, set_window_index, window_pos_by_index};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    pub session: String,
+    /// RFC 3339 time of the save
+    pub saved_at: String,
+    /// Position in `windows` of the active window
+    pub active_window: usize,
+    pub windows: Vec<WindowSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct WindowSnapshot {
+    pub index: usize,
+    pub name: String,
+    /// The name was set by the user, so automatic-rename leaves it alone
+    #[serde(default)]
+    pub manual_rename: bool,
+    pub active_path: Vec<usize>,
+    pub layout: LayoutSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum LayoutSnapshot {
+    #[serde(rename = "split")]
+    Split { kind: String, sizes: Vec<u16>, children: Vec<LayoutSnapshot> },
+    #[serde(rename = "pane")]
+    Pane { title: String, cwd: Option<String> },
+}
+
+impl LayoutSnapshot {
+    fn of(node: &Node) -> Self {
+        match node {
+            Node::Leaf(p) => LayoutSnapshot::Pane { title: p.title.clone(), cwd: pane_cwd(p) },
+            Node::Split { kind, sizes, children } => LayoutSnapshot::Split {
+                kind: match kind { LayoutKind::Horizontal => "Horizontal", LayoutKind::Vertical => "Vertical" }.to_string(),
+                sizes: sizes.clone(),
+                children: children.iter().map(LayoutSnapshot::of).collect(),
+            },
+        }
+    }
+
+    /// The panes' working directories, in tree order.
+    fn dirs(&self, out: &mut Vec<Option<String>>) {
+        match self {
+            LayoutSnapshot::Pane { cwd, .. } => out.push(cwd.clone()),
+            LayoutSnapshot::Split { children, .. } => for c in children { c.dirs(out) },
+        }
+    }
+
+    /// Rebuild the tree, taking panes in tree order.
+    fn build(&self, panes: &mut impl Iterator<Item = Pane>) -> Option<Node> {
+        match self {
+            LayoutSnapshot::Pane { title, .. } => {
+                let mut p = panes.next()?;
+                p.title = title.clone();
+                Some(Node::Leaf(p))
+            }
+            LayoutSnapshot::Split { kind, sizes, children } => Some(Node::Split {
+                kind: if kind == "Vertical" { LayoutKind::Vertical } else { LayoutKind::Horizontal },
+                sizes: sizes.clone(),
+                children: children.iter().map(|c| c.build(panes)).collect::<Option<Vec<_>>>()?,
+            }),
+        }
+    }
+}
+
+/// The working directory of the pane's foreground process, when it can be read.
+fn pane_cwd(p: &Pane) -> Option<String> {
+    p.child_pid.and_then(crate::platform::process_info::get_foreground_cwd).filter(|d| !d.is_empty())
+}
+
+pub fn snapshot(app: &AppState) -> Snapshot {
+    Snapshot {
+        session: app.session_name.clone(),
+        saved_at: chrono::Local::now().to_rfc3339(),
+        active_window: app.active_idx,
+        windows: app.windows.iter().map(|w| WindowSnapshot {
+            index: w.index,
+            name: w.name.clone(),
+            manual_rename: w.manual_rename,
+            active_path: w.active_path.clone(),
+            layout: LayoutSnapshot::of(&w.root),
+        }).collect(),
+    }
+}
+
+/// `~/.psmux/resurrect`
+pub fn snapshot_dir() -> PathBuf {
+    Path::new(&crate::session::psmux_dir()).join("resurrect")
+}
+
+/// Write `app`'s snapshot to `<dir>/<session>.json`, returning the path.
+pub fn save_to(dir: &Path, app: &AppState) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", app.session_name));
+    let json = serde_json::to_string_pretty(&snapshot(app)).map_err(io::Error::other)?;
+    // Write beside and rename, so a crash mid-save keeps the old snapshot
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+/// Read `<dir>/<name>.json`, or the most recently saved snapshot when
+/// `name` is None.
+pub fn load_from(dir: &Path, name: Option<&str>) -> io::Result<Snapshot> {
+    let path = match name {
+        Some(n) => dir.join(format!("{}.json", n)),
+        None => std::fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+            .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+            .map(|e| e.path())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no saved sessions"))?,
+    };
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+/// Run `f` with the server's directory set to `dir`, when it still exists.
+fn in_dir<T>(dir: Option<&str>, f: impl FnOnce() -> T) -> T {
+    let dir = dir.filter(|d| Path::new(d).is_dir());
+    let saved = if dir.is_some() { std::env::current_dir().ok() } else { None };
+    if let Some(d) = dir { std::env::set_current_dir(d).ok(); }
+    let r = f();
+    if let Some(prev) = saved { std::env::set_current_dir(prev).ok(); }
+    r
+}
+
+/// Recreate the snapshot's windows after the existing ones, each pane
+/// running the default shell in its saved directory, and make the saved
+/// active window current.  Returns how many windows were restored.
+pub fn restore(app: &mut AppState, pty_system: &dyn portable_pty::PtySystem, snap: &Snapshot) -> io::Result<usize> {
+    let mut active = None;
+    for (n, ws) in snap.windows.iter().enumerate() {
+        let mut dirs = Vec::new();
+        ws.layout.dirs(&mut dirs);
+        in_dir(dirs[0].as_deref(), || crate::pane::create_window(pty_system, app, None))?;
+        let mut pos = app.active_idx;
+        let size = match &app.windows[pos].root {
+            Node::Leaf(p) => portable_pty::PtySize { rows: p.last_rows, cols: p.last_cols, pixel_width: 0, pixel_height: 0 },
+            Node::Split { .. } => unreachable!("a new window has one pane"),
+        };
+        let mut rest = Vec::new();
+        for d in &dirs[1..] {
+            match in_dir(d.as_deref(), || crate::pane::spawn_pane(app, pty_system, size, None)) {
+                Ok(p) => rest.push(p),
+                Err(e) => {
+                    for mut p in rest { crate::platform::process_kill::kill_process_tree(&mut p.child); }
+                    let mut win = remove_window(app, pos);
+                    crate::tree::kill_all_children(&mut win.root);
+                    return Err(e);
+                }
+            }
+        }
+        let placeholder = Node::Split { kind: LayoutKind::Horizontal, sizes: Vec::new(), children: Vec::new() };
+        let first = match std::mem::replace(&mut app.windows[pos].root, placeholder) {
+            Node::Leaf(p) => p,
+            Node::Split { .. } => unreachable!(),
+        };
+        let root = ws.layout.build(&mut std::iter::once(first).chain(rest)).expect("one pane per leaf");
+        if window_pos_by_index(app, ws.index).is_none() { pos = set_window_index(app, pos, ws.index); }
+        let win = &mut app.windows[pos];
+        win.active_path = if active_pane(&root, &ws.active_path).is_some() { ws.active_path.clone() } else { first_leaf_path(&root) };
+        win.root = root;
+        win.name = ws.name.clone();
+        win.manual_rename = ws.manual_rename;
+        if n == snap.active_window { active = Some(win.id); }
+    }
+    if let Some(pos) = active.and_then(|id| crate::tree::find_window_index_by_id(app, id)) { app.active_idx = pos; }
+    crate::tree::resize_all_panes(app);
+    Ok(snap.windows.len())
+}
+
+fn first_leaf_path(mut node: &Node) -> Vec<usize> {
+    let mut path = Vec::new();
+    while let Node::Split { children, .. } = node {
+        match children.first() { Some(c) => { path.push(0); node = c; } None => break }
+    }
+    path
+}
+
+/// `@resurrect-on-start` is on.
+pub fn restore_on_start(app: &AppState) -> bool {
+    matches!(app.environment.get("@resurrect-on-start").map(|s| s.as_str()), Some("on" | "1" | "true" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::kill_all_children;
+
+    fn kinds(node: &Node) -> String {
+        match node {
+            Node::Leaf(p) => p.title.clone(),
+            Node::Split { kind, sizes, children } => format!("{}{:?}({})",
+                if *kind == LayoutKind::Vertical { "V" } else { "H" }, sizes,
+                children.iter().map(kinds).collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    #[test]
+    fn snapshots_restore_layout_names_and_focus() {
+        let pty_system = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        let dir = std::env::temp_dir().join(format!("psmux-resurrect-{}", std::process::id()));
+
+        let snap = Snapshot {
+            session: "work".into(),
+            saved_at: String::new(),
+            active_window: 1,
+            windows: vec![
+                WindowSnapshot { index: 1, name: "editor".into(), manual_rename: true, active_path: vec![], layout: LayoutSnapshot::Pane { title: "vim".into(), cwd: None } },
+                WindowSnapshot { index: 3, name: "logs".into(), manual_rename: false, active_path: vec![1, 0], layout: LayoutSnapshot::Split {
+                    kind: "Horizontal".into(), sizes: vec![30, 70], children: vec![
+                        LayoutSnapshot::Pane { title: "a".into(), cwd: None },
+                        LayoutSnapshot::Split { kind: "Vertical".into(), sizes: vec![20, 40, 40], children: vec![
+                            LayoutSnapshot::Pane { title: "b".into(), cwd: None },
+                            LayoutSnapshot::Pane { title: "c".into(), cwd: Some("/no/such/dir".into()) },
+                            LayoutSnapshot::Pane { title: "d".into(), cwd: None },
+                        ] },
+                    ] } },
+            ],
+        };
+        assert_eq!(restore(&mut app, &*pty_system, &snap).unwrap(), 2);
+        assert_eq!(app.windows.iter().map(|w| (w.index, w.name.as_str(), w.manual_rename)).collect::<Vec<_>>(),
+            [(1, "editor", true), (3, "logs", false)]);
+        assert_eq!(app.active_idx, 1);
+        assert_eq!(kinds(&app.windows[1].root), "H[30, 70](a,V[20, 40, 40](b,c,d))");
+        assert_eq!(app.windows[1].active_path, [1, 0]);
+
+        // Saved and read back, the snapshot describes the same session
+        let path = save_to(&dir, &app).unwrap();
+        assert_eq!(path, dir.join("work.json"));
+        let back = load_from(&dir, None).unwrap();
+        assert_eq!(back.active_window, 1);
+        assert_eq!(back.windows.len(), 2);
+        assert_eq!(back.windows[1].active_path, [1, 0]);
+        assert!(matches!(&back.windows[1].layout, LayoutSnapshot::Split { sizes, .. } if sizes == &[30, 70]));
+        assert!(load_from(&dir, Some("missing")).is_err());
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
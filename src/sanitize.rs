@@ -0,0 +1,166 @@
+//! What may reach the client's terminal outside ratatui.
+//!
+//! Trust model: anything a pane produces — its output, the titles and
+//! names derived from it, text it hands to `display-message` or `run-shell`
+//! — is untrusted, since any program can run in a pane.  Pane output is
+//! parsed by vt100 and redrawn through ratatui, which drops control
+//! characters from every string it draws, so nothing a pane prints reaches
+//! the outer terminal as an escape sequence that way.
+//!
+//! The few writes that go around ratatui (the OSC 52 copy from a
+//! `%clipboard` directive, the `set-titles` title) go through `write_raw`,
+//! which keeps only an explicit allowlist: window title (OSC 0/1/2),
+//! cursor shape (DECSCUSR), bell, and OSC 52 clipboard writes when the
+//! caller allows them.  Anything else (mode changes, key remapping, DCS,
+//! clipboard queries, C1 controls) is dropped, and over-long sequences with
+//! it.  Strings meant for titles and messages are cleaned with `text`
+//! first.
+
+use std::io::{self, Write};
+
+/// Longest title kept, in characters.
+pub const MAX_TITLE: usize = 256;
+
+/// Longest message kept, in characters.
+pub const MAX_MESSAGE: usize = 1024;
+
+/// Longest OSC 52 payload kept, in bytes.
+pub const MAX_CLIPBOARD: usize = 1 << 20;
+
+/// `s` with control characters shown as `_` and cut to `max` characters.
+pub fn text(s: &str, max: usize) -> String {
+    s.chars().take(max).map(|c| if c.is_control() { '_' } else { c }).collect()
+}
+
+/// The escape that sets the terminal's title to `s`, cleaned with `text`.
+pub fn title(s: &str) -> String {
+    format!("\x1b]2;{}\x07", text(s, MAX_TITLE))
+}
+
+/// Write `bytes` to the terminal, keeping only the allowed sequences (OSC 52
+/// only with `osc52`) and plain text.
+pub fn write_raw(out: &mut impl Write, bytes: &[u8], osc52: bool) -> io::Result<()> {
+    out.write_all(filter(bytes, osc52).as_bytes())?;
+    out.flush()
+}
+
+/// `bytes` with everything but plain text and the allowed sequences removed.
+pub fn filter(bytes: &[u8], osc52: bool) -> String {
+    let chars: Vec<char> = String::from_utf8_lossy(bytes).chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\x1b' => match chars.get(i + 1) {
+                Some(']') => {
+                    let (body, next) = string_body(&chars, i + 2);
+                    if let Some(seq) = body.and_then(|b| allowed_osc(&b, osc52)) { out.push_str(&seq); }
+                    i = next;
+                }
+                Some('[') => {
+                    let end = csi_end(&chars, i + 2);
+                    let seq: String = chars[i..end].iter().collect();
+                    if is_cursor_shape(&seq) { out.push_str(&seq); }
+                    i = end;
+                }
+                // DCS, SOS, PM and APC run to ST like OSC
+                Some('P' | 'X' | '^' | '_') => i = string_body(&chars, i + 2).1,
+                Some(_) => i += 2,
+                None => i += 1,
+            },
+            '\x07' => { out.push('\x07'); i += 1; }
+            c if c.is_control() => i += 1,
+            c => { out.push(c); i += 1; }
+        }
+    }
+    out
+}
+
+/// The body of a string sequence starting at `start` and where scanning
+/// resumes.  The body is None when it is unterminated, too long or holds
+/// control characters; an ESC that does not start ST ends it unterminated.
+fn string_body(chars: &[char], start: usize) -> (Option<String>, usize) {
+    let mut valid = true;
+    for i in start..chars.len() {
+        match chars[i] {
+            '\x07' => return (valid.then(|| chars[start..i].iter().collect()), i + 1),
+            '\x1b' if chars.get(i + 1) == Some(&'\\') => return (valid.then(|| chars[start..i].iter().collect()), i + 2),
+            '\x1b' => return (None, i),
+            c if c.is_control() => valid = false,
+            _ => {}
+        }
+        if i - start > MAX_CLIPBOARD + 16 { valid = false; }
+    }
+    (None, chars.len())
+}
+
+/// End of a CSI sequence whose parameters start at `start`.  A control
+/// character cancels the sequence, leaving it to be scanned again.
+fn csi_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() && ('\x20'..='\x3f').contains(&chars[i]) { i += 1; }
+    if i < chars.len() && ('\x40'..='\x7e').contains(&chars[i]) { i += 1; }
+    i
+}
+
+/// DECSCUSR: `ESC [ Ps SP q` with Ps 0 to 6.
+fn is_cursor_shape(seq: &str) -> bool {
+    seq.strip_prefix("\x1b[").and_then(|s| s.strip_suffix(" q"))
+        .is_some_and(|n| n.is_empty() || n.parse::<u8>().is_ok_and(|n| n <= 6))
+}
+
+/// An allowed OSC, rebuilt with a BEL terminator.
+fn allowed_osc(body: &str, osc52: bool) -> Option<String> {
+    let (ps, pt) = body.split_once(';')?;
+    match ps {
+        "0" | "1" | "2" if pt.chars().count() <= MAX_TITLE => Some(format!("\x1b]{};{}\x07", ps, pt)),
+        "52" if osc52 => {
+            let (selection, data) = pt.split_once(';')?;
+            let ok = selection.chars().all(|c| "cpqs01234567".contains(c))
+                && !data.is_empty() && data.len() <= MAX_CLIPBOARD
+                && data.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='));
+            ok.then(|| format!("\x1b]52;{};{}\x07", selection, data))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::base64_encode;
+
+    #[test]
+    fn allowed_sequences_pass() {
+        let copy = crate::clipboard::osc52(&base64_encode("hi"));
+        assert_eq!(filter(copy.as_bytes(), true), copy);
+        assert_eq!(filter(b"\x1b]2;build\x1b\\\x1b[2 q\x07", false), "\x1b]2;build\x07\x1b[2 q\x07");
+        assert_eq!(filter(title("a\x1b]52;c;?\x07b").as_bytes(), false), "\x1b]2;a_]52;c;?_b\x07");
+    }
+
+    #[test]
+    fn hostile_sequences_are_dropped() {
+        // Clipboard reads, and writes when not allowed
+        assert_eq!(filter(b"\x1b]52;c;?\x07", true), "");
+        assert_eq!(filter(crate::clipboard::osc52("aGk=").as_bytes(), false), "");
+        assert_eq!(filter(b"\x1b]52;c;not base64!\x07", true), "");
+        // Mode changes, key remapping, terminal reset, DCS, C1 CSI
+        assert_eq!(filter(b"a\x1b[?1049lb\x1b[0;97;98pc\x1bcd", false), "abcd");
+        assert_eq!(filter(b"\x1bP+q544e\x1b\\x", false), "x");
+        assert_eq!(filter("\u{9b}?25lx".as_bytes(), false), "?25lx");
+        // Cursor shapes out of range, other OSCs, unterminated sequences
+        assert_eq!(filter(b"\x1b[9 q\x1b]8;;http://x\x07link\x1b]2;open", false), "link");
+        // A sequence cut short by ESC does not swallow the next one
+        assert_eq!(filter(b"\x1b]2;a\x1b[1 q", false), "\x1b[1 q");
+    }
+
+    #[test]
+    fn lengths_are_limited() {
+        let long = "x".repeat(MAX_TITLE + 1);
+        assert_eq!(filter(format!("\x1b]0;{}\x07", long).as_bytes(), false), "");
+        assert_eq!(title(&long), format!("\x1b]2;{}\x07", &long[..MAX_TITLE]));
+        let big = crate::clipboard::osc52(&"A".repeat(MAX_CLIPBOARD + 4));
+        assert_eq!(filter(big.as_bytes(), true), "");
+        assert_eq!(text("one\ntwo\x1b[31m", 7), "one_two");
+    }
+}
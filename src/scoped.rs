@@ -0,0 +1,170 @@
+//! Options a window (`set -w`) or a pane (`set -p`) can set for itself:
+//! synchronize-panes, automatic-rename, the monitor options and
+//! remain-on-exit.  A value is looked up on the pane, then its window,
+//! then the session's in AppState; the server reads them through the
+//! functions here rather than the AppState fields.
+
+use crate::types::{AppState, Pane, Window};
+
+/// The options a window or pane can set, as `set-option` names them.
+pub const NAMES: &[&str] = &[
+    "synchronize-panes", "automatic-rename", "monitor-activity", "monitor-bell", "monitor-silence", "remain-on-exit",
+];
+
+/// What one window or pane has set; None inherits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScopedOptions {
+    pub synchronize_panes: Option<bool>,
+    pub automatic_rename: Option<bool>,
+    pub monitor_activity: Option<bool>,
+    pub monitor_bell: Option<bool>,
+    pub monitor_silence: Option<u64>,
+    pub remain_on_exit: Option<bool>,
+}
+
+fn on_off(b: bool) -> String {
+    if b { "on".into() } else { "off".into() }
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl ScopedOptions {
+    /// The value set here for `name`, if any.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "synchronize-panes" => self.synchronize_panes.map(on_off),
+            "automatic-rename" => self.automatic_rename.map(on_off),
+            "monitor-activity" => self.monitor_activity.map(on_off),
+            "monitor-bell" => self.monitor_bell.map(on_off),
+            "monitor-silence" => self.monitor_silence.map(|n| n.to_string()),
+            "remain-on-exit" => self.remain_on_exit.map(on_off),
+            _ => None,
+        }
+    }
+
+    /// Set `name` here, or with None unset it.  Returns false for a value
+    /// the option cannot take.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> bool {
+        fn put<T>(slot: &mut Option<T>, value: Option<&str>, parse: impl Fn(&str) -> Option<T>) -> bool {
+            match value {
+                None => { *slot = None; true }
+                Some(v) => parse(v).map(|x| *slot = Some(x)).is_some(),
+            }
+        }
+        match name {
+            "synchronize-panes" => put(&mut self.synchronize_panes, value, flag),
+            "automatic-rename" => put(&mut self.automatic_rename, value, flag),
+            "monitor-activity" => put(&mut self.monitor_activity, value, flag),
+            "monitor-bell" => put(&mut self.monitor_bell, value, flag),
+            "monitor-silence" => put(&mut self.monitor_silence, value, |v| v.parse().ok()),
+            "remain-on-exit" => put(&mut self.remain_on_exit, value, flag),
+            _ => false,
+        }
+    }
+}
+
+/// Where an option's effective value comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin { Pane, Window, Session }
+
+/// `name`'s effective value for `pane` of `win` (either may be left out)
+/// and where it was set.
+pub fn value(app: &AppState, win: Option<&Window>, pane: Option<&Pane>, name: &str) -> (String, Origin) {
+    if let Some(v) = pane.and_then(|p| p.options.get(name)) { return (v, Origin::Pane); }
+    if let Some(v) = win.and_then(|w| w.options.get(name)) { return (v, Origin::Window); }
+    let session = match name {
+        "synchronize-panes" => on_off(app.sync_input),
+        "automatic-rename" => on_off(app.automatic_rename),
+        "monitor-activity" => on_off(app.monitor_activity),
+        "monitor-bell" => on_off(app.monitor_bell),
+        "monitor-silence" => app.monitor_silence.to_string(),
+        "remain-on-exit" => on_off(app.remain_on_exit),
+        _ => String::new(),
+    };
+    (session, Origin::Session)
+}
+
+/// synchronize-panes for `win`: typing goes to all of its panes.
+pub fn synchronize_panes(app: &AppState, win: &Window) -> bool {
+    win.options.synchronize_panes.unwrap_or(app.sync_input)
+}
+
+/// synchronize-panes for the active window.
+pub fn active_synchronized(app: &AppState) -> bool {
+    app.windows.get(app.active_idx).map_or(app.sync_input, |w| synchronize_panes(app, w))
+}
+
+pub fn automatic_rename(app: &AppState, win: &Window) -> bool {
+    win.options.automatic_rename.unwrap_or(app.automatic_rename)
+}
+
+pub fn monitor_activity(app: &AppState, win: &Window) -> bool {
+    win.options.monitor_activity.unwrap_or(app.monitor_activity)
+}
+
+pub fn monitor_bell(app: &AppState, win: &Window) -> bool {
+    win.options.monitor_bell.unwrap_or(app.monitor_bell)
+}
+
+pub fn monitor_silence(app: &AppState, win: &Window) -> u64 {
+    win.options.monitor_silence.unwrap_or(app.monitor_silence)
+}
+
+/// remain-on-exit for the panes of `win` that do not set their own.
+pub fn remain_on_exit(app: &AppState, win: &Window) -> bool {
+    win.options.remain_on_exit.unwrap_or(app.remain_on_exit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_fall_back_pane_window_session() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+
+        // Only the session's
+        let (w0, w1) = (&app.windows[0], &app.windows[1]);
+        assert_eq!(value(&app, Some(w0), None, "monitor-silence"), ("0".to_string(), Origin::Session));
+        assert!(!synchronize_panes(&app, w0) && !synchronize_panes(&app, w1));
+
+        // The first window sets its own; the second still follows the session
+        assert!(app.windows[0].options.set("synchronize-panes", Some("on")));
+        assert!(app.windows[0].options.set("monitor-silence", Some("30")));
+        assert!(!app.windows[0].options.set("monitor-silence", Some("soon")));
+        assert!(!app.windows[0].options.set("remain-on-exit", Some("maybe")));
+        app.monitor_silence = 5;
+        let (w0, w1) = (&app.windows[0], &app.windows[1]);
+        assert!(synchronize_panes(&app, w0) && !synchronize_panes(&app, w1));
+        assert_eq!((monitor_silence(&app, w0), monitor_silence(&app, w1)), (30, 5));
+        assert_eq!(value(&app, Some(w0), None, "synchronize-panes"), ("on".to_string(), Origin::Window));
+
+        // A pane's own value beats its window's
+        app.remain_on_exit = true;
+        app.windows[0].options.set("remain-on-exit", Some("off"));
+        let path = app.windows[0].active_path.clone();
+        let pane = crate::tree::active_pane_mut(&mut app.windows[0].root, &path).unwrap();
+        pane.options.set("remain-on-exit", Some("on"));
+        let (w0, w1) = (&app.windows[0], &app.windows[1]);
+        let pane = crate::tree::active_pane(&w0.root, &w0.active_path);
+        assert_eq!(value(&app, Some(w0), pane, "remain-on-exit"), ("on".to_string(), Origin::Pane));
+        assert_eq!(value(&app, Some(w0), None, "remain-on-exit"), ("off".to_string(), Origin::Window));
+        assert!(remain_on_exit(&app, w1));
+
+        // Unset: inherited again
+        app.windows[0].options.set("synchronize-panes", None);
+        assert_eq!(value(&app, Some(&app.windows[0]), None, "synchronize-panes").1, Origin::Session);
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+}
@@ -1,22 +1,35 @@
 use std::io::{self, BufRead, Write};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::pipe::PipeStream;
-use crate::types::{CtrlReq, LayoutKind, WaitForOp};
-use crate::cli::parse_target;
-use crate::util::base64_decode;
+use crate::transport::Stream;
+use crate::types::{CtrlReq, LayoutKind, OptionScope, SplitOpts, SwapWith, SwitchTo, WaitForOp};
+use crate::bandwidth::{FrameKind, LOW_BANDWIDTH_FRAME_MS};
+use crate::cli::{parse_target, target_is_destination, target_names_pane};
+use crate::util::{base64_decode, base64_decode_bytes, parse_hex_bytes};
 use crate::commands::parse_command_line;
-use super::helpers::TMUX_COMMANDS;
+use super::helpers::{TMUX_COMMANDS, flag_value, has_flag, new_match, pane_size_arg, target_keeps_focus};
+use super::options::SCOPED_OPTIONS;
 
-/// Handle a single named pipe connection from a client.
+/// Source of per-connection client ids for attached clients.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Minimum spacing between ClientActivity notifications from one client;
+/// idle tracking only needs second resolution.
+const ACTIVITY_NOTIFY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Handle a single client connection.
 /// Parses auth, optional TARGET/PERSISTENT flags, then dispatches commands
-/// to the main server event loop via the `tx` channel.
+/// to the main server event loop via the `tx` channel.  On a `trusted`
+/// transport (only the server's user can connect) AUTH is optional; older
+/// clients still send it.
 pub(crate) fn handle_connection(
-    stream: PipeStream,
+    stream: Stream,
     tx: mpsc::Sender<CtrlReq>,
     session_key: &str,
-    aliases: std::sync::Arc<std::sync::RwLock<std::collections::HashMap<String, String>>>,
+    trusted: bool,
 ) {
 // Clone stream for writing, original goes into BufReader for reading
 let mut write_stream = match stream.try_clone() {
@@ -24,33 +37,32 @@ let mut write_stream = match stream.try_clone() {
     Err(_) => return,
 };
 
-// Named pipes use blocking reads — no timeout needed for auth
 let _ = stream.set_read_timeout(Some(Duration::from_millis(5000)));
 let mut r = io::BufReader::new(stream);
 
-// Read the authentication line
-let mut auth_line = String::new();
-if r.read_line(&mut auth_line).is_err() {
+// Read the first line: AUTH, or on a trusted transport the first command
+let mut first_line = String::new();
+if r.read_line(&mut first_line).is_err() {
     return;
 }
 
-// Verify session key
-let auth_line = auth_line.trim();
-if !auth_line.starts_with("AUTH ") {
-    // Legacy client without auth - reject for security
-    let _ = write_stream.write_all(b"ERROR: Authentication required\n");
+if let Some(provided_key) = first_line.trim().strip_prefix("AUTH ") {
+    // Verify session key
+    if provided_key != session_key {
+        let _ = write_stream.write_all(b"ERROR: Invalid session key\n");
+        let _ = write_stream.flush();
+        return;
+    }
+    // Auth successful - send OK and flush immediately
+    let _ = write_stream.write_all(b"OK\n");
     let _ = write_stream.flush();
-    return;
-}
-let provided_key = auth_line.strip_prefix("AUTH ").unwrap_or("");
-if provided_key != session_key {
-    let _ = write_stream.write_all(b"ERROR: Invalid session key\n");
+    first_line.clear();
+} else if !trusted {
+    // Any local user can reach a TCP port - reject clients without the key
+    let _ = write_stream.write_all(b"ERROR: Authentication required\n");
     let _ = write_stream.flush();
     return;
 }
-// Auth successful - send OK and flush immediately
-let _ = write_stream.write_all(b"OK\n");
-let _ = write_stream.flush();
 
 // Set short read timeout for batched command processing
 let _ = r.get_ref().set_read_timeout(Some(Duration::from_millis(50)));
@@ -61,14 +73,25 @@ let mut resp_tx_opt: Option<mpsc::Sender<mpsc::Receiver<String>>> = None;
 let mut global_target_win: Option<usize> = None;
 let mut global_target_pane: Option<usize> = None;
 let mut global_pane_is_id = false;
-let mut line = String::new();
-if r.read_line(&mut line).is_err() {
+// Set by refresh-client -f low-bandwidth (see crate::bandwidth)
+let low_bandwidth = Arc::new(AtomicBool::new(false));
+// Set by client-suspend 1 (see crate::suspend)
+let suspended = Arc::new(AtomicBool::new(false));
+// Set by dump-state -D: frames go out as deltas (see crate::delta)
+let deltas = Arc::new(AtomicBool::new(false));
+let mut last_frame_at: Option<Instant> = None;
+let mut line = first_line;
+if line.is_empty() && r.read_line(&mut line).is_err() {
     return;
 }
 
 // Check if client requests persistent connection mode
 if line.trim() == "PERSISTENT" {
     persistent = true;
+    // Ack, so an attaching client knows this step finished (it times out
+    // the handshake otherwise)
+    let _ = write_stream.write_all(b"OK\n");
+    let _ = write_stream.flush();
     // Enable TCP_NODELAY for low-latency persistent connections
     let _ = r.get_ref().set_nodelay(true);
     let _ = write_stream.set_nodelay(true);
@@ -81,9 +104,48 @@ if line.trim() == "PERSISTENT" {
     // writes it to TCP in order.
     let mut ws_bg = write_stream.try_clone().unwrap();
     let (resp_tx, resp_rx) = mpsc::channel::<mpsc::Receiver<String>>();
+    let low_bw = low_bandwidth.clone();
+    let held = suspended.clone();
+    let want_deltas = deltas.clone();
     std::thread::spawn(move || {
+        // Last frame written, kept in low-bandwidth mode so a repeat goes out as "NC"
+        let mut last_frame = String::new();
+        // Rows of the last frame written, for delta frames
+        let mut sent_rows = crate::delta::FrameRows::default();
         while let Ok(rrx) = resp_rx.recv() {
             if let Ok(text) = rrx.recv() {
+                if text == crate::delta::FULL_FRAME { sent_rows.clear(); continue; }
+                // The client is about to ask for a whole frame: send it even if a repeat
+                if text == "%redraw" { last_frame.clear(); sent_rows.clear(); }
+                if held.load(Ordering::Relaxed) {
+                    // The first frame after resuming is sent whole
+                    last_frame.clear();
+                    sent_rows.clear();
+                    if let Some(out) = crate::suspend::gate_line(&text, true) {
+                        let _ = write!(ws_bg, "{}\n", out);
+                        let _ = ws_bg.flush();
+                    }
+                    continue;
+                }
+                if low_bw.load(Ordering::Relaxed) && text.starts_with('{') {
+                    if text == last_frame {
+                        let _ = ws_bg.write_all(b"NC\n");
+                        let _ = ws_bg.flush();
+                        continue;
+                    }
+                    last_frame.clone_from(&text);
+                    // Plain frames are never diffed; the next styled one goes out whole
+                    sent_rows.clear();
+                } else if !last_frame.is_empty() && !low_bw.load(Ordering::Relaxed) {
+                    last_frame.clear();
+                }
+                if want_deltas.load(Ordering::Relaxed) && !low_bw.load(Ordering::Relaxed) && text.starts_with('{') {
+                    if let Some(delta) = sent_rows.encode(&text) {
+                        let _ = write!(ws_bg, "{}\n", delta);
+                        let _ = ws_bg.flush();
+                        continue;
+                    }
+                }
                 let _ = write!(ws_bg, "{}\n", text);
                 let _ = ws_bg.flush();
             }
@@ -96,6 +158,16 @@ if line.trim() == "PERSISTENT" {
     }
 }
 
+// FORMAT binary: this client takes packed rows (see crate::cells)
+let mut binary_frames = false;
+if persistent && line.trim().starts_with("FORMAT ") {
+    binary_frames = line.trim().strip_prefix("FORMAT ") == Some("binary");
+    line.clear();
+    if r.read_line(&mut line).is_err() {
+        return;
+    }
+}
+
 // Check if this line is a TARGET specification
 // Save raw target for relative pane specifiers like :.+ and :.-
 let mut global_raw_target: Option<String> = None;
@@ -113,6 +185,15 @@ if line.trim().starts_with("TARGET ") {
     }
 }
 
+// Set once this persistent connection sends client-attach
+let mut client_id: Option<u64> = None;
+// Identifies this connection's begin-batch to the server
+let mut batch_token: Option<u64> = None;
+let mut batch_open = false;
+let mut last_activity_sent: Option<Instant> = None;
+// Commands chained after the one being run (if-shell branches)
+let mut queued_lines: std::collections::VecDeque<String> = std::collections::VecDeque::new();
+
 // Process commands in a loop to handle batching
 loop {
     if line.trim().is_empty() {
@@ -134,20 +215,8 @@ loop {
     
     // Use quote-aware parser to preserve arguments with spaces
     let parsed = parse_command_line(&line);
-    let raw_cmd = parsed.get(0).map(|s| s.as_str()).unwrap_or("");
-    // Check command aliases before normal dispatch
-    let alias_expanded = if let Ok(map) = aliases.read() {
-        map.get(raw_cmd).cloned()
-    } else { None };
-    let (cmd, args): (&str, Vec<&str>) = if let Some(ref expanded) = alias_expanded {
-        // Alias expansion: replace command name, keep original args
-        let expanded_parts: Vec<&str> = expanded.split_whitespace().collect();
-        let mut all_args: Vec<&str> = expanded_parts[1..].to_vec();
-        all_args.extend(parsed.iter().skip(1).map(|s| s.as_str()));
-        (expanded_parts.first().copied().unwrap_or(raw_cmd), all_args)
-    } else {
-        (raw_cmd, parsed.iter().skip(1).map(|s| s.as_str()).collect())
-    };
+    let cmd = parsed.get(0).map(|s| s.as_str()).unwrap_or("");
+    let args: Vec<&str> = parsed.iter().skip(1).map(|s| s.as_str()).collect();
 
 // Parse -t argument from command line (takes precedence over global TARGET)
 let mut target_win: Option<usize> = global_target_win;
@@ -187,34 +256,92 @@ let args: Vec<&str> = {
     }
     filtered
 };
-if let Some(wid) = target_win { let _ = tx.send(CtrlReq::FocusWindow(wid)); }
-if let Some(pid) = target_pane { 
-    if pane_is_id {
-        let _ = tx.send(CtrlReq::FocusPane(pid));
+// Anything but frame polling and terminal housekeeping counts as client input
+if let Some(id) = client_id {
+    if !matches!(cmd, "dump-state" | "client-size" | "focus-in" | "focus-out")
+        && last_activity_sent.map_or(true, |t| t.elapsed() >= ACTIVITY_NOTIFY_INTERVAL)
+    {
+        let _ = tx.send(CtrlReq::ClientActivity(id));
+        last_activity_sent = Some(Instant::now());
+    }
+}
+// For these commands -t names the destination index, not a window to focus
+let target_is_destination = target_is_destination(cmd);
+// A target naming a window or pane is resolved by the server first, and a
+// bad one fails the command with tmux's "can't find window" error
+let resolved = match raw_target.as_deref().map(|spec| (spec, parse_target(spec))) {
+    Some((spec, t)) if !target_is_destination && target_names_pane(&t) => {
+        let (rtx, rrx) = mpsc::channel();
+        let _ = tx.send(CtrlReq::ResolveTarget(t, spec.to_string(), rtx));
+        match rrx.recv_timeout(Duration::from_millis(2000)) {
+            Ok(Ok(r)) => Some(r),
+            Ok(Err(e)) => {
+                if persistent {
+                    // An attached client shows it on the status line
+                    let _ = tx.send(CtrlReq::ShowMessage(e));
+                } else {
+                    let _ = writeln!(write_stream, "{}", e);
+                    let _ = write_stream.flush();
+                }
+                if !persistent { break; }
+                line.clear();
+                continue;
+            }
+            Err(_) => None,
+        }
+    }
+    _ => None,
+};
+// The pane a command acts on without moving the user's focus to it
+let mut at_pane: Option<usize> = None;
+if let Some((win, pid)) = resolved {
+    target_win = Some(win);
+    if target_keeps_focus(cmd) {
+        at_pane = Some(pid);
     } else {
-        let _ = tx.send(CtrlReq::FocusPaneByIndex(pid));
+        let _ = tx.send(CtrlReq::FocusWindow(win));
+        let _ = tx.send(CtrlReq::FocusPane(pid));
+    }
+} else {
+    if let Some(wid) = target_win.filter(|_| !target_is_destination) { let _ = tx.send(CtrlReq::FocusWindow(wid)); }
+    if let Some(pid) = target_pane.filter(|_| !target_is_destination) {
+        if pane_is_id {
+            let _ = tx.send(CtrlReq::FocusPane(pid));
+        } else {
+            let _ = tx.send(CtrlReq::FocusPaneByIndex(pid));
+        }
     }
 }
 match cmd {
+    // Answers nothing when the connection's target exists (checked above)
+    "resolve-target" => { if !persistent { break; } }
     "new-window" | "neww" => {
         let name: Option<String> = args.windows(2).find(|w| w[0] == "-n").map(|w| w[1].trim_matches('"').to_string());
         let start_dir: Option<String> = args.windows(2).find(|w| w[0] == "-c").map(|w| w[1].trim_matches('"').to_string());
         let detached = args.iter().any(|a| *a == "-d");
         let print_info = args.iter().any(|a| *a == "-P");
+        let kill = args.iter().any(|a| *a == "-k");
         let format_str: Option<String> = args.windows(2).find(|w| w[0] == "-F").map(|w| w[1].trim_matches('"').to_string());
         let cmd_str: Option<String> = args.iter()
             .find(|a| !a.starts_with('-') && args.windows(2).all(|w| !(w[0] == "-n" && w[1] == **a)) && args.windows(2).all(|w| !(w[0] == "-c" && w[1] == **a)) && args.windows(2).all(|w| !(w[0] == "-F" && w[1] == **a)))
             .map(|s| s.trim_matches('"').to_string());
         if print_info {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::NewWindowPrint(cmd_str, name, detached, start_dir, format_str, rtx));
+            let _ = tx.send(CtrlReq::NewWindowPrint(cmd_str, name, detached, start_dir, target_win, kill, format_str, rtx));
             if let Ok(text) = rrx.recv_timeout(Duration::from_millis(2000)) {
                 let _ = write!(write_stream, "{}\n", text);
                 let _ = write_stream.flush();
             }
             if !persistent { break; }
         } else {
-            let _ = tx.send(CtrlReq::NewWindow(cmd_str, name, detached, start_dir));
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::NewWindow(cmd_str, name, detached, start_dir, target_win, kill, rtx));
+            if let Ok(err_msg) = rrx.recv_timeout(Duration::from_millis(2000)) {
+                if !err_msg.is_empty() {
+                    let _ = write!(write_stream, "{}\n", err_msg);
+                    let _ = write_stream.flush();
+                }
+            }
         }
     }
     "split-window" | "splitw" => {
@@ -223,17 +350,13 @@ match cmd {
         let print_info = args.iter().any(|a| *a == "-P");
         let format_str: Option<String> = args.windows(2).find(|w| w[0] == "-F").map(|w| w[1].trim_matches('"').to_string());
         let start_dir: Option<String> = args.windows(2).find(|w| w[0] == "-c").map(|w| w[1].trim_matches('"').to_string());
-        let size_pct: Option<u16> = args.windows(2).find(|w| w[0] == "-p").and_then(|w| w[1].parse().ok())
-            .or_else(|| args.windows(2).find(|w| w[0] == "-l").and_then(|w| {
-                let s = w[1].trim_matches('%');
-                s.parse().ok()
-            }));
+        let opts = SplitOpts { size: pane_size_arg(&args), before: has_flag(&args, 'b'), full: has_flag(&args, 'f') };
         let cmd_str: Option<String> = args.iter()
             .find(|a| !a.starts_with('-') && args.windows(2).all(|w| !(w[0] == "-c" && w[1] == **a)) && args.windows(2).all(|w| !(w[0] == "-p" && w[1] == **a)) && args.windows(2).all(|w| !(w[0] == "-l" && w[1] == **a)) && args.windows(2).all(|w| !(w[0] == "-F" && w[1] == **a)))
             .map(|s| s.trim_matches('"').to_string());
         if print_info {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::SplitWindowPrint(kind, cmd_str, detached, start_dir, size_pct, format_str, rtx));
+            let _ = tx.send(CtrlReq::SplitWindowPrint(kind, cmd_str, detached, start_dir, opts, format_str, rtx, at_pane));
             if let Ok(text) = rrx.recv_timeout(Duration::from_millis(2000)) {
                 let _ = write!(write_stream, "{}\n", text);
                 let _ = write_stream.flush();
@@ -241,7 +364,7 @@ match cmd {
             if !persistent { break; }
         } else {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::SplitWindow(kind, cmd_str, detached, start_dir, size_pct, rtx));
+            let _ = tx.send(CtrlReq::SplitWindow(kind, cmd_str, detached, start_dir, opts, rtx, at_pane));
             if let Ok(err_msg) = rrx.recv_timeout(Duration::from_millis(2000)) {
                 if !err_msg.is_empty() {
                     let _ = write!(write_stream, "{}\n", err_msg);
@@ -250,16 +373,16 @@ match cmd {
             }
         }
     }
-    "kill-pane" | "killp" => { let _ = tx.send(CtrlReq::KillPane); }
+    "kill-pane" | "killp" => { let _ = tx.send(CtrlReq::KillPane(at_pane)); }
     "capture-pane" | "capturep" => {
         let print_stdout = args.iter().any(|a| *a == "-p");
         let join_lines = args.iter().any(|a| *a == "-J");
         let escape_seqs = args.iter().any(|a| *a == "-e");
-        // Parse -S start and -E end (negative = scrollback offset, - = entire scrollback)
+        // Parse -S start and -E end (negative = lines of scrollback, - = its start / the screen's end)
         let s_arg = args.windows(2).find(|w| w[0] == "-S").map(|w| w[1]);
         let e_arg = args.windows(2).find(|w| w[0] == "-E").map(|w| w[1]);
         let start: Option<i32> = match s_arg {
-            Some("-") => Some(0), // entire scrollback start
+            Some("-") => Some(i32::MIN), // start of the scrollback
             Some(v) => v.parse::<i32>().ok(),
             None => None,
         };
@@ -270,24 +393,21 @@ match cmd {
         };
         let (rtx, rrx) = mpsc::channel::<String>();
         if escape_seqs {
-            let _ = tx.send(CtrlReq::CapturePaneStyled(rtx, start, end));
-        } else if s_arg.is_some() || e_arg.is_some() {
-            let _ = tx.send(CtrlReq::CapturePaneRange(rtx, start, end));
+            let _ = tx.send(CtrlReq::CapturePaneStyled(rtx, start, end, join_lines, at_pane));
+        } else if s_arg.is_some() || e_arg.is_some() || join_lines {
+            let _ = tx.send(CtrlReq::CapturePaneRange(rtx, start, end, join_lines, at_pane));
         } else {
-            let _ = tx.send(CtrlReq::CapturePane(rtx));
+            let _ = tx.send(CtrlReq::CapturePane(rtx, at_pane));
         }
-        if let Ok(mut text) = rrx.recv() {
-            if join_lines {
-                // Remove trailing whitespace from each line (join wrapped lines)
-                text = text.lines().map(|l| l.trim_end()).collect::<Vec<_>>().join("\n");
-            }
+        if let Ok(text) = rrx.recv() {
             if print_stdout {
                 // Write text directly — it already ends with \n from capture
                 let _ = write_stream.write_all(text.as_bytes());
                 let _ = write_stream.flush();
                 if !persistent { break; }
             } else {
-                let _ = tx.send(CtrlReq::SetBuffer(text));
+                let name = args.windows(2).find(|w| w[0] == "-b").map(|w| w[1].to_string());
+                let _ = tx.send(CtrlReq::SetBuffer(name, text, false));
             }
         }
     }
@@ -302,7 +422,25 @@ match cmd {
     }
     "dump-state" => {
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::DumpState(rtx, persistent));
+        let low = low_bandwidth.load(Ordering::Relaxed);
+        // -F: a whole frame, never "NC" (the client is repainting)
+        let full = args.contains(&"-F");
+        if low && !full && last_frame_at.map_or(false, |t| t.elapsed() < Duration::from_millis(LOW_BANDWIDTH_FRAME_MS)) {
+            // Over this client's frame-rate cap: answer "no change" without building a frame
+            let _ = rtx.send("NC".to_string());
+        } else {
+            // -a: every pane, for a low-bandwidth client that has no rows cached for some
+            let kind = if !low { if binary_frames { FrameKind::Binary } else { FrameKind::Styled } } else if args.contains(&"-a") { FrameKind::PlainAll } else { FrameKind::Plain };
+            last_frame_at = Some(Instant::now());
+            // -D: this client rebuilds frames from deltas
+            if args.contains(&"-D") { deltas.store(true, Ordering::Relaxed); }
+            if let (true, Some(rtx_bg)) = (full, &resp_tx_opt) {
+                let (ftx, frx) = mpsc::channel::<String>();
+                let _ = ftx.send(crate::delta::FULL_FRAME.to_string());
+                let _ = rtx_bg.send(frx);
+            }
+            let _ = tx.send(CtrlReq::DumpState(rtx, persistent && !full, kind, client_id));
+        }
         if let Some(ref rtx_bg) = resp_tx_opt {
             // Persistent mode: hand off to writer thread (non-blocking).
             // This lets the read loop keep processing keys immediately.
@@ -329,6 +467,59 @@ match cmd {
     "send-key" => {
         if let Some(payload) = args.get(0) { let _ = tx.send(CtrlReq::SendKey(payload.to_string())); }
     }
+    "send-bytes" => {
+        // send-bytes --hex H | --base64 B [--newline] [--expect P --timeout S]
+        // The --expect pattern is base64 so spaces survive the line protocol
+        let mut payload: Result<Vec<u8>, String> = Err("--hex or --base64 is required".to_string());
+        let mut newline = false;
+        let mut expect: Option<String> = None;
+        let mut timeout = 2.0f64;
+        let mut i = 0;
+        while i < args.len() {
+            let val = args.get(i + 1).copied().unwrap_or("");
+            match args[i] {
+                "--hex" => { payload = parse_hex_bytes(val); i += 1; }
+                "--base64" => { payload = base64_decode_bytes(val).ok_or_else(|| "invalid base64 payload".to_string()); i += 1; }
+                "--newline" => newline = true,
+                "--expect" => { expect = base64_decode(val); i += 1; }
+                "--timeout" => { timeout = val.parse().unwrap_or(timeout); i += 1; }
+                _ => {}
+            }
+            i += 1;
+        }
+        let re = match expect.as_deref().map(|p| crate::pattern::compile(p, false)).transpose() {
+            Ok(re) => re,
+            Err(e) => { payload = Err(format!("--expect: {}", e)); None }
+        };
+        let reply = match payload {
+            Err(e) => format!("send-bytes: {}", e),
+            Ok(mut bytes) => {
+                if newline { bytes.push(b'\r'); }
+                let capture = || {
+                    let (rtx, rrx) = mpsc::channel::<String>();
+                    let _ = tx.send(CtrlReq::CapturePane(rtx, at_pane));
+                    rrx.recv_timeout(Duration::from_millis(2000)).unwrap_or_default()
+                };
+                let before = if re.is_some() { capture() } else { String::new() };
+                let _ = tx.send(CtrlReq::SendBytes(bytes, at_pane));
+                match re {
+                    None => String::new(),
+                    Some(re) => {
+                        let deadline = Instant::now() + Duration::from_secs_f64(timeout.max(0.0));
+                        loop {
+                            std::thread::sleep(Duration::from_millis(25));
+                            if let Some(m) = new_match(&re, &before, &capture()) { break m; }
+                            if Instant::now() >= deadline {
+                                break format!("send-bytes: no output matching '{}' within {}s", re.as_str(), timeout);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        if !reply.is_empty() { let _ = writeln!(write_stream, "{}", reply); let _ = write_stream.flush(); }
+        if !persistent { break; }
+    }
     "zoom-pane" | "resize-pane" | "resizep" if args.iter().any(|a| *a == "-Z") => { let _ = tx.send(CtrlReq::ZoomPane); }
     "zoom-pane" => { let _ = tx.send(CtrlReq::ZoomPane); }
     "copy-enter" => { let _ = tx.send(CtrlReq::CopyEnter); }
@@ -337,8 +528,18 @@ match cmd {
     }
     "copy-anchor" => { let _ = tx.send(CtrlReq::CopyAnchor); }
     "copy-yank" => { let _ = tx.send(CtrlReq::CopyYank); }
+    "client-prefix" => { let _ = tx.send(CtrlReq::ClientPrefix(client_id, args.first() == Some(&"1"))); }
+    "client-subscribe" => {
+        let kind = if binary_frames { FrameKind::Binary } else { FrameKind::Styled };
+        let _ = tx.send(CtrlReq::ClientSubscribe(client_id, (args.first() != Some(&"0")).then_some(kind)));
+    }
+    "client-suspend" => {
+        let on = args.first() == Some(&"1");
+        suspended.store(on, Ordering::Relaxed);
+        let _ = tx.send(CtrlReq::ClientSuspend(client_id, on));
+    }
     "client-size" => {
-        if args.len() >= 2 { if let (Ok(w), Ok(h)) = (args[0].parse::<u16>(), args[1].parse::<u16>()) { let _ = tx.send(CtrlReq::ClientSize(w, h)); } }
+        if args.len() >= 2 { if let (Ok(w), Ok(h)) = (args[0].parse::<u16>(), args[1].parse::<u16>()) { let _ = tx.send(CtrlReq::ClientSize(client_id, w, h)); } }
     }
     "focus-pane" => {
         if let Some(pid) = args.get(0).and_then(|s| s.parse::<usize>().ok()) { let _ = tx.send(CtrlReq::FocusPaneCmd(pid)); }
@@ -361,6 +562,9 @@ match cmd {
     "mouse-up" => {
         if args.len()>=2 { if let (Ok(x),Ok(y))=(args[0].parse::<u16>(),args[1].parse::<u16>()) { let _ = tx.send(CtrlReq::MouseUp(x,y)); } }
     }
+    "mouse-select" => {
+        if args.len()>=3 { if let (Ok(x),Ok(y),Ok(n))=(args[0].parse::<u16>(),args[1].parse::<u16>(),args[2].parse::<u8>()) { let _ = tx.send(CtrlReq::MouseSelect(x,y,n)); } }
+    }
     "mouse-up-right" => {
         if args.len()>=2 { if let (Ok(x),Ok(y))=(args[0].parse::<u16>(),args[1].parse::<u16>()) { let _ = tx.send(CtrlReq::MouseUpRight(x,y)); } }
     }
@@ -382,7 +586,30 @@ match cmd {
     }
     "next-window" | "next" => { let _ = tx.send(CtrlReq::NextWindow); }
     "previous-window" | "prev" => { let _ = tx.send(CtrlReq::PrevWindow); }
-    "rename-window" | "renamew" => { if let Some(name) = args.get(0) { let _ = tx.send(CtrlReq::RenameWindow((*name).to_string())); } }
+    "rename-window" | "renamew" => {
+        if let Some(name) = args.get(0) {
+            if persistent {
+                let _ = tx.send(CtrlReq::RenameWindow((*name).to_string(), None, at_pane));
+            } else {
+                let (rtx, rrx) = mpsc::channel::<String>();
+                let _ = tx.send(CtrlReq::RenameWindow((*name).to_string(), Some(rtx), at_pane));
+                if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+                break;
+            }
+        }
+    }
+    "save-session" | "restore-session" => {
+        let snapshot = args.iter().find(|a| !a.starts_with('-')).map(|s| s.to_string());
+        let req = |resp| if cmd == "save-session" { CtrlReq::SaveSession(resp) } else { CtrlReq::RestoreSession(snapshot.clone(), resp) };
+        if persistent {
+            let _ = tx.send(req(None));
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(req(Some(rtx)));
+            if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+            break;
+        }
+    }
     "list-windows" | "lsw" => {
         // Extract -F format if provided
         let fmt = args.windows(2).find(|w| w[0] == "-F").map(|w| w[1].to_string());
@@ -407,48 +634,24 @@ match cmd {
     "toggle-sync" => { let _ = tx.send(CtrlReq::ToggleSync); }
     "set-pane-title" => { let title = args.join(" "); let _ = tx.send(CtrlReq::SetPaneTitle(title)); }
     "send-keys" => {
-        let literal = args.iter().any(|a| *a == "-l");
-        let has_x = args.iter().any(|a| *a == "-X");
-        // Parse -N <count> for repeat
-        let mut repeat_count: usize = 1;
-        if let Some(n_pos) = args.iter().position(|a| *a == "-N") {
-            if let Some(count_str) = args.get(n_pos + 1) {
-                repeat_count = count_str.parse::<usize>().unwrap_or(1).max(1);
-            }
-        }
-        if has_x {
-            // send-keys -X copy-mode-command — flags are only -X, -N, -t
-            let cmd_parts: Vec<&str> = args.iter().filter(|a| **a != "-X" && !a.starts_with('-')).copied().collect();
-            for _ in 0..repeat_count {
-                let _ = tx.send(CtrlReq::SendKeysX(cmd_parts.join(" ")));
-            }
-        } else {
-            // Proper flag-then-keys parsing: flags are only recognized BEFORE
-            // the first key argument. Once text starts, everything is a key.
-            // This fixes arguments starting with '-' being silently dropped.
-            let mut keys: Vec<&str> = Vec::new();
-            let mut parsing_flags = true;
-            let mut skip_next = false;
-            for arg in args.iter() {
-                if skip_next { skip_next = false; continue; }
-                if !parsing_flags {
-                    keys.push(arg);
-                } else if *arg == "--" {
-                    parsing_flags = false;
-                } else if *arg == "-l" || *arg == "-H" || *arg == "-R" {
-                    // Known boolean flags — skip
-                } else if *arg == "-t" || *arg == "-N" {
-                    // Known flags with an argument — skip next too
-                    skip_next = true;
-                } else {
-                    // First non-flag argument — start collecting keys
-                    parsing_flags = false;
-                    keys.push(arg);
+        match crate::input::parse_send_keys(&args) {
+            Ok(sk) => {
+                if sk.reset { let _ = tx.send(CtrlReq::ResetTerminal(at_pane)); }
+                if sk.copy_command {
+                    // send-keys -X copy-mode-command
+                    for _ in 0..sk.repeat { let _ = tx.send(CtrlReq::SendKeysX(sk.keys.join(" "), at_pane)); }
+                } else if sk.hex {
+                    match crate::input::hex_keys(&sk.keys) {
+                        Ok(bytes) => { let _ = tx.send(CtrlReq::SendBytes(bytes.repeat(sk.repeat), at_pane)); }
+                        Err(e) if persistent => { let _ = tx.send(CtrlReq::ShowMessage(e)); }
+                        Err(e) => { let _ = writeln!(write_stream, "psmux: send-keys: {}", e); let _ = write_stream.flush(); }
+                    }
+                } else if !sk.keys.is_empty() {
+                    for _ in 0..sk.repeat { let _ = tx.send(CtrlReq::SendKeys(sk.keys.join(" "), sk.literal, at_pane)); }
                 }
             }
-            for _ in 0..repeat_count {
-                let _ = tx.send(CtrlReq::SendKeys(keys.join(" "), literal));
-            }
+            Err(e) if persistent => { let _ = tx.send(CtrlReq::ShowMessage(e)); }
+            Err(e) => { let _ = writeln!(write_stream, "psmux: send-keys: {}", e); let _ = write_stream.flush(); }
         }
     }
     "select-pane" | "selectp" => {
@@ -510,7 +713,7 @@ match cmd {
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
-    "kill-window" | "killw" => { let _ = tx.send(CtrlReq::KillWindow); }
+    "kill-window" | "killw" => { let _ = tx.send(CtrlReq::KillWindow(at_pane)); }
     "kill-session" => { let _ = tx.send(CtrlReq::KillSession); }
     "has-session" => {
         let (rtx, rrx) = mpsc::channel::<bool>();
@@ -525,10 +728,20 @@ match cmd {
         }
     }
     "swap-pane" | "swapp" => {
-        let dir = if args.iter().any(|a| *a == "-U") { "U" }
-            else if args.iter().any(|a| *a == "-D") { "D" }
-            else { "D" };
-        let _ = tx.send(CtrlReq::SwapPane(dir.to_string()));
+        // -t names the pane to swap with; without it, the marked pane
+        let with = if args.contains(&"-U") { SwapWith::Previous }
+            else if args.contains(&"-D") { SwapWith::Next }
+            else if let Some(t) = raw_target.clone() { SwapWith::Target(t) }
+            else { SwapWith::Marked };
+        let keep_focus = has_flag(&args, 'd');
+        if persistent {
+            let _ = tx.send(CtrlReq::SwapPane(with, keep_focus, None));
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::SwapPane(with, keep_focus, Some(rtx)));
+            if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+            break;
+        }
     }
     "resize-pane" | "resizep" => {
         // Check for zoom toggle first (issue #35)
@@ -550,20 +763,36 @@ match cmd {
             let _ = tx.send(CtrlReq::ResizePane(dir.to_string(), amount));
         }
     }
-    "set-buffer" => {
-        let content = args.iter().filter(|a| !a.starts_with('-')).cloned().collect::<Vec<&str>>().join(" ");
-        let _ = tx.send(CtrlReq::SetBuffer(content));
+    "set-buffer" | "setb" => {
+        let mut name = None;
+        let mut append = false;
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < args.len() {
+            match args[i] {
+                "-a" => append = true,
+                "-b" => { name = args.get(i + 1).map(|s| s.to_string()); i += 1; }
+                "-t" => i += 1,
+                "-w" => {}
+                a => words.push(a),
+            }
+            i += 1;
+        }
+        let _ = tx.send(CtrlReq::SetBuffer(name, words.join(" "), append));
     }
     "paste-buffer" | "pasteb" => {
-        let buf_idx: Option<usize> = args.windows(2).find(|w| w[0] == "-b").and_then(|w| w[1].parse().ok());
+        let name = args.windows(2).find(|w| w[0] == "-b").map(|w| w[1].to_string());
         let (rtx, rrx) = mpsc::channel::<String>();
-        if let Some(idx) = buf_idx {
-            let _ = tx.send(CtrlReq::ShowBufferAt(rtx, idx));
-        } else {
-            let _ = tx.send(CtrlReq::ShowBuffer(rtx));
-        }
-        if let Ok(text) = rrx.recv() {
-            let _ = tx.send(CtrlReq::SendText(text));
+        let _ = tx.send(CtrlReq::ShowBuffer(rtx, name.clone()));
+        if let Ok(mut text) = rrx.recv() {
+            // -p: with no buffers, paste the system clipboard
+            if text.is_empty() && name.is_none() && args.contains(&"-p") {
+                let (ctx, crx) = mpsc::channel::<String>();
+                let _ = tx.send(CtrlReq::ReadClipboard(ctx));
+                text = crx.recv().unwrap_or_default();
+            }
+            if !text.is_empty() { let _ = tx.send(CtrlReq::PasteText(text, args.contains(&"-s"))); }
+            if args.contains(&"-d") { let _ = tx.send(CtrlReq::DeleteBuffer(name)); }
         }
     }
     "list-buffers" | "lsb" => {
@@ -577,25 +806,44 @@ match cmd {
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
-    "show-buffer" => {
+    "show-buffer" | "showb" => {
+        let name = args.windows(2).find(|w| w[0] == "-b").map(|w| w[1].to_string());
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::ShowBuffer(rtx));
+        let _ = tx.send(CtrlReq::ShowBuffer(rtx, name));
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
-    "delete-buffer" => { let _ = tx.send(CtrlReq::DeleteBuffer); }
-    "choose-buffer" => {
+    "delete-buffer" | "deleteb" => {
+        let name = args.windows(2).find(|w| w[0] == "-b").map(|w| w[1].to_string());
+        let _ = tx.send(CtrlReq::DeleteBuffer(name));
+    }
+    "choose-buffer" | "chooseb" => {
+        let fmt = args.windows(2).find(|w| w[0] == "-F").map(|w| w[1].to_string());
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::ChooseBuffer(rtx));
+        match fmt {
+            Some(fmt_str) => { let _ = tx.send(CtrlReq::ListBuffersFormat(rtx, fmt_str)); }
+            None => { let _ = tx.send(CtrlReq::ChooseBuffer(rtx)); }
+        }
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
     "display-message" | "display" => {
-        let fmt = args.iter().filter(|a| !a.starts_with('-')).cloned().collect::<Vec<&str>>().join(" ");
-        let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::DisplayMessage(rtx, fmt));
-        if let Ok(text) = rrx.recv() { let _ = writeln!(write_stream, "{}", text); let _ = write_stream.flush(); }
-        if !persistent { break; }
+        if let Some(pos) = args.iter().position(|a| *a == "-I") {
+            // Payload is base64 so stdin's newlines survive the line protocol
+            if let Some(text) = args.get(pos + 1).and_then(|b| base64_decode(b)) {
+                let _ = tx.send(CtrlReq::DisplayInput(text, at_pane));
+            }
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            if args.contains(&"-a") {
+                let _ = tx.send(CtrlReq::DisplayVariables(rtx, at_pane));
+            } else {
+                let fmt = args.iter().filter(|a| !a.starts_with('-')).cloned().collect::<Vec<&str>>().join(" ");
+                let _ = tx.send(CtrlReq::DisplayMessage(rtx, fmt, at_pane));
+            }
+            if let Ok(text) = rrx.recv() { let _ = writeln!(write_stream, "{}", text); let _ = write_stream.flush(); }
+            if !persistent { break; }
+        }
     }
     "last-window" | "last" => { let _ = tx.send(CtrlReq::LastWindow); }
     "last-pane" | "lastp" => { let _ = tx.send(CtrlReq::LastPane); }
@@ -604,21 +852,71 @@ match cmd {
         let _ = tx.send(CtrlReq::RotateWindow(reverse));
     }
     "display-panes" | "displayp" => { let _ = tx.send(CtrlReq::DisplayPanes); }
-    "break-pane" | "breakp" => { let _ = tx.send(CtrlReq::BreakPane); }
-    "join-pane" | "joinp" => {
-        if let Some(wid) = args.iter().find(|a| !a.starts_with('-')).and_then(|s| s.parse::<usize>().ok()) {
-            let _ = tx.send(CtrlReq::JoinPane(wid));
+    "break-pane" | "breakp" => { let _ = tx.send(CtrlReq::BreakPane(has_flag(&args, 'd'))); }
+    "join-pane" | "joinp" | "move-pane" | "movep" => {
+        let kind = if has_flag(&args, 'h') { LayoutKind::Horizontal } else { LayoutKind::Vertical };
+        let src = flag_value(&args, "-s").map(str::to_string);
+        // A bare number is the destination window (`join-pane 2`)
+        let dst = raw_target.clone().or_else(|| args.iter().enumerate()
+            .find(|(i, a)| a.parse::<usize>().is_ok() && (*i == 0 || !matches!(args[i - 1], "-s" | "-l" | "-p")))
+            .map(|(_, n)| format!(":{}", n)));
+        let size = pane_size_arg(&args);
+        let keep_focus = has_flag(&args, 'd');
+        if persistent {
+            let _ = tx.send(CtrlReq::JoinPane(src, dst, kind, size, keep_focus, None));
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::JoinPane(src, dst, kind, size, keep_focus, Some(rtx)));
+            if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+            break;
+        }
+    }
+    "respawn-pane" | "respawnp" => {
+        let kill = has_flag(&args, 'k');
+        if persistent {
+            let _ = tx.send(CtrlReq::RespawnPane(kill, None, at_pane));
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::RespawnPane(kill, Some(rtx), at_pane));
+            if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+            break;
         }
     }
-    "respawn-pane" | "respawnp" => { let _ = tx.send(CtrlReq::RespawnPane); }
     "session-info" => {
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::SessionInfo(rtx));
+        let _ = tx.send(CtrlReq::SessionInfo(rtx, has_flag(&args, 'v')));
         if let Ok(line) = rrx.recv() { let _ = write!(write_stream, "{}\n", line); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
-    "client-attach" => { let _ = tx.send(CtrlReq::ClientAttach); if !persistent { let _ = write!(write_stream, "ok\n"); } }
-    "client-detach" => { let _ = tx.send(CtrlReq::ClientDetach); if !persistent { let _ = write!(write_stream, "ok\n"); } }
+    "client-attach" => {
+        // Persistent clients get an id and a directive channel so the server
+        // can track them individually (idle detach, messages).  -r marks a
+        // remote (SSH) client; -l names the session it switched from.
+        if let (Some(ref rtx_bg), None) = (&resp_tx_opt, client_id) {
+            let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+            let pipe = write_stream.try_clone().ok().map(Arc::from);
+            let last = args.windows(2).find(|w| w[0] == "-l").map(|w| w[1].to_string());
+            let _ = tx.send(CtrlReq::ClientRegister(id, args.contains(&"-r"), rtx_bg.clone(), low_bandwidth.clone(), pipe, last));
+            client_id = Some(id);
+        }
+        let _ = tx.send(CtrlReq::ClientAttach);
+        if !persistent { let _ = write!(write_stream, "ok\n"); }
+    }
+    "client-environment" => {
+        if let Some(encoded) = args.first() {
+            let _ = tx.send(CtrlReq::ClientEnvironment(crate::environ::decode_client_env(encoded)));
+        }
+    }
+    "client-prompt-history" => {
+        if let Some(entry) = args.first().and_then(|b| base64_decode(b)) {
+            let _ = tx.send(CtrlReq::PromptHistory(entry));
+        }
+    }
+    "client-detach" => {
+        if let Some(id) = client_id.take() { let _ = tx.send(CtrlReq::ClientUnregister(id)); }
+        let _ = tx.send(CtrlReq::ClientDetach);
+        if !persistent { let _ = write!(write_stream, "ok\n"); }
+    }
     "bind-key" | "bind" => {
         let mut table = "prefix".to_string();
         let mut repeatable = false;
@@ -645,9 +943,22 @@ match cmd {
         }
     }
     "unbind-key" | "unbind" => {
-        let non_flag_args: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
-        if let Some(key) = non_flag_args.first() {
-            let _ = tx.send(CtrlReq::UnbindKey(key.to_string()));
+        let mut table = "prefix".to_string();
+        let mut all = false;
+        let mut key = None;
+        let mut i = 0;
+        while i < args.len() {
+            match args[i] {
+                "-T" => { if let Some(t) = args.get(i + 1) { table = t.to_string(); } i += 1; }
+                "-n" => table = "root".to_string(),
+                "-a" => all = true,
+                a if a.starts_with('-') && a.len() > 1 => {}
+                a => { key = Some(a.to_string()); break; }
+            }
+            i += 1;
+        }
+        if all || key.is_some() {
+            let _ = tx.send(CtrlReq::UnbindKey(table, if all { None } else { key }));
         }
     }
     "list-keys" | "lsk" => {
@@ -657,11 +968,19 @@ match cmd {
         if !persistent { break; }
     }
     "set-option" | "set" | "set-window-option" | "setw" => {
-        let has_u = args.iter().any(|a| *a == "-u");
-        let has_a = args.iter().any(|a| *a == "-a");
-        let has_q = args.iter().any(|a| *a == "-q");
+        let has_u = has_flag(&args, 'u');
+        let has_a = has_flag(&args, 'a');
+        let has_q = has_flag(&args, 'q');
         let non_flag_args: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
-        if has_u {
+        let scope = if has_flag(&args, 'g') { None }
+            else if has_flag(&args, 'p') { Some(OptionScope::Pane) }
+            else if has_flag(&args, 'w') || matches!(cmd, "set-window-option" | "setw") { Some(OptionScope::Window) }
+            else { None };
+        let scope = scope.filter(|_| non_flag_args.first().is_some_and(|o| SCOPED_OPTIONS.contains(o)));
+        if let Some(scope) = scope {
+            let value = (!has_u).then(|| non_flag_args[1..].join(" "));
+            let _ = tx.send(CtrlReq::SetScopedOption(scope, non_flag_args[0].to_string(), value, at_pane));
+        } else if has_u {
             if let Some(option) = non_flag_args.first() {
                 let _ = tx.send(CtrlReq::SetOptionUnset(option.to_string()));
             }
@@ -677,30 +996,35 @@ match cmd {
             // set -q <option> with no value — silently ignore
         }
     }
-    "show-options" | "show" | "show-window-options" | "showw" => {
-        let has_v = args.iter().any(|a| *a == "-v");
-        let has_q = args.iter().any(|a| *a == "-q");
+    "show-options" | "show" | "show-option" | "show-window-options" | "show-window-option" | "showw" => {
+        let has_v = has_flag(&args, 'v');
+        let has_q = has_flag(&args, 'q');
+        // -w, -p and showw show what the active window or pane sees
+        let scope = if has_flag(&args, 'g') { None }
+            else if has_flag(&args, 'p') { Some(OptionScope::Pane) }
+            else if has_flag(&args, 'w') || cmd.starts_with("show-window-option") || cmd == "showw" { Some(OptionScope::Window) }
+            else { None };
         let opt_name: Option<&str> = args.iter()
             .filter(|a| !a.starts_with('-'))
             .copied()
             .last();
-        if has_v || (opt_name.is_some() && !has_q) {
+        if let Some(name) = opt_name {
             // Single-option query: show-options -v <name> or show <name>
-            if let Some(name) = opt_name {
-                let (rtx, rrx) = mpsc::channel::<String>();
-                let _ = tx.send(CtrlReq::ShowOptionValue(rtx, name.to_string()));
-                if let Ok(text) = rrx.recv() {
-                    if has_v {
-                        let _ = write!(write_stream, "{}\n", text);
-                    } else {
-                        let _ = write!(write_stream, "{} {}\n", name, text);
-                    }
-                    let _ = write_stream.flush();
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::ShowOptionValue(rtx, name.to_string(), scope, at_pane));
+            if let Ok(text) = rrx.recv() {
+                if has_q && text.is_empty() {
+                    // -q: an unset option prints nothing (scripts test for "")
+                } else if has_v {
+                    let _ = write!(write_stream, "{}\n", text);
+                } else {
+                    let _ = write!(write_stream, "{} {}\n", name, text);
                 }
+                let _ = write_stream.flush();
             }
         } else {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::ShowOptions(rtx));
+            let _ = tx.send(CtrlReq::ShowOptions(rtx, scope, at_pane));
             if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         }
         if !persistent { break; }
@@ -712,11 +1036,22 @@ match cmd {
         }
     }
     "move-window" | "movew" => {
-        let target = args.iter().find(|a| a.parse::<usize>().is_ok()).and_then(|s| s.parse().ok());
-        let _ = tx.send(CtrlReq::MoveWindow(target));
+        let source = flag_value(&args, "-s").map(str::to_string);
+        // A bare index, but not the -s value
+        let target = target_win.or_else(|| args.iter().enumerate()
+            .filter(|&(i, _)| i == 0 || args[i - 1] != "-s")
+            .find_map(|(_, a)| a.parse().ok()));
+        let (rtx, rrx) = mpsc::channel::<String>();
+        let _ = tx.send(CtrlReq::MoveWindow(source, target, has_flag(&args, 'k'), has_flag(&args, 'r'), has_flag(&args, 'd'), rtx));
+        if let Ok(err_msg) = rrx.recv_timeout(Duration::from_millis(2000)) {
+            if !err_msg.is_empty() {
+                if persistent { let _ = tx.send(CtrlReq::ShowMessage(err_msg)); }
+                else { let _ = write!(write_stream, "{}\n", err_msg); let _ = write_stream.flush(); }
+            }
+        }
     }
     "swap-window" | "swapw" => {
-        if let Some(target) = args.iter().find(|a| a.parse::<usize>().is_ok()).and_then(|s| s.parse().ok()) {
+        if let Some(target) = target_win.or_else(|| args.iter().find(|a| a.parse::<usize>().is_ok()).and_then(|s| s.parse().ok())) {
             let _ = tx.send(CtrlReq::SwapWindow(target));
         }
     }
@@ -734,26 +1069,28 @@ match cmd {
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
-    "move-pane" | "movep" => {
-        if let Some(target) = args.iter().find(|a| a.parse::<usize>().is_ok()).and_then(|s| s.parse().ok()) {
-            let _ = tx.send(CtrlReq::MovePane(target));
-        }
-    }
     "pipe-pane" | "pipep" => {
         let stdin_flag = args.iter().any(|a| *a == "-I");
         let stdout_flag = args.iter().any(|a| *a == "-O");
-        let _toggle = args.iter().any(|a| *a == "-o");
+        let toggle = args.iter().any(|a| *a == "-o");
         let cmd = args.iter().filter(|a| !a.starts_with('-')).cloned().collect::<Vec<&str>>().join(" ");
         let (stdin, stdout) = if !stdin_flag && !stdout_flag {
             (false, true)
         } else {
             (stdin_flag, stdout_flag)
         };
-        let _ = tx.send(CtrlReq::PipePane(cmd, stdin, stdout));
+        let _ = tx.send(CtrlReq::PipePane(cmd, stdin, stdout, toggle, at_pane));
     }
     "select-layout" | "selectl" => {
         let layout = args.iter().find(|a| !a.starts_with('-')).unwrap_or(&"tiled").to_string();
-        let _ = tx.send(CtrlReq::SelectLayout(layout));
+        if persistent {
+            let _ = tx.send(CtrlReq::SelectLayout(layout, None));
+        } else {
+            let (rtx, rrx) = mpsc::channel::<String>();
+            let _ = tx.send(CtrlReq::SelectLayout(layout, Some(rtx)));
+            if let Ok(err) = rrx.recv() { let _ = write!(write_stream, "{}", err); let _ = write_stream.flush(); }
+            break;
+        }
     }
     "next-layout" => {
         let _ = tx.send(CtrlReq::NextLayout);
@@ -770,57 +1107,115 @@ match cmd {
             let table = args.windows(2).find(|w| w[0] == "-T").map(|w| w[1].to_string()).unwrap_or_default();
             let _ = tx.send(CtrlReq::SwitchClientTable(table));
         } else {
-            let target = args.iter().find(|a| !a.starts_with('-')).unwrap_or(&"").to_string();
-            let _ = tx.send(CtrlReq::SwitchClient(target));
+            let to = if let Some(t) = raw_target.as_deref() { SwitchTo::Named(t.trim_matches('"').to_string()) }
+                else if has_flag(&args, 'n') { SwitchTo::Next }
+                else if has_flag(&args, 'p') { SwitchTo::Previous }
+                else if has_flag(&args, 'l') { SwitchTo::Last }
+                else { SwitchTo::Named(String::new()) };
+            let (rtx, rrx) = mpsc::channel();
+            let _ = tx.send(CtrlReq::SwitchClient(client_id, flag_value(&args, "-c").map(str::to_string), to, rtx));
+            if let Ok(Err(e)) = rrx.recv() {
+                if persistent {
+                    let _ = tx.send(CtrlReq::ShowMessage(e));
+                } else {
+                    let _ = writeln!(write_stream, "psmux: switch-client: {}", e);
+                    let _ = write_stream.flush();
+                }
+            }
         }
     }
     "lock-client" => {
         let _ = tx.send(CtrlReq::LockClient);
     }
+    "begin-batch" | "commit-batch" | "abort-batch" => {
+        // Frames to every client are held back between begin-batch and
+        // commit-batch on this connection (see helpers::FrameBatch).
+        let (rtx, rrx) = mpsc::channel::<String>();
+        let token = *batch_token.get_or_insert_with(|| NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed));
+        if cmd == "begin-batch" {
+            let _ = tx.send(CtrlReq::BeginBatch(token, rtx));
+        } else {
+            let _ = tx.send(CtrlReq::EndBatch(token, cmd == "commit-batch", rtx));
+        }
+        if let Ok(err_msg) = rrx.recv_timeout(Duration::from_millis(2000)) {
+            if err_msg.is_empty() {
+                batch_open = cmd == "begin-batch";
+            } else {
+                let _ = write!(write_stream, "{}\n", err_msg);
+                let _ = write_stream.flush();
+            }
+        }
+    }
     "refresh-client" => {
-        let _ = tx.send(CtrlReq::RefreshClient);
+        // -C WxH (or W,H): the size tmux control clients report, here the
+        // size used while no client is attached
+        if let Some(size) = args.windows(2).find(|w| w[0] == "-C").map(|w| w[1]) {
+            let mut dims = size.split(['x', ',']).map(|d| d.parse::<u16>().ok());
+            if let (Some(Some(w)), Some(Some(h)), None) = (dims.next(), dims.next(), dims.next()) {
+                let _ = tx.send(CtrlReq::ResizeWindow(Some(w), Some(h)));
+            }
+        } else {
+            let flags = args.windows(2).find(|w| w[0] == "-f").map(|w| w[1].trim_matches('"').to_string());
+            let _ = tx.send(CtrlReq::RefreshClient(client_id, flags));
+        }
     }
-    "suspend-client" => {
-        let _ = tx.send(CtrlReq::SuspendClient);
+    "suspend-client" | "suspendc" => {
+        let _ = tx.send(CtrlReq::SuspendClient(client_id));
     }
     "copy-mode-page-up" => {
         let _ = tx.send(CtrlReq::CopyModePageUp);
     }
-    "clear-history" => {
-        let _ = tx.send(CtrlReq::ClearHistory);
+    "clear-history" | "clearhist" => {
+        let _ = tx.send(CtrlReq::ClearHistory(args.contains(&"-H"), at_pane));
     }
-    "save-buffer" | "saveb" => {
-        let path = args.iter().find(|a| !a.starts_with('-')).unwrap_or(&"").to_string();
-        let _ = tx.send(CtrlReq::SaveBuffer(path));
+    "clear-screen-and-history" => {
+        // C-l has the shell redraw its prompt on the emptied screen
+        let _ = tx.send(CtrlReq::ClearHistory(true, at_pane));
+        let _ = tx.send(CtrlReq::SendBytes(vec![0x0c], at_pane));
     }
-    "load-buffer" | "loadb" => {
-        let path = args.iter().find(|a| !a.starts_with('-')).unwrap_or(&"").to_string();
-        let _ = tx.send(CtrlReq::LoadBuffer(path));
+    "save-buffer" | "saveb" | "load-buffer" | "loadb" => {
+        let name = args.windows(2).find(|w| w[0] == "-b").map(|w| w[1].to_string());
+        let path = args.iter().enumerate()
+            .find(|(i, a)| !a.starts_with('-') && (*i == 0 || args[i - 1] != "-b"))
+            .map(|(_, a)| a.to_string()).unwrap_or_default();
+        let _ = tx.send(if cmd.starts_with('s') { CtrlReq::SaveBuffer(path, name) } else { CtrlReq::LoadBuffer(path, name) });
     }
     "set-environment" | "setenv" => {
-        let non_flag: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
-        if non_flag.len() >= 2 {
-            let _ = tx.send(CtrlReq::SetEnvironment(non_flag[0].to_string(), non_flag[1].to_string()));
-        } else if non_flag.len() == 1 {
-            let _ = tx.send(CtrlReq::SetEnvironment(non_flag[0].to_string(), String::new()));
+        if let Some(set) = crate::environ::parse_set(&args) {
+            let _ = tx.send(CtrlReq::SetEnvironment(set));
         }
     }
     "show-environment" | "showenv" => {
+        let name = args.iter().find(|a| !a.starts_with('-')).copied();
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::ShowEnvironment(rtx));
-        if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
+        let _ = tx.send(CtrlReq::ShowEnvironment(rtx, has_flag(&args, 'g')));
+        if let Ok(text) = rrx.recv() {
+            match name {
+                // show-environment NAME prints just that variable
+                Some(n) => {
+                    let prefix = format!("{}=", n);
+                    let removed = format!("-{}", n);
+                    if let Some(l) = text.lines().find(|l| l.starts_with(&prefix) || *l == removed) {
+                        let _ = write!(write_stream, "{}\n", l);
+                    }
+                }
+                None => { let _ = write!(write_stream, "{}\n", text); }
+            }
+            let _ = write_stream.flush();
+        }
         if !persistent { break; }
     }
     "set-hook" => {
-        let non_flag: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
-        if non_flag.len() >= 2 {
-            let _ = tx.send(CtrlReq::SetHook(non_flag[0].to_string(), non_flag[1..].join(" ")));
+        if let Some(set) = crate::hooks::parse_set_hook(&args) {
+            let _ = tx.send(CtrlReq::SetHook(set));
         }
     }
     "show-hooks" => {
         let (rtx, rrx) = mpsc::channel::<String>();
-        let _ = tx.send(CtrlReq::ShowHooks(rtx));
-        if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
+        // -g changes nothing: a session's hooks are all global ones
+        let only = args.iter().find(|a| !a.starts_with('-')).map(|a| a.to_string());
+        let _ = tx.send(CtrlReq::ShowHooks(rtx, only));
+        if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}", text); let _ = write_stream.flush(); }
         if !persistent { break; }
     }
     "wait-for" => {
@@ -835,57 +1230,80 @@ match cmd {
         let _ = tx.send(CtrlReq::WaitFor(channel, op));
     }
     "display-menu" | "menu" => {
-        let mut x_pos: Option<i16> = None;
-        let mut y_pos: Option<i16> = None;
-        let mut i = 0;
-        while i < args.len() {
-            match args[i] {
-                "-x" => { if let Some(v) = args.get(i+1) { x_pos = v.parse().ok(); i += 1; } }
-                "-y" => { if let Some(v) = args.get(i+1) { y_pos = v.parse().ok(); i += 1; } }
-                _ => {}
-            }
-            i += 1;
-        }
-        let menu = args.iter().filter(|a| !a.starts_with('-') && a.parse::<i16>().is_err()).cloned().collect::<Vec<&str>>().join(" ");
-        let _ = tx.send(CtrlReq::DisplayMenu(menu, x_pos, y_pos));
+        // Taken from the line as typed: separators are quoted empty words
+        let def = line.trim_start().split_once(char::is_whitespace).map_or("", |(_, r)| r);
+        let _ = tx.send(CtrlReq::DisplayMenu(def.to_string()));
     }
     "display-popup" | "popup" => {
-        let close_on_exit = args.iter().any(|a| *a == "-E");
-        let mut width: u16 = 80;
-        let mut height: u16 = 24;
-        let mut i = 0;
-        while i < args.len() {
-            match args[i] {
-                "-w" => { if let Some(v) = args.get(i+1) { width = v.trim_end_matches('%').parse().unwrap_or(80); i += 1; } }
-                "-h" => { if let Some(v) = args.get(i+1) { height = v.trim_end_matches('%').parse().unwrap_or(24); i += 1; } }
-                _ => {}
+        match crate::popup::parse_args(&args) {
+            Ok(p) => { let _ = tx.send(CtrlReq::DisplayPopup(p)); }
+            Err(e) if persistent => { let _ = tx.send(CtrlReq::ShowMessage(e)); }
+            Err(e) => {
+                let _ = writeln!(write_stream, "psmux: display-popup: {}", e);
+                let _ = write_stream.flush();
             }
-            i += 1;
         }
-        let content = args.iter().filter(|a| !a.starts_with('-')).cloned().collect::<Vec<&str>>().join(" ");
-        let _ = tx.send(CtrlReq::DisplayPopup(content, width, height, close_on_exit));
     }
     "confirm-before" | "confirm" => {
-        let mut prompt: Option<String> = None;
-        let mut i = 0;
-        while i < args.len() {
-            if args[i] == "-p" {
-                if let Some(p) = args.get(i+1) { prompt = Some(p.to_string()); i += 1; }
+        match crate::prompt::parse_confirm(&args) {
+            Ok(p) => { let _ = tx.send(CtrlReq::ConfirmBefore(p)); }
+            Err(e) if persistent => { let _ = tx.send(CtrlReq::ShowMessage(e)); }
+            Err(e) => {
+                let _ = writeln!(write_stream, "psmux: confirm-before: {}", e);
+                let _ = write_stream.flush();
             }
-            i += 1;
         }
-        let non_flag: Vec<&str> = args.iter().filter(|a| !a.starts_with('-') && Some(&a.to_string()) != prompt.as_ref()).copied().collect();
-        let command = non_flag.join(" ");
-        let prompt_str = prompt.unwrap_or_else(|| format!("Run '{}'?", command));
-        let _ = tx.send(CtrlReq::ConfirmBefore(prompt_str, command));
     }
     // tmux standard aliases
-    "detach-client" | "detach" => { let _ = tx.send(CtrlReq::ClientDetach); }
+    "detach-client" | "detach" => {
+        // -t client, -a all but it; the clients told leave by themselves
+        let (rtx, rrx) = mpsc::channel();
+        let target = raw_target.as_deref().map(|t| t.trim_matches('"').to_string());
+        let _ = tx.send(CtrlReq::DetachClient(client_id, target, has_flag(&args, 'a'), rtx));
+        if let Ok(Err(e)) = rrx.recv() {
+            if persistent {
+                let _ = tx.send(CtrlReq::ShowMessage(e));
+            } else {
+                let _ = writeln!(write_stream, "psmux: detach-client: {}", e);
+                let _ = write_stream.flush();
+            }
+        }
+    }
     "attach-session" | "attach" => { let _ = tx.send(CtrlReq::ClientAttach); }
     "kill-server" => { let _ = tx.send(CtrlReq::KillServer); }
-    "choose-tree" | "choose-window" | "choose-session" => {
-        // These are interactive choosers — send a dump that client handles
-        // For now, map to listing which the client renders as a chooser
+    "choose-tree" | "choose-window" => {
+        // Shown on the attached client; -s may pick the one entry at once
+        let (rtx, rrx) = mpsc::channel();
+        let _ = tx.send(CtrlReq::ChooseTree(args.iter().map(|a| a.to_string()).collect(), cmd == "choose-window", rtx));
+        match rrx.recv() {
+            Ok(Ok(Some(chosen))) => {
+                let mut chain = crate::config::split_chained_commands_pub(&chosen).into_iter();
+                if let Some(first) = chain.next() {
+                    queued_lines.extend(chain.map(|c| format!("{}\n", c)));
+                    line.clear();
+                    line.push_str(&first);
+                    line.push('\n');
+                    continue;
+                }
+            }
+            Ok(Err(e)) => {
+                if persistent {
+                    let _ = tx.send(CtrlReq::ShowMessage(e));
+                } else {
+                    let _ = writeln!(write_stream, "{}", e);
+                    let _ = write_stream.flush();
+                }
+            }
+            _ => {}
+        }
+        if !persistent { break; }
+    }
+    "choose-session" if persistent => {
+        // The client lists every server itself
+        let _ = tx.send(CtrlReq::ChooseSession(client_id));
+    }
+    "choose-session" => {
+        // Listed for the client, which renders its own session chooser
         let (rtx, rrx) = mpsc::channel::<String>();
         let _ = tx.send(CtrlReq::ListTree(rtx));
         if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
@@ -906,61 +1324,54 @@ match cmd {
         if !persistent { break; }
     }
     "command-prompt" => {
-        let initial = args.windows(2).find(|w| w[0] == "-I").map(|w| w[1].to_string()).unwrap_or_default();
-        let _ = tx.send(CtrlReq::CommandPrompt(initial));
+        match crate::prompt::parse_args(&args) {
+            Ok(p) => { let _ = tx.send(CtrlReq::CommandPrompt(p)); }
+            Err(e) if persistent => { let _ = tx.send(CtrlReq::ShowMessage(e)); }
+            Err(e) => {
+                let _ = writeln!(write_stream, "psmux: command-prompt: {}", e);
+                let _ = write_stream.flush();
+            }
+        }
     }
     "run-shell" | "run" => {
-        let background = args.iter().any(|a| *a == "-b");
-        let cmd_parts: Vec<&str> = args.iter().filter(|a| !a.starts_with('-')).copied().collect();
-        let shell_cmd = cmd_parts.join(" ");
-        let shell_cmd = shell_cmd.trim_matches(|c: char| c == '\'' || c == '"').to_string();
+        // Re-parse from the original line to keep the quoted command whole
+        let run_parsed = parse_command_line(line.trim());
+        let background = run_parsed.iter().skip(1).any(|a| a.starts_with('-') && a.contains('b'));
+        let shell_cmd = run_parsed.iter().skip(1).filter(|a| !a.starts_with('-')).cloned().collect::<Vec<_>>().join(" ");
         if !shell_cmd.is_empty() {
-            if background {
-                let _ = std::process::Command::new("pwsh").args(["-NoProfile", "-Command", &shell_cmd]).spawn();
+            if background || persistent {
+                // Key bindings: the output goes to the client's view overlay
+                let _ = tx.send(CtrlReq::RunShell(shell_cmd, background, None));
             } else {
-                let output = std::process::Command::new("pwsh")
-                    .args(["-NoProfile", "-Command", &shell_cmd]).output();
-                if let Ok(out) = output {
-                    let text = String::from_utf8_lossy(&out.stdout);
-                    if !text.is_empty() {
-                        let _ = write!(write_stream, "{}", text);
-                        let _ = write_stream.flush();
-                    }
+                // CLI: output, then the exit status for the caller to exit with
+                let (rtx, rrx) = mpsc::channel::<(i32, String)>();
+                let _ = tx.send(CtrlReq::RunShell(shell_cmd, false, Some(rtx)));
+                if let Ok((status, text)) = rrx.recv() {
+                    let _ = write!(write_stream, "{}", text);
+                    if !text.is_empty() && !text.ends_with('\n') { let _ = write!(write_stream, "\n"); }
+                    let _ = write!(write_stream, "%exit {}\n", status);
+                    let _ = write_stream.flush();
                 }
             }
         }
+        if !persistent { break; }
     }
     "if-shell" | "if" => {
         // Re-parse from the original line to preserve quoted arguments
-        let if_parsed = parse_command_line(line.trim());
-        let format_mode = if_parsed.iter().any(|a| a == "-F" || a == "-bF" || a == "-Fb");
-        // Collect positional args (skip command name and flags)
-        let positional: Vec<&str> = if_parsed.iter().skip(1)
-            .filter(|a| !a.starts_with('-'))
-            .map(|s| s.as_str())
-            .collect();
-        if positional.len() >= 2 {
-            let condition = positional[0];
-            let true_cmd = positional[1];
-            let false_cmd = positional.get(2).copied();
-            let success = if format_mode {
-                !condition.is_empty() && condition != "0"
-            } else {
-                std::process::Command::new("pwsh")
-                    .args(["-NoProfile", "-Command", condition])
-                    .stdout(std::process::Stdio::null())
-                    .stderr(std::process::Stdio::null())
-                    .status()
-                    .map(|s| s.success()).unwrap_or(false)
-            };
-            let cmd_to_run = if success { Some(true_cmd) } else { false_cmd };
-            if let Some(chosen) = cmd_to_run {
-                // Feed the chosen command back into the line buffer so the
-                // main dispatch loop processes it as a regular command.
-                line.clear();
-                line.push_str(chosen);
-                line.push('\n');
-                continue;  // re-enter the dispatch loop with the new command
+        if let Some(ifs) = crate::commands::parse_if_shell(line.trim()) {
+            let (rtx, rrx) = mpsc::channel::<Option<String>>();
+            let _ = tx.send(CtrlReq::IfShell(ifs.condition, ifs.then_cmd, ifs.else_cmd, ifs.format, ifs.background, rtx));
+            if let Ok(Some(chosen)) = rrx.recv() {
+                // Feed the chosen commands back into the line buffer so the
+                // main dispatch loop processes them as regular commands.
+                let mut chain = crate::config::split_chained_commands_pub(&chosen).into_iter();
+                if let Some(first) = chain.next() {
+                    queued_lines.extend(chain.map(|c| format!("{}\n", c)));
+                    line.clear();
+                    line.push_str(&first);
+                    line.push('\n');
+                    continue;  // re-enter the dispatch loop with the new command
+                }
             }
         }
     }
@@ -968,11 +1379,11 @@ match cmd {
         let fmt = args.windows(2).find(|w| w[0] == "-F").map(|w| w[1].to_string());
         if let Some(fmt_str) = fmt {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::DisplayMessage(rtx, fmt_str));
+            let _ = tx.send(CtrlReq::DisplayMessage(rtx, fmt_str, None));
             if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         } else {
             let (rtx, rrx) = mpsc::channel::<String>();
-            let _ = tx.send(CtrlReq::SessionInfo(rtx));
+            let _ = tx.send(CtrlReq::SessionInfo(rtx, false));
             if let Ok(text) = rrx.recv() { let _ = write!(write_stream, "{}\n", text); let _ = write_stream.flush(); }
         }
         if !persistent { break; }
@@ -1005,10 +1416,8 @@ match cmd {
     "resize-window" | "resizew" => {
         let abs_x = args.windows(2).find(|w| w[0] == "-x").and_then(|w| w[1].parse::<u16>().ok());
         let abs_y = args.windows(2).find(|w| w[0] == "-y").and_then(|w| w[1].parse::<u16>().ok());
-        if let Some(xv) = abs_x {
-            let _ = tx.send(CtrlReq::ResizeWindow("x".to_string(), xv));
-        } else if let Some(yv) = abs_y {
-            let _ = tx.send(CtrlReq::ResizeWindow("y".to_string(), yv));
+        if abs_x.is_some() || abs_y.is_some() {
+            let _ = tx.send(CtrlReq::ResizeWindow(abs_x, abs_y));
         }
     }
     "respawn-window" | "respawnw" => {
@@ -1021,13 +1430,56 @@ match cmd {
     "focus-in" => { let _ = tx.send(CtrlReq::FocusIn); }
     "focus-out" => { let _ = tx.send(CtrlReq::FocusOut); }
     "choose-client" => {
-        // Single-client model — choose-client is a no-op
+        // Shown on the client that asked; the rows detach by default
+        let (rtx, rrx) = mpsc::channel();
+        let mut template = None;
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            if matches!(*a, "-F" | "-f" | "-O" | "-K" | "-t") { it.next(); }
+            else if !a.starts_with('-') { template = Some(a.trim_matches('"').to_string()); }
+        }
+        let _ = tx.send(CtrlReq::ChooseClient(client_id, template, rtx));
+        if let Ok(Err(e)) = rrx.recv() {
+            if persistent {
+                let _ = tx.send(CtrlReq::ShowMessage(e));
+            } else {
+                let _ = writeln!(write_stream, "psmux: choose-client: {}", e);
+                let _ = write_stream.flush();
+            }
+        }
     }
     "customize-mode" => {
         // tmux 3.2+ customize-mode — stub for compatibility
     }
-    _ => {}
+    _ => {
+        // Not a psmux command: maybe a command-alias, run in its place
+        let (rtx, rrx) = mpsc::channel();
+        let _ = tx.send(CtrlReq::ExpandAlias(line.trim().to_string(), rtx));
+        match rrx.recv_timeout(Duration::from_millis(2000)) {
+            Ok(Ok(Some(expanded))) => {
+                let mut chain = crate::config::split_chained_commands_pub(&expanded).into_iter();
+                if let Some(first) = chain.next() {
+                    for (i, c) in chain.enumerate() { queued_lines.insert(i, format!("{}\n", c)); }
+                    line.clear();
+                    line.push_str(&first);
+                    line.push('\n');
+                    continue;
+                }
+            }
+            Ok(Err(e)) => {
+                if persistent {
+                    let _ = tx.send(CtrlReq::ShowMessage(e));
+                } else {
+                    let _ = writeln!(write_stream, "psmux: {}: {}", cmd, e);
+                    let _ = write_stream.flush();
+                }
+            }
+            _ => {}
+        }
+    }
 }
+    // The rest of an if-shell branch's `\;` chain goes first
+    if let Some(next) = queued_lines.pop_front() { line = next; continue; }
     // Try to read next command for batching (with timeout)
     line.clear();
     match r.read_line(&mut line) {
@@ -1042,4 +1494,14 @@ match cmd {
         Ok(_) => {} // Continue processing
     }
 } // end command loop
+if let (true, Some(token)) = (batch_open, batch_token) {
+    // Dropped mid-batch: release the other clients' frames
+    let (rtx, _rrx) = mpsc::channel::<String>();
+    let _ = tx.send(CtrlReq::EndBatch(token, false, rtx));
+}
+if let Some(id) = client_id {
+    // Dropped without client-detach: the client is gone all the same
+    let _ = tx.send(CtrlReq::ClientUnregister(id));
+    let _ = tx.send(CtrlReq::ClientDetach);
+}
 }
@@ -1,8 +1,15 @@
 use std::io;
+use std::time::{Duration, Instant};
 
-use crate::types::{AppState, Node, Window};
-use crate::format::expand_format_for_window;
+use chrono::{DateTime, Local};
+
+use crate::types::{AppState, Node, PaneSize, ParsedTarget, SwitchTo, Window};
+use crate::pane::create_window;
+use crate::tree::{active_pane, find_window_index_by_id, kill_all_children};
+use crate::window_ops::{pick_window_index, set_window_index, take_window, window_indices, window_pos_by_index};
+use crate::format::{collect_pane_ids, FormatCache};
 use crate::util::WinInfo;
+use crate::alerts::Alert;
 
 /// Collect all leaf pane paths in tree order (for next/prev pane cycling).
 pub(crate) fn collect_pane_paths_server(node: &Node, path: &mut Vec<usize>, panes: &mut Vec<Vec<usize>>) {
@@ -18,6 +25,95 @@ pub(crate) fn collect_pane_paths_server(node: &Node, path: &mut Vec<usize>, pane
     }
 }
 
+/// `-c` of new-window and split-window: formats such as
+/// `#{pane_current_path}` expand for the target pane; an empty result means
+/// no directory was given.
+pub(crate) fn expand_start_dir(app: &AppState, dir: Option<String>) -> Option<String> {
+    let dir = match dir {
+        Some(d) if d.contains('#') => crate::format::expand_format(&d, app),
+        other => other?,
+    };
+    (!dir.is_empty()).then_some(dir)
+}
+
+/// new-window: create a window at display index `index` (the smallest free
+/// one when `None`), replacing an existing window there only when `kill`.
+/// The new window becomes active unless `detached`.  Returns its position.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn new_window(app: &mut AppState, pty_system: &dyn portable_pty::PtySystem, cmd: Option<&str>,
+    name: Option<String>, detached: bool, start_dir: Option<&str>, index: Option<usize>, kill: bool) -> Result<usize, String> {
+    if let Some(n) = &name { check_window_name(app, n, None)?; }
+    let idx = pick_window_index(&window_indices(app), app.window_base_index, index, kill)?;
+    let prev_id = app.windows.get(app.active_idx).map(|w| w.id);
+    if let Some(pos) = window_pos_by_index(app, idx) {
+        // -k: the occupant goes, and its index is reused rather than renumbered away
+        let mut old = take_window(app, pos);
+        kill_all_children(&mut old.root);
+        if app.active_idx > pos || app.active_idx >= app.windows.len() {
+            app.active_idx = app.active_idx.saturating_sub(1);
+        }
+    }
+    let saved_dir = if start_dir.is_some() { std::env::current_dir().ok() } else { None };
+    if let Some(dir) = start_dir { std::env::set_current_dir(dir).ok(); }
+    let created = create_window(pty_system, app, cmd);
+    if let Some(prev) = saved_dir { std::env::set_current_dir(prev).ok(); }
+    created.map_err(|e| e.to_string())?;
+    let mut pos = app.active_idx;
+    if index.is_some() { pos = set_window_index(app, pos, idx); }
+    if let Some(n) = name { app.windows[pos].name = n; }
+    if detached {
+        if let Some(prev) = prev_id.and_then(|id| find_window_index_by_id(app, id)) { app.active_idx = prev; }
+    }
+    Ok(pos)
+}
+
+/// move-window: give the window `source` names (the active one when None)
+/// display index `target` (the smallest free one when None), replacing a
+/// window there only when `kill`.  The moved window becomes active unless
+/// `detached`.  With `renumber` (-r) every window is numbered from
+/// base-index in order instead.
+pub(crate) fn move_window(app: &mut AppState, source: Option<&str>, target: Option<usize>, kill: bool, renumber: bool, detached: bool) -> Result<(), String> {
+    if renumber {
+        let base = app.window_base_index;
+        for (w, idx) in app.windows.iter_mut().zip(base..) { w.index = idx; }
+        return Ok(());
+    }
+    let pos = match source {
+        Some(s) => {
+            let idx = resolve_target(app, &crate::cli::parse_target(s), s)?.0;
+            window_pos_by_index(app, idx).ok_or_else(|| format!("can't find window: {}", s))?
+        }
+        None => app.active_idx,
+    };
+    let Some(win) = app.windows.get(pos) else { return Err("no current window".to_string()) };
+    if target == Some(win.index) { return Ok(()); }
+    let id = win.id;
+    let idx = pick_window_index(&window_indices(app), app.window_base_index, target, kill)?;
+    if let Some(occupant) = window_pos_by_index(app, idx) {
+        // -k: the occupant goes and the moved window takes its index
+        let mut old = take_window(app, occupant);
+        kill_all_children(&mut old.root);
+        if app.active_idx > occupant || app.active_idx >= app.windows.len() {
+            app.active_idx = app.active_idx.saturating_sub(1);
+        }
+    }
+    let pos = find_window_index_by_id(app, id).unwrap_or(app.active_idx);
+    let pos = set_window_index(app, pos, idx);
+    if !detached && pos != app.active_idx {
+        app.last_window_idx = app.active_idx;
+        app.active_idx = pos;
+    }
+    Ok(())
+}
+
+/// send-bytes --expect: the last match of `re` in the pane text `now`, once
+/// `now` holds more matches than `before` (the text when the bytes were sent).
+pub(crate) fn new_match(re: &regex::Regex, before: &str, now: &str) -> Option<String> {
+    let seen = re.find_iter(before).count();
+    let found: Vec<&str> = re.find_iter(now).map(|m| m.as_str()).collect();
+    if found.len() > seen { found.last().map(|m| m.to_string()) } else { None }
+}
+
 /// Serialize key_tables into a compact JSON array for syncing to the client.
 /// Format: [{"t":"prefix","k":"x","c":"split-window -v","r":false}, ...]
 pub(crate) fn serialize_bindings_json(app: &AppState) -> String {
@@ -26,6 +122,8 @@ pub(crate) fn serialize_bindings_json(app: &AppState) -> String {
     let mut out = String::from("[");
     let mut first = true;
     for (table_name, binds) in &app.key_tables {
+        // Copy-mode keys are handled by the server; clients never look them up
+        if table_name.starts_with("copy-mode") { continue; }
         for bind in binds {
             if !first { out.push(','); }
             first = false;
@@ -61,22 +159,25 @@ pub(crate) fn json_escape_string(s: &str) -> String {
 }
 
 /// Build windows JSON with pre-expanded tab_text for each window.
-/// The tab_text is the fully expanded window-status-format / window-status-current-format.
-pub(crate) fn list_windows_json_with_tabs(app: &AppState) -> io::Result<String> {
+/// The tab_text is the fully expanded window-status-format / window-status-current-format,
+/// reused from `cache` while nothing it read has changed.
+pub(crate) fn list_windows_json_with_tabs(app: &AppState, cache: &mut FormatCache) -> io::Result<String> {
     let mut v: Vec<WinInfo> = Vec::new();
     for (i, w) in app.windows.iter().enumerate() {
         let is_active = i == app.active_idx;
         let fmt = if is_active { &app.window_status_current_format } else { &app.window_status_format };
-        let tab = expand_format_for_window(fmt, app, i);
+        let tab = cache.expand(fmt, app, i);
         v.push(WinInfo {
             id: w.id,
+            index: w.index,
             name: w.name.clone(),
             active: is_active,
             activity: w.activity_flag,
             tab_text: tab,
+            alert_style: crate::alerts::tab_style(app, w),
         });
     }
-    serde_json::to_string(&v).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("json error: {e}")))
+    serde_json::to_string(&v).map_err(|e| io::Error::other(format!("json error: {e}")))
 }
 
 /// Sum data_version counters across all panes in the active window.
@@ -107,23 +208,377 @@ pub(crate) fn window_data_version(win: &Window) -> u64 {
     v
 }
 
-/// Check non-active windows for output activity and set their activity_flag
-pub(crate) fn check_window_activity(app: &mut AppState) {
-    if !app.monitor_activity { return; }
+/// Check every window for pane output and bells since the last call and
+/// raise the alerts that brings.  Returns true when an alert flag changed
+/// (the status line needs redrawing).
+pub(crate) fn check_window_activity(app: &mut AppState, now: DateTime<Local>) -> bool {
+    let versions: Vec<u64> = app.windows.iter().map(window_data_version).collect();
+    let bells: Vec<bool> = app.windows.iter_mut().map(crate::alerts::take_bells).collect();
+    let (flags_changed, raised) = note_window_activity(app, &versions, &bells, now);
+    crate::alerts::raise(app, &raised);
+    flags_changed
+}
+
+/// Record the windows' current data versions and bells: stamp
+/// `activity_at` on those that produced output and `visited_at` on the
+/// active one, and set the alert flags of the others as monitor-activity,
+/// monitor-silence and monitor-bell ask; the active window's are cleared.
+/// Returns whether a flag changed and the alerts newly raised.
+pub(crate) fn note_window_activity(app: &mut AppState, versions: &[u64], bells: &[bool], now: DateTime<Local>) -> (bool, Vec<(usize, Alert)>) {
     let active = app.active_idx;
-    for (i, win) in app.windows.iter_mut().enumerate() {
-        if i == active {
-            // Active window: clear flag, update version
-            win.activity_flag = false;
-            win.last_seen_version = window_data_version(win);
+    let mut flags_changed = false;
+    let mut raised = Vec::new();
+    let monitors: Vec<(bool, u64, bool)> = app.windows.iter()
+        .map(|w| (crate::scoped::monitor_activity(app, w), crate::scoped::monitor_silence(app, w), crate::scoped::monitor_bell(app, w)))
+        .collect();
+    for (i, (((win, &cur), &bell), &(monitor_activity, monitor_silence, monitor_bell))) in app.windows.iter_mut().zip(versions).zip(bells).zip(&monitors).enumerate() {
+        let output = cur != win.last_seen_version;
+        win.last_seen_version = cur;
+        if output { win.activity_at = now; }
+        let bell = bell && monitor_bell;
+        let flags = if i == active {
+            win.visited_at = now;
+            if bell { raised.push((i, Alert::Bell)); }
+            (false, false, false)
+        } else {
+            let silence = chrono::Duration::seconds(monitor_silence.min(i64::MAX as u64) as i64);
+            let quiet = monitor_silence > 0 && now - win.activity_at.max(win.visited_at) >= silence;
+            let flags = (win.activity_flag || (output && monitor_activity), win.silence_flag || quiet, win.bell_flag || bell);
+            if flags.0 && !win.activity_flag { raised.push((i, Alert::Activity)); }
+            if flags.1 && !win.silence_flag { raised.push((i, Alert::Silence)); }
+            if flags.2 && !win.bell_flag { raised.push((i, Alert::Bell)); }
+            flags
+        };
+        flags_changed |= flags != (win.activity_flag, win.silence_flag, win.bell_flag);
+        (win.activity_flag, win.silence_flag, win.bell_flag) = flags;
+    }
+    (flags_changed, raised)
+}
+
+/// What idle-window-timeout does to a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IdleWindow {
+    /// Newly idle: flagged `?`
+    Flagged,
+    /// Idle under `idle-window-action kill`: it goes in IDLE_KILL_GRACE
+    /// seconds unless it is used
+    Warned,
+    /// The grace period passed with the window still idle
+    Kill,
+}
+
+/// Seconds between the kill announcement and the kill.
+pub(crate) const IDLE_KILL_GRACE: i64 = 60;
+
+/// Check every window against idle-window-timeout: one with no output and
+/// no visit for that many hours is flagged, and under `kill` announced and
+/// then killed, once.  Output or a visit clears both.  A window with a dead
+/// pane or a pane running something other than its shell is only flagged.
+/// Returns whether a flag cleared, and (window id, what happened) for the
+/// caller to report and do.
+pub(crate) fn idle_window_actions(app: &mut AppState, now: DateTime<Local>) -> (bool, Vec<(usize, IdleWindow)>) {
+    let timeout = chrono::Duration::hours(app.idle_window_timeout.min(1 << 20) as i64);
+    let kill = app.idle_window_action == "kill";
+    let lookup = app.process_lookup;
+    let mut cleared = false;
+    let mut actions = Vec::new();
+    for win in app.windows.iter_mut() {
+        let idle = app.idle_window_timeout > 0 && now - win.activity_at.max(win.visited_at) >= timeout;
+        if !idle {
+            cleared |= win.idle_flag;
+            (win.idle_flag, win.idle_kill_at) = (false, None);
+            continue;
+        }
+        if !win.idle_flag {
+            win.idle_flag = true;
+            actions.push((win.id, IdleWindow::Flagged));
+        }
+        match win.idle_kill_at {
+            _ if !kill || window_is_busy(&win.root, &lookup) => win.idle_kill_at = None,
+            None => {
+                win.idle_kill_at = Some(now + chrono::Duration::seconds(IDLE_KILL_GRACE));
+                actions.push((win.id, IdleWindow::Warned));
+            }
+            Some(at) if now >= at => {
+                win.idle_kill_at = None;
+                actions.push((win.id, IdleWindow::Kill));
+            }
+            Some(_) => {}
+        }
+    }
+    (cleared, actions)
+}
+
+/// A pane is dead (kept by remain-on-exit) or running a program of its own.
+fn window_is_busy(node: &Node, lookup: &crate::pane::ProcessLookup) -> bool {
+    match node {
+        Node::Leaf(p) => p.dead || p.child_pid.is_some_and(|pid| (lookup.foreground_pid)(pid) != pid),
+        Node::Split { children, .. } => children.iter().any(|c| window_is_busy(c, lookup)),
+    }
+}
+
+/// Kill the window with id `id`, as idle-window-action kill does.
+pub(crate) fn kill_idle_window(app: &mut AppState, id: usize) {
+    let Some(pos) = app.windows.iter().position(|w| w.id == id) else { return };
+    if app.windows.len() < 2 { return; }
+    let mut win = crate::window_ops::remove_window(app, pos);
+    kill_all_children(&mut win.root);
+    if app.active_idx > pos { app.active_idx -= 1; }
+    app.active_idx = app.active_idx.min(app.windows.len() - 1);
+}
+
+/// What the idle check wants done to an attached client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum IdleAction {
+    /// Show (or refresh) the countdown: seconds left before detach
+    Warn(u64),
+    /// Input arrived during the countdown; clear the message
+    Cancel,
+    /// Idle for client-idle-timeout seconds; detach it
+    Detach,
+}
+
+/// Evaluate client-idle-timeout / client-idle-warning for every registered
+/// client.  Updates each client's `idle_warned` flag; the caller delivers
+/// the directives and removes detached clients.
+pub(crate) fn idle_client_actions(app: &mut AppState, now: Instant) -> Vec<(u64, IdleAction)> {
+    let timeout = app.client_idle_timeout;
+    let warning = app.client_idle_warning.min(timeout);
+    let mut actions = Vec::new();
+    for c in app.clients.iter_mut() {
+        let exempt = timeout == 0 || (!c.remote && app.client_idle_exempt_local);
+        if exempt {
+            if c.idle_warned {
+                c.idle_warned = false;
+                actions.push((c.id, IdleAction::Cancel));
+            }
             continue;
         }
-        let cur = window_data_version(win);
-        if cur != win.last_seen_version {
-            win.activity_flag = true;
-            win.last_seen_version = cur;
+        let idle = now.saturating_duration_since(c.last_input).as_secs();
+        if idle >= timeout {
+            actions.push((c.id, IdleAction::Detach));
+        } else if warning > 0 && idle >= timeout - warning {
+            c.idle_warned = true;
+            actions.push((c.id, IdleAction::Warn(timeout - idle)));
+        } else if c.idle_warned {
+            c.idle_warned = false;
+            actions.push((c.id, IdleAction::Cancel));
+        }
+    }
+    actions
+}
+
+/// `list-clients`: one line per attached client with its terminal size,
+/// when it attached and its flags, oldest first.
+pub(crate) fn list_clients(app: &AppState) -> String {
+    app.clients.iter().map(|c| format!("{}\n", client_line(app, c))).collect()
+}
+
+fn client_line(app: &AppState, c: &crate::types::ClientConn) -> String {
+    let window = app.windows.get(app.active_idx).map_or("", |w| w.name.as_str());
+    let mut flags = vec!["attached"];
+    if c.remote { flags.push("remote"); }
+    if c.suspended { flags.push("suspended"); }
+    format!("client{}: {}: {} [{}x{}] (created {}) ({})", c.id, app.session_name, window, c.size.0, c.size.1,
+        c.attached_at.format("%a %b %e %H:%M:%S %Y"), flags.join(","))
+}
+
+/// choose-client (prefix `D`): show the client that asked a chooser of
+/// the attached clients.  Choosing one runs `template` with `%%` replaced
+/// by its name, detaching it by default.
+pub(crate) fn choose_client(app: &AppState, requester: Option<u64>, template: Option<&str>) -> Result<(), String> {
+    let template = template.unwrap_or("detach-client -t %%");
+    let shown_on = requester.filter(|id| app.clients.iter().any(|c| c.id == *id))
+        .or_else(|| crate::bandwidth::current_client(app).map(|c| c.id))
+        .ok_or_else(|| "no current client".to_string())?;
+    let rows: Vec<crate::chooser::Choice> = app.clients.iter().map(|c| crate::chooser::Choice {
+        label: client_line(app, c),
+        command: template.replace("%%", &format!("client{}", c.id)),
+        current: c.id == shown_on,
+    }).collect();
+    let client = app.clients.iter().find(|c| c.id == shown_on).ok_or_else(|| "no current client".to_string())?;
+    client.send_directive(crate::chooser::directive(&rows));
+    Ok(())
+}
+
+/// The clients `detach-client` detaches: the one `-t` names (`client3` or
+/// `3`), else the one that asked, else the most recently active one; with
+/// `-a` every client but that one.
+pub(crate) fn clients_to_detach(app: &AppState, requester: Option<u64>, target: Option<&str>, all_but: bool) -> Result<Vec<u64>, String> {
+    let id = match target {
+        Some(t) => {
+            let id = t.strip_prefix("client").unwrap_or(t).parse::<u64>().ok()
+                .filter(|id| app.clients.iter().any(|c| c.id == *id));
+            Some(id.ok_or_else(|| format!("can't find client: {}", t))?)
         }
+        None => requester.filter(|id| app.clients.iter().any(|c| c.id == *id))
+            .or_else(|| crate::bandwidth::current_client(app).map(|c| c.id)),
+    };
+    if all_but {
+        return Ok(app.clients.iter().map(|c| c.id).filter(|c| Some(*c) != id).collect());
+    }
+    id.map(|id| vec![id]).ok_or_else(|| "no current client".to_string())
+}
+
+/// Apply `refresh-client -f <spec>` to the client that asked, else the most
+/// recently active one, and acknowledge with its new `%flags`.  Returns the
+/// id of the client that was changed.
+pub(crate) fn apply_refresh_flags(app: &mut AppState, requester: Option<u64>, spec: &str) -> Option<u64> {
+    use crate::bandwidth::{apply_client_flag, client_flags, current_client, LOW_BANDWIDTH_FLAG, NO_STATUS_FLAG};
+    use std::sync::atomic::Ordering;
+    let id = requester.or_else(|| current_client(app).map(|c| c.id))?;
+    let c = app.clients.iter_mut().find(|c| c.id == id)?;
+    let low = apply_client_flag(c.low_bandwidth.load(Ordering::Relaxed), LOW_BANDWIDTH_FLAG, spec);
+    c.low_bandwidth.store(low, Ordering::Relaxed);
+    c.no_status = apply_client_flag(c.no_status, NO_STATUS_FLAG, spec);
+    c.send_directive(format!("%flags {}", client_flags(low, c.no_status)));
+    Some(id)
+}
+
+/// Plain `refresh-client`: have the client that asked, else the most
+/// recently active one, repaint from scratch (`%redraw`).  Returns the id
+/// of the client told.
+pub(crate) fn repaint_client(app: &AppState, requester: Option<u64>) -> Option<u64> {
+    let id = requester.or_else(|| crate::bandwidth::current_client(app).map(|c| c.id))?;
+    let c = app.clients.iter().find(|c| c.id == id)?;
+    c.send_directive("%redraw".to_string());
+    Some(id)
+}
+
+/// `switch-client`: tell the client `-c` names (else the one that asked,
+/// else the most recently active one) to reattach to another session.
+/// The client does the switch itself on `%switch <registry name>`.
+pub(crate) fn switch_client(app: &AppState, requester: Option<u64>, client: Option<&str>, to: &SwitchTo) -> Result<(), String> {
+    // The same client detach-client would pick
+    let id = clients_to_detach(app, requester, client, false)?[0];
+    let c = app.clients.iter().find(|c| c.id == id).ok_or_else(|| "no current client".to_string())?;
+    let ns = app.socket_name.as_ref().map(|s| format!("{s}__"));
+    let running: Vec<String> = crate::session::list_session_records(&crate::session::psmux_dir()).into_iter()
+        .map(|(base, _)| base)
+        .filter(|base| ns.as_ref().map_or(!base.contains("__"), |p| base.starts_with(p.as_str())))
+        .filter(|base| crate::transport::exists(base))
+        .collect();
+    let target = switch_target(app, c.last_session.as_deref(), &running, to)?;
+    if target != app.port_file_base() {
+        c.send_directive(format!("%switch {}", target));
     }
+    Ok(())
+}
+
+/// The registry name of the session `to` picks.  `running` are the
+/// servers up in this session's -L namespace; -n and -p go round them by
+/// name and -l is `last`, the one the client was on before.
+pub(crate) fn switch_target(app: &AppState, last: Option<&str>, running: &[String], to: &SwitchTo) -> Result<String, String> {
+    let current = app.port_file_base();
+    let ns = app.socket_name.as_ref().map(|s| format!("{s}__")).unwrap_or_default();
+    match to {
+        SwitchTo::Named(t) => {
+            // "work:2.1" is the session "work"; ":2" is this one
+            let name = t.split(':').next().unwrap_or("");
+            if name.is_empty() { return Ok(current); }
+            let base = format!("{}{}", ns, name);
+            if base == current || running.contains(&base) { Ok(base) } else { Err(format!("can't find session: {}", name)) }
+        }
+        SwitchTo::Next | SwitchTo::Previous => {
+            let mut names: Vec<&String> = running.iter().collect();
+            if !names.contains(&&current) { names.push(&current); }
+            names.sort();
+            let pos = names.iter().position(|n| **n == current).unwrap_or(0);
+            let step = if *to == SwitchTo::Next { 1 } else { names.len() - 1 };
+            Ok(names[(pos + step) % names.len()].clone())
+        }
+        SwitchTo::Last => last.filter(|l| running.iter().any(|r| r == l))
+            .map(str::to_string)
+            .ok_or_else(|| "can't find last session".to_string()),
+    }
+}
+
+/// How long a frame for subscribed clients waits for more changes, so a
+/// burst of output goes out as one frame.
+pub(crate) const PUSH_DEBOUNCE: Duration = Duration::from_millis(8);
+
+/// Least spacing of pushed frames (about 60 fps), for output that never
+/// settles.
+pub(crate) const PUSH_MIN_INTERVAL: Duration = Duration::from_millis(16);
+
+/// When to push the frame for a change seen at `now`.
+pub(crate) fn push_due(now: Instant, last_push: Option<Instant>) -> Instant {
+    let settled = now + PUSH_DEBOUNCE;
+    last_push.map_or(settled, |at| settled.max(at + PUSH_MIN_INTERVAL))
+}
+
+/// Send a frame to every client that subscribed (`client-subscribe 1`)
+/// and is neither suspended nor low-bandwidth.  Each is asked for through
+/// `tx` like the client's own dump-state, and its answer is written in
+/// order with the client's directives.  Returns how many were sent.
+pub(crate) fn push_frames(app: &AppState, tx: &std::sync::mpsc::Sender<crate::types::CtrlReq>) -> usize {
+    use std::sync::atomic::Ordering;
+    let mut pushed = 0;
+    for c in app.clients.iter().filter(|c| !c.suspended && !c.low_bandwidth.load(Ordering::Relaxed)) {
+        let Some(kind) = c.push else { continue };
+        let (rtx, rrx) = std::sync::mpsc::channel();
+        if c.directives.send(rrx).is_ok() {
+            let _ = tx.send(crate::types::CtrlReq::DumpState(rtx, true, kind, Some(c.id)));
+            pushed += 1;
+        }
+    }
+    pushed
+}
+
+/// Whether an unchanged dump-state can be answered "NC": the client was
+/// already sent frame `version`.  A request from a connection that has not
+/// registered as a client can only tell when it is the only one attached.
+pub(crate) fn client_has_frame(app: &AppState, client: Option<u64>, version: u64) -> bool {
+    match client.and_then(|id| app.clients.iter().find(|c| c.id == id)) {
+        Some(c) => c.frame_version == version,
+        None => app.attached_clients <= 1,
+    }
+}
+
+/// Record that `client` was sent frame `version`.
+pub(crate) fn note_frame_sent(app: &mut AppState, client: Option<u64>, version: u64) {
+    if let Some(c) = client.and_then(|id| app.clients.iter_mut().find(|c| c.id == id)) {
+        c.frame_version = version;
+    }
+}
+
+/// How long a begin-batch may hold frames back before it is committed
+/// on the owner's behalf.
+pub(crate) const BATCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// An open begin-batch / commit-batch bracket.  Commands inside it apply
+/// as usual, but dump-state answers "NC" until the owning connection
+/// commits or aborts, drops, or BATCH_TIMEOUT passes.
+pub(crate) struct FrameBatch {
+    owner: u64,
+    started: Instant,
+}
+
+/// begin-batch: open a batch for `owner`.  Only one may be open at a time.
+pub(crate) fn begin_batch(batch: &mut Option<FrameBatch>, owner: u64, now: Instant) -> Result<(), String> {
+    match batch {
+        Some(b) if b.owner == owner => Err("begin-batch: a batch is already open on this connection".to_string()),
+        Some(_) => Err("begin-batch: another connection has a batch open".to_string()),
+        None => { *batch = Some(FrameBatch { owner, started: now }); Ok(()) }
+    }
+}
+
+/// commit-batch / abort-batch: close `owner`'s batch.
+pub(crate) fn end_batch(batch: &mut Option<FrameBatch>, owner: u64, cmd: &str) -> Result<(), String> {
+    match batch {
+        Some(b) if b.owner == owner => { *batch = None; Ok(()) }
+        _ => Err(format!("{}: no batch open on this connection", cmd)),
+    }
+}
+
+/// Close a batch whose owner has not committed within BATCH_TIMEOUT.
+/// Returns true if one was closed.
+pub(crate) fn expire_batch(batch: &mut Option<FrameBatch>, now: Instant) -> bool {
+    if batch.as_ref().is_some_and(|b| now.saturating_duration_since(b.started) >= BATCH_TIMEOUT) {
+        *batch = None;
+        return true;
+    }
+    false
 }
 
 /// Complete list of supported tmux-compatible commands (for list-commands).
@@ -131,7 +586,7 @@ pub(crate) const TMUX_COMMANDS: &[&str] = &[
     "attach-session (attach)", "bind-key (bind)", "break-pane (breakp)",
     "capture-pane (capturep)", "choose-buffer", "choose-client",
     "choose-session", "choose-tree", "choose-window",
-    "clear-history (clearhist)", "clock-mode", "command-prompt",
+    "clear-history (clearhist)", "clear-screen-and-history", "clock-mode", "command-prompt",
     "confirm-before (confirm)", "copy-mode", "customize-mode",
     "delete-buffer (deleteb)", "detach-client (detach)",
     "display-menu (menu)", "display-message (display)",
@@ -170,3 +625,1014 @@ pub(crate) const TMUX_COMMANDS: &[&str] = &[
     "unbind-key (unbind)", "unlink-window (unlinkw)",
     "wait-for (wait)",
 ];
+
+/// Display index of the window and id of the pane named by the window and
+/// pane parts of a `-t` target.  A window is a display index, `@id` or
+/// name (exact, else a prefix matching one window); a pane is an index
+/// counted from pane-base-index or `%id`.  Parts left out mean the active
+/// window or pane.  Errors read like tmux's.
+pub(crate) fn resolve_target(app: &AppState, t: &ParsedTarget, spec: &str) -> Result<(usize, usize), String> {
+    let named_window = t.window.is_some() || t.window_name.is_some();
+    let wi = if let Some(w) = t.window {
+        if t.window_is_id { find_window_index_by_id(app, w) }
+        // A window called "2024" is still found when no window has that index
+        else { window_pos_by_index(app, w).map_or_else(|| find_window_by_name(app, &w.to_string()), |i| Ok(Some(i)))? }
+    } else if let Some(name) = &t.window_name {
+        find_window_by_name(app, name)?
+    } else {
+        Some(app.active_idx)
+    };
+    let wi = wi.filter(|&i| i < app.windows.len()).ok_or_else(|| format!("can't find window: {}", spec))?;
+    let pane_err = || format!("can't find pane: {}", spec);
+    if t.pane_is_id {
+        let pid = t.pane.ok_or_else(pane_err)?;
+        let in_window = |w: &Window| { let mut ids = Vec::new(); collect_pane_ids(&w.root, &mut ids); ids.contains(&pid) };
+        let owner = if named_window { in_window(&app.windows[wi]).then_some(wi) } else { app.windows.iter().position(in_window) };
+        return owner.map(|wi| (app.windows[wi].index, pid)).ok_or_else(pane_err);
+    }
+    let win = &app.windows[wi];
+    let pid = match t.pane {
+        Some(n) => {
+            let mut ids = Vec::new();
+            collect_pane_ids(&win.root, &mut ids);
+            n.checked_sub(app.pane_base_index).and_then(|i| ids.get(i).copied())
+        }
+        None => active_pane(&win.root, &win.active_path).map(|p| p.id),
+    };
+    pid.map(|pid| (win.index, pid)).ok_or_else(pane_err)
+}
+
+/// Position of the window a target's window name picks:
+///   `=name`   exactly that name
+///   `name#N`  the window called `name` whose index is N
+///   `name`    an exact name, else a unique prefix of one
+/// Several candidates are an error naming their indexes rather than a
+/// silent pick of the first.
+pub(crate) fn find_window_by_name(app: &AppState, name: &str) -> Result<Option<usize>, String> {
+    if let Some((base, n)) = name.rsplit_once('#').and_then(|(b, n)| Some((b, n.parse::<usize>().ok()?))) {
+        let base = base.strip_prefix('=').unwrap_or(base);
+        return Ok(app.windows.iter().position(|w| w.name == base && w.index == n));
+    }
+    let (exact_only, name) = match name.strip_prefix('=') { Some(n) => (true, n), None => (false, name) };
+    let pick = |matches: Vec<usize>| match matches.as_slice() {
+        [] => Ok(None),
+        [one] => Ok(Some(*one)),
+        many => {
+            let list: Vec<String> = many.iter().map(|&i| app.windows[i].index.to_string()).collect();
+            Err(format!("ambiguous target: {} (windows {})", name, list.join(", ")))
+        }
+    };
+    let exact = pick(app.windows.iter().enumerate().filter(|(_, w)| w.name == name).map(|(i, _)| i).collect())?;
+    if exact.is_some() || exact_only { return Ok(exact); }
+    pick(app.windows.iter().enumerate().filter(|(_, w)| w.name.starts_with(name)).map(|(i, _)| i).collect())
+}
+
+/// With allow-duplicate-window-names off, refuse `name` when a window other
+/// than position `except` already has it.
+pub(crate) fn check_window_name(app: &AppState, name: &str, except: Option<usize>) -> Result<(), String> {
+    if app.allow_duplicate_window_names { return Ok(()); }
+    match app.windows.iter().enumerate().find(|(i, w)| Some(*i) != except && w.name == name) {
+        Some((_, w)) => Err(format!("duplicate window name: {} (window {})", name, w.index)),
+        None => Ok(()),
+    }
+}
+
+/// find-window output: matching windows with same-named ones grouped
+/// together, each duplicate followed by the `name#N` target that picks it.
+pub(crate) fn find_window_lines(app: &AppState, pattern: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for w in app.windows.iter().filter(|w| w.name.contains(pattern)) {
+        if !names.contains(&w.name.as_str()) { names.push(&w.name); }
+    }
+    let mut output = String::new();
+    for name in names {
+        let group: Vec<&Window> = app.windows.iter().filter(|w| w.name == name).collect();
+        for w in &group {
+            if group.len() > 1 { output.push_str(&format!("{}: {} [] {}#{}\n", w.index, w.name, w.name, w.index)); }
+            else { output.push_str(&format!("{}: {} []\n", w.index, w.name)); }
+        }
+    }
+    output
+}
+
+/// Commands that act on their -t pane without making it the active one
+/// (split-window focuses the new pane instead).  Every spelling the
+/// connection's match arm takes is listed, one arm per line.
+pub(crate) fn target_keeps_focus(cmd: &str) -> bool {
+    matches!(cmd, "send-keys" | "send-bytes"
+        | "capture-pane" | "capturep"
+        | "kill-pane" | "killp"
+        | "display-message" | "display"
+        | "pipe-pane" | "pipep"
+        | "respawn-pane" | "respawnp"
+        | "clear-history" | "clearhist"
+        | "clear-screen-and-history"
+        | "resolve-target"
+        | "set-option" | "set" | "set-window-option" | "setw"
+        | "show-options" | "show" | "show-option" | "show-window-options" | "show-window-option" | "showw"
+        | "rename-window" | "renamew"
+        | "kill-window" | "killw"
+        | "split-window" | "splitw")
+}
+
+/// Does a command's argument list carry the single-letter `flag`, either
+/// alone (`-q`) or inside a cluster (`-gqv`)?
+pub(crate) fn has_flag(args: &[&str], flag: char) -> bool {
+    args.iter().any(|a| {
+        a.len() > 1 && a.starts_with('-') && !a.starts_with("--")
+            && a[1..].chars().all(|c| c.is_ascii_alphabetic())
+            && a[1..].contains(flag)
+    })
+}
+
+/// Position of the window holding pane `pid` (a `-t` target), or the
+/// active window when there is none.
+pub(crate) fn target_window_pos(app: &AppState, pid: Option<usize>) -> usize {
+    pid.and_then(|id| app.windows.iter().position(|w| crate::tree::pane_path(&w.root, id).is_some()))
+        .unwrap_or(app.active_idx)
+}
+
+/// Pane `pid` (a `-t` target), or the active pane when there is none.
+pub(crate) fn target_pane_mut(app: &mut AppState, pid: Option<usize>) -> Option<&mut crate::types::Pane> {
+    let pos = target_window_pos(app, pid);
+    let win = &mut app.windows[pos];
+    let path = pid.and_then(|id| crate::tree::pane_path(&win.root, id)).unwrap_or_else(|| win.active_path.clone());
+    crate::tree::active_pane_mut(&mut win.root, &path)
+}
+
+/// Run `f` with pane `pid` (a `-t` target) standing in for the active pane.
+/// The focus is swapped inside this one request, so no other request or
+/// frame sees it, and the active window and the target window's active
+/// pane are put back before returning.
+pub(crate) fn at_pane<T>(app: &mut AppState, pid: Option<usize>, f: impl FnOnce(&mut AppState) -> T) -> T {
+    let found = pid.and_then(|id| app.windows.iter().enumerate()
+        .find_map(|(wi, w)| crate::tree::pane_path(&w.root, id).map(|path| (wi, path))));
+    let Some((wi, path)) = found else { return f(app) };
+    if wi == app.active_idx && path == app.windows[wi].active_path { return f(app); }
+    let prev_win = app.windows[app.active_idx].id;
+    let target_win = app.windows[wi].id;
+    let prev_pane = active_pane(&app.windows[wi].root, &app.windows[wi].active_path).map(|p| p.id);
+    crate::copy_mode::switch_with_copy_save(app, |app| { app.active_idx = wi; app.windows[wi].active_path = path; });
+    let out = f(app);
+    crate::copy_mode::switch_with_copy_save(app, |app| {
+        // The command may have closed panes or windows: put back what is left
+        if let (Some(twi), Some(pp)) = (find_window_index_by_id(app, target_win), prev_pane) {
+            if let Some(p) = crate::tree::pane_path(&app.windows[twi].root, pp) { app.windows[twi].active_path = p; }
+        }
+        if let Some(i) = find_window_index_by_id(app, prev_win) { app.active_idx = i; }
+    });
+    out
+}
+
+/// split-window on pane `pid` (a -t target): `split` runs with the target
+/// standing in for the active pane and says whether a pane was made.  The
+/// new pane then takes the focus, in the target's window, unless
+/// `detached`; with -d the focus stays where it was.
+pub(crate) fn split_at_pane<T>(app: &mut AppState, pid: Option<usize>, detached: bool, split: impl FnOnce(&mut AppState) -> (bool, T)) -> T {
+    let (new_pane, out) = at_pane(app, pid, |app| {
+        let prev_pane = crate::window_ops::active_pane_id(app, app.active_idx);
+        let (made, out) = split(app);
+        let new_pane = crate::window_ops::active_pane_id(app, app.active_idx).filter(|_| made);
+        if detached { refocus_pane(app, prev_pane); }
+        (new_pane, out)
+    });
+    if let Some(id) = new_pane.filter(|_| !detached) { focus_pane(app, id); }
+    out
+}
+
+/// Focus pane `pid` and its window, which becomes the current one (the
+/// window left is the last one), as a focus-moving -t target does.
+pub(crate) fn focus_pane(app: &mut AppState, pid: usize) {
+    let Some(wi) = app.windows.iter().position(|w| crate::tree::pane_path(&w.root, pid).is_some()) else { return };
+    crate::copy_mode::switch_with_copy_save(app, |app| {
+        if wi != app.active_idx { app.last_window_idx = app.active_idx; }
+        crate::tree::focus_pane_by_id(app, pid);
+    });
+}
+
+/// run-shell: expand `command`'s formats and run it on a worker thread.
+/// The CLI gets the status and output through `resp`; otherwise the output
+/// ends up in a view over the active pane, unless `background` (-b).  Either
+/// way `ShellDone` comes back through `done` once the command exits.
+pub(crate) fn run_shell(app: &AppState, command: String, background: bool, resp: Option<std::sync::mpsc::Sender<(i32, String)>>, done: std::sync::mpsc::Sender<crate::types::CtrlReq>) {
+    use crate::types::CtrlReq;
+    let cmd = crate::format::expand_format(&command, app);
+    let mut c = crate::config::shell_command(&cmd, Some(&app.port_file_base()));
+    // set-environment's variables are passed on, as for config jobs
+    crate::environ::apply_to_job(&mut c, &crate::environ::composed(app));
+    std::thread::spawn(move || {
+        let (status, output) = crate::config::shell_output(c);
+        match resp {
+            Some(resp) => { let _ = resp.send((status, output)); let _ = done.send(CtrlReq::ShellDone(cmd, status, None)); }
+            None => { let _ = done.send(CtrlReq::ShellDone(cmd, status, (!background).then_some(output))); }
+        }
+    });
+}
+
+/// A run-shell command exited: record its status for `#{last_shell_exit}`
+/// and show any `output` (with a failing status noted) to the client.
+pub(crate) fn shell_done(app: &mut AppState, cmd: &str, status: i32, output: Option<String>) {
+    app.last_shell_exit = status;
+    if let Some(mut text) = output {
+        if status != 0 {
+            if !text.is_empty() && !text.ends_with('\n') { text.push('\n'); }
+            text.push_str(&format!("'{}' returned {}\n", cmd, status));
+        }
+        if !text.is_empty() {
+            let directive = format!("%view {} {}", crate::util::base64_encode(cmd), crate::util::base64_encode(&text));
+            if let Some(c) = crate::bandwidth::current_client(app) { c.send_directive(directive); }
+        }
+    }
+}
+
+/// kill-server / kill-session: end every pane and leave the registry.  A
+/// daemon exits here; an in-process server (`--no-daemon`) returns instead,
+/// so its client can restore the terminal.
+pub(crate) fn shut_down(app: &mut AppState) -> io::Result<()> {
+    for win in app.windows.iter_mut() {
+        kill_all_children(&mut win.root);
+    }
+    // Brief delay to let child processes fully terminate
+    crate::popup::stop_spare(app);
+    std::thread::sleep(Duration::from_millis(100));
+    let _ = crate::history::save_history(app);
+    crate::session::remove_session_files(&app.port_file_base());
+    if super::IN_PROCESS.load(std::sync::atomic::Ordering::Relaxed) { return Ok(()); }
+    std::process::exit(0);
+}
+
+/// Make pane `pid` the active window's active pane again, as `-d` does
+/// after a new pane took the focus.
+pub(crate) fn refocus_pane(app: &mut AppState, pid: Option<usize>) {
+    let win = &mut app.windows[app.active_idx];
+    if let Some(path) = pid.and_then(|id| crate::tree::pane_path(&win.root, id)) { win.active_path = path; }
+}
+
+/// The value following `flag` in an argument list, unquoted.
+pub(crate) fn flag_value<'a>(args: &[&'a str], flag: &str) -> Option<&'a str> {
+    args.windows(2).find(|w| w[0] == flag).map(|w| w[1].trim_matches('"'))
+}
+
+/// The size asked for a new pane: `-p 30`, `-l 30%` or `-l 20` cells.
+pub(crate) fn pane_size_arg(args: &[&str]) -> Option<PaneSize> {
+    if let Some(p) = flag_value(args, "-p") { return p.parse().ok().map(PaneSize::Percent); }
+    let l = flag_value(args, "-l")?;
+    match l.strip_suffix('%') {
+        Some(p) => p.parse().ok().map(PaneSize::Percent),
+        None => l.parse().ok().map(PaneSize::Cells),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use crate::types::ClientConn;
+    use crate::cli::{parse_target, target_names_pane};
+
+    fn app_with_client(remote: bool, last_input: Instant) -> (AppState, mpsc::Receiver<mpsc::Receiver<String>>) {
+        let mut app = AppState::new("test_session".to_string());
+        app.client_idle_timeout = 60;
+        app.client_idle_warning = 30;
+        let (tx, rx) = mpsc::channel();
+        app.clients.push(ClientConn { remote, last_input, directives: tx, ..ClientConn::test(1) });
+        (app, rx)
+    }
+
+    #[test]
+    fn send_bytes_payloads_and_expected_replies() {
+        use crate::util::{parse_hex_bytes, base64_decode_bytes, base64_encode_bytes};
+        assert_eq!(parse_hex_bytes("1b5b 366e").unwrap(), b"\x1b[6n");
+        assert_eq!(parse_hex_bytes("1b5b3").unwrap_err(), "odd number of hex digits (5): every byte needs two");
+        assert_eq!(parse_hex_bytes("1bzz").unwrap_err(), "invalid hex digit 'z' at position 3");
+        let raw: Vec<u8> = (0..=255).collect();
+        assert_eq!(base64_decode_bytes(&base64_encode_bytes(&raw)).unwrap(), raw);
+        // A reply already on screen does not count; a fresh one does
+        let re = regex::Regex::new(r"\^\[\[\d+;\d+R").unwrap();
+        let before = "$ responder\nreply: ^[[3;1R\n";
+        assert_eq!(new_match(&re, before, before), None);
+        assert_eq!(new_match(&re, before, &format!("{}reply: ^[[4;1R\n", before)).as_deref(), Some("^[[4;1R"));
+    }
+
+    #[test]
+    fn idle_client_warned_then_detached() {
+        let start = Instant::now();
+        let (mut app, _rx) = app_with_client(true, start);
+        assert!(idle_client_actions(&mut app, start + Duration::from_secs(29)).is_empty());
+        assert_eq!(idle_client_actions(&mut app, start + Duration::from_secs(30)), vec![(1, IdleAction::Warn(30))]);
+        assert_eq!(idle_client_actions(&mut app, start + Duration::from_secs(55)), vec![(1, IdleAction::Warn(5))]);
+        assert_eq!(idle_client_actions(&mut app, start + Duration::from_secs(60)), vec![(1, IdleAction::Detach)]);
+    }
+
+    #[test]
+    fn input_during_countdown_cancels_warning() {
+        let start = Instant::now();
+        let (mut app, _rx) = app_with_client(true, start);
+        let t = start + Duration::from_secs(45);
+        assert_eq!(idle_client_actions(&mut app, t), vec![(1, IdleAction::Warn(15))]);
+        app.clients[0].last_input = t;
+        assert_eq!(idle_client_actions(&mut app, t + Duration::from_secs(1)), vec![(1, IdleAction::Cancel)]);
+        assert!(idle_client_actions(&mut app, t + Duration::from_secs(2)).is_empty());
+    }
+
+    #[test]
+    fn local_client_exempt_unless_disabled() {
+        let start = Instant::now();
+        let (mut app, _rx) = app_with_client(false, start);
+        let late = start + Duration::from_secs(120);
+        assert!(idle_client_actions(&mut app, late).is_empty());
+        app.client_idle_exempt_local = false;
+        assert_eq!(idle_client_actions(&mut app, late), vec![(1, IdleAction::Detach)]);
+    }
+
+    #[test]
+    fn zero_timeout_disables_and_clears_warning() {
+        let start = Instant::now();
+        let (mut app, _rx) = app_with_client(true, start);
+        assert_eq!(idle_client_actions(&mut app, start + Duration::from_secs(40)), vec![(1, IdleAction::Warn(20))]);
+        app.client_idle_timeout = 0;
+        assert_eq!(idle_client_actions(&mut app, start + Duration::from_secs(500)), vec![(1, IdleAction::Cancel)]);
+        assert!(idle_client_actions(&mut app, start + Duration::from_secs(600)).is_empty());
+    }
+
+    #[test]
+    fn no_status_toggles_only_the_requesting_client() {
+        let start = Instant::now();
+        let (mut app, rx1) = app_with_client(true, start);
+        let (tx2, rx2) = mpsc::channel();
+        app.clients.push(ClientConn { last_input: start, directives: tx2, ..ClientConn::test(2) });
+        assert_eq!(apply_refresh_flags(&mut app, Some(2), "no-status"), Some(2));
+        assert!(!app.clients[0].no_status);
+        assert!(app.clients[1].no_status);
+        assert_eq!(rx2.try_recv().unwrap().recv().unwrap(), "%flags focused,no-status");
+        assert!(rx1.try_recv().is_err());
+        apply_refresh_flags(&mut app, Some(2), "!no-status");
+        assert!(!app.clients[1].no_status);
+        assert_eq!(rx2.try_recv().unwrap().recv().unwrap(), "%flags focused");
+        // Unknown clients are left alone
+        assert_eq!(apply_refresh_flags(&mut app, Some(9), "no-status"), None);
+    }
+
+    #[test]
+    fn clients_negotiate_the_window_size() {
+        let start = Instant::now();
+        let (mut app, _rx1) = app_with_client(false, start);
+        let (tx2, _rx2) = mpsc::channel();
+        app.clients.push(ClientConn { remote: true, last_input: start + Duration::from_secs(1), directives: tx2, ..ClientConn::test(2) });
+        app.attached_clients = 2;
+        app.clients[0].size = (120, 30);
+        crate::tree::fit_to_clients(&mut app, None);
+        assert_eq!(crate::tree::window_size(&app), (120, 30));
+        // The second client has a smaller terminal in one direction only
+        app.clients[1].size = (80, 40);
+        crate::tree::fit_to_clients(&mut app, None);
+        assert_eq!(crate::tree::window_size(&app), (80, 30));
+        app.window_size = "largest".to_string();
+        assert_eq!(crate::tree::clients_size(&app), Some((120, 40)));
+        app.window_size = "latest".to_string();
+        assert_eq!(crate::tree::clients_size(&app), Some((80, 40)));
+        app.window_size = "manual".to_string();
+        assert_eq!(crate::tree::clients_size(&app), None);
+
+        let lines = list_clients(&app);
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("client1: test_session: "), "{}", lines[0]);
+        assert!(lines[0].contains("[120x30] (created ") && lines[0].ends_with("(attached)"), "{}", lines[0]);
+        assert!(lines[1].starts_with("client2: ") && lines[1].contains("[80x40]") && lines[1].ends_with("(attached,remote)"), "{}", lines[1]);
+
+        // The smaller client leaving lets the window grow back
+        app.window_size = "smallest".to_string();
+        app.clients.retain(|c| c.id != 2);
+        crate::tree::fit_to_clients(&mut app, None);
+        assert_eq!(crate::tree::window_size(&app), (120, 30));
+        // and the last one leaving returns it to the detached size
+        app.clients.clear();
+        app.attached_clients = 0;
+        crate::tree::fit_to_clients(&mut app, None);
+        assert_eq!(crate::tree::window_size(&app), app.headless_size);
+    }
+
+    #[test]
+    fn detach_client_targets() {
+        let start = Instant::now();
+        let (mut app, rx1) = app_with_client(true, start);
+        for id in [2, 3] {
+            let (tx, _rx) = mpsc::channel();
+            app.clients.push(ClientConn { last_input: start + Duration::from_secs(id), directives: tx, ..ClientConn::test(id) });
+        }
+        assert_eq!(clients_to_detach(&app, Some(1), None, false), Ok(vec![1]));
+        // Not from a client: the one used last
+        assert_eq!(clients_to_detach(&app, None, None, false), Ok(vec![3]));
+        assert_eq!(clients_to_detach(&app, Some(1), Some("client2"), false), Ok(vec![2]));
+        assert_eq!(clients_to_detach(&app, Some(1), Some("2"), false), Ok(vec![2]));
+        assert_eq!(clients_to_detach(&app, Some(1), None, true), Ok(vec![2, 3]));
+        assert_eq!(clients_to_detach(&app, Some(1), Some("client3"), true), Ok(vec![1, 2]));
+        assert_eq!(clients_to_detach(&app, Some(1), Some("client9"), false), Err("can't find client: client9".to_string()));
+
+        // prefix D: the rows detach each client and start on the one asking
+        choose_client(&app, Some(1), None).unwrap();
+        let shown = rx1.try_recv().unwrap().recv().unwrap();
+        let rows: Vec<crate::chooser::Choice> = serde_json::from_str(shown.strip_prefix("%choose ").unwrap()).unwrap();
+        assert_eq!(rows.iter().map(|r| r.command.as_str()).collect::<Vec<_>>(),
+            ["detach-client -t client1", "detach-client -t client2", "detach-client -t client3"]);
+        assert!(rows[0].current && rows[0].label.starts_with("client1: test_session: "));
+        app.clients.clear();
+        assert_eq!(choose_client(&app, None, None), Err("no current client".to_string()));
+    }
+
+    #[test]
+    fn frames_are_pushed_to_subscribed_clients() {
+        let start = Instant::now();
+        // A change waits out the debounce, and frames keep their spacing
+        assert_eq!(push_due(start, None), start + PUSH_DEBOUNCE);
+        assert_eq!(push_due(start, Some(start)), start + PUSH_MIN_INTERVAL);
+        assert_eq!(push_due(start + Duration::from_secs(1), Some(start)), start + Duration::from_secs(1) + PUSH_DEBOUNCE);
+
+        let (mut app, rx) = app_with_client(false, start);
+        let (tx, ctrl) = mpsc::channel();
+        assert_eq!(push_frames(&app, &tx), 0);
+        app.clients[0].push = Some(crate::bandwidth::FrameKind::Styled);
+        assert_eq!(push_frames(&app, &tx), 1);
+        // The frame is built like a persistent dump-state and written in order with directives
+        let frame = rx.try_recv().unwrap();
+        match ctrl.try_recv().unwrap() {
+            crate::types::CtrlReq::DumpState(resp, true, crate::bandwidth::FrameKind::Styled, Some(1)) => { resp.send("{}".into()).unwrap(); }
+            _ => panic!("expected a frame request"),
+        }
+        assert_eq!(frame.recv().unwrap(), "{}");
+        // A FORMAT binary client is pushed packed frames
+        app.clients[0].push = Some(crate::bandwidth::FrameKind::Binary);
+        assert_eq!(push_frames(&app, &tx), 1);
+        assert!(matches!(ctrl.try_recv().unwrap(), crate::types::CtrlReq::DumpState(_, true, crate::bandwidth::FrameKind::Binary, Some(1))));
+        // Not while suspended or in low-bandwidth mode, which asks at its own pace
+        app.clients[0].suspended = true;
+        assert_eq!(push_frames(&app, &tx), 0);
+        app.clients[0].suspended = false;
+        app.clients[0].low_bandwidth.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(push_frames(&app, &tx), 0);
+    }
+
+    #[test]
+    fn unchanged_frames_are_nc_per_client() {
+        let start = Instant::now();
+        let (mut app, _rx1) = app_with_client(false, start);
+        app.attached_clients = 1;
+        note_frame_sent(&mut app, Some(1), 1);
+        assert!(client_has_frame(&app, Some(1), 1));
+
+        let (tx2, _rx2) = mpsc::channel();
+        app.clients.push(ClientConn { last_input: start, directives: tx2, ..ClientConn::test(2) });
+        app.attached_clients = 2;
+        // Client 1 polling while idle is not sent its frame again
+        assert!(client_has_frame(&app, Some(1), 1));
+        // Client 2 has not seen it yet, then has
+        assert!(!client_has_frame(&app, Some(2), 1));
+        note_frame_sent(&mut app, Some(2), 1);
+        assert!(client_has_frame(&app, Some(2), 1));
+        // A newer frame built for client 2 is news to client 1
+        note_frame_sent(&mut app, Some(2), 2);
+        assert!(!client_has_frame(&app, Some(1), 2));
+        // Unregistered connections only get "NC" with a single client attached
+        assert!(!client_has_frame(&app, None, 2));
+        app.attached_clients = 1;
+        assert!(client_has_frame(&app, None, 2));
+    }
+
+    #[test]
+    fn switch_client_targets() {
+        let start = Instant::now();
+        let (mut app, _rx) = app_with_client(false, start);
+        app.session_name = "dev".to_string();
+        let running: Vec<String> = ["api", "dev", "web"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Named("web".into())), Ok("web".to_string()));
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Named("web:2.1".into())), Ok("web".to_string()));
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Named("gone".into())), Err("can't find session: gone".to_string()));
+        // -n and -p go round the sessions by name
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Next), Ok("web".to_string()));
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Previous), Ok("api".to_string()));
+        app.session_name = "web".to_string();
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Next), Ok("api".to_string()));
+        assert_eq!(switch_target(&app, Some("dev"), &running, &SwitchTo::Last), Ok("dev".to_string()));
+        assert_eq!(switch_target(&app, Some("old"), &running, &SwitchTo::Last), Err("can't find last session".to_string()));
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Last), Err("can't find last session".to_string()));
+        // -L namespaces: registry names carry the prefix
+        app.socket_name = Some("ci".to_string());
+        let running: Vec<String> = ["ci__a", "ci__web"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Named("a".into())), Ok("ci__a".to_string()));
+        assert_eq!(switch_target(&app, None, &running, &SwitchTo::Previous), Ok("ci__a".to_string()));
+    }
+
+    #[test]
+    fn there_is_one_run_server() {
+        // Every entry point goes through server::run_server; a second copy
+        // would start collecting fixes of its own
+        fn walk(dir: &std::path::Path, defs: &mut Vec<String>) {
+            let re = regex::Regex::new(r"\bfn\s+run_server\b").unwrap();
+            for entry in std::fs::read_dir(dir).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() { walk(&path, defs); continue; }
+                if path.extension().is_some_and(|e| e == "rs") {
+                    let src = std::fs::read_to_string(&path).unwrap();
+                    defs.extend(re.find_iter(&src).map(|_| path.display().to_string()));
+                }
+            }
+        }
+        let mut defs = Vec::new();
+        walk(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut defs);
+        assert_eq!(defs.len(), 1, "{:?}", defs);
+        assert!(defs[0].ends_with("mod.rs"), "{:?}", defs);
+    }
+
+    #[test]
+    fn only_the_requesting_client_repaints() {
+        let start = Instant::now();
+        let (mut app, rx1) = app_with_client(true, start);
+        let (tx2, rx2) = mpsc::channel();
+        app.clients.push(ClientConn { last_input: start, directives: tx2, ..ClientConn::test(2) });
+        assert_eq!(repaint_client(&app, Some(2)), Some(2));
+        assert_eq!(rx2.try_recv().unwrap().recv().unwrap(), "%redraw");
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(repaint_client(&app, Some(9)), None);
+    }
+
+    fn window(id: usize, created: DateTime<Local>) -> Window {
+        let mut win = Window::bare(id, id, &format!("w{}", id));
+        (win.activity_at, win.visited_at, win.created_at) = (created, created, created);
+        win
+    }
+
+    #[test]
+    fn window_options_override_the_session_monitors() {
+        use crate::types::OptionScope;
+        use super::super::options::{apply_scoped_option, show_scoped_options};
+        let t0 = Local::now();
+        let mut app = AppState::new("test_session".to_string());
+        app.monitor_activity = true;
+        for id in 0..3 { app.windows.push(window(id, t0)); }
+        // `set -w monitor-activity off` in window 1 leaves window 2 monitored
+        app.active_idx = 1;
+        apply_scoped_option(&mut app, OptionScope::Window, "monitor-activity", Some("off".into()));
+        app.active_idx = 0;
+        note_window_activity(&mut app, &[0, 4, 4], &[false; 3], t0);
+        assert!(!app.windows[1].activity_flag);
+        assert!(app.windows[2].activity_flag);
+
+        // show -w: the window's own value plain, inherited ones starred
+        app.active_idx = 1;
+        let shown = show_scoped_options(&app, OptionScope::Window, "prefix C-b\nmonitor-activity on\n");
+        assert!(shown.starts_with("prefix C-b\n"));
+        assert!(shown.contains("\nmonitor-activity off\n"));
+        assert!(shown.contains("\nsynchronize-panes* off\n"));
+        assert!(!shown.contains("monitor-activity on"));
+        // -u goes back to the session's
+        apply_scoped_option(&mut app, OptionScope::Window, "monitor-activity", None);
+        assert!(show_scoped_options(&app, OptionScope::Window, "").contains("monitor-activity* on\n"));
+    }
+
+    #[test]
+    fn chooser_orders_windows_by_activity_and_visits() {
+        use crate::util::{WinTree, WindowSort};
+        let t0 = Local::now();
+        let at = |s: i64| t0 + chrono::Duration::seconds(s);
+        let mut app = AppState::new("test_session".to_string());
+        app.monitor_activity = true;
+        for id in 0..3 { app.windows.push(window(id, t0)); }
+        app.active_idx = 0;
+        assert!(!note_window_activity(&mut app, &[0, 0, 0], &[false; 3], t0).0);
+        // Output in window 2, then the user visits window 1, then output in window 0
+        assert!(note_window_activity(&mut app, &[0, 0, 5], &[false; 3], at(1)).0);
+        assert!(app.windows[2].activity_flag);
+        app.active_idx = 1;
+        note_window_activity(&mut app, &[0, 0, 5], &[false; 3], at(2));
+        note_window_activity(&mut app, &[3, 0, 5], &[false; 3], at(3));
+        assert!(app.windows[0].activity_flag);
+        assert!(!app.windows[1].activity_flag);
+        assert_eq!(app.windows[0].activity_at, at(3));
+        assert_eq!(app.windows[1].visited_at, at(3));
+        assert_eq!(app.windows[0].visited_at, at(1));
+
+        // The order survives the trip through list-tree / dump-state JSON
+        let wins: Vec<WinTree> = serde_json::from_str(&crate::util::list_tree_json(&app).unwrap()).unwrap();
+        let ids = |sort: WindowSort| -> Vec<usize> { sort.order(&wins).into_iter().map(|i| wins[i].id).collect() };
+        assert_eq!(ids(WindowSort::Index), vec![0, 1, 2]);
+        assert_eq!(ids(WindowSort::Activity), vec![0, 2, 1]);
+        assert_eq!(ids(WindowSort::Visited), vec![1, 0, 2]);
+        assert_eq!(WindowSort::Visited.next(), WindowSort::Index);
+
+        let fmt = |var: &str, w: usize| crate::format::expand_format_for_window(&format!("#{{{}}}", var), &app, w);
+        assert_eq!(fmt("window_activity", 2), at(1).timestamp().to_string());
+        assert_eq!(fmt("window_last_visited", 1), at(3).timestamp().to_string());
+    }
+
+    #[test]
+    fn idle_windows_are_flagged_then_killed_once() {
+        let t0 = Local::now();
+        let at = |m: i64| t0 + chrono::Duration::minutes(m);
+        let mut app = AppState::new("test_session".to_string());
+        app.idle_window_timeout = 2;
+        for id in 0..3 { app.windows.push(window(id, t0)); }
+        app.windows[0].visited_at = at(120);
+        assert_eq!(idle_window_actions(&mut app, at(119)), (false, vec![]));
+        assert_eq!(idle_window_actions(&mut app, at(120)).1, [(1, IdleWindow::Flagged), (2, IdleWindow::Flagged)]);
+        assert!(idle_window_actions(&mut app, at(121)).1.is_empty());
+        let flags = |app: &AppState, w| crate::format::expand_format_for_window("#{window_flags}", app, w);
+        assert_eq!(flags(&app, 1), "?");
+        // Output clears it
+        app.windows[2].activity_at = at(122);
+        assert_eq!(idle_window_actions(&mut app, at(122)), (true, vec![]));
+        assert_eq!(flags(&app, 2), "");
+
+        // kill: announced, then killed after the grace period unless visited
+        app.idle_window_action = "kill".into();
+        app.windows[2].activity_at = t0;
+        assert_eq!(idle_window_actions(&mut app, at(123)).1, [(1, IdleWindow::Warned), (2, IdleWindow::Flagged), (2, IdleWindow::Warned)]);
+        app.windows[2].visited_at = at(123);
+        assert_eq!(idle_window_actions(&mut app, at(123)), (true, vec![]));
+        assert_eq!(idle_window_actions(&mut app, at(124)).1, [(1, IdleWindow::Kill)]);
+        kill_idle_window(&mut app, 1);
+        assert_eq!(app.windows.iter().map(|w| w.id).collect::<Vec<_>>(), [0, 2]);
+        assert!(idle_window_actions(&mut app, at(125)).1.is_empty());
+        assert_eq!(crate::format::expand_format_for_window("#{window_created}", &app, 1), t0.timestamp().to_string());
+    }
+
+    #[test]
+    fn busy_and_dead_windows_are_only_flagged() {
+        let t0 = Local::now();
+        let at = |h: i64| t0 + chrono::Duration::hours(h);
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("test_session".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        (app.idle_window_timeout, app.idle_window_action) = (1, "kill".into());
+        for w in app.windows.iter_mut() { (w.activity_at, w.visited_at) = (t0, t0); }
+        app.windows[0].visited_at = at(5);
+        app.active_idx = 0;
+        {
+            let win = &mut app.windows[1];
+            crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().child_pid = Some(100);
+        }
+        // Something other than the shell is running in window 1
+        app.process_lookup.foreground_pid = |pid| pid + 1;
+        assert_eq!(idle_window_actions(&mut app, at(1)).1, [(app.windows[1].id, IdleWindow::Flagged)]);
+        app.process_lookup.foreground_pid = |pid| pid;
+        {
+            let win = &mut app.windows[1];
+            crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().dead = true;
+        }
+        assert!(idle_window_actions(&mut app, at(2)).1.is_empty());
+        {
+            let win = &mut app.windows[1];
+            crate::tree::active_pane_mut(&mut win.root, &win.active_path).unwrap().dead = false;
+        }
+        assert_eq!(idle_window_actions(&mut app, at(3)).1, [(app.windows[1].id, IdleWindow::Warned)]);
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn alerts_flag_other_windows_and_clear_on_entry() {
+        let t0 = Local::now();
+        let at = |s: i64| t0 + chrono::Duration::seconds(s);
+        let (mut app, rx) = app_with_client(false, Instant::now());
+        (app.monitor_activity, app.monitor_silence) = (true, 10);
+        for id in 0..3 { app.windows.push(window(id, t0)); }
+        app.active_idx = 0;
+        let none = [false; 3];
+        assert!(note_window_activity(&mut app, &[0, 0, 0], &none, t0).1.is_empty());
+
+        // Output in window 1, bells in windows 0 (current) and 2
+        let (changed, raised) = note_window_activity(&mut app, &[0, 4, 0], &[true, false, true], at(1));
+        assert!(changed);
+        assert_eq!(raised, [(0, Alert::Bell), (1, Alert::Activity), (2, Alert::Bell)]);
+        assert!(!app.windows[0].bell_flag);
+        // Raised again only once cleared
+        assert!(note_window_activity(&mut app, &[0, 8, 0], &[false, false, true], at(2)).1.is_empty());
+
+        // Ten seconds without output
+        let (_, raised) = note_window_activity(&mut app, &[0, 8, 0], &none, at(12));
+        assert_eq!(raised, [(1, Alert::Silence), (2, Alert::Silence)]);
+        let flags = |app: &AppState, w| crate::format::expand_format_for_window("#{window_flags}", app, w);
+        assert_eq!((flags(&app, 1), flags(&app, 2)), ("#~".to_string(), "!~".to_string()));
+        assert_eq!(crate::alerts::tab_style(&app, &app.windows[2]), app.window_status_bell_style);
+
+        // Entering a window clears its flags
+        app.active_idx = 2;
+        assert_eq!(note_window_activity(&mut app, &[0, 8, 0], &none, at(13)), (true, vec![]));
+        assert_eq!(flags(&app, 2), "*");
+        assert!(crate::alerts::tab_style(&app, &app.windows[2]).is_empty());
+
+        // One bell for the clients, or a message under visual-*;
+        // bell-action other leaves out the current window's bells
+        crate::alerts::raise(&mut app, &[(0, Alert::Bell), (1, Alert::Activity)]);
+        assert_eq!(rx.try_recv().unwrap().recv().unwrap(), "%bell");
+        assert!(rx.try_recv().is_err());
+        (app.visual_silence, app.bell_action) = (true, "other".into());
+        crate::alerts::raise(&mut app, &[(1, Alert::Silence), (2, Alert::Bell)]);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(app.pending_message.as_deref(), Some("Silence in window 1"));
+    }
+
+    #[test]
+    fn flag_clusters_are_recognised() {
+        let args = ["-gqv", "@tpm_plugins"];
+        assert!(has_flag(&args, 'q'));
+        assert!(has_flag(&args, 'v'));
+        assert!(!has_flag(&args, 'u'));
+        assert!(!has_flag(&["-1"], '1'));
+        assert!(!has_flag(&["--quiet"], 'q'));
+    }
+
+    #[test]
+    fn pane_sizes_in_cells_or_percent() {
+        assert_eq!(pane_size_arg(&["-h", "-l", "20"]), Some(PaneSize::Cells(20)));
+        assert_eq!(pane_size_arg(&["-l", "30%"]), Some(PaneSize::Percent(30)));
+        assert_eq!(pane_size_arg(&["-p", "40", "-s", "%1"]), Some(PaneSize::Percent(40)));
+        assert_eq!(pane_size_arg(&["-l", "wide"]), None);
+        assert_eq!(pane_size_arg(&["-d"]), None);
+    }
+
+    #[test]
+    fn one_batch_at_a_time_and_only_its_owner_closes_it() {
+        let start = Instant::now();
+        let mut batch = None;
+        assert!(begin_batch(&mut batch, 1, start).is_ok());
+        assert_eq!(begin_batch(&mut batch, 1, start).unwrap_err(), "begin-batch: a batch is already open on this connection");
+        assert_eq!(begin_batch(&mut batch, 2, start).unwrap_err(), "begin-batch: another connection has a batch open");
+        assert_eq!(end_batch(&mut batch, 2, "commit-batch").unwrap_err(), "commit-batch: no batch open on this connection");
+        assert!(batch.is_some());
+        assert!(end_batch(&mut batch, 1, "commit-batch").is_ok());
+        assert!(batch.is_none());
+        assert!(end_batch(&mut batch, 1, "abort-batch").is_err());
+    }
+
+    #[test]
+    fn forgotten_batches_expire() {
+        let start = Instant::now();
+        let mut batch = None;
+        begin_batch(&mut batch, 1, start).unwrap();
+        assert!(!expire_batch(&mut batch, start + BATCH_TIMEOUT / 2));
+        assert!(batch.is_some());
+        assert!(expire_batch(&mut batch, start + BATCH_TIMEOUT));
+        assert!(batch.is_none());
+        assert!(!expire_batch(&mut batch, start + BATCH_TIMEOUT * 2));
+        // The next script can start its own
+        assert!(begin_batch(&mut batch, 2, start + BATCH_TIMEOUT * 2).is_ok());
+    }
+
+    #[test]
+    fn move_window_places_kills_and_renumbers() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        app.window_base_index = 1;
+        for _ in 0..3 { create_window(&*pty, &mut app, None).unwrap(); }
+        let ids: Vec<usize> = app.windows.iter().map(|w| w.id).collect();
+        let layout = |app: &AppState| app.windows.iter().map(|w| (w.index, w.id)).collect::<Vec<_>>();
+        app.active_idx = 0;
+
+        // To a free number, and the moved window is selected
+        move_window(&mut app, Some(":1"), Some(7), false, false, false).unwrap();
+        assert_eq!(layout(&app), vec![(2, ids[1]), (3, ids[2]), (7, ids[0])]);
+        assert_eq!(app.windows[app.active_idx].id, ids[0]);
+        // A taken number is refused without -k
+        assert_eq!(move_window(&mut app, Some(":2"), Some(3), false, false, true), Err("index 3 in use".to_string()));
+        // -k replaces the window there; -d leaves the selection alone
+        move_window(&mut app, Some(":2"), Some(3), true, false, true).unwrap();
+        assert_eq!(layout(&app), vec![(3, ids[1]), (7, ids[0])]);
+        assert_eq!(app.windows[app.active_idx].id, ids[0]);
+        assert_eq!(crate::format::expand_format_for_window("#{window_index}", &app, app.active_idx), "7");
+
+        // -r numbers them from base-index in order
+        move_window(&mut app, None, None, false, true, false).unwrap();
+        assert_eq!(layout(&app), vec![(1, ids[1]), (2, ids[0])]);
+        assert_eq!(app.windows[app.active_idx].id, ids[0]);
+    }
+
+    #[test]
+    fn targets_resolve_by_index_name_and_id() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        app.window_base_index = 1;
+        create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        create_window(&*pty, &mut app, None).unwrap();
+        app.windows[0].name = "editor".to_string();
+        app.windows[1].name = "logs".to_string();
+        let mut ids = Vec::new();
+        collect_pane_ids(&app.windows[0].root, &mut ids);
+        let logs_pane = active_pane(&app.windows[1].root, &app.windows[1].active_path).unwrap().id;
+        let w1 = app.windows[0].index;
+        let w2 = app.windows[1].index;
+        assert_eq!((w1, w2), (1, 2));
+
+        let resolve = |spec: &str| resolve_target(&app, &parse_target(spec), spec);
+        assert_eq!(resolve("work:1.1"), Ok((1, ids[1])));
+        assert_eq!(resolve("work:editor.0"), Ok((1, ids[0])));
+        // A unique prefix names a window; the active pane is used when none is given
+        assert_eq!(resolve("work:ed"), Ok((1, ids[1])));
+        assert_eq!(resolve(":2"), Ok((2, logs_pane)));
+        assert_eq!(resolve(&format!("%{}", ids[0])), Ok((1, ids[0])));
+        assert_eq!(resolve(&format!(":@{}", app.windows[1].id)), Ok((2, logs_pane)));
+        assert_eq!(resolve(":0").unwrap_err(), "can't find window: :0");
+        assert_eq!(resolve("work:nosuch").unwrap_err(), "can't find window: work:nosuch");
+        assert_eq!(resolve("work:1.5").unwrap_err(), "can't find pane: work:1.5");
+        // A pane id has to be in the window given with it
+        assert_eq!(resolve(&format!("work:logs.%{}", ids[0])).unwrap_err(), format!("can't find pane: work:logs.%{}", ids[0]));
+        // Relative forms are left to the commands that read them
+        assert!(!target_names_pane(&parse_target(":.+")));
+        assert!(!target_names_pane(&parse_target(":{last}")));
+        assert!(!target_names_pane(&parse_target("work")));
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn targeted_commands_act_on_their_pane_and_leave_focus_alone() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        for _ in 0..2 {
+            create_window(&*pty, &mut app, None).unwrap();
+            crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+            crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        }
+        app.windows[0].active_path = vec![0];
+        app.active_idx = 1;
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        collect_pane_ids(&app.windows[0].root, &mut first);
+        collect_pane_ids(&app.windows[1].root, &mut second);
+        let focus = |app: &AppState| (app.active_idx, app.windows.iter().map(|w| w.active_path.clone()).collect::<Vec<_>>());
+        let before = focus(&app);
+
+        // Two connections' commands alternate, each on its own -t pane
+        for (a, b) in [(first[1], second[0]), (first[2], second[1])] {
+            let shown = at_pane(&mut app, Some(a), |app| crate::format::expand_format("#{pane_id}", app));
+            assert_eq!(shown, format!("%{a}"));
+            assert_eq!(focus(&app), before);
+            let shown = at_pane(&mut app, Some(b), |app| crate::format::expand_format("#{pane_id}", app));
+            assert_eq!(shown, format!("%{b}"));
+            assert_eq!(focus(&app), before);
+        }
+        assert_eq!(target_pane_mut(&mut app, Some(first[2])).map(|p| p.id), Some(first[2]));
+        assert_eq!(target_window_pos(&app, Some(first[2])), 0);
+
+        // Killing another window's pane keeps that window's active pane
+        at_pane(&mut app, Some(first[1]), crate::pane::kill_active_pane).unwrap();
+        assert_eq!(app.active_idx, 1);
+        assert_eq!(active_pane(&app.windows[0].root, &app.windows[0].active_path).map(|p| p.id), Some(first[0]));
+        assert_eq!(app.windows[1].active_path, before.1[1]);
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn split_window_splits_its_target_pane() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        for _ in 0..2 {
+            create_window(&*pty, &mut app, None).unwrap();
+            crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        }
+        app.windows[0].active_path = vec![0];
+        app.active_idx = 1;
+        let mut first = Vec::new();
+        collect_pane_ids(&app.windows[0].root, &mut first);
+        let focus = |app: &AppState| (app.active_idx, app.windows.iter().map(|w| w.active_path.clone()).collect::<Vec<_>>());
+        let before = focus(&app);
+        let split = |app: &mut AppState| {
+            let made = crate::pane::split_active_with_command(app, crate::types::LayoutKind::Vertical, None, Some(&*pty)).is_ok();
+            (made, crate::format::expand_format("#{window_index}", app))
+        };
+        let panes = |app: &AppState| crate::tree::count_panes(&app.windows[0].root);
+
+        // -d: the other window's pane is split and nobody's focus moves
+        assert_eq!(split_at_pane(&mut app, Some(first[1]), true, split), app.windows[0].index.to_string());
+        assert_eq!(panes(&app), 3);
+        assert_eq!(focus(&app), before);
+
+        // Without -d the new pane, next to the target, is focused in its window
+        split_at_pane(&mut app, Some(first[1]), false, split);
+        assert_eq!(panes(&app), 4);
+        assert_eq!((app.active_idx, app.last_window_idx), (0, 1));
+        let new = crate::window_ops::active_pane_id(&app, 0).unwrap();
+        assert!(!first.contains(&new));
+        // It went beside the target (right half), not the window's active pane (left)
+        assert_eq!(crate::tree::pane_path(&app.windows[0].root, new).map(|p| p[0]), Some(1));
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn run_shell_expands_formats_and_records_the_exit_status() {
+        use crate::types::CtrlReq;
+        use std::sync::mpsc;
+        let wait = Duration::from_secs(10);
+        let mut app = AppState::new("run_shell_test".to_string());
+        app.windows.push(Window::bare(0, 0, "main"));
+        let (done_tx, done_rx) = mpsc::channel();
+
+        // From the CLI: the shell sees the expanded command and the status comes back
+        let (rtx, rrx) = mpsc::channel();
+        run_shell(&app, "echo #{session_name}".to_string(), false, Some(rtx), done_tx.clone());
+        let (status, output) = rrx.recv_timeout(wait).unwrap();
+        assert_eq!((status, output.trim()), (0, "run_shell_test"));
+        let Ok(CtrlReq::ShellDone(cmd, 0, None)) = done_rx.recv_timeout(wait) else { panic!("no ShellDone") };
+        assert_eq!(cmd, "echo run_shell_test");
+
+        // -b hands the command to a worker and returns at once
+        let start = Instant::now();
+        run_shell(&app, "sleep 1; exit 3".to_string(), true, None, done_tx);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(done_rx.try_recv().is_err());
+        assert_eq!(crate::format::expand_format("#{last_shell_exit}", &app), "0");
+
+        // #{last_shell_exit} follows once the command has finished
+        let Ok(CtrlReq::ShellDone(cmd, status, output)) = done_rx.recv_timeout(wait) else { panic!("no ShellDone") };
+        assert_eq!((status, output.as_deref()), (3, None));
+        shell_done(&mut app, &cmd, status, output);
+        assert_eq!(crate::format::expand_format("#{last_shell_exit}", &app), "3");
+    }
+
+    #[test]
+    fn in_process_kill_returns_instead_of_exiting() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new(format!("shut_down_{}", std::process::id()));
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        let dir = std::env::temp_dir().join(format!("psmux_shut_down_{}", std::process::id()));
+        let history = dir.join("history");
+        app.history_file = Some(history.to_string_lossy().to_string());
+        app.command_history.push("kill-server".to_string());
+        create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, crate::types::LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+
+        super::super::IN_PROCESS.store(true, std::sync::atomic::Ordering::Relaxed);
+        // Reaching the asserts at all means the test process is still here
+        assert!(shut_down(&mut app).is_ok());
+        assert!(history.exists());
+        fn ended(node: &mut Node) -> usize {
+            match node {
+                Node::Leaf(p) => p.child.try_wait().ok().flatten().is_some() as usize,
+                Node::Split { children, .. } => children.iter_mut().map(ended).sum(),
+            }
+        }
+        assert_eq!(ended(&mut app.windows[0].root), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn focus_keeping_commands_list_every_spelling() {
+        // Top-level arms of the command match in connection.rs
+        let src = include_str!("connection.rs");
+        for line in src.lines().filter(|l| l.starts_with("    \"")) {
+            let Some((head, _)) = line.split_once("=>") else { continue };
+            let names: Vec<&str> = head.split('|').map(|n| n.trim().trim_matches('"')).collect();
+            if names.iter().any(|n| target_keeps_focus(n)) {
+                for n in &names { assert!(target_keeps_focus(n), "{} is missing beside {:?}", n, names); }
+            }
+        }
+        assert!(target_keeps_focus("show-window-option"));
+    }
+
+    #[test]
+    fn duplicate_window_names_are_never_picked_silently() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("work".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        for _ in 0..4 { create_window(&*pty, &mut app, None).unwrap(); }
+        for (w, name) in app.windows.iter_mut().zip(["api", "api-docs", "api", "2024"]) { w.name = name.to_string(); }
+        let resolve = |spec: &str| resolve_target(&app, &parse_target(spec), spec).map(|r| r.0);
+
+        // Exact names win over prefixes; `=` allows nothing else
+        assert_eq!(resolve(":api-docs"), Ok(1));
+        assert_eq!(resolve(":=api-docs"), Ok(1));
+        assert_eq!(resolve(":=api-d").unwrap_err(), "can't find window: :=api-d");
+        // A unique prefix still works, an ambiguous one lists the candidates
+        assert_eq!(resolve(":api-d"), Ok(1));
+        assert_eq!(resolve(":ap").unwrap_err(), "ambiguous target: ap (windows 0, 1, 2)");
+        assert_eq!(resolve(":api").unwrap_err(), "ambiguous target: api (windows 0, 2)");
+        assert_eq!(resolve(":=api").unwrap_err(), "ambiguous target: api (windows 0, 2)");
+        // name#N picks one of the duplicates by index
+        assert_eq!(resolve(":api#2"), Ok(2));
+        assert_eq!(resolve(":=api#0"), Ok(0));
+        assert!(resolve(":api#1").is_err());
+        // Indexes come first; a numeric name is found when no index matches
+        assert_eq!(resolve(":3"), Ok(3));
+        assert_eq!(resolve(":2024"), Ok(3));
+
+        assert_eq!(find_window_lines(&app, "api"), "0: api [] api#0\n2: api [] api#2\n1: api-docs []\n");
+        assert!(check_window_name(&app, "api", None).is_ok());
+        app.allow_duplicate_window_names = false;
+        assert_eq!(check_window_name(&app, "api", None).unwrap_err(), "duplicate window name: api (window 0)");
+        // Renaming a window to its own name is not a duplicate
+        assert!(check_window_name(&app, "2024", Some(3)).is_ok());
+        assert!(new_window(&mut app, &*pty, None, Some("api-docs".into()), true, None, None, false).is_err());
+        assert_eq!(app.windows.len(), 4);
+
+        for w in app.windows.iter_mut() { kill_all_children(&mut w.root); }
+    }
+}
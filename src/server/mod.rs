@@ -4,53 +4,70 @@ mod connection;
 
 use std::io::{self, Write};
 use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 use std::env;
-use crate::pipe::{self, PipeStream};
+use crate::pipe;
 
 use portable_pty::native_pty_system;
-use ratatui::prelude::Rect;
 
-use crate::types::{AppState, CtrlReq, Mode, FocusDir, LayoutKind, PipePaneState, VERSION,
-    WaitChannel, WaitForOp, Node, Action, Bind, PopupPty};
+use crate::types::{AppState, CtrlReq, Mode, FocusDir, SwapWith, VERSION,
+    WaitChannel, WaitForOp, Node, Action, Bind};
 use crate::platform::install_console_ctrl_handler;
-use crate::pane::{create_window, create_window_raw, split_active_with_command, kill_active_pane};
+use crate::pane::{create_window, create_window_raw, split_pane, kill_active_pane};
 use crate::tree::{self, active_pane, active_pane_mut, resize_all_panes, kill_all_children,
-    find_window_index_by_id, focus_pane_by_id, focus_pane_by_index, get_active_pane_id,
+    find_window_index_by_id, focus_pane_by_id, focus_pane_by_index,
     path_exists};
 
-use helpers::{collect_pane_paths_server, serialize_bindings_json, json_escape_string,
-    list_windows_json_with_tabs, combined_data_version, TMUX_COMMANDS};
-use options::{get_option_value, apply_set_option};
+use helpers::{collect_pane_paths_server, json_escape_string,
+    list_windows_json_with_tabs, combined_data_version, idle_client_actions, IdleAction, apply_refresh_flags, repaint_client, check_window_activity, new_window};
+pub(crate) use helpers::{serialize_bindings_json, TMUX_COMMANDS};
+use options::{get_option_value, apply_set_option, apply_scoped_option, scoped_option_value, show_scoped_options, SCOPED_OPTIONS};
 
-use crate::input::{send_text_to_active, send_key_to_active, move_focus};
+use crate::input::{send_text_to_active, send_key_to_active, encode_send_keys, move_focus};
 use crate::copy_mode::{enter_copy_mode, exit_copy_mode, move_copy_cursor, current_prompt_pos,
-    yank_selection, scroll_copy_up, scroll_copy_down, switch_with_copy_save,
+    yank_to_register, scroll_copy_up, switch_with_copy_save, run_copy_command,
     capture_active_pane_text, capture_active_pane_range, capture_active_pane_styled};
+use crate::bandwidth::FrameKind;
 use crate::layout::{dump_layout_json, dump_layout_json_fast, apply_layout, cycle_layout,
     cycle_layout_reverse};
 use crate::window_ops::{toggle_zoom, remote_mouse_down, remote_mouse_drag, remote_mouse_up,
     remote_mouse_button, remote_mouse_motion, remote_scroll_up, remote_scroll_down,
-    swap_pane, break_pane_to_window, unzoom_if_zoomed, resize_pane_vertical,
-    resize_pane_horizontal, resize_pane_absolute, rotate_panes, respawn_active_pane};
+    swap_pane, swap_panes, break_pane_to_window, join_pane, unzoom_if_zoomed, resize_pane_vertical,
+    resize_pane_horizontal, resize_pane_absolute, rotate_panes, respawn_active_pane,
+    window_pos_by_index, remove_window};
 use crate::config::{load_config, parse_key_string, format_key_binding, normalize_key_for_binding,
-    parse_config_content, parse_config_line};
-use crate::commands::{parse_command_to_action, format_action, parse_menu_definition};
+    parse_config_content};
+use crate::commands::{parse_command_to_action, parse_menu_definition};
 use crate::util::{list_windows_json, list_tree_json, list_windows_tmux};
 use crate::format::{expand_format, format_list_windows, format_list_panes, set_buffer_idx_override};
-use crate::help;
+
+/// Set when the server runs on a thread of the attaching process
+/// (`--no-daemon`): killing it then returns from run_server instead of
+/// exiting, so the client can still restore the terminal.
+static IN_PROCESS: AtomicBool = AtomicBool::new(false);
+
+/// Run the server for `session_name` on a thread of this process, with
+/// the same arguments a `psmux server` process gets.
+pub fn spawn_in_process(session_name: String, socket_name: Option<String>, initial_command: Option<String>, raw_command: Option<Vec<String>>) -> thread::JoinHandle<io::Result<()>> {
+    IN_PROCESS.store(true, Ordering::Relaxed);
+    thread::spawn(move || run_server(session_name, socket_name, initial_command, raw_command))
+}
 
 pub fn run_server(session_name: String, socket_name: Option<String>, initial_command: Option<String>, raw_command: Option<Vec<String>>) -> io::Result<()> {
-    // Write crash info to a log file when stderr is unavailable (detached server)
-    std::panic::set_hook(Box::new(|info| {
-        let home = std::env::var("USERPROFILE").or_else(|_| std::env::var("HOME")).unwrap_or_default();
-        let path = format!("{}\\.psmux\\crash.log", home);
-        let bt = std::backtrace::Backtrace::force_capture();
-        let _ = std::fs::write(&path, format!("{info}\n\nBacktrace:\n{bt}"));
-    }));
-    // Install console control handler to prevent termination on client detach
-    install_console_ctrl_handler();
+    // The panic hook and console handler are process-wide: an in-process
+    // server leaves them to its client
+    if !IN_PROCESS.load(Ordering::Relaxed) {
+        // Write crash info to a log file when stderr is unavailable (detached server)
+        std::panic::set_hook(Box::new(|info| {
+            let path = format!("{}\\crash.log", crate::session::psmux_dir());
+            let bt = std::backtrace::Backtrace::force_capture();
+            let _ = std::fs::write(&path, format!("{info}\n\nBacktrace:\n{bt}"));
+        }));
+        // Install console control handler to prevent termination on client detach
+        install_console_ctrl_handler();
+    }
 
     let pty_system = native_pty_system();
 
@@ -62,17 +79,21 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
     // Set up the control channel BEFORE creating the initial window.
     let (tx, rx) = mpsc::channel::<CtrlReq>();
     app.control_rx = Some(rx);
+    let exit_tx = tx.clone();
+    app.exit_watcher = Some(crate::platform::exit_watch::ExitWatcher::new(move |pane_id, pid, status| {
+        let _ = exit_tx.send(CtrlReq::ChildExited(pane_id, pid, status));
+    }));
 
-    // Create the first named pipe instance to claim the pipe name.
-    let pipe_base = app.port_file_base();
-    let first_pipe = pipe::create_server_pipe(&pipe_base, true)?;
+    // Start listening (claiming the socket file or pipe name) before the
+    // registry record goes out.
+    let mut listener = crate::transport::listen(&app.port_file_base(), app.socket_name.as_deref())?;
+    let endpoint = listener.endpoint();
 
     // Write key file IMMEDIATELY after binding, BEFORE creating the
     // initial window.  The client polls for the key file to know the server is
     // ready to accept connections.  Writing early (before the slow ConPTY +
     // pwsh spawn) shaves 200-400ms off first-start latency.
-    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-    let dir = format!("{}\\.psmux", home);
+    let dir = crate::session::psmux_dir();
     let _ = std::fs::create_dir_all(&dir);
 
     // Generate a random session key for security
@@ -86,64 +107,53 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
         format!("{:016x}", h.finish())
     };
 
-    let keypath = format!("{}\\{}.key", dir, app.port_file_base());
-    let _ = std::fs::write(&keypath, &session_key);
-
-    // Try to set file permissions to user-only (Windows)
-    #[cfg(windows)]
-    {
-        // Recreate key file with restricted permissions
-        let _ = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&keypath)
-            .map(|mut f| std::io::Write::write_all(&mut f, session_key.as_bytes()));
-    }
+    // Registry record: key + pid + format version, so clients can tell a
+    // live compatible server from a stale or foreign one.
+    let trusted = endpoint.trusted();
+    let _ = crate::session::write_session_record(&app.port_file_base(), &session_key, app.socket_name.as_deref(), endpoint);
 
     // Create initial window with optional command (this spawns ConPTY + pwsh,
     // which is the slowest step — but the port file is already written so the
-    // client can connect immediately without waiting)
+    // client can connect immediately without waiting).  With
+    // @resurrect-on-start on, a plain new session restores the latest
+    // snapshot instead, falling back to the usual window.
+    let restored = raw_command.is_none() && initial_command.is_none() && crate::resurrect::restore_on_start(&app)
+        && crate::resurrect::load_from(&crate::resurrect::snapshot_dir(), None)
+            .and_then(|snap| crate::resurrect::restore(&mut app, &*pty_system, &snap))
+            .map_or(false, |n| n > 0);
     if let Some(ref raw_args) = raw_command {
         create_window_raw(&*pty_system, &mut app, raw_args)?;
-    } else {
+    } else if !restored {
         create_window(&*pty_system, &mut app, initial_command.as_deref())?;
     }
     
-    // Shared command aliases map — updated by main loop, read by handler threads
-    let shared_aliases: std::sync::Arc<std::sync::RwLock<std::collections::HashMap<String, String>>> =
-        std::sync::Arc::new(std::sync::RwLock::new(app.command_aliases.clone()));
-    let shared_aliases_main = shared_aliases.clone();
+    // run-shell jobs report back on the control channel
+    let shell_tx = tx.clone();
+    // Frames for subscribed clients are asked for on it too
+    let push_tx = tx.clone();
 
     thread::spawn(move || {
-        // The first pipe instance was already created above.
-        let mut current_pipe = first_pipe;
-        loop {
-            // Wait for a client to connect to this pipe instance
-            if pipe::wait_for_connection(current_pipe).is_err() {
-                break;
-            }
-            let stream = PipeStream::from_handle(current_pipe);
+        while let Ok(stream) = listener.accept() {
             let tx = tx.clone();
             let session_key_clone = session_key.clone();
-            let aliases = shared_aliases.clone();
             thread::spawn(move || {
-                connection::handle_connection(stream, tx, &session_key_clone, aliases);
+                connection::handle_connection(stream, tx, &session_key_clone, trusted);
             });
-            // Create a new pipe instance for the next client
-            match pipe::create_server_pipe(&pipe_base, false) {
-                Ok(h) => current_pipe = h,
-                Err(_) => break,
-            }
         }
     });
     let mut state_dirty = true;
     let mut cached_dump_state = String::new();
     let mut cached_data_version: u64 = 0;
+    // Styled or Binary: the kind of frame cached_dump_state holds
+    let mut cached_dump_kind = FrameKind::Styled;
+    // Bumped whenever cached_dump_state is rebuilt (see ClientConn::frame_version)
+    let mut cached_frame_version: u64 = 0;
     // Cached metadata JSON — windows/tree/prefix change only on structural
     // mutations, so we rebuild them lazily via `meta_dirty`.
     let mut meta_dirty = true;
     let mut cached_windows_json = String::new();
+    // Window tabs and status-left/right, expanded again only when what they read changes
+    let mut format_cache = crate::format::FormatCache::default();
     let mut cached_tree_json = String::new();
     let mut cached_prefix_str = String::new();
     let mut cached_prefix2_str = String::new();
@@ -163,8 +173,27 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
     // take 10-30ms.  Without this, each "no-change" polling cycle costs up
     // to 5ms, adding cumulative latency visible as heavy input lag.
     let mut echo_pending_until: Option<Instant> = None;
+    // client-idle-timeout is checked about once per second.
+    let mut last_idle_check = Instant::now();
+    // A status line showing the time is redrawn every status-interval
+    let mut last_status_refresh = Instant::now();
+    let mut reap_schedule = tree::ReapSchedule::new(cfg!(windows), Instant::now());
+    // When the current one-off status message (app.pending_message) expires
+    let mut message_clear_at: Option<Instant> = None;
+    // begin-batch / commit-batch: frames are held back while this is open
+    let mut frame_batch: Option<helpers::FrameBatch> = None;
+    // When subscribed clients are next sent a frame (PUSH_DEBOUNCE after a change)
+    let mut push_at: Option<Instant> = None;
+    let mut last_push: Option<Instant> = None;
 
     loop {
+        // Hooks for what happened outside a command (panes exiting, alerts)
+        crate::hooks::run_pending(&mut app);
+        // Config run-shell / if-shell -b jobs (queued at startup or by a later
+        // source-file) start now that requests are being answered.
+        crate::config::spawn_pending_shell_jobs(&mut app);
+        crate::popup::keep_spare_ready(&mut app);
+        if crate::popup::reap(&mut app) { state_dirty = true; }
         // Adaptive timeout: 1ms when echo-pending or fresh PTY data just
         // arrived (so we can serve the waiting dump-state request quickly),
         // 5ms otherwise to stay idle-friendly.
@@ -173,7 +202,8 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
             state_dirty = true;
         }
         let echo_active = echo_pending_until.map_or(false, |t| t.elapsed().as_millis() < 50);
-        let timeout_ms: u64 = if echo_active || data_ready { 1 } else { 5 };
+        let mut timeout_ms: u64 = if echo_active || data_ready { 1 } else { 5 };
+        if let Some(at) = push_at { timeout_ms = timeout_ms.min(at.saturating_duration_since(Instant::now()).as_millis() as u64); }
         if let Some(rx) = app.control_rx.as_ref() {
             if let Ok(req) = rx.recv_timeout(Duration::from_millis(timeout_ms)) {
                 let mut pending = vec![req];
@@ -199,94 +229,71 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     let mutates_state = !matches!(&req, CtrlReq::DumpState(..));
                     let mut hook_event: Option<&str> = None;
                     match req {
-                CtrlReq::NewWindow(cmd, name, detached, start_dir) => {
-                    let prev_idx = app.active_idx;
-                    let saved_dir = if start_dir.is_some() { env::current_dir().ok() } else { None };
-                    if let Some(dir) = &start_dir { env::set_current_dir(dir).ok(); }
-                    if let Err(e) = create_window(&*pty_system, &mut app, cmd.as_deref()) {
-                        eprintln!("psmux: new-window error: {e}");
+                CtrlReq::NewWindow(cmd, name, detached, start_dir, index, kill, resp) => {
+                    let start_dir = helpers::expand_start_dir(&app, start_dir);
+                    match new_window(&mut app, &*pty_system, cmd.as_deref(), name, detached, start_dir.as_deref(), index, kill) {
+                        Ok(_) => { let _ = resp.send(String::new()); }
+                        Err(e) => { let _ = resp.send(format!("psmux: new-window: {e}")); }
                     }
-                    if let Some(prev) = saved_dir { env::set_current_dir(prev).ok(); }
-                    if let Some(n) = name { app.windows.last_mut().map(|w| w.name = n); }
-                    if detached { app.active_idx = prev_idx; }
                     resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-new-window");
                 }
-                CtrlReq::NewWindowPrint(cmd, name, detached, start_dir, format_str, resp) => {
-                    let prev_idx = app.active_idx;
-                    let saved_dir = if start_dir.is_some() { env::current_dir().ok() } else { None };
-                    if let Some(dir) = &start_dir { env::set_current_dir(dir).ok(); }
-                    if let Err(e) = create_window(&*pty_system, &mut app, cmd.as_deref()) {
-                        eprintln!("psmux: new-window error: {e}");
+                CtrlReq::NewWindowPrint(cmd, name, detached, start_dir, index, kill, format_str, resp) => {
+                    let start_dir = helpers::expand_start_dir(&app, start_dir);
+                    match new_window(&mut app, &*pty_system, cmd.as_deref(), name, detached, start_dir.as_deref(), index, kill) {
+                        Ok(new_win_idx) => {
+                            // Use full format engine for -P output (tmux compatible)
+                            let fmt = format_str.as_deref().unwrap_or("#{session_name}:#{window_index}");
+                            let _ = resp.send(crate::format::expand_format_for_window(fmt, &app, new_win_idx));
+                        }
+                        Err(e) => { let _ = resp.send(format!("psmux: new-window: {e}")); }
                     }
-                    if let Some(prev) = saved_dir { env::set_current_dir(prev).ok(); }
-                    if let Some(n) = name { app.windows.last_mut().map(|w| w.name = n); }
-                    // Use full format engine for -P output (tmux compatible)
-                    let new_win_idx = app.windows.len() - 1;
-                    let fmt = format_str.as_deref().unwrap_or("#{session_name}:#{window_index}");
-                    let pane_info = crate::format::expand_format_for_window(fmt, &app, new_win_idx);
-                    if detached { app.active_idx = prev_idx; }
-                    let _ = resp.send(pane_info);
                     resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-new-window");
                 }
-                CtrlReq::SplitWindow(k, cmd, detached, start_dir, size_pct, resp) => {
+                CtrlReq::SplitWindow(k, cmd, detached, start_dir, opts, resp, pid) => {
+                    let start_dir = helpers::expand_start_dir(&app, start_dir);
                     let saved_dir = if start_dir.is_some() { env::current_dir().ok() } else { None };
                     if let Some(dir) = &start_dir { env::set_current_dir(dir).ok(); }
-                    let prev_path = app.windows[app.active_idx].active_path.clone();
-                    if let Err(e) = split_active_with_command(&mut app, k, cmd.as_deref(), Some(&*pty_system)) {
-                        let _ = resp.send(format!("psmux: split-window: {e}"));
-                    } else {
-                        let _ = resp.send(String::new());
-                    }
-                    // Apply size if specified (as percentage)
-                    if let Some(_pct) = size_pct {
-                        // Size will be applied by resize_all_panes using the layout ratios
-                    }
-                    if detached {
-                        // Revert focus to the previously active pane.
-                        // After split, prev_path now points to a Split node;
-                        // the original pane is child [0] of that Split.
-                        let mut revert_path = prev_path;
-                        revert_path.push(0);
-                        app.windows[app.active_idx].active_path = revert_path;
-                    }
+                    let err = helpers::split_at_pane(&mut app, pid, detached, |app| {
+                        match split_pane(app, k, cmd.as_deref(), Some(&*pty_system), opts) {
+                            Ok(()) => (true, String::new()),
+                            Err(e) => (false, format!("psmux: split-window: {e}")),
+                        }
+                    });
+                    let _ = resp.send(err);
                     if let Some(prev) = saved_dir { env::set_current_dir(prev).ok(); }
                     resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-split-window");
                 }
-                CtrlReq::SplitWindowPrint(k, cmd, detached, start_dir, size_pct, format_str, resp) => {
+                CtrlReq::SplitWindowPrint(k, cmd, detached, start_dir, opts, format_str, resp, pid) => {
+                    let start_dir = helpers::expand_start_dir(&app, start_dir);
                     let saved_dir = if start_dir.is_some() { env::current_dir().ok() } else { None };
                     if let Some(dir) = &start_dir { env::set_current_dir(dir).ok(); }
-                    let prev_path = app.windows[app.active_idx].active_path.clone();
-                    if let Err(e) = split_active_with_command(&mut app, k, cmd.as_deref(), Some(&*pty_system)) {
-                        eprintln!("psmux: split-window error: {e}");
-                    }
-                    if let Some(_pct) = size_pct { }
-                    // Use full format engine for -P output (tmux compatible)
-                    let fmt = format_str.as_deref().unwrap_or("#{session_name}:#{window_index}.#{pane_index}");
-                    let pane_info = crate::format::expand_format_for_window(fmt, &app, app.active_idx);
-                    if detached {
-                        let mut revert_path = prev_path;
-                        revert_path.push(0);
-                        app.windows[app.active_idx].active_path = revert_path;
-                    }
+                    let pane_info = helpers::split_at_pane(&mut app, pid, detached, |app| {
+                        let split = split_pane(app, k, cmd.as_deref(), Some(&*pty_system), opts);
+                        if let Err(e) = &split {
+                            eprintln!("psmux: split-window error: {e}");
+                        }
+                        // Use full format engine for -P output (tmux compatible)
+                        let fmt = format_str.as_deref().unwrap_or("#{session_name}:#{window_index}.#{pane_index}");
+                        (split.is_ok(), crate::format::expand_format_for_window(fmt, app, app.active_idx))
+                    });
                     let _ = resp.send(pane_info);
                     if let Some(prev) = saved_dir { env::set_current_dir(prev).ok(); }
                     resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-split-window");
                 }
-                CtrlReq::KillPane => { let _ = kill_active_pane(&mut app); resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-kill-pane"); }
-                CtrlReq::CapturePane(resp) => {
-                    if let Some(text) = capture_active_pane_text(&mut app)? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
+                CtrlReq::KillPane(pid) => { let _ = helpers::at_pane(&mut app, pid, kill_active_pane); resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-kill-pane"); }
+                CtrlReq::CapturePane(resp, pid) => {
+                    if let Some(text) = helpers::at_pane(&mut app, pid, capture_active_pane_text)? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
                 }
-                CtrlReq::CapturePaneStyled(resp, s, e) => {
-                    if let Some(text) = capture_active_pane_styled(&mut app, s, e)? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
+                CtrlReq::CapturePaneStyled(resp, s, e, join, pid) => {
+                    if let Some(text) = helpers::at_pane(&mut app, pid, |app| capture_active_pane_styled(app, s, e, join))? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
                 }
-                CtrlReq::CapturePaneRange(resp, s, e) => {
-                    if let Some(text) = capture_active_pane_range(&mut app, s, e)? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
+                CtrlReq::CapturePaneRange(resp, s, e, join, pid) => {
+                    if let Some(text) = helpers::at_pane(&mut app, pid, |app| capture_active_pane_range(app, s, e, join))? { let _ = resp.send(text); } else { let _ = resp.send(String::new()); }
                 }
                 CtrlReq::FocusWindow(wid) => {
                     // wid is a display index (same as tmux window number), convert to internal array index
-                    if wid >= app.window_base_index {
-                        let internal_idx = wid - app.window_base_index;
-                        if internal_idx < app.windows.len() && internal_idx != app.active_idx {
+                    if let Some(internal_idx) = window_pos_by_index(&app, wid) {
+                        if internal_idx != app.active_idx {
                             switch_with_copy_save(&mut app, |app| {
                                 app.last_window_idx = app.active_idx;
                                 app.active_idx = internal_idx;
@@ -303,13 +310,48 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     if app.windows[app.active_idx].active_path != old_path { unzoom_if_zoomed(&mut app); }
                     meta_dirty = true;
                 }
+                CtrlReq::ResolveTarget(target, spec, resp) => {
+                    let _ = resp.send(helpers::resolve_target(&app, &target, &spec));
+                }
+                CtrlReq::ExpandAlias(line, resp) => {
+                    let _ = resp.send(crate::alias::expand(&app.command_aliases, &line));
+                }
+                CtrlReq::RunShell(command, background, resp) => {
+                    helpers::run_shell(&app, command, background, resp, shell_tx.clone());
+                }
+                CtrlReq::IfShell(condition, then_cmd, else_cmd, format_mode, background, resp) => {
+                    if format_mode {
+                        let ok = crate::config::format_is_true(&crate::format::expand_format(&condition, &app));
+                        let _ = resp.send(if ok { Some(then_cmd) } else { else_cmd });
+                    } else if background {
+                        // Branches run through the CLI once the condition exits
+                        app.pending_shell_jobs.push(crate::types::ShellJob::IfShell { condition, then_cmd, else_cmd });
+                        let _ = resp.send(None);
+                    } else {
+                        let cmd = crate::format::expand_format(&condition, &app);
+                        let mut c = crate::config::shell_command(&cmd, Some(&app.port_file_base()));
+                        crate::environ::apply_to_job(&mut c, &crate::environ::composed(&app));
+                        c.stdout(std::process::Stdio::null())
+                            .stderr(std::process::Stdio::null());
+                        thread::spawn(move || {
+                            let ok = c.status().map(|s| s.success()).unwrap_or(false);
+                            let _ = resp.send(if ok { Some(then_cmd) } else { else_cmd });
+                        });
+                    }
+                }
+                CtrlReq::ShellDone(cmd, status, output) => {
+                    helpers::shell_done(&mut app, &cmd, status, output);
+                }
                 CtrlReq::FocusPaneByIndex(idx) => {
                     let old_path = app.windows[app.active_idx].active_path.clone();
                     switch_with_copy_save(&mut app, |app| { focus_pane_by_index(app, idx); });
                     if app.windows[app.active_idx].active_path != old_path { unzoom_if_zoomed(&mut app); }
                     meta_dirty = true;
                 }
-                CtrlReq::SessionInfo(resp) => {
+                CtrlReq::SessionInfo(resp, true) => {
+                    let _ = resp.send(serde_json::to_string(&crate::session::session_info(&app)).unwrap_or_default());
+                }
+                CtrlReq::SessionInfo(resp, false) => {
                     let attached = if app.attached_clients > 0 { " (attached)" } else { "" };
                     let windows = app.windows.len();
                     let created = app.created_at.format("%a %b %e %H:%M:%S %Y");
@@ -317,18 +359,57 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     let _ = resp.send(line);
                 }
                 CtrlReq::ClientAttach => { app.attached_clients = app.attached_clients.saturating_add(1); hook_event = Some("client-attached"); }
-                CtrlReq::ClientDetach => { app.attached_clients = app.attached_clients.saturating_sub(1); hook_event = Some("client-detached"); }
+                CtrlReq::ClientDetach => {
+                    app.attached_clients = app.attached_clients.saturating_sub(1);
+                    // The last client gone: back to the size set for no client
+                    crate::tree::fit_to_clients(&mut app, None);
+                    hook_event = Some("client-detached");
+                }
+                CtrlReq::ClientRegister(id, remote, directives, low_bandwidth, pipe, last_session) => {
+                    app.clients.push(crate::types::ClientConn {
+                        id, remote, last_input: Instant::now(), idle_warned: false, directives, low_bandwidth,
+                        no_status: false, size: (0, 0), prefix_armed: false, suspended: false, attached_at: chrono::Local::now(), pipe,
+                        last_session, push: None, frame_version: 0,
+                    });
+                }
+                CtrlReq::ClientUnregister(id) => {
+                    // The others may fit a larger window now
+                    app.clients.retain(|c| c.id != id);
+                    crate::tree::fit_to_clients(&mut app, None);
+                    state_dirty = true;
+                }
+                CtrlReq::DetachClient(requester, target, all_but, resp) => {
+                    match helpers::clients_to_detach(&app, requester, target.as_deref(), all_but) {
+                        Ok(ids) => {
+                            // Gone from the set now; their connections end when
+                            // they leave or are cut off (ClientConn::detach)
+                            for id in ids {
+                                if let Some(pos) = app.clients.iter().position(|c| c.id == id) { app.clients.remove(pos).detach(); }
+                            }
+                            crate::tree::fit_to_clients(&mut app, None);
+                            state_dirty = true;
+                            let _ = resp.send(Ok(()));
+                        }
+                        Err(e) => { let _ = resp.send(Err(e)); }
+                    }
+                }
+                CtrlReq::ClientActivity(id) => {
+                    if let Some(c) = app.clients.iter_mut().find(|c| c.id == id) { c.last_input = Instant::now(); }
+                    if app.window_size == "latest" { crate::tree::fit_to_clients(&mut app, None); }
+                }
                 CtrlReq::DumpLayout(resp) => {
                     let json = dump_layout_json(&mut app)?;
                     let _ = resp.send(json);
                 }
-                CtrlReq::DumpState(resp, allow_nc) => {
+                CtrlReq::DumpState(resp, allow_nc, kind, client) => {
                     // ── Automatic rename: resolve foreground process ──
                     {
                         let in_copy = matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. });
-                        if app.automatic_rename && !in_copy {
-                            for win in app.windows.iter_mut() {
-                                if win.manual_rename { continue; }
+                        if !in_copy {
+                            let renamed: Vec<bool> = app.windows.iter().map(|w| crate::scoped::automatic_rename(&app, w)).collect();
+                            let mut due: Vec<usize> = Vec::new();
+                            for (wi, win) in app.windows.iter_mut().enumerate() {
+                                if win.manual_rename || !renamed[wi] { continue; }
                                 if let Some(p) = crate::tree::active_pane_mut(&mut win.root, &win.active_path) {
                                     if p.dead { continue; }
                                     if p.last_title_check.elapsed().as_millis() < 1000 { continue; }
@@ -336,19 +417,32 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                                     if p.child_pid.is_none() {
                                         p.child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*p.child) };
                                     }
-                                    let new_name = if let Some(pid) = p.child_pid {
+                                    due.push(wi);
+                                }
+                            }
+                            // The default format is just the process name; anything
+                            // else goes through the format engine for that window
+                            let custom = app.automatic_rename_format != crate::pane::DEFAULT_AUTOMATIC_RENAME_FORMAT;
+                            for wi in due {
+                                let new_name = if custom {
+                                    crate::format::expand_format_for_window(&app.automatic_rename_format, &app, wi)
+                                } else {
+                                    let win = &app.windows[wi];
+                                    let Some(p) = crate::tree::active_pane(&win.root, &win.active_path) else { continue };
+                                    if let Some(pid) = p.child_pid {
                                         crate::platform::process_info::get_foreground_process_name(pid)
                                             .unwrap_or_else(|| "shell".into())
                                     } else if !p.title.is_empty() {
                                         p.title.clone()
                                     } else {
                                         continue;
-                                    };
-                                    if !new_name.is_empty() && win.name != new_name {
-                                        win.name = new_name;
-                                        meta_dirty = true;
-                                        state_dirty = true;
                                     }
+                                };
+                                let win = &mut app.windows[wi];
+                                if !new_name.is_empty() && win.name != new_name {
+                                    win.name = new_name;
+                                    meta_dirty = true;
+                                    state_dirty = true;
                                 }
                             }
                         }
@@ -357,17 +451,37 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     // instead of cloning 50-100KB of JSON.
                     // Only allowed for persistent connections that already have
                     // the previous frame; one-shot connections always need full state.
+                    // Plain frames bypass the cache; their connection drops repeats.
+                    let plain = kind.is_plain();
+                    // Inside a batch, clients keep their last frame; the
+                    // commit marks everything dirty so they get one new one.
+                    if frame_batch.is_some() && allow_nc && !cached_dump_state.is_empty() {
+                        let _ = resp.send("NC".to_string());
+                        continue;
+                    }
                     if allow_nc
+                        && !plain
                         && !state_dirty
                         && !cached_dump_state.is_empty()
                         && cached_data_version == combined_data_version(&app)
                     {
-                        let _ = resp.send("NC".to_string());
-                        continue;
+                        // With several clients attached, another client may have
+                        // consumed the dirty frame (e.g. a live border drag), so
+                        // this one may not have it yet: resend the cached frame
+                        // (or build one, if it was cached in the other encoding).
+                        if helpers::client_has_frame(&app, client, cached_frame_version) {
+                            let _ = resp.send("NC".to_string());
+                            continue;
+                        }
+                        if cached_dump_kind == kind {
+                            helpers::note_frame_sent(&mut app, client, cached_frame_version);
+                            let _ = resp.send(cached_dump_state.clone());
+                            continue;
+                        }
                     }
                     // Rebuild metadata cache if structural changes happened.
                     if meta_dirty {
-                        cached_windows_json = list_windows_json_with_tabs(&app)?;
+                        cached_windows_json = list_windows_json_with_tabs(&app, &mut format_cache)?;
                         cached_tree_json = list_tree_json(&app)?;
                         cached_prefix_str = format_key_binding(&app.prefix_key);
                         cached_prefix2_str = app.prefix2_key.as_ref().map(|k| format_key_binding(k)).unwrap_or_default();
@@ -378,22 +492,34 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         meta_dirty = false;
                     }
                     let _t_layout = std::time::Instant::now();
-                    let layout_json = dump_layout_json_fast(&mut app)?;
+                    let layout_json = dump_layout_json_fast(&mut app, kind)?;
                     let _layout_ms = _t_layout.elapsed().as_micros();
                     combined_buf.clear();
                     let ss_escaped = json_escape_string(&cached_status_style);
-                    let sl_expanded = json_escape_string(&expand_format(&app.status_left, &app));
-                    let sr_expanded = json_escape_string(&expand_format(&app.status_right, &app));
+                    let sl_expanded = json_escape_string(&format_cache.expand(&app.status_left, &app, app.active_idx));
+                    let sr_expanded = json_escape_string(&format_cache.expand(&app.status_right, &app, app.active_idx));
                     let pbs_escaped = json_escape_string(&app.pane_border_style);
                     let pabs_escaped = json_escape_string(&app.pane_active_border_style);
+                    let pbl_escaped = json_escape_string(&app.pane_border_lines);
+                    let pbi_escaped = json_escape_string(&app.pane_border_indicators);
+                    let pbst_escaped = json_escape_string(&app.pane_border_status);
                     let wsf_escaped = json_escape_string(&app.window_status_format);
                     let wscf_escaped = json_escape_string(&app.window_status_current_format);
                     let wss_escaped = json_escape_string(&app.window_status_separator);
                     let ws_style_escaped = json_escape_string(&app.window_status_style);
                     let wsc_style_escaped = json_escape_string(&app.window_status_current_style);
                     let mode_style_escaped = json_escape_string(&app.mode_style);
+                    let match_style_escaped = json_escape_string(&app.copy_mode_match_style);
+                    let current_match_style_escaped = json_escape_string(&app.copy_mode_current_match_style);
                     let status_position_escaped = json_escape_string(&app.status_position);
                     let status_justify_escaped = json_escape_string(&app.status_justify);
+                    // prefix-indicator off is sent as null
+                    let pis_escaped = json_escape_string(&app.prefix_indicator_style);
+                    let key_table_escaped = json_escape_string(app.current_key_table.as_deref().unwrap_or(""));
+                    let title_escaped = if app.set_titles {
+                        let fmt = if app.set_titles_string.is_empty() { "#S:#I:#W" } else { &app.set_titles_string };
+                        json_escape_string(&expand_format(fmt, &app))
+                    } else { String::new() };
                     // Build status_format JSON array for multi-line status bar
                     let status_format_json = {
                         let mut sf = String::from("[");
@@ -407,15 +533,24 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         sf
                     };
                     let _ = std::fmt::Write::write_fmt(&mut combined_buf, format_args!(
-                        "{{\"layout\":{},\"windows\":{},\"prefix\":\"{}\",\"prefix2\":\"{}\",\"tree\":{},\"base_index\":{},\"prediction_dimming\":{},\"status_style\":\"{}\",\"status_left\":\"{}\",\"status_right\":\"{}\",\"pane_border_style\":\"{}\",\"pane_active_border_style\":\"{}\",\"wsf\":\"{}\",\"wscf\":\"{}\",\"wss\":\"{}\",\"ws_style\":\"{}\",\"wsc_style\":\"{}\",\"clock_mode\":{},\"bindings\":{},\"status_left_length\":{},\"status_right_length\":{},\"status_lines\":{},\"status_format\":{},\"mode_style\":\"{}\",\"status_position\":\"{}\",\"status_justify\":\"{}\"}}",
-                        layout_json, cached_windows_json, cached_prefix_str, cached_prefix2_str, cached_tree_json, cached_base_index, cached_pred_dim, ss_escaped, sl_expanded, sr_expanded, pbs_escaped, pabs_escaped, wsf_escaped, wscf_escaped, wss_escaped, ws_style_escaped, wsc_style_escaped,
+                        "{{\"layout\":{},\"windows\":{},\"prefix\":\"{}\",\"prefix2\":\"{}\",\"tree\":{},\"base_index\":{},\"prediction_dimming\":{},\"status_style\":\"{}\",\"status_left\":\"{}\",\"status_right\":\"{}\",\"pane_border_style\":\"{}\",\"pane_active_border_style\":\"{}\",\"pane_border_lines\":\"{}\",\"pane_border_indicators\":\"{}\",\"pane_border_status\":\"{}\",\"wsf\":\"{}\",\"wscf\":\"{}\",\"wss\":\"{}\",\"ws_style\":\"{}\",\"wsc_style\":\"{}\",\"clock_mode\":{},\"bindings\":{},\"status_left_length\":{},\"status_right_length\":{},\"status_lines\":{},\"status_format\":{},\"mode_style\":\"{}\",\"copy_mode_match_style\":\"{}\",\"copy_mode_current_match_style\":\"{}\",\"status_position\":\"{}\",\"status_justify\":\"{}\",\"prefix_indicator\":{},\"key_table\":\"{}\",\"local_echo\":\"{}\",\"title\":\"{}\",\"double_click_ms\":{},\"repeat_time\":{},\"prefix_timeout\":{},\"confirm_kill\":{},\"root_any_binding\":{},\"right_click_paste\":{},\"popup\":{}}}",
+                        layout_json, cached_windows_json, cached_prefix_str, cached_prefix2_str, cached_tree_json, cached_base_index, cached_pred_dim, ss_escaped, sl_expanded, sr_expanded, pbs_escaped, pabs_escaped, pbl_escaped, pbi_escaped, pbst_escaped, wsf_escaped, wscf_escaped, wss_escaped, ws_style_escaped, wsc_style_escaped,
                         matches!(app.mode, Mode::ClockMode), cached_bindings_json,
-                        app.status_left_length, app.status_right_length, app.status_lines, status_format_json,
-                        mode_style_escaped, status_position_escaped, status_justify_escaped,
+                        app.status_left_length, app.status_right_length, if app.status_visible { app.status_lines } else { 0 }, status_format_json,
+                        mode_style_escaped, match_style_escaped, current_match_style_escaped, status_position_escaped, status_justify_escaped,
+                        if app.prefix_indicator { format!("\"{}\"", pis_escaped) } else { "null".to_string() }, key_table_escaped,
+                        app.local_echo, title_escaped, crate::window_ops::double_click_ms(&app), app.repeat_time_ms, app.prefix_timeout_ms, app.confirm_kill, app.root_any_binding, crate::window_ops::right_click_paste(&app), crate::popup::frame_json(&app),
                     ));
+                    if plain {
+                        let _ = resp.send(combined_buf.clone());
+                        continue;
+                    }
                     cached_dump_state.clear();
                     cached_dump_state.push_str(&combined_buf);
                     cached_data_version = combined_data_version(&app);
+                    cached_dump_kind = kind;
+                    cached_frame_version += 1;
+                    helpers::note_frame_sent(&mut app, client, cached_frame_version);
                     state_dirty = false;
                     // Timing log: dump-state build time
                     if std::env::var("PSMUX_LATENCY_LOG").unwrap_or_default() == "1" {
@@ -432,9 +567,29 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     let _ = resp.send(combined_buf.clone());
                 }
+                CtrlReq::SendText(s) if crate::window_ops::typing_to_dead_pane(&app) => {
+                    if s == "x" { let _ = kill_active_pane(&mut app); resize_all_panes(&mut app); meta_dirty = true; hook_event = Some("after-kill-pane"); }
+                }
+                CtrlReq::SendKey(k) if crate::window_ops::typing_to_dead_pane(&app) => {
+                    if k.eq_ignore_ascii_case("enter") {
+                        match respawn_active_pane(&mut app, Some(&*pty_system), false) {
+                            Ok(()) => { state_dirty = true; hook_event = Some("after-respawn-pane"); }
+                            Err(e) => { app.pending_message = Some(e.to_string()); }
+                        }
+                    }
+                }
+                // An open popup takes everything typed; send-keys still goes to panes
+                CtrlReq::SendText(s) | CtrlReq::SendPaste(s) if crate::popup::is_open(&app) => { crate::popup::text(&mut app, &s); state_dirty = true; }
+                CtrlReq::SendKey(k) if crate::popup::is_open(&app) => { crate::popup::key(&mut app, &k); state_dirty = true; }
                 CtrlReq::SendText(s) => { send_text_to_active(&mut app, &s)?; echo_pending_until = Some(Instant::now()); }
                 CtrlReq::SendKey(k) => { send_key_to_active(&mut app, &k)?; echo_pending_until = Some(Instant::now()); }
                 CtrlReq::SendPaste(s) => { send_text_to_active(&mut app, &s)?; echo_pending_until = Some(Instant::now()); }
+                CtrlReq::PasteText(s, sync) => {
+                    let broadcast = crate::input::paste_broadcasts(&app, sync);
+                    crate::input::paste_to_panes(&mut app, &s, broadcast);
+                    echo_pending_until = Some(Instant::now());
+                }
+                CtrlReq::SendBytes(b, pid) => { helpers::at_pane(&mut app, pid, |app| crate::input::send_bytes_to_active(app, &b)); echo_pending_until = Some(Instant::now()); }
                 CtrlReq::ZoomPane => { toggle_zoom(&mut app); hook_event = Some("after-resize-pane"); }
                 CtrlReq::CopyEnter => { enter_copy_mode(&mut app); }
                 CtrlReq::CopyEnterPageUp => {
@@ -447,10 +602,39 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                 CtrlReq::ClockMode => { app.mode = Mode::ClockMode; }
                 CtrlReq::CopyMove(dx, dy) => { move_copy_cursor(&mut app, dx, dy); }
                 CtrlReq::CopyAnchor => { if let Some((r,c)) = current_prompt_pos(&mut app) { app.copy_anchor = Some((r,c)); app.copy_anchor_scroll_offset = app.copy_scroll_offset; app.copy_pos = Some((r,c)); } }
-                CtrlReq::CopyYank => { let _ = yank_selection(&mut app); exit_copy_mode(&mut app); }
-                CtrlReq::ClientSize(w, h) => { 
-                    app.last_window_area = Rect { x: 0, y: 0, width: w, height: h }; 
-                    resize_all_panes(&mut app);
+                CtrlReq::CopyYank => { let _ = yank_to_register(&mut app); exit_copy_mode(&mut app); }
+                CtrlReq::ChildExited(pane_id, pid, status) => {
+                    for w in app.windows.iter_mut() {
+                        tree::for_each_pane_mut(&mut w.root, &mut |p| {
+                            if p.id == pane_id && p.child_pid == Some(pid) { p.exit_status = status; }
+                        });
+                    }
+                    reap_schedule.child_exited();
+                }
+                CtrlReq::ClientPrefix(client, armed) => {
+                    if let Some(c) = app.clients.iter_mut().find(|c| Some(c.id) == client) { c.prefix_armed = armed; }
+                    state_dirty = true;
+                }
+                CtrlReq::ClientSubscribe(client, kind) => {
+                    if let Some(c) = app.clients.iter_mut().find(|c| Some(c.id) == client) { c.push = kind; }
+                    // The subscriber's first frame
+                    state_dirty = true;
+                }
+                CtrlReq::ClientSuspend(client, on) => {
+                    if let Some(c) = app.clients.iter_mut().find(|c| Some(c.id) == client) { c.suspended = on; }
+                }
+                CtrlReq::ClientSize(client, w, h) => {
+                    let (tracked, resized) = match app.clients.iter_mut().find(|c| Some(c.id) == client) {
+                        Some(c) => { let resized = c.size != (w, h); c.size = (w, h); (true, resized) }
+                        None => (false, false),
+                    };
+                    if resized {
+                        let mut ctx = crate::hooks::active_context(&app);
+                        ctx.extend([("client_width".into(), w.to_string()), ("client_height".into(), h.to_string())]);
+                        crate::hooks::queue(&mut app, "client-resized", ctx);
+                    }
+                    crate::tree::fit_to_clients(&mut app, (!tracked).then_some((w, h)));
+                    state_dirty = true;
                 }
                 CtrlReq::FocusPaneCmd(pid) => {
                     let old_path = app.windows[app.active_idx].active_path.clone();
@@ -459,364 +643,147 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     meta_dirty = true;
                 }
                 CtrlReq::FocusWindowCmd(wid) => { switch_with_copy_save(&mut app, |app| { if let Some(idx) = find_window_index_by_id(app, wid) { app.active_idx = idx; } }); resize_all_panes(&mut app); meta_dirty = true; }
-                CtrlReq::MouseDown(x,y) => { if app.mouse_enabled { remote_mouse_down(&mut app, x, y); state_dirty = true; meta_dirty = true; } }
-                CtrlReq::MouseDownRight(x,y) => { if app.mouse_enabled { remote_mouse_button(&mut app, x, y, 2, true); state_dirty = true; } }
-                CtrlReq::MouseDownMiddle(x,y) => { if app.mouse_enabled { remote_mouse_button(&mut app, x, y, 1, true); state_dirty = true; } }
-                CtrlReq::MouseDrag(x,y) => { if app.mouse_enabled { remote_mouse_drag(&mut app, x, y); state_dirty = true; } }
+                // Mouse over an open popup is its command's, in popup cells
+                CtrlReq::MouseDown(x, y) if crate::popup::is_open(&app) => {
+                    if app.mouse_enabled { crate::popup::mouse(&mut app, 0, x, y, true); state_dirty = true; }
+                }
+                CtrlReq::MouseDownRight(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 2, x, y, true); } }
+                CtrlReq::MouseDownMiddle(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 1, x, y, true); } }
+                CtrlReq::MouseDrag(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 32, x, y, true); } }
+                CtrlReq::MouseUp(x, y) | CtrlReq::MouseSelect(x, y, _) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 0, x, y, false); } }
+                CtrlReq::MouseUpRight(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 2, x, y, false); } }
+                CtrlReq::MouseUpMiddle(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 1, x, y, false); } }
+                CtrlReq::MouseMove(..) if crate::popup::is_open(&app) => {}
+                CtrlReq::ScrollUp(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 64, x, y, true); } }
+                CtrlReq::ScrollDown(x, y) if crate::popup::is_open(&app) => { if app.mouse_enabled { crate::popup::mouse(&mut app, 65, x, y, true); } }
+                CtrlReq::MouseDown(x,y) => { app.mouse_at = (x, y); if app.mouse_enabled { remote_mouse_down(&mut app, x, y); state_dirty = true; meta_dirty = true; } }
+                CtrlReq::MouseDownRight(x,y) => {
+                    app.mouse_at = (x, y);
+                    // A right-click the client passed on opens a context menu
+                    if app.mouse_enabled {
+                        if let Some(menu) = crate::menu::at_pointer(&mut app, x, y) { let _ = crate::menu::show(&app, &menu); }
+                        state_dirty = true;
+                    }
+                }
+                CtrlReq::MouseDownMiddle(x,y) => { app.mouse_at = (x, y); if app.mouse_enabled { let area = app.last_window_area; crate::window_ops::middle_click(&mut app, area, x, y, true); state_dirty = true; meta_dirty = true; } }
+                CtrlReq::MouseDrag(x,y) => { if app.mouse_enabled { remote_mouse_drag(&mut app, x, y); state_dirty = true; if app.drag.is_some() { meta_dirty = true; } } }
                 CtrlReq::MouseUp(x,y) => { if app.mouse_enabled { remote_mouse_up(&mut app, x, y); state_dirty = true; } }
+                CtrlReq::MouseSelect(x,y,clicks) => { if app.mouse_enabled { crate::window_ops::remote_mouse_select(&mut app, x, y, clicks); state_dirty = true; meta_dirty = true; } }
                 CtrlReq::MouseUpRight(x,y) => { if app.mouse_enabled { remote_mouse_button(&mut app, x, y, 2, false); state_dirty = true; } }
-                CtrlReq::MouseUpMiddle(x,y) => { if app.mouse_enabled { remote_mouse_button(&mut app, x, y, 1, false); state_dirty = true; } }
+                CtrlReq::MouseUpMiddle(x,y) => { if app.mouse_enabled { let area = app.last_window_area; crate::window_ops::middle_click(&mut app, area, x, y, false); state_dirty = true; } }
                 CtrlReq::MouseMove(x,y) => { if app.mouse_enabled { remote_mouse_motion(&mut app, x, y); } }
                 CtrlReq::ScrollUp(x, y) => { if app.mouse_enabled { remote_scroll_up(&mut app, x, y); state_dirty = true; } }
                 CtrlReq::ScrollDown(x, y) => { if app.mouse_enabled { remote_scroll_down(&mut app, x, y); state_dirty = true; } }
                 CtrlReq::NextWindow => { if !app.windows.is_empty() { switch_with_copy_save(&mut app, |app| { app.last_window_idx = app.active_idx; app.active_idx = (app.active_idx + 1) % app.windows.len(); }); resize_all_panes(&mut app); } meta_dirty = true; hook_event = Some("after-select-window"); }
                 CtrlReq::PrevWindow => { if !app.windows.is_empty() { switch_with_copy_save(&mut app, |app| { app.last_window_idx = app.active_idx; app.active_idx = (app.active_idx + app.windows.len() - 1) % app.windows.len(); }); resize_all_panes(&mut app); } meta_dirty = true; hook_event = Some("after-select-window"); }
-                CtrlReq::RenameWindow(name) => { let win = &mut app.windows[app.active_idx]; win.name = name; win.manual_rename = true; meta_dirty = true; hook_event = Some("after-rename-window"); }
+                CtrlReq::RenameWindow(name, resp, pid) => {
+                    let pos = helpers::target_window_pos(&app, pid);
+                    match helpers::check_window_name(&app, &name, Some(pos)) {
+                        Ok(()) => {
+                            let win = &mut app.windows[pos]; win.name = name; win.manual_rename = true; meta_dirty = true; hook_event = Some("after-rename-window");
+                            if let Some(resp) = resp { let _ = resp.send(String::new()); }
+                        }
+                        Err(e) => match resp {
+                            Some(resp) => { let _ = resp.send(format!("psmux: rename-window: {e}\n")); }
+                            None => { app.pending_message = Some(e); }
+                        },
+                    }
+                }
+                CtrlReq::SaveSession(resp) => {
+                    let (err, note) = match crate::resurrect::save_to(&crate::resurrect::snapshot_dir(), &app) {
+                        Ok(path) => (String::new(), format!("session saved to {}", path.display())),
+                        Err(e) => (format!("psmux: save-session: {e}\n"), format!("save-session: {e}")),
+                    };
+                    match resp {
+                        Some(resp) => { let _ = resp.send(err); }
+                        None => { app.pending_message = Some(note); }
+                    }
+                }
+                CtrlReq::RestoreSession(name, resp) => {
+                    let dir = crate::resurrect::snapshot_dir();
+                    // Without a name: this session's own snapshot, else the latest
+                    let name = name.or_else(|| dir.join(format!("{}.json", app.session_name)).is_file().then(|| app.session_name.clone()));
+                    let restored = crate::resurrect::load_from(&dir, name.as_deref())
+                        .and_then(|snap| crate::resurrect::restore(&mut app, &*pty_system, &snap).map(|n| (n, snap.session)));
+                    let (err, note) = match restored {
+                        Ok((n, from)) => (String::new(), format!("restored {} window{} from {}", n, if n == 1 { "" } else { "s" }, from)),
+                        Err(e) => (format!("psmux: restore-session: {e}\n"), format!("restore-session: {e}")),
+                    };
+                    match resp {
+                        Some(resp) => { let _ = resp.send(err); }
+                        None => { app.pending_message = Some(note); }
+                    }
+                    state_dirty = true; meta_dirty = true;
+                }
                 CtrlReq::ListWindows(resp) => { let json = list_windows_json(&app)?; let _ = resp.send(json); }
                 CtrlReq::ListWindowsTmux(resp) => { let text = list_windows_tmux(&app); let _ = resp.send(text); }
                 CtrlReq::ListWindowsFormat(resp, fmt) => { let text = format_list_windows(&app, &fmt); let _ = resp.send(text); }
                 CtrlReq::ListTree(resp) => { let json = list_tree_json(&app)?; let _ = resp.send(json); }
-                CtrlReq::ToggleSync => { app.sync_input = !app.sync_input; }
+                CtrlReq::ChooseTree(args, windows_only, resp) => {
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                    let _ = resp.send(crate::chooser::run(&app, &args, windows_only));
+                }
+                CtrlReq::ToggleSync => {
+                    let on = crate::scoped::active_synchronized(&app);
+                    if let Some(win) = app.windows.get_mut(app.active_idx) { win.options.synchronize_panes = Some(!on); }
+                }
                 CtrlReq::SetPaneTitle(title) => {
                     let win = &mut app.windows[app.active_idx];
                     if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) { p.title = title; }
                 }
-                CtrlReq::SendKeys(keys, literal) => {
-                    let in_copy = matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. });
-                    if in_copy {
-                        // In copy/search mode — route through mode-aware handlers
-                        if literal {
-                            send_text_to_active(&mut app, &keys)?;
-                        } else {
-                            let parts: Vec<&str> = keys.split_whitespace().collect();
-                            for key in parts.iter() {
-                                let key_upper = key.to_uppercase();
-                                let normalized = match key_upper.as_str() {
-                                    "ENTER" => "enter",
-                                    "TAB" => "tab",
-                                    "BTAB" | "BACKTAB" => "btab",
-                                    "ESCAPE" | "ESC" => "esc",
-                                    "SPACE" => "space",
-                                    "BSPACE" | "BACKSPACE" => "backspace",
-                                    "UP" => "up",
-                                    "DOWN" => "down",
-                                    "RIGHT" => "right",
-                                    "LEFT" => "left",
-                                    "HOME" => "home",
-                                    "END" => "end",
-                                    "PAGEUP" | "PPAGE" => "pageup",
-                                    "PAGEDOWN" | "NPAGE" => "pagedown",
-                                    "DELETE" | "DC" => "delete",
-                                    "INSERT" | "IC" => "insert",
-                                    _ => "",
-                                };
-                                if !normalized.is_empty() {
-                                    send_key_to_active(&mut app, normalized)?;
-                                } else if key_upper.starts_with("C-") || key_upper.starts_with("M-") || (key_upper.starts_with("F") && key_upper.len() >= 2 && key_upper[1..].chars().all(|c| c.is_ascii_digit())) {
-                                    send_key_to_active(&mut app, &key.to_lowercase())?;
-                                } else {
-                                    // Plain text char — route through send_text_to_active (handles copy mode chars)
-                                    send_text_to_active(&mut app, key)?;
-                                }
-                            }
-                        }
-                    } else if literal {
-                        send_text_to_active(&mut app, &keys)?;
-                    } else {
-                        let parts: Vec<&str> = keys.split_whitespace().collect();
-                        for (i, key) in parts.iter().enumerate() {
-                            let key_upper = key.to_uppercase();
-                            let _is_special = matches!(key_upper.as_str(), 
-                                "ENTER" | "TAB" | "BTAB" | "BACKTAB" | "ESCAPE" | "ESC" | "SPACE" | "BSPACE" | "BACKSPACE" |
-                                "UP" | "DOWN" | "RIGHT" | "LEFT" | "HOME" | "END" |
-                                "PAGEUP" | "PPAGE" | "PAGEDOWN" | "NPAGE" | "DELETE" | "DC" | "INSERT" | "IC" |
-                                "F1" | "F2" | "F3" | "F4" | "F5" | "F6" | "F7" | "F8" | "F9" | "F10" | "F11" | "F12"
-                            ) || key_upper.starts_with("C-") || key_upper.starts_with("M-");
-                            
-                            match key_upper.as_str() {
-                                "ENTER" => send_text_to_active(&mut app, "\r")?,
-                                "TAB" => send_text_to_active(&mut app, "\t")?,
-                                "BTAB" | "BACKTAB" => send_text_to_active(&mut app, "\x1b[Z")?,
-                                "ESCAPE" | "ESC" => send_text_to_active(&mut app, "\x1b")?,
-                                "SPACE" => send_text_to_active(&mut app, " ")?,
-                                "BSPACE" | "BACKSPACE" => send_text_to_active(&mut app, "\x7f")?,
-                                "UP" => send_text_to_active(&mut app, "\x1b[A")?,
-                                "DOWN" => send_text_to_active(&mut app, "\x1b[B")?,
-                                "RIGHT" => send_text_to_active(&mut app, "\x1b[C")?,
-                                "LEFT" => send_text_to_active(&mut app, "\x1b[D")?,
-                                "HOME" => send_text_to_active(&mut app, "\x1b[H")?,
-                                "END" => send_text_to_active(&mut app, "\x1b[F")?,
-                                "PAGEUP" | "PPAGE" => send_text_to_active(&mut app, "\x1b[5~")?,
-                                "PAGEDOWN" | "NPAGE" => send_text_to_active(&mut app, "\x1b[6~")?,
-                                "DELETE" | "DC" => send_text_to_active(&mut app, "\x1b[3~")?,
-                                "INSERT" | "IC" => send_text_to_active(&mut app, "\x1b[2~")?,
-                                "F1" => send_text_to_active(&mut app, "\x1bOP")?,
-                                "F2" => send_text_to_active(&mut app, "\x1bOQ")?,
-                                "F3" => send_text_to_active(&mut app, "\x1bOR")?,
-                                "F4" => send_text_to_active(&mut app, "\x1bOS")?,
-                                "F5" => send_text_to_active(&mut app, "\x1b[15~")?,
-                                "F6" => send_text_to_active(&mut app, "\x1b[17~")?,
-                                "F7" => send_text_to_active(&mut app, "\x1b[18~")?,
-                                "F8" => send_text_to_active(&mut app, "\x1b[19~")?,
-                                "F9" => send_text_to_active(&mut app, "\x1b[20~")?,
-                                "F10" => send_text_to_active(&mut app, "\x1b[21~")?,
-                                "F11" => send_text_to_active(&mut app, "\x1b[23~")?,
-                                "F12" => send_text_to_active(&mut app, "\x1b[24~")?,
-                                s if s.starts_with("C-M-") || s.starts_with("C-m-") => {
-                                    if let Some(c) = key.chars().nth(4) {
-                                        let ctrl = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a').wrapping_add(1);
-                                        send_text_to_active(&mut app, &format!("\x1b{}", ctrl as char))?;
-                                    }
-                                }
-                                s if s.starts_with("C-") => {
-                                    if let Some(c) = s.chars().nth(2) {
-                                        let ctrl = (c.to_ascii_lowercase() as u8).wrapping_sub(b'a').wrapping_add(1);
-                                        send_text_to_active(&mut app, &String::from(ctrl as char))?;
-                                    }
-                                }
-                                s if s.starts_with("M-") => {
-                                    if let Some(c) = key.chars().nth(2) {
-                                        send_text_to_active(&mut app, &format!("\x1b{}", c))?;
-                                    }
-                                }
-                                _ => {
-                                    send_text_to_active(&mut app, key)?;
-                                    if i + 1 < parts.len() {
-                                        let next_upper = parts[i + 1].to_uppercase();
-                                        let next_is_special = matches!(next_upper.as_str(),
-                                            "ENTER" | "TAB" | "BTAB" | "BACKTAB" | "ESCAPE" | "ESC" | "SPACE" | "BSPACE" | "BACKSPACE" |
-                                            "UP" | "DOWN" | "RIGHT" | "LEFT" | "HOME" | "END" |
-                                            "PAGEUP" | "PPAGE" | "PAGEDOWN" | "NPAGE" | "DELETE" | "DC" | "INSERT" | "IC" |
-                                            "F1" | "F2" | "F3" | "F4" | "F5" | "F6" | "F7" | "F8" | "F9" | "F10" | "F11" | "F12"
-                                        ) || next_upper.starts_with("C-") || next_upper.starts_with("M-");
-                                        if !next_is_special {
-                                            send_text_to_active(&mut app, " ")?;
-                                        }
+                CtrlReq::SendKeys(keys, literal, pid) => {
+                    helpers::at_pane(&mut app, pid, |app| -> io::Result<()> {
+                        let in_copy = matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. });
+                        if in_copy {
+                            // In copy/search mode — route through mode-aware handlers
+                            if literal {
+                                send_text_to_active(app, &keys)?;
+                            } else {
+                                let parts: Vec<&str> = keys.split_whitespace().collect();
+                                for key in parts.iter() {
+                                    let key_upper = key.to_uppercase();
+                                    let normalized = match key_upper.as_str() {
+                                        "ENTER" => "enter",
+                                        "TAB" => "tab",
+                                        "BTAB" | "BACKTAB" => "btab",
+                                        "ESCAPE" | "ESC" => "esc",
+                                        "SPACE" => "space",
+                                        "BSPACE" | "BACKSPACE" => "backspace",
+                                        "UP" => "up",
+                                        "DOWN" => "down",
+                                        "RIGHT" => "right",
+                                        "LEFT" => "left",
+                                        "HOME" => "home",
+                                        "END" => "end",
+                                        "PAGEUP" | "PPAGE" => "pageup",
+                                        "PAGEDOWN" | "NPAGE" => "pagedown",
+                                        "DELETE" | "DC" => "delete",
+                                        "INSERT" | "IC" => "insert",
+                                        _ => "",
+                                    };
+                                    if !normalized.is_empty() {
+                                        send_key_to_active(app, normalized)?;
+                                    } else if key_upper.starts_with("C-") || key_upper.starts_with("M-") || (key_upper.starts_with("F") && key_upper.len() >= 2 && key_upper[1..].chars().all(|c| c.is_ascii_digit())) {
+                                        send_key_to_active(app, &key.to_lowercase())?;
+                                    } else {
+                                        // Plain text char — route through send_text_to_active (handles copy mode chars)
+                                        send_text_to_active(app, key)?;
                                     }
                                 }
                             }
+                        } else if literal {
+                            send_text_to_active(app, &keys)?;
+                        } else {
+                            // Build the whole sequence first so each pane gets it
+                            // in a single write (matters with synchronize-panes).
+                            send_text_to_active(app, &encode_send_keys(&keys))?;
                         }
-                    }
+                        Ok(())
+                    })?;
                     echo_pending_until = Some(Instant::now());
                 }
-                CtrlReq::SendKeysX(cmd) => {
-                    // send-keys -X: dispatch copy-mode commands by name
-                    // This is the primary mechanism used by tmux-yank and other plugins
-                    let in_copy = matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. });
-                    if !in_copy {
-                        // Auto-enter copy mode for commands that require it
-                        enter_copy_mode(&mut app);
-                    }
-                    match cmd.as_str() {
-                        "cancel" => {
-                            app.mode = Mode::Passthrough;
-                            app.copy_anchor = None;
-                            app.copy_pos = None;
-                            app.copy_scroll_offset = 0;
-                            let win = &mut app.windows[app.active_idx];
-                            if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
-                                if let Ok(mut parser) = p.term.lock() {
-                                    parser.screen_mut().set_scrollback(0);
-                                }
-                            }
-                        }
-                        "begin-selection" => {
-                            if let Some((r,c)) = crate::copy_mode::get_copy_pos(&mut app) {
-                                app.copy_anchor = Some((r,c));
-                                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                                app.copy_pos = Some((r,c));
-                                app.copy_selection_mode = crate::types::SelectionMode::Char;
-                            }
-                        }
-                        "select-line" => {
-                            if let Some((r,c)) = crate::copy_mode::get_copy_pos(&mut app) {
-                                app.copy_anchor = Some((r,c));
-                                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                                app.copy_pos = Some((r,c));
-                                app.copy_selection_mode = crate::types::SelectionMode::Line;
-                            }
-                        }
-                        "rectangle-toggle" => {
-                            app.copy_selection_mode = match app.copy_selection_mode {
-                                crate::types::SelectionMode::Rect => crate::types::SelectionMode::Char,
-                                _ => crate::types::SelectionMode::Rect,
-                            };
-                        }
-                        "copy-selection" => {
-                            let _ = yank_selection(&mut app);
-                        }
-                        "copy-selection-and-cancel" => {
-                            let _ = yank_selection(&mut app);
-                            app.mode = Mode::Passthrough;
-                            app.copy_scroll_offset = 0;
-                            app.copy_pos = None;
-                        }
-                        "copy-selection-no-clear" => {
-                            let _ = yank_selection(&mut app);
-                        }
-                        s if s.starts_with("copy-pipe-and-cancel") || s.starts_with("copy-pipe") => {
-                            // copy-pipe[-and-cancel] [command] — yank + pipe to command
-                            let _ = yank_selection(&mut app);
-                            // Extract pipe command from argument if present
-                            let cancel = s.contains("cancel");
-                            let pipe_cmd = cmd.strip_prefix("copy-pipe-and-cancel")
-                                .or_else(|| cmd.strip_prefix("copy-pipe"))
-                                .unwrap_or("")
-                                .trim();
-                            if !pipe_cmd.is_empty() {
-                                if let Some(text) = app.paste_buffers.first().cloned() {
-                                    // Pipe yanked text to the command's stdin
-                                    if let Ok(mut child) = std::process::Command::new(if cfg!(windows) { "pwsh" } else { "sh" })
-                                        .args(if cfg!(windows) { vec!["-NoProfile", "-Command", pipe_cmd] } else { vec!["-c", pipe_cmd] })
-                                        .stdin(std::process::Stdio::piped())
-                                        .stdout(std::process::Stdio::null())
-                                        .stderr(std::process::Stdio::null())
-                                        .spawn() {
-                                        if let Some(mut stdin) = child.stdin.take() {
-                                            use std::io::Write;
-                                            let _ = stdin.write_all(text.as_bytes());
-                                        }
-                                        let _ = child.wait();
-                                    }
-                                }
-                            }
-                            if cancel {
-                                app.mode = Mode::Passthrough;
-                                app.copy_scroll_offset = 0;
-                                app.copy_pos = None;
-                            }
-                        }
-                        "cursor-up" => { move_copy_cursor(&mut app, 0, -1); }
-                        "cursor-down" => { move_copy_cursor(&mut app, 0, 1); }
-                        "cursor-left" => { move_copy_cursor(&mut app, -1, 0); }
-                        "cursor-right" => { move_copy_cursor(&mut app, 1, 0); }
-                        "start-of-line" => { crate::copy_mode::move_to_line_start(&mut app); }
-                        "end-of-line" => { crate::copy_mode::move_to_line_end(&mut app); }
-                        "back-to-indentation" => { crate::copy_mode::move_to_first_nonblank(&mut app); }
-                        "next-word" => { crate::copy_mode::move_word_forward(&mut app); }
-                        "previous-word" => { crate::copy_mode::move_word_backward(&mut app); }
-                        "next-word-end" => { crate::copy_mode::move_word_end(&mut app); }
-                        "next-space" => { crate::copy_mode::move_word_forward_big(&mut app); }
-                        "previous-space" => { crate::copy_mode::move_word_backward_big(&mut app); }
-                        "next-space-end" => { crate::copy_mode::move_word_end_big(&mut app); }
-                        "top-line" => { crate::copy_mode::move_to_screen_top(&mut app); }
-                        "middle-line" => { crate::copy_mode::move_to_screen_middle(&mut app); }
-                        "bottom-line" => { crate::copy_mode::move_to_screen_bottom(&mut app); }
-                        "history-top" => { crate::copy_mode::scroll_to_top(&mut app); }
-                        "history-bottom" => { crate::copy_mode::scroll_to_bottom(&mut app); }
-                        "halfpage-up" => {
-                            let half = app.windows.get(app.active_idx)
-                                .and_then(|w| active_pane(&w.root, &w.active_path))
-                                .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                            scroll_copy_up(&mut app, half);
-                        }
-                        "halfpage-down" => {
-                            let half = app.windows.get(app.active_idx)
-                                .and_then(|w| active_pane(&w.root, &w.active_path))
-                                .map(|p| (p.last_rows / 2) as usize).unwrap_or(10);
-                            scroll_copy_down(&mut app, half);
-                        }
-                        "page-up" => { scroll_copy_up(&mut app, 20); }
-                        "page-down" => { scroll_copy_down(&mut app, 20); }
-                        "scroll-up" => { scroll_copy_up(&mut app, 1); }
-                        "scroll-down" => { scroll_copy_down(&mut app, 1); }
-                        "search-forward" | "search-forward-incremental" => {
-                            app.mode = Mode::CopySearch { input: String::new(), forward: true };
-                        }
-                        "search-backward" | "search-backward-incremental" => {
-                            app.mode = Mode::CopySearch { input: String::new(), forward: false };
-                        }
-                        "search-again" => { crate::copy_mode::search_next(&mut app); }
-                        "search-reverse" => { crate::copy_mode::search_prev(&mut app); }
-                        "copy-end-of-line" => { let _ = crate::copy_mode::copy_end_of_line(&mut app); app.mode = Mode::Passthrough; app.copy_scroll_offset = 0; app.copy_pos = None; }
-                        "select-word" => {
-                            // Select the word under cursor
-                            crate::copy_mode::move_word_backward(&mut app);
-                            if let Some((r,c)) = crate::copy_mode::get_copy_pos(&mut app) {
-                                app.copy_anchor = Some((r,c));
-                                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                                app.copy_selection_mode = crate::types::SelectionMode::Char;
-                            }
-                            crate::copy_mode::move_word_end(&mut app);
-                        }
-                        "other-end" => {
-                            if let (Some(a), Some(p)) = (app.copy_anchor, app.copy_pos) {
-                                app.copy_anchor = Some(p);
-                                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                                app.copy_pos = Some(a);
-                            }
-                        }
-                        "clear-selection" => {
-                            app.copy_anchor = None;
-                            app.copy_selection_mode = crate::types::SelectionMode::Char;
-                        }
-                        "append-selection" => {
-                            // Append to existing buffer instead of replacing
-                            let _ = yank_selection(&mut app);
-                            if app.paste_buffers.len() >= 2 {
-                                let appended = format!("{}{}", app.paste_buffers[1], app.paste_buffers[0]);
-                                app.paste_buffers[0] = appended;
-                            }
-                        }
-                        "append-selection-and-cancel" => {
-                            let _ = yank_selection(&mut app);
-                            if app.paste_buffers.len() >= 2 {
-                                let appended = format!("{}{}", app.paste_buffers[1], app.paste_buffers[0]);
-                                app.paste_buffers[0] = appended;
-                            }
-                            app.mode = Mode::Passthrough;
-                            app.copy_scroll_offset = 0;
-                            app.copy_pos = None;
-                        }
-                        "copy-line" => {
-                            // Select entire current line and yank
-                            if let Some((r, _)) = crate::copy_mode::get_copy_pos(&mut app) {
-                                app.copy_anchor = Some((r, 0));
-                                app.copy_anchor_scroll_offset = app.copy_scroll_offset;
-                                app.copy_selection_mode = crate::types::SelectionMode::Line;
-                                let cols = app.windows.get(app.active_idx)
-                                    .and_then(|w| active_pane(&w.root, &w.active_path))
-                                    .map(|p| p.last_cols).unwrap_or(80);
-                                app.copy_pos = Some((r, cols.saturating_sub(1)));
-                                let _ = yank_selection(&mut app);
-                            }
-                            app.mode = Mode::Passthrough;
-                            app.copy_scroll_offset = 0;
-                            app.copy_pos = None;
-                        }
-                        s if s.starts_with("goto-line") => {
-                            // goto-line <N> — jump to line N in scrollback
-                            let n = s.strip_prefix("goto-line").unwrap_or("").trim()
-                                .parse::<u16>().unwrap_or(0);
-                            app.copy_pos = Some((n, 0));
-                        }
-                        "jump-forward" => { app.copy_find_char_pending = Some(0); }
-                        "jump-backward" => { app.copy_find_char_pending = Some(1); }
-                        "jump-to-forward" => { app.copy_find_char_pending = Some(2); }
-                        "jump-to-backward" => { app.copy_find_char_pending = Some(3); }
-                        "jump-again" => {
-                            // Repeat last find-char in same direction
-                            // We'd need to store last char; for now emit the pending
-                        }
-                        "jump-reverse" => {
-                            // Repeat last find-char in reverse direction
-                        }
-                        "next-paragraph" => {
-                            crate::copy_mode::move_next_paragraph(&mut app);
-                        }
-                        "previous-paragraph" => {
-                            crate::copy_mode::move_prev_paragraph(&mut app);
-                        }
-                        "next-matching-bracket" => {
-                            crate::copy_mode::move_matching_bracket(&mut app);
-                        }
-                        "stop-selection" => {
-                            // Keep cursor position but stop extending selection
-                            app.copy_anchor = None;
-                        }
-                        _ => {} // ignore unknown copy-mode commands
-                    }
-                }
+                CtrlReq::SendKeysX(cmd, pid) => { helpers::at_pane(&mut app, pid, |app| run_copy_command(app, &cmd)); }
+                CtrlReq::ResetTerminal(pid) => { helpers::at_pane(&mut app, pid, crate::input::reset_active_terminal); state_dirty = true; }
                 CtrlReq::SelectPane(dir) => {
                     // Auto-unzoom when navigating to another pane (tmux behavior).
                     // For directional nav: unzoom first so compute_rects uses
@@ -859,9 +826,10 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         }
                         "mark" => {
                             // select-pane -m: mark the current pane
-                            let win = &app.windows[app.active_idx];
-                            if let Some(pid) = get_active_pane_id(&win.root, &win.active_path) {
-                                app.marked_pane = Some((app.active_idx, pid));
+                            for w in app.windows.iter_mut() { tree::for_each_pane_mut(&mut w.root, &mut |p| p.marked = false); }
+                            let win = &mut app.windows[app.active_idx];
+                            if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+                                p.marked = true;
                             }
                         }
                         "next" => {
@@ -906,7 +874,7 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         }
                         "unmark" => {
                             // select-pane -M: clear the marked pane
-                            app.marked_pane = None;
+                            for w in app.windows.iter_mut() { tree::for_each_pane_mut(&mut w.root, &mut |p| p.marked = false); }
                         }
                         _ => {}
                     }
@@ -914,9 +882,8 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     hook_event = Some("after-select-pane");
                 }
                 CtrlReq::SelectWindow(idx) => {
-                    if idx >= app.window_base_index {
-                        let internal_idx = idx - app.window_base_index;
-                        if internal_idx < app.windows.len() && internal_idx != app.active_idx {
+                    if let Some(internal_idx) = window_pos_by_index(&app, idx) {
+                        if internal_idx != app.active_idx {
                             switch_with_copy_save(&mut app, |app| {
                                 app.last_window_idx = app.active_idx;
                                 app.active_idx = internal_idx;
@@ -972,11 +939,11 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                             Node::Split { children, .. } => { for c in children { collect_all_panes(c, panes); } }
                         }
                     }
-                    for (wi, win) in app.windows.iter().enumerate() {
+                    for win in app.windows.iter() {
                         let mut panes = Vec::new();
                         collect_all_panes(&win.root, &mut panes);
                         for (id, cols, rows) in panes {
-                            output.push_str(&format!("{}:{}: %{} [{}x{}]\n", app.session_name, wi + app.window_base_index, id, cols, rows));
+                            output.push_str(&format!("{}:{}: %{} [{}x{}]\n", app.session_name, win.index, id, cols, rows));
                         }
                     }
                     let _ = resp.send(output);
@@ -988,9 +955,10 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     let _ = resp.send(lines.join("\n"));
                 }
-                CtrlReq::KillWindow => {
+                CtrlReq::KillWindow(pid) => {
                     if app.windows.len() > 1 {
-                        let mut win = app.windows.remove(app.active_idx);
+                        let pos = helpers::target_window_pos(&app, pid);
+                        let mut win = remove_window(&mut app, pos);
                         kill_all_children(&mut win.root);
                         if app.active_idx >= app.windows.len() { app.active_idx = app.windows.len() - 1; }
                     } else {
@@ -999,45 +967,54 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     hook_event = Some("window-closed");
                 }
-                CtrlReq::KillSession => {
-                    // Kill all child processes in all windows before exiting
-                    for win in app.windows.iter_mut() {
-                        kill_all_children(&mut win.root);
-                    }
-                    // Brief delay to let child processes fully terminate
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let keypath = format!("{}\\.psmux\\{}.key", home, app.port_file_base());
-                    let _ = std::fs::remove_file(&keypath);
-                    std::process::exit(0);
-                }
+                CtrlReq::KillSession => return helpers::shut_down(&mut app),
                 CtrlReq::HasSession(resp) => {
                     let _ = resp.send(true);
                 }
                 CtrlReq::RenameSession(name) => {
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let old_keypath = format!("{}\\.psmux\\{}.key", home, app.port_file_base());
+                    let old_keypath = crate::session::key_file_path(&app.port_file_base());
                     // Compute new key file base with socket_name prefix
                     let new_base = if let Some(ref sn) = app.socket_name {
                         format!("{}__{}" , sn, name)
                     } else {
                         name.clone()
                     };
-                    let new_keypath = format!("{}\\.psmux\\{}.key", home, new_base);
+                    let new_keypath = crate::session::key_file_path(&new_base);
                     if let Ok(key) = std::fs::read_to_string(&old_keypath) {
                         let _ = std::fs::remove_file(&old_keypath);
                         let _ = std::fs::write(&new_keypath, key);
                     }
                     app.session_name = name;
+                    let ctx = crate::hooks::active_context(&app);
+                    crate::hooks::queue(&mut app, "session-renamed", ctx);
                     hook_event = Some("after-rename-session");
                 }
-                CtrlReq::SwapPane(dir) => {
-                    match dir.as_str() {
-                        "U" => { swap_pane(&mut app, FocusDir::Up); }
-                        "D" => { swap_pane(&mut app, FocusDir::Down); }
-                        _ => { swap_pane(&mut app, FocusDir::Down); }
+                CtrlReq::SwapPane(with, keep_focus, resp) => {
+                    let target = match with {
+                        SwapWith::Previous => { swap_pane(&mut app, FocusDir::Up, keep_focus); Ok(None) }
+                        SwapWith::Next => { swap_pane(&mut app, FocusDir::Down, keep_focus); Ok(None) }
+                        SwapWith::Target(spec) => helpers::resolve_target(&app, &crate::cli::parse_target(&spec), &spec).map(|(_, pid)| Some(pid)),
+                        // The mark is used up by the swap; with no mark, the
+                        // next pane as before
+                        SwapWith::Marked => match tree::marked_pane(&app) {
+                            Some((_, pid)) => {
+                                for w in app.windows.iter_mut() { tree::for_each_pane_mut(&mut w.root, &mut |p| p.marked = false); }
+                                Ok(Some(pid))
+                            }
+                            None => { swap_pane(&mut app, FocusDir::Down, keep_focus); Ok(None) }
+                        },
+                    };
+                    match target.and_then(|t| t.map_or(Ok(()), |pid| swap_panes(&mut app, pid, keep_focus))) {
+                        Ok(()) => {
+                            hook_event = Some("after-swap-pane");
+                            meta_dirty = true;
+                            if let Some(resp) = resp { let _ = resp.send(String::new()); }
+                        }
+                        Err(e) => match resp {
+                            Some(resp) => { let _ = resp.send(format!("psmux: swap-pane: {e}\n")); }
+                            None => { app.pending_message = Some(e); }
+                        },
                     }
-                    hook_event = Some("after-swap-pane");
                 }
                 CtrlReq::ResizePane(dir, amount) => {
                     match dir.as_str() {
@@ -1047,42 +1024,61 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     hook_event = Some("after-resize-pane");
                 }
-                CtrlReq::SetBuffer(content) => {
-                    app.paste_buffers.insert(0, content);
-                    if app.paste_buffers.len() > 10 { app.paste_buffers.pop(); }
+                CtrlReq::SetBuffer(name, content, append) => {
+                    if append { app.paste_buffers.append(name.as_deref(), &content); }
+                    else { app.paste_buffers.set(name.as_deref(), content); }
                 }
                 CtrlReq::ListBuffers(resp) => {
                     let mut output = String::new();
-                    for (i, buf) in app.paste_buffers.iter().enumerate() {
-                        let preview: String = buf.chars().take(50).collect();
-                        output.push_str(&format!("buffer{}: {} bytes: \"{}\"\n", i, buf.len(), preview));
+                    for buf in app.paste_buffers.iter() {
+                        output.push_str(&crate::buffers::list_line(buf));
+                        output.push('\n');
                     }
                     let _ = resp.send(output);
                 }
                 CtrlReq::ListBuffersFormat(resp, fmt) => {
                     let mut output = Vec::new();
-                    for (i, _buf) in app.paste_buffers.iter().enumerate() {
+                    for i in 0..app.paste_buffers.len() {
                         set_buffer_idx_override(Some(i));
                         output.push(expand_format(&fmt, &app));
                         set_buffer_idx_override(None);
                     }
                     let _ = resp.send(output.join("\n"));
                 }
-                CtrlReq::ShowBuffer(resp) => {
-                    let content = app.paste_buffers.first().cloned().unwrap_or_default();
+                CtrlReq::ShowBuffer(resp, name) => {
+                    let content = match app.paste_buffers.get(name.as_deref()) {
+                        Some(b) => b.text.clone(),
+                        None => {
+                            if let Some(n) = name { app.pending_message = Some(format!("no buffer {}", n)); }
+                            String::new()
+                        }
+                    };
                     let _ = resp.send(content);
                 }
-                CtrlReq::ShowBufferAt(resp, idx) => {
-                    let content = app.paste_buffers.get(idx).cloned().unwrap_or_default();
-                    let _ = resp.send(content);
+                CtrlReq::ReadClipboard(resp) => {
+                    let _ = resp.send(crate::clipboard::read_system(&app).unwrap_or_default());
                 }
-                CtrlReq::DeleteBuffer => {
-                    if !app.paste_buffers.is_empty() { app.paste_buffers.remove(0); }
+                CtrlReq::DeleteBuffer(name) => {
+                    if let (Err(e), Some(_)) = (app.paste_buffers.delete(name.as_deref()), &name) { app.pending_message = Some(e); }
                 }
-                CtrlReq::DisplayMessage(resp, fmt) => {
-                    let result = expand_format(&fmt, &app);
+                CtrlReq::DisplayMessage(resp, fmt, pid) => {
+                    let result = helpers::at_pane(&mut app, pid, |app| expand_format(&fmt, app));
                     let _ = resp.send(result);
                 }
+                CtrlReq::ShowMessage(text) => { app.pending_message = Some(text); }
+                CtrlReq::DisplayVariables(resp, pid) => {
+                    let _ = resp.send(helpers::at_pane(&mut app, pid, |app| crate::format::list_format_variables(app, app.active_idx)));
+                }
+                CtrlReq::DisplayInput(text, pid) => {
+                    // Shown as pane output, not sent to the program; bare LFs
+                    // get a CR so piped lines start at the left edge
+                    let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+                    if let Some(p) = helpers::target_pane_mut(&mut app, pid) {
+                        if let Ok(mut parser) = p.term.lock() { parser.process(text.as_bytes()); }
+                        p.data_version.fetch_add(1, std::sync::atomic::Ordering::Release);
+                    }
+                    state_dirty = true;
+                }
                 CtrlReq::LastWindow => {
                     if app.windows.len() > 1 && app.last_window_idx < app.windows.len() {
                         switch_with_copy_save(&mut app, |app| {
@@ -1118,54 +1114,48 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     app.mode = Mode::PaneChooser { opened_at: std::time::Instant::now() };
                     state_dirty = true;
                 }
-                CtrlReq::BreakPane => {
-                    break_pane_to_window(&mut app);
+                CtrlReq::BreakPane(detached) => {
+                    break_pane_to_window(&mut app, detached);
                     hook_event = Some("after-break-pane");
                     meta_dirty = true;
                 }
-                CtrlReq::JoinPane(target_win) => {
-                    // Real join-pane: extract active pane from current window and
-                    // graft it as a vertical split into the target window.
-                    let src_idx = app.active_idx;
-                    if target_win < app.windows.len() && target_win != src_idx {
-                        let src_path = app.windows[src_idx].active_path.clone();
-                        let src_root = std::mem::replace(&mut app.windows[src_idx].root,
-                            Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
-                        let (remaining, extracted) = tree::extract_node(src_root, &src_path);
-                        if let Some(pane_node) = extracted {
-                            let src_empty = remaining.is_none();
-                            if let Some(rem) = remaining {
-                                app.windows[src_idx].root = rem;
-                                app.windows[src_idx].active_path = tree::first_leaf_path(&app.windows[src_idx].root);
-                            }
-                            // Adjust target index if source window will be removed
-                            let tgt = if src_empty && target_win > src_idx { target_win - 1 } else { target_win };
-                            if src_empty {
-                                app.windows.remove(src_idx);
-                                if app.active_idx >= app.windows.len() {
-                                    app.active_idx = app.windows.len().saturating_sub(1);
-                                }
-                            }
-                            // Graft pane into target window
-                            if tgt < app.windows.len() {
-                                let tgt_path = app.windows[tgt].active_path.clone();
-                                tree::replace_leaf_with_split(&mut app.windows[tgt].root, &tgt_path, LayoutKind::Vertical, pane_node);
-                                app.active_idx = tgt;
-                            }
-                            resize_all_panes(&mut app);
+                CtrlReq::JoinPane(src, dst, kind, size, keep_focus, resp) => {
+                    // The pane keeps everything attached to it; -s defaults
+                    // to the active pane and -t to the active pane too
+                    let pane_of = |app: &AppState, spec: Option<&String>| match spec {
+                        Some(spec) => helpers::resolve_target(app, &crate::cli::parse_target(spec), spec).map(|(_, pid)| pid),
+                        None => {
+                            let win = &app.windows[app.active_idx];
+                            crate::tree::get_active_pane_id(&win.root, &win.active_path).ok_or_else(|| "no active pane".to_string())
+                        }
+                    };
+                    let joined = pane_of(&app, src.as_ref())
+                        .and_then(|s| pane_of(&app, dst.as_ref()).map(|d| (s, d)))
+                        .and_then(|(s, d)| join_pane(&mut app, s, d, kind, size, keep_focus));
+                    match joined {
+                        Ok(()) => {
                             meta_dirty = true;
                             hook_event = Some("after-join-pane");
-                        } else {
-                            // Extraction failed — restore
-                            if let Some(rem) = remaining {
-                                app.windows[src_idx].root = rem;
-                            }
+                            if let Some(resp) = resp { let _ = resp.send(String::new()); }
                         }
+                        Err(e) => match resp {
+                            Some(resp) => { let _ = resp.send(format!("psmux: join-pane: {e}\n")); }
+                            None => { app.pending_message = Some(e); }
+                        },
                     }
                 }
-                CtrlReq::RespawnPane => {
-                    respawn_active_pane(&mut app, Some(&*pty_system))?;
-                    hook_event = Some("after-respawn-pane");
+                CtrlReq::RespawnPane(kill, resp, pid) => {
+                    match helpers::at_pane(&mut app, pid, |app| respawn_active_pane(app, Some(&*pty_system), kill)) {
+                        Ok(()) => {
+                            state_dirty = true;
+                            hook_event = Some("after-respawn-pane");
+                            if let Some(resp) = resp { let _ = resp.send(String::new()); }
+                        }
+                        Err(e) => match resp {
+                            Some(resp) => { let _ = resp.send(format!("psmux: respawn-pane: {e}\n")); }
+                            None => { app.pending_message = Some(e.to_string()); }
+                        },
+                    }
                 }
                 CtrlReq::BindKey(table_name, key, command, repeat) => {
                     if let Some(kc) = parse_key_string(&key) {
@@ -1186,47 +1176,28 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     meta_dirty = true;
                     state_dirty = true;
                 }
-                CtrlReq::UnbindKey(key) => {
-                    if let Some(kc) = parse_key_string(&key) {
-                        let kc = normalize_key_for_binding(kc);
-                        for table in app.key_tables.values_mut() {
-                            table.retain(|b| b.key != kc);
+                CtrlReq::UnbindKey(table_name, key) => {
+                    match key {
+                        Some(key) => {
+                            if let Some(kc) = parse_key_string(&key) {
+                                crate::config::unbind_key(&mut app, &table_name, Some(kc));
+                            }
                         }
+                        None => crate::config::unbind_key(&mut app, &table_name, None),
                     }
                     meta_dirty = true;
                     state_dirty = true;
                 }
                 CtrlReq::ListKeys(resp) => {
-                    // Build list-keys output from the canonical help module
-                    let user_iter = app.key_tables.iter().flat_map(|(table_name, binds)| {
-                        binds.iter().map(move |bind| {
-                            let key_str = format_key_binding(&bind.key);
-                            let action_str = format_action(&bind.action);
-                            (table_name.as_str(), key_str, action_str, bind.repeat)
-                        })
-                    });
-                    let output = help::build_list_keys_output(user_iter);
-                    let _ = resp.send(output);
+                    let _ = resp.send(crate::config::list_keys(&app));
                 }
                 CtrlReq::SetOption(option, value) => {
                     apply_set_option(&mut app, &option, &value, false);
-                    // Update shared aliases if command-alias changed
-                    if option == "command-alias" {
-                        if let Ok(mut map) = shared_aliases_main.write() {
-                            *map = app.command_aliases.clone();
-                        }
-                    }
                     meta_dirty = true;
                     state_dirty = true;
                 }
                 CtrlReq::SetOptionQuiet(option, value, quiet) => {
                     apply_set_option(&mut app, &option, &value, quiet);
-                    // Update shared aliases if command-alias changed
-                    if option == "command-alias" {
-                        if let Ok(mut map) = shared_aliases_main.write() {
-                            *map = app.command_aliases.clone();
-                        }
-                    }
                     meta_dirty = true;
                     state_dirty = true;
                 }
@@ -1234,23 +1205,50 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     // Reset option to default or remove @user-option
                     if option.starts_with('@') {
                         app.environment.remove(&option);
+                    } else if let Some(index) = crate::alias::parse_option(&option) {
+                        crate::alias::unset(&mut app.command_aliases, index);
                     } else {
                         match option.as_str() {
+                            "update-environment" => { app.environment.remove(&option); }
                             "status-left" => { app.status_left = "psmux:#I".to_string(); }
                             "status-right" => { app.status_right = "#{?window_bigger,[#{window_offset_x}#,#{window_offset_y}] ,}\"#{=21:pane_title}\" %H:%M %d-%b-%y".to_string(); }
                             "mouse" => { app.mouse_enabled = true; }
                             "escape-time" => { app.escape_time_ms = 500; }
-                            "history-limit" => { app.history_limit = 2000; }
+                            "repeat-time" => { app.repeat_time_ms = 500; }
+                            "prefix-timeout" => { app.prefix_timeout_ms = 0; }
+                            "confirm-kill" => { app.confirm_kill = true; }
+                            "history-limit" => if app.history_limit != 2000 { app.history_limit = 2000; crate::copy_mode::apply_history_limit(&mut app); },
+                            "buffer-limit" => { app.paste_buffers.limit = crate::buffers::DEFAULT_LIMIT; }
+                            "local-echo" => { app.local_echo = "off".to_string(); }
+                            "sync-paste" => { app.sync_paste = false; }
                             "display-time" => { app.display_time_ms = 750; }
+                            "pattern-timeout" => { app.pattern_timeout_ms = crate::pattern::DEFAULT_TIMEOUT_MS; }
                             "mode-keys" => { app.mode_keys = "emacs".to_string(); }
                             "status" => { app.status_visible = true; }
                             "status-position" => { app.status_position = "bottom".to_string(); }
                             "status-style" => { app.status_style = String::new(); }
                             "renumber-windows" => { app.renumber_windows = false; }
+                            "idle-window-timeout" => { app.idle_window_timeout = 0; }
+                            "idle-window-action" => { app.idle_window_action = "flag".to_string(); }
                             "remain-on-exit" => { app.remain_on_exit = false; }
                             "automatic-rename" => { app.automatic_rename = true; }
+                            "automatic-rename-format" => { app.automatic_rename_format = crate::pane::DEFAULT_AUTOMATIC_RENAME_FORMAT.to_string(); }
+                            "allow-duplicate-window-names" => { app.allow_duplicate_window_names = true; }
+                            "popup-warm-shell" => { app.popup_warm_shell = true; }
+                            "popup-close-key" => { app.popup_close_key = crate::popup::DEFAULT_CLOSE_KEY.to_string(); }
                             "pane-border-style" => { app.pane_border_style = String::new(); }
                             "pane-active-border-style" => { app.pane_active_border_style = "fg=green".to_string(); }
+                            "pane-border-lines" => { app.pane_border_lines = "single".to_string(); }
+                            "pane-border-indicators" => { app.pane_border_indicators = "colour".to_string(); }
+                            "pane-border-status" => { crate::tree::set_pane_border_status(&mut app, "off"); }
+                            "pane-border-format" => { app.pane_border_format = crate::types::DEFAULT_PANE_BORDER_FORMAT.to_string(); }
+                            "highlight-pattern" => { app.highlight_pattern = String::new(); }
+                            "pane-encoding" => { app.pane_encoding = "utf8".to_string(); crate::encoding::refresh(&app); }
+                            "compat-version" => { app.compat_version = crate::types::TMUX_COMPAT_VERSION.to_string(); }
+                            "copy-mode-match-style" => { app.copy_mode_match_style = "bg=cyan,fg=black".to_string(); }
+                            "copy-mode-current-match-style" => { app.copy_mode_current_match_style = "bg=magenta,fg=black".to_string(); }
+                            "prefix-indicator" => { app.prefix_indicator = true; }
+                            "prefix-indicator-style" => { app.prefix_indicator_style = "reverse".to_string(); }
                             "window-status-format" => { app.window_status_format = "#I:#W#{?window_flags,#{window_flags}, }".to_string(); }
                             "window-status-current-format" => { app.window_status_current_format = "#I:#W#{?window_flags,#{window_flags}, }".to_string(); }
                             "window-status-separator" => { app.window_status_separator = " ".to_string(); }
@@ -1260,25 +1258,36 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         }
                     }
                 }
+                CtrlReq::SetScopedOption(scope, option, value, pid) => {
+                    helpers::at_pane(&mut app, pid, |app| apply_scoped_option(app, scope, &option, value));
+                    state_dirty = true;
+                }
                 CtrlReq::SetOptionAppend(option, value) => {
                     // Append to existing option value
                     if option.starts_with('@') {
                         let existing = app.environment.get(&option).cloned().unwrap_or_default();
                         app.environment.insert(option, format!("{}{}", existing, value));
+                    } else if option == "command-alias" {
+                        crate::alias::append(&mut app.command_aliases, &value);
                     } else {
                         match option.as_str() {
                             "status-left" => { app.status_left.push_str(&value); }
+                            "update-environment" => {
+                                let names = format!("{} {}", crate::environ::update_names(&app).join(" "), value.trim());
+                                app.environment.insert(option, names);
+                            }
                             "status-right" => { app.status_right.push_str(&value); }
                             "status-style" => { app.status_style.push_str(&value); }
                             "pane-border-style" => { app.pane_border_style.push_str(&value); }
                             "pane-active-border-style" => { app.pane_active_border_style.push_str(&value); }
+                            "pane-border-format" => { app.pane_border_format.push_str(&value); }
                             "window-status-format" => { app.window_status_format.push_str(&value); }
                             "window-status-current-format" => { app.window_status_current_format.push_str(&value); }
                             _ => {}
                         }
                     }
                 }
-                CtrlReq::ShowOptions(resp) => {
+                CtrlReq::ShowOptions(resp, scope, pid) => {
                     let mut output = String::new();
                     output.push_str(&format!("prefix {}\n", format_key_binding(&app.prefix_key)));
                     if let Some(ref p2) = app.prefix2_key {
@@ -1287,6 +1296,9 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     output.push_str(&format!("base-index {}\n", app.window_base_index));
                     output.push_str(&format!("pane-base-index {}\n", app.pane_base_index));
                     output.push_str(&format!("escape-time {}\n", app.escape_time_ms));
+                    output.push_str(&format!("repeat-time {}\n", app.repeat_time_ms));
+                    output.push_str(&format!("prefix-timeout {}\n", app.prefix_timeout_ms));
+                    output.push_str(&format!("confirm-kill {}\n", if app.confirm_kill { "on" } else { "off" }));
                     output.push_str(&format!("mouse {}\n", if app.mouse_enabled { "on" } else { "off" }));
                     output.push_str(&format!("status {}\n", if app.status_visible { "on" } else { "off" }));
                     output.push_str(&format!("status-position {}\n", app.status_position));
@@ -1295,13 +1307,37 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     output.push_str(&format!("history-limit {}\n", app.history_limit));
                     output.push_str(&format!("display-time {}\n", app.display_time_ms));
                     output.push_str(&format!("display-panes-time {}\n", app.display_panes_time_ms));
+                    output.push_str(&format!("pattern-timeout {}\n", app.pattern_timeout_ms));
                     output.push_str(&format!("mode-keys {}\n", app.mode_keys));
                     output.push_str(&format!("focus-events {}\n", if app.focus_events { "on" } else { "off" }));
                     output.push_str(&format!("renumber-windows {}\n", if app.renumber_windows { "on" } else { "off" }));
                     output.push_str(&format!("automatic-rename {}\n", if app.automatic_rename { "on" } else { "off" }));
+                    output.push_str(&format!("automatic-rename-format \"{}\"\n", app.automatic_rename_format));
+                    output.push_str(&format!("allow-duplicate-window-names {}\n", if app.allow_duplicate_window_names { "on" } else { "off" }));
+                    output.push_str(&format!("popup-warm-shell {}\n", if app.popup_warm_shell { "on" } else { "off" }));
+                    output.push_str(&format!("popup-close-key {}\n", app.popup_close_key));
                     output.push_str(&format!("monitor-activity {}\n", if app.monitor_activity { "on" } else { "off" }));
+                    output.push_str(&format!("visual-activity {}\n", if app.visual_activity { "on" } else { "off" }));
+                    output.push_str(&format!("monitor-bell {}\n", if app.monitor_bell { "on" } else { "off" }));
+                    output.push_str(&format!("visual-bell {}\n", if app.visual_bell { "on" } else { "off" }));
+                    output.push_str(&format!("bell-action {}\n", app.bell_action));
+                    output.push_str(&format!("monitor-silence {}\n", app.monitor_silence));
+                    output.push_str(&format!("idle-window-timeout {}\n", app.idle_window_timeout));
+                    output.push_str(&format!("idle-window-action {}\n", app.idle_window_action));
+                    output.push_str(&format!("visual-silence {}\n", if app.visual_silence { "on" } else { "off" }));
                     output.push_str(&format!("synchronize-panes {}\n", if app.sync_input { "on" } else { "off" }));
+                    output.push_str(&format!("sync-paste {}\n", if app.sync_paste { "on" } else { "off" }));
                     output.push_str(&format!("remain-on-exit {}\n", if app.remain_on_exit { "on" } else { "off" }));
+                    output.push_str(&format!("client-idle-timeout {}\n", app.client_idle_timeout));
+                    output.push_str(&format!("client-idle-warning {}\n", app.client_idle_warning));
+                    output.push_str(&format!("client-idle-exempt-local {}\n", if app.client_idle_exempt_local { "on" } else { "off" }));
+                    if let Some(ref path) = app.history_file {
+                        output.push_str(&format!("history-file \"{}\"\n", path));
+                    }
+                    output.push_str(&format!("prompt-history-limit {}\n", app.prompt_history_limit));
+                    output.push_str(&format!("buffer-limit {}\n", app.paste_buffers.limit));
+                    output.push_str(&format!("scroll-without-copy-mode {}\n", if app.scroll_without_copy_mode { "on" } else { "off" }));
+                    output.push_str(&format!("scrolled-input {}\n", if app.scrolled_input_block { "block" } else { "snap" }));
                     output.push_str(&format!("set-titles {}\n", if app.set_titles { "on" } else { "off" }));
                     if !app.set_titles_string.is_empty() {
                         output.push_str(&format!("set-titles-string \"{}\"\n", app.set_titles_string));
@@ -1322,6 +1358,14 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     if !app.pane_active_border_style.is_empty() {
                         output.push_str(&format!("pane-active-border-style \"{}\"\n", app.pane_active_border_style));
                     }
+                    output.push_str(&format!("pane-border-lines {}\n", app.pane_border_lines));
+                    output.push_str(&format!("pane-border-indicators {}\n", app.pane_border_indicators));
+                    output.push_str(&format!("pane-border-status {}\n", app.pane_border_status));
+                    output.push_str(&format!("pane-border-format \"{}\"\n", app.pane_border_format));
+                    if !app.highlight_pattern.is_empty() {
+                        output.push_str(&format!("highlight-pattern \"{}\"\n", app.highlight_pattern));
+                    }
+                    output.push_str(&format!("pane-encoding {}\n", app.pane_encoding));
                     if !app.status_style.is_empty() {
                         output.push_str(&format!("status-style \"{}\"\n", app.status_style));
                     }
@@ -1333,6 +1377,8 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     output.push_str(&format!("status-interval {}\n", app.status_interval));
                     output.push_str(&format!("status-justify {}\n", app.status_justify));
+                    output.push_str(&format!("prefix-indicator {}\n", if app.prefix_indicator { "on" } else { "off" }));
+                    output.push_str(&format!("prefix-indicator-style \"{}\"\n", app.prefix_indicator_style));
                     output.push_str(&format!("window-status-format \"{}\"\n", app.window_status_format));
                     output.push_str(&format!("window-status-current-format \"{}\"\n", app.window_status_current_format));
                     if !app.window_status_style.is_empty() {
@@ -1353,6 +1399,8 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     if !app.mode_style.is_empty() {
                         output.push_str(&format!("mode-style \"{}\"\n", app.mode_style));
                     }
+                    output.push_str(&format!("copy-mode-match-style \"{}\"\n", app.copy_mode_match_style));
+                    output.push_str(&format!("copy-mode-current-match-style \"{}\"\n", app.copy_mode_current_match_style));
                     // Include @user-options (used by plugins)
                     for (key, val) in &app.environment {
                         if key.starts_with('@') {
@@ -1367,12 +1415,16 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     output.push_str(&format!("window-size {}\n", app.window_size));
                     output.push_str(&format!("allow-passthrough {}\n", app.allow_passthrough));
                     output.push_str(&format!("set-clipboard {}\n", app.set_clipboard));
+                    output.push_str(&format!("local-echo {}\n", app.local_echo));
+                    output.push_str(&format!("compat-version {}\n", app.compat_version));
                     if !app.copy_command.is_empty() {
                         output.push_str(&format!("copy-command \"{}\"\n", app.copy_command));
                     }
-                    for (alias, expansion) in &app.command_aliases {
-                        output.push_str(&format!("command-alias \"{}={}\"\n", alias, expansion));
+                    for line in crate::alias::show_lines(&app.command_aliases) {
+                        output.push_str(&line);
+                        output.push('\n');
                     }
+                    if let Some(scope) = scope { output = helpers::at_pane(&mut app, pid, |app| show_scoped_options(app, scope, &output)); }
                     let _ = resp.send(output);
                 }
                 CtrlReq::SourceFile(path) => {
@@ -1396,25 +1448,27 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         parse_config_content(&mut app, &contents);
                     }
                 }
-                CtrlReq::MoveWindow(target) => {
-                    if let Some(t) = target {
-                        if t < app.windows.len() && app.active_idx != t {
-                            let win = app.windows.remove(app.active_idx);
-                            let insert_idx = if t > app.active_idx { t - 1 } else { t };
-                            app.windows.insert(insert_idx.min(app.windows.len()), win);
-                            app.active_idx = insert_idx.min(app.windows.len() - 1);
-                        }
+                CtrlReq::MoveWindow(source, target, kill, renumber, detached, resp) => {
+                    match helpers::move_window(&mut app, source.as_deref(), target, kill, renumber, detached) {
+                        Ok(()) => { let _ = resp.send(String::new()); }
+                        Err(e) => { let _ = resp.send(format!("psmux: move-window: {e}")); }
                     }
+                    resize_all_panes(&mut app); meta_dirty = true;
                 }
                 CtrlReq::SwapWindow(target) => {
-                    if target < app.windows.len() && app.active_idx != target {
+                    // Windows trade places; the indices stay with the slots
+                    if let Some(target) = window_pos_by_index(&app, target).filter(|&t| t != app.active_idx) {
+                        let (a, b) = (app.windows[app.active_idx].index, app.windows[target].index);
                         app.windows.swap(app.active_idx, target);
+                        app.windows[app.active_idx].index = a;
+                        app.windows[target].index = b;
                     }
                 }
                 CtrlReq::LinkWindow(_target) => {}
                 CtrlReq::UnlinkWindow => {
                     if app.windows.len() > 1 {
-                        let mut win = app.windows.remove(app.active_idx);
+                        let pos = app.active_idx;
+                        let mut win = remove_window(&mut app, pos);
                         kill_all_children(&mut win.root);
                         if app.active_idx >= app.windows.len() {
                             app.active_idx = app.windows.len() - 1;
@@ -1422,182 +1476,130 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                 }
                 CtrlReq::FindWindow(resp, pattern) => {
-                    let mut output = String::new();
-                    for (i, win) in app.windows.iter().enumerate() {
-                        if win.name.contains(&pattern) {
-                            output.push_str(&format!("{}: {} []\n", i + app.window_base_index, win.name));
-                        }
-                    }
-                    let _ = resp.send(output);
-                }
-                CtrlReq::MovePane(target_win) => {
-                    // move-pane is an alias for join-pane
-                    let src_idx = app.active_idx;
-                    if target_win < app.windows.len() && target_win != src_idx {
-                        let src_path = app.windows[src_idx].active_path.clone();
-                        let src_root = std::mem::replace(&mut app.windows[src_idx].root,
-                            Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
-                        let (remaining, extracted) = tree::extract_node(src_root, &src_path);
-                        if let Some(pane_node) = extracted {
-                            let src_empty = remaining.is_none();
-                            if let Some(rem) = remaining {
-                                app.windows[src_idx].root = rem;
-                                app.windows[src_idx].active_path = tree::first_leaf_path(&app.windows[src_idx].root);
-                            }
-                            let tgt = if src_empty && target_win > src_idx { target_win - 1 } else { target_win };
-                            if src_empty {
-                                app.windows.remove(src_idx);
-                                if app.active_idx >= app.windows.len() {
-                                    app.active_idx = app.windows.len().saturating_sub(1);
-                                }
-                            }
-                            if tgt < app.windows.len() {
-                                let tgt_path = app.windows[tgt].active_path.clone();
-                                tree::replace_leaf_with_split(&mut app.windows[tgt].root, &tgt_path, LayoutKind::Vertical, pane_node);
-                                app.active_idx = tgt;
-                            }
-                            resize_all_panes(&mut app);
-                            meta_dirty = true;
-                        } else {
-                            if let Some(rem) = remaining {
-                                app.windows[src_idx].root = rem;
+                    let _ = resp.send(helpers::find_window_lines(&app, &pattern));
+                }
+                CtrlReq::PipePane(cmd, stdin, stdout, toggle, pid) => {
+                    if let Some(pane) = helpers::target_pane_mut(&mut app, pid) {
+                        // No command closes the pipe; so does -o on a piped pane,
+                        // and a new command replaces the old one
+                        let piped = pane.pipe.is_some();
+                        pane.stop_pipe();
+                        if !cmd.is_empty() && !(toggle && piped) {
+                            if let Err(e) = crate::pipe_pane::start(pane, &cmd, stdin, stdout) {
+                                app.pending_message = Some(format!("pipe-pane: {e}"));
                             }
                         }
                     }
                 }
-                CtrlReq::PipePane(cmd, stdin, stdout) => {
-                    let win = &app.windows[app.active_idx];
-                    let pane_id = get_active_pane_id(&win.root, &win.active_path).unwrap_or(0);
-                    
-                    if cmd.is_empty() {
-                        app.pipe_panes.retain(|p| p.pane_id != pane_id);
-                    } else {
-                        if let Some(idx) = app.pipe_panes.iter().position(|p| p.pane_id == pane_id) {
-                            if let Some(ref mut proc) = app.pipe_panes[idx].process {
-                                let _ = proc.kill();
-                            }
-                            app.pipe_panes.remove(idx);
-                        } else {
-                            #[cfg(windows)]
-                            let process = std::process::Command::new("pwsh")
-                                .args(["-NoProfile", "-Command", &cmd])
-                                .stdin(if stdout { std::process::Stdio::piped() } else { std::process::Stdio::null() })
-                                .stdout(if stdin { std::process::Stdio::piped() } else { std::process::Stdio::null() })
-                                .stderr(std::process::Stdio::null())
-                                .spawn()
-                                .ok();
-                            
-                            app.pipe_panes.push(PipePaneState {
-                                pane_id,
-                                process,
-                                stdin,
-                                stdout,
-                            });
+                CtrlReq::SelectLayout(layout, resp) => {
+                    match apply_layout(&mut app, &layout) {
+                        Ok(()) => {
+                            state_dirty = true;
+                            if let Some(resp) = resp { let _ = resp.send(String::new()); }
                         }
+                        Err(e) => match resp {
+                            Some(resp) => { let _ = resp.send(format!("psmux: select-layout: {e}\n")); }
+                            None => { app.pending_message = Some(e); }
+                        },
                     }
                 }
-                CtrlReq::SelectLayout(layout) => {
-                    apply_layout(&mut app, &layout);
-                    state_dirty = true;
-                }
                 CtrlReq::NextLayout => {
                     cycle_layout(&mut app);
                     state_dirty = true;
                 }
                 CtrlReq::ListClients(resp) => {
-                    let mut output = String::new();
-                    output.push_str(&format!("/dev/pts/0: {}: {} [{}x{}] (utf8)\n", 
-                        app.session_name, 
-                        app.windows[app.active_idx].name,
-                        app.last_window_area.width,
-                        app.last_window_area.height
-                    ));
-                    let _ = resp.send(output);
+                    let _ = resp.send(helpers::list_clients(&app));
+                }
+                CtrlReq::SwitchClient(requester, client, to, resp) => {
+                    let _ = resp.send(helpers::switch_client(&app, requester, client.as_deref(), &to));
                 }
-                CtrlReq::SwitchClient(_target) => {}
                 CtrlReq::SwitchClientTable(table) => {
-                    app.current_key_table = Some(table);
+                    crate::input::switch_key_table(&mut app, &table);
                     state_dirty = true;
                 }
                 CtrlReq::ListCommands(resp) => {
                     let cmds = TMUX_COMMANDS.join("\n");
                     let _ = resp.send(cmds);
                 }
+                CtrlReq::BeginBatch(owner, resp) => {
+                    let r = helpers::begin_batch(&mut frame_batch, owner, Instant::now());
+                    let _ = resp.send(r.err().unwrap_or_default());
+                }
+                CtrlReq::EndBatch(owner, commit, resp) => {
+                    let cmd = if commit { "commit-batch" } else { "abort-batch" };
+                    let r = helpers::end_batch(&mut frame_batch, owner, cmd);
+                    if r.is_ok() { state_dirty = true; meta_dirty = true; }
+                    let _ = resp.send(r.err().unwrap_or_default());
+                }
                 CtrlReq::LockClient => {}
-                CtrlReq::RefreshClient => { state_dirty = true; meta_dirty = true; }
-                CtrlReq::SuspendClient => {}
+                CtrlReq::RefreshClient(requester, flags) => {
+                    // -f changes flags; otherwise the client repaints and
+                    // asks for a whole frame (dump-state -F)
+                    match flags {
+                        Some(spec) => { apply_refresh_flags(&mut app, requester, &spec); }
+                        None => { repaint_client(&app, requester); }
+                    }
+                    state_dirty = true; meta_dirty = true;
+                }
+                CtrlReq::SuspendClient(requester) => {
+                    // The requesting client, else the one used most recently
+                    let target = match requester {
+                        Some(id) => app.clients.iter().find(|c| c.id == id),
+                        None => app.clients.iter().filter(|c| !c.suspended).max_by_key(|c| c.last_input),
+                    };
+                    if let Some(c) = target { c.send_directive("%suspend".to_string()); }
+                }
+                CtrlReq::ChooseSession(requester) => {
+                    let target = match requester {
+                        Some(id) => app.clients.iter().find(|c| c.id == id),
+                        None => app.clients.iter().filter(|c| !c.suspended).max_by_key(|c| c.last_input),
+                    };
+                    if let Some(c) = target { c.send_directive("%choose-session".to_string()); }
+                }
                 CtrlReq::CopyModePageUp => {
                     enter_copy_mode(&mut app);
                     move_copy_cursor(&mut app, 0, -20);
                 }
-                CtrlReq::ClearHistory => {
-                    let win = &mut app.windows[app.active_idx];
-                    if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+                CtrlReq::ClearHistory(screen_too, pid) => {
+                    let history_limit = app.history_limit;
+                    if let Some(p) = helpers::target_pane_mut(&mut app, pid) {
                         if let Ok(mut parser) = p.term.lock() {
-                            *parser = vt100::Parser::new(p.last_rows, p.last_cols, app.history_limit);
+                            crate::copy_mode::clear_scrollback(&mut parser, history_limit, screen_too);
                         }
+                        p.data_version.fetch_add(1, std::sync::atomic::Ordering::Release);
                     }
+                    // Copy mode stays on, but the history it was scrolled into is gone
+                    app.copy_scroll_offset = 0;
+                    app.copy_anchor_scroll_offset = 0;
                 }
-                CtrlReq::SaveBuffer(path) => {
-                    if let Some(content) = app.paste_buffers.first() {
-                        let _ = std::fs::write(&path, content);
+                CtrlReq::SaveBuffer(path, name) => {
+                    if let Some(b) = app.paste_buffers.get(name.as_deref()) {
+                        let _ = std::fs::write(&path, &b.text);
                     }
                 }
-                CtrlReq::LoadBuffer(path) => {
+                CtrlReq::LoadBuffer(path, name) => {
                     if let Ok(content) = std::fs::read_to_string(&path) {
-                        app.paste_buffers.insert(0, content);
-                        if app.paste_buffers.len() > 10 {
-                            app.paste_buffers.pop();
-                        }
+                        app.paste_buffers.set(name.as_deref(), content);
                     }
                 }
-                CtrlReq::SetEnvironment(key, value) => {
-                    app.environment.insert(key.clone(), value.clone());
-                    env::set_var(&key, &value);
+                CtrlReq::SetEnvironment(set) => {
+                    crate::environ::apply_set(&mut app, &set);
                 }
-                CtrlReq::ShowEnvironment(resp) => {
-                    let mut output = String::new();
-                    // Show psmux/tmux-specific environment vars
-                    for (key, value) in &app.environment {
-                        output.push_str(&format!("{}={}\n", key, value));
-                    }
-                    // Also show inherited PSMUX_/TMUX_ vars from process env
-                    for (key, value) in env::vars() {
-                        if (key.starts_with("PSMUX") || key.starts_with("TMUX")) && !app.environment.contains_key(&key) {
-                            output.push_str(&format!("{}={}\n", key, value));
-                        }
-                    }
-                    let _ = resp.send(output);
+                CtrlReq::ShowEnvironment(resp, global) => {
+                    let _ = resp.send(crate::environ::show(&app, global));
                 }
-                CtrlReq::SetHook(hook, cmd) => {
-                    app.hooks.entry(hook).or_insert_with(Vec::new).push(cmd);
+                CtrlReq::ClientEnvironment(vars) => {
+                    crate::environ::update_from_client(&mut app, &vars);
                 }
-                CtrlReq::ShowHooks(resp) => {
-                    let mut output = String::new();
-                    for (name, commands) in &app.hooks {
-                        for cmd in commands {
-                            output.push_str(&format!("{} -> {}\n", name, cmd));
-                        }
-                    }
-                    if output.is_empty() {
-                        output.push_str("(no hooks)\n");
-                    }
-                    let _ = resp.send(output);
+                CtrlReq::SetHook(set) => {
+                    crate::hooks::apply(&mut app, set);
+                    state_dirty = true;
                 }
-                CtrlReq::RemoveHook(hook) => {
-                    app.hooks.remove(&hook);
+                CtrlReq::ShowHooks(resp, only) => {
+                    let _ = resp.send(app.hooks.show(only.as_deref()));
                 }
                 CtrlReq::KillServer => {
-                    // Kill all child processes in all windows before exiting
-                    for win in app.windows.iter_mut() {
-                        kill_all_children(&mut win.root);
-                    }
-                    // Brief delay to let child processes fully terminate
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-                    let keypath = format!("{}\\.psmux\\{}.key", home, app.port_file_base());
-                    let _ = std::fs::remove_file(&keypath);
-                    std::process::exit(0);
+                    return helpers::shut_down(&mut app);
                 }
                 CtrlReq::WaitFor(channel, op) => {
                     match op {
@@ -1631,88 +1633,37 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         }
                     }
                 }
-                CtrlReq::DisplayMenu(menu_def, x, y) => {
-                    let menu = parse_menu_definition(&menu_def, x, y);
-                    if !menu.items.is_empty() {
-                        app.mode = Mode::MenuMode { menu };
-                    }
-                }
-                CtrlReq::DisplayPopup(command, width, height, close_on_exit) => {
-                    if !command.is_empty() {
-                        // Try to spawn with PTY for interactive programs (fzf, etc.)
-                        let pty_result = Some(portable_pty::native_pty_system())
-                            .and_then(|pty_sys| {
-                                let pty_size = portable_pty::PtySize { rows: height.saturating_sub(2), cols: width.saturating_sub(2), pixel_width: 0, pixel_height: 0 };
-                                let pair = pty_sys.openpty(pty_size).ok()?;
-                                let mut cmd_builder = portable_pty::CommandBuilder::new(if cfg!(windows) { "pwsh" } else { "sh" });
-                                if cfg!(windows) { cmd_builder.args(["-NoProfile", "-Command", &command]); } else { cmd_builder.args(["-c", &command]); }
-                                let child = pair.slave.spawn_command(cmd_builder).ok()?;
-                                // Close the slave handle immediately – required for ConPTY.
-                                drop(pair.slave);
-                                let term = std::sync::Arc::new(std::sync::Mutex::new(vt100::Parser::new(pty_size.rows, pty_size.cols, 0)));
-                                let term_reader = term.clone();
-                                if let Ok(mut reader) = pair.master.try_clone_reader() {
-                                    std::thread::spawn(move || {
-                                        let mut buf = [0u8; 8192];
-                                        loop {
-                                            match reader.read(&mut buf) {
-                                                Ok(n) if n > 0 => { if let Ok(mut p) = term_reader.lock() { p.process(&buf[..n]); } }
-                                                _ => break,
-                                            }
-                                        }
-                                    });
-                                }
-                                let mut pty_writer = pair.master.take_writer().ok()?;
-                                crate::pane::conpty_preemptive_dsr_response(&mut *pty_writer);
-                                Some(PopupPty { master: pair.master, writer: pty_writer, child, term })
-                            });
-                        
-                        app.mode = Mode::PopupMode {
-                            command: command.clone(),
-                            output: String::new(),
-                            process: None,
-                            width,
-                            height,
-                            close_on_exit,
-                            popup_pty: pty_result,
-                        };
-                    } else {
-                        app.mode = Mode::PopupMode {
-                            command: String::new(),
-                            output: "Press 'q' or Escape to close\n".to_string(),
-                            process: None,
-                            width,
-                            height,
-                            close_on_exit: true,
-                            popup_pty: None,
-                        };
+                CtrlReq::DisplayMenu(menu_def) => {
+                    let menu = crate::menu::from_definition(&mut app, &parse_menu_definition(&menu_def));
+                    if let Err(e) = crate::menu::show(&app, &menu) {
+                        app.pending_message = Some(e);
                     }
                 }
-                CtrlReq::ConfirmBefore(prompt, cmd) => {
-                    let prompt_text = if prompt.is_empty() {
-                        format!("Confirm: {} (y/n)?", cmd)
-                    } else {
-                        format!("{} (y/n)?", prompt)
-                    };
-                    app.mode = Mode::ConfirmMode {
-                        prompt: prompt_text,
-                        command: cmd,
-                        input: String::new(),
-                    };
+                CtrlReq::DisplayPopup(args) => {
+                    if args.close { crate::popup::close(&mut app); } else { crate::popup::open(&mut app, &args); }
+                    state_dirty = true;
+                }
+                CtrlReq::ConfirmBefore(prompt) => {
+                    crate::prompt::show(&mut app, prompt);
                 }
                 CtrlReq::ResizePaneAbsolute(axis, size) => {
                     resize_pane_absolute(&mut app, &axis, size);
                 }
-                CtrlReq::ShowOptionValue(resp, name) => {
-                    let val = get_option_value(&app, &name);
+                CtrlReq::ShowOptionValue(resp, name, scope, pid) => {
+                    let val = match scope {
+                        Some(scope) if SCOPED_OPTIONS.contains(&name.as_str()) => helpers::at_pane(&mut app, pid, |app| scoped_option_value(app, scope, &name).0),
+                        _ => get_option_value(&app, &name),
+                    };
                     let _ = resp.send(val);
                 }
+                CtrlReq::ChooseClient(requester, template, resp) => {
+                    let _ = resp.send(helpers::choose_client(&app, requester, template.as_deref()));
+                }
                 CtrlReq::ChooseBuffer(resp) => {
                     let mut output = String::new();
-                    for (i, buf) in app.paste_buffers.iter().enumerate() {
-                        let preview: String = buf.chars().take(50).collect();
-                        let preview = preview.replace('\n', "\\n").replace('\r', "");
-                        output.push_str(&format!("buffer{}: {} bytes: \"{}\"\n", i, buf.len(), preview));
+                    for buf in app.paste_buffers.iter() {
+                        output.push_str(&crate::buffers::list_line(buf));
+                        output.push('\n');
                     }
                     let _ = resp.send(output);
                 }
@@ -1724,7 +1675,8 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                         app.session_name,
                         app.windows.len(),
                         (chrono::Local::now() - app.created_at).num_seconds(),
-                        pipe::pipe_name_for_session(&app.port_file_base())
+                        crate::session::read_session_record(&app.port_file_base()).ok().and_then(|r| r.endpoint)
+                            .map_or_else(|| pipe::pipe_name_for_session(&app.port_file_base()), |e| e.to_string())
                     );
                     let _ = resp.send(info);
                 }
@@ -1777,39 +1729,129 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
                     }
                     hook_event = Some("pane-focus-out");
                 }
-                CtrlReq::CommandPrompt(initial) => {
-                    app.mode = Mode::CommandPrompt { input: initial.clone(), cursor: initial.len() };
+                CtrlReq::CommandPrompt(prompt) => {
+                    crate::prompt::show(&mut app, prompt);
                     state_dirty = true;
                 }
+                CtrlReq::PromptHistory(entry) => {
+                    crate::history::record_command(&mut app, &entry, Instant::now());
+                }
                 CtrlReq::ShowMessages(resp) => {
                     // Return message log (tmux stores recent log messages)
                     let _ = resp.send(String::new());
                 }
-                CtrlReq::ResizeWindow(_dim, _size) => {
-                    // On Windows, window size is controlled by the terminal emulator;
-                    // resize-window is a no-op since we adapt to the terminal size.
+                CtrlReq::ResizeWindow(w, h) => {
+                    crate::tree::set_headless_size(&mut app, w, h);
+                    state_dirty = true; meta_dirty = true;
                 }
                 CtrlReq::RespawnWindow => {
                     // Kill all panes in the active window and respawn	
-                    respawn_active_pane(&mut app, Some(&*pty_system))?;
+                    respawn_active_pane(&mut app, Some(&*pty_system), true)?;
                     state_dirty = true;
                 }
             }
             // Fire any hooks registered for the event that just occurred
             if let Some(event) = hook_event {
-                let cmds: Vec<String> = app.hooks.get(event).cloned().unwrap_or_default();
-                for cmd in cmds {
-                    parse_config_line(&mut app, &cmd);
-                }
+                let context = crate::hooks::active_context(&app);
+                crate::hooks::run(&mut app, event, context);
             }
+            crate::hooks::run_pending(&mut app);
             if mutates_state {
                 state_dirty = true;
             }
         }
             }
         }
+        // Abandon a border drag whose button-up never arrived (released
+        // outside the terminal); apply the final size to the PTYs.
+        if app.drag.as_ref().map_or(false, |d| d.last_event.elapsed() >= tree::DRAG_TIMEOUT) {
+            app.drag = None;
+            resize_all_panes(&mut app);
+            state_dirty = true;
+        }
+        // prefix-timeout: an unused key table goes back to root
+        if app.current_key_table.is_some() && crate::input::prefix_timed_out(&app, app.key_table_at) {
+            app.current_key_table = None;
+            state_dirty = true;
+        }
+        if check_window_activity(&mut app, chrono::Local::now()) { meta_dirty = true; }
+        // The clock must tick on idle panes too: a dirty frame is never "NC"
+        if last_status_refresh.elapsed() >= Duration::from_secs(1)
+            && crate::format::status_refresh_interval(&app).is_some_and(|every| last_status_refresh.elapsed() >= every)
+        {
+            last_status_refresh = Instant::now();
+            state_dirty = true;
+            meta_dirty = true;
+        }
+        if crate::copy_mode::run_incremental_search_if_due(&mut app, Instant::now()) { state_dirty = true; }
+        crate::clipboard::accept_from_panes(&mut app);
+        // A script that never commits must not freeze every client
+        if helpers::expire_batch(&mut frame_batch, Instant::now()) { state_dirty = true; meta_dirty = true; }
+        // Subscribed clients are sent a frame once a burst of changes settles
+        if (state_dirty || meta_dirty) && frame_batch.is_none() {
+            push_at.get_or_insert_with(|| helpers::push_due(Instant::now(), last_push));
+        }
+        if push_at.is_some_and(|at| Instant::now() >= at) {
+            push_at = None;
+            if helpers::push_frames(&app, &push_tx) > 0 { last_push = Some(Instant::now()); }
+        }
+        if let Some(msg) = app.pending_message.take() {
+            for c in &app.clients { c.send_directive(format!("%message {}", msg)); }
+            message_clear_at = Some(Instant::now() + Duration::from_millis(app.display_time_ms));
+        } else if message_clear_at.map_or(false, |t| Instant::now() >= t) {
+            message_clear_at = None;
+            for c in &app.clients { c.send_directive("%message-clear".to_string()); }
+        }
+        // Detach clients that have been idle past client-idle-timeout,
+        // warning them client-idle-warning seconds beforehand.
+        if last_idle_check.elapsed() >= Duration::from_secs(1) {
+            last_idle_check = Instant::now();
+            // Prompt history is saved here too, debounced, so a crash loses little
+            crate::history::save_history_if_due(&mut app, last_idle_check);
+            let (cleared, idle_windows) = helpers::idle_window_actions(&mut app, chrono::Local::now());
+            if cleared { meta_dirty = true; }
+            for (id, action) in idle_windows {
+                let Some(index) = app.windows.iter().find(|w| w.id == id).map(|w| w.index) else { continue };
+                let hours = app.idle_window_timeout;
+                match action {
+                    helpers::IdleWindow::Flagged => {
+                        app.pending_message = Some(format!("window {} idle for {}h", index, hours));
+                    }
+                    helpers::IdleWindow::Warned => {
+                        app.pending_message = Some(format!("window {} idle for {}h: killing it in {}s", index, hours, helpers::IDLE_KILL_GRACE));
+                    }
+                    helpers::IdleWindow::Kill => {
+                        helpers::kill_idle_window(&mut app, id);
+                        app.pending_message = Some(format!("killed idle window {}", index));
+                        resize_all_panes(&mut app);
+                        state_dirty = true;
+                    }
+                }
+                meta_dirty = true;
+            }
+            for (id, action) in idle_client_actions(&mut app, last_idle_check) {
+                let Some(pos) = app.clients.iter().position(|c| c.id == id) else { continue };
+                match action {
+                    IdleAction::Warn(left) => {
+                        app.clients[pos].send_directive(format!("%message detaching in {}s — press any key", left));
+                    }
+                    IdleAction::Cancel => { app.clients[pos].send_directive("%message-clear".to_string()); }
+                    IdleAction::Detach => {
+                        // The client answers %detach with its normal client-detach,
+                        // which drops attached_clients and fires client-detached.
+                        let client = app.clients.remove(pos);
+                        client.detach();
+                        let path = format!("{}\\server.log", crate::session::psmux_dir());
+                        if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open(&path) {
+                            let _ = writeln!(f, "{}: client {} detached after {}s idle", app.session_name, id, app.client_idle_timeout);
+                        }
+                    }
+                }
+            }
+        }
+        crate::pipe_pane::feed_input(&mut app);
         // Check if all windows/panes have exited
-        let (all_empty, any_pruned) = tree::reap_children(&mut app)?;
+        let (all_empty, any_pruned) = if reap_schedule.due(Instant::now()) { tree::reap_children(&mut app)? } else { (false, false) };
         if any_pruned {
             // A pane exited naturally - resize remaining panes to fill the space
             resize_all_panes(&mut app);
@@ -1817,9 +1859,9 @@ pub fn run_server(session_name: String, socket_name: Option<String>, initial_com
             meta_dirty = true;
         }
         if all_empty {
-            let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-            let keypath = format!("{}\\.psmux\\{}.key", home, app.port_file_base());
-            let _ = std::fs::remove_file(&keypath);
+            crate::popup::stop_spare(&mut app);
+            let _ = crate::history::save_history(&mut app);
+            crate::session::remove_session_files(&app.port_file_base());
             break;
         }
         // recv_timeout already handles the wait; no additional sleep needed.
@@ -1,4 +1,4 @@
-use crate::types::AppState;
+use crate::types::{AppState, OptionScope};
 use crate::config::{format_key_binding, parse_key_string};
 
 /// Get a single option's value by name (for `show-options -v name`).
@@ -9,6 +9,9 @@ pub(crate) fn get_option_value(app: &AppState, name: &str) -> String {
         "base-index" => app.window_base_index.to_string(),
         "pane-base-index" => app.pane_base_index.to_string(),
         "escape-time" => app.escape_time_ms.to_string(),
+        "repeat-time" => app.repeat_time_ms.to_string(),
+        "prefix-timeout" => app.prefix_timeout_ms.to_string(),
+        "confirm-kill" => if app.confirm_kill { "on".into() } else { "off".into() },
         "mouse" => if app.mouse_enabled { "on".into() } else { "off".into() },
         "status" => {
             if !app.status_visible { "off".into() }
@@ -20,13 +23,22 @@ pub(crate) fn get_option_value(app: &AppState, name: &str) -> String {
         "status-right" => app.status_right.clone(),
         "history-limit" => app.history_limit.to_string(),
         "display-time" => app.display_time_ms.to_string(),
+        "pattern-timeout" => app.pattern_timeout_ms.to_string(),
         "display-panes-time" => app.display_panes_time_ms.to_string(),
         "mode-keys" => app.mode_keys.clone(),
         "focus-events" => if app.focus_events { "on".into() } else { "off".into() },
         "renumber-windows" => if app.renumber_windows { "on".into() } else { "off".into() },
         "automatic-rename" => if app.automatic_rename { "on".into() } else { "off".into() },
+        "automatic-rename-format" => app.automatic_rename_format.clone(),
+        "allow-duplicate-window-names" => if app.allow_duplicate_window_names { "on".into() } else { "off".into() },
+        "popup-warm-shell" => if app.popup_warm_shell { "on".into() } else { "off".into() },
+        "popup-close-key" => app.popup_close_key.clone(),
         "monitor-activity" => if app.monitor_activity { "on".into() } else { "off".into() },
+        "visual-activity" => if app.visual_activity { "on".into() } else { "off".into() },
+        "monitor-bell" => if app.monitor_bell { "on".into() } else { "off".into() },
+        "visual-silence" => if app.visual_silence { "on".into() } else { "off".into() },
         "synchronize-panes" => if app.sync_input { "on".into() } else { "off".into() },
+        "sync-paste" => if app.sync_paste { "on".into() } else { "off".into() },
         "remain-on-exit" => if app.remain_on_exit { "on".into() } else { "off".into() },
         "set-titles" => if app.set_titles { "on".into() } else { "off".into() },
         "set-titles-string" => app.set_titles_string.clone(),
@@ -37,6 +49,12 @@ pub(crate) fn get_option_value(app: &AppState, name: &str) -> String {
         "word-separators" => app.word_separators.clone(),
         "pane-border-style" => app.pane_border_style.clone(),
         "pane-active-border-style" => app.pane_active_border_style.clone(),
+        "pane-border-lines" => app.pane_border_lines.clone(),
+        "pane-border-indicators" => app.pane_border_indicators.clone(),
+        "pane-border-status" => app.pane_border_status.clone(),
+        "pane-border-format" => app.pane_border_format.clone(),
+        "highlight-pattern" => app.highlight_pattern.clone(),
+        "pane-encoding" => app.pane_encoding.clone(),
         "status-style" => app.status_style.clone(),
         "window-status-format" => app.window_status_format.clone(),
         "window-status-current-format" => app.window_status_current_format.clone(),
@@ -49,26 +67,42 @@ pub(crate) fn get_option_value(app: &AppState, name: &str) -> String {
         "message-style" => app.message_style.clone(),
         "message-command-style" => app.message_command_style.clone(),
         "mode-style" => app.mode_style.clone(),
+        "copy-mode-match-style" => app.copy_mode_match_style.clone(),
+        "copy-mode-current-match-style" => app.copy_mode_current_match_style.clone(),
         "status-left-style" => app.status_left_style.clone(),
         "status-right-style" => app.status_right_style.clone(),
         "status-interval" => app.status_interval.to_string(),
         "status-justify" => app.status_justify.clone(),
+        "prefix-indicator" => if app.prefix_indicator { "on".into() } else { "off".into() },
+        "prefix-indicator-style" => app.prefix_indicator_style.clone(),
         "bell-action" => app.bell_action.clone(),
         "visual-bell" => if app.visual_bell { "on".into() } else { "off".into() },
         "monitor-silence" => app.monitor_silence.to_string(),
+        "idle-window-timeout" => app.idle_window_timeout.to_string(),
+        "idle-window-action" => app.idle_window_action.clone(),
+        "client-idle-timeout" => app.client_idle_timeout.to_string(),
+        "client-idle-warning" => app.client_idle_warning.to_string(),
+        "client-idle-exempt-local" => if app.client_idle_exempt_local { "on".into() } else { "off".into() },
+        "root-any-binding" => if app.root_any_binding { "on".into() } else { "off".into() },
+        "scroll-without-copy-mode" => if app.scroll_without_copy_mode { "on".into() } else { "off".into() },
+        "scrolled-input" => if app.scrolled_input_block { "block".into() } else { "snap".into() },
+        "history-file" => app.history_file.clone().unwrap_or_default(),
+        "prompt-history-limit" => app.prompt_history_limit.to_string(),
+        "buffer-limit" => app.paste_buffers.limit.to_string(),
         "status-left-length" => app.status_left_length.to_string(),
         "status-right-length" => app.status_right_length.to_string(),
         "window-size" => app.window_size.clone(),
         "allow-passthrough" => app.allow_passthrough.clone(),
         "copy-command" => app.copy_command.clone(),
+        "compat-version" => app.compat_version.clone(),
         "set-clipboard" => app.set_clipboard.clone(),
+        "local-echo" => app.local_echo.clone(),
         "main-pane-width" => app.main_pane_width.to_string(),
         "main-pane-height" => app.main_pane_height.to_string(),
-        "command-alias" => {
-            app.command_aliases.iter()
-                .map(|(k, v)| format!("{}={}", k, v))
-                .collect::<Vec<_>>()
-                .join(",")
+        "command-alias" => app.command_aliases.values().cloned().collect::<Vec<_>>().join(","),
+        "update-environment" => crate::environ::update_names(app).join(" "),
+        _ if crate::alias::parse_option(name).is_some() => {
+            crate::alias::parse_option(name).flatten().and_then(|i| app.command_aliases.get(&i).cloned()).unwrap_or_default()
         }
         _ => {
             // Support @user-options and other env-stored options (e.g. default-terminal)
@@ -77,6 +111,88 @@ pub(crate) fn get_option_value(app: &AppState, name: &str) -> String {
     }
 }
 
+/// Options that can also be set for a window (`-w`) or a pane (`-p`);
+/// the others are session-wide whatever the flags.
+pub(crate) const SCOPED_OPTIONS: &[&str] = &[
+    "highlight-pattern", "pane-encoding",
+    "synchronize-panes", "automatic-rename", "monitor-activity", "monitor-bell", "monitor-silence", "remain-on-exit",
+];
+
+/// A scoped option's effective value for the active window (`scope`
+/// Window) or its active pane, and whether it is inherited from a wider
+/// setting rather than set there.
+pub(crate) fn scoped_option_value(app: &AppState, scope: OptionScope, option: &str) -> (String, bool) {
+    let win = app.windows.get(app.active_idx);
+    let pane = win.filter(|_| scope == OptionScope::Pane).and_then(|w| crate::tree::active_pane(&w.root, &w.active_path));
+    let own = |p: Option<&String>, w: Option<&String>, session: &str| match (p, w) {
+        (Some(v), _) => (v.clone(), scope != OptionScope::Pane),
+        (None, Some(v)) => (v.clone(), scope != OptionScope::Window),
+        (None, None) => (session.to_string(), true),
+    };
+    match option {
+        "highlight-pattern" => own(pane.and_then(|p| p.highlight_pattern.as_ref()), win.and_then(|w| w.highlight_pattern.as_ref()), &app.highlight_pattern),
+        "pane-encoding" => own(pane.and_then(|p| p.pane_encoding.as_ref()), win.and_then(|w| w.pane_encoding.as_ref()), &app.pane_encoding),
+        _ => {
+            let (value, origin) = crate::scoped::value(app, win, pane, option);
+            let inherited = match scope {
+                OptionScope::Window => origin != crate::scoped::Origin::Window,
+                OptionScope::Pane => origin != crate::scoped::Origin::Pane,
+            };
+            (value, inherited)
+        }
+    }
+}
+
+/// `show -w` / `show -p`: the session listing with the scoped options
+/// replaced by their effective values, an inherited one marked `name*`.
+pub(crate) fn show_scoped_options(app: &AppState, scope: OptionScope, listing: &str) -> String {
+    let mut out: String = listing.lines()
+        .filter(|l| !SCOPED_OPTIONS.contains(&l.split(' ').next().unwrap_or("")))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    for option in SCOPED_OPTIONS {
+        let (value, inherited) = scoped_option_value(app, scope, option);
+        let mark = if inherited { "*" } else { "" };
+        if *option == "highlight-pattern" {
+            out.push_str(&format!("{}{} \"{}\"\n", option, mark, value));
+        } else {
+            out.push_str(&format!("{}{} {}\n", option, mark, value));
+        }
+    }
+    out
+}
+
+/// Set (or with None unset) an option of the active window or pane, which
+/// then overrides the session's.
+pub(crate) fn apply_scoped_option(app: &mut AppState, scope: OptionScope, option: &str, value: Option<String>) {
+    let win = &mut app.windows[app.active_idx];
+    let slot = match (option, scope) {
+        ("highlight-pattern", OptionScope::Window) => &mut win.highlight_pattern,
+        ("highlight-pattern", OptionScope::Pane) => match crate::tree::active_pane_mut(&mut win.root, &win.active_path) {
+            Some(p) => &mut p.highlight_pattern,
+            None => return,
+        },
+        _ if option == "pane-encoding" && value.as_deref().is_some_and(|v| crate::encoding::Encoding::parse(v).is_none()) => return,
+        ("pane-encoding", OptionScope::Window) => &mut win.pane_encoding,
+        ("pane-encoding", OptionScope::Pane) => match crate::tree::active_pane_mut(&mut win.root, &win.active_path) {
+            Some(p) => &mut p.pane_encoding,
+            None => return,
+        },
+        (_, OptionScope::Window) => {
+            win.options.set(option, value.as_deref());
+            return;
+        }
+        (_, OptionScope::Pane) => {
+            if let Some(p) = crate::tree::active_pane_mut(&mut win.root, &win.active_path) {
+                p.options.set(option, value.as_deref());
+            }
+            return;
+        }
+    };
+    *slot = value;
+    if option == "pane-encoding" { crate::encoding::refresh(app); }
+}
+
 /// Apply a set-option command. If `quiet` is true, unknown options are silently ignored.
 pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, quiet: bool) {
     match option {
@@ -118,7 +234,10 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
         }
         "history-limit" => {
             if let Ok(limit) = value.parse::<usize>() {
-                app.history_limit = limit;
+                if limit != app.history_limit {
+                    app.history_limit = limit;
+                    crate::copy_mode::apply_history_limit(app);
+                }
             }
         }
         "display-time" => {
@@ -126,6 +245,11 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
                 app.display_time_ms = ms;
             }
         }
+        "pattern-timeout" => {
+            if let Ok(ms) = value.parse::<u64>() {
+                app.pattern_timeout_ms = ms;
+            }
+        }
         "display-panes-time" => {
             if let Ok(ms) = value.parse::<u64>() {
                 app.display_panes_time_ms = ms;
@@ -136,6 +260,12 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
                 app.repeat_time_ms = ms;
             }
         }
+        "prefix-timeout" => {
+            if let Ok(ms) = value.parse::<u64>() {
+                app.prefix_timeout_ms = ms;
+            }
+        }
+        "confirm-kill" => { app.confirm_kill = value == "on" || value == "true" || value == "1"; }
         "mode-keys" => { app.mode_keys = value.to_string(); }
         "status" => {
             // Handle numeric values for multi-line status bar (tmux 3.2+)
@@ -190,7 +320,44 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
         "aggressive-resize" => { app.aggressive_resize = matches!(value, "on" | "true" | "1"); }
         "monitor-activity" => { app.monitor_activity = matches!(value, "on" | "true" | "1"); }
         "visual-activity" => { app.visual_activity = matches!(value, "on" | "true" | "1"); }
+        "monitor-bell" => { app.monitor_bell = matches!(value, "on" | "true" | "1"); }
+        "visual-bell" => { app.visual_bell = matches!(value, "on" | "true" | "1"); }
+        "visual-silence" => { app.visual_silence = matches!(value, "on" | "true" | "1"); }
+        "bell-action" => {
+            if matches!(value, "any" | "none" | "current" | "other") { app.bell_action = value.to_string(); }
+        }
+        "monitor-silence" => {
+            if let Ok(n) = value.parse::<u64>() { app.monitor_silence = n; }
+        }
+        "idle-window-timeout" => {
+            if let Ok(n) = value.parse::<u64>() { app.idle_window_timeout = n; }
+        }
+        "idle-window-action" => {
+            if matches!(value, "flag" | "kill") { app.idle_window_action = value.to_string(); }
+        }
         "synchronize-panes" => { app.sync_input = matches!(value, "on" | "true" | "1"); }
+        "sync-paste" => { app.sync_paste = matches!(value, "on" | "true" | "1"); }
+        "client-idle-timeout" => {
+            if let Ok(n) = value.parse::<u64>() { app.client_idle_timeout = n; }
+        }
+        "client-idle-warning" => {
+            if let Ok(n) = value.parse::<u64>() { app.client_idle_warning = n; }
+        }
+        "client-idle-exempt-local" => { app.client_idle_exempt_local = matches!(value, "on" | "true" | "1"); }
+        "history-file" => { crate::history::set_history_file(app, value); }
+        "prompt-history-limit" => {
+            if let Ok(n) = value.parse::<usize>() { app.prompt_history_limit = n; }
+        }
+        "buffer-limit" => {
+            if let Ok(n) = value.parse::<usize>() { app.paste_buffers.limit = n.max(1); app.paste_buffers.trim(); }
+        }
+        "root-any-binding" => { app.root_any_binding = matches!(value, "on" | "true" | "1"); }
+        "scroll-without-copy-mode" => { app.scroll_without_copy_mode = matches!(value, "on" | "true" | "1"); }
+        "scrolled-input" => { app.scrolled_input_block = value == "block"; }
+        "automatic-rename-format" => { app.automatic_rename_format = value.to_string(); }
+        "allow-duplicate-window-names" => { app.allow_duplicate_window_names = matches!(value, "on" | "true" | "1"); }
+        "popup-warm-shell" => { app.popup_warm_shell = matches!(value, "on" | "true" | "1"); }
+        "popup-close-key" => { app.popup_close_key = value.to_string(); }
         "automatic-rename" => {
             app.automatic_rename = matches!(value, "on" | "true" | "1");
             // When user explicitly enables automatic-rename, clear manual_rename
@@ -208,6 +375,19 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
         "cursor-blink" => { std::env::set_var("PSMUX_CURSOR_BLINK", if matches!(value, "on"|"true"|"1") { "1" } else { "0" }); }
         "pane-border-style" => { app.pane_border_style = value.to_string(); }
         "pane-active-border-style" => { app.pane_active_border_style = value.to_string(); }
+        "pane-border-lines" => { app.pane_border_lines = value.to_string(); }
+        "pane-border-indicators" => { app.pane_border_indicators = value.to_string(); }
+        "pane-border-status" => {
+            if matches!(value, "off" | "top" | "bottom") { crate::tree::set_pane_border_status(app, value); }
+        }
+        "pane-border-format" => { app.pane_border_format = value.to_string(); }
+        "highlight-pattern" => { app.highlight_pattern = value.to_string(); }
+        "pane-encoding" => {
+            if crate::encoding::Encoding::parse(value).is_some() {
+                app.pane_encoding = value.to_string();
+                crate::encoding::refresh(app);
+            }
+        }
         "window-status-format" => { app.window_status_format = value.to_string(); }
         "window-status-current-format" => { app.window_status_current_format = value.to_string(); }
         "window-status-separator" => { app.window_status_separator = value.to_string(); }
@@ -217,11 +397,15 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
         "window-status-bell-style" => { app.window_status_bell_style = value.to_string(); }
         "window-status-last-style" => { app.window_status_last_style = value.to_string(); }
         "mode-style" => { app.mode_style = value.to_string(); }
+        "copy-mode-match-style" => { app.copy_mode_match_style = value.to_string(); }
+        "copy-mode-current-match-style" => { app.copy_mode_current_match_style = value.to_string(); }
         "message-style" => { app.message_style = value.to_string(); }
         "message-command-style" => { app.message_command_style = value.to_string(); }
         "status-left-style" => { app.status_left_style = value.to_string(); }
         "status-right-style" => { app.status_right_style = value.to_string(); }
         "status-justify" => { app.status_justify = value.to_string(); }
+        "prefix-indicator" => { app.prefix_indicator = matches!(value, "on" | "true" | "1"); }
+        "prefix-indicator-style" => { app.prefix_indicator_style = value.to_string(); }
         "status-interval" => {
             if let Ok(n) = value.parse::<u64>() { app.status_interval = n; }
         }
@@ -231,17 +415,22 @@ pub(crate) fn apply_set_option(app: &mut AppState, option: &str, value: &str, qu
         "main-pane-height" => {
             if let Ok(n) = value.parse::<u16>() { app.main_pane_height = n; }
         }
-        "window-size" => { app.window_size = value.to_string(); }
+        "window-size" => {
+            app.window_size = value.to_string();
+            crate::tree::fit_to_clients(app, None);
+        }
         "allow-passthrough" => { app.allow_passthrough = value.to_string(); }
         "copy-command" => { app.copy_command = value.to_string(); }
+        "compat-version" => {
+            if !value.trim().is_empty() { app.compat_version = value.trim().to_string(); }
+        }
         "set-clipboard" => { app.set_clipboard = value.to_string(); }
-        "command-alias" => {
-            // Format: "alias=expansion" e.g. "splitp=split-window"
-            if let Some(pos) = value.find('=') {
-                let alias = value[..pos].trim().to_string();
-                let expansion = value[pos+1..].trim().to_string();
-                app.command_aliases.insert(alias, expansion);
-            }
+        "local-echo" => {
+            if matches!(value, "on" | "off" | "auto") { app.local_echo = value.to_string(); }
+        }
+        _ if crate::alias::parse_option(option).is_some() => {
+            // "alias=expansion" e.g. "splitp=split-window", optionally at an index
+            crate::alias::set(&mut app.command_aliases, crate::alias::parse_option(option).flatten(), value);
         }
         _ => {
             // Handle status-format[N] patterns
@@ -1,22 +1,265 @@
 use std::io::{self, Write};
 use std::env;
 
-use crate::pipe;
+use serde::{Serialize, Deserialize};
 
-/// Clean up any stale session key files (where server pipe no longer exists)
-pub fn cleanup_stale_sessions() {
-    let home = match env::var("USERPROFILE").or_else(|_| env::var("HOME")) {
-        Ok(h) => h,
-        Err(_) => return,
+use crate::transport::{self, Endpoint};
+use crate::config::format_key_binding;
+use crate::types::{AppState, VERSION};
+
+/// Format version of the per-session registry file (`<base>.key`).
+/// Bump this whenever the file layout or the pipe protocol changes in a way
+/// older binaries cannot understand; readers reject records from the future.
+pub const REGISTRY_VERSION: u32 = 1;
+
+/// One session's entry in the on-disk registry.
+///
+/// Version 1 is a single JSON object.  Version 0 (legacy) was the bare
+/// session key on one line; it is still accepted by `parse_session_record`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionRecord {
+    pub version: u32,
+    pub key: String,
+    /// PID of the server process (0 = unknown, legacy record)
+    #[serde(default)]
+    pub pid: u32,
+    /// psmux version that wrote the record (informational)
+    #[serde(default)]
+    pub psmux: String,
+    /// -L socket name the server was started with
+    #[serde(default)]
+    pub socket: Option<String>,
+    /// Where the server listens (see crate::transport).  None: a server
+    /// older than the transports, on its named pipe with AUTH.
+    #[serde(default)]
+    pub endpoint: Option<Endpoint>,
+}
+
+fn home_dir() -> String {
+    env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default()
+}
+
+/// The registry directory used when neither PSMUX_DIR nor --socket-dir is set.
+/// Debug builds live in a `dev` subdirectory so they never trample the
+/// key files and last_session of an installed release.
+fn default_psmux_dir() -> String {
+    if cfg!(debug_assertions) {
+        format!("{}\\.psmux\\dev", home_dir())
+    } else {
+        format!("{}\\.psmux", home_dir())
+    }
+}
+
+/// Directory holding the session registry (key files, last_session, logs).
+/// Honors PSMUX_DIR, which the global `--socket-dir` flag also sets.
+pub fn psmux_dir() -> String {
+    match env::var("PSMUX_DIR") {
+        Ok(d) if !d.trim().is_empty() => d.trim().trim_end_matches(['\\', '/']).to_string(),
+        _ => default_psmux_dir(),
+    }
+}
+
+/// Path of the registry file for a session (port file base name).
+pub fn key_file_path(base: &str) -> String {
+    format!("{}\\{}.key", psmux_dir(), base)
+}
+
+/// Short tag that namespaces pipe names for non-default registry dirs, so two
+/// servers with the same session name in different dirs do not collide.
+/// Returns None for the release default dir to stay compatible with older clients.
+pub fn registry_tag() -> Option<String> {
+    let dir = psmux_dir();
+    if dir.eq_ignore_ascii_case(&format!("{}\\.psmux", home_dir())) {
+        return None;
+    }
+    // FNV-1a over the lowercased path: stable across processes and builds
+    let mut h: u32 = 0x811c9dc5;
+    for b in dir.to_ascii_lowercase().bytes() {
+        h ^= b as u32;
+        h = h.wrapping_mul(0x01000193);
+    }
+    Some(format!("{:08x}", h))
+}
+
+/// Parse a registry file in either the current JSON format or the legacy
+/// bare-key format (reported as version 0).
+pub fn parse_session_record(text: &str) -> Option<SessionRecord> {
+    let t = text.trim();
+    if t.is_empty() { return None; }
+    if t.starts_with('{') {
+        return serde_json::from_str::<SessionRecord>(t).ok();
+    }
+    if t.contains(char::is_whitespace) { return None; }
+    Some(SessionRecord { version: 0, key: t.to_string(), pid: 0, psmux: String::new(), socket: None, endpoint: None })
+}
+
+/// Check that a record was written by a compatible build and that its server
+/// is still running.  `alive` is injected so the check can be unit-tested.
+pub fn validate_session_record(rec: &SessionRecord, alive: impl Fn(u32) -> bool) -> io::Result<String> {
+    if rec.version > REGISTRY_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("session registry version {} is newer than supported ({}); written by psmux {}", rec.version, REGISTRY_VERSION, rec.psmux)));
+    }
+    if rec.pid != 0 && !alive(rec.pid) {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("server pid {} is not running", rec.pid)));
+    }
+    Ok(rec.key.clone())
+}
+
+/// Write this server's registry file.
+pub fn write_session_record(base: &str, key: &str, socket: Option<&str>, endpoint: Endpoint) -> io::Result<()> {
+    let rec = SessionRecord {
+        version: REGISTRY_VERSION,
+        key: key.to_string(),
+        pid: std::process::id(),
+        psmux: env!("CARGO_PKG_VERSION").to_string(),
+        socket: socket.map(|s| s.to_string()),
+        endpoint: Some(endpoint),
     };
-    let psmux_dir = format!("{}\\.psmux", home);
-    if let Ok(entries) = std::fs::read_dir(&psmux_dir) {
+    let json = serde_json::to_string(&rec).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("json error: {e}")))?;
+    std::fs::write(key_file_path(base), json)
+}
+
+/// `session-info -v`: one session as tooling sees it, one JSON object per
+/// line.  `ls --json` prints an array of these.  New fields are only ever
+/// added, so readers should ignore keys they do not know.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionInfo {
+    pub name: String,
+    /// -L socket namespace, if any
+    #[serde(default)]
+    pub socket_name: Option<String>,
+    /// Session group (psmux has no groups yet; always null)
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Unix timestamps
+    pub created: i64,
+    pub last_activity: i64,
+    pub attached: usize,
+    #[serde(default)]
+    pub clients: Vec<ClientInfo>,
+    pub windows: usize,
+    pub active_window: String,
+    pub prefix: String,
+    pub version: String,
+}
+
+/// An attached client in [`SessionInfo`], with its last reported size.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Describe the session for `session-info -v`.
+pub fn session_info(app: &AppState) -> SessionInfo {
+    let last_activity = app.windows.iter().map(|w| w.activity_at).max().unwrap_or(app.created_at);
+    SessionInfo {
+        name: app.session_name.clone(),
+        socket_name: app.socket_name.clone(),
+        group: None,
+        created: app.created_at.timestamp(),
+        last_activity: last_activity.max(app.created_at).timestamp(),
+        attached: app.attached_clients,
+        clients: app.clients.iter().map(|c| ClientInfo { id: c.id, width: c.size.0, height: c.size.1 }).collect(),
+        windows: app.windows.len(),
+        active_window: app.windows.get(app.active_idx).map(|w| w.name.clone()).unwrap_or_default(),
+        prefix: format_key_binding(&app.prefix_key),
+        version: VERSION.to_string(),
+    }
+}
+
+/// Read and validate a session's registry record.
+pub fn read_session_record(session: &str) -> io::Result<SessionRecord> {
+    let text = std::fs::read_to_string(key_file_path(session))?;
+    let rec = parse_session_record(&text)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unreadable session registry file"))?;
+    validate_session_record(&rec, crate::platform::process_info::is_process_alive)?;
+    Ok(rec)
+}
+
+/// List every parseable registry record in `dir` as (port file base, record),
+/// sorted by name.  No validation is done here.
+pub fn list_session_records(dir: &str) -> Vec<(String, SessionRecord)> {
+    let mut out = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|e| e == "key").unwrap_or(false) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    if let Some(rec) = std::fs::read_to_string(&path).ok().and_then(|t| parse_session_record(&t)) {
+                        out.push((stem.to_string(), rec));
+                    }
+                }
+            }
+        }
+    }
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    out
+}
+
+/// `ls --json`: `session-info -v` from every live server in the registry,
+/// or only those in one -L namespace.  Servers too old to answer in JSON
+/// are left out.
+pub fn collect_session_infos(socket: Option<&str>) -> Vec<SessionInfo> {
+    let ns_prefix = socket.map(|l| format!("{l}__"));
+    list_session_records(&psmux_dir()).into_iter()
+        .filter(|(base, _)| ns_prefix.as_ref().map_or(true, |p| base.starts_with(p.as_str())))
+        .filter(|(base, _)| transport::exists(base))
+        .filter_map(|(base, _)| send_cmd_response(&base, b"session-info -v\n").ok())
+        .filter_map(|reply| reply.lines().next().and_then(|l| serde_json::from_str(l).ok()))
+        .collect()
+}
+
+/// A session chooser row from a server's `session-info -v` reply, or its
+/// plain `session-info` line when the server predates JSON.
+pub fn chooser_label(reply: &str) -> Option<String> {
+    let line = reply.lines().next()?.trim();
+    if line.is_empty() { return None; }
+    let Ok(info) = serde_json::from_str::<SessionInfo>(line) else { return Some(line.to_string()) };
+    let created = chrono::DateTime::from_timestamp(info.created, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%a %b %e %H:%M:%S %Y").to_string())
+        .unwrap_or_default();
+    let attached = if info.attached > 0 { " (attached)" } else { "" };
+    Some(format!("{}: {} windows (created {}){}", info.name, info.windows, created, attached))
+}
+
+/// The session chooser (prefix `s`, `choose-session`): every server in the
+/// registry as (registry name, row), sorted by name.  Files left behind by
+/// servers that are gone are removed; a server that is there but does
+/// not answer stays listed as not responding.
+pub fn chooser_sessions(dir: &str) -> Vec<(String, String)> {
+    let mut rows = Vec::new();
+    for (base, _) in list_session_records(dir) {
+        if !transport::exists(&base) {
+            let _ = std::fs::remove_file(std::path::Path::new(dir).join(format!("{base}.key")));
+            continue;
+        }
+        let label = send_cmd_response(&base, b"session-info -v\n").ok()
+            .and_then(|reply| chooser_label(&reply))
+            .unwrap_or_else(|| format!("{}: (not responding)", base));
+        rows.push((base, label));
+    }
+    rows
+}
+
+/// Clean up any stale session key files (where server pipe no longer exists,
+/// or the advertised server pid is dead)
+pub fn cleanup_stale_sessions() {
+    let dir = psmux_dir();
+    if let Ok(entries) = std::fs::read_dir(&dir) {
         for entry in entries.flatten() {
             let path = entry.path();
             if path.extension().map(|e| e == "key").unwrap_or(false) {
                 if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if !pipe::pipe_exists(stem) {
-                        let _ = std::fs::remove_file(&path);
+                    let dead = match read_session_record(stem) {
+                        Ok(_) => !transport::exists(stem),
+                        // Records from a newer psmux belong to that binary; leave them alone
+                        Err(e) => e.kind() != io::ErrorKind::InvalidData || !transport::exists(stem),
+                    };
+                    if dead {
+                        remove_session_files(stem);
                     }
                 }
             }
@@ -24,6 +267,16 @@ pub fn cleanup_stale_sessions() {
     }
 }
 
+/// Remove a session's registry file, and its socket file if it has one.
+pub fn remove_session_files(base: &str) {
+    let path = key_file_path(base);
+    let rec = std::fs::read_to_string(&path).ok().and_then(|t| parse_session_record(&t));
+    if let Some(Endpoint::Unix(socket)) = rec.and_then(|r| r.endpoint) {
+        let _ = std::fs::remove_file(socket);
+    }
+    let _ = std::fs::remove_file(path);
+}
+
 // Keep the old name as an alias for compatibility with callers
 pub fn cleanup_stale_port_files() {
     cleanup_stale_sessions();
@@ -31,54 +284,34 @@ pub fn cleanup_stale_port_files() {
 
 /// Read the session key from the key file
 pub fn read_session_key(session: &str) -> io::Result<String> {
-    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).unwrap_or_default();
-    let keypath = format!("{}\\.psmux\\{}.key", home, session);
-    std::fs::read_to_string(&keypath).map(|s| s.trim().to_string())
+    read_session_record(session).map(|r| r.key)
 }
 
-/// Send an authenticated command to a session via named pipe
-pub fn send_auth_cmd(session_name: &str, key: &str, cmd: &[u8]) -> io::Result<()> {
-    if let Ok(handle) = pipe::connect_to_pipe(session_name, 1000) {
-        let mut s = pipe::PipeStream::from_handle(handle);
-        let _ = write!(s, "AUTH {}\n", key);
-        let _ = s.write_all(cmd);
-        let _ = s.flush();
-    }
-    Ok(())
-}
-
-/// Send an authenticated command and get response via named pipe
-pub fn send_auth_cmd_response(session_name: &str, key: &str, cmd: &[u8]) -> io::Result<String> {
-    let handle = pipe::connect_to_pipe(session_name, 2000)?;
-    let mut s = pipe::PipeStream::from_handle(handle);
-    let _ = write!(s, "AUTH {}\n", key);
+/// Send a command to a session and get its response
+pub fn send_cmd_response(session_name: &str, cmd: &[u8]) -> io::Result<String> {
+    let mut s = transport::connect(session_name, 2000)?;
     let _ = s.write_all(cmd);
     let _ = s.flush();
-    let mut br = std::io::BufReader::new(&mut s);
-    let mut auth_line = String::new();
-    let _ = std::io::BufRead::read_line(&mut br, &mut auth_line);
     let mut buf = String::new();
-    let _ = std::io::Read::read_to_string(&mut br, &mut buf);
+    let _ = std::io::Read::read_to_string(&mut s, &mut buf);
     Ok(buf)
 }
 
 pub fn send_control(line: String) -> io::Result<()> {
     let target = env::var("PSMUX_TARGET_SESSION").ok().unwrap_or_else(|| "default".to_string());
     let full_target = env::var("PSMUX_TARGET_FULL").ok();
-    // Verify session exists by checking pipe
-    if !pipe::pipe_exists(&target) {
+    // Verify session exists by checking its endpoint
+    if !transport::exists(&target) {
         return Err(io::Error::new(io::ErrorKind::Other, format!("no server running on session '{}'", target)));
     }
-    let session_key = read_session_key(&target).unwrap_or_default();
-    let handle = pipe::connect_to_pipe(&target, 2000)?;
-    let mut stream = pipe::PipeStream::from_handle(handle);
-    let _ = write!(stream, "AUTH {}\n", session_key);
+    let mut stream = transport::connect(&target, 2000)?;
     if let Some(ref ft) = full_target {
         let _ = write!(stream, "TARGET {}\n", ft);
     }
     let _ = write!(stream, "{}", line);
     let _ = stream.flush();
-    // Read the "OK" response to drain the receive buffer before closing.
+    // Wait for the server to take the command (it closes the connection,
+    // or answers) before closing.
     let mut buf = [0u8; 64];
     let _ = std::io::Read::read(&mut stream, &mut buf);
     Ok(())
@@ -87,13 +320,10 @@ pub fn send_control(line: String) -> io::Result<()> {
 pub fn send_control_with_response(line: String) -> io::Result<String> {
     let target = env::var("PSMUX_TARGET_SESSION").ok().unwrap_or_else(|| "default".to_string());
     let full_target = env::var("PSMUX_TARGET_FULL").ok();
-    if !pipe::pipe_exists(&target) {
+    if !transport::exists(&target) {
         return Err(io::Error::new(io::ErrorKind::Other, format!("no server running on session '{}'", target)));
     }
-    let session_key = read_session_key(&target).unwrap_or_default();
-    let handle = pipe::connect_to_pipe(&target, 2000)?;
-    let mut stream = pipe::PipeStream::from_handle(handle);
-    let _ = write!(stream, "AUTH {}\n", session_key);
+    let mut stream = transport::connect(&target, 2000)?;
     if let Some(ref ft) = full_target {
         let _ = write!(stream, "TARGET {}\n", ft);
     }
@@ -109,32 +339,60 @@ pub fn send_control_with_response(line: String) -> io::Result<String> {
             Err(_) => break,
         }
     }
-    let result = String::from_utf8_lossy(&buf).to_string();
-    // Strip the "OK\n" AUTH response prefix if present
-    let result = if result.starts_with("OK\n") {
-        result[3..].to_string()
-    } else if result.starts_with("OK\r\n") {
-        result[4..].to_string()
-    } else {
-        result
-    };
-    Ok(result)
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+/// Ask the server whether the window and pane parts of the -t target
+/// exist.  Returns the server's error ("can't find window: work:7") when
+/// not, and None when they do or no server could be asked.
+pub fn target_error() -> Option<String> {
+    let resp = send_control_with_response("resolve-target\n".to_string()).ok()?;
+    let resp = resp.trim();
+    if resp.is_empty() { None } else { Some(resp.to_string()) }
+}
+
+/// The server a pane's `TMUX` value (`/tmp/psmux-<pid>/<socket>,0,0`)
+/// points at, found by the pid in its registry record.
+pub fn server_from_tmux_env(tmux: &str) -> Option<String> {
+    let pid = tmux_env_pid(tmux)?;
+    list_session_records(&psmux_dir()).into_iter()
+        .find(|(base, rec)| rec.pid == pid && transport::exists(base))
+        .map(|(base, _)| base)
+}
+
+/// For commands whose -t names a client or a session to move to rather than
+/// the server to ask: returns the -t value the global handler took and aims
+/// the request at the server the client is on instead (this pane's, else
+/// the one attached last), never at a session named like the -t value.
+pub fn retarget_to_client_server() -> Option<String> {
+    let target = env::var("PSMUX_TARGET_FULL").ok();
+    env::remove_var("PSMUX_TARGET_FULL");
+    match env::var("TMUX").ok().and_then(|t| server_from_tmux_env(&t)).or_else(resolve_last_session_name) {
+        Some(base) => env::set_var("PSMUX_TARGET_SESSION", base),
+        None => env::remove_var("PSMUX_TARGET_SESSION"),
+    }
+    target
+}
+
+fn tmux_env_pid(tmux: &str) -> Option<u32> {
+    let path = tmux.split(',').next()?;
+    let rest = &path[path.find("psmux-")? + "psmux-".len()..];
+    rest.split('/').next()?.parse().ok().filter(|p| *p != 0)
 }
 
 pub fn resolve_last_session_name() -> Option<String> {
-    let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).ok()?;
-    let dir = format!("{}\\.psmux", home);
+    let dir = psmux_dir();
     let last = std::fs::read_to_string(format!("{}\\last_session", dir)).ok();
     if let Some(name) = last {
         let name = name.trim().to_string();
-        if pipe::pipe_exists(&name) { return Some(name); }
+        if transport::exists(&name) { return Some(name); }
     }
     let mut picks: Vec<(String, std::time::SystemTime)> = Vec::new();
     if let Ok(rd) = std::fs::read_dir(&dir) {
         for e in rd.flatten() {
             if let Some(fname) = e.file_name().to_str() {
                 if let Some((base, ext)) = fname.rsplit_once('.') {
-                    if ext == "key" && pipe::pipe_exists(base) {
+                    if ext == "key" && transport::exists(base) {
                         if let Ok(md) = e.metadata() {
                             picks.push((base.to_string(), md.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH)));
                         }
@@ -149,7 +407,7 @@ pub fn resolve_last_session_name() -> Option<String> {
 
 pub fn resolve_default_session_name() -> Option<String> {
     if let Ok(name) = env::var("PSMUX_DEFAULT_SESSION") {
-        if pipe::pipe_exists(&name) { return Some(name); }
+        if transport::exists(&name) { return Some(name); }
     }
     let home = env::var("USERPROFILE").or_else(|_| env::var("HOME")).ok()?;
     let candidates = [format!("{}\\.psmuxrc", home), format!("{}\\.psmux\\pmuxrc", home)];
@@ -157,17 +415,16 @@ pub fn resolve_default_session_name() -> Option<String> {
         if let Ok(text) = std::fs::read_to_string(cfg) {
             let line = text.lines().find(|l| !l.trim().is_empty())?;
             let name = if let Some(rest) = line.strip_prefix("default-session ") { rest.trim().to_string() } else { line.trim().to_string() };
-            if pipe::pipe_exists(&name) { return Some(name); }
+            if transport::exists(&name) { return Some(name); }
         }
     }
     None
 }
 
-/// Send a control message to a session via named pipe (no auth).
+/// Send a control message to a session, without waiting for it.
 /// Used by app-mode to send commands to its own server.
 pub fn send_control_to_session(session_name: &str, msg: &str) -> io::Result<()> {
-    if let Ok(handle) = pipe::connect_to_pipe(session_name, 1000) {
-        let mut stream = pipe::PipeStream::from_handle(handle);
+    if let Ok(mut stream) = transport::connect(session_name, 1000) {
         let _ = stream.write_all(msg.as_bytes());
         let _ = stream.flush();
     }
@@ -181,6 +438,7 @@ pub fn reap_children_placeholder() -> io::Result<bool> { Ok(false) }
 pub struct TreeEntry {
     pub session_name: String,
     pub is_session_header: bool,
+    /// Display index (`#I`), not the position in the window list
     pub window_index: Option<usize>,
     pub window_name: String,
     pub window_panes: usize,
@@ -190,13 +448,10 @@ pub struct TreeEntry {
 }
 
 /// List all running sessions and their windows for choose-tree display.
-/// Queries each running server via named pipe for window list info.
-pub fn list_all_sessions_tree(current_session: &str, current_windows: &[(String, usize, String, bool)]) -> Vec<TreeEntry> {
-    let home = match env::var("USERPROFILE").or_else(|_| env::var("HOME")) {
-        Ok(h) => h,
-        Err(_) => return vec![],
-    };
-    let psmux_dir = format!("{}\\.psmux", home);
+/// Queries each running server for window list info.
+/// `current_windows` are (display index, name, panes, size, active).
+pub fn list_all_sessions_tree(current_session: &str, current_windows: &[(usize, String, usize, String, bool)]) -> Vec<TreeEntry> {
+    let psmux_dir = psmux_dir();
     let mut sessions: Vec<(String, std::time::SystemTime)> = Vec::new();
 
     if let Ok(entries) = std::fs::read_dir(&psmux_dir) {
@@ -204,7 +459,7 @@ pub fn list_all_sessions_tree(current_session: &str, current_windows: &[(String,
             let path = entry.path();
             if path.extension().map(|e| e == "key").unwrap_or(false) {
                 if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if pipe::pipe_exists(stem) {
+                    if transport::exists(stem) {
                         let mtime = entry.metadata()
                             .and_then(|m| m.modified())
                             .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
@@ -234,11 +489,11 @@ pub fn list_all_sessions_tree(current_session: &str, current_windows: &[(String,
 
         if is_current {
             // Use local data for the current session (fast, no IPC)
-            for (i, (wname, panes, size, is_active)) in current_windows.iter().enumerate() {
+            for (wi, wname, panes, size, is_active) in current_windows.iter() {
                 tree.push(TreeEntry {
                     session_name: name.clone(),
                     is_session_header: false,
-                    window_index: Some(i),
+                    window_index: Some(*wi),
                     window_name: wname.clone(),
                     window_panes: *panes,
                     window_size: size.clone(),
@@ -247,9 +502,8 @@ pub fn list_all_sessions_tree(current_session: &str, current_windows: &[(String,
                 });
             }
         } else {
-            // Query remote session for its window list via named pipe
-            let key = read_session_key(name).unwrap_or_default();
-            if let Ok(resp) = send_auth_cmd_response(name, &key, b"list-windows -F \"#{window_index}:#{window_name}:#{window_panes}:#{window_width}x#{window_height}:#{window_active}\"\n") {
+            // Query remote session for its window list
+            if let Ok(resp) = send_cmd_response(name, b"list-windows -F \"#{window_index}:#{window_name}:#{window_panes}:#{window_width}x#{window_height}:#{window_active}\"\n") {
                 for line in resp.lines() {
                     let line = line.trim();
                     if line.is_empty() { continue; }
@@ -364,3 +618,131 @@ pub fn kill_remaining_server_processes() {
         .args(&["-f", "psmux|pmux"])
         .status();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(tag: &str) -> std::path::PathBuf {
+        let d = std::env::temp_dir().join(format!("psmux-registry-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&d);
+        std::fs::create_dir_all(&d).unwrap();
+        d
+    }
+
+    #[test]
+    fn client_targets_are_not_sessions() {
+        // detach-client -t client3 outside any pane, with no server running:
+        // the -t goes on to the server as the client, not as the session to ask
+        let dir = scratch_dir("client-target");
+        env::set_var("PSMUX_DIR", &dir);
+        env::remove_var("TMUX");
+        env::set_var("PSMUX_TARGET_FULL", "client3");
+        env::set_var("PSMUX_TARGET_SESSION", "client3");
+        assert_eq!(retarget_to_client_server().as_deref(), Some("client3"));
+        assert!(env::var("PSMUX_TARGET_SESSION").is_err());
+        assert!(env::var("PSMUX_TARGET_FULL").is_err());
+        env::remove_var("PSMUX_DIR");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parses_current_json_format() {
+        let rec = parse_session_record(r#"{"version":1,"key":"abc123","pid":42,"psmux":"0.4.1","socket":"work"}"#).unwrap();
+        assert_eq!(rec.version, 1);
+        assert_eq!(rec.key, "abc123");
+        assert_eq!(rec.pid, 42);
+        assert_eq!(rec.socket.as_deref(), Some("work"));
+        // Written before the transports: the named pipe, with AUTH
+        assert_eq!(rec.endpoint, None);
+        let rec = parse_session_record(r#"{"version":1,"key":"k","pid":42,"socket":"tcp","endpoint":{"tcp":50123}}"#).unwrap();
+        assert_eq!(rec.endpoint, Some(Endpoint::Tcp(50123)));
+    }
+
+    #[test]
+    fn session_info_round_trips_through_json() {
+        let mut app = AppState::new("work".to_string());
+        app.socket_name = Some("ci".to_string());
+        app.clients.push(crate::types::ClientConn { size: (120, 40), ..crate::types::ClientConn::test(3) });
+        app.attached_clients = 1;
+        let info = session_info(&app);
+        assert_eq!(info.name, "work");
+        assert_eq!(info.socket_name.as_deref(), Some("ci"));
+        assert_eq!(info.clients, vec![ClientInfo { id: 3, width: 120, height: 40 }]);
+        assert_eq!(info.prefix, "C-b");
+        assert_eq!(info.version, VERSION);
+        assert!(info.last_activity >= info.created);
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<SessionInfo>(&json).unwrap(), info);
+        // Older servers' objects without the optional fields still parse
+        let minimal = r#"{"name":"a","created":1,"last_activity":1,"attached":0,"windows":1,"active_window":"pwsh","prefix":"C-b","version":"0.1.0","extra":true}"#;
+        let old: SessionInfo = serde_json::from_str(minimal).unwrap();
+        assert!(old.clients.is_empty() && old.socket_name.is_none() && old.group.is_none());
+    }
+
+    #[test]
+    fn parses_legacy_bare_key() {
+        let rec = parse_session_record("0123456789abcdef\r\n").unwrap();
+        assert_eq!(rec.version, 0);
+        assert_eq!(rec.key, "0123456789abcdef");
+        assert_eq!(rec.pid, 0);
+        assert!(parse_session_record("").is_none());
+        assert!(parse_session_record("not a key").is_none());
+    }
+
+    #[test]
+    fn rejects_dead_pid_and_future_version() {
+        let mut rec = parse_session_record(r#"{"version":1,"key":"k","pid":7}"#).unwrap();
+        assert_eq!(validate_session_record(&rec, |_| true).unwrap(), "k");
+        let err = validate_session_record(&rec, |_| false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        // Legacy records carry no pid, so liveness cannot be checked
+        let legacy = parse_session_record("k").unwrap();
+        assert!(validate_session_record(&legacy, |_| false).is_ok());
+        rec.version = REGISTRY_VERSION + 1;
+        let err = validate_session_record(&rec, |_| true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn separate_dirs_list_independently() {
+        let a = scratch_dir("a");
+        let b = scratch_dir("b");
+        std::fs::write(a.join("default.key"), r#"{"version":1,"key":"ka","pid":1}"#).unwrap();
+        std::fs::write(a.join("work.key"), "legacykey").unwrap();
+        std::fs::write(b.join("default.key"), r#"{"version":1,"key":"kb","pid":2}"#).unwrap();
+        std::fs::write(b.join("last_session"), "default").unwrap();
+        let la = list_session_records(a.to_str().unwrap());
+        let lb = list_session_records(b.to_str().unwrap());
+        assert_eq!(la.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), vec!["default", "work"]);
+        assert_eq!(la[0].1.key, "ka");
+        assert_eq!(la[1].1.version, 0);
+        assert_eq!(lb.len(), 1);
+        assert_eq!(lb[0].1.key, "kb");
+        let _ = std::fs::remove_dir_all(&a);
+        let _ = std::fs::remove_dir_all(&b);
+    }
+
+    #[test]
+    fn server_pid_from_tmux_env() {
+        assert_eq!(tmux_env_pid("/tmp/psmux-4242/default,0,0"), Some(4242));
+        assert_eq!(tmux_env_pid("/tmp/psmux-17/ci,0,0"), Some(17));
+        assert_eq!(tmux_env_pid("/tmp/tmux-1000/default,123,0"), None);
+        assert_eq!(tmux_env_pid(""), None);
+    }
+
+    #[test]
+    fn chooser_rows_from_session_info() {
+        let mut app = AppState::new("work".to_string());
+        app.attached_clients = 1;
+        let reply = serde_json::to_string(&session_info(&app)).unwrap();
+        let created = app.created_at.format("%a %b %e %H:%M:%S %Y");
+        assert_eq!(chooser_label(&reply).unwrap(), format!("work: 0 windows (created {}) (attached)", created));
+        app.attached_clients = 0;
+        let reply = serde_json::to_string(&session_info(&app)).unwrap();
+        assert!(!chooser_label(&reply).unwrap().ends_with("(attached)"));
+        // An older server answers -v with its plain line
+        assert_eq!(chooser_label("old: 2 windows (created x)\n").unwrap(), "old: 2 windows (created x)");
+        assert_eq!(chooser_label(""), None);
+    }
+}
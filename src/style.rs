@@ -156,12 +156,52 @@ fn apply_modifier(token: &str, style: &mut Style) {
 
 // ─── Inline style parsing ───────────────────────────────────────────────────
 
+/// A clickable part of the status line, marked in a format with
+/// `#[range=window|N]`, `#[range=pane|%N]` or `#[range=user|name]` and
+/// ended by `#[norange]` (or the next range).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusRange {
+    /// A window index, or `@id`
+    Window(String),
+    /// A pane, `%id`
+    Pane(String),
+    User(String),
+}
+
+impl StatusRange {
+    fn parse(spec: &str) -> Option<StatusRange> {
+        let (kind, arg) = spec.split_once('|').unwrap_or((spec, ""));
+        match kind {
+            "window" => Some(StatusRange::Window(arg.to_string())),
+            "pane" => Some(StatusRange::Pane(arg.to_string())),
+            "user" => Some(StatusRange::User(arg.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The command a click on the range runs.
+    pub fn command(&self) -> String {
+        match self {
+            StatusRange::Window(w) if w.starts_with('@') => format!("select-window -t {}", w),
+            StatusRange::Window(w) => format!("select-window -t :{}", w),
+            StatusRange::Pane(p) => format!("select-pane -t {}", p),
+            StatusRange::User(name) => format!("set-hook -R @user-range-{}-click", name),
+        }
+    }
+}
+
 /// Parse inline `#[fg=...,bg=...,bold]` style directives from pre-expanded text.
 ///
 /// Unlike `parse_status()`, this does NOT re-expand status variables.
 /// Use for text already expanded by the format engine (e.g. window tab labels).
 pub fn parse_inline_styles(text: &str, base_style: Style) -> Vec<Span<'static>> {
-    let mut spans: Vec<Span<'static>> = Vec::new();
+    parse_inline_ranges(text, base_style, None).into_iter().map(|(s, _)| s).collect()
+}
+
+/// `parse_inline_styles` that also gives each span the range it is in,
+/// starting in `range`.
+pub fn parse_inline_ranges(text: &str, base_style: Style, mut range: Option<StatusRange>) -> Vec<(Span<'static>, Option<StatusRange>)> {
+    let mut spans = Vec::new();
     let mut cur_style = base_style;
     let mut i = 0;
     let bytes = text.as_bytes();
@@ -174,6 +214,8 @@ pub fn parse_inline_styles(text: &str, base_style: Style) -> Vec<Span<'static>>
                     if p.starts_with("fg=") { cur_style = cur_style.fg(map_color(&p[3..])); }
                     else if p.starts_with("bg=") { cur_style = cur_style.bg(map_color(&p[3..])); }
                     else if p == "default" || p == "none" { cur_style = base_style; }
+                    else if let Some(spec) = p.strip_prefix("range=") { range = StatusRange::parse(spec); }
+                    else if p == "norange" { range = None; }
                     else { apply_modifier(p, &mut cur_style); }
                 }
                 i += 2 + end + 1;
@@ -186,18 +228,60 @@ pub fn parse_inline_styles(text: &str, base_style: Style) -> Vec<Span<'static>>
         }
         let chunk = &text[i..j];
         if !chunk.is_empty() {
-            spans.push(Span::styled(chunk.to_string(), cur_style));
+            spans.push((Span::styled(chunk.to_string(), cur_style), range.clone()));
         }
         i = j;
     }
     spans
 }
 
+/// The columns each range covers in `spans` laid out from column `x`:
+/// (first column, column after, range).
+pub fn range_columns(spans: &[(Span<'static>, Option<StatusRange>)], x: u16) -> Vec<(u16, u16, StatusRange)> {
+    let mut out: Vec<(u16, u16, StatusRange)> = Vec::new();
+    let mut at = x;
+    for (span, range) in spans {
+        let end = at.saturating_add(span.width() as u16);
+        if let Some(r) = range {
+            match out.last_mut() {
+                Some(last) if last.1 == at && last.2 == *r => last.1 = end,
+                _ => out.push((at, end, r.clone())),
+            }
+        }
+        at = end;
+    }
+    out
+}
+
 /// Calculate the visual display width of styled spans (sum of text content widths).
 pub fn spans_visual_width(spans: &[Span]) -> usize {
     spans.iter().map(|s| s.content.len()).sum()
 }
 
+// ─── Prefix indicator ───────────────────────────────────────────────────────
+
+/// Text of the status-line key-state indicator: the prefix key in caret form
+/// while it is armed (or the prefix table is current), the table name while
+/// another non-root table is, `COPY` in copy mode, and nothing otherwise.
+pub fn prefix_indicator_text(prefix: &str, armed: bool, key_table: Option<&str>, copy_mode: bool) -> Option<String> {
+    if armed || key_table == Some("prefix") {
+        return Some(match prefix.strip_prefix("C-") {
+            Some(k) if k.chars().count() == 1 => format!("^{}", k.to_uppercase()),
+            _ => prefix.to_string(),
+        });
+    }
+    match key_table {
+        Some(t) if t != "root" => Some(t.to_string()),
+        _ if copy_mode => Some("COPY".to_string()),
+        _ => None,
+    }
+}
+
+/// The indicator as a span in `prefix-indicator-style` over the status style.
+pub fn prefix_indicator_spans(text: Option<&str>, style: &str, base: Style) -> Vec<Span<'static>> {
+    text.map(|t| vec![Span::styled(format!(" {} ", t), base.patch(parse_tmux_style(style)))]).unwrap_or_default()
+}
+
 // ─── Status bar parsing ─────────────────────────────────────────────────────
 
 /// Expand simple status variables (`#I`, `#W`, `#S`, `%H:%M`) in a fragment.
@@ -241,3 +325,55 @@ pub fn parse_status(fmt: &str, session_name: &str, win_name: &str, win_idx: usiz
     }
     spans
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indicator spans for one key state, as (text, reversed).
+    fn shown(armed: bool, table: Option<&str>, copy_mode: bool) -> Vec<(String, bool)> {
+        let base = Style::default().fg(Color::Black).bg(Color::Green);
+        let text = prefix_indicator_text("C-b", armed, table, copy_mode);
+        prefix_indicator_spans(text.as_deref(), "reverse", base).into_iter()
+            .map(|s| (s.content.to_string(), s.style.add_modifier.contains(Modifier::REVERSED)))
+            .collect()
+    }
+
+    #[test]
+    fn prefix_indicator_follows_the_key_state() {
+        // Idle, then the prefix is pressed, then the binding resolves it
+        assert!(shown(false, None, false).is_empty());
+        assert_eq!(shown(true, None, false), vec![(" ^B ".to_string(), true)]);
+        assert!(shown(false, Some("root"), false).is_empty());
+        // switch-client -T resize, then back to root in copy mode
+        assert_eq!(shown(false, Some("resize"), false), vec![(" resize ".to_string(), true)]);
+        assert_eq!(shown(false, None, true), vec![(" COPY ".to_string(), true)]);
+        // Arming the prefix from copy mode shows the prefix
+        assert_eq!(shown(true, None, true), vec![(" ^B ".to_string(), true)]);
+        assert_eq!(shown(false, Some("prefix"), false), vec![(" ^B ".to_string(), true)]);
+        assert_eq!(prefix_indicator_text("M-a", true, None, false).as_deref(), Some("M-a"));
+    }
+
+    #[test]
+    fn ranges_mark_clickable_columns() {
+        let text = "#[fg=red]ab#[range=window|3]cd#[bold]é#[norange] #[range=pane|%7]x#[range=user|cpu]yy#[range=bogus]z";
+        let spans = parse_inline_ranges(text, Style::default(), None);
+        let plain: String = spans.iter().map(|(s, _)| s.content.as_ref()).collect();
+        assert_eq!(plain, "abcdé xyyz");
+        let cols = range_columns(&spans, 10);
+        assert_eq!(cols, vec![
+            (12, 15, StatusRange::Window("3".into())),
+            (16, 17, StatusRange::Pane("%7".into())),
+            (17, 19, StatusRange::User("cpu".into())),
+        ]);
+        assert_eq!(cols[0].2.command(), "select-window -t :3");
+        assert_eq!(StatusRange::Window("@4".into()).command(), "select-window -t @4");
+        assert_eq!(cols[1].2.command(), "select-pane -t %7");
+        assert_eq!(cols[2].2.command(), "set-hook -R @user-range-cpu-click");
+        // A window's tab starts in its range and may end it early
+        let tab = parse_inline_ranges("1:sh#[norange]*", Style::default(), Some(StatusRange::Window("@1".into())));
+        assert_eq!(range_columns(&tab, 0), vec![(0, 4, StatusRange::Window("@1".into()))]);
+        // The styles are those parse_inline_styles gives
+        assert_eq!(spans.iter().map(|(s, _)| s.clone()).collect::<Vec<_>>(), parse_inline_styles(text, Style::default()));
+    }
+}
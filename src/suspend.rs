@@ -0,0 +1,215 @@
+//! suspend-client (prefix C-z): the attached client hands the terminal back
+//! to the shell it was started from until the user resumes it.
+//!
+//! Windows has no SIGTSTP, so the client leaves the alternate screen and
+//! raw mode, prints [`SUSPEND_PROMPT`] and waits for Enter.  Under an
+//! MSYS/Cygwin pty (mintty) it stops itself with a real SIGTSTP instead,
+//! and `fg` resumes it.  Either way the server keeps the client attached
+//! but answers its frame requests with "NC" and holds back messages until
+//! it resumes (`client-suspend 1` / `client-suspend 0`).
+
+use std::io::{self, Write};
+
+/// Printed on the parent shell's screen while the client is suspended.
+pub const SUSPEND_PROMPT: &str = "psmux client suspended — press Enter to resume (or run 'exit' to detach)";
+
+/// How a suspended client came back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Resumed {
+    /// Re-enter the session with a full redraw
+    Attach,
+    /// The user typed `exit`: detach instead
+    Detach,
+}
+
+/// The terminal operations a suspend goes through.
+pub trait SuspendTerminal {
+    /// Leave the alternate screen and raw mode, restoring the cooked console
+    fn leave(&mut self) -> io::Result<()>;
+    /// Back into raw mode and the alternate screen, cleared for a full redraw
+    fn enter(&mut self) -> io::Result<()>;
+    /// Print `prompt` and read one line from the console
+    fn prompt(&mut self, prompt: &str) -> io::Result<String>;
+    /// Stop this process with SIGTSTP; returns once it is continued
+    fn stop_self(&mut self) -> io::Result<()>;
+}
+
+/// A pty provided by MSYS2 or Cygwin (mintty), where job control works.
+pub fn is_posix_pty(var: impl Fn(&str) -> Option<String>) -> bool {
+    let posix_env = var("MSYSTEM").is_some() || var("OSTYPE").is_some_and(|o| o.contains("cygwin") || o.contains("msys"));
+    posix_env && var("TERM_PROGRAM").as_deref() == Some("mintty")
+}
+
+/// Suspend until the user resumes.  `notify` tells the server the client
+/// is suspended (true) and back (false); it is called with false even
+/// when the terminal could not be restored, so frames are never held
+/// back for good.
+pub fn suspend<T: SuspendTerminal>(term: &mut T, posix_pty: bool, mut notify: impl FnMut(bool)) -> io::Result<Resumed> {
+    notify(true);
+    let resumed = (|| {
+        term.leave()?;
+        let resumed = if posix_pty {
+            term.stop_self()?;
+            Resumed::Attach
+        } else {
+            match term.prompt(SUSPEND_PROMPT)?.trim() {
+                "exit" => Resumed::Detach,
+                _ => Resumed::Attach,
+            }
+        };
+        term.enter()?;
+        Ok(resumed)
+    })();
+    notify(false);
+    resumed
+}
+
+/// What the connection writer sends for `text` while its client is
+/// suspended: "NC" in place of a frame, so requests still get an answer,
+/// and nothing for messages and views, which would be stale on resume.
+pub fn gate_line(text: &str, suspended: bool) -> Option<&str> {
+    if !suspended { return Some(text); }
+    if text.starts_with('{') { return Some("NC"); }
+    match text.strip_prefix('%') {
+        Some(d) if d.starts_with("message") || d.starts_with("view ") => None,
+        _ => Some(text),
+    }
+}
+
+/// The real console: crossterm on the client's ratatui terminal.
+pub struct Console<'a, W: io::Write> {
+    pub terminal: &'a mut ratatui::Terminal<ratatui::backend::CrosstermBackend<W>>,
+    pub input: &'a crate::ssh_input::InputSource,
+}
+
+impl<W: io::Write> SuspendTerminal for Console<'_, W> {
+    fn leave(&mut self) -> io::Result<()> {
+        use crossterm::event::{DisableBracketedPaste, DisableMouseCapture};
+        crossterm::terminal::disable_raw_mode()?;
+        let out = self.terminal.backend_mut();
+        crossterm::execute!(out, crossterm::style::Print("\x1b[0m\x1b[0 q"), DisableMouseCapture, DisableBracketedPaste,
+            crossterm::terminal::LeaveAlternateScreen, crossterm::cursor::Show)
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        use crossterm::event::{EnableBracketedPaste, EnableMouseCapture};
+        crossterm::terminal::enable_raw_mode()?;
+        let out = self.terminal.backend_mut();
+        crossterm::execute!(out, crossterm::terminal::EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
+        crate::rendering::apply_cursor_style(out)?;
+        self.terminal.clear()
+    }
+
+    fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+        let out = self.terminal.backend_mut();
+        write!(out, "\r\n{}\r\n", prompt)?;
+        out.flush()?;
+        let mut line = String::new();
+        if matches!(self.input, crate::ssh_input::InputSource::Crossterm) {
+            io::stdin().read_line(&mut line)?;
+            return Ok(line);
+        }
+        // Over SSH the VT reader thread owns stdin: take keys from it, echoing
+        use crossterm::event::{Event, KeyCode, KeyEventKind};
+        loop {
+            let Some(Event::Key(k)) = self.input.read_timeout(std::time::Duration::from_millis(250))? else { continue };
+            if k.kind == KeyEventKind::Release { continue; }
+            match k.code {
+                KeyCode::Enter => { write!(out, "\r\n")?; out.flush()?; return Ok(line); }
+                KeyCode::Backspace if line.pop().is_some() => { write!(out, "\x08 \x08")?; }
+                KeyCode::Char(c) => { line.push(c); write!(out, "{}", c)?; }
+                _ => {}
+            }
+            out.flush()?;
+        }
+    }
+
+    fn stop_self(&mut self) -> io::Result<()> {
+        // The pty's kill reaches this process through its Cygwin pid shim
+        std::process::Command::new("kill")
+            .args(["-TSTP", &std::process::id().to_string()])
+            .status()
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records terminal transitions, and what the connection writer lets
+    /// through while the client is at the prompt.
+    struct Fake {
+        log: Rc<RefCell<Vec<String>>>,
+        suspended: Rc<RefCell<bool>>,
+        answer: &'static str,
+    }
+
+    impl Fake {
+        fn push(&self, s: &str) { self.log.borrow_mut().push(s.to_string()); }
+        /// The server pushing a frame and a message at the connection
+        fn server_writes(&self) {
+            for line in [r#"{"layout":{}}"#, "%message hi", "%detach"] {
+                if let Some(out) = gate_line(line, *self.suspended.borrow()) { self.push(&format!("wrote {}", out)); }
+            }
+        }
+    }
+
+    impl SuspendTerminal for Fake {
+        fn leave(&mut self) -> io::Result<()> { self.push("cooked"); Ok(()) }
+        fn enter(&mut self) -> io::Result<()> { self.push("raw"); Ok(()) }
+        fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+            assert_eq!(prompt, SUSPEND_PROMPT);
+            self.server_writes();
+            self.push("prompt");
+            Ok(self.answer.to_string())
+        }
+        fn stop_self(&mut self) -> io::Result<()> { self.server_writes(); self.push("SIGTSTP"); Ok(()) }
+    }
+
+    fn run(answer: &'static str, posix: bool) -> (Resumed, Vec<String>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let suspended = Rc::new(RefCell::new(false));
+        let mut term = Fake { log: log.clone(), suspended: suspended.clone(), answer };
+        let notify_log = log.clone();
+        let r = suspend(&mut term, posix, |on| {
+            *suspended.borrow_mut() = on;
+            notify_log.borrow_mut().push(format!("client-suspend {}", on as u8));
+        }).unwrap();
+        term.server_writes();
+        let out = log.borrow().clone();
+        (r, out)
+    }
+
+    #[test]
+    fn suspend_and_resume_on_enter() {
+        let (r, log) = run("\r\n", false);
+        assert_eq!(r, Resumed::Attach);
+        assert_eq!(log, [
+            "client-suspend 1", "cooked",
+            // Frames become NC and messages are held back while suspended
+            "wrote NC", "wrote %detach", "prompt",
+            "raw", "client-suspend 0",
+            r#"wrote {"layout":{}}"#, "wrote %message hi", "wrote %detach",
+        ]);
+    }
+
+    #[test]
+    fn exit_at_the_prompt_detaches() {
+        assert_eq!(run("exit\n", false).0, Resumed::Detach);
+    }
+
+    #[test]
+    fn posix_ptys_get_a_real_sigtstp() {
+        let (r, log) = run("", true);
+        assert_eq!(r, Resumed::Attach);
+        assert!(log.iter().any(|l| l == "SIGTSTP"));
+        assert!(!log.iter().any(|l| l == "prompt"));
+        let env = |pairs: &'static [(&'static str, &'static str)]| move |k: &str| pairs.iter().find(|p| p.0 == k).map(|p| p.1.to_string());
+        assert!(is_posix_pty(env(&[("MSYSTEM", "MINGW64"), ("TERM_PROGRAM", "mintty")])));
+        assert!(!is_posix_pty(env(&[("MSYSTEM", "MINGW64"), ("TERM_PROGRAM", "vscode")])));
+        assert!(!is_posix_pty(env(&[("TERM_PROGRAM", "mintty")])));
+    }
+}
@@ -0,0 +1,265 @@
+//! Client/server transport.
+//!
+//! A server listens on one of three endpoints, picked when it starts and
+//! written to its registry record so clients know how to reach it:
+//!
+//! - an AF_UNIX socket file in the registry dir (Windows 10 1803+), the default;
+//! - a named pipe (`pipe.rs`) where AF_UNIX sockets are not available;
+//! - 127.0.0.1 TCP, only with `-L tcp`.
+//!
+//! The socket file and the pipe carry a DACL admitting only the server's
+//! user (and SYSTEM), so a client on either is already known and skips the
+//! key-file AUTH step.  Any local user can reach a TCP port, so there
+//! `connect` sends the session key before handing the stream back.
+
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+/// The `-L` socket name that selects the TCP transport.
+pub const TCP_SOCKET_NAME: &str = "tcp";
+
+/// One end of a client/server connection.
+pub trait Transport: Read + Write + Send + Sync {
+    /// A second handle on the same connection, for a reader/writer split.
+    fn try_clone(&self) -> io::Result<Stream>;
+
+    /// Limit on a blocking read (`None`: wait for ever).
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Send small writes straight away (TCP_NODELAY).
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()>;
+
+    /// Server side: cut the client off.  Reads and writes on every handle
+    /// to the connection fail from then on, so a blocked reader returns.
+    fn disconnect(&self);
+}
+
+/// A connection over whichever transport the server uses.
+pub type Stream = Box<dyn Transport>;
+
+/// A server's endpoint, accepting clients.
+pub trait Listener: Send {
+    /// Block until the next client connects.
+    fn accept(&mut self) -> io::Result<Stream>;
+
+    /// Where clients reach this listener, for the registry record.
+    fn endpoint(&self) -> Endpoint;
+}
+
+/// Where a server listens, as written to its registry record.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Endpoint {
+    /// Named pipe path (`\\.\pipe\psmux-<sid>-<base>`)
+    Pipe(String),
+    /// AF_UNIX socket file
+    Unix(String),
+    /// Port on 127.0.0.1
+    Tcp(u16),
+}
+
+impl Endpoint {
+    /// Only the server's user can connect, so no session key is asked for.
+    pub fn trusted(&self) -> bool {
+        !matches!(self, Endpoint::Tcp(_))
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Pipe(path) | Endpoint::Unix(path) => f.write_str(path),
+            Endpoint::Tcp(port) => write!(f, "127.0.0.1:{}", port),
+        }
+    }
+}
+
+/// Start listening for session `base`: on TCP for `-L tcp`, otherwise on
+/// an AF_UNIX socket, falling back to a named pipe where that fails.
+pub fn listen(base: &str, socket_name: Option<&str>) -> io::Result<Box<dyn Listener>> {
+    if socket_name == Some(TCP_SOCKET_NAME) {
+        return Ok(Box::new(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?));
+    }
+    if let Some(path) = socket_path(&crate::session::psmux_dir(), base) {
+        if let Ok(listener) = crate::afunix::UnixListener::bind(&path) {
+            return Ok(Box::new(listener));
+        }
+    }
+    Ok(Box::new(crate::pipe::PipeListener::bind(base)?))
+}
+
+/// The AF_UNIX socket file for session `base` in registry dir `dir`, unless
+/// the path does not fit in a `sockaddr_un` (ASCII only, to be safe).
+fn socket_path(dir: &str, base: &str) -> Option<String> {
+    let path = format!("{}\\{}.sock", dir, base);
+    (path.is_ascii() && path.len() < crate::afunix::SUN_PATH_LEN).then_some(path)
+}
+
+/// Where session `base`'s server listens, and the key to send it first if
+/// it wants one.  A record from before the transports means the named pipe
+/// with AUTH; without a readable record the pipe is tried as it is.
+fn lookup(base: &str) -> (Endpoint, Option<String>) {
+    match crate::session::read_session_record(base) {
+        Ok(rec) => match rec.endpoint {
+            Some(endpoint) => {
+                let key = (!endpoint.trusted()).then_some(rec.key);
+                (endpoint, key)
+            }
+            None => (Endpoint::Pipe(crate::pipe::pipe_name_for_session(base)), Some(rec.key)),
+        },
+        Err(_) => (Endpoint::Pipe(crate::pipe::pipe_name_for_session(base)), None),
+    }
+}
+
+/// Open a connection to session `base`, waiting up to `timeout_ms`.
+pub fn connect(base: &str, timeout_ms: u32) -> io::Result<Stream> {
+    let (endpoint, key) = lookup(base);
+    connect_to(&endpoint, key.as_deref(), timeout_ms)
+}
+
+/// Open a connection to `endpoint`.  With a `key`, the AUTH step is done
+/// here, so callers see the same stream on every transport.
+fn connect_to(endpoint: &Endpoint, key: Option<&str>, timeout_ms: u32) -> io::Result<Stream> {
+    let timeout = Duration::from_millis(timeout_ms.max(1) as u64);
+    let mut stream: Stream = match endpoint {
+        Endpoint::Pipe(path) => Box::new(crate::pipe::connect(path, timeout_ms)?),
+        Endpoint::Unix(path) => Box::new(crate::afunix::UnixStream::connect(path)?),
+        Endpoint::Tcp(port) => Box::new(TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, *port)), timeout)?),
+    };
+    if let Some(key) = key {
+        authenticate(&mut *stream, key, timeout)?;
+    }
+    Ok(stream)
+}
+
+/// Send the session key and check the server's reply.  The reply is read a
+/// byte at a time so nothing the server sends after it is consumed here.
+fn authenticate(stream: &mut dyn Transport, key: &str, timeout: Duration) -> io::Result<()> {
+    stream.write_all(format!("AUTH {}\n", key).as_bytes())?;
+    stream.flush()?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while reply.last() != Some(&b'\n') {
+        if stream.read(&mut byte)? == 0 { break; }
+        reply.push(byte[0]);
+    }
+    stream.set_read_timeout(None)?;
+    let reply = String::from_utf8_lossy(&reply);
+    match reply.trim_end() {
+        "OK" => Ok(()),
+        r if r.starts_with("ERROR") => Err(io::Error::new(io::ErrorKind::PermissionDenied, r.to_string())),
+        r => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply to AUTH: {}", crate::sanitize::text(r, 80)))),
+    }
+}
+
+/// Is session `base`'s server there to connect to?
+pub fn exists(base: &str) -> bool {
+    match lookup(base).0 {
+        Endpoint::Pipe(path) => crate::pipe::exists(&path),
+        Endpoint::Unix(path) => crate::afunix::UnixStream::connect(&path).is_ok(),
+        Endpoint::Tcp(port) => TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)), Duration::from_millis(200)).is_ok(),
+    }
+}
+
+impl Transport for TcpStream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+
+    fn disconnect(&self) {
+        let _ = self.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+impl Listener for TcpListener {
+    fn accept(&mut self) -> io::Result<Stream> {
+        let (stream, _) = TcpListener::accept(self)?;
+        Ok(Box::new(stream))
+    }
+
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Tcp(self.local_addr().map_or(0, |a| a.port()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    /// A one-connection server on 127.0.0.1: answers the first line with
+    /// `reply`, then echoes every further line back.
+    fn tcp_server(reply: &'static str) -> (Endpoint, std::thread::JoinHandle<String>) {
+        let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let endpoint = Listener::endpoint(&listener);
+        let server = std::thread::spawn(move || {
+            let stream = Listener::accept(&mut listener).unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream);
+            let mut first = String::new();
+            lines.read_line(&mut first).unwrap();
+            writer.write_all(reply.as_bytes()).unwrap();
+            for line in lines.lines() {
+                let Ok(line) = line else { break };
+                writeln!(writer, "echo {}", line).unwrap();
+            }
+            first
+        });
+        (endpoint, server)
+    }
+
+    #[test]
+    fn tcp_connections_send_the_key_first() {
+        let (endpoint, server) = tcp_server("OK\n");
+        let mut stream = connect_to(&endpoint, Some("k3y"), 2000).unwrap();
+        // The reply to AUTH is read here; the caller's first line is its own
+        stream.write_all(b"list-windows\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream.try_clone().unwrap()).read_line(&mut line).unwrap();
+        assert_eq!(line, "echo list-windows\n");
+        drop(stream);
+        assert_eq!(server.join().unwrap(), "AUTH k3y\n");
+    }
+
+    #[test]
+    fn refused_keys_are_errors() {
+        let (endpoint, _server) = tcp_server("ERROR: Invalid session key\n");
+        let err = connect_to(&endpoint, Some("wrong"), 2000).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "ERROR: Invalid session key");
+        let (endpoint, _server) = tcp_server("{\"layout\":1}\n");
+        assert_eq!(connect_to(&endpoint, Some("k3y"), 2000).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn only_tcp_asks_for_the_key() {
+        assert!(Endpoint::Pipe(r"\\.\pipe\psmux-S-1-5-21-1-work".into()).trusted());
+        assert!(Endpoint::Unix(r"C:\Users\a\.psmux\work.sock".into()).trusted());
+        assert!(!Endpoint::Tcp(50123).trusted());
+        assert_eq!(Endpoint::Tcp(50123).to_string(), "127.0.0.1:50123");
+        // As the registry record carries it
+        assert_eq!(serde_json::to_string(&Endpoint::Tcp(50123)).unwrap(), r#"{"tcp":50123}"#);
+        let unix: Endpoint = serde_json::from_str(r#"{"unix":"C:\\psmux\\work.sock"}"#).unwrap();
+        assert_eq!(unix, Endpoint::Unix(r"C:\psmux\work.sock".into()));
+    }
+
+    #[test]
+    fn socket_paths_fit_in_sockaddr_un() {
+        assert_eq!(socket_path(r"C:\Users\a\.psmux", "ci__work").as_deref(), Some(r"C:\Users\a\.psmux\ci__work.sock"));
+        // Too long for sun_path, or not ASCII: the server takes a named pipe
+        assert_eq!(socket_path(&format!(r"C:\{}", "d".repeat(100)), "work"), None);
+        assert_eq!(socket_path(r"C:\Users\zoë\.psmux", "work"), None);
+    }
+}
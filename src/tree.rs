@@ -1,7 +1,8 @@
 use std::io;
+use std::time::{Duration, Instant};
 use ratatui::prelude::*;
 
-use crate::types::{AppState, Pane, Node, LayoutKind, DragState};
+use crate::types::{AppState, Pane, PaneSize, Node, LayoutKind, DragState, Window};
 use crate::platform::process_kill;
 
 /// Split an area into sub-rects with 1px gaps between them for separator lines.
@@ -44,6 +45,19 @@ pub fn split_with_gaps(is_horizontal: bool, sizes: &[u16], area: Rect) -> Vec<Re
     rects
 }
 
+/// Splitter glyphs (vertical, horizontal) for `pane-border-lines`.  Panes
+/// share one splitter between neighbours and have no border at the window
+/// edges, so this only changes how that one column or row is drawn.
+pub fn border_glyphs(lines: &str) -> (char, char) {
+    match lines {
+        "double" => ('║', '═'),
+        "heavy" => ('┃', '━'),
+        "simple" => ('|', '-'),
+        "none" => (' ', ' '),
+        _ => ('│', '─'),
+    }
+}
+
 pub fn active_pane_mut<'a>(node: &'a mut Node, path: &Vec<usize>) -> Option<&'a mut Pane> {
     let mut cur = node;
     for &idx in path.iter() {
@@ -81,6 +95,23 @@ pub fn replace_leaf_with_split(node: &mut Node, path: &Vec<usize>, kind: LayoutK
     }
 }
 
+/// Fewest cells a side of a sized split is left with.
+pub const MIN_SPLIT_CELLS: u16 = 2;
+
+/// Sizes for splitting a place `extent` cells across in two, the second
+/// part getting `size`, with neither part under `MIN_SPLIT_CELLS`.  The
+/// sizes are cell counts, so the split comes out exact at this size.
+pub fn sized_split(extent: u16, size: PaneSize) -> Vec<u16> {
+    let available = extent.saturating_sub(1);
+    if available < 2 * MIN_SPLIT_CELLS { return vec![50, 50]; }
+    let cells = match size {
+        PaneSize::Cells(n) => n,
+        PaneSize::Percent(p) => ((available as u32 * p.min(100) as u32 + 50) / 100) as u16,
+    };
+    let cells = cells.clamp(MIN_SPLIT_CELLS, available - MIN_SPLIT_CELLS);
+    vec![available - cells, cells]
+}
+
 pub fn kill_leaf(node: &mut Node, path: &Vec<usize>) {
     *node = remove_node(std::mem::replace(node, Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] }), path);
 }
@@ -90,7 +121,7 @@ pub fn kill_leaf(node: &mut Node, path: &Vec<usize>) {
 /// processes (shells, sub-processes, servers, etc.) are terminated.
 pub fn kill_node(mut n: Node) {
     match &mut n {
-        Node::Leaf(p) => { process_kill::kill_process_tree(&mut p.child); }
+        Node::Leaf(p) => { process_kill::kill_process_tree(&mut p.child); p.stop_pipe(); }
         Node::Split { children, .. } => {
             for child in children.iter_mut() {
                 kill_all_children(child);
@@ -214,7 +245,90 @@ pub fn resize_all_panes(app: &mut AppState) {
     if app.windows.is_empty() { return; }
     let area = app.last_window_area;
     if area.width == 0 || area.height == 0 { return; }
-    
+
+    // Only resize the active window immediately — background windows will be
+    // resized lazily when switched to.  This avoids O(total_panes) ConPTY
+    // resize syscalls on every structural change.
+    if app.active_idx < app.windows.len() {
+        resize_window_panes(&mut app.windows[app.active_idx], area);
+    }
+    crate::copy_mode::fit_copy_mode_after_resize(app);
+}
+
+/// Rows `pane-border-status` takes from the window for the line drawn above
+/// (`top`) or below (`bottom`) the panes.
+pub fn border_status_rows(app: &AppState) -> u16 {
+    if matches!(app.pane_border_status.as_str(), "top" | "bottom") { 1 } else { 0 }
+}
+
+/// Lay the window out in a client area of `w`x`h`: the panes get all of
+/// it but the `pane-border-status` row.
+pub fn set_window_size(app: &mut AppState, w: u16, h: u16) {
+    app.last_window_area = crate::layout::panes_area(Rect::new(0, 0, w, h), &app.pane_border_status);
+    resize_all_panes(app);
+}
+
+/// Set the window size used while no client is attached (`resize-window`,
+/// `refresh-client -C`); attached clients size the window to their
+/// terminal unless `window-size` is manual.
+pub fn set_headless_size(app: &mut AppState, w: Option<u16>, h: Option<u16>) {
+    let (cw, ch) = app.headless_size;
+    app.headless_size = (w.unwrap_or(cw).max(1), h.unwrap_or(ch).max(1));
+    if app.attached_clients == 0 || app.window_size == "manual" {
+        let (w, h) = app.headless_size;
+        set_window_size(app, w, h);
+    }
+}
+
+/// The size attached clients agree on under `window-size`: the smallest
+/// width and height among them (`smallest`, the default), the largest
+/// (`largest`) or the size of the client used last (`latest`).  Clients
+/// that have not reported a size yet or are suspended have no say; `None`
+/// when no client does, or with `manual`.
+pub fn clients_size(app: &AppState) -> Option<(u16, u16)> {
+    let sized = app.clients.iter().filter(|c| !c.suspended && c.size.0 > 0 && c.size.1 > 0);
+    match app.window_size.as_str() {
+        "manual" => None,
+        "latest" => sized.max_by_key(|c| c.last_input).map(|c| c.size),
+        "largest" => sized.map(|c| c.size).reduce(|a, b| (a.0.max(b.0), a.1.max(b.1))),
+        _ => sized.map(|c| c.size).reduce(|a, b| (a.0.min(b.0), a.1.min(b.1))),
+    }
+}
+
+/// Lay the window out again after a client came, went, resized or
+/// `window-size` changed.  `reported` is the size a client without an id
+/// sent, used when no tracked client has one.
+pub fn fit_to_clients(app: &mut AppState, reported: Option<(u16, u16)>) {
+    let size = match clients_size(app) {
+        Some(size) => Some(size),
+        None if app.window_size == "manual" => Some(app.headless_size),
+        None if reported.is_some() => reported,
+        None if app.attached_clients == 0 => Some(app.headless_size),
+        None => None,
+    };
+    if let Some((w, h)) = size {
+        if window_size(app) != (w, h) { set_window_size(app, w, h); }
+    }
+}
+
+/// The client area the window was last laid out in.
+pub fn window_size(app: &AppState) -> (u16, u16) {
+    let area = app.last_window_area;
+    let below = if app.pane_border_status == "bottom" { border_status_rows(app) } else { 0 };
+    (area.width, area.y + area.height + below)
+}
+
+/// Set `pane-border-status`, laying the window out again around its row.
+pub fn set_pane_border_status(app: &mut AppState, value: &str) {
+    let (w, h) = window_size(app);
+    app.pane_border_status = value.to_string();
+    set_window_size(app, w, h);
+}
+
+/// Resize the PTYs of one window's panes to their places in `area`.
+pub fn resize_window_panes(win: &mut Window, area: Rect) {
+    if area.width == 0 || area.height == 0 { return; }
+
     fn resize_node(node: &mut Node, rects: &[(Vec<usize>, Rect)], path: &mut Vec<usize>) {
         match node {
             Node::Leaf(pane) => {
@@ -255,22 +369,16 @@ pub fn resize_all_panes(app: &mut AppState) {
             }
         }
     }
-    
-    // Only resize the active window immediately — background windows will be
-    // resized lazily when switched to.  This avoids O(total_panes) ConPTY
-    // resize syscalls on every structural change.
-    if app.active_idx < app.windows.len() {
-        let win = &mut app.windows[app.active_idx];
-        let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
-        compute_rects(&win.root, area, &mut rects);
-        let mut path = Vec::new();
-        resize_node(&mut win.root, &rects, &mut path);
-    }
+
+    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
+    compute_rects(&win.root, area, &mut rects);
+    let mut path = Vec::new();
+    resize_node(&mut win.root, &rects, &mut path);
 }
 
 pub fn kill_all_children(node: &mut Node) {
     match node {
-        Node::Leaf(p) => { process_kill::kill_process_tree(&mut p.child); }
+        Node::Leaf(p) => { process_kill::kill_process_tree(&mut p.child); p.stop_pipe(); }
         Node::Split { children, .. } => { for child in children.iter_mut() { kill_all_children(child); } }
     }
 }
@@ -307,6 +415,38 @@ pub fn compute_split_borders(node: &Node, area: Rect, out: &mut Vec<(Vec<usize>,
     rec(node, area, &mut path, out);
 }
 
+/// The splitter under (x, y), in the same form as compute_split_borders.
+/// Only the splitter cell itself counts, and only within the extent of the
+/// split it divides, so neighbouring panes keep their edge columns.
+pub fn splitter_at(node: &Node, area: Rect, x: u16, y: u16) -> Option<(Vec<usize>, LayoutKind, usize, u16, u16)> {
+    fn rec(node: &Node, area: Rect, path: &mut Vec<usize>, x: u16, y: u16) -> Option<(Vec<usize>, LayoutKind, usize, u16, u16)> {
+        let Node::Split { kind, sizes, children } = node else { return None; };
+        if !area.contains(Position { x, y }) { return None; }
+        let effective_sizes: Vec<u16> = if sizes.len() == children.len() {
+            sizes.clone()
+        } else { vec![(100 / children.len().max(1)) as u16; children.len()] };
+        let is_horizontal = matches!(*kind, LayoutKind::Horizontal);
+        let rects = split_with_gaps(is_horizontal, &effective_sizes, area);
+        for (i, r) in rects.iter().enumerate() {
+            if r.contains(Position { x, y }) {
+                path.push(i);
+                let hit = rec(&children[i], *r, path, x, y);
+                path.pop();
+                return hit;
+            }
+            if i + 1 < rects.len() {
+                let (pos, on) = if is_horizontal { (r.x + r.width, x == r.x + r.width) } else { (r.y + r.height, y == r.y + r.height) };
+                if on {
+                    let total_px = if is_horizontal { area.width } else { area.height };
+                    return Some((path.clone(), *kind, i, pos, total_px));
+                }
+            }
+        }
+        None
+    }
+    rec(node, area, &mut Vec::new(), x, y)
+}
+
 pub fn split_sizes_at<'a>(node: &'a Node, path: Vec<usize>, idx: usize) -> Option<(u16,u16)> {
     let mut cur = node;
     for &i in path.iter() {
@@ -317,22 +457,53 @@ pub fn split_sizes_at<'a>(node: &'a Node, path: Vec<usize>, idx: usize) -> Optio
     } else { None }
 }
 
-pub fn adjust_split_sizes(root: &mut Node, d: &DragState, x: u16, y: u16) {
+/// Abandon a border drag after this long without a mouse event (the
+/// button-up was lost, e.g. released outside the terminal window).
+pub const DRAG_TIMEOUT: Duration = Duration::from_secs(3);
+/// Minimum interval between PTY resizes while a border drag is in progress.
+pub const DRAG_RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
+/// Smallest share (in percent) either side of a dragged border may shrink to.
+const DRAG_MIN_PCT: u16 = 5;
+
+/// Move the dragged border to follow the pointer at (x, y).
+///
+/// The delta is measured against where the border sits in the *current*
+/// layout rather than the press point, and the DragState is re-anchored each
+/// call, so fast drags (or a layout change mid-drag) can never leave it stale.
+/// Sizes are clamped so neither side drops below the minimum or inverts.
+/// Returns true if the split sizes changed.
+pub fn adjust_split_sizes(root: &mut Node, area: Rect, d: &mut DragState, x: u16, y: u16) -> bool {
+    d.last_event = Instant::now();
+    d.start_x = x;
+    d.start_y = y;
+    let mut borders = Vec::new();
+    compute_split_borders(root, area, &mut borders);
+    let (border_pos, total_px) = match borders.iter().find(|(p, k, i, _, _)| *p == d.split_path && *k == d.kind && *i == d.index) {
+        Some((_, _, _, pos, total)) => (*pos, (*total).max(1)),
+        None => return false,
+    };
+    d.total_pixels = total_px;
+    let pointer = match d.kind { LayoutKind::Horizontal => x, LayoutKind::Vertical => y };
+    let pixel_delta = pointer as i32 - border_pos as i32;
     if let Some(Node::Split { sizes, .. }) = get_split_mut(root, &d.split_path) {
-        let total_pct = sizes[d.index] + sizes[d.index+1];
-        let min_pct = 5u16;
-        // Convert pixel delta to percentage delta
-        let pixel_delta: i32 = match d.kind {
-            LayoutKind::Horizontal => x as i32 - d.start_x as i32,
-            LayoutKind::Vertical => y as i32 - d.start_y as i32,
-        };
-        let total_px = d.total_pixels.max(1) as i32;
-        let pct_delta = (pixel_delta * total_pct as i32) / total_px;
-        let left = (d.left_initial as i32 + pct_delta).clamp(min_pct as i32, (total_pct - min_pct) as i32) as u16;
-        let right = total_pct - left;
+        if d.index + 1 >= sizes.len() { return false; }
+        let all_pct: i32 = sizes.iter().map(|&s| s as i32).sum();
+        let pair_pct = sizes[d.index] as i32 + sizes[d.index + 1] as i32;
+        // Keep each side at least DRAG_MIN_PCT and at least one cell wide
+        let min_pct = (DRAG_MIN_PCT as i32).max((all_pct + total_px as i32 - 1) / total_px as i32);
+        if pair_pct < 2 * min_pct { return false; }
+        // Rounds toward zero; sub-step moves are picked up on later events
+        // because the delta is always re-measured from the live border.
+        let pct_delta = pixel_delta * all_pct / total_px as i32;
+        let left = (sizes[d.index] as i32 + pct_delta).clamp(min_pct, pair_pct - min_pct) as u16;
+        d.left_initial = left;
+        d._right_initial = pair_pct as u16 - left;
+        if left == sizes[d.index] { return false; }
         sizes[d.index] = left;
-        sizes[d.index+1] = right;
+        sizes[d.index + 1] = pair_pct as u16 - left;
+        return true;
     }
+    false
 }
 
 pub fn get_split_mut<'a>(node: &'a mut Node, path: &Vec<usize>) -> Option<&'a mut Node> {
@@ -343,16 +514,28 @@ pub fn get_split_mut<'a>(node: &'a mut Node, path: &Vec<usize>) -> Option<&'a mu
     Some(cur)
 }
 
-pub fn prune_exited(n: Node, remain_on_exit: bool) -> Option<Node> {
+/// Drop the panes whose process exited, or with remain-on-exit (the
+/// window's `remain_on_exit` unless a pane sets its own) keep them dead.
+/// Each pane that ends is pushed to `ended` with whether it was kept.
+pub fn prune_exited(n: Node, remain_on_exit: bool, ended: &mut Vec<(usize, bool)>) -> Option<Node> {
     match n {
         Node::Leaf(mut p) => {
             if p.dead { return Some(Node::Leaf(p)); }
             match p.child.try_wait() {
-                Ok(Some(_)) => {
-                    if remain_on_exit {
+                Ok(Some(status)) => {
+                    p.exit_status.get_or_insert(status.exit_code());
+                    let keep = p.options.remain_on_exit.unwrap_or(remain_on_exit);
+                    ended.push((p.id, keep));
+                    if keep {
                         p.dead = true;
+                        // A fresh counter one past the old one: the frame is
+                        // redrawn and the reader thread, left holding the
+                        // only reference to the old one, stops
+                        let seen = p.data_version.load(std::sync::atomic::Ordering::Acquire);
+                        p.data_version = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(seen + 1));
                         Some(Node::Leaf(p))
                     } else {
+                        p.stop_pipe();
                         None
                     }
                 }
@@ -363,7 +546,7 @@ pub fn prune_exited(n: Node, remain_on_exit: bool) -> Option<Node> {
             let mut new_children: Vec<Node> = Vec::new();
             let mut new_sizes: Vec<u16> = Vec::new();
             for (i, child) in children.into_iter().enumerate() {
-                if let Some(c) = prune_exited(child, remain_on_exit) {
+                if let Some(c) = prune_exited(child, remain_on_exit, ended) {
                     new_children.push(c);
                     new_sizes.push(sizes.get(i).copied().unwrap_or(0));
                 }
@@ -474,11 +657,31 @@ pub fn get_nth_pane(node: &Node, n: usize) -> Option<&Pane> {
     panes.get(n).copied()
 }
 
+/// Call `f` on every pane in the tree.
+pub fn for_each_pane_mut(node: &mut Node, f: &mut dyn FnMut(&mut Pane)) {
+    match node {
+        Node::Leaf(p) => f(p),
+        Node::Split { children, .. } => { for c in children.iter_mut() { for_each_pane_mut(c, f); } }
+    }
+}
+
+/// Window position and pane id of the pane marked with select-pane -m.
+pub fn marked_pane(app: &AppState) -> Option<(usize, usize)> {
+    fn rec(node: &Node) -> Option<usize> {
+        match node {
+            Node::Leaf(p) => if p.marked { Some(p.id) } else { None },
+            Node::Split { children, .. } => children.iter().find_map(rec),
+        }
+    }
+    app.windows.iter().enumerate().find_map(|(i, w)| rec(&w.root).map(|id| (i, id)))
+}
+
 pub fn find_window_index_by_id(app: &AppState, wid: usize) -> Option<usize> {
     app.windows.iter().position(|w| w.id == wid)
 }
 
-pub fn focus_pane_by_id(app: &mut AppState, pid: usize) {
+/// Path of the pane with id `pid` in the tree, if it is there.
+pub fn pane_path(node: &Node, pid: usize) -> Option<Vec<usize>> {
     fn rec(node: &Node, path: &mut Vec<usize>, found: &mut Option<Vec<usize>>, pid: usize) {
         match node {
             Node::Leaf(p) => { if p.id == pid { *found = Some(path.clone()); } }
@@ -487,11 +690,14 @@ pub fn focus_pane_by_id(app: &mut AppState, pid: usize) {
             }
         }
     }
+    let mut found = None;
+    rec(node, &mut Vec::new(), &mut found, pid);
+    found
+}
+
+pub fn focus_pane_by_id(app: &mut AppState, pid: usize) {
     for (wi, w) in app.windows.iter().enumerate() {
-        let mut path = Vec::new();
-        let mut found = None;
-        rec(&w.root, &mut path, &mut found, pid);
-        if let Some(p) = found { app.active_idx = wi; let win = &mut app.windows[wi]; win.active_path = p; return; }
+        if let Some(p) = pane_path(&w.root, pid) { app.active_idx = wi; let win = &mut app.windows[wi]; win.active_path = p; return; }
     }
 }
 
@@ -561,14 +767,61 @@ pub fn pane_index_in_window(node: &Node, path: &[usize]) -> Option<usize> {
     if walk(node, target_id, &mut idx) { Some(idx) } else { None }
 }
 
+/// When the server loop walks the tree with [`reap_children`]: straight away
+/// after the exit watcher reports a process, otherwise only every
+/// [`ReapSchedule::SWEEP`] as a safety net for exits it did not see.
+/// Without a watcher every tick is a sweep.
+pub struct ReapSchedule {
+    event_driven: bool,
+    exited: bool,
+    last_sweep: Instant,
+    /// Reaps triggered by a ChildExited notification
+    pub event_reaps: u64,
+    /// Periodic reaps
+    pub sweeps: u64,
+}
+
+impl ReapSchedule {
+    pub const SWEEP: Duration = Duration::from_millis(500);
+
+    pub fn new(event_driven: bool, now: Instant) -> Self {
+        ReapSchedule { event_driven, exited: false, last_sweep: now, event_reaps: 0, sweeps: 0 }
+    }
+
+    /// A pane process exit was reported.
+    pub fn child_exited(&mut self) { self.exited = true; }
+
+    /// Should this tick reap?
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self.exited {
+            self.exited = false;
+            self.event_reaps += 1;
+        } else if !self.event_driven || now.duration_since(self.last_sweep) >= Self::SWEEP {
+            self.sweeps += 1;
+        } else {
+            return false;
+        }
+        self.last_sweep = now;
+        true
+    }
+}
+
 /// Reap exited children from the app. Returns (all_empty, any_pruned).
 pub fn reap_children(app: &mut AppState) -> io::Result<(bool, bool)> {
-    let remain = app.remain_on_exit;
     let mut any_pruned = false;
     for i in (0..app.windows.len()).rev() {
+        let remain = crate::scoped::remain_on_exit(app, &app.windows[i]);
         let leaves_before = count_panes(&app.windows[i].root);
         let root = std::mem::replace(&mut app.windows[i].root, Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
-        match prune_exited(root, remain) {
+        // Taken first: the window may be gone once its panes are
+        let win_ctx = crate::hooks::window_context(app, i);
+        let mut ended = Vec::new();
+        let pruned = prune_exited(root, remain, &mut ended);
+        for (id, kept) in ended {
+            let event = if kept { "pane-died" } else { "pane-exited" };
+            crate::hooks::queue(app, event, crate::hooks::with_pane(win_ctx.clone(), id));
+        }
+        match pruned {
             Some(new_root) => {
                 let leaves_after = count_panes(&new_root);
                 if leaves_after < leaves_before {
@@ -578,9 +831,10 @@ pub fn reap_children(app: &mut AppState) -> io::Result<(bool, bool)> {
                 if !path_exists(&app.windows[i].root, &app.windows[i].active_path) {
                     app.windows[i].active_path = first_leaf_path(&app.windows[i].root);
                 }
+                if leaves_after < leaves_before { crate::layout::retile(&mut app.windows[i]); }
             }
             None => {
-                app.windows.remove(i);
+                crate::window_ops::remove_window(app, i);
                 any_pruned = true;
                 // Adjust active_idx after removing a window
                 if !app.windows.is_empty() {
@@ -610,3 +864,211 @@ pub fn collect_leaves(node: Node) -> Vec<Node> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A split whose children are childless splits: no PTYs needed, but
+    /// compute_split_borders / get_split_mut see the same shape as real panes.
+    fn synthetic(kind: LayoutKind, sizes: Vec<u16>) -> Node {
+        let children = sizes.iter().map(|_| Node::Split { kind, sizes: vec![], children: vec![] }).collect();
+        Node::Split { kind, sizes, children }
+    }
+
+    fn press(root: &Node, area: Rect, index: usize) -> DragState {
+        let mut borders = Vec::new();
+        compute_split_borders(root, area, &mut borders);
+        let (path, kind, idx, pos, total) = borders.into_iter().find(|b| b.2 == index).unwrap();
+        let (left, right) = split_sizes_at(root, path.clone(), idx).unwrap();
+        let (x, y) = if kind == LayoutKind::Horizontal { (pos, 0) } else { (0, pos) };
+        DragState { split_path: path, kind, index: idx, start_x: x, start_y: y, left_initial: left, _right_initial: right, total_pixels: total, last_event: Instant::now(), last_pty_resize: Instant::now() }
+    }
+
+    #[test]
+    fn idle_panes_are_only_swept_periodically() {
+        let start = Instant::now();
+        let mut polling = ReapSchedule::new(false, start);
+        let mut watched = ReapSchedule::new(true, start);
+        let (mut polled, mut reaped) = (0, 0);
+        // Two seconds of 5ms ticks with nothing exiting
+        for tick in 1..=400u64 {
+            let now = start + Duration::from_millis(tick * 5);
+            if polling.due(now) { polled += 1; }
+            if watched.due(now) { reaped += 1; }
+        }
+        assert_eq!(polled, 400);
+        assert_eq!(reaped, 4);
+        assert_eq!((watched.sweeps, watched.event_reaps), (4, 0));
+        // An exit is reaped on the very next tick, not at the next sweep
+        let now = start + Duration::from_millis(2005);
+        assert!(!watched.due(now));
+        watched.child_exited();
+        assert!(watched.due(now));
+        assert!(!watched.due(now + Duration::from_millis(5)));
+        assert_eq!(watched.event_reaps, 1);
+    }
+
+    #[test]
+    fn sized_splits_come_out_exact_and_keep_both_sides() {
+        let area = Rect::new(0, 0, 81, 24);
+        let widths = |size| split_with_gaps(true, &sized_split(81, size), area).iter().map(|r| r.width).collect::<Vec<_>>();
+        assert_eq!(widths(PaneSize::Cells(20)), [60, 20]);
+        assert_eq!(widths(PaneSize::Percent(30)), [56, 24]);
+        // Neither side goes under two cells
+        assert_eq!(widths(PaneSize::Cells(79)), [2, 78]);
+        assert_eq!(widths(PaneSize::Percent(0)), [78, 2]);
+        // Too small to size: halves
+        assert_eq!(sized_split(4, PaneSize::Cells(1)), [50, 50]);
+    }
+
+    #[test]
+    fn side_by_side_panes_share_one_splitter_column() {
+        // 80 columns, two panes: one splitter column instead of a box
+        // around each pane (which would leave 38 + 38)
+        let rects = split_with_gaps(true, &[50, 50], Rect::new(0, 0, 80, 24));
+        assert_eq!(rects, vec![Rect::new(0, 0, 39, 24), Rect::new(40, 0, 40, 24)]);
+        assert_eq!(rects.iter().map(|r| r.width).sum::<u16>(), 79);
+        // Three panes lose two columns, and no rows are lost to edges
+        let rects = split_with_gaps(true, &[33, 33, 34], Rect::new(0, 0, 80, 24));
+        assert_eq!(rects.iter().map(|r| r.width).sum::<u16>(), 78);
+        assert!(rects.iter().all(|r| r.y == 0 && r.height == 24));
+    }
+
+    #[test]
+    fn nested_splitters_sit_between_their_own_panes() {
+        // Left pane | (top / bottom) on 80x24
+        let area = Rect::new(0, 0, 80, 24);
+        let root = Node::Split { kind: LayoutKind::Horizontal, sizes: vec![50, 50], children: vec![
+            synthetic(LayoutKind::Vertical, vec![]),
+            synthetic(LayoutKind::Vertical, vec![50, 50]),
+        ] };
+        let mut borders = Vec::new();
+        compute_split_borders(&root, area, &mut borders);
+        let found: Vec<_> = borders.iter().map(|(p, k, i, pos, total)| (p.clone(), *k == LayoutKind::Horizontal, *i, *pos, *total)).collect();
+        assert_eq!(found, vec![(vec![], true, 0, 39, 80), (vec![1], false, 0, 11, 24)]);
+        // Hit-testing only claims the splitter cells themselves
+        assert_eq!(splitter_at(&root, area, 39, 5).map(|b| b.0), Some(vec![]));
+        assert_eq!(splitter_at(&root, area, 60, 11).map(|b| b.0), Some(vec![1]));
+        assert!(splitter_at(&root, area, 40, 5).is_none());
+        assert!(splitter_at(&root, area, 38, 5).is_none());
+        // The right column's splitter does not extend into the left pane
+        assert!(splitter_at(&root, area, 20, 11).is_none());
+    }
+
+    #[test]
+    fn border_lines_pick_splitter_glyphs() {
+        assert_eq!(border_glyphs("single"), ('│', '─'));
+        assert_eq!(border_glyphs("double"), ('║', '═'));
+        assert_eq!(border_glyphs("heavy"), ('┃', '━'));
+        assert_eq!(border_glyphs("simple"), ('|', '-'));
+        assert_eq!(border_glyphs("none"), (' ', ' '));
+        assert_eq!(border_glyphs("bogus"), border_glyphs("single"));
+    }
+
+    fn sizes_of(root: &Node) -> Vec<u16> {
+        match root { Node::Split { sizes, .. } => sizes.clone(), _ => vec![] }
+    }
+
+    #[test]
+    fn fast_drag_is_monotonic_and_clamped() {
+        let area = Rect::new(0, 0, 101, 30);
+        let mut root = synthetic(LayoutKind::Horizontal, vec![50, 50]);
+        let mut d = press(&root, area, 0);
+        // Large jumps to the right, well past the far edge
+        let mut prev = sizes_of(&root)[0];
+        for x in [60u16, 85, 99, 140, 200] {
+            adjust_split_sizes(&mut root, area, &mut d, x, 5);
+            let s = sizes_of(&root);
+            assert!(s[0] >= prev, "left shrank while dragging right: {:?}", s);
+            assert!(s[1] >= DRAG_MIN_PCT, "right side below min: {:?}", s);
+            assert_eq!(s[0] + s[1], 100);
+            prev = s[0];
+        }
+        // And back past the left edge
+        for x in [40u16, 10, 0] {
+            adjust_split_sizes(&mut root, area, &mut d, x, 5);
+            let s = sizes_of(&root);
+            assert!(s[0] <= prev, "left grew while dragging left: {:?}", s);
+            assert!(s[0] >= DRAG_MIN_PCT);
+            prev = s[0];
+        }
+        assert_eq!(prev, DRAG_MIN_PCT);
+    }
+
+    #[test]
+    fn drag_tracks_pointer_not_press_point() {
+        let area = Rect::new(0, 0, 101, 30);
+        let mut root = synthetic(LayoutKind::Horizontal, vec![50, 50]);
+        let mut d = press(&root, area, 0);
+        adjust_split_sizes(&mut root, area, &mut d, 70, 0);
+        // Repeating the same pointer position must not move the border again
+        let once = sizes_of(&root);
+        adjust_split_sizes(&mut root, area, &mut d, 70, 0);
+        assert_eq!(sizes_of(&root), once);
+        assert_eq!(d.left_initial, once[0]);
+        assert_eq!(d.start_x, 70);
+    }
+
+    #[test]
+    fn vertical_drag_middle_border_keeps_neighbours() {
+        let area = Rect::new(0, 0, 80, 62);
+        let mut root = synthetic(LayoutKind::Vertical, vec![34, 33, 33]);
+        let mut d = press(&root, area, 1);
+        for y in [45u16, 55, 70, 90] {
+            adjust_split_sizes(&mut root, area, &mut d, 0, y);
+        }
+        let s = sizes_of(&root);
+        assert_eq!(s[0], 34);
+        assert_eq!(s[1] + s[2], 66);
+        assert_eq!(s[2], DRAG_MIN_PCT);
+    }
+
+    #[test]
+    fn tiny_pair_is_left_alone() {
+        let area = Rect::new(0, 0, 100, 30);
+        let mut root = synthetic(LayoutKind::Horizontal, vec![92, 4, 4]);
+        let mut d = press(&root, area, 1);
+        assert!(!adjust_split_sizes(&mut root, area, &mut d, 0, 0));
+        assert_eq!(sizes_of(&root), vec![92, 4, 4]);
+    }
+
+    // stty is sh's
+    #[cfg(not(windows))]
+    #[test]
+    fn the_headless_size_lays_out_windows_until_a_client_attaches() {
+        let pty = portable_pty::native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = "sh".to_string();
+        set_headless_size(&mut app, Some(100), Some(20));
+        assert_eq!(app.last_window_area, Rect::new(0, 0, 100, 20));
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        resize_all_panes(&mut app);
+
+        // The new right-hand pane's program sees the columns it was given
+        {
+            let win = &mut app.windows[0];
+            let p = active_pane_mut(&mut win.root, &win.active_path).unwrap();
+            crate::input::write_pane_input(&mut *p.writer, b"\rstty size\r");
+        }
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut shown = String::new();
+        while Instant::now() < deadline && !shown.contains("20 50") {
+            std::thread::sleep(Duration::from_millis(20));
+            shown = crate::copy_mode::capture_active_pane_range(&mut app, None, None, false).unwrap().unwrap_or_default();
+        }
+        assert!(shown.contains("20 50"), "{shown}");
+        assert_eq!(crate::format::expand_format("#{window_width}x#{window_height}", &app), "100x20");
+
+        // An attached client's size stands, unless window-size is manual
+        app.attached_clients = 1;
+        set_headless_size(&mut app, Some(60), None);
+        assert_eq!((app.headless_size, app.last_window_area.width), ((60, 20), 100));
+        app.window_size = "manual".to_string();
+        set_headless_size(&mut app, None, Some(10));
+        assert_eq!(app.last_window_area, Rect::new(0, 0, 60, 10));
+
+        kill_all_children(&mut app.windows[0].root);
+    }
+}
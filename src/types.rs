@@ -1,5 +1,5 @@
 use std::sync::{Arc, Mutex, mpsc};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crossterm::event::{KeyCode, KeyModifiers};
 use portable_pty::MasterPty;
@@ -7,6 +7,15 @@ use ratatui::prelude::Rect;
 use chrono::Local;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// tmux version psmux answers to: `tmux -V` when it runs under the tmux
+/// name, and the default of `compat-version` (`#{version}`).  Plugins gate
+/// features on it, so it names the tmux release whose commands and flags
+/// psmux covers rather than psmux's own version.
+pub const TMUX_COMPAT_VERSION: &str = "3.3a";
+
+/// Default `pane-border-format`, tmux's: the pane's index, reversed on the
+/// active pane, and its title.
+pub const DEFAULT_PANE_BORDER_FORMAT: &str = "#{?pane_active,#[reverse],}#{pane_index}#[default] \"#{pane_title}\"";
 
 pub struct Pane {
     pub master: Box<dyn MasterPty>,
@@ -33,14 +42,70 @@ pub struct Pane {
     /// Cached VT bridge detection result (for mouse injection).
     /// Updated on first mouse event and refreshed every 2 seconds.
     pub vt_bridge_cache: Option<(Instant, bool)>,
+    /// Command line of the foreground process, for `#{pane_current_command_full}`
+    pub cmdline_cache: std::cell::RefCell<crate::pane::CommandLineCache>,
+    /// `#{pane_current_path}` and `#{pane_current_command}`, briefly cached
+    pub process_info: std::cell::RefCell<crate::pane::ProcessInfoCache>,
+    /// Working directory the shell last reported with OSC 9;9, set by the PTY reader thread
+    pub reported_cwd: std::sync::Arc<std::sync::Mutex<Option<String>>>,
+    /// Bells the program rang, counted by the PTY reader thread
+    pub bells: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    /// `bells` when the server last looked
+    pub bells_seen: u64,
+    /// The rows this pane last went out with in a frame, reused while
+    /// `data_version` and the pane's size stay the same
+    pub rows_cache: crate::layout::RowsCache,
     /// Per-pane copy mode state (tmux-style pane-local copy mode).
     /// Some(_) when this pane is in copy mode, None otherwise.
     pub copy_state: Option<CopyModeState>,
+    /// True while the parser's scrollback offset is a plain view offset
+    /// (scroll-without-copy-mode), not copy-mode state.
+    pub view_scrolled: bool,
+    /// pipe-pane process attached to this pane.  Kept on the pane (not in
+    /// a table keyed by pane id) so break-pane / join-pane move it along.
+    pub pipe: Option<PipePaneState>,
+    /// Where the PTY reader thread copies output while `pipe` streams it
+    pub pipe_tap: crate::pipe_pane::Tap,
+    /// Marked with select-pane -m (at most one pane in the session)
+    pub marked: bool,
+    /// Exit code of the pane's process once it has exited
+    pub exit_status: Option<u32>,
+    /// highlight-pattern set for this pane (`set -p`); None inherits the
+    /// window's
+    pub highlight_pattern: Option<String>,
+    /// pane-encoding set for this pane (`set -p`); None inherits the window's
+    pub pane_encoding: Option<String>,
+    /// Options set for this pane itself (`set -p`)
+    pub options: crate::scoped::ScopedOptions,
+    /// The pane-encoding in effect, read by the PTY reader thread
+    pub encoding: std::sync::Arc<std::sync::atomic::AtomicU8>,
+}
+
+impl Pane {
+    /// Stop this pane's pipe-pane process, if any.
+    pub fn stop_pipe(&mut self) {
+        crate::pipe_pane::stop(self);
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum LayoutKind { Horizontal, Vertical }
 
+/// The size asked for a new pane: `-l 20` cells, or `-p 30` / `-l 30%`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaneSize { Cells(u16), Percent(u16) }
+
+/// How split-window places the new pane.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SplitOpts {
+    /// `-l`/`-p`; half the place when None
+    pub size: Option<PaneSize>,
+    /// `-b`: before (left of or above) the place rather than after it
+    pub before: bool,
+    /// `-f`: split the whole window rather than the active pane
+    pub full: bool,
+}
+
 pub enum Node {
     Leaf(Pane),
     Split { kind: LayoutKind, sizes: Vec<u16>, children: Vec<Node> },
@@ -51,6 +116,9 @@ pub struct Window {
     pub active_path: Vec<usize>,
     pub name: String,
     pub id: usize,
+    /// Display index (`#I`).  Windows are kept sorted by it; gaps are
+    /// allowed unless renumber-windows is on.
+    pub index: usize,
     /// Activity flag: set when pane output is received while window is not active
     pub activity_flag: bool,
     /// Bell flag: set when a bell (\x07) is detected in a pane
@@ -61,11 +129,49 @@ pub struct Window {
     pub last_output_time: std::time::Instant,
     /// Last observed combined data_version for activity detection
     pub last_seen_version: u64,
+    /// When a pane in this window last produced output (`#{window_activity}`)
+    pub activity_at: chrono::DateTime<Local>,
+    /// When this window was last the active one (`#{window_last_visited}`)
+    pub visited_at: chrono::DateTime<Local>,
+    /// When this window was created (`#{window_created}`)
+    pub created_at: chrono::DateTime<Local>,
+    /// Idle past idle-window-timeout (`?` in `#{window_flags}`)
+    pub idle_flag: bool,
+    /// Under `idle-window-action kill`: when the idle window goes
+    pub idle_kill_at: Option<chrono::DateTime<Local>>,
     /// True when the user has manually renamed this window (auto-rename won't override).
     /// Cleared when `set automatic-rename on` is explicitly set.
     pub manual_rename: bool,
     /// Current position in the named layout cycle (0..4)
     pub layout_index: usize,
+    /// Laid out with `tiled` and not split or resized since: losing a pane
+    /// tiles the rest again
+    pub tiled: bool,
+    /// highlight-pattern set for this window (`set -w`); None inherits the
+    /// session's
+    pub highlight_pattern: Option<String>,
+    /// pane-encoding set for this window (`set -w`); None inherits the
+    /// session's
+    pub pane_encoding: Option<String>,
+    /// Options set for this window itself (`set -w`)
+    pub options: crate::scoped::ScopedOptions,
+}
+
+#[cfg(test)]
+impl Window {
+    /// A window with no panes and default flags, for tests that only look
+    /// at window metadata.
+    pub fn bare(id: usize, index: usize, name: &str) -> Window {
+        let now = chrono::Local::now();
+        Window {
+            root: Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] },
+            active_path: vec![], name: name.into(), id, index,
+            activity_flag: false, bell_flag: false, silence_flag: false,
+            last_output_time: std::time::Instant::now(), last_seen_version: 0,
+            activity_at: now, visited_at: now, created_at: now, idle_flag: false, idle_kill_at: None,
+            manual_rename: false, layout_index: 0, tiled: false, highlight_pattern: None, pane_encoding: None, options: Default::default(),
+        }
+    }
 }
 
 /// A menu item for display-menu
@@ -75,6 +181,8 @@ pub struct MenuItem {
     pub key: Option<char>,
     pub command: String,
     pub is_separator: bool,
+    /// The command was a display-menu: shown beside this one
+    pub submenu: Option<Box<Menu>>,
 }
 
 /// A parsed menu structure
@@ -83,8 +191,8 @@ pub struct Menu {
     pub title: String,
     pub items: Vec<MenuItem>,
     pub selected: usize,
-    pub x: Option<i16>,
-    pub y: Option<i16>,
+    pub x: Option<crate::menu::Place>,
+    pub y: Option<crate::menu::Place>,
 }
 
 /// Hook definition - command to run on certain events
@@ -104,12 +212,20 @@ pub struct PopupPty {
 
 /// Pipe pane state - process piping pane output
 pub struct PipePaneState {
-    pub pane_id: usize,
     pub process: Option<std::process::Child>,
     pub stdin: bool,
     pub stdout: bool,
+    /// With `-I`: what the command wrote, to be typed into the pane
+    pub input: Option<mpsc::Receiver<Vec<u8>>>,
 }
 
+/// Which pane swap-pane swaps the active pane with
+pub enum SwapWith { Previous, Next, Target(String), Marked }
+
+/// Where `set-option -w` or `-p` sets an option: the active window or pane
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionScope { Window, Pane }
+
 /// Wait-for channel state
 pub struct WaitChannel {
     pub locked: bool,
@@ -118,7 +234,9 @@ pub struct WaitChannel {
 
 pub enum Mode {
     Passthrough,
-    Prefix { armed_at: Instant },
+    /// `repeating`: a `-r` binding just ran, so for repeat-time another
+    /// repeatable key runs without the prefix
+    Prefix { armed_at: Instant, repeating: bool },
     CommandPrompt { input: String, cursor: usize },
     WindowChooser { selected: usize, tree: Vec<crate::session::TreeEntry> },
     RenamePrompt { input: String },
@@ -132,9 +250,18 @@ pub enum Mode {
         command: String, 
         output: String, 
         process: Option<std::process::Child>,
+        /// Window cells, border included
+        x: u16,
+        y: u16,
         width: u16,
         height: u16,
-        close_on_exit: bool,
+        close_on_exit: crate::popup::CloseOn,
+        /// `-T`, expanded
+        title: String,
+        /// `-b` border style
+        border: String,
+        /// Set once the command has exited and the popup stays open
+        exit_code: Option<u32>,
         /// Optional: interactive PTY for the popup (fzf, etc.)  
         popup_pty: Option<PopupPty>,
     },
@@ -142,7 +269,8 @@ pub enum Mode {
     ConfirmMode { 
         prompt: String, 
         command: String,
-        input: String,
+        /// confirm-before -y: Enter confirms too
+        default_yes: bool,
     },
     /// Copy-mode search input
     CopySearch {
@@ -166,10 +294,11 @@ pub struct CopyModeState {
     pub anchor_scroll_offset: usize,
     pub pos: Option<(u16, u16)>,
     pub scroll_offset: usize,
+    pub hscroll: Option<usize>,
     pub selection_mode: SelectionMode,
     pub search_query: String,
     pub count: Option<usize>,
-    pub search_matches: Vec<(u16, u16, u16)>,
+    pub search_matches: Vec<(i64, u16, u16)>,
     pub search_idx: usize,
     pub search_forward: bool,
     pub find_char_pending: Option<u8>,
@@ -193,13 +322,22 @@ pub struct AppState {
     pub mode: Mode,
     pub escape_time_ms: u64,
     pub repeat_time_ms: u64,
+    /// prefix-timeout: an armed prefix or a switch-client -T table goes
+    /// back to root after this many ms; 0 never
+    pub prefix_timeout_ms: u64,
+    /// confirm-kill: the default `x` and `&` ask before killing
+    pub confirm_kill: bool,
     pub prefix_key: (KeyCode, KeyModifiers),
     pub prefix2_key: Option<(KeyCode, KeyModifiers)>,
     pub prediction_dimming: bool,
     pub drag: Option<DragState>,
     pub last_window_area: Rect,
+    /// Where the last mouse button went down (server coordinates), for
+    /// `display-menu -x M -y M`
+    pub mouse_at: (u16, u16),
     pub mouse_enabled: bool,
-    pub paste_buffers: Vec<String>,
+    /// Paste buffers, most recent first; also holds buffer-limit
+    pub paste_buffers: crate::buffers::PasteBuffers,
     pub status_left: String,
     pub status_right: String,
     pub window_base_index: usize,
@@ -208,16 +346,27 @@ pub struct AppState {
     pub copy_anchor_scroll_offset: usize,
     pub copy_pos: Option<(u16,u16)>,
     pub copy_scroll_offset: usize,
+    /// Column offset of copy mode's unwrapped view, where each logical line
+    /// is one row scrolled sideways; None shows rows wrapped as usual
+    pub copy_hscroll: Option<usize>,
     /// Selection mode: Char (default), Line (V), Rect (C-v)
     pub copy_selection_mode: SelectionMode,
     /// Copy-mode search query
     pub copy_search_query: String,    /// Numeric prefix count for copy-mode motions (vi-style)
-    pub copy_count: Option<usize>,    /// Copy-mode search matches: (row, col_start, col_end) in screen coords
-    pub copy_search_matches: Vec<(u16, u16, u16)>,
+    pub copy_count: Option<usize>,    /// Copy-mode search matches, top to bottom: (line, first column, last
+    /// column), lines numbered from the top of the live screen with history
+    /// negative (as `copy_mode::for_each_line`)
+    pub copy_search_matches: Vec<(i64, u16, u16)>,
     /// Current match index in copy_search_matches
     pub copy_search_idx: usize,
     /// Search direction: true = forward (/), false = backward (?)
     pub copy_search_forward: bool,
+    /// When the search prompt's debounced incremental search is due
+    pub copy_search_due: Option<Instant>,
+    /// Time budget in ms for matching a user regex (`pattern-timeout`)
+    pub pattern_timeout_ms: u64,
+    /// Exit status of the last run-shell job (`#{last_shell_exit}`)
+    pub last_shell_exit: i32,
     /// Pending find-char operation: (f=0,F=1,t=2,T=3) for next char input
     pub copy_find_char_pending: Option<u8>,
     /// Pending text-object prefix: 0 = 'a' (a-word), 1 = 'i' (inner-word)
@@ -226,14 +375,24 @@ pub struct AppState {
     pub copy_register_pending: bool,
     /// Currently selected named register (a-z), None = default unnamed
     pub copy_register: Option<char>,
-    /// Named registers a-z for copy-mode yank/paste
-    pub named_registers: std::collections::HashMap<char, String>,
     pub display_map: Vec<(usize, Vec<usize>)>,
     /// Key tables: "prefix" (default), "root", "copy-mode-vi", "copy-mode-emacs", etc.
     pub key_tables: std::collections::HashMap<String, Vec<Bind>>,
     /// Current key table for switch-client -T (None = normal mode)
     pub current_key_table: Option<String>,
+    /// When current_key_table was chosen, for prefix-timeout
+    pub key_table_at: Instant,
+    /// root-any-binding: let an `Any` binding in the root table catch unbound keys
+    pub root_any_binding: bool,
+    /// scroll-without-copy-mode: the wheel scrolls a pane's view back without
+    /// entering copy mode
+    pub scroll_without_copy_mode: bool,
+    /// scrolled-input block: drop input to a scrolled-back pane instead of
+    /// snapping it to live output first
+    pub scrolled_input_block: bool,
     pub control_rx: Option<mpsc::Receiver<CtrlReq>>,
+    /// Reports pane process exits to the server loop (server only)
+    pub exit_watcher: Option<crate::platform::exit_watch::ExitWatcher>,
     pub session_name: String,
     /// Numeric session ID (tmux-compatible: $0, $1, $2...).
     pub session_id: usize,
@@ -241,17 +400,33 @@ pub struct AppState {
     /// When set, port/key files are stored as `{socket_name}__{session_name}.port`.
     pub socket_name: Option<String>,
     pub attached_clients: usize,
+    /// Persistent attached client connections (per-client tracking)
+    pub clients: Vec<ClientConn>,
+    /// client-idle-timeout: detach a client after this many seconds without input (0 = off)
+    pub client_idle_timeout: u64,
+    /// client-idle-warning: show a countdown this many seconds before an idle detach
+    pub client_idle_warning: u64,
+    /// client-idle-exempt-local: never idle-detach clients attached from the local console
+    pub client_idle_exempt_local: bool,
     pub created_at: chrono::DateTime<Local>,
     pub next_win_id: usize,
     pub next_pane_id: usize,
     pub zoom_saved: Option<Vec<(Vec<usize>, Vec<u16>)>>,
     pub sync_input: bool,
-    /// Hooks: map of hook name to list of commands
-    pub hooks: std::collections::HashMap<String, Vec<String>>,
+    /// sync-paste: pastes follow synchronize-panes too (default off: they go
+    /// to one pane)
+    pub sync_paste: bool,
+    /// Hooks: each event's commands (set-hook)
+    pub hooks: crate::hooks::Hooks,
+    /// Hooks fired outside a command, run by the server loop
+    pub pending_hooks: Vec<crate::hooks::Pending>,
+    /// run-shell / if-shell -b jobs from config, started by the server loop
+    pub pending_shell_jobs: Vec<ShellJob>,
+    /// One-off status-line message for attached clients, shown for
+    /// display-time (sent by the server loop)
+    pub pending_message: Option<String>,
     /// Wait-for channels: map of channel name to list of waiting senders
     pub wait_channels: std::collections::HashMap<String, WaitChannel>,
-    /// Pipe pane processes
-    pub pipe_panes: Vec<PipePaneState>,
     /// Last active window index (for last-window command)
     pub last_window_idx: usize,
     /// Last active pane path (for last-pane command)
@@ -284,9 +459,31 @@ pub struct AppState {
     pub renumber_windows: bool,
     /// automatic-rename: update window name from active pane's running command
     pub automatic_rename: bool,
-    /// monitor-activity / visual-activity: stored for compat
+    /// automatic-rename-format: what an automatically renamed window is called
+    pub automatic_rename_format: String,
+    /// allow-duplicate-window-names: off makes rename-window and new-window -n
+    /// refuse a name another window already has
+    pub allow_duplicate_window_names: bool,
+    /// popup-warm-shell: keep a started shell ready for the next popup
+    pub popup_warm_shell: bool,
+    /// The ready shell while popup-warm-shell is on (see popup.rs)
+    pub popup_spare: Option<PopupPty>,
+    /// When to try again after a spare failed to start
+    pub popup_spare_retry: Option<Instant>,
+    /// popup-close-key: closes a popup while its command runs
+    pub popup_close_key: String,
+    /// Process queries used by pane format variables (replaced in tests)
+    pub process_lookup: crate::pane::ProcessLookup,
+    /// monitor-activity: flag windows other than the current one that print
     pub monitor_activity: bool,
+    /// visual-activity: show a message for an activity alert instead of
+    /// ringing the client's bell
     pub visual_activity: bool,
+    /// monitor-bell: flag windows other than the current one that ring a bell
+    pub monitor_bell: bool,
+    /// visual-silence: show a message for a silence alert instead of
+    /// ringing the client's bell
+    pub visual_silence: bool,
     /// remain-on-exit: keep panes open after process exits
     pub remain_on_exit: bool,
     /// aggressive-resize: resize window to smallest attached client
@@ -295,12 +492,30 @@ pub struct AppState {
     pub set_titles: bool,
     /// set-titles-string: format for terminal title
     pub set_titles_string: String,
-    /// Environment variables set via set-environment
+    /// Options kept by name: @user options and the ones psmux only stores
     pub environment: std::collections::HashMap<String, String>,
+    /// set-environment -g: what every session's new panes start with
+    pub global_env: crate::environ::EnvMap,
+    /// set-environment without -g: this session's, over the global one
+    pub session_env: crate::environ::EnvMap,
     /// pane-border-style: style for inactive pane borders
     pub pane_border_style: String,
     /// pane-active-border-style: style for active pane borders
     pub pane_active_border_style: String,
+    /// pane-border-lines: splitter glyphs (single, double, heavy, simple, none)
+    pub pane_border_lines: String,
+    /// pane-border-indicators: how the active pane is marked (off, colour,
+    /// arrows, both)
+    pub pane_border_indicators: String,
+    /// pane-border-status: a line above (top) or below (bottom) each pane
+    /// showing `pane_border_format`, or off
+    pub pane_border_status: String,
+    /// pane-border-format: what the pane-border-status line shows
+    pub pane_border_format: String,
+    /// highlight-pattern: `PATTERN=STYLE;...` kept highlighted in panes
+    pub highlight_pattern: String,
+    /// pane-encoding: how pane output is decoded (utf8, cp437, cp1252)
+    pub pane_encoding: String,
     /// window-status-format: format for inactive window tabs
     pub window_status_format: String,
     /// window-status-current-format: format for active window tab
@@ -323,26 +538,48 @@ pub struct AppState {
     pub message_command_style: String,
     /// mode-style: style for copy-mode highlighting
     pub mode_style: String,
+    /// copy-mode-match-style: style for copy-mode search matches
+    pub copy_mode_match_style: String,
+    /// copy-mode-current-match-style: style for the current search match
+    pub copy_mode_current_match_style: String,
     /// status-left-style: style for status-left area
     pub status_left_style: String,
     /// status-right-style: style for status-right area
     pub status_right_style: String,
-    /// Marked pane: (window_index, pane_id) — set by select-pane -m
-    pub marked_pane: Option<(usize, usize)>,
     /// monitor-silence: seconds of silence before flagging (0 = off)
     pub monitor_silence: u64,
+    /// idle-window-timeout: hours without output or a visit before a
+    /// window counts as idle (0 = off)
+    pub idle_window_timeout: u64,
+    /// idle-window-action: "flag" or "kill" an idle window
+    pub idle_window_action: String,
     /// bell-action: "any", "none", "current", "other"
     pub bell_action: String,
-    /// visual-bell: show visual indicator on bell
+    /// visual-bell: show a message for a bell instead of ringing the
+    /// client's bell
     pub visual_bell: bool,
     /// Command prompt history
     pub command_history: Vec<String>,
     /// Command prompt history index (for up/down navigation)
     pub command_history_idx: usize,
+    /// Copy-mode search history
+    pub search_history: Vec<String>,
+    /// Copy-mode search history index (for up/down navigation)
+    pub search_history_idx: usize,
+    /// history-file: where prompt history persists (None = not saved)
+    pub history_file: Option<String>,
+    /// prompt-history-limit: entries kept per history (default 100)
+    pub prompt_history_limit: usize,
+    /// When unsaved history changes began (drives the debounced save)
+    pub history_dirty_since: Option<Instant>,
     /// status-interval: seconds between status-line refreshes (default 15)
     pub status_interval: u64,
     /// status-justify: left, centre, right, absolute-centre
     pub status_justify: String,
+    /// prefix-indicator: show the key state at the right of the status line
+    pub prefix_indicator: bool,
+    /// prefix-indicator-style: style of that indicator
+    pub prefix_indicator_style: String,
     /// main-pane-width: percentage for main pane in main-vertical layout (0 = use 60% heuristic)
     pub main_pane_width: u16,
     /// main-pane-height: percentage for main pane in main-horizontal layout (0 = use 60% heuristic)
@@ -357,14 +594,22 @@ pub struct AppState {
     pub status_format: Vec<String>,
     /// window-size: "smallest", "largest", "manual", "latest" (default "latest")
     pub window_size: String,
+    /// Window size while no client is attached, and with window-size
+    /// manual (`resize-window -x/-y`, `refresh-client -C`)
+    pub headless_size: (u16, u16),
     /// allow-passthrough: "on", "off", "all" (default "off")
     pub allow_passthrough: String,
     /// copy-command: command to pipe yanked text to (default empty)
     pub copy_command: String,
+    /// compat-version: tmux version `#{version}` reports (default
+    /// TMUX_COMPAT_VERSION)
+    pub compat_version: String,
     /// command-alias: map of alias name to expansion
-    pub command_aliases: std::collections::HashMap<String, String>,
+    pub command_aliases: crate::alias::Aliases,
     /// set-clipboard: "on", "off", "external" (default "on")
     pub set_clipboard: String,
+    /// local-echo: "on", "off", "auto" (above a measured round trip)
+    pub local_echo: String,
 }
 
 impl AppState {
@@ -377,6 +622,8 @@ impl AppState {
             mode: Mode::Passthrough,
             escape_time_ms: 500,
             repeat_time_ms: 500,
+            prefix_timeout_ms: 0,
+            confirm_kill: true,
             prefix_key: (crossterm::event::KeyCode::Char('b'), crossterm::event::KeyModifiers::CONTROL),
             prefix2_key: None,
             prediction_dimming: std::env::var("PSMUX_DIM_PREDICTIONS")
@@ -384,8 +631,9 @@ impl AppState {
                 .unwrap_or(false),
             drag: None,
             last_window_area: Rect { x: 0, y: 0, width: 120, height: 30 },
+            mouse_at: (0, 0),
             mouse_enabled: true,
-            paste_buffers: Vec::new(),
+            paste_buffers: crate::buffers::PasteBuffers::default(),
             status_left: "[#S] ".to_string(),
             status_right: "#{?window_bigger,[#{window_offset_x}#,#{window_offset_y}] ,}\"#{=21:pane_title}\" %H:%M %d-%b-%y".to_string(),
             window_base_index: 0,
@@ -393,21 +641,29 @@ impl AppState {
             copy_anchor_scroll_offset: 0,
             copy_pos: None,
             copy_scroll_offset: 0,
+            copy_hscroll: None,
             copy_selection_mode: SelectionMode::Char,
             copy_count: None,
             copy_search_query: String::new(),
             copy_search_matches: Vec::new(),
             copy_search_idx: 0,
             copy_search_forward: true,
+            copy_search_due: None,
+            pattern_timeout_ms: crate::pattern::DEFAULT_TIMEOUT_MS,
+            last_shell_exit: 0,
             copy_find_char_pending: None,
             copy_text_object_pending: None,
             copy_register_pending: false,
             copy_register: None,
-            named_registers: std::collections::HashMap::new(),
             display_map: Vec::new(),
             key_tables: std::collections::HashMap::new(),
             current_key_table: None,
+            key_table_at: Instant::now(),
+            root_any_binding: false,
+            scroll_without_copy_mode: false,
+            scrolled_input_block: false,
             control_rx: None,
+            exit_watcher: None,
             session_name,
             session_id: {
                 static NEXT_SESSION_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
@@ -415,14 +671,21 @@ impl AppState {
             },
             socket_name: None,
             attached_clients: 0,
+            clients: Vec::new(),
+            client_idle_timeout: 0,
+            client_idle_warning: 0,
+            client_idle_exempt_local: true,
             created_at: Local::now(),
             next_win_id: 1,
             next_pane_id: 1,
             zoom_saved: None,
             sync_input: false,
-            hooks: std::collections::HashMap::new(),
+            sync_paste: false,
+            hooks: Default::default(),
+            pending_hooks: Vec::new(),
+            pending_shell_jobs: Vec::new(),
+            pending_message: None,
             wait_channels: std::collections::HashMap::new(),
-            pipe_panes: Vec::new(),
             last_window_idx: 0,
             last_pane_path: Vec::new(),
             tab_positions: Vec::new(),
@@ -439,15 +702,32 @@ impl AppState {
             word_separators: " -_@".to_string(),
             renumber_windows: false,
             automatic_rename: true,
+            automatic_rename_format: crate::pane::DEFAULT_AUTOMATIC_RENAME_FORMAT.to_string(),
+            allow_duplicate_window_names: true,
+            popup_warm_shell: true,
+            popup_spare: None,
+            popup_spare_retry: None,
+            popup_close_key: crate::popup::DEFAULT_CLOSE_KEY.to_string(),
+            process_lookup: crate::pane::ProcessLookup::PLATFORM,
             monitor_activity: false,
             visual_activity: false,
+            monitor_bell: true,
+            visual_silence: false,
             remain_on_exit: false,
             aggressive_resize: false,
             set_titles: false,
             set_titles_string: String::new(),
             environment: std::collections::HashMap::new(),
+            global_env: Default::default(),
+            session_env: Default::default(),
             pane_border_style: String::new(),
             pane_active_border_style: "fg=green".to_string(),
+            pane_border_lines: "single".to_string(),
+            pane_border_indicators: "colour".to_string(),
+            pane_border_status: "off".to_string(),
+            pane_border_format: DEFAULT_PANE_BORDER_FORMAT.to_string(),
+            highlight_pattern: String::new(),
+            pane_encoding: "utf8".to_string(),
             window_status_format: "#I:#W#{?window_flags,#{window_flags}, }".to_string(),
             window_status_current_format: "#I:#W#{?window_flags,#{window_flags}, }".to_string(),
             window_status_separator: " ".to_string(),
@@ -459,27 +739,40 @@ impl AppState {
             message_style: "bg=yellow,fg=black".to_string(),
             message_command_style: "bg=black,fg=yellow".to_string(),
             mode_style: "bg=yellow,fg=black".to_string(),
+            copy_mode_match_style: "bg=cyan,fg=black".to_string(),
+            copy_mode_current_match_style: "bg=magenta,fg=black".to_string(),
             status_left_style: String::new(),
             status_right_style: String::new(),
-            marked_pane: None,
             monitor_silence: 0,
+            idle_window_timeout: 0,
+            idle_window_action: "flag".to_string(),
             bell_action: "any".to_string(),
             visual_bell: false,
             command_history: Vec::new(),
             command_history_idx: 0,
+            search_history: Vec::new(),
+            search_history_idx: 0,
+            history_file: None,
+            prompt_history_limit: 100,
+            history_dirty_since: None,
             status_interval: 15,
             status_justify: "left".to_string(),
+            prefix_indicator: true,
+            prefix_indicator_style: "reverse".to_string(),
             main_pane_width: 0,
             main_pane_height: 0,
             status_left_length: 10,
             status_right_length: 40,
             status_lines: 1,
             status_format: Vec::new(),
-            window_size: "latest".to_string(),
+            window_size: "smallest".to_string(),
+            headless_size: (120, 30),
             allow_passthrough: "off".to_string(),
             copy_command: String::new(),
-            command_aliases: std::collections::HashMap::new(),
+            compat_version: TMUX_COMPAT_VERSION.to_string(),
+            command_aliases: Default::default(),
             set_clipboard: "on".to_string(),
+            local_echo: "off".to_string(),
         }
     }
 
@@ -495,6 +788,93 @@ impl AppState {
     }
 }
 
+/// A shell command queued from a config file.  Plugin scripts call back into
+/// the server (`tmux set-option ...`), so these run on a worker thread once
+/// the server loop is answering requests.
+pub enum ShellJob {
+    /// run-shell [-b] <command>
+    Run { command: String, background: bool },
+    /// if-shell -b <condition> <then> [<else>]
+    IfShell { condition: String, then_cmd: String, else_cmd: Option<String> },
+}
+
+/// A persistent client connection that sent `client-attach`.
+pub struct ClientConn {
+    pub id: u64,
+    /// Attached over SSH rather than from the local console
+    pub remote: bool,
+    /// Last time the client sent input (keys, paste, mouse)
+    pub last_input: Instant,
+    /// An idle-detach countdown is currently shown on this client
+    pub idle_warned: bool,
+    /// Out-of-band lines for this client (`%detach`, `%message ...`).
+    /// Feeds the connection's writer thread so they stay ordered with frames.
+    pub directives: mpsc::Sender<mpsc::Receiver<String>>,
+    /// Low-bandwidth mode (`refresh-client -f low-bandwidth`).  Shared with
+    /// the connection, which caps this client's frame rate.
+    pub low_bandwidth: Arc<std::sync::atomic::AtomicBool>,
+    /// The client draws panes over the status line (`attach --no-status`)
+    pub no_status: bool,
+    /// Terminal size from the client's last client-size (0x0 until then)
+    pub size: (u16, u16),
+    /// The prefix key is armed on this client (`client-prefix 1`)
+    pub prefix_armed: bool,
+    /// The client is suspended (`client-suspend 1`, see crate::suspend)
+    pub suspended: bool,
+    /// When the client attached (`list-clients`, `client_created`)
+    pub attached_at: chrono::DateTime<Local>,
+    /// A handle on the client's connection, to close it from the server
+    /// when the client does not leave by itself (`None` in tests)
+    pub pipe: Option<Arc<dyn crate::transport::Transport>>,
+    /// Registry name of the session this client was on before (switch-client -l)
+    pub last_session: Option<String>,
+    /// Frames of this kind are pushed to the client as the screen changes
+    /// instead of waiting for dump-state (`client-subscribe 1`)
+    pub push: Option<crate::bandwidth::FrameKind>,
+    /// Version of the last full frame sent to this client (0: none yet),
+    /// so an unchanged dump-state is answered "NC" only once it has it
+    pub frame_version: u64,
+}
+
+#[cfg(test)]
+impl ClientConn {
+    /// A local client attached now, with no pipe.  Its directives go
+    /// nowhere; tests that read them pass their own sender.
+    pub fn test(id: u64) -> Self {
+        ClientConn {
+            id, remote: false, last_input: Instant::now(), idle_warned: false, directives: mpsc::channel().0,
+            low_bandwidth: Default::default(), no_status: false, size: (0, 0), prefix_armed: false, suspended: false,
+            attached_at: Local::now(), pipe: None, last_session: None, push: None, frame_version: 0,
+        }
+    }
+}
+
+impl ClientConn {
+    /// How long a detached client has to leave before its pipe is closed.
+    pub const DETACH_GRACE: Duration = Duration::from_secs(2);
+
+    /// Detach this client: it answers `%detach` by restoring its terminal
+    /// and leaving.  One that does not (a dead SSH session) is disconnected
+    /// after DETACH_GRACE, which ends its connection on the server.
+    pub fn detach(&self) {
+        self.send_directive("%detach".to_string());
+        if let Some(pipe) = self.pipe.clone() {
+            std::thread::spawn(move || {
+                std::thread::sleep(Self::DETACH_GRACE);
+                pipe.disconnect();
+            });
+        }
+    }
+
+    /// Queue a directive line for this client.  Returns false if the
+    /// connection has gone away.
+    pub fn send_directive(&self, line: String) -> bool {
+        let (tx, rx) = mpsc::channel::<String>();
+        let _ = tx.send(line);
+        self.directives.send(rx).is_ok()
+    }
+}
+
 pub struct DragState {
     pub split_path: Vec<usize>,
     pub kind: LayoutKind,
@@ -505,6 +885,11 @@ pub struct DragState {
     pub _right_initial: u16,
     /// Total pixel dimension of the parent split area along the split axis.
     pub total_pixels: u16,
+    /// Time of the last press/drag event; a drag idle past `DRAG_TIMEOUT`
+    /// is abandoned (the button-up was lost outside the terminal).
+    pub last_event: Instant,
+    /// Time the pane PTYs were last resized during this drag (debounce).
+    pub last_pty_resize: Instant,
 }
 
 #[derive(Clone)]
@@ -535,33 +920,74 @@ pub enum Action {
 #[derive(Clone)]
 pub struct Bind { pub key: (KeyCode, KeyModifiers), pub action: Action, pub repeat: bool }
 
+/// Where switch-client moves a client: -t, -n, -p or -l.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SwitchTo {
+    Named(String),
+    Next,
+    Previous,
+    Last,
+}
+
 pub enum CtrlReq {
-    NewWindow(Option<String>, Option<String>, bool, Option<String>),  // cmd, name, detached, start_dir
-    NewWindowPrint(Option<String>, Option<String>, bool, Option<String>, Option<String>, mpsc::Sender<String>),  // cmd, name, detached, start_dir, format, resp
-    SplitWindow(LayoutKind, Option<String>, bool, Option<String>, Option<u16>, mpsc::Sender<String>),  // kind, cmd, detached, start_dir, size_percent, error_resp
-    SplitWindowPrint(LayoutKind, Option<String>, bool, Option<String>, Option<u16>, Option<String>, mpsc::Sender<String>),  // kind, cmd, detached, start_dir, size_percent, format, resp
-    KillPane,
-    CapturePane(mpsc::Sender<String>),
-    CapturePaneStyled(mpsc::Sender<String>, Option<i32>, Option<i32>),
+    NewWindow(Option<String>, Option<String>, bool, Option<String>, Option<usize>, bool, mpsc::Sender<String>),  // cmd, name, detached, start_dir, index, kill, err resp
+    NewWindowPrint(Option<String>, Option<String>, bool, Option<String>, Option<usize>, bool, Option<String>, mpsc::Sender<String>),  // cmd, name, detached, start_dir, index, kill, format, resp
+    SplitWindow(LayoutKind, Option<String>, bool, Option<String>, SplitOpts, mpsc::Sender<String>, Option<usize>),  // kind, cmd, detached, start_dir, -l/-p/-b/-f, error_resp, -t pane
+    SplitWindowPrint(LayoutKind, Option<String>, bool, Option<String>, SplitOpts, Option<String>, mpsc::Sender<String>, Option<usize>),  // kind, cmd, detached, start_dir, -l/-p/-b/-f, format, resp, -t pane
+    KillPane(Option<usize>),  // -t pane id, else the active pane
+    CapturePane(mpsc::Sender<String>, Option<usize>),  // resp, -t pane id
+    CapturePaneStyled(mpsc::Sender<String>, Option<i32>, Option<i32>, bool, Option<usize>),  // resp, start, end, join wrapped lines, -t pane id
     FocusWindow(usize),
     FocusPane(usize),
     FocusPaneByIndex(usize),
-    SessionInfo(mpsc::Sender<String>),
-    CapturePaneRange(mpsc::Sender<String>, Option<i32>, Option<i32>),
+    /// Resolve the window and pane parts of a -t target (parsed, as given)
+    /// to (window display index, pane id)
+    ResolveTarget(ParsedTarget, String, mpsc::Sender<Result<(usize, usize), String>>),
+    /// A command line naming no psmux command, with its command-alias
+    /// expanded (None when it names no alias either)
+    ExpandAlias(String, mpsc::Sender<Result<Option<String>, String>>),
+    /// run-shell: command (formats unexpanded), -b, and where to send
+    /// (exit status, output) for a CLI caller; without one, output is
+    /// shown to the current client
+    RunShell(String, bool, Option<mpsc::Sender<(i32, String)>>),
+    /// A run-shell job finished: command, exit status, output to show
+    ShellDone(String, i32, Option<String>),
+    /// if-shell: condition, then, else, -F, -b; answered with the branch for
+    /// the connection to run (None for neither, or with -b)
+    IfShell(String, String, Option<String>, bool, bool, mpsc::Sender<Option<String>>),
+    /// session-info; true asks for the JSON form (`-v`)
+    SessionInfo(mpsc::Sender<String>, bool),
+    CapturePaneRange(mpsc::Sender<String>, Option<i32>, Option<i32>, bool, Option<usize>),
     ClientAttach,
     ClientDetach,
+    ClientRegister(u64, bool, mpsc::Sender<mpsc::Receiver<String>>, Arc<std::sync::atomic::AtomicBool>, Option<Arc<dyn crate::transport::Transport>>, Option<String>),  // (client id, remote, directive channel, low-bandwidth, connection, last session)
+    ClientUnregister(u64),
+    /// detach-client: (requesting client, -t client, -a, error reply)
+    DetachClient(Option<u64>, Option<String>, bool, mpsc::Sender<Result<(), String>>),
+    ClientActivity(u64),
     DumpLayout(mpsc::Sender<String>),
-    DumpState(mpsc::Sender<String>, bool),  // (resp, allow_nc)
+    DumpState(mpsc::Sender<String>, bool, crate::bandwidth::FrameKind, Option<u64>),  // (resp, allow_nc, frame kind, client id)
     SendText(String),
     SendKey(String),
     SendPaste(String),
+    /// paste-buffer: text, and whether to paste into every pane (`-s`)
+    PasteText(String, bool),
+    /// send-bytes: bytes written to the active pane exactly as given
+    SendBytes(Vec<u8>, Option<usize>),
     ZoomPane,
     CopyEnter,
     CopyEnterPageUp,
     CopyMove(i16, i16),
     CopyAnchor,
     CopyYank,
-    ClientSize(u16, u16),
+    /// client-size from a connection, with its client id once attached
+    ClientSize(Option<u64>, u16, u16),
+    /// A pane's process exited (pane id, pid, exit code), from the exit watcher
+    ChildExited(usize, u32, Option<u32>),
+    /// client-prefix: a client armed (true) or resolved (false) its prefix
+    ClientPrefix(Option<u64>, bool),
+    ClientSuspend(Option<u64>, bool),
+    ClientSubscribe(Option<u64>, Option<crate::bandwidth::FrameKind>),
     FocusPaneCmd(usize),
     FocusWindowCmd(usize),
     MouseDown(u16,u16),
@@ -569,6 +995,9 @@ pub enum CtrlReq {
     MouseDownMiddle(u16,u16),
     MouseDrag(u16,u16),
     MouseUp(u16,u16),
+    /// Release of a double (2) or triple (3) click: select and yank the word
+    /// or line under the pointer
+    MouseSelect(u16,u16,u8),
     MouseUpRight(u16,u16),
     MouseUpMiddle(u16,u16),
     MouseMove(u16,u16),
@@ -576,92 +1005,128 @@ pub enum CtrlReq {
     ScrollDown(u16, u16),
     NextWindow,
     PrevWindow,
-    RenameWindow(String),
+    /// New name, and where to send "" or the error for a CLI caller
+    /// (None: a client, shown as a message)
+    RenameWindow(String, Option<mpsc::Sender<String>>, Option<usize>),
+    /// save-session: answers with an error, or nothing (None: shown as a message)
+    SaveSession(Option<mpsc::Sender<String>>),
+    /// restore-session [snapshot]: answered like SaveSession
+    RestoreSession(Option<String>, Option<mpsc::Sender<String>>),
     ListWindows(mpsc::Sender<String>),
     ListWindowsTmux(mpsc::Sender<String>),
     ListWindowsFormat(mpsc::Sender<String>, String),
     ListTree(mpsc::Sender<String>),
+    /// choose-tree / choose-window (true) arguments; answered with the
+    /// command `-s` chose at once, or an error
+    ChooseTree(Vec<String>, bool, mpsc::Sender<Result<Option<String>, String>>),
     ToggleSync,
     SetPaneTitle(String),
-    SendKeys(String, bool),
-    SendKeysX(String),  // send-keys -X copy-mode-command
+    SendKeys(String, bool, Option<usize>),
+    SendKeysX(String, Option<usize>),  // send-keys -X copy-mode-command
+    /// send-keys -R: reset the active pane's terminal state
+    ResetTerminal(Option<usize>),
     SelectPane(String),
     SelectWindow(usize),
     ListPanes(mpsc::Sender<String>),
     ListPanesFormat(mpsc::Sender<String>, String),
     ListAllPanes(mpsc::Sender<String>),
     ListAllPanesFormat(mpsc::Sender<String>, String),
-    KillWindow,
+    KillWindow(Option<usize>),
     KillSession,
     HasSession(mpsc::Sender<bool>),
     RenameSession(String),
-    SwapPane(String),
+    SwapPane(SwapWith, bool, Option<mpsc::Sender<String>>),  // with, -d
     ResizePane(String, u16),
-    SetBuffer(String),
+    /// Buffer name (None for a new automatic buffer), text, append (`-a`)
+    SetBuffer(Option<String>, String, bool),
     ListBuffers(mpsc::Sender<String>),
     ListBuffersFormat(mpsc::Sender<String>, String),
-    ShowBuffer(mpsc::Sender<String>),
-    ShowBufferAt(mpsc::Sender<String>, usize),
-    DeleteBuffer,
-    DisplayMessage(mpsc::Sender<String>, String),
+    /// The named buffer's text, or the most recent buffer's when None
+    ShowBuffer(mpsc::Sender<String>, Option<String>),
+    /// The system clipboard's text (paste-buffer -p with no buffers)
+    ReadClipboard(mpsc::Sender<String>),
+    DeleteBuffer(Option<String>),
+    DisplayMessage(mpsc::Sender<String>, String, Option<usize>),
+    /// Show text on the clients' status line for display-time
+    ShowMessage(String),
+    /// display-message -a: every format variable as name=value lines
+    DisplayVariables(mpsc::Sender<String>, Option<usize>),
+    /// display-message -I: text written to the target pane's screen
+    DisplayInput(String, Option<usize>),
     LastWindow,
     LastPane,
     RotateWindow(bool),
     DisplayPanes,
-    BreakPane,
-    JoinPane(usize),
-    RespawnPane,
+    BreakPane(bool),  // -d
+    // (-s source pane, -t target pane, -h/-v, -l/-p, -d)
+    JoinPane(Option<String>, Option<String>, LayoutKind, Option<PaneSize>, bool, Option<mpsc::Sender<String>>),
+    RespawnPane(bool, Option<mpsc::Sender<String>>, Option<usize>),  // -k
     BindKey(String, String, String, bool),  // table, key, command, repeat
-    UnbindKey(String),
+    UnbindKey(String, Option<String>),
     ListKeys(mpsc::Sender<String>),
     SetOption(String, String),
     SetOptionQuiet(String, String, bool),  // set-option with quiet flag
     SetOptionUnset(String),  // set-option -u
     SetOptionAppend(String, String),  // set-option -a
-    ShowOptions(mpsc::Sender<String>),
+    SetScopedOption(OptionScope, String, Option<String>, Option<usize>),  // set-option -w/-p; None unsets
+    ShowOptions(mpsc::Sender<String>, Option<OptionScope>, Option<usize>),  // -w/-p: effective values for the active window/pane
     SourceFile(String),
-    MoveWindow(Option<usize>),
+    MoveWindow(Option<String>, Option<usize>, bool, bool, bool, mpsc::Sender<String>),  // source, index, kill, renumber, detached, err resp
     SwapWindow(usize),
     LinkWindow(String),
     UnlinkWindow,
     FindWindow(mpsc::Sender<String>, String),
-    MovePane(usize),
-    PipePane(String, bool, bool),
-    SelectLayout(String),
+    PipePane(String, bool, bool, bool, Option<usize>),  // command, -I, -O, -o, -t pane id
+    SelectLayout(String, Option<mpsc::Sender<String>>),
     NextLayout,
     ListClients(mpsc::Sender<String>),
-    SwitchClient(String),
+    /// switch-client from a client (its id) or the CLI (None), for the
+    /// client -c names or else that one
+    SwitchClient(Option<u64>, Option<String>, SwitchTo, mpsc::Sender<Result<(), String>>),
     LockClient,
-    RefreshClient,
-    SuspendClient,
+    RefreshClient(Option<u64>, Option<String>),  // (requesting client, -f flags)
+    /// begin-batch from a connection; replies "" or an error message
+    BeginBatch(u64, mpsc::Sender<String>),
+    /// commit-batch (true) or abort-batch / connection drop (false)
+    EndBatch(u64, bool, mpsc::Sender<String>),
+    /// suspend-client from a client (its id) or the CLI (None)
+    SuspendClient(Option<u64>),
+    /// choose-session: open the session chooser on the client that asked,
+    /// else the one used most recently
+    ChooseSession(Option<u64>),
     CopyModePageUp,
-    ClearHistory,
-    SaveBuffer(String),
-    LoadBuffer(String),
-    SetEnvironment(String, String),
-    ShowEnvironment(mpsc::Sender<String>),
-    SetHook(String, String),
-    ShowHooks(mpsc::Sender<String>),
-    RemoveHook(String),
+    /// Clear the active pane's scrollback; `true` (-H) clears the screen too
+    ClearHistory(bool, Option<usize>),
+    SaveBuffer(String, Option<String>),
+    LoadBuffer(String, Option<String>),
+    SetEnvironment(crate::environ::SetEnv),
+    ShowEnvironment(mpsc::Sender<String>, bool),  // -g: the global environment
+    ClientEnvironment(Vec<(String, String)>),  // an attaching client's, for update-environment
+    SetHook(crate::hooks::SetHook),  // set, -u removes, -R runs now
+    ShowHooks(mpsc::Sender<String>, Option<String>),
     KillServer,
     WaitFor(String, WaitForOp),
-    DisplayMenu(String, Option<i16>, Option<i16>),
-    DisplayPopup(String, u16, u16, bool),
-    ConfirmBefore(String, String),
+    /// display-menu's arguments, as typed
+    DisplayMenu(String),
+    DisplayPopup(crate::popup::PopupArgs),
+    ConfirmBefore(crate::prompt::Prompt),
     ClockMode,
     ResizePaneAbsolute(String, u16),
-    ShowOptionValue(mpsc::Sender<String>, String),
+    ShowOptionValue(mpsc::Sender<String>, String, Option<OptionScope>, Option<usize>),
     ChooseBuffer(mpsc::Sender<String>),
+    /// choose-client: (requesting client, template, error reply)
+    ChooseClient(Option<u64>, Option<String>, mpsc::Sender<Result<(), String>>),
     ServerInfo(mpsc::Sender<String>),
     SendPrefix,
     PrevLayout,
     SwitchClientTable(String),
     ListCommands(mpsc::Sender<String>),
-    ResizeWindow(String, u16),
+    ResizeWindow(Option<u16>, Option<u16>),
     RespawnWindow,
     FocusIn,
     FocusOut,
-    CommandPrompt(String),
+    CommandPrompt(crate::prompt::Prompt),
+    PromptHistory(String),  // an answer given at a client's command-prompt
     ShowMessages(mpsc::Sender<String>),
 }
 
@@ -684,6 +1149,8 @@ pub enum WaitForOp {
 pub struct ParsedTarget {
     pub session: Option<String>,
     pub window: Option<usize>,
+    /// Window given by name (exact, or a prefix of one window's name)
+    pub window_name: Option<String>,
     pub pane: Option<usize>,
     pub pane_is_id: bool,
     pub window_is_id: bool,
@@ -46,23 +46,78 @@ pub fn infer_title_from_prompt(screen: &vt100::Screen, rows: u16, cols: u16) ->
 // resolve_last_session_name and resolve_default_session_name are in session.rs
 
 #[derive(Serialize, Deserialize)]
-pub struct WinInfo { pub id: usize, pub name: String, pub active: bool, #[serde(default)] pub activity: bool, #[serde(default)] pub tab_text: String }
+pub struct WinInfo {
+    pub id: usize, pub name: String, pub active: bool, #[serde(default)] pub activity: bool, #[serde(default)] pub tab_text: String,
+    /// The window's display index (#{window_index})
+    #[serde(default)] pub index: usize,
+    /// Style for the tab while the window has an alert (empty without one)
+    #[serde(default)] pub alert_style: String,
+}
 
-#[derive(Serialize, Deserialize)]
-pub struct PaneInfo { pub id: usize, pub title: String }
+/// A pane as the choosers see it: `index` counts from pane-base-index and
+/// `command` is `#{pane_current_command}`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PaneInfo {
+    pub id: usize, pub title: String,
+    #[serde(default)] pub index: usize,
+    #[serde(default)] pub command: String,
+    #[serde(default)] pub width: u16,
+    #[serde(default)] pub height: u16,
+    #[serde(default)] pub active: bool,
+}
 
-#[derive(Serialize, Deserialize)]
-pub struct WinTree { pub id: usize, pub name: String, pub active: bool, pub panes: Vec<PaneInfo> }
+/// A window as the choosers see it.  `index` is the display index (`#I`);
+/// `activity_ms` / `visited_ms` are the window's last output and last
+/// focus, in Unix milliseconds.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WinTree {
+    pub id: usize, pub name: String, pub active: bool, pub panes: Vec<PaneInfo>,
+    #[serde(default)] pub index: usize,
+    #[serde(default)] pub activity_ms: i64,
+    #[serde(default)] pub visited_ms: i64,
+}
+
+/// Window order in the window chooser; `o` cycles through them.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum WindowSort {
+    #[default]
+    Index,
+    Activity,
+    Visited,
+}
+
+impl WindowSort {
+    pub fn next(self) -> Self {
+        match self { WindowSort::Index => WindowSort::Activity, WindowSort::Activity => WindowSort::Visited, WindowSort::Visited => WindowSort::Index }
+    }
+
+    /// Shown in the chooser title.
+    pub fn label(self) -> &'static str {
+        match self { WindowSort::Index => "index", WindowSort::Activity => "activity", WindowSort::Visited => "visited" }
+    }
+
+    /// Positions into `wins` (which is in index order) in this order; most
+    /// recent first for the time-based orders, ties in index order.
+    pub fn order(self, wins: &[WinTree]) -> Vec<usize> {
+        let mut pos: Vec<usize> = (0..wins.len()).collect();
+        match self {
+            WindowSort::Index => {}
+            WindowSort::Activity => pos.sort_by_key(|&i| std::cmp::Reverse(wins[i].activity_ms)),
+            WindowSort::Visited => pos.sort_by_key(|&i| std::cmp::Reverse(wins[i].visited_ms)),
+        }
+        pos
+    }
+}
 
 pub fn list_windows_json(app: &AppState) -> io::Result<String> {
     let mut v: Vec<WinInfo> = Vec::new();
-    for (i, w) in app.windows.iter().enumerate() { v.push(WinInfo { id: w.id, name: w.name.clone(), active: i == app.active_idx, activity: w.activity_flag, tab_text: String::new() }); }
+    for (i, w) in app.windows.iter().enumerate() { v.push(WinInfo { id: w.id, index: w.index, name: w.name.clone(), active: i == app.active_idx, activity: w.activity_flag, tab_text: String::new(), alert_style: crate::alerts::tab_style(app, w) }); }
     let s = serde_json::to_string(&v).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("json error: {e}")))?;
     Ok(s)
 }
 
 /// tmux-compatible list-windows output: one line per window
-/// Format: `<index>: <name><flag> (<pane_count> panes) [<width>x<height>]`
+/// Format: `<index>: <name><flag> (<pane_count> panes) [<width>x<height>] [layout <layout>]`
 pub fn list_windows_tmux(app: &AppState) -> String {
     use crate::tree::*;
     fn count_panes(node: &Node) -> usize {
@@ -78,23 +133,34 @@ pub fn list_windows_tmux(app: &AppState) -> String {
         let (width, height) = if let Some(p) = active_pane(&w.root, &w.active_path) {
             (p.last_cols, p.last_rows)
         } else { (120, 30) };
-        lines.push(format!("{}: {}{} ({} panes) [{}x{}]", i + app.window_base_index, w.name, flag, pane_count, width, height));
+        let layout = crate::format::generate_window_layout(&w.root, app.last_window_area);
+        lines.push(format!("{}: {}{} ({} panes) [{}x{}] [layout {}]", w.index, w.name, flag, pane_count, width, height, layout));
     }
     lines.join("\n")
 }
 
 pub fn list_tree_json(app: &AppState) -> io::Result<String> {
-    fn collect_panes(node: &Node, out: &mut Vec<PaneInfo>) {
+    fn collect_panes(node: &Node, active: Option<usize>, base: usize, out: &mut Vec<PaneInfo>) {
         match node {
-            Node::Leaf(p) => { out.push(PaneInfo { id: p.id, title: p.title.clone() }); }
-            Node::Split { children, .. } => { for c in children.iter() { collect_panes(c, out); } }
+            Node::Leaf(p) => {
+                out.push(PaneInfo {
+                    id: p.id, title: p.title.clone(), index: base + out.len(),
+                    command: crate::pane::pane_current_command(p),
+                    width: p.last_cols, height: p.last_rows, active: active == Some(p.id),
+                });
+            }
+            Node::Split { children, .. } => { for c in children.iter() { collect_panes(c, active, base, out); } }
         }
     }
     let mut v: Vec<WinTree> = Vec::new();
     for (i, w) in app.windows.iter().enumerate() {
         let mut panes = Vec::new();
-        collect_panes(&w.root, &mut panes);
-        v.push(WinTree { id: w.id, name: w.name.clone(), active: i == app.active_idx, panes });
+        let active = crate::tree::active_pane(&w.root, &w.active_path).map(|p| p.id);
+        collect_panes(&w.root, active, app.pane_base_index, &mut panes);
+        v.push(WinTree {
+            id: w.id, name: w.name.clone(), active: i == app.active_idx, panes, index: w.index,
+            activity_ms: w.activity_at.timestamp_millis(), visited_ms: w.visited_at.timestamp_millis(),
+        });
     }
     let s = serde_json::to_string(&v).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("json error: {e}")))?;
     Ok(s)
@@ -103,7 +169,10 @@ pub fn list_tree_json(app: &AppState) -> io::Result<String> {
 pub const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
 pub fn base64_encode(data: &str) -> String {
-    let bytes = data.as_bytes();
+    base64_encode_bytes(data.as_bytes())
+}
+
+pub fn base64_encode_bytes(bytes: &[u8]) -> String {
     let mut result = String::new();
     for chunk in bytes.chunks(3) {
         let b0 = chunk[0] as usize;
@@ -126,6 +195,10 @@ pub fn base64_encode(data: &str) -> String {
 }
 
 pub fn base64_decode(encoded: &str) -> Option<String> {
+    String::from_utf8(base64_decode_bytes(encoded)?).ok()
+}
+
+pub fn base64_decode_bytes(encoded: &str) -> Option<Vec<u8>> {
     let mut result = Vec::new();
     let chars: Vec<u8> = encoded.bytes().filter(|&b| b != b'=').collect();
     for chunk in chars.chunks(4) {
@@ -142,7 +215,20 @@ pub fn base64_decode(encoded: &str) -> Option<String> {
             }
         }
     }
-    String::from_utf8(result).ok()
+    Some(result)
+}
+
+/// Decode a `send-bytes --hex` payload.  Whitespace between digits is
+/// ignored; anything else that is not a hex digit is an error.
+pub fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    if let Some((i, c)) = digits.iter().enumerate().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex digit '{}' at position {}", c, i + 1));
+    }
+    if digits.len() % 2 != 0 {
+        return Err(format!("odd number of hex digits ({}): every byte needs two", digits.len()));
+    }
+    Ok(digits.chunks(2).map(|p| (p[0].to_digit(16).unwrap() * 16 + p[1].to_digit(16).unwrap()) as u8).collect())
 }
 
 /// Return color name as a string. Uses static strings for Default and
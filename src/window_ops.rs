@@ -1,14 +1,15 @@
 use std::io::{self, Write};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use portable_pty::{PtySize, native_pty_system};
 use ratatui::prelude::*;
 
-use crate::types::{AppState, Mode, Pane, Node, LayoutKind, DragState, Window, FocusDir};
-use crate::tree::{active_pane_mut, compute_rects, compute_split_borders,
-    split_sizes_at, adjust_split_sizes, get_split_mut, resize_all_panes};
+use crate::types::{AppState, Mode, Pane, PaneSize, Node, LayoutKind, DragState, Window, FocusDir};
+use crate::tree::{active_pane_mut, compute_rects, splitter_at,
+    split_sizes_at, adjust_split_sizes, get_split_mut, resize_all_panes, DRAG_RESIZE_DEBOUNCE};
 use crate::pane::{detect_shell, build_default_shell, set_tmux_env};
-use crate::copy_mode::{scroll_copy_up, scroll_copy_down, yank_selection};
+use crate::copy_mode::{scroll_copy_up, scroll_copy_down, yank_to_register};
 use crate::platform::mouse_inject;
 
 /// Mouse debug logger — writes to ~/.psmux/mouse_debug.log when enabled.
@@ -51,7 +52,7 @@ fn pane_inner_cell(area: Rect, abs_x: u16, abs_y: u16) -> (u16, u16) {
 }
 
 /// Write a mouse event to the child PTY using the encoding the child requested.
-fn write_mouse_event_remote(master: &mut dyn std::io::Write, button: u8, col: u16, row: u16, press: bool, enc: vt100::MouseProtocolEncoding) {
+pub(crate) fn write_mouse_event_remote(master: &mut dyn std::io::Write, button: u8, col: u16, row: u16, press: bool, enc: vt100::MouseProtocolEncoding) {
     match enc {
         vt100::MouseProtocolEncoding::Sgr => {
             let ch = if press { 'M' } else { 'm' };
@@ -298,6 +299,83 @@ pub fn toggle_zoom(app: &mut AppState) {
     resize_all_panes(app);
 }
 
+/// Smallest display index at or above `base` that no window uses.
+pub fn smallest_free_index(used: &[usize], base: usize) -> usize {
+    let mut n = base;
+    while used.contains(&n) { n += 1; }
+    n
+}
+
+/// Display index for a window being created (or moved): the index given
+/// with `-t`, or the smallest free one.  An index that is taken is refused
+/// unless `kill` (-k) allows replacing the window there.
+pub fn pick_window_index(used: &[usize], base: usize, requested: Option<usize>, kill: bool) -> Result<usize, String> {
+    match requested {
+        None => Ok(smallest_free_index(used, base)),
+        Some(n) if used.contains(&n) && !kill => Err(format!("index {} in use", n)),
+        Some(n) => Ok(n),
+    }
+}
+
+/// Indices of the remaining windows after one closes: renumber-windows
+/// closes the gap, otherwise every window keeps its number.
+pub fn indices_after_close(remaining: &[usize], base: usize, renumber: bool) -> Vec<usize> {
+    if renumber { (base..base + remaining.len()).collect() } else { remaining.to_vec() }
+}
+
+pub fn window_indices(app: &AppState) -> Vec<usize> {
+    app.windows.iter().map(|w| w.index).collect()
+}
+
+/// Position in `app.windows` of the window with display index `idx`.
+pub fn window_pos_by_index(app: &AppState, idx: usize) -> Option<usize> {
+    app.windows.iter().position(|w| w.index == idx)
+}
+
+/// Insert `win` in display-index order and return its position.
+/// `active_idx` and `last_window_idx` keep pointing at the same windows.
+pub fn insert_window(app: &mut AppState, win: Window) -> usize {
+    let pos = app.windows.iter().position(|w| w.index > win.index).unwrap_or(app.windows.len());
+    app.windows.insert(pos, win);
+    if app.windows.len() > 1 {
+        if app.active_idx >= pos { app.active_idx += 1; }
+        if app.last_window_idx >= pos { app.last_window_idx += 1; }
+    }
+    let ctx = crate::hooks::window_context(app, pos);
+    crate::hooks::queue(app, "window-linked", ctx);
+    pos
+}
+
+/// Take the window at `pos` out without renumbering (new-window -k reuses
+/// its index).  Callers fix up `active_idx`.
+pub fn take_window(app: &mut AppState, pos: usize) -> Window {
+    let ctx = crate::hooks::window_context(app, pos);
+    crate::hooks::queue(app, "window-unlinked", ctx);
+    let win = app.windows.remove(pos);
+    if app.last_window_idx > pos { app.last_window_idx -= 1; }
+    win
+}
+
+/// Remove the window at `pos`, renumbering the rest when renumber-windows
+/// is on.  Callers fix up `active_idx`.
+pub fn remove_window(app: &mut AppState, pos: usize) -> Window {
+    let win = take_window(app, pos);
+    let indices = indices_after_close(&window_indices(app), app.window_base_index, app.renumber_windows);
+    for (w, idx) in app.windows.iter_mut().zip(indices) { w.index = idx; }
+    win
+}
+
+/// Give the window at `pos` display index `idx` and return its new position.
+pub fn set_window_index(app: &mut AppState, pos: usize, idx: usize) -> usize {
+    let was_active = app.active_idx == pos;
+    let mut win = take_window(app, pos);
+    if app.active_idx > pos { app.active_idx -= 1; }
+    win.index = idx;
+    let new_pos = insert_window(app, win);
+    if was_active { app.active_idx = new_pos; }
+    new_pos
+}
+
 /// Compute tab positions on the server side to match the client's status bar layout.
 /// The client renders: "[session_name] idx: window_name idx: window_name ..."
 pub fn update_tab_positions(app: &mut AppState) {
@@ -308,8 +386,7 @@ pub fn update_tab_positions(app: &mut AppState) {
     cursor_x += session_label_len;
     // Window tabs: "idx: window_name " for each window
     for (i, w) in app.windows.iter().enumerate() {
-        let display_idx = i + app.window_base_index;
-        let label = format!("{}: {} ", display_idx, w.name);
+        let label = format!("{}: {} ", w.index, w.name);
         let start_x = cursor_x;
         cursor_x += label.len() as u16;
         tab_pos.push((i, start_x, cursor_x));
@@ -322,7 +399,7 @@ pub fn remote_mouse_down(app: &mut AppState, x: u16, y: u16) {
     update_tab_positions(app);
 
     // Check tab click on status bar
-    let status_row = app.last_window_area.y + app.last_window_area.height;
+    let status_row = crate::tree::window_size(app).1;
     if y == status_row {
         for &(win_idx, x_start, x_end) in app.tab_positions.iter() {
             if x >= x_start && x < x_end && win_idx < app.windows.len() {
@@ -354,18 +431,13 @@ pub fn remote_mouse_down(app: &mut AppState, x: u16, y: u16) {
         return;
     }
 
-    let mut on_border = false;
-    let mut borders: Vec<(Vec<usize>, LayoutKind, usize, u16, u16)> = Vec::new();
-    compute_split_borders(&win.root, app.last_window_area, &mut borders);
-    let tol = 1u16;
-    for (path, kind, idx, pos, total_px) in borders.iter() {
-        match kind {
-            LayoutKind::Horizontal => {
-                if x >= pos.saturating_sub(tol) && x <= pos + tol { if let Some((left,right)) = split_sizes_at(&win.root, path.clone(), *idx) { app.drag = Some(DragState { split_path: path.clone(), kind: *kind, index: *idx, start_x: *pos, start_y: y, left_initial: left, _right_initial: right, total_pixels: *total_px }); } on_border = true; break; }
-            }
-            LayoutKind::Vertical => {
-                if y >= pos.saturating_sub(tol) && y <= pos + tol { if let Some((left,right)) = split_sizes_at(&win.root, path.clone(), *idx) { app.drag = Some(DragState { split_path: path.clone(), kind: *kind, index: *idx, start_x: x, start_y: *pos, left_initial: left, _right_initial: right, total_pixels: *total_px }); } on_border = true; break; }
-            }
+    // Only the splitter cell itself starts a drag; a pane's edge column is the pane's
+    let splitter = splitter_at(&win.root, app.last_window_area, x, y);
+    let on_border = splitter.is_some();
+    if let Some((path, kind, idx, pos, total_px)) = splitter {
+        if let Some((left, right)) = split_sizes_at(&win.root, path.clone(), idx) {
+            let (start_x, start_y) = match kind { LayoutKind::Horizontal => (pos, y), LayoutKind::Vertical => (x, pos) };
+            app.drag = Some(DragState { split_path: path, kind, index: idx, start_x, start_y, left_initial: left, _right_initial: right, total_pixels: total_px, last_event: Instant::now(), last_pty_resize: Instant::now() });
         }
     }
 
@@ -399,8 +471,16 @@ pub fn remote_mouse_drag(app: &mut AppState, x: u16, y: u16) {
         return;
     }
 
-    if let Some(d) = &app.drag {
-        adjust_split_sizes(&mut win.root, d, x, y);
+    if let Some(mut d) = app.drag.take() {
+        let area = app.last_window_area;
+        let changed = adjust_split_sizes(&mut app.windows[app.active_idx].root, area, &mut d, x, y);
+        if changed { app.windows[app.active_idx].tiled = false; }
+        // Resize the pane PTYs as the border moves, but debounced: ConPTY
+        // resizes are expensive and a fast drag emits dozens of events.
+        let resize_now = changed && d.last_pty_resize.elapsed() >= DRAG_RESIZE_DEBOUNCE;
+        if resize_now { d.last_pty_resize = Instant::now(); }
+        app.drag = Some(d);
+        if resize_now { resize_all_panes(app); }
     } else {
         // Forward drag to child pane
         if let Some(area) = rects.iter().find(|(path, _)| *path == win.active_path).map(|(_, a)| *a) {
@@ -428,7 +508,7 @@ pub fn remote_mouse_up(app: &mut AppState, x: u16, y: u16) {
             }
             app.copy_pos = Some((row, col));
         }
-        let _ = yank_selection(app);
+        let _ = yank_to_register(app);
         return;
     }
 
@@ -451,6 +531,52 @@ pub fn remote_mouse_up(app: &mut AppState, x: u16, y: u16) {
     }
 }
 
+/// Default for `@double-click-time`.
+pub const DOUBLE_CLICK_MS: u64 = 300;
+
+/// `@double-click-time`: the longest gap, in milliseconds, between the
+/// clicks of a double or triple click.
+pub fn double_click_ms(app: &AppState) -> u64 {
+    app.environment.get("@double-click-time").and_then(|v| v.parse().ok()).unwrap_or(DOUBLE_CLICK_MS)
+}
+
+/// `@pane-right-click paste`: a right-click in a pane pastes (pwsh
+/// style) instead of opening the pane menu.
+pub fn right_click_paste(app: &AppState) -> bool {
+    app.environment.get("@pane-right-click").is_some_and(|v| v == "paste")
+}
+
+/// Release of a double (`clicks` 2) or triple (3) click: focus the pane
+/// under the pointer and select the word (honouring `word-separators`) or
+/// the line there in copy mode, then yank it like `y`.  Copy mode entered
+/// for the click is left again; an application that asked for the mouse
+/// gets a plain release instead.
+pub fn remote_mouse_select(app: &mut AppState, x: u16, y: u16, clicks: u8) {
+    let win = &mut app.windows[app.active_idx];
+    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
+    compute_rects(&win.root, app.last_window_area, &mut rects);
+    let Some((path, area)) = rects.into_iter().find(|(_, r)| r.contains(ratatui::layout::Position { x, y })) else { return };
+    let wants_mouse = active_pane_mut(&mut win.root, &path)
+        .is_some_and(|p| p.term.lock().map_or(false, |t| t.screen().mouse_protocol_mode() != vt100::MouseProtocolMode::None));
+    if wants_mouse || matches!(app.mode, Mode::CopySearch { .. }) {
+        return remote_mouse_up(app, x, y);
+    }
+    let entered = !matches!(app.mode, Mode::CopyMode);
+    if entered {
+        if path != win.active_path {
+            app.last_pane_path = std::mem::replace(&mut win.active_path, path);
+        }
+        crate::copy_mode::enter_copy_mode(app);
+    } else {
+        win.active_path = path;
+    }
+    app.copy_pos = Some(copy_cell_for_area(area, x, y));
+    let cmd = if clicks >= 3 { "select-line" } else { "select-word" };
+    crate::copy_mode::run_copy_command(app, cmd);
+    let _ = yank_to_register(app);
+    if entered { crate::copy_mode::run_copy_command(app, "cancel"); }
+}
+
 /// Forward a non-left mouse button press/release to the child.
 pub fn remote_mouse_button(app: &mut AppState, x: u16, y: u16, button: u8, press: bool) {
     let win = &mut app.windows[app.active_idx];
@@ -480,6 +606,53 @@ pub fn remote_mouse_button(app: &mut AppState, x: u16, y: u16, button: u8, press
     }
 }
 
+/// Middle button: focus the pane under the pointer, then pass the click to
+/// its application if that asked for the mouse, or else paste the most
+/// recent buffer into it.  The paste reaches the other panes of a
+/// synchronized window only with sync-paste on.  Ignored in copy mode.
+pub fn middle_click(app: &mut AppState, area: Rect, x: u16, y: u16, press: bool) {
+    if matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. }) { return; }
+    let win = &mut app.windows[app.active_idx];
+    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
+    compute_rects(&win.root, area, &mut rects);
+    let Some((path, rect)) = rects.into_iter().find(|(_, r)| r.contains(ratatui::layout::Position { x, y })) else { return };
+    if press && path != win.active_path {
+        app.last_pane_path = std::mem::replace(&mut win.active_path, path.clone());
+    }
+    let win_name = win.name.clone();
+    let Some(pane) = active_pane_mut(&mut win.root, &path) else { return };
+    let wants_mouse = pane.term.lock().map_or(false, |t| t.screen().mouse_protocol_mode() != vt100::MouseProtocolMode::None);
+    if wants_mouse {
+        let (col, row) = pane_inner_cell_0based(rect, x, y);
+        let state = if press { mouse_inject::FROM_LEFT_2ND_BUTTON_PRESSED } else { 0 };
+        inject_mouse_combined(pane, col, row, 1, press, state, 0, &win_name);
+        return;
+    }
+    if !press { return; }
+    if let Some(text) = app.paste_buffers.top().cloned() {
+        let broadcast = crate::input::paste_broadcasts(app, false);
+        crate::input::paste_to_panes(app, &text, broadcast);
+    }
+}
+
+/// A right-click on a pane whose application asked for the mouse goes to
+/// it (press and release) instead of opening the pane menu.  Returns
+/// whether it did.
+pub fn right_click_to_app(app: &mut AppState, x: u16, y: u16) -> bool {
+    let win = &mut app.windows[app.active_idx];
+    let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
+    compute_rects(&win.root, app.last_window_area, &mut rects);
+    let Some((path, rect)) = rects.into_iter().find(|(_, r)| r.contains(ratatui::layout::Position { x, y })) else { return false };
+    let win_name = win.name.clone();
+    let Some(pane) = active_pane_mut(&mut win.root, &path) else { return false };
+    let wants_mouse = pane.term.lock().map_or(false, |t| t.screen().mouse_protocol_mode() != vt100::MouseProtocolMode::None);
+    if !wants_mouse { return false; }
+    let (col, row) = pane_inner_cell_0based(rect, x, y);
+    inject_mouse_combined(pane, col, row, 2, true, mouse_inject::RIGHTMOST_BUTTON_PRESSED, 0, &win_name);
+    inject_mouse_combined(pane, col, row, 2, false, 0, 0, &win_name);
+    true
+}
+
 /// Forward mouse motion to the child PTY - currently disabled to avoid garbage.
 /// Most TUI apps don't want constant mouse position updates without button held.
 pub fn remote_mouse_motion(_app: &mut AppState, _x: u16, _y: u16) {
@@ -500,6 +673,73 @@ fn copy_cell_for_area(area: Rect, x: u16, y: u16) -> (u16, u16) {
     (row, col)
 }
 
+// ─── Scroll without copy mode ───────────────────────────────────────────────
+//
+// With scroll-without-copy-mode on, the wheel moves a pane's view back through
+// its scrollback while output keeps arriving.  The offset lives in the
+// parser's scrollback: vt100 bumps it as new lines scroll in, so the view
+// stays pinned on the same history lines.
+
+/// Lines a pane's view is scrolled back (0 = following live output).
+pub fn view_offset(pane: &Pane) -> usize {
+    if !pane.view_scrolled { return 0; }
+    pane.term.lock().map(|p| p.screen().scrollback()).unwrap_or(0)
+}
+
+/// Move a parser's view `delta` lines (positive = further back), clamped to
+/// the available scrollback.  Returns the new offset.
+pub fn scroll_parser_view(parser: &mut vt100::Parser, delta: isize) -> usize {
+    let current = parser.screen().scrollback();
+    let target = if delta >= 0 { current.saturating_add(delta as usize) } else { current.saturating_sub(delta.unsigned_abs()) };
+    parser.screen_mut().set_scrollback(target);
+    parser.screen().scrollback()
+}
+
+/// Scroll a pane's view; reaching the bottom goes back to following output.
+pub fn scroll_pane_view(pane: &mut Pane, delta: isize) {
+    if pane.copy_state.is_some() { return; }
+    let Ok(mut parser) = pane.term.lock() else { return };
+    pane.view_scrolled = scroll_parser_view(&mut parser, delta) > 0;
+}
+
+/// Return a scrolled pane to live output.
+pub fn snap_pane_view(pane: &mut Pane) {
+    if !pane.view_scrolled { return; }
+    pane.view_scrolled = false;
+    if let Ok(mut parser) = pane.term.lock() {
+        parser.screen_mut().set_scrollback(0);
+    }
+}
+
+/// What input does to a scrolled-back view: (snap to live, pass the input
+/// on).  `releases` is Escape or `q`, which only snap when input is blocked.
+pub fn scrolled_input_outcome(block: bool, releases: bool) -> (bool, bool) {
+    if !block { return (true, true); }
+    (releases, false)
+}
+
+/// Should a wheel event over this pane scroll its view rather than reach
+/// the app?  Full-screen and mouse-aware apps keep the wheel.
+fn wheel_scrolls_view(app: &AppState, pane: &Pane) -> bool {
+    if !app.scroll_without_copy_mode || pane.copy_state.is_some() { return false; }
+    let Ok(parser) = pane.term.lock() else { return false };
+    let screen = parser.screen();
+    !screen.alternate_screen() && screen.mouse_protocol_mode() == vt100::MouseProtocolMode::None
+}
+
+/// Scroll the active pane's view for a wheel event; false when the event
+/// should go to the pane instead.
+pub fn wheel_scroll_view(app: &mut AppState, up: bool) -> bool {
+    let win = &app.windows[app.active_idx];
+    let Some(p) = crate::tree::active_pane(&win.root, &win.active_path) else { return false };
+    if !wheel_scrolls_view(app, p) { return false; }
+    let win = &mut app.windows[app.active_idx];
+    if let Some(p) = active_pane_mut(&mut win.root, &win.active_path) {
+        scroll_pane_view(p, if up { 3 } else { -3 });
+    }
+    true
+}
+
 fn remote_scroll_wheel(app: &mut AppState, x: u16, y: u16, up: bool) {
     if matches!(app.mode, Mode::CopyMode) {
         if up { scroll_copy_up(app, 3); } else { scroll_copy_down(app, 3); }
@@ -525,6 +765,9 @@ fn remote_scroll_wheel(app: &mut AppState, x: u16, y: u16, up: bool) {
             .map(|(_, area)| *area);
     }
 
+    if wheel_scroll_view(app, up) { return; }
+    let win = &mut app.windows[app.active_idx];
+
     let (col, row) = target_area.map_or((0, 0), |area| pane_inner_cell_0based(area, x, y));
     let win_name = win.name.clone();
     let sgr_btn: u8 = if up { 64 } else { 65 };
@@ -539,26 +782,59 @@ fn remote_scroll_wheel(app: &mut AppState, x: u16, y: u16, up: bool) {
 pub fn remote_scroll_up(app: &mut AppState, x: u16, y: u16) { remote_scroll_wheel(app, x, y, true); }
 pub fn remote_scroll_down(app: &mut AppState, x: u16, y: u16) { remote_scroll_wheel(app, x, y, false); }
 
-pub fn swap_pane(app: &mut AppState, dir: FocusDir) {
-    let win = &mut app.windows[app.active_idx];
+/// Swap the active pane with the previous (`Up`, `swap-pane -U`) or next
+/// (`Down`, `-D`) pane of the window, wrapping around.
+pub fn swap_pane(app: &mut AppState, dir: FocusDir, keep_focus: bool) {
+    let win = &app.windows[app.active_idx];
     let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
     compute_rects(&win.root, app.last_window_area, &mut rects);
-    
-    let mut active_idx = None;
-    for (i, (path, _)) in rects.iter().enumerate() { 
-        if *path == win.active_path { active_idx = Some(i); break; } 
+    let Some(ai) = rects.iter().position(|(path, _)| *path == win.active_path) else { return };
+    let n = rects.len();
+    let other = if matches!(dir, FocusDir::Up | FocusDir::Left) { (ai + n - 1) % n } else { (ai + 1) % n };
+    let Some(target) = crate::tree::get_active_pane_id(&win.root, &rects[other].0) else { return };
+    let _ = swap_panes(app, target, keep_focus);
+}
+
+/// Swap the active pane with pane `target`, which may be in another window.
+/// The panes trade places and keep their ids, titles and processes, and
+/// both windows' panes are resized to their new places.  The focus follows
+/// the active pane, into the other window if that is where it went; with
+/// `keep_focus` (`swap-pane -d`) the current window and the focused place
+/// stay as they were.
+pub fn swap_panes(app: &mut AppState, target: usize, keep_focus: bool) -> Result<(), String> {
+    unzoom_if_zoomed(app);
+    let src_win = app.active_idx;
+    let src_path = app.windows[src_win].active_path.clone();
+    let (dst_win, dst_path) = app.windows.iter().enumerate()
+        .find_map(|(i, w)| crate::tree::pane_path(&w.root, target).map(|p| (i, p)))
+        .ok_or_else(|| format!("can't find pane: %{}", target))?;
+    if (src_win, &src_path) == (dst_win, &dst_path) { return Ok(()); }
+    if get_split_mut(&mut app.windows[src_win].root, &src_path).is_none() { return Err("no active pane".to_string()); }
+
+    // Put `node` at `path` of window `w`, returning what was there
+    fn put(app: &mut AppState, w: usize, path: &Vec<usize>, node: Node) -> Node {
+        std::mem::replace(get_split_mut(&mut app.windows[w].root, path).expect("pane path"), node)
     }
-    let Some(ai) = active_idx else { return; };
-    let (_, arect) = &rects[ai];
-    
-    if let Some(ni) = crate::input::find_best_pane_in_direction(&rects, ai, arect, dir) {
-        win.active_path = rects[ni].0.clone();
+    let placeholder = Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] };
+    let src = put(app, src_win, &src_path, placeholder);
+    let dst = put(app, dst_win, &dst_path, src);
+    put(app, src_win, &src_path, dst);
+
+    if !keep_focus {
+        app.windows[dst_win].active_path = dst_path;
+        app.active_idx = dst_win;
     }
+    let area = app.last_window_area;
+    crate::tree::resize_window_panes(&mut app.windows[src_win], area);
+    if dst_win != src_win { crate::tree::resize_window_panes(&mut app.windows[dst_win], area); }
+    crate::copy_mode::fit_copy_mode_after_resize(app);
+    Ok(())
 }
 
 pub fn resize_pane_vertical(app: &mut AppState, amount: i16) {
     let win = &mut app.windows[app.active_idx];
     if win.active_path.is_empty() { return; }
+    win.tiled = false;
     
     for depth in (0..win.active_path.len()).rev() {
         let parent_path = win.active_path[..depth].to_vec();
@@ -584,6 +860,7 @@ pub fn resize_pane_vertical(app: &mut AppState, amount: i16) {
 pub fn resize_pane_horizontal(app: &mut AppState, amount: i16) {
     let win = &mut app.windows[app.active_idx];
     if win.active_path.is_empty() { return; }
+    win.tiled = false;
     
     for depth in (0..win.active_path.len()).rev() {
         let parent_path = win.active_path[..depth].to_vec();
@@ -611,6 +888,7 @@ pub fn resize_pane_horizontal(app: &mut AppState, amount: i16) {
 pub fn resize_pane_absolute(app: &mut AppState, axis: &str, target: u16) {
     let win = &mut app.windows[app.active_idx];
     if win.active_path.is_empty() { return; }
+    win.tiled = false;
     let target_kind = if axis == "x" { LayoutKind::Horizontal } else { LayoutKind::Vertical };
     for depth in (0..win.active_path.len()).rev() {
         let parent_path = win.active_path[..depth].to_vec();
@@ -653,22 +931,50 @@ pub fn rotate_panes(app: &mut AppState, reverse: bool) {
     }
 }
 
-pub fn break_pane_to_window(app: &mut AppState) {
-    let src_idx = app.active_idx;
-    let src_path = app.windows[src_idx].active_path.clone();
-    
-    // Extract the active pane from the current window using tree operations
-    let src_root = std::mem::replace(&mut app.windows[src_idx].root,
+/// Lift pane `pid` out of window `src_idx` for break-pane and join-pane.
+/// Scrollback, title, view offset, pipe-pane and the mark all live on the
+/// `Pane`, so they move with it; copy mode is left first so the
+/// session-wide copy cursor never points into a pane that has moved.  The
+/// window keeps its active pane unless that is the one taken.  Returns the
+/// pane and whether its window is now empty (the caller removes it).
+fn take_pane(app: &mut AppState, src_idx: usize, pid: usize) -> Option<(Node, bool)> {
+    if matches!(app.mode, Mode::CopyMode | Mode::CopySearch { .. }) {
+        crate::copy_mode::exit_copy_mode(app);
+    }
+    let win = &mut app.windows[src_idx];
+    let src_path = crate::tree::pane_path(&win.root, pid)?;
+    let active = crate::tree::get_active_pane_id(&win.root, &win.active_path).filter(|&a| a != pid);
+    let src_root = std::mem::replace(&mut win.root,
         Node::Split { kind: LayoutKind::Horizontal, sizes: vec![], children: vec![] });
     let (remaining, extracted) = crate::tree::extract_node(src_root, &src_path);
-    
-    if let Some(pane_node) = extracted {
-        let src_empty = remaining.is_none();
-        if let Some(rem) = remaining {
-            app.windows[src_idx].root = rem;
-            app.windows[src_idx].active_path = crate::tree::first_leaf_path(&app.windows[src_idx].root);
+    let src_empty = remaining.is_none();
+    if let Some(rem) = remaining {
+        win.root = rem;
+        if extracted.is_some() {
+            win.active_path = active.and_then(|a| crate::tree::pane_path(&win.root, a))
+                .unwrap_or_else(|| crate::tree::first_leaf_path(&win.root));
+            crate::layout::retile(win);
         }
-        
+    }
+    let mut pane_node = extracted?;
+    if let Node::Leaf(p) = &mut pane_node {
+        p.copy_state = None;
+    }
+    Some((pane_node, src_empty))
+}
+
+/// Id of the active pane of the window at `wi`.
+pub fn active_pane_id(app: &AppState, wi: usize) -> Option<usize> {
+    let win = app.windows.get(wi)?;
+    crate::tree::get_active_pane_id(&win.root, &win.active_path)
+}
+
+/// break-pane: move the active pane into a window of its own, focused
+/// there unless `detached` (`-d`).
+pub fn break_pane_to_window(app: &mut AppState, detached: bool) {
+    let src_idx = app.active_idx;
+    let Some(pid) = active_pane_id(app, src_idx) else { return };
+    if let Some((pane_node, src_empty)) = take_pane(app, src_idx, pid) {
         // Determine the window name from the pane
         let win_name = match &pane_node {
             Node::Leaf(p) => p.title.clone(),
@@ -676,36 +982,126 @@ pub fn break_pane_to_window(app: &mut AppState) {
         };
         
         // Create new window containing the extracted pane
-        app.windows.push(Window {
+        let index = smallest_free_index(&window_indices(app), app.window_base_index);
+        let src_id = app.windows[src_idx].id;
+        let new_pos = insert_window(app, Window {
             root: pane_node,
             active_path: vec![],
             name: win_name,
             id: app.next_win_id,
+            index,
             activity_flag: false,
             bell_flag: false,
             silence_flag: false,
             last_output_time: std::time::Instant::now(),
             last_seen_version: 0,
+            activity_at: chrono::Local::now(),
+            visited_at: chrono::Local::now(),
+            created_at: chrono::Local::now(),
+            idle_flag: false,
+            idle_kill_at: None,
             manual_rename: false,
             layout_index: 0,
+            tiled: false,
+            highlight_pattern: None,
+            pane_encoding: None,
+            options: Default::default(),
         });
         app.next_win_id += 1;
-        
+        let new_id = app.windows[new_pos].id;
+
         if src_empty {
-            app.windows.remove(src_idx);
+            if let Some(src_pos) = app.windows.iter().position(|w| w.id == src_id) {
+                remove_window(app, src_pos);
+            }
         }
-        
-        // Switch to the new window
-        app.active_idx = app.windows.len() - 1;
-    } else {
-        // Extraction failed — restore
-        if let Some(rem) = remaining {
-            app.windows[src_idx].root = rem;
+        let focus = if detached && !src_empty { src_id } else { new_id };
+        app.active_idx = app.windows.iter().position(|w| w.id == focus).unwrap_or(0);
+        let area = app.last_window_area;
+        for w in app.windows.iter_mut().filter(|w| w.id == src_id || w.id == new_id) {
+            crate::tree::resize_window_panes(w, area);
+        }
+        crate::copy_mode::fit_copy_mode_after_resize(app);
+    }
+}
+
+/// join-pane / move-pane: move pane `src` next to pane `dst`, splitting
+/// `dst`'s place (`kind`) with the moved pane after it.  The moved pane
+/// gets `size` of the place, or half of it.  The panes may be in the same
+/// window or in different ones; a window left empty is closed.  The moved
+/// pane is focused unless `keep_focus` (`-d`).
+pub fn join_pane(app: &mut AppState, src: usize, dst: usize, kind: LayoutKind, size: Option<PaneSize>, keep_focus: bool) -> Result<(), String> {
+    fn find(app: &AppState, pid: usize) -> Result<usize, String> {
+        app.windows.iter().position(|w| crate::tree::pane_path(&w.root, pid).is_some())
+            .ok_or_else(|| format!("can't find pane: %{}", pid))
+    }
+    if src == dst { return Err("source and target panes must be different".to_string()); }
+    let src_win = find(app, src)?;
+    find(app, dst)?;
+    unzoom_if_zoomed(app);
+    let current_id = app.windows[app.active_idx].id;
+    let src_id = app.windows[src_win].id;
+    let (pane_node, src_empty) = take_pane(app, src_win, src).ok_or("no pane to move")?;
+    if src_empty {
+        if let Some(src_pos) = app.windows.iter().position(|w| w.id == src_id) {
+            remove_window(app, src_pos);
         }
     }
+
+    let tgt = find(app, dst)?;
+    let area = app.last_window_area;
+    let win = &mut app.windows[tgt];
+    let dst_path = crate::tree::pane_path(&win.root, dst).ok_or("no target pane")?;
+    let active = crate::tree::get_active_pane_id(&win.root, &win.active_path);
+    let sizes = size.and_then(|size| {
+        let mut rects = Vec::new();
+        compute_rects(&win.root, area, &mut rects);
+        let (_, r) = rects.into_iter().find(|(p, _)| *p == dst_path)?;
+        Some(crate::tree::sized_split(if kind == LayoutKind::Horizontal { r.width } else { r.height }, size))
+    });
+    crate::tree::replace_leaf_with_split(&mut win.root, &dst_path, kind, pane_node);
+    if let (Some(sizes), Some(Node::Split { sizes: s, .. })) = (sizes, get_split_mut(&mut win.root, &dst_path)) {
+        *s = sizes;
+    }
+    win.tiled = false;
+    if keep_focus {
+        if let Some(path) = active.and_then(|a| crate::tree::pane_path(&win.root, a)) { win.active_path = path; }
+        app.active_idx = app.windows.iter().position(|w| w.id == current_id).unwrap_or(tgt);
+    } else {
+        win.active_path = dst_path;
+        win.active_path.push(1);
+        app.active_idx = tgt;
+    }
+    crate::tree::resize_window_panes(&mut app.windows[tgt], area);
+    if let Some(src_pos) = app.windows.iter().position(|w| w.id == src_id).filter(|&p| p != tgt) {
+        crate::tree::resize_window_panes(&mut app.windows[src_pos], area);
+    }
+    crate::copy_mode::fit_copy_mode_after_resize(app);
+    Ok(())
+}
+
+/// Move the active pane into the window at position `target` as a vertical
+/// split of that window's active pane, and focus it there.  Returns false
+/// if nothing moved.
+pub fn join_pane_to_window(app: &mut AppState, target: usize) -> bool {
+    if target == app.active_idx || target >= app.windows.len() { return false; }
+    let (Some(src), Some(dst)) = (active_pane_id(app, app.active_idx), active_pane_id(app, target)) else { return false };
+    join_pane(app, src, dst, LayoutKind::Vertical, None, false).is_ok()
+}
+
+/// Does typing go to a pane remain-on-exit kept after its program exited?
+/// Then Enter respawns it and `x` kills it.
+pub fn typing_to_dead_pane(app: &AppState) -> bool {
+    matches!(app.mode, Mode::Passthrough)
+        && app.windows.get(app.active_idx)
+            .and_then(|w| crate::tree::active_pane(&w.root, &w.active_path))
+            .is_some_and(|p| p.dead)
 }
 
-pub fn respawn_active_pane(app: &mut AppState, pty_system_ref: Option<&dyn portable_pty::PtySystem>) -> io::Result<()> {
+/// respawn-pane: start the shell again in the active pane, which keeps its
+/// id.  A pane whose program is still running is only respawned with
+/// `kill` (`-k`), which kills that program first.
+pub fn respawn_active_pane(app: &mut AppState, pty_system_ref: Option<&dyn portable_pty::PtySystem>, kill: bool) -> io::Result<()> {
     // Reuse provided PTY system or create one as fallback
     let owned_pty;
     let pty_system: &dyn portable_pty::PtySystem = if let Some(ps) = pty_system_ref {
@@ -714,9 +1110,14 @@ pub fn respawn_active_pane(app: &mut AppState, pty_system_ref: Option<&dyn porta
         owned_pty = native_pty_system();
         &*owned_pty
     };
+    let vars = crate::environ::composed(app);
     let win = &mut app.windows[app.active_idx];
     let Some(pane) = active_pane_mut(&mut win.root, &win.active_path) else { return Ok(()); };
     let pane_id = pane.id;
+    if !pane.dead && matches!(pane.child.try_wait(), Ok(None)) {
+        if !kill { return Err(io::Error::new(io::ErrorKind::Other, format!("pane %{} still active", pane_id))); }
+        crate::platform::process_kill::kill_process_tree(&mut pane.child);
+    }
     
     let size = PtySize { rows: pane.last_rows, cols: pane.last_cols, pixel_width: 0, pixel_height: 0 };
     let pair = pty_system.openpty(size).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("openpty error: {e}")))?;
@@ -725,29 +1126,551 @@ pub fn respawn_active_pane(app: &mut AppState, pty_system_ref: Option<&dyn porta
     } else {
         detect_shell()
     };
+    crate::environ::apply_to_pane(&mut shell_cmd, &vars);
     set_tmux_env(&mut shell_cmd, pane_id, app.socket_name.as_deref());
     let child = pair.slave.spawn_command(shell_cmd).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("spawn shell error: {e}")))?;
     // Close the slave handle immediately – required for ConPTY.
     drop(pair.slave);
-    let term: Arc<Mutex<vt100::Parser>> = Arc::new(Mutex::new(vt100::Parser::new(size.rows, size.cols, app.history_limit)));
+    // The old output stays in the history above a separator line
+    let separator = format!("──── respawned at {} ────", chrono::Local::now().format("%H:%M"));
+    let mut parser = match pane.term.lock() {
+        Ok(mut old) => crate::copy_mode::respawn_parser(&mut old, app.history_limit, &separator),
+        Err(_) => vt100::Parser::new(size.rows, size.cols, app.history_limit),
+    };
+    parser.screen_mut().set_size(size.rows, size.cols);
+    let term: Arc<Mutex<vt100::Parser>> = Arc::new(Mutex::new(parser));
     let term_reader = term.clone();
     let reader = pair.master.try_clone_reader().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("clone reader error: {e}")))?;
     
     let data_version = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
     let dv_writer = data_version.clone();
     
-    crate::pane::spawn_reader_thread(reader, term_reader, dv_writer);
+    // The new program starts wherever it starts; forget what the old one reported
+    pane.reported_cwd = Arc::new(Mutex::new(None));
+    pane.process_info = Default::default();
+    crate::pane::spawn_reader_thread(reader, term_reader, dv_writer, pane.bells.clone(), pane.encoding.clone(), pane.pipe_tap.clone(), pane.reported_cwd.clone());
     
     let mut pty_writer = pair.master.take_writer().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("take writer error: {e}")))?;
     crate::pane::conpty_preemptive_dsr_response(&mut *pty_writer);
     
+    let child_pid = unsafe { crate::platform::mouse_inject::get_child_pid(&*child) };
+    if let (Some(watcher), Some(pid)) = (&app.exit_watcher, child_pid) { watcher.watch(pane_id, pid); }
     pane.master = pair.master;
     pane.writer = pty_writer;
     pane.child = child;
     pane.term = term;
     pane.data_version = data_version;
-    pane.child_pid = None;
+    pane.rows_cache = Default::default();
+    pane.child_pid = child_pid;
     pane.dead = false;
+    pane.exit_status = None;
+    pane.view_scrolled = false;
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5-row parser with 20 numbered lines printed, 100 lines of scrollback.
+    fn parser_with_history() -> vt100::Parser {
+        let mut parser = vt100::Parser::new(5, 20, 100);
+        for i in 0..20 { parser.process(format!("line{}\r\n", i).as_bytes()); }
+        parser
+    }
+
+    fn top_row(parser: &vt100::Parser) -> String {
+        parser.screen().contents_between(0, 0, 0, 20).trim_end().to_string()
+    }
+
+    /// Window 0 split in two and window 1 with a single pane, all running
+    /// a plain shell.
+    fn app_with_panes() -> AppState {
+        let pty = native_pty_system();
+        let mut app = AppState::new("t".to_string());
+        app.default_shell = if cfg!(windows) { "cmd.exe" } else { "sh" }.to_string();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        crate::pane::split_active_with_command(&mut app, LayoutKind::Horizontal, None, Some(&*pty)).unwrap();
+        crate::pane::create_window(&*pty, &mut app, None).unwrap();
+        app.active_idx = 0;
+        app
+    }
+
+    fn active(app: &mut AppState) -> &mut Pane {
+        let win = &mut app.windows[app.active_idx];
+        active_pane_mut(&mut win.root, &win.active_path).unwrap()
+    }
+
+    fn count(app: &AppState, f: fn(&Pane) -> bool) -> usize {
+        fn rec(n: &Node, f: fn(&Pane) -> bool) -> usize {
+            match n {
+                Node::Leaf(p) => f(p) as usize,
+                Node::Split { children, .. } => children.iter().map(|c| rec(c, f)).sum(),
+            }
+        }
+        app.windows.iter().map(|w| rec(&w.root, f)).sum()
+    }
+
+    #[test]
+    fn pane_attachments_survive_break_and_join() {
+        let mut app = app_with_panes();
+        let p = active(&mut app);
+        let (id, term) = (p.id, Arc::as_ptr(&p.term));
+        p.title = "build".to_string();
+        p.marked = true;
+        p.pipe = Some(crate::types::PipePaneState { process: None, stdin: false, stdout: true, input: None });
+        {
+            let mut parser = p.term.lock().unwrap();
+            for i in 0..200 { parser.process(format!("line{}\r\n", i).as_bytes()); }
+            scroll_parser_view(&mut parser, 5);
+        }
+        p.view_scrolled = true;
+        let home = app.windows[0].id;
+
+        let check = |app: &mut AppState| {
+            let p = active(app);
+            assert_eq!((p.id, p.title.as_str(), p.marked, p.view_scrolled), (id, "build", true, true));
+            assert!(p.pipe.as_ref().map_or(false, |pipe| pipe.stdout));
+            assert!(std::ptr::eq(Arc::as_ptr(&p.term), term), "scrollback is the same parser");
+            assert!(p.term.lock().unwrap().screen().scrollback() > 0);
+            // No other pane picked up the mark or the pipe
+            assert_eq!(count(app, |p| p.marked), 1);
+            assert_eq!(count(app, |p| p.pipe.is_some()), 1);
+            assert_eq!(crate::tree::marked_pane(app), Some((app.active_idx, id)));
+        };
+
+        break_pane_to_window(&mut app, false);
+        assert_eq!(app.windows.len(), 3);
+        assert_eq!(crate::tree::count_panes(&app.windows[app.active_idx].root), 1);
+        check(&mut app);
+
+        let target = app.windows.iter().position(|w| w.id == home).unwrap();
+        assert!(join_pane_to_window(&mut app, target));
+        assert_eq!(app.windows.len(), 2);
+        assert_eq!(app.windows[app.active_idx].id, home);
+        assert_eq!(crate::tree::count_panes(&app.windows[app.active_idx].root), 2);
+        check(&mut app);
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn only_the_splitter_cell_starts_a_drag() {
+        let mut app = app_with_panes();
+        app.last_window_area = Rect::new(0, 0, 80, 24);
+        let mut rects: Vec<(Vec<usize>, Rect)> = Vec::new();
+        compute_rects(&app.windows[0].root, app.last_window_area, &mut rects);
+        let left = rects[0].1;
+        let splitter = left.x + left.width;
+        app.windows[0].active_path = vec![1];
+
+        // The left pane's last column belongs to the pane, even next to the splitter
+        remote_mouse_down(&mut app, splitter - 1, 5);
+        assert!(app.drag.is_none());
+        assert_eq!(app.windows[0].active_path, vec![0]);
+
+        remote_mouse_down(&mut app, splitter, 5);
+        let drag = app.drag.as_ref().expect("clicking the splitter starts a drag");
+        assert_eq!((drag.split_path.clone(), drag.index, drag.start_x, drag.start_y), (vec![], 0, splitter, 5));
+        assert!(matches!(drag.kind, LayoutKind::Horizontal));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    /// Pane input, recorded in place of the pane's PTY.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.lock().unwrap().extend_from_slice(buf); Ok(buf.len()) }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    impl Recorder {
+        fn take(&self) -> String { String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap() }
+    }
+
+    #[test]
+    fn synchronized_typing_is_broadcast_but_pastes_are_not() {
+        let mut app = app_with_panes();
+        let mut inputs = Vec::new();
+        crate::tree::for_each_pane_mut(&mut app.windows[0].root, &mut |p| {
+            let r = Recorder::default();
+            p.writer = Box::new(r.clone());
+            inputs.push(r);
+        });
+        let [left, right] = [inputs[0].clone(), inputs[1].clone()];
+        // The right pane's application asked for bracketed paste
+        if let Node::Split { children, .. } = &app.windows[0].root {
+            if let Node::Leaf(p) = &children[1] { p.term.lock().unwrap().process(b"\x1b[?2004h"); }
+        }
+        app.sync_input = true;
+        app.paste_buffers.push("pasted".into());
+        let area = Rect::new(0, 0, 80, 24);
+
+        crate::input::send_text_to_active(&mut app, "ls").unwrap();
+        assert_eq!((left.take(), right.take()), ("ls".to_string(), "ls".to_string()));
+
+        // Middle-click in the right pane: only it gets the paste, and focus
+        middle_click(&mut app, area, 70, 5, true);
+        middle_click(&mut app, area, 70, 5, false);
+        assert_eq!((left.take(), right.take()), (String::new(), "\x1b[200~pasted\x1b[201~".to_string()));
+        assert_eq!(app.windows[0].active_path, [1]);
+
+        // With sync-paste the paste follows synchronize-panes
+        app.sync_paste = true;
+        middle_click(&mut app, area, 5, 5, true);
+        assert_eq!((left.take(), right.take()), ("pasted".to_string(), "\x1b[200~pasted\x1b[201~".to_string()));
+        assert_eq!(app.windows[0].active_path, [0]);
+
+        // paste-buffer -s broadcasts without synchronize-panes
+        app.sync_input = false;
+        crate::copy_mode::paste_latest(&mut app).unwrap();
+        assert_eq!((left.take(), right.take()), ("pasted".to_string(), String::new()));
+        let broadcast = crate::input::paste_broadcasts(&app, true);
+        crate::input::paste_to_panes(&mut app, "all", broadcast);
+        assert_eq!((left.take(), right.take()), ("all".to_string(), "\x1b[200~all\x1b[201~".to_string()));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn double_and_triple_clicks_yank_a_word_and_a_line() {
+        let mut app = app_with_panes();
+        app.set_clipboard = "off".to_string();
+        app.last_window_area = Rect::new(0, 0, 41, 10);
+        let mut parser = vt100::Parser::new(10, 20, 100);
+        parser.process(b"foo bar.baz qux\r\nnext");
+        let p = active(&mut app);
+        p.term = Arc::new(Mutex::new(parser));
+        p.last_rows = 10;
+        p.last_cols = 20;
+
+        // The right-hand pane
+        remote_mouse_select(&mut app, 26, 0, 2);
+        assert_eq!(app.paste_buffers.top().map(String::as_str), Some("bar"));
+        assert!(matches!(app.mode, Mode::Passthrough));
+        remote_mouse_select(&mut app, 30, 0, 3);
+        assert_eq!(app.paste_buffers.top().map(String::as_str), Some("foo bar.baz qux\n"));
+
+        // Already in copy mode, it stays there with the word selected
+        crate::copy_mode::enter_copy_mode(&mut app);
+        remote_mouse_select(&mut app, 34, 0, 2);
+        assert_eq!(app.paste_buffers.top().map(String::as_str), Some("qux"));
+        assert!(matches!(app.mode, Mode::CopyMode));
+        assert_eq!((app.copy_anchor, app.copy_pos), (Some((0, 12)), Some((0, 14))));
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn moving_a_pane_leaves_copy_mode() {
+        let mut app = app_with_panes();
+        crate::copy_mode::enter_copy_mode(&mut app);
+        assert!(matches!(app.mode, Mode::CopyMode));
+        let id = active(&mut app).id;
+        let target = 1;
+        assert!(join_pane_to_window(&mut app, target));
+        assert!(matches!(app.mode, Mode::Passthrough));
+        assert!(app.copy_pos.is_none());
+        let p = active(&mut app);
+        assert_eq!(p.id, id);
+        assert!(p.copy_state.is_none());
+        // The source window kept its other pane and nothing there is in copy mode
+        assert_eq!(count(&app, |p| p.copy_state.is_some()), 0);
+        assert_eq!(crate::tree::count_panes(&app.windows[0].root), 1);
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    /// Pane ids of window `w` in leaf order.
+    fn pane_ids(app: &AppState, w: usize) -> Vec<usize> {
+        let mut rects = Vec::new();
+        compute_rects(&app.windows[w].root, app.last_window_area, &mut rects);
+        rects.iter().filter_map(|(path, _)| crate::tree::get_active_pane_id(&app.windows[w].root, path)).collect()
+    }
+
+    #[test]
+    fn swapping_within_a_window_moves_or_keeps_the_focus() {
+        let mut app = app_with_panes();
+        let [a, b] = pane_ids(&app, 0)[..] else { panic!("two panes") };
+        app.windows[0].active_path = vec![0];
+
+        swap_pane(&mut app, FocusDir::Down, false);
+        assert_eq!(pane_ids(&app, 0), [b, a]);
+        assert_eq!((app.windows[0].active_path.clone(), active(&mut app).id), (vec![1], a));
+
+        // -d: the focus stays on the first place, now holding the other pane
+        swap_pane(&mut app, FocusDir::Up, true);
+        assert_eq!(pane_ids(&app, 0), [a, b]);
+        assert_eq!((app.windows[0].active_path.clone(), active(&mut app).id), (vec![1], b));
+
+        assert!(swap_panes(&mut app, 999, false).is_err());
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn joining_splits_the_target_pane_at_the_size_asked() {
+        let mut app = app_with_panes();
+        let [a, b] = pane_ids(&app, 0)[..] else { panic!("two panes") };
+        let [c] = pane_ids(&app, 1)[..] else { panic!("one pane") };
+        app.windows[0].active_path = vec![1];
+
+        // -s names a pane that is not the active one; -t a pane of window 1
+        assert!(join_pane(&mut app, a, c, LayoutKind::Horizontal, Some(PaneSize::Percent(30)), false).is_ok());
+        assert_eq!((pane_ids(&app, 0), pane_ids(&app, 1)), (vec![b], vec![c, a]));
+        assert_eq!(app.active_idx, 1);
+        assert_eq!(active(&mut app).id, a);
+        let w = app.last_window_area.width - 1;
+        let widths: Vec<u16> = [vec![0], vec![1]].iter()
+            .map(|p| active_pane_mut(&mut app.windows[1].root, p).unwrap().last_cols).collect();
+        assert_eq!(widths, [w - (w * 30 + 50) / 100, (w * 30 + 50) / 100]);
+
+        // Back into window 0 with -d: window 1 and its active pane stay
+        app.windows[1].active_path = vec![0];
+        assert!(join_pane(&mut app, a, b, LayoutKind::Vertical, None, true).is_ok());
+        assert_eq!((pane_ids(&app, 0), pane_ids(&app, 1)), (vec![b, a], vec![c]));
+        assert_eq!((app.active_idx, active(&mut app).id), (1, c));
+
+        assert!(join_pane(&mut app, c, c, LayoutKind::Vertical, None, false).is_err());
+        assert!(join_pane(&mut app, 999, c, LayoutKind::Vertical, None, false).is_err());
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn moving_the_last_pane_of_a_window_closes_it() {
+        let mut app = app_with_panes();
+        let [c] = pane_ids(&app, 1)[..] else { panic!("one pane") };
+        let [_, b] = pane_ids(&app, 0)[..] else { panic!("two panes") };
+        let home = app.windows[0].id;
+        assert!(join_pane(&mut app, c, b, LayoutKind::Vertical, Some(PaneSize::Cells(5)), true).is_ok());
+        assert_eq!(app.windows.len(), 1);
+        assert_eq!(app.windows[app.active_idx].id, home);
+        assert_eq!(active_pane_mut(&mut app.windows[0].root, &vec![1, 1]).unwrap().last_rows, 5);
+
+        // break-pane -d leaves the focus where it was
+        break_pane_to_window(&mut app, true);
+        assert_eq!(app.windows.len(), 2);
+        assert_eq!(app.windows[app.active_idx].id, home);
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn a_respawned_pane_keeps_its_id_and_old_output() {
+        let mut app = app_with_panes();
+        let pty = native_pty_system();
+        // A live pane needs -k
+        let err = respawn_active_pane(&mut app, Some(&*pty), false).unwrap_err();
+        assert!(err.to_string().ends_with("still active"), "{err}");
+        let p = active(&mut app);
+        let id = p.id;
+        p.term.lock().unwrap().process(b"\x1b[2J\x1b[Hbuild failed\r\n");
+        p.dead = true;
+        p.exit_status = Some(1);
+        assert!(respawn_active_pane(&mut app, Some(&*pty), false).is_ok());
+
+        let p = active(&mut app);
+        assert_eq!((p.id, p.dead, p.exit_status), (id, false, None));
+        let mut term = p.term.lock().unwrap();
+        term.screen_mut().set_scrollback(usize::MAX);
+        let history = term.screen().contents();
+        assert!(history.starts_with("build failed"), "{history:?}");
+        assert!(history.contains("respawned at"), "{history:?}");
+        drop(term);
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn remain_on_exit_keeps_an_exited_pane_until_it_is_respawned() {
+        let mut app = app_with_panes();
+        app.remain_on_exit = true;
+        let pty = native_pty_system();
+        let id = active(&mut app).id;
+        let reader_version = active(&mut app).data_version.clone();
+        crate::platform::process_kill::kill_process_tree(&mut active(&mut app).child);
+        let deadline = Instant::now() + std::time::Duration::from_secs(10);
+        while !active(&mut app).dead && Instant::now() < deadline {
+            crate::tree::reap_children(&mut app).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let p = active(&mut app);
+        assert!(p.dead && p.exit_status.is_some());
+        assert_eq!(p.id, id);
+        // The reader thread is left with a counter the pane no longer uses
+        assert!(!Arc::ptr_eq(&reader_version, &p.data_version));
+        assert!(typing_to_dead_pane(&app));
+
+        assert!(respawn_active_pane(&mut app, Some(&*pty), false).is_ok());
+        assert!(!typing_to_dead_pane(&app));
+        // -k respawns a live pane
+        assert!(respawn_active_pane(&mut app, Some(&*pty), true).is_ok());
+        assert_eq!((active(&mut app).id, active(&mut app).dead), (id, false));
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn swapping_across_windows_moves_the_panes_and_resizes_them() {
+        let mut app = app_with_panes();
+        app.windows[0].active_path = vec![0];
+        let [a, b] = pane_ids(&app, 0)[..] else { panic!("two panes") };
+        let [c] = pane_ids(&app, 1)[..] else { panic!("one pane") };
+        active(&mut app).title = "build".to_string();
+
+        assert!(swap_panes(&mut app, c, false).is_ok());
+        assert_eq!((pane_ids(&app, 0), pane_ids(&app, 1)), (vec![c, b], vec![a]));
+        // The focus went with the pane into the other window
+        assert_eq!(app.active_idx, 1);
+        let p = active(&mut app);
+        assert_eq!((p.id, p.title.as_str()), (a, "build"));
+        // Each pane took the size of its new place
+        for w in 0..2 {
+            let mut rects = Vec::new();
+            compute_rects(&app.windows[w].root, app.last_window_area, &mut rects);
+            for (path, rect) in rects {
+                let p = active_pane_mut(&mut app.windows[w].root, &path).unwrap();
+                assert_eq!((p.last_rows, p.last_cols), (rect.height, rect.width));
+            }
+        }
+
+        for w in app.windows.iter_mut() { crate::tree::kill_all_children(&mut w.root); }
+    }
+
+    #[test]
+    fn view_offset_is_clamped_to_scrollback() {
+        let mut parser = parser_with_history();
+        assert_eq!(scroll_parser_view(&mut parser, 3), 3);
+        assert_eq!(scroll_parser_view(&mut parser, -10), 0);
+        // 20 lines printed into 5 rows: 16 are in scrollback
+        assert_eq!(scroll_parser_view(&mut parser, 1000), 16);
+    }
+
+    #[test]
+    fn scrolled_view_stays_pinned_as_output_arrives() {
+        let mut parser = parser_with_history();
+        scroll_parser_view(&mut parser, 6);
+        let pinned = top_row(&parser);
+        for i in 20..30 { parser.process(format!("line{}\r\n", i).as_bytes()); }
+        assert_eq!(top_row(&parser), pinned);
+        assert_eq!(parser.screen().scrollback(), 16);
+    }
+
+    #[test]
+    fn pinned_offset_never_exceeds_a_full_scrollback() {
+        let mut parser = vt100::Parser::new(5, 20, 10);
+        for i in 0..20 { parser.process(format!("line{}\r\n", i).as_bytes()); }
+        assert_eq!(scroll_parser_view(&mut parser, 8), 8);
+        for i in 20..40 { parser.process(format!("line{}\r\n", i).as_bytes()); }
+        assert!(parser.screen().scrollback() <= 10);
+    }
+
+    #[test]
+    fn scrolling_back_to_the_bottom_follows_again() {
+        let mut parser = parser_with_history();
+        scroll_parser_view(&mut parser, 3);
+        assert_eq!(scroll_parser_view(&mut parser, -3), 0);
+        assert!(top_row(&parser).starts_with("line16"));
+    }
+
+    #[test]
+    fn input_snaps_or_is_blocked() {
+        // Default: any input snaps to live and goes through
+        assert_eq!(scrolled_input_outcome(false, false), (true, true));
+        assert_eq!(scrolled_input_outcome(false, true), (true, true));
+        // Blocked: ordinary input is dropped, Escape/q only snap back
+        assert_eq!(scrolled_input_outcome(true, false), (false, false));
+        assert_eq!(scrolled_input_outcome(true, true), (true, false));
+    }
+
+    /// A session's display indices driven through the same rules the
+    /// server applies on new-window, kill-window and move-window.
+    struct Session { used: Vec<usize>, base: usize, renumber: bool }
+
+    impl Session {
+        fn new(base: usize, renumber: bool) -> Self { Session { used: Vec::new(), base, renumber } }
+        fn create(&mut self, requested: Option<usize>, kill: bool) -> Result<usize, String> {
+            let n = pick_window_index(&self.used, self.base, requested, kill)?;
+            if !self.used.contains(&n) { self.used.push(n); self.used.sort(); }
+            Ok(n)
+        }
+        fn kill(&mut self, idx: usize) {
+            self.used.retain(|&i| i != idx);
+            self.used = indices_after_close(&self.used, self.base, self.renumber);
+        }
+        fn move_to(&mut self, from: usize, to: usize) -> Result<(), String> {
+            let n = pick_window_index(&self.used, self.base, Some(to), false)?;
+            self.used.retain(|&i| i != from);
+            self.used.push(n);
+            self.used.sort();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn window_indices_across_create_kill_move() {
+        for base in [0, 1, 5] {
+            for renumber in [false, true] {
+                let b = base;
+                let mut s = Session::new(base, renumber);
+                for _ in 0..3 { s.create(None, false).unwrap(); }
+                assert_eq!(s.used, vec![b, b + 1, b + 2]);
+
+                s.kill(b + 1);
+                assert_eq!(s.used, if renumber { vec![b, b + 1] } else { vec![b, b + 2] });
+                // Plain new-window fills the gap, or appends when there is none
+                assert_eq!(s.create(None, false).unwrap(), if renumber { b + 2 } else { b + 1 });
+                assert_eq!(s.used, vec![b, b + 1, b + 2]);
+
+                // -t picks a free slot, even past a gap
+                assert_eq!(s.create(Some(b + 5), false).unwrap(), b + 5);
+                assert_eq!(s.create(Some(b), false), Err(format!("index {} in use", b)));
+                // -k replaces the occupant in place
+                assert_eq!(s.create(Some(b), true).unwrap(), b);
+                assert_eq!(s.used, vec![b, b + 1, b + 2, b + 5]);
+
+                assert!(s.move_to(b + 5, b + 1).is_err());
+                s.move_to(b + 5, b + 3).unwrap();
+                assert_eq!(s.used, vec![b, b + 1, b + 2, b + 3]);
+
+                s.kill(b);
+                assert_eq!(s.used, if renumber { vec![b, b + 1, b + 2] } else { vec![b + 1, b + 2, b + 3] });
+                assert_eq!(s.create(None, false).unwrap(), if renumber { b + 3 } else { b });
+            }
+        }
+    }
+
+    #[test]
+    fn free_index_search_starts_at_base() {
+        assert_eq!(smallest_free_index(&[], 1), 1);
+        assert_eq!(smallest_free_index(&[0, 2], 1), 1);
+        assert_eq!(smallest_free_index(&[1, 2, 4], 1), 3);
+        assert_eq!(smallest_free_index(&[0, 1, 2], 0), 3);
+    }
+
+    fn ids(app: &AppState) -> Vec<usize> { app.windows.iter().map(|w| w.id).collect() }
+
+    #[test]
+    fn app_windows_stay_sorted_and_active_follows() {
+        let mut app = AppState::new("idx".to_string());
+        insert_window(&mut app, Window::bare(10, 0, "w10"));
+        insert_window(&mut app, Window::bare(12, 2, "w12"));
+        app.active_idx = 1;
+        // Filling the gap shifts the active window's position, not its identity
+        assert_eq!(insert_window(&mut app, Window::bare(11, 1, "w11")), 1);
+        assert_eq!(ids(&app), vec![10, 11, 12]);
+        assert_eq!(app.windows[app.active_idx].id, 12);
+
+        assert_eq!(set_window_index(&mut app, 2, 7), 2);
+        assert_eq!(window_indices(&app), vec![0, 1, 7]);
+        assert_eq!(window_pos_by_index(&app, 7), Some(2));
+        assert_eq!(window_pos_by_index(&app, 2), None);
+
+        remove_window(&mut app, 1);
+        assert_eq!(window_indices(&app), vec![0, 7]);
+        app.renumber_windows = true;
+        app.active_idx = 0;
+        remove_window(&mut app, 0);
+        assert_eq!(window_indices(&app), vec![0]);
+        assert_eq!(ids(&app), vec![12]);
+    }
+}
@@ -0,0 +1,129 @@
+# psmux Frame Batch Test
+# begin-batch on a control connection holds frames back from every client
+# until commit-batch, so a scripted multi-step change (new window, several
+# splits, a layout) reaches an attached client as exactly one new frame.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_batch.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+$S = "batch_test"
+& $PSMUX kill-session -t $S 2>$null
+Start-Sleep -Seconds 1
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d" -WindowStyle Hidden
+Start-Sleep -Seconds 3
+
+$pf = "$env:USERPROFILE\.psmux\${S}.port"
+$kf = "$env:USERPROFILE\.psmux\${S}.key"
+$port = [int](Get-Content $pf -Raw).Trim()
+$key = (Get-Content $kf -Raw).Trim()
+
+function Connect {
+    $tcp = New-Object System.Net.Sockets.TcpClient
+    $tcp.NoDelay = $true
+    $tcp.Connect("127.0.0.1", $port)
+    $ns = $tcp.GetStream()
+    $ns.ReadTimeout = 10000
+    $c = @{ Tcp = $tcp; W = (New-Object System.IO.StreamWriter($ns)); R = (New-Object System.IO.StreamReader($ns)) }
+    $c.W.WriteLine("AUTH $key"); $c.W.Flush()
+    if ($c.R.ReadLine() -ne "OK") { Write-Error "auth failed"; exit 1 }
+    $c.W.WriteLine("PERSISTENT"); $c.W.Flush()
+    return $c
+}
+
+# The attached client whose frames are watched
+$obs = Connect
+$obs.W.WriteLine("client-attach"); $obs.W.Flush()
+$obs.W.WriteLine("client-size 120 40"); $obs.W.Flush()
+# The script driving the change
+$ctl = Connect
+Start-Sleep -Milliseconds 500
+
+# Next reply to dump-state, skipping %-directives
+function Dump {
+    $obs.W.WriteLine("dump-state"); $obs.W.Flush()
+    while ($true) {
+        $r = $obs.R.ReadLine()
+        if ($r -notlike "%*") { return $r }
+    }
+}
+function Leaves { param($frame) ([regex]::Matches($frame, '"type":"leaf"')).Count }
+function Send { param($line) $ctl.W.WriteLine($line); $ctl.W.Flush(); Start-Sleep -Milliseconds 150 }
+
+$before = Dump
+Write-Info "Initial frame has $(Leaves $before) pane(s)"
+
+Write-Test "The observer sees no frames while the batch is open"
+Send "begin-batch"
+$frames = @()
+Send "new-window"
+$frames += Dump
+for ($i = 0; $i -lt 5; $i++) {
+    Send "split-window"
+    $frames += Dump
+}
+Send "select-layout tiled"
+$frames += Dump
+$held = @($frames | Where-Object { $_ -ne "NC" })
+if ($held.Count -eq 0) { Write-Pass "$($frames.Count) requests answered NC" } else { Write-Fail "$($held.Count) frame(s) leaked during the batch" }
+
+Write-Test "A nested begin-batch is refused"
+$ctl.W.WriteLine("begin-batch"); $ctl.W.Flush()
+$err = $ctl.R.ReadLine()
+if ($err -like "begin-batch:*already open*") { Write-Pass $err } else { Write-Fail "got '$err'" }
+
+Write-Test "commit-batch releases exactly one frame with the finished layout"
+Send "commit-batch"
+$changed = 0
+$last = $before
+for ($i = 0; $i -lt 5; $i++) {
+    $f = Dump
+    if ($f -ne "NC") {
+        if ((Leaves $f) -ne (Leaves $last)) { $changed++ }
+        $last = $f
+    }
+    Start-Sleep -Milliseconds 100
+}
+$n = Leaves $last
+if ($n -eq 6 -and $changed -eq 1) { Write-Pass "one frame, $n panes" } else { Write-Fail "$changed layout change(s), $n panes" }
+
+Write-Test "commit-batch with no batch open is an error"
+$ctl.W.WriteLine("commit-batch"); $ctl.W.Flush()
+$err = $ctl.R.ReadLine()
+if ($err -like "commit-batch: no batch open*") { Write-Pass $err } else { Write-Fail "got '$err'" }
+
+Write-Test "Dropping the connection mid-batch releases frames"
+Send "begin-batch"
+Send "split-window"
+$ctl.Tcp.Close()
+Start-Sleep -Milliseconds 500
+$f = Dump
+if ($f -ne "NC" -and (Leaves $f) -eq 7) { Write-Pass "7 panes" } else { Write-Fail "got '$($f.Substring(0, [Math]::Min(40, $f.Length)))'" }
+
+$obs.W.WriteLine("client-detach"); $obs.W.Flush()
+$obs.Tcp.Close()
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-session -t $S 2>$null
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "FRAME BATCH TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed: $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed: $($script:TestsFailed)" -ForegroundColor Red
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }
@@ -0,0 +1,61 @@
+# psmux headless window size Test
+# With no client attached, resize-window -x/-y (and refresh-client -C WxH,
+# as tmux control clients send it) sets the size windows are laid out in.
+# The panes' programs see the columns they were given.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_headless_size.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+$S = "headless_size_test"
+& $PSMUX kill-session -t $S 2>$null
+Start-Sleep -Seconds 1
+
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d" -WindowStyle Hidden
+Start-Sleep -Seconds 4
+
+Write-Test "resize-window sets the window size without a client"
+& $PSMUX resize-window -t $S -x 120 -y 40
+Start-Sleep -Milliseconds 500
+$size = (& $PSMUX display-message -t $S -p '#{window_width}x#{window_height}' 2>&1 | Out-String).Trim()
+if ($size -eq "120x40") { Write-Pass $size } else { Write-Fail "window is '$size'" }
+
+Write-Test "A pane split side by side gets its share of the 120 columns"
+& $PSMUX split-window -h -t $S
+Start-Sleep -Seconds 3
+& $PSMUX send-keys -t $S 'Write-Output "cols=$($Host.UI.RawUI.WindowSize.Width)"' Enter
+Start-Sleep -Seconds 2
+$shown = & $PSMUX capture-pane -t $S -p 2>&1 | Out-String
+$width = (& $PSMUX display-message -t $S -p '#{pane_width}' 2>&1 | Out-String).Trim()
+if ($width -eq "60" -and $shown -match "cols=60") { Write-Pass "pane is 60 columns" } else { Write-Fail "pane_width '$width', capture: $shown" }
+
+Write-Test "refresh-client -C WxH does the same"
+& $PSMUX refresh-client -t $S -C 100x30
+Start-Sleep -Milliseconds 500
+$size = (& $PSMUX display-message -t $S -p '#{window_width}x#{window_height}' 2>&1 | Out-String).Trim()
+if ($size -eq "100x30") { Write-Pass $size } else { Write-Fail "window is '$size'" }
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-session -t $S 2>$null
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "HEADLESS SIZE TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed: $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed: $($script:TestsFailed)" -ForegroundColor Red
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }
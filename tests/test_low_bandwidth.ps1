@@ -0,0 +1,99 @@
+# psmux Low-Bandwidth Mode Test
+# refresh-client -f low-bandwidth on a persistent connection switches it to
+# plain frames: capped to one per 100ms, no per-cell styling, and inactive
+# panes deferred.  -f !low-bandwidth switches back.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_low_bandwidth.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+$S = "lowbw_test"
+& $PSMUX kill-session -t $S 2>$null
+Start-Sleep -Seconds 1
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d" -WindowStyle Hidden
+Start-Sleep -Seconds 3
+& $PSMUX split-window -h -t $S 2>$null
+Start-Sleep -Seconds 1
+
+$pf = "$env:USERPROFILE\.psmux\${S}.port"
+$kf = "$env:USERPROFILE\.psmux\${S}.key"
+$port = [int](Get-Content $pf -Raw).Trim()
+$key = (Get-Content $kf -Raw).Trim()
+
+$tcp = New-Object System.Net.Sockets.TcpClient
+$tcp.NoDelay = $true
+$tcp.Connect("127.0.0.1", $port)
+$ns = $tcp.GetStream()
+$ns.ReadTimeout = 10000
+$wr = New-Object System.IO.StreamWriter($ns)
+$rd = New-Object System.IO.StreamReader($ns)
+$wr.WriteLine("AUTH $key"); $wr.Flush()
+if ($rd.ReadLine() -ne "OK") { Write-Error "auth failed"; exit 1 }
+$wr.WriteLine("PERSISTENT"); $wr.Flush()
+$wr.WriteLine("client-attach"); $wr.Flush()
+$wr.WriteLine("client-size 120 30"); $wr.Flush()
+Start-Sleep -Milliseconds 500
+
+# Next reply to dump-state, skipping %-directives
+function Dump {
+    $wr.WriteLine("dump-state"); $wr.Flush()
+    while ($true) {
+        $r = $rd.ReadLine()
+        if ($r -notlike "%*") { return $r }
+    }
+}
+
+Write-Test "Normal frames carry styled runs for both panes"
+$normal = Dump
+if ($normal -match '"fg"' -and $normal -notmatch '"deferred":true') { Write-Pass "$($normal.Length) bytes" } else { Write-Fail "unexpected frame" }
+
+Write-Test "refresh-client -f low-bandwidth is acknowledged with %flags"
+$wr.WriteLine("refresh-client -f low-bandwidth"); $wr.Flush()
+$ack = $rd.ReadLine()
+while ($ack -notlike "%flags*") { $ack = $rd.ReadLine() }
+if ($ack -eq "%flags focused,low-bandwidth") { Write-Pass $ack } else { Write-Fail "got '$ack'" }
+
+Write-Test "Low-bandwidth frames are plain and defer the inactive pane"
+Start-Sleep -Milliseconds 150
+$low = Dump
+if ($low -match '"deferred":true' -and $low -notmatch '"fg"' -and $low.Length -lt $normal.Length) { Write-Pass "$($low.Length) bytes" } else { Write-Fail "got $($low.Length) bytes" }
+
+Write-Test "A second request inside 100ms is answered NC"
+$again = Dump
+if ($again -eq "NC") { Write-Pass "NC" } else { Write-Fail "got $($again.Length) bytes" }
+
+Write-Test "refresh-client -f !low-bandwidth restores styled frames"
+$wr.WriteLine("refresh-client -f !low-bandwidth"); $wr.Flush()
+$ack = $rd.ReadLine()
+while ($ack -notlike "%flags*") { $ack = $rd.ReadLine() }
+Start-Sleep -Milliseconds 150
+$back = Dump
+if ($ack -eq "%flags focused" -and $back -match '"fg"') { Write-Pass $ack } else { Write-Fail "got '$ack'" }
+
+$wr.WriteLine("client-detach"); $wr.Flush()
+$tcp.Close()
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-session -t $S 2>$null
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "LOW-BANDWIDTH TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed: $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed: $($script:TestsFailed)" -ForegroundColor Red
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }
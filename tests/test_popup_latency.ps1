@@ -0,0 +1,119 @@
+# test_popup_latency.ps1 — display-popup time-to-first-output, warm vs cold
+#
+# Each popup's command writes a marker file as its first action; the time
+# from invoking display-popup to the marker appearing is the popup's
+# startup latency.  Measured with popup-warm-shell on (the server's spare
+# shell) and off (a new ConPTY + pwsh per popup).
+#
+# Also checks that a spare shell never carries a directory or environment
+# variable from one popup into the next, and that -d / -e popups get
+# exactly what they asked for.
+
+param(
+    [int]$Runs = 5,
+    [int]$TimeoutSec = 20,
+    # Warm popups must start at least this many times faster than cold ones
+    [double]$TargetSpeedup = 2.0
+)
+
+$ErrorActionPreference = "Stop"
+$PSMUX = Join-Path $PSScriptRoot "..\target\release\psmux.exe"
+if (-not (Test-Path $PSMUX)) {
+    $PSMUX = Join-Path $PSScriptRoot "..\target\release\tmux.exe"
+}
+if (-not (Test-Path $PSMUX)) {
+    Write-Host "ERROR: Cannot find psmux.exe or tmux.exe in target\release\" -ForegroundColor Red
+    exit 1
+}
+$PSMUX = (Resolve-Path $PSMUX).Path
+
+$PASS = 0; $FAIL = 0; $TOTAL_TESTS = 0
+function Write-Pass { param([string]$msg) $script:PASS++; $script:TOTAL_TESTS++; Write-Host "  PASS: $msg" -ForegroundColor Green }
+function Write-Fail { param([string]$msg) $script:FAIL++; $script:TOTAL_TESTS++; Write-Host "  FAIL: $msg" -ForegroundColor Red }
+function Write-Info { param([string]$msg) Write-Host "  INFO: $msg" -ForegroundColor Gray }
+function Write-Metric { param([string]$label, [double]$ms)
+    $color = if ($ms -lt 300) { "Green" } elseif ($ms -lt 1000) { "Yellow" } else { "Red" }
+    Write-Host ("  {0,-50} {1,8:N0} ms" -f $label, $ms) -ForegroundColor $color
+}
+
+$SESSION = "popup_latency_$PID"
+$MARKER_DIR = Join-Path $env:TEMP "psmux_popup_$PID"
+New-Item -ItemType Directory -Force -Path $MARKER_DIR | Out-Null
+
+# Open a popup whose command writes $Content to a fresh marker file, and
+# return (elapsed ms, marker content) once the file appears.
+function Invoke-Popup {
+    param([string]$Content, [string[]]$Extra = @())
+    $marker = Join-Path $MARKER_DIR ([guid]::NewGuid().ToString() + ".txt")
+    $cmd = "Set-Content -LiteralPath '$marker' -Value ($Content)"
+    $sw = [System.Diagnostics.Stopwatch]::StartNew()
+    & $PSMUX display-popup -t $SESSION -E @Extra $cmd 2>&1 | Out-Null
+    while (-not (Test-Path $marker) -and $sw.ElapsedMilliseconds -lt ($TimeoutSec * 1000)) {
+        Start-Sleep -Milliseconds 5
+    }
+    $ms = $sw.ElapsedMilliseconds
+    $text = if (Test-Path $marker) { (Get-Content -LiteralPath $marker -Raw).Trim() } else { $null }
+    return @{ Ms = $ms; Text = $text }
+}
+
+function Measure-Popups {
+    param([string]$Label)
+    $times = @()
+    for ($i = 1; $i -le $Runs; $i++) {
+        # Let the server start the next spare before timing another popup
+        Start-Sleep -Milliseconds 1500
+        $r = Invoke-Popup -Content "'ok'"
+        if ($r.Text -ne "ok") { Write-Fail "$Label popup $i never ran"; continue }
+        Write-Metric "$Label popup $i" $r.Ms
+        $times += $r.Ms
+    }
+    if ($times.Count -eq 0) { return $null }
+    return ($times | Measure-Object -Average).Average
+}
+
+Write-Host ""
+Write-Host "================================================================" -ForegroundColor Cyan
+Write-Host " display-popup latency" -ForegroundColor Cyan
+Write-Host "================================================================" -ForegroundColor Cyan
+
+Start-Process -FilePath $PSMUX -ArgumentList "new-session", "-s", $SESSION, "-d" -WindowStyle Hidden | Out-Null
+Start-Sleep -Seconds 3
+
+& $PSMUX set-option -t $SESSION -g popup-warm-shell off 2>&1 | Out-Null
+$coldAvg = Measure-Popups -Label "cold"
+& $PSMUX set-option -t $SESSION -g popup-warm-shell on 2>&1 | Out-Null
+$warmAvg = Measure-Popups -Label "warm"
+
+if ($coldAvg -and $warmAvg) {
+    Write-Metric "cold AVG" $coldAvg
+    Write-Metric "warm AVG" $warmAvg
+    $speedup = $coldAvg / [math]::Max($warmAvg, 1)
+    Write-Info ("speedup: {0:N1}x (target {1:N1}x)" -f $speedup, $TargetSpeedup)
+    if ($speedup -ge $TargetSpeedup) { Write-Pass "warm popups start $([math]::Round($speedup, 1))x faster" }
+    else { Write-Fail "warm popups only $([math]::Round($speedup, 1))x faster" }
+}
+
+Write-Host ""
+Write-Host " State isolation" -ForegroundColor Cyan
+Start-Sleep -Milliseconds 1500
+$r = Invoke-Popup -Content "'set'; Set-Location C:\; `$env:PSMUX_POPUP_LEAK = 'leaked'"
+Start-Sleep -Milliseconds 1500
+$r = Invoke-Popup -Content "`"`$PWD|`$env:PSMUX_POPUP_LEAK`""
+if ($r.Text -and $r.Text -notmatch 'leaked') { Write-Pass "environment does not carry into the next popup" }
+else { Write-Fail "next popup saw: $($r.Text)" }
+if ($r.Text -and $r.Text -notmatch '^C:\\\|') { Write-Pass "directory does not carry into the next popup" }
+else { Write-Fail "next popup saw: $($r.Text)" }
+
+$r = Invoke-Popup -Content "`"`$PWD|`$env:PSMUX_POPUP_VAR`"" -Extra @("-d", $MARKER_DIR, "-e", "PSMUX_POPUP_VAR=given")
+if ($r.Text -eq "$MARKER_DIR|given") { Write-Pass "-d and -e popups start in their own shell" }
+else { Write-Fail "-d/-e popup saw: $($r.Text)" }
+
+& $PSMUX kill-session -t $SESSION 2>&1 | Out-Null
+Remove-Item -Recurse -Force $MARKER_DIR -ErrorAction SilentlyContinue
+
+Write-Host ""
+Write-Host "  Tests passed: $PASS / $TOTAL_TESTS" -ForegroundColor $(if ($FAIL -eq 0) { "Green" } else { "Red" })
+if ($FAIL -gt 0) {
+    Write-Host "  Tests FAILED: $FAIL" -ForegroundColor Red
+    exit 1
+}
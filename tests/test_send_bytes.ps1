@@ -0,0 +1,72 @@
+# psmux send-bytes Test
+# send-bytes writes exact bytes to a pane (--hex or --base64), bypassing
+# key-name translation, and --expect waits for the pane to answer.  The
+# pane runs a small responder that answers a DSR query (ESC [ 6 n) with a
+# cursor position report, standing in for a real terminal application.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_send_bytes.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+$S = "sendbytes_test"
+& $PSMUX kill-session -t $S 2>$null
+Start-Sleep -Seconds 1
+
+# Reads raw input and answers ESC [ 6 n with a made-up report "CPR 7;3R"
+$responder = "$env:TEMP\psmux_dsr_responder.ps1"
+@'
+[Console]::TreatControlCAsInput = $true
+Write-Host "responder ready"
+$buf = ""
+while ($true) {
+    $k = [Console]::ReadKey($true)
+    $buf += $k.KeyChar
+    if ($buf.EndsWith("$([char]27)[6n")) { Write-Host "CPR 7;3R"; $buf = "" }
+    if ($buf.Length -gt 64) { $buf = $buf.Substring($buf.Length - 8) }
+}
+'@ | Set-Content -Path $responder -Encoding UTF8
+
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d pwsh -NoProfile -File `"$responder`"" -WindowStyle Hidden
+Start-Sleep -Seconds 4
+
+Write-Test "Odd-length hex is rejected with a useful error"
+$err = & $PSMUX send-bytes -t $S --hex 1b5b3 2>&1 | Out-String
+if ($LASTEXITCODE -ne 0 -and $err -match "odd number of hex digits \(5\)") { Write-Pass $err.Trim() } else { Write-Fail "exit $LASTEXITCODE, '$err'" }
+
+Write-Test "A DSR query sent as hex gets the responder's report"
+$out = & $PSMUX send-bytes -t $S --hex 1b5b366e --expect 'CPR \d+;\d+R' --timeout 3 2>&1 | Out-String
+if ($LASTEXITCODE -eq 0 -and $out.Trim() -eq "CPR 7;3R") { Write-Pass $out.Trim() } else { Write-Fail "exit $LASTEXITCODE, '$out'" }
+
+Write-Test "The same query as base64 gets a second report"
+$out = & $PSMUX send-bytes -t $S --base64 G1s2bg== --expect 'CPR \d+;\d+R' --timeout 3 2>&1 | Out-String
+if ($LASTEXITCODE -eq 0 -and $out.Trim() -eq "CPR 7;3R") { Write-Pass $out.Trim() } else { Write-Fail "exit $LASTEXITCODE, '$out'" }
+
+Write-Test "--expect times out when nothing new matches"
+$out = & $PSMUX send-bytes -t $S --hex 41 --expect 'never-printed' --timeout 1 2>&1 | Out-String
+if ($LASTEXITCODE -ne 0 -and $out -match "no output matching") { Write-Pass $out.Trim() } else { Write-Fail "exit $LASTEXITCODE, '$out'" }
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-session -t $S 2>$null
+Remove-Item $responder -ErrorAction SilentlyContinue
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "SEND-BYTES TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed: $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed: $($script:TestsFailed)" -ForegroundColor Red
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }
@@ -0,0 +1,124 @@
+# psmux TPM End-to-End Test
+# Installs tpm plus tmux-sensible, tmux-yank and tmux-prefix-highlight into a
+# temporary home, loads them from a .tmux.conf, and checks they took effect.
+# Needs git and bash (Git for Windows) on PATH.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_tpm_plugins.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+$script:TestsSkipped = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Skip { param($msg) Write-Host "[SKIP] $msg" -ForegroundColor Yellow; $script:TestsSkipped++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+if (-not (Get-Command bash -ErrorAction SilentlyContinue)) { Write-Skip "bash not found; tpm needs it"; exit 0 }
+if (-not (Get-Command git -ErrorAction SilentlyContinue)) { Write-Skip "git not found"; exit 0 }
+
+# Temporary home so the user's own config and plugins are untouched
+$realHome = $env:USERPROFILE
+$TMPHOME = Join-Path $env:TEMP "psmux_tpm_$PID"
+New-Item -ItemType Directory -Path "$TMPHOME\.tmux\plugins" -Force | Out-Null
+$env:USERPROFILE = $TMPHOME
+$env:HOME = $TMPHOME
+$env:PSMUX_DIR = "$TMPHOME\.psmux"
+
+$plugins = @(
+    "tmux-plugins/tpm",
+    "tmux-plugins/tmux-sensible",
+    "tmux-plugins/tmux-yank",
+    "tmux-plugins/tmux-prefix-highlight"
+)
+foreach ($p in $plugins) {
+    $name = $p.Split('/')[1]
+    Write-Info "Cloning $p..."
+    git clone --depth 1 "https://github.com/$p" "$TMPHOME\.tmux\plugins\$name" 2>&1 | Out-Null
+}
+if (-not (Test-Path "$TMPHOME\.tmux\plugins\tpm\tpm")) { Write-Fail "tpm clone failed"; exit 1 }
+
+@"
+set -g @plugin 'tmux-plugins/tpm'
+set -g @plugin 'tmux-plugins/tmux-sensible'
+set -g @plugin 'tmux-plugins/tmux-yank'
+set -g @plugin 'tmux-plugins/tmux-prefix-highlight'
+set -g status-right '#{prefix_highlight} | %H:%M'
+run '~/.tmux/plugins/tpm/tpm'
+"@ | Set-Content -Path "$TMPHOME\.tmux.conf" -Encoding ASCII
+
+function Psmux { & $PSMUX @args 2>&1; Start-Sleep -Milliseconds 300 }
+
+$S = "tpm_test"
+& $PSMUX kill-server 2>$null
+Start-Sleep -Seconds 1
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d" -WindowStyle Hidden
+Start-Sleep -Seconds 3
+# Plugins run on a worker thread after startup; give them time to finish
+Start-Sleep -Seconds 5
+
+Write-Test "Session is up after loading tpm"
+& $PSMUX has-session -t $S 2>$null
+if ($LASTEXITCODE -eq 0) { Write-Pass "session $S running" } else { Write-Fail "session $S not running" }
+
+Write-Test "tmux -V through the shim reports a tmux version"
+$env:PSMUX_AS_TMUX = "1"
+$v = (& $PSMUX -V | Out-String).Trim()
+Remove-Item Env:PSMUX_AS_TMUX
+if ($v -match "^tmux \d") { Write-Pass "version: $v" } else { Write-Fail "version: '$v'" }
+
+Write-Test "@plugin is stored as a user option"
+$v = (Psmux show-option -gqv "@plugin" -t $S | Out-String).Trim()
+if ($v -match "tmux-plugins/") { Write-Pass "@plugin: $v" } else { Write-Fail "@plugin: '$v'" }
+
+Write-Test "show-option -gqv on an unset option prints nothing"
+$v = (Psmux show-option -gqv "@no_such_option" -t $S | Out-String).Trim()
+if ($v -eq "") { Write-Pass "empty" } else { Write-Fail "got '$v'" }
+
+Write-Test "TMUX_PLUGIN_MANAGER_PATH is visible through show-environment"
+$v = (Psmux show-environment -g TMUX_PLUGIN_MANAGER_PATH -t $S | Out-String).Trim()
+if ($v -match "^TMUX_PLUGIN_MANAGER_PATH=.*plugins") { Write-Pass $v } else { Write-Info "not set by tpm: '$v'"; Write-Skip "TMUX_PLUGIN_MANAGER_PATH" }
+
+Write-Test "tmux-sensible: escape-time lowered"
+$v = (Psmux show-options -g -v escape-time -t $S | Out-String).Trim()
+if ($v -eq "0") { Write-Pass "escape-time: $v" } else { Write-Fail "escape-time: '$v'" }
+
+Write-Test "tmux-yank: copy-mode-vi y is bound"
+$keys = (Psmux list-keys -t $S | Out-String)
+if ($keys -match "copy-mode-vi\s+y\s") { Write-Pass "copy-mode-vi y bound" } else { Write-Fail "no copy-mode-vi y binding" }
+
+Write-Test "prefix-highlight: placeholder replaced in status-right"
+$v = (Psmux show-options -gqv status-right -t $S | Out-String).Trim()
+if ($v -notmatch "#\{prefix_highlight\}" -and $v -match "client_prefix") { Write-Pass "status-right: $v" }
+else { Write-Fail "status-right: '$v'" }
+
+Write-Test "prefix-highlight: status-right expands without leftovers"
+$v = (Psmux display-message -p -t $S "#{T:status-right}" | Out-String).Trim()
+if ($v -notmatch "#\{" -and $v -match "\|") { Write-Pass "expanded: $v" } else { Write-Fail "expanded: '$v'" }
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-server 2>$null
+Start-Sleep -Seconds 1
+$env:USERPROFILE = $realHome
+$env:HOME = $realHome
+Remove-Item Env:PSMUX_DIR
+Remove-Item $TMPHOME -Recurse -Force -ErrorAction SilentlyContinue
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "TPM PLUGIN TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed:  $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed:  $($script:TestsFailed)" -ForegroundColor Red
+Write-Host "Skipped: $($script:TestsSkipped)" -ForegroundColor Yellow
+Write-Host "Total:   $($script:TestsPassed + $script:TestsFailed + $script:TestsSkipped)"
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }
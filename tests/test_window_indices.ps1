@@ -0,0 +1,85 @@
+# psmux Window Index Test
+# new-window fills the smallest free index, -t places a window at a given
+# index (refused when taken unless -k), and select-window/list-windows use
+# the real indices, gaps included.
+# Run: pwsh -NoProfile -ExecutionPolicy Bypass -File tests\test_window_indices.ps1
+
+$ErrorActionPreference = "Continue"
+$script:TestsPassed = 0
+$script:TestsFailed = 0
+
+function Write-Pass { param($msg) Write-Host "[PASS] $msg" -ForegroundColor Green; $script:TestsPassed++ }
+function Write-Fail { param($msg) Write-Host "[FAIL] $msg" -ForegroundColor Red; $script:TestsFailed++ }
+function Write-Info { param($msg) Write-Host "[INFO] $msg" -ForegroundColor Cyan }
+function Write-Test { param($msg) Write-Host "[TEST] $msg" -ForegroundColor White }
+
+$PSMUX = (Resolve-Path "$PSScriptRoot\..\target\release\psmux.exe" -ErrorAction SilentlyContinue).Path
+if (-not $PSMUX) { $PSMUX = (Resolve-Path "$PSScriptRoot\..\target\debug\psmux.exe" -ErrorAction SilentlyContinue).Path }
+if (-not $PSMUX) { Write-Error "psmux binary not found"; exit 1 }
+Write-Info "Using: $PSMUX"
+
+function Psmux { & $PSMUX @args 2>&1; Start-Sleep -Milliseconds 300 }
+function Indices { (& $PSMUX list-windows -t $S -F "#{window_index}" 2>&1 | Out-String).Trim() -split "\s+" -join "," }
+
+$S = "winidx_test"
+& $PSMUX kill-session -t $S 2>$null
+Start-Sleep -Seconds 1
+Start-Process -FilePath $PSMUX -ArgumentList "new-session -s $S -d" -WindowStyle Hidden
+Start-Sleep -Seconds 3
+
+Psmux new-window -t $S | Out-Null
+Psmux new-window -t $S | Out-Null
+Write-Test "Three windows get 0,1,2"
+$v = Indices
+if ($v -eq "0,1,2") { Write-Pass $v } else { Write-Fail "got '$v'" }
+
+Write-Test "Killing window 1 leaves a gap (renumber-windows off)"
+Psmux kill-window -t "${S}:1" | Out-Null
+$v = Indices
+if ($v -eq "0,2") { Write-Pass $v } else { Write-Fail "got '$v'" }
+
+Write-Test "select-window reaches an index past the gap"
+Psmux select-window -t "${S}:2" | Out-Null
+$v = (Psmux display-message -p -t $S "#{window_index}" | Out-String).Trim()
+if ($v -eq "2") { Write-Pass "active: $v" } else { Write-Fail "active: '$v'" }
+
+Write-Test "new-window fills the gap"
+Psmux new-window -t $S | Out-Null
+$v = Indices
+if ($v -eq "0,1,2") { Write-Pass $v } else { Write-Fail "got '$v'" }
+
+Write-Test "new-window -t :5 creates window 5"
+Psmux new-window -t "${S}:5" | Out-Null
+$v = Indices
+if ($v -eq "0,1,2,5") { Write-Pass $v } else { Write-Fail "got '$v'" }
+
+Write-Test "new-window -t on a taken index fails"
+$out = (& $PSMUX new-window -t "${S}:5" 2>&1 | Out-String).Trim()
+$code = $LASTEXITCODE
+Start-Sleep -Milliseconds 300
+if ($code -ne 0 -and $out -match "index 5 in use" -and (Indices) -eq "0,1,2,5") { Write-Pass $out } else { Write-Fail "exit $code, '$out'" }
+
+Write-Test "new-window -k -t replaces the window there"
+Psmux new-window -k -t "${S}:5" -n replaced | Out-Null
+$v = (& $PSMUX list-windows -t $S -F "#{window_index}:#{window_name}" 2>&1 | Out-String)
+if ((Indices) -eq "0,1,2,5" -and $v -match "5:replaced") { Write-Pass "replaced" } else { Write-Fail "got '$v'" }
+
+Write-Test "renumber-windows closes gaps on kill"
+Psmux set-option -g -t $S renumber-windows on | Out-Null
+Psmux kill-window -t "${S}:1" | Out-Null
+$v = Indices
+if ($v -eq "0,1,2") { Write-Pass $v } else { Write-Fail "got '$v'" }
+
+# ============================================================
+# Cleanup
+# ============================================================
+& $PSMUX kill-session -t $S 2>$null
+
+Write-Host ""
+Write-Host ("=" * 60)
+Write-Host "WINDOW INDEX TEST RESULTS"
+Write-Host ("=" * 60)
+Write-Host "Passed: $($script:TestsPassed)" -ForegroundColor Green
+Write-Host "Failed: $($script:TestsFailed)" -ForegroundColor Red
+
+if ($script:TestsFailed -gt 0) { exit 1 } else { exit 0 }